@@ -2,7 +2,10 @@
 //! to contruct a software pipeline. The stage uses all available CPU cores and
 //! can do its processing in parallel with signature verification on the GPU.
 
-use solana_runtime::mev::{ExecutedTransactionOutput, Mev, MevMsg};
+use solana_runtime::mev::{
+    arbitrage::get_swap_pools, failures::ExecutionFailure, utils::B58Pubkey,
+    ExecutedTransactionOutput, Mev, MevMsg,
+};
 use {
     crate::{
         forward_packet_batches_by_accounts::ForwardPacketBatchesByAccounts,
@@ -138,8 +141,11 @@ pub struct ExecuteAndCommitTransactionsOutput {
     commit_transactions_result: Result<Vec<CommitTransactionDetails>, PohRecorderError>,
     execute_and_commit_timings: LeaderExecuteAndCommitTimings,
     error_counters: TransactionErrorMetrics,
+    // For MEV batches, the error of every transaction in the batch, if it
+    // failed. Empty for other batches.
+    transaction_errors: Vec<Option<TransactionError>>,
 
-    mev_sanitized_tx_profit: Option<(SanitizedTransaction, u64)>,
+    mev_sanitized_tx_profit: Vec<(SanitizedTransaction, u64)>,
 }
 
 #[derive(Debug, Default)]
@@ -1537,6 +1543,14 @@ impl BankingStage {
                 }
             })
             .collect();
+        let transaction_errors = if transaction_batch_type == TransactionBatchType::Mev {
+            execution_results
+                .iter()
+                .map(|execution_result| execution_result.flattened_result().err())
+                .collect()
+        } else {
+            Vec::new()
+        };
 
         let (last_blockhash, lamports_per_signature) =
             bank.last_blockhash_and_lamports_per_signature();
@@ -1581,6 +1595,31 @@ impl BankingStage {
         );
         retryable_transaction_indexes.extend(retryable_record_transaction_indexes);
         let transactions_attempted_execution_count = execution_results.len();
+
+        // With `confirm_trigger_before_craft`, the bank holds MEV transactions
+        // back until we know whether their trigger made it into the block.
+        let mev_triggers: Vec<_> = match mev {
+            Some(mev) if mev.confirm_trigger_before_craft => batch
+                .sanitized_transactions()
+                .iter()
+                .zip(&execution_results)
+                .zip(&transactions_execute_and_record_status)
+                .map(|((tx, execution_result), record_status)| {
+                    let committed = commit_transactions_result.is_ok()
+                        && matches!(record_status, CommitTransactionDetails::Committed { .. })
+                        && execution_result.was_executed_successfully();
+                    (*tx.signature(), committed)
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let take_mev_tx = |mev_sanitized_tx_profit: Vec<(SanitizedTransaction, u64)>| match mev {
+            Some(mev) if mev.confirm_trigger_before_craft => {
+                mev.take_committed_trigger_tx(mev_triggers)
+            }
+            _ => mev_sanitized_tx_profit,
+        };
+
         if let Err(e) = commit_transactions_result {
             return ExecuteAndCommitTransactionsOutput {
                 transactions_attempted_execution_count,
@@ -1590,7 +1629,8 @@ impl BankingStage {
                 commit_transactions_result: Err(e),
                 execute_and_commit_timings,
                 error_counters,
-                mev_sanitized_tx_profit,
+                transaction_errors,
+                mev_sanitized_tx_profit: take_mev_tx(mev_sanitized_tx_profit),
             };
         }
 
@@ -1682,7 +1722,8 @@ impl BankingStage {
             commit_transactions_result: Ok(transactions_execute_and_record_status),
             execute_and_commit_timings,
             error_counters,
-            mev_sanitized_tx_profit,
+            transaction_errors,
+            mev_sanitized_tx_profit: take_mev_tx(mev_sanitized_tx_profit),
         }
     }
 
@@ -1891,6 +1932,9 @@ impl BankingStage {
         qos_service: &QosService,
         mev: Option<&Mev>,
     ) -> ProcessTransactionsSummary {
+        if let Some(mev) = mev {
+            mev.tick(bank.slot(), bank.clock().unix_timestamp);
+        }
         let mut chunk_start = 0;
         let mut all_retryable_tx_indexes = vec![];
         // All the transactions that attempted execution. See description of
@@ -1924,6 +1968,8 @@ impl BankingStage {
                 qos_service,
                 mev,
             );
+            // Opportunities are detected while the batch executes.
+            let detected_at = Instant::now();
 
             let ProcessTransactionBatchOutput {
                 cost_model_throttled_transactions_count: new_cost_model_throttled_transactions_count,
@@ -1947,30 +1993,78 @@ impl BankingStage {
                 ..
             } = execute_and_commit_transactions_output;
 
-            if let Some((mev_sanitized_tx, profit)) = mev_sanitized_tx_profit {
+            // The transactions of a split opportunity are executed one batch
+            // after the other, in order. Every part trades against the pools
+            // as the parts before it left them, so after a part failed we do
+            // not execute the rest.
+            let mut previous_part_failed = false;
+            // Once our leader slot ended, we can no longer record our
+            // transactions, we hand them to the fallback RPC if there is one.
+            let mut slot_ended = false;
+            for (mev_sanitized_tx, profit) in mev_sanitized_tx_profit {
                 let transaction_hash = *mev_sanitized_tx.message_hash();
                 let transaction_signature = *mev_sanitized_tx.signature();
+                let mev = mev.expect("MEV should exist when executing MEV txs");
+                if slot_ended && mev.forward_transaction(&mev_sanitized_tx) {
+                    mev.release_inflight_exposure(&transaction_signature);
+                    continue;
+                }
+                if previous_part_failed {
+                    mev.release_txs(bank.slot(), [&mev_sanitized_tx]);
+                    continue;
+                }
+                let pools = get_swap_pools(&mev_sanitized_tx)
+                    .into_iter()
+                    .map(B58Pubkey)
+                    .collect();
+                let mev_batch = [mev_sanitized_tx];
                 let process_transaction_batch_output = Self::process_and_record_transactions(
                     bank,
-                    &[mev_sanitized_tx],
+                    &mev_batch,
                     poh,
                     chunk_start,
                     TransactionBatchType::Mev,
                     transaction_status_sender.clone(),
                     gossip_vote_sender,
                     qos_service,
-                    mev,
+                    Some(mev),
                 );
-                mev.expect("MEV should exist when executing MEV txs")
-                    .log_send_channel
+                let is_successful = process_transaction_batch_output
+                    .execute_and_commit_transactions_output
+                    .executed_with_successful_result_count
+                    == 1;
+                let error = process_transaction_batch_output
+                    .execute_and_commit_transactions_output
+                    .transaction_errors
+                    .first()
+                    .cloned()
+                    .flatten()
+                    .map(|err| ExecutionFailure::from_transaction_error(&err));
+                slot_ended = matches!(
+                    process_transaction_batch_output
+                        .execute_and_commit_transactions_output
+                        .commit_transactions_result,
+                    Err(PohRecorderError::MaxHeightReached)
+                );
+                if slot_ended {
+                    mev.forward_transaction(&mev_batch[0]);
+                } else {
+                    mev.record_execution(bank.slot(), error.as_ref());
+                }
+                previous_part_failed = !is_successful;
+                mev.release_inflight_exposure(&transaction_signature);
+                let reported_profit = mev.take_reported_profit(&transaction_signature);
+                mev.log_send_channel
                     .send(MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
                         transaction_hash,
                         transaction_signature,
-                        is_successful: process_transaction_batch_output
-                            .execute_and_commit_transactions_output
-                            .executed_with_successful_result_count
-                            == 1,
+                        is_successful,
                         possible_profit: profit,
+                        pools,
+                        detection_to_execution_us: detected_at.elapsed().as_micros() as u64,
+                        slot_time: bank.clock().unix_timestamp,
+                        error,
+                        reported_profit,
                     }))
                     .expect("Failed ExecutedTransaction message")
             }
@@ -172,6 +172,16 @@ impl ReplaySlotStats {
                 self.execute_timings.details.data_size_changed,
                 i64
             ),
+            (
+                "execute_details_mev_readonly_lock_count",
+                self.execute_timings.details.mev_readonly_lock_count,
+                i64
+            ),
+            (
+                "execute_details_mev_writable_lock_count",
+                self.execute_timings.details.mev_writable_lock_count,
+                i64
+            ),
             (
                 "execute_details_create_executor_register_syscalls_us",
                 self.execute_timings
@@ -1,7 +1,7 @@
 //! The `validator` module hosts all the validator microservices.
 
 pub use solana_perf::report_target_features;
-use solana_runtime::mev::{utils::get_mev_config_file, Mev, MevLog, MevMsg};
+use solana_runtime::mev::{utils::get_mev_config_file, Mev, MevLog, MevMsg, ValidatorAttribution};
 use {
     crate::{
         broadcast_stage::BroadcastStageType,
@@ -502,9 +502,40 @@ impl Validator {
                 info!("MEV enabled with config path: {:?}", config_path);
                 let mev_config = get_mev_config_file(config_path);
                 info!("Watching programs: {:?}", mev_config.watched_programs);
-                let mev_log = MevLog::new(&mev_config);
-                let mev = Mev::new(mev_log.log_send_channel.clone(), mev_config);
-                (Some(mev_log), Some(mev))
+                let validator_attribution = Some(ValidatorAttribution {
+                    identity: id,
+                    vote_account: Some(*vote_account),
+                });
+                let mev_event_notifier = geyser_plugin_service
+                    .as_ref()
+                    .filter(|_| mev_config.geyser_notifications)
+                    .and_then(|geyser_plugin_service| {
+                        geyser_plugin_service.get_mev_event_notifier()
+                    });
+                let mev_log = match mev_event_notifier {
+                    Some(notifier) => {
+                        MevLog::with_notifier(&mev_config, validator_attribution, notifier)
+                    }
+                    None => MevLog::new(&mev_config, validator_attribution),
+                };
+                // A config that parses but is not valid should not take down
+                // the validator, it keeps running without MEV.
+                match Mev::try_new(mev_log.log_send_channel.clone(), mev_config) {
+                    Ok(mev) => {
+                        mev.log_config();
+                        #[cfg(unix)]
+                        spawn_mev_status_signal_handler(mev.clone());
+                        (Some(mev_log), Some(mev))
+                    }
+                    Err(err) => {
+                        error!(
+                            "MEV is DISABLED, the MEV config at {:?} is not valid: {}",
+                            config_path, err
+                        );
+                        datapoint_error!("mev-disabled", ("error", err.to_string(), String));
+                        (None, None)
+                    }
+                }
             }
             None => ((None, None)),
         };
@@ -1193,6 +1224,33 @@ impl Validator {
     }
 }
 
+/// Write the MEV status next to the MEV log on every SIGUSR1, see
+/// `Mev::write_status`, and log the config that MEV runs with, see
+/// `Mev::log_config`. The validator also reopens its log file on SIGUSR1,
+/// both handlers run.
+#[cfg(unix)]
+fn spawn_mev_status_signal_handler(mev: Mev) {
+    let mut signals = match signal_hook::iterator::Signals::new(&[signal_hook::consts::SIGUSR1]) {
+        Ok(signals) => signals,
+        Err(err) => {
+            warn!(
+                "Unable to register SIGUSR1 handler for the MEV status: {:?}",
+                err
+            );
+            return;
+        }
+    };
+    Builder::new()
+        .name("solMevStatus".to_string())
+        .spawn(move || {
+            for _signal in signals.forever() {
+                mev.write_status();
+                mev.log_config();
+            }
+        })
+        .unwrap();
+}
+
 fn active_vote_account_exists_in_bank(bank: &Arc<Bank>, vote_account: &Pubkey) -> bool {
     if let Some(account) = &bank.get_account(vote_account) {
         if let Some(vote_state) = VoteState::from(account) {
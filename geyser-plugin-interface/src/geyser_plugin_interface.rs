@@ -83,6 +83,19 @@ pub enum ReplicaBlockInfoVersions<'a> {
     V0_0_1(&'a ReplicaBlockInfo<'a>),
 }
 
+/// An event from the MEV module, e.g. an opportunity that it found, or the
+/// outcome of a transaction that it crafted.
+#[derive(Clone, Debug)]
+pub struct ReplicaMevEventInfo<'a> {
+    /// The event serialized as JSON, in the same format as in the MEV log
+    /// file: an object with the kind of `event`, and its `data`.
+    pub event: &'a str,
+}
+
+pub enum ReplicaMevEventInfoVersions<'a> {
+    V0_0_1(&'a ReplicaMevEventInfo<'a>),
+}
+
 /// Errors returned by plugin calls
 #[derive(Error, Debug)]
 pub enum GeyserPluginError {
@@ -206,6 +219,13 @@ pub trait GeyserPlugin: Any + Send + Sync + std::fmt::Debug {
         Ok(())
     }
 
+    /// Called for every event from the MEV module, in the order in which they
+    /// are written to the MEV log.
+    #[allow(unused_variables)]
+    fn notify_mev_event(&mut self, event: ReplicaMevEventInfoVersions) -> Result<()> {
+        Ok(())
+    }
+
     /// Check if the plugin is interested in account data
     /// Default is true -- if the plugin is not interested in
     /// account data, please return false.
@@ -219,4 +239,11 @@ pub trait GeyserPlugin: Any + Send + Sync + std::fmt::Debug {
     fn transaction_notifications_enabled(&self) -> bool {
         false
     }
+
+    /// Check if the plugin is interested in MEV events
+    /// Default is false -- if the plugin is not interested in
+    /// MEV events, please return false.
+    fn mev_event_notifications_enabled(&self) -> bool {
+        false
+    }
 }
@@ -72,4 +72,14 @@ impl GeyserPluginManager {
         }
         false
     }
+
+    /// Check if there is any plugin interested in MEV events
+    pub fn mev_event_notifications_enabled(&self) -> bool {
+        for plugin in &self.plugins {
+            if plugin.mev_event_notifications_enabled() {
+                return true;
+            }
+        }
+        false
+    }
 }
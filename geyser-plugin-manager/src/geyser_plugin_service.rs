@@ -3,8 +3,9 @@ use {
         accounts_update_notifier::AccountsUpdateNotifierImpl,
         block_metadata_notifier::BlockMetadataNotifierImpl,
         block_metadata_notifier_interface::BlockMetadataNotifierLock,
-        geyser_plugin_manager::GeyserPluginManager, slot_status_notifier::SlotStatusNotifierImpl,
-        slot_status_observer::SlotStatusObserver, transaction_notifier::TransactionNotifierImpl,
+        geyser_plugin_manager::GeyserPluginManager, mev_event_notifier::MevEventNotifierImpl,
+        slot_status_notifier::SlotStatusNotifierImpl, slot_status_observer::SlotStatusObserver,
+        transaction_notifier::TransactionNotifierImpl,
     },
     crossbeam_channel::Receiver,
     log::*,
@@ -12,7 +13,9 @@ use {
         optimistically_confirmed_bank_tracker::BankNotification,
         transaction_notifier_interface::TransactionNotifierLock,
     },
-    solana_runtime::accounts_update_notifier_interface::AccountsUpdateNotifier,
+    solana_runtime::{
+        accounts_update_notifier_interface::AccountsUpdateNotifier, mev::MevEventNotifier,
+    },
     std::{
         fs::File,
         io::Read,
@@ -51,6 +54,7 @@ pub struct GeyserPluginService {
     accounts_update_notifier: Option<AccountsUpdateNotifier>,
     transaction_notifier: Option<TransactionNotifierLock>,
     block_metadata_notifier: Option<BlockMetadataNotifierLock>,
+    mev_event_notifier: Option<Arc<dyn MevEventNotifier>>,
 }
 
 impl GeyserPluginService {
@@ -83,6 +87,7 @@ impl GeyserPluginService {
         let account_data_notifications_enabled =
             plugin_manager.account_data_notifications_enabled();
         let transaction_notifications_enabled = plugin_manager.transaction_notifications_enabled();
+        let mev_event_notifications_enabled = plugin_manager.mev_event_notifications_enabled();
 
         let plugin_manager = Arc::new(RwLock::new(plugin_manager));
 
@@ -103,6 +108,13 @@ impl GeyserPluginService {
                 None
             };
 
+        let mev_event_notifier: Option<Arc<dyn MevEventNotifier>> =
+            if mev_event_notifications_enabled {
+                Some(Arc::new(MevEventNotifierImpl::new(plugin_manager.clone())))
+            } else {
+                None
+            };
+
         let (slot_status_observer, block_metadata_notifier): (
             Option<SlotStatusObserver>,
             Option<BlockMetadataNotifierLock>,
@@ -129,6 +141,7 @@ impl GeyserPluginService {
             accounts_update_notifier,
             transaction_notifier,
             block_metadata_notifier,
+            mev_event_notifier,
         })
     }
 
@@ -208,6 +221,10 @@ impl GeyserPluginService {
         self.block_metadata_notifier.clone()
     }
 
+    pub fn get_mev_event_notifier(&self) -> Option<Arc<dyn MevEventNotifier>> {
+        self.mev_event_notifier.clone()
+    }
+
     pub fn join(self) -> thread::Result<()> {
         if let Some(mut slot_status_observer) = self.slot_status_observer {
             slot_status_observer.join()?;
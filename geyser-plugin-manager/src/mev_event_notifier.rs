@@ -0,0 +1,68 @@
+/// Module responsible for notifying plugins of MEV events
+use {
+    crate::geyser_plugin_manager::GeyserPluginManager,
+    log::*,
+    solana_geyser_plugin_interface::geyser_plugin_interface::{
+        ReplicaMevEventInfo, ReplicaMevEventInfoVersions,
+    },
+    solana_measure::measure::Measure,
+    solana_metrics::*,
+    solana_runtime::mev::MevEventNotifier,
+    std::sync::{Arc, RwLock},
+};
+
+/// This implementation of MevEventNotifier is passed to the MEV log thread at
+/// the validator startup, when MEV is enabled with `geyser_notifications`. The
+/// thread invokes notify_mev_event for every event that it logs, and the
+/// implementation in turn invokes the notify_mev_event of each plugin enabled
+/// with MEV event notification managed by the GeyserPluginManager.
+pub(crate) struct MevEventNotifierImpl {
+    plugin_manager: Arc<RwLock<GeyserPluginManager>>,
+}
+
+impl MevEventNotifier for MevEventNotifierImpl {
+    fn notify_mev_event(&self, event: &str) {
+        let mut measure = Measure::start("geyser-plugin-notify_plugins_of_mev_event");
+        let mev_event_info = ReplicaMevEventInfo { event };
+
+        let mut plugin_manager = self.plugin_manager.write().unwrap();
+
+        if plugin_manager.plugins.is_empty() {
+            return;
+        }
+
+        for plugin in plugin_manager.plugins.iter_mut() {
+            if !plugin.mev_event_notifications_enabled() {
+                continue;
+            }
+            match plugin.notify_mev_event(ReplicaMevEventInfoVersions::V0_0_1(&mev_event_info)) {
+                Err(err) => {
+                    error!(
+                        "Failed to notify MEV event, error: ({}) to plugin {}",
+                        err,
+                        plugin.name()
+                    )
+                }
+                Ok(_) => {
+                    trace!(
+                        "Successfully notified MEV event to plugin {}",
+                        plugin.name()
+                    );
+                }
+            }
+        }
+        measure.stop();
+        inc_new_counter_debug!(
+            "geyser-plugin-notify_plugins_of_mev_event-us",
+            measure.as_us() as usize,
+            10000,
+            10000
+        );
+    }
+}
+
+impl MevEventNotifierImpl {
+    pub fn new(plugin_manager: Arc<RwLock<GeyserPluginManager>>) -> Self {
+        Self { plugin_manager }
+    }
+}
@@ -6,8 +6,10 @@ use solana_program::{
     instruction::{AccountMeta, Instruction},
     program::invoke,
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
 };
+use spl_token::state::Account;
 use spl_token_swap::instruction::{swap, Swap};
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -16,66 +18,145 @@ struct SwapParams {
     minimum_amount_out: u64,
 }
 
+/// Swap A to B, and then straight back from B to A. The pool ends up where it
+/// started, up to the fees, so there is no opportunity in it, even though the
+/// price moved within the instruction.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct RoundTripSwapParams {
+    amount_in: u64,
+    minimum_amount_out: u64,
+    /// When 0, we swap back all that the first swap got us.
+    reverse_amount_in: u64,
+    reverse_minimum_amount_out: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+enum InnerSwapInstruction {
+    Swap(SwapParams),
+    RoundTripSwap(RoundTripSwapParams),
+}
+
+/// The accounts of a swap, in the order of the instruction's accounts.
+#[derive(Clone, Copy)]
+struct SwapAccounts<'a, 'b> {
+    token_swap_program: &'a AccountInfo<'b>,
+    swap_info: &'a AccountInfo<'b>,
+    authority_info: &'a AccountInfo<'b>,
+    user_transfer_authority_info: &'a AccountInfo<'b>,
+    source_info: &'a AccountInfo<'b>,
+    swap_source_info: &'a AccountInfo<'b>,
+    swap_destination_info: &'a AccountInfo<'b>,
+    destination_info: &'a AccountInfo<'b>,
+    pool_mint_info: &'a AccountInfo<'b>,
+    pool_fee_account_info: &'a AccountInfo<'b>,
+    token_program_info: &'a AccountInfo<'b>,
+}
+
+impl<'a, 'b> SwapAccounts<'a, 'b> {
+    fn new(accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+        Ok(SwapAccounts {
+            token_swap_program: next_account_info(account_info_iter)?,
+            swap_info: next_account_info(account_info_iter)?,
+            authority_info: next_account_info(account_info_iter)?,
+            user_transfer_authority_info: next_account_info(account_info_iter)?,
+            source_info: next_account_info(account_info_iter)?,
+            swap_source_info: next_account_info(account_info_iter)?,
+            swap_destination_info: next_account_info(account_info_iter)?,
+            destination_info: next_account_info(account_info_iter)?,
+            pool_mint_info: next_account_info(account_info_iter)?,
+            pool_fee_account_info: next_account_info(account_info_iter)?,
+            token_program_info: next_account_info(account_info_iter)?,
+        })
+    }
+
+    /// The same accounts, for a swap in the other direction.
+    fn reversed(&self) -> Self {
+        SwapAccounts {
+            source_info: self.destination_info,
+            swap_source_info: self.swap_destination_info,
+            swap_destination_info: self.swap_source_info,
+            destination_info: self.source_info,
+            ..*self
+        }
+    }
+
+    fn destination_balance(&self) -> Result<u64, ProgramError> {
+        Ok(Account::unpack(&self.destination_info.data.borrow())?.amount)
+    }
+
+    fn invoke_swap(&self, amount_in: u64, minimum_amount_out: u64) -> ProgramResult {
+        let swap_ix = swap(
+            self.token_swap_program.key,
+            &spl_token::id(),
+            self.swap_info.key,
+            self.authority_info.key,
+            self.user_transfer_authority_info.key,
+            self.source_info.key,
+            self.swap_source_info.key,
+            self.swap_destination_info.key,
+            self.destination_info.key,
+            self.pool_mint_info.key,
+            self.pool_fee_account_info.key,
+            None,
+            Swap {
+                amount_in,
+                minimum_amount_out,
+            },
+        )?;
+        invoke(
+            &swap_ix,
+            &[
+                self.swap_info.clone(),
+                self.authority_info.clone(),
+                self.user_transfer_authority_info.clone(),
+                self.source_info.clone(),
+                self.swap_source_info.clone(),
+                self.swap_destination_info.clone(),
+                self.destination_info.clone(),
+                self.pool_mint_info.clone(),
+                self.pool_fee_account_info.clone(),
+                self.token_program_info.clone(),
+                self.token_swap_program.clone(),
+            ],
+        )
+    }
+}
+
 entrypoint!(process_instruction);
 fn process_instruction(
     _program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    let token_swap_program = next_account_info(account_info_iter)?;
-    let swap_info = next_account_info(account_info_iter)?;
-    let authority_info = next_account_info(account_info_iter)?;
-    let user_transfer_authority_info = next_account_info(account_info_iter)?;
-    let source_info = next_account_info(account_info_iter)?;
-    let swap_source_info = next_account_info(account_info_iter)?;
-    let swap_destination_info = next_account_info(account_info_iter)?;
-    let destination_info = next_account_info(account_info_iter)?;
-    let pool_mint_info = next_account_info(account_info_iter)?;
-    let pool_fee_account_info = next_account_info(account_info_iter)?;
-    let token_program_info = next_account_info(account_info_iter)?;
-
-    let swap_params = SwapParams::try_from_slice(instruction_data)?;
-
-    let swap_ix = swap(
-        token_swap_program.key,
-        &spl_token::id(),
-        swap_info.key,
-        authority_info.key,
-        user_transfer_authority_info.key,
-        source_info.key,
-        swap_source_info.key,
-        swap_destination_info.key,
-        destination_info.key,
-        pool_mint_info.key,
-        pool_fee_account_info.key,
-        None,
-        Swap {
-            amount_in: swap_params.amount_in,
-            minimum_amount_out: swap_params.minimum_amount_out,
-        },
-    )?;
-    invoke(
-        &swap_ix,
-        &[
-            swap_info.clone(),
-            authority_info.clone(),
-            user_transfer_authority_info.clone(),
-            source_info.clone(),
-            swap_source_info.clone(),
-            swap_destination_info.clone(),
-            destination_info.clone(),
-            pool_mint_info.clone(),
-            pool_fee_account_info.clone(),
-            token_program_info.clone(),
-            token_swap_program.clone(),
-        ],
-    )?;
-    Ok(())
+    let swap_accounts = SwapAccounts::new(accounts)?;
+    match InnerSwapInstruction::try_from_slice(instruction_data)? {
+        InnerSwapInstruction::Swap(swap_params) => {
+            swap_accounts.invoke_swap(swap_params.amount_in, swap_params.minimum_amount_out)
+        }
+        InnerSwapInstruction::RoundTripSwap(round_trip_params) => {
+            let balance_before = swap_accounts.destination_balance()?;
+            swap_accounts.invoke_swap(
+                round_trip_params.amount_in,
+                round_trip_params.minimum_amount_out,
+            )?;
+            let reverse_amount_in = match round_trip_params.reverse_amount_in {
+                0 => swap_accounts
+                    .destination_balance()?
+                    .saturating_sub(balance_before),
+                amount => amount,
+            };
+            swap_accounts.reversed().invoke_swap(
+                reverse_amount_in,
+                round_trip_params.reverse_minimum_amount_out,
+            )
+        }
+    }
 }
 
-pub fn inner_swap(
-    program_id: &Pubkey,
+/// The accounts of `inner_swap` and `round_trip_swap`.
+#[allow(clippy::too_many_arguments)]
+fn swap_account_metas(
     token_swap_program: &Pubkey,
     token_program_id: &Pubkey,
     swap_pubkey: &Pubkey,
@@ -87,16 +168,8 @@ pub fn inner_swap(
     destination_pubkey: &Pubkey,
     pool_mint_pubkey: &Pubkey,
     pool_fee_pubkey: &Pubkey,
-    amount_in: u64,
-    minimum_amount_out: u64,
-) -> Result<Instruction, ProgramError> {
-    let data = SwapParams {
-        amount_in,
-        minimum_amount_out,
-    }
-    .try_to_vec()?;
-
-    let accounts = vec![
+) -> Vec<AccountMeta> {
+    vec![
         AccountMeta::new_readonly(*token_swap_program, false),
         AccountMeta::new_readonly(*swap_pubkey, false),
         AccountMeta::new_readonly(*authority_pubkey, false),
@@ -108,11 +181,95 @@ pub fn inner_swap(
         AccountMeta::new(*pool_mint_pubkey, false),
         AccountMeta::new(*pool_fee_pubkey, false),
         AccountMeta::new_readonly(*token_program_id, false),
-    ];
+    ]
+}
+
+pub fn inner_swap(
+    program_id: &Pubkey,
+    token_swap_program: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InnerSwapInstruction::Swap(SwapParams {
+        amount_in,
+        minimum_amount_out,
+    })
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: *program_id,
+        accounts: swap_account_metas(
+            token_swap_program,
+            token_program_id,
+            swap_pubkey,
+            authority_pubkey,
+            user_transfer_authority_pubkey,
+            source_pubkey,
+            swap_source_pubkey,
+            swap_destination_pubkey,
+            destination_pubkey,
+            pool_mint_pubkey,
+            pool_fee_pubkey,
+        ),
+        data,
+    })
+}
+
+/// Swap `amount_in` from `source_pubkey` into `destination_pubkey`, and then
+/// `reverse_amount_in` back, in a single instruction. With a
+/// `reverse_amount_in` of 0, we swap back all that the first swap got us.
+#[allow(clippy::too_many_arguments)]
+pub fn round_trip_swap(
+    program_id: &Pubkey,
+    token_swap_program: &Pubkey,
+    token_program_id: &Pubkey,
+    swap_pubkey: &Pubkey,
+    authority_pubkey: &Pubkey,
+    user_transfer_authority_pubkey: &Pubkey,
+    source_pubkey: &Pubkey,
+    swap_source_pubkey: &Pubkey,
+    swap_destination_pubkey: &Pubkey,
+    destination_pubkey: &Pubkey,
+    pool_mint_pubkey: &Pubkey,
+    pool_fee_pubkey: &Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    reverse_amount_in: u64,
+    reverse_minimum_amount_out: u64,
+) -> Result<Instruction, ProgramError> {
+    let data = InnerSwapInstruction::RoundTripSwap(RoundTripSwapParams {
+        amount_in,
+        minimum_amount_out,
+        reverse_amount_in,
+        reverse_minimum_amount_out,
+    })
+    .try_to_vec()?;
 
     Ok(Instruction {
         program_id: *program_id,
-        accounts,
+        accounts: swap_account_metas(
+            token_swap_program,
+            token_program_id,
+            swap_pubkey,
+            authority_pubkey,
+            user_transfer_authority_pubkey,
+            source_pubkey,
+            swap_source_pubkey,
+            swap_destination_pubkey,
+            destination_pubkey,
+            pool_mint_pubkey,
+            pool_fee_pubkey,
+        ),
         data,
     })
 }
@@ -0,0 +1,117 @@
+use serde::Serialize;
+use solana_client::rpc_client::RpcClient;
+use spl_token::{
+    solana_program::{program_pack::Pack, pubkey::Pubkey},
+    state::{Account, Mint},
+};
+
+/// A token amount as we print it: the raw amount, and the amount in whole
+/// tokens according to the decimals of its mint.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenAmount {
+    pub amount: u64,
+    pub ui_amount: String,
+}
+
+impl TokenAmount {
+    pub fn new(amount: u64, decimals: u8) -> Self {
+        TokenAmount {
+            amount,
+            ui_amount: format_ui_amount(amount, decimals),
+        }
+    }
+}
+
+/// Format a raw `amount` of a mint with `decimals` in whole tokens, without
+/// trailing zeros, e.g. 1500000 with 6 decimals is "1.5". We format the
+/// digits rather than divide, so large amounts stay exact.
+pub fn format_ui_amount(amount: u64, decimals: u8) -> String {
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", amount, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// Get the decimals of the mints of `token_accounts`, in the same order.
+///
+/// We read the token accounts to learn their mints, and then all the mints with
+/// a single `get_multiple_accounts` call.
+pub fn get_mint_decimals(
+    rpc_client: &RpcClient,
+    token_accounts: &[Pubkey],
+) -> Result<Vec<u8>, String> {
+    let mints = rpc_client
+        .get_multiple_accounts(token_accounts)
+        .map_err(|err| format!("Could not get token accounts: {}", err))?
+        .into_iter()
+        .zip(token_accounts)
+        .map(|(account, address)| {
+            let account =
+                account.ok_or_else(|| format!("Token account {} does not exist", address))?;
+            Account::unpack(&account.data)
+                .map(|token_account| token_account.mint)
+                .map_err(|err| format!("Invalid token account {}: {}", address, err))
+        })
+        .collect::<Result<Vec<Pubkey>, String>>()?;
+
+    rpc_client
+        .get_multiple_accounts(&mints)
+        .map_err(|err| format!("Could not get mints: {}", err))?
+        .into_iter()
+        .zip(&mints)
+        .map(|(account, address)| {
+            let account = account.ok_or_else(|| format!("Mint {} does not exist", address))?;
+            Mint::unpack(&account.data)
+                .map(|mint| mint.decimals)
+                .map_err(|err| format!("Invalid mint {}: {}", address, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_ui_amount_without_decimals() {
+        assert_eq!(format_ui_amount(0, 0), "0");
+        assert_eq!(format_ui_amount(1_500_000, 0), "1500000");
+    }
+
+    #[test]
+    fn test_format_ui_amount_with_6_decimals() {
+        assert_eq!(format_ui_amount(0, 6), "0");
+        assert_eq!(format_ui_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_ui_amount(1_000_000, 6), "1");
+        assert_eq!(format_ui_amount(1, 6), "0.000001");
+        assert_eq!(format_ui_amount(123_456_789, 6), "123.456789");
+    }
+
+    #[test]
+    fn test_format_ui_amount_with_9_decimals() {
+        assert_eq!(format_ui_amount(1_500_000, 9), "0.0015");
+        assert_eq!(format_ui_amount(2_000_000_000, 9), "2");
+        assert_eq!(format_ui_amount(1, 9), "0.000000001");
+    }
+
+    #[test]
+    fn test_format_ui_amount_large_amounts() {
+        assert_eq!(format_ui_amount(u64::MAX, 0), "18446744073709551615");
+        assert_eq!(format_ui_amount(u64::MAX, 9), "18446744073.709551615");
+        assert_eq!(format_ui_amount(u64::MAX, 20), "0.18446744073709551615");
+        assert_eq!(format_ui_amount(u64::MAX, 255).len(), 257);
+    }
+
+    #[test]
+    fn test_token_amount_json() {
+        assert_eq!(
+            serde_json::to_string(&TokenAmount::new(1_500_000, 6)).unwrap(),
+            r#"{"amount":1500000,"ui_amount":"1.5"}"#
+        );
+    }
+}
@@ -1,13 +1,17 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use amount::{get_mint_decimals, TokenAmount};
+use clap::{CommandFactory, ErrorKind, Parser, Subcommand, ValueEnum};
+use pool_file::{read_pool_file, resolve_pool_accounts, PoolAccounts, PoolOverrides};
 use solana_client::rpc_client::RpcClient;
 use solana_program::pubkey::Pubkey;
 use solana_sdk::{commitment_config::CommitmentConfig, signature::read_keypair_file};
-use utils::{create_token_pool, get_default_keypair_path, inner_swap};
+use utils::{create_token_pool, get_default_keypair_path, inner_swap, round_trip_swap};
 
 use crate::utils::swap_tokens;
 
+mod amount;
+mod pool_file;
 mod utils;
 
 #[derive(Parser, Debug)]
@@ -22,15 +26,27 @@ pub struct Opts {
     #[clap(long)]
     signer_path: Option<PathBuf>,
 
+    /// Required for `init`, for the swaps it can come from `--pool-file`
+    /// instead.
     #[clap(long)]
-    token_swap_a_account: Pubkey,
+    token_swap_a_account: Option<Pubkey>,
     #[clap(long)]
-    token_swap_b_account: Pubkey,
+    token_swap_b_account: Option<Pubkey>,
+
+    #[clap(long, value_enum, default_value = "json")]
+    output: OutputFormat,
 
     #[clap(subcommand)]
     subcommand: OptSubcommand,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    /// A table of names and values.
+    Text,
+    Json,
+}
+
 #[derive(Parser, Debug)]
 struct InitializeTokenSwap {
     #[clap(long, default_value = "25")]
@@ -51,18 +67,28 @@ struct InitializeTokenSwap {
     host_fee_denominator: u64,
 }
 
+/// The pool to swap through, either from a file printed by `init`, or from
+/// flags. Flags take precedence over the file.
 #[derive(Parser, Debug)]
-struct SwapTokens {
+struct PoolOpts {
     #[clap(long)]
-    token_swap_account: Pubkey,
+    pool_file: Option<PathBuf>,
     #[clap(long)]
-    token_a_client: Pubkey,
+    token_swap_account: Option<Pubkey>,
     #[clap(long)]
-    token_b_client: Pubkey,
+    pool_mint: Option<Pubkey>,
+    #[clap(long)]
+    pool_fee: Option<Pubkey>,
+}
+
+#[derive(Parser, Debug)]
+struct SwapTokens {
+    #[clap(flatten)]
+    pool: PoolOpts,
     #[clap(long)]
-    pool_mint: Pubkey,
+    token_a_client: Pubkey,
     #[clap(long)]
-    pool_fee: Pubkey,
+    token_b_client: Pubkey,
     #[clap(long)]
     amount: u64,
     #[clap(long)]
@@ -73,20 +99,41 @@ struct SwapTokens {
 struct InnerSwap {
     #[clap(long)]
     caller_account: Pubkey,
-    #[clap(long)]
-    token_swap_account: Pubkey,
+    #[clap(flatten)]
+    pool: PoolOpts,
     #[clap(long)]
     token_a_client: Pubkey,
     #[clap(long)]
     token_b_client: Pubkey,
     #[clap(long)]
-    pool_mint: Pubkey,
+    amount: u64,
+    #[clap(long)]
+    minimum_amount_out: u64,
+}
+
+/// Swap A to B and then B back to A, in a single instruction of the inner
+/// swap program. This moves the price of the pool within the transaction, but
+/// leaves it where it was, up to the fees.
+#[derive(Parser, Debug)]
+struct RoundTripSwap {
+    #[clap(long)]
+    caller_account: Pubkey,
+    #[clap(flatten)]
+    pool: PoolOpts,
+    #[clap(long)]
+    token_a_client: Pubkey,
     #[clap(long)]
-    pool_fee: Pubkey,
+    token_b_client: Pubkey,
     #[clap(long)]
     amount: u64,
     #[clap(long)]
     minimum_amount_out: u64,
+    /// The amount of B to swap back into A, by default all that the first
+    /// swap got us.
+    #[clap(long, default_value = "0")]
+    reverse_amount_in: u64,
+    #[clap(long)]
+    reverse_minimum_amount_out: u64,
 }
 
 #[derive(Debug, Subcommand)]
@@ -94,17 +141,71 @@ enum OptSubcommand {
     Init(InitializeTokenSwap),
     Swap(SwapTokens),
     InnerSwap(InnerSwap),
+    RoundTripSwap(RoundTripSwap),
+}
+
+/// Print `message` as a usage error, and exit.
+fn exit_with_error(kind: ErrorKind, message: String) -> ! {
+    Opts::command().error(kind, message).exit()
+}
+
+fn get_pool_accounts(opts: &Opts, pool_opts: &PoolOpts) -> PoolAccounts {
+    let pool = pool_opts
+        .pool_file
+        .as_ref()
+        .map(|path| read_pool_file(path).unwrap_or_else(|err| exit_with_error(ErrorKind::Io, err)));
+    let overrides = PoolOverrides {
+        token_swap_account: pool_opts.token_swap_account,
+        token_swap_a_account: opts.token_swap_a_account,
+        token_swap_b_account: opts.token_swap_b_account,
+        pool_mint: pool_opts.pool_mint,
+        pool_fee: pool_opts.pool_fee,
+    };
+    resolve_pool_accounts(&opts.token_swap_program_id, pool.as_ref(), &overrides)
+        .unwrap_or_else(|err| exit_with_error(ErrorKind::ValueValidation, err))
+}
+
+/// The amounts of a swap from the token of `token_a_client` into the token of
+/// `token_b_client`, with the decimals of their mints.
+fn get_swap_amounts(
+    rpc_client: &RpcClient,
+    token_a_client: &Pubkey,
+    token_b_client: &Pubkey,
+    amount: u64,
+    minimum_amount_out: u64,
+) -> (TokenAmount, TokenAmount) {
+    let decimals = get_mint_decimals(rpc_client, &[*token_a_client, *token_b_client])
+        .unwrap_or_else(|err| exit_with_error(ErrorKind::Io, err));
+    (
+        TokenAmount::new(amount, decimals[0]),
+        TokenAmount::new(minimum_amount_out, decimals[1]),
+    )
 }
 
 fn main() {
     let opts = Opts::parse();
-    let signer_path = opts.signer_path.unwrap_or(get_default_keypair_path());
+    let signer_path = opts
+        .signer_path
+        .clone()
+        .unwrap_or_else(get_default_keypair_path);
     let rpc_client =
         RpcClient::new_with_commitment(opts.cluster.clone(), CommitmentConfig::confirmed());
     let signer_keypair = read_keypair_file(signer_path).unwrap();
 
-    let tx_output = match opts.subcommand {
+    let tx_output = match &opts.subcommand {
         OptSubcommand::Init(init_opts) => {
+            let missing = |flag: &str| {
+                exit_with_error(
+                    ErrorKind::MissingRequiredArgument,
+                    format!("--{} is required for init", flag),
+                )
+            };
+            let token_swap_a_account = opts
+                .token_swap_a_account
+                .unwrap_or_else(|| missing("token-swap-a-account"));
+            let token_swap_b_account = opts
+                .token_swap_b_account
+                .unwrap_or_else(|| missing("token-swap-b-account"));
             let fees = spl_token_swap::curve::fees::Fees {
                 trade_fee_numerator: init_opts.trade_fee_numerator,
                 trade_fee_denominator: init_opts.trade_fee_denominator,
@@ -119,40 +220,97 @@ fn main() {
                 &rpc_client,
                 &signer_keypair,
                 &opts.token_swap_program_id,
-                &opts.token_swap_a_account,
-                &opts.token_swap_b_account,
+                &token_swap_a_account,
+                &token_swap_b_account,
                 fees,
             )
         }
-        OptSubcommand::Swap(swap_opts) => swap_tokens(
-            &rpc_client,
-            &signer_keypair,
-            &opts.token_swap_program_id,
-            &swap_opts.token_swap_account,
-            &swap_opts.token_a_client,
-            &opts.token_swap_a_account,
-            &opts.token_swap_b_account,
-            &swap_opts.token_b_client,
-            &swap_opts.pool_mint,
-            &swap_opts.pool_fee,
-            swap_opts.amount,
-            swap_opts.minimum_amount_out,
-        ),
-        OptSubcommand::InnerSwap(inner_swap_opts) => inner_swap(
-            &rpc_client,
-            &signer_keypair,
-            &inner_swap_opts.caller_account,
-            &opts.token_swap_program_id,
-            &inner_swap_opts.token_swap_account,
-            &inner_swap_opts.token_a_client,
-            &opts.token_swap_a_account,
-            &opts.token_swap_b_account,
-            &inner_swap_opts.token_b_client,
-            &inner_swap_opts.pool_mint,
-            &inner_swap_opts.pool_fee,
-            inner_swap_opts.amount,
-            inner_swap_opts.minimum_amount_out,
-        ),
+        OptSubcommand::Swap(swap_opts) => {
+            let pool = get_pool_accounts(&opts, &swap_opts.pool);
+            let (amount, minimum_amount_out) = get_swap_amounts(
+                &rpc_client,
+                &swap_opts.token_a_client,
+                &swap_opts.token_b_client,
+                swap_opts.amount,
+                swap_opts.minimum_amount_out,
+            );
+            swap_tokens(
+                &rpc_client,
+                &signer_keypair,
+                &opts.token_swap_program_id,
+                &pool.token_swap_account,
+                &swap_opts.token_a_client,
+                &pool.token_swap_a_account,
+                &pool.token_swap_b_account,
+                &swap_opts.token_b_client,
+                &pool.pool_mint,
+                &pool.pool_fee,
+                amount,
+                minimum_amount_out,
+            )
+        }
+        OptSubcommand::InnerSwap(inner_swap_opts) => {
+            let pool = get_pool_accounts(&opts, &inner_swap_opts.pool);
+            let (amount, minimum_amount_out) = get_swap_amounts(
+                &rpc_client,
+                &inner_swap_opts.token_a_client,
+                &inner_swap_opts.token_b_client,
+                inner_swap_opts.amount,
+                inner_swap_opts.minimum_amount_out,
+            );
+            inner_swap(
+                &rpc_client,
+                &signer_keypair,
+                &inner_swap_opts.caller_account,
+                &opts.token_swap_program_id,
+                &pool.token_swap_account,
+                &inner_swap_opts.token_a_client,
+                &pool.token_swap_a_account,
+                &pool.token_swap_b_account,
+                &inner_swap_opts.token_b_client,
+                &pool.pool_mint,
+                &pool.pool_fee,
+                amount,
+                minimum_amount_out,
+            )
+        }
+        OptSubcommand::RoundTripSwap(round_trip_opts) => {
+            let pool = get_pool_accounts(&opts, &round_trip_opts.pool);
+            let (amount, minimum_amount_out) = get_swap_amounts(
+                &rpc_client,
+                &round_trip_opts.token_a_client,
+                &round_trip_opts.token_b_client,
+                round_trip_opts.amount,
+                round_trip_opts.minimum_amount_out,
+            );
+            let (reverse_amount_in, reverse_minimum_amount_out) = get_swap_amounts(
+                &rpc_client,
+                &round_trip_opts.token_b_client,
+                &round_trip_opts.token_a_client,
+                round_trip_opts.reverse_amount_in,
+                round_trip_opts.reverse_minimum_amount_out,
+            );
+            round_trip_swap(
+                &rpc_client,
+                &signer_keypair,
+                &round_trip_opts.caller_account,
+                &opts.token_swap_program_id,
+                &pool.token_swap_account,
+                &round_trip_opts.token_a_client,
+                &pool.token_swap_a_account,
+                &pool.token_swap_b_account,
+                &round_trip_opts.token_b_client,
+                &pool.pool_mint,
+                &pool.pool_fee,
+                amount,
+                minimum_amount_out,
+                reverse_amount_in,
+                reverse_minimum_amount_out,
+            )
+        }
     };
-    println!("{}", serde_json::to_string(&tx_output).unwrap());
+    match opts.output {
+        OutputFormat::Text => print!("{}", tx_output.to_text()),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&tx_output).unwrap()),
+    }
 }
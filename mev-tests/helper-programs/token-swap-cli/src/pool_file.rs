@@ -0,0 +1,173 @@
+use std::{fs::read_to_string, path::Path};
+
+use serde::Deserialize;
+use solana_program::pubkey::Pubkey;
+
+use crate::utils::TokenPool;
+
+/// A pool file holds either the `Init` output as-is, or only the pool in it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PoolFile {
+    InitOutput {
+        #[serde(rename = "SwapInit")]
+        swap_init: TokenPool,
+    },
+    Pool(TokenPool),
+}
+
+pub fn parse_pool_file(contents: &str) -> Result<TokenPool, String> {
+    match serde_json::from_str(contents) {
+        Ok(PoolFile::InitOutput { swap_init }) => Ok(swap_init),
+        Ok(PoolFile::Pool(pool)) => Ok(pool),
+        Err(err) => Err(format!("Invalid pool file: {}", err)),
+    }
+}
+
+pub fn read_pool_file(path: &Path) -> Result<TokenPool, String> {
+    let contents = read_to_string(path)
+        .map_err(|err| format!("Could not read pool file {}: {}", path.display(), err))?;
+    parse_pool_file(&contents)
+}
+
+/// The accounts of the pool that `Swap` and `InnerSwap` trade through.
+#[derive(Debug, PartialEq)]
+pub struct PoolAccounts {
+    pub token_swap_account: Pubkey,
+    pub token_swap_a_account: Pubkey,
+    pub token_swap_b_account: Pubkey,
+    pub pool_mint: Pubkey,
+    pub pool_fee: Pubkey,
+}
+
+/// Pool accounts passed as flags, these take precedence over the pool file.
+#[derive(Debug, Default)]
+pub struct PoolOverrides {
+    pub token_swap_account: Option<Pubkey>,
+    pub token_swap_a_account: Option<Pubkey>,
+    pub token_swap_b_account: Option<Pubkey>,
+    pub pool_mint: Option<Pubkey>,
+    pub pool_fee: Option<Pubkey>,
+}
+
+/// Combine the pool file, if any, with the flags. Every account must be set by
+/// at least one of the two.
+pub fn resolve_pool_accounts(
+    token_swap_program_id: &Pubkey,
+    pool: Option<&TokenPool>,
+    overrides: &PoolOverrides,
+) -> Result<PoolAccounts, String> {
+    if let Some(program_id) = pool.and_then(|pool| pool.token_swap_program_id) {
+        if program_id != *token_swap_program_id {
+            return Err(format!(
+                "The pool file belongs to token swap program {}, but --token-swap-program-id is {}",
+                program_id, token_swap_program_id
+            ));
+        }
+    }
+
+    let resolve = |flag: &str, value: Option<Pubkey>, from_file: fn(&TokenPool) -> Pubkey| {
+        value
+            .or_else(|| pool.map(from_file))
+            .ok_or_else(|| format!("Missing --{}, and no --pool-file was given", flag))
+    };
+    Ok(PoolAccounts {
+        token_swap_account: resolve("token-swap-account", overrides.token_swap_account, |pool| {
+            pool.address
+        })?,
+        token_swap_a_account: resolve(
+            "token-swap-a-account",
+            overrides.token_swap_a_account,
+            |pool| pool.token_swap_a_account,
+        )?,
+        token_swap_b_account: resolve(
+            "token-swap-b-account",
+            overrides.token_swap_b_account,
+            |pool| pool.token_swap_b_account,
+        )?,
+        pool_mint: resolve("pool-mint", overrides.pool_mint, |pool| pool.pool_mint)?,
+        pool_fee: resolve("pool-fee", overrides.pool_fee, |pool| pool.pool_fee)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_pool(token_swap_program_id: Option<Pubkey>) -> TokenPool {
+        TokenPool {
+            address: Pubkey::new_unique(),
+            pool_mint: Pubkey::new_unique(),
+            pool_fee: Pubkey::new_unique(),
+            token_swap_a_account: Pubkey::new_unique(),
+            token_swap_b_account: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            token_swap_program_id,
+        }
+    }
+
+    #[test]
+    fn test_parse_pool_file() {
+        let pool = sample_pool(Some(Pubkey::new_unique()));
+        let pool_json = serde_json::to_string(&pool).unwrap();
+        assert_eq!(parse_pool_file(&pool_json), Ok(pool.clone()));
+        let init_output = format!("{{\"SwapInit\":{}}}", pool_json);
+        assert_eq!(parse_pool_file(&init_output), Ok(pool));
+        assert!(parse_pool_file("{\"address\":\"not a pubkey\"}").is_err());
+    }
+
+    #[test]
+    fn test_resolve_from_file_only() {
+        let program_id = Pubkey::new_unique();
+        let pool = sample_pool(Some(program_id));
+        let accounts =
+            resolve_pool_accounts(&program_id, Some(&pool), &PoolOverrides::default()).unwrap();
+        assert_eq!(
+            accounts,
+            PoolAccounts {
+                token_swap_account: pool.address,
+                token_swap_a_account: pool.token_swap_a_account,
+                token_swap_b_account: pool.token_swap_b_account,
+                pool_mint: pool.pool_mint,
+                pool_fee: pool.pool_fee,
+            }
+        );
+    }
+
+    #[test]
+    fn test_resolve_flags_override_file() {
+        let program_id = Pubkey::new_unique();
+        let pool = sample_pool(None);
+        let overrides = PoolOverrides {
+            pool_mint: Some(Pubkey::new_unique()),
+            token_swap_a_account: Some(Pubkey::new_unique()),
+            ..PoolOverrides::default()
+        };
+        let accounts = resolve_pool_accounts(&program_id, Some(&pool), &overrides).unwrap();
+        assert_eq!(accounts.token_swap_account, pool.address);
+        assert_eq!(Some(accounts.pool_mint), overrides.pool_mint);
+        assert_eq!(
+            Some(accounts.token_swap_a_account),
+            overrides.token_swap_a_account
+        );
+        assert_eq!(accounts.token_swap_b_account, pool.token_swap_b_account);
+
+        // Without a file, every account needs a flag.
+        assert_eq!(
+            resolve_pool_accounts(&program_id, None, &overrides),
+            Err("Missing --token-swap-account, and no --pool-file was given".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_resolve_program_id_mismatch() {
+        let pool = sample_pool(Some(Pubkey::new_unique()));
+        let err = resolve_pool_accounts(
+            &Pubkey::new_unique(),
+            Some(&pool),
+            &PoolOverrides::default(),
+        )
+        .unwrap_err();
+        assert!(err.starts_with("The pool file belongs to token swap program"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use solana_client::rpc_client::RpcClient;
 use solana_program::{
     hash::Hash, instruction::Instruction, rent::Rent, system_instruction, sysvar,
@@ -16,6 +16,8 @@ use spl_token_swap::{
     instruction::Swap,
 };
 
+use crate::amount::TokenAmount;
+
 pub fn get_rent(rpc_client: &RpcClient) -> Rent {
     let account = rpc_client.get_account(&sysvar::rent::id()).unwrap();
     bincode::deserialize(&account.data).unwrap()
@@ -118,21 +120,147 @@ pub fn sign_and_send_transaction<T: Signers>(
 pub fn serialize_b58<S: Serializer, T: ToString>(x: &T, serializer: S) -> Result<S::Ok, S::Error> {
     serializer.serialize_str(&x.to_string())
 }
-#[derive(Serialize)]
+
+/// Function to use when serializing an optional public key, to print it using
+/// base58.
+pub fn serialize_opt_b58<S: Serializer>(
+    x: &Option<Pubkey>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match x {
+        Some(pubkey) => serializer.serialize_some(&pubkey.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Function to use when deserializing a public key from base58.
+pub fn deserialize_b58<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+/// Function to use when deserializing an optional public key from base58.
+pub fn deserialize_opt_b58<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Pubkey>, D::Error> {
+    let s: Option<String> = Option::deserialize(deserializer)?;
+    s.map(|s| s.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// The accounts of a token pool, as printed by `Init`. `Swap` and `InnerSwap`
+/// can read them back with `--pool-file`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenPool {
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub address: Pubkey,
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub pool_mint: Pubkey,
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub pool_fee: Pubkey,
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub token_swap_a_account: Pubkey,
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub token_swap_b_account: Pubkey,
+    #[serde(serialize_with = "serialize_b58", deserialize_with = "deserialize_b58")]
+    pub authority: Pubkey,
+
+    /// The program that owns the pool. When the file includes it, it must match
+    /// `--token-swap-program-id`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        serialize_with = "serialize_opt_b58",
+        deserialize_with = "deserialize_opt_b58"
+    )]
+    pub token_swap_program_id: Option<Pubkey>,
+}
+
+/// The result of `Swap` and `InnerSwap`.
+#[derive(Serialize)]
+pub struct SwapOutput {
     #[serde(serialize_with = "serialize_b58")]
-    address: Pubkey,
-    #[serde(serialize_with = "serialize_b58")]
-    pool_mint: Pubkey,
+    pub hash: Hash,
+    /// In the token of `--token-a-client`.
+    pub amount: TokenAmount,
+    /// In the token of `--token-b-client`.
+    pub minimum_amount_out: TokenAmount,
+}
+
+/// The result of `RoundTripSwap`.
+#[derive(Serialize)]
+pub struct RoundTripSwapOutput {
     #[serde(serialize_with = "serialize_b58")]
-    pool_fee: Pubkey,
+    pub hash: Hash,
+    /// In the token of `--token-a-client`.
+    pub amount: TokenAmount,
+    /// In the token of `--token-b-client`.
+    pub minimum_amount_out: TokenAmount,
+    /// In the token of `--token-b-client`.
+    pub reverse_amount_in: TokenAmount,
+    /// In the token of `--token-a-client`.
+    pub reverse_minimum_amount_out: TokenAmount,
 }
 
 #[derive(Serialize)]
 pub enum TransactionOutput {
     SwapInit(TokenPool),
-    #[serde(serialize_with = "serialize_b58")]
-    Swap(Hash),
+    Swap(SwapOutput),
+    RoundTripSwap(RoundTripSwapOutput),
+}
+
+impl TransactionOutput {
+    /// Format the output as a table of names and values, for `--output text`.
+    pub fn to_text(&self) -> String {
+        let rows: Vec<(&str, String)> = match self {
+            TransactionOutput::SwapInit(pool) => vec![
+                ("address", pool.address.to_string()),
+                ("pool_mint", pool.pool_mint.to_string()),
+                ("pool_fee", pool.pool_fee.to_string()),
+                (
+                    "token_swap_a_account",
+                    pool.token_swap_a_account.to_string(),
+                ),
+                (
+                    "token_swap_b_account",
+                    pool.token_swap_b_account.to_string(),
+                ),
+                ("authority", pool.authority.to_string()),
+            ],
+            TransactionOutput::Swap(swap) => vec![
+                ("hash", swap.hash.to_string()),
+                ("amount", format_text_amount(&swap.amount)),
+                (
+                    "minimum_amount_out",
+                    format_text_amount(&swap.minimum_amount_out),
+                ),
+            ],
+            TransactionOutput::RoundTripSwap(swap) => vec![
+                ("hash", swap.hash.to_string()),
+                ("amount", format_text_amount(&swap.amount)),
+                (
+                    "minimum_amount_out",
+                    format_text_amount(&swap.minimum_amount_out),
+                ),
+                (
+                    "reverse_amount_in",
+                    format_text_amount(&swap.reverse_amount_in),
+                ),
+                (
+                    "reverse_minimum_amount_out",
+                    format_text_amount(&swap.reverse_minimum_amount_out),
+                ),
+            ],
+        };
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        rows.iter()
+            .map(|(name, value)| format!("{:<width$}  {}\n", name, value, width = width))
+            .collect()
+    }
+}
+
+fn format_text_amount(amount: &TokenAmount) -> String {
+    format!("{} ({} raw)", amount.ui_amount, amount.amount)
 }
 
 pub fn create_token_pool(
@@ -246,6 +374,10 @@ pub fn create_token_pool(
         address: token_pool_account.pubkey(),
         pool_mint: pool_mint_pubkey,
         pool_fee: pool_fee_keypair.pubkey(),
+        token_swap_a_account: *token_a_account,
+        token_swap_b_account: *token_b_account,
+        authority: authority_pubkey,
+        token_swap_program_id: Some(*token_swap_program_id),
     })
 }
 
@@ -268,8 +400,8 @@ pub fn swap_tokens(
     token_b_client: &Pubkey,
     pool_mint: &Pubkey,
     pool_fee: &Pubkey,
-    amount: u64,
-    minimum_amount_out: u64,
+    amount: TokenAmount,
+    minimum_amount_out: TokenAmount,
 ) -> TransactionOutput {
     let (authority_pubkey, _authority_bump_seed) = Pubkey::find_program_address(
         &[&token_swap_account.to_bytes()[..]],
@@ -290,13 +422,17 @@ pub fn swap_tokens(
         pool_fee,
         None,
         Swap {
-            amount_in: amount,
-            minimum_amount_out,
+            amount_in: amount.amount,
+            minimum_amount_out: minimum_amount_out.amount,
         },
     )
     .unwrap();
     let tx = sign_and_send_transaction(&signer_keypair, &rpc_client, &[ix], &[signer_keypair]);
-    TransactionOutput::Swap(tx.message.hash())
+    TransactionOutput::Swap(SwapOutput {
+        hash: tx.message.hash(),
+        amount,
+        minimum_amount_out,
+    })
 }
 
 pub fn inner_swap(
@@ -311,8 +447,8 @@ pub fn inner_swap(
     token_b_client: &Pubkey,
     pool_mint: &Pubkey,
     pool_fee: &Pubkey,
-    amount: u64,
-    minimum_amount_out: u64,
+    amount: TokenAmount,
+    minimum_amount_out: TokenAmount,
 ) -> TransactionOutput {
     let (authority_pubkey, _authority_bump_seed) = Pubkey::find_program_address(
         &[&token_swap_account.to_bytes()[..]],
@@ -332,10 +468,67 @@ pub fn inner_swap(
         token_b_client,
         pool_mint,
         pool_fee,
+        amount.amount,
+        minimum_amount_out.amount,
+    )
+    .unwrap();
+    let tx = sign_and_send_transaction(&signer_keypair, &rpc_client, &[ix], &[signer_keypair]);
+    TransactionOutput::Swap(SwapOutput {
+        hash: tx.message.hash(),
         amount,
         minimum_amount_out,
+    })
+}
+
+/// Swap `amount` from `token_a_client` into `token_b_client`, and then
+/// `reverse_amount_in` back, in a single instruction of the inner swap program.
+pub fn round_trip_swap(
+    rpc_client: &RpcClient,
+    signer_keypair: &Keypair,
+    caller_swap_program_id: &Pubkey,
+    token_swap_program_id: &Pubkey,
+    token_swap_account: &Pubkey,
+    token_a_client: &Pubkey,
+    token_a_account: &Pubkey,
+    token_b_account: &Pubkey,
+    token_b_client: &Pubkey,
+    pool_mint: &Pubkey,
+    pool_fee: &Pubkey,
+    amount: TokenAmount,
+    minimum_amount_out: TokenAmount,
+    reverse_amount_in: TokenAmount,
+    reverse_minimum_amount_out: TokenAmount,
+) -> TransactionOutput {
+    let (authority_pubkey, _authority_bump_seed) = Pubkey::find_program_address(
+        &[&token_swap_account.to_bytes()[..]],
+        &token_swap_program_id,
+    );
+
+    let ix = inner_swap::round_trip_swap(
+        caller_swap_program_id,
+        token_swap_program_id,
+        &spl_token::id(),
+        token_swap_account,
+        &authority_pubkey,
+        &signer_keypair.pubkey(),
+        token_a_client,
+        token_a_account,
+        token_b_account,
+        token_b_client,
+        pool_mint,
+        pool_fee,
+        amount.amount,
+        minimum_amount_out.amount,
+        reverse_amount_in.amount,
+        reverse_minimum_amount_out.amount,
     )
     .unwrap();
     let tx = sign_and_send_transaction(&signer_keypair, &rpc_client, &[ix], &[signer_keypair]);
-    TransactionOutput::Swap(tx.message.hash())
+    TransactionOutput::RoundTripSwap(RoundTripSwapOutput {
+        hash: tx.message.hash(),
+        amount,
+        minimum_amount_out,
+        reverse_amount_in,
+        reverse_minimum_amount_out,
+    })
 }
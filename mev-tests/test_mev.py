@@ -26,6 +26,8 @@ from util import (
     restart_validator,
     compile_bpf_program,
     read_mev_log,
+    get_compute_units_consumed,
+    record_cu_calibration,
 )
 
 
@@ -186,6 +188,7 @@ print(f'> Token Pool created with address {token_pool_p2.token_swap_account}')
 
 ## create toml file
 d_data = {
+    'config_version': 1,
     'log_path': '/tmp/mev.log',
     'watched_programs': [token_swap_program_id],
     'user_authority_path': miner_authority_key.keypair_path,
@@ -306,6 +309,22 @@ assert mev_logs[len(mev_logs) - 1]['data']['possible_profit'] == 216
 post_balance = float(spl_token('balance', '--address', pool_tokens[1]))
 assert int(post_balance * 1e9) - int(initial_balance * 1e9) == 216
 
+# Measure what the MEV transaction consumed, to calibrate the estimate of the
+# engine, see `cu_calibration_file`.
+mev_tx_signature = mev_logs[len(mev_logs) - 1]['data']['transaction_signature']
+compute_units = get_compute_units_consumed(mev_tx_signature, token_swap_program_id)
+print(
+    f'> The MEV transaction consumed {compute_units.total} compute units, '
+    f'per hop {compute_units.per_instruction}'
+)
+assert len(compute_units.per_instruction) == 3
+assert 0 < sum(compute_units.per_instruction) <= compute_units.total
+cu_calibration_path = os.getenv(
+    'CU_CALIBRATION_PATH', f'{test_dir}/cu_calibration.json'
+)
+record_cu_calibration(cu_calibration_path, hops=3, compute_units=compute_units.total)
+print(f'> Wrote the compute units to {cu_calibration_path}')
+
 
 print('> Compiling the BPF program to swap with an inner program')
 compile_bpf_program(
@@ -332,4 +351,37 @@ tx_hash = token_pool_p0.inner_swap(
 mev_logs = read_mev_log('/tmp/mev.log')
 assert mev_logs[len(mev_logs) - 1]['transaction_hash'] == tx_hash
 
+print('> Moving the price of a pool and restoring it with an inner program')
+n_logs_before = len(mev_logs)
+tx_hash = token_pool_p0.round_trip_swap(
+    inner_program=inner_token_swap_program_id,
+    token_a_client=t0_account.pubkey,
+    token_b_client=t1_account.pubkey,
+    amount=1_000,
+    minimum_amount_out=0,
+    # Swap back all that the first swap got us.
+    reverse_amount_in=0,
+    reverse_minimum_amount_out=0,
+)
+
+# We see the transaction, but the pool is where it was, up to the fees, so
+# there is nothing to take.
+mev_logs = read_mev_log('/tmp/mev.log')
+new_logs = mev_logs[n_logs_before:]
+assert new_logs[len(new_logs) - 1]['transaction_hash'] == tx_hash
+assert not any(log.get('event') == 'opportunity' for log in new_logs)
+
+pre_pool = new_logs[len(new_logs) - 1]['orca_pre_tx_pool'][
+    token_pool_p0.token_swap_account
+]
+post_pool = new_logs[len(new_logs) - 1]['orca_post_tx_pool'][
+    token_pool_p0.token_swap_account
+]
+# Token B goes out and comes back in full, token A keeps the fees of both
+# legs: a trade fee of 0.25% and an owner fee of 0.05% each, and a unit of
+# rounding.
+fee_tolerance_a = 2 * 0.003 * 1_000 + 2
+assert 0 <= post_pool['pool_a_balance'] - pre_pool['pool_a_balance'] <= fee_tolerance_a
+assert post_pool['pool_b_balance'] == pre_pool['pool_b_balance']
+
 test_validator.terminate()
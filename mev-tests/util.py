@@ -231,6 +231,76 @@ def rpc_get_account_info(address: str) -> Optional[Dict[str, Any]]:
     return account_info
 
 
+def rpc_get_transaction(signature: str) -> Dict[str, Any]:
+    """
+    Call getTransaction, see https://docs.solana.com/developing/clients/jsonrpc-api#gettransaction.
+    """
+    result: Dict[str, Any] = solana_rpc(
+        method='getTransaction',
+        params=[signature, {'encoding': 'json', 'commitment': 'confirmed'}],
+    )
+    transaction: Dict[str, Any] = result['result']
+    return transaction
+
+
+class ComputeUnits(NamedTuple):
+    # What the transaction consumed as a whole.
+    total: int
+    # What every top-level instruction of `program_id` consumed, in order.
+    per_instruction: List[int]
+
+
+def get_compute_units_consumed(signature: str, program_id: str) -> ComputeUnits:
+    """
+    Return the compute units that a confirmed transaction consumed, and the
+    ones of its top-level instructions of `program_id`, e.g. one per hop of an
+    MEV transaction. The RPC node does not report `computeUnitsConsumed`, so
+    we read the `Program <id> consumed <units> of <limit> compute units` lines
+    of the logs. Only those of top-level instructions add up to the total, the
+    ones of inner instructions are included in those.
+    """
+    meta = rpc_get_transaction(signature)['meta']
+    total = 0
+    per_instruction = []
+    depth = 0
+    for line in meta['logMessages']:
+        words = line.split(' ')
+        if len(words) >= 4 and words[0] == 'Program' and words[2] == 'invoke':
+            depth = int(words[3].strip('[]'))
+        elif len(words) >= 4 and words[0] == 'Program' and words[2] == 'consumed':
+            if depth == 1:
+                total += int(words[3])
+                if words[1] == program_id:
+                    per_instruction.append(int(words[3]))
+        elif len(words) >= 3 and words[0] == 'Program' and words[2] in (
+            'success',
+            'failed:',
+        ):
+            depth -= 1
+    return ComputeUnits(total, per_instruction)
+
+
+def record_cu_calibration(calibration_path: str, hops: int, compute_units: int) -> None:
+    """
+    Add a transaction with `hops` hops that consumed `compute_units` to the
+    calibration file that `cu_calibration_file` reads, see
+    `runtime/src/mev/compute_units.rs`, and create the file when it does not
+    exist. Like `CuCalibration::record`, we keep the most that we measured.
+    """
+    calibration: Dict[str, Any] = {'version': 1, 'by_hop_count': {}}
+    if os.path.isfile(calibration_path):
+        with open(calibration_path, 'r') as f:
+            calibration = json.load(f)
+        assert calibration['version'] == 1, 'Unknown calibration file version'
+    hop_count = calibration['by_hop_count'].setdefault(
+        str(hops), {'compute_units': 0, 'samples': 0}
+    )
+    hop_count['compute_units'] = max(hop_count['compute_units'], compute_units)
+    hop_count['samples'] += 1
+    with open(calibration_path, 'w') as f:
+        json.dump(calibration, f, indent=2)
+
+
 class TokenPool(NamedTuple):
     token_swap_program_id: str
     token_swap_account: str
@@ -278,7 +348,7 @@ class TokenPool(NamedTuple):
                 str(minimum_amount_out),
             )
         )
-        return swap_json['Swap']
+        return swap_json['Swap']['hash']
 
     def inner_swap(
         self,
@@ -320,7 +390,55 @@ class TokenPool(NamedTuple):
                 str(minimum_amount_out),
             )
         )
-        return swap_json['Swap']
+        return swap_json['Swap']['hash']
+
+    def round_trip_swap(
+        self,
+        inner_program: str,
+        token_a_client: str,
+        token_b_client: str,
+        amount: int,
+        minimum_amount_out: int,
+        reverse_amount_in: int,
+        reverse_minimum_amount_out: int,
+    ) -> str:
+        swap_json = json.loads(
+            run(
+                'cargo',
+                'run',
+                '--manifest-path',
+                './mev-tests/helper-programs/token-swap-cli/Cargo.toml',
+                '--',
+                '--token-swap-program-id',
+                self.token_swap_program_id,
+                '--token-swap-a-account',
+                self.token_swap_a_account,
+                '--token-swap-b-account',
+                self.token_swap_b_account,
+                'round-trip-swap',
+                '--caller-account',
+                inner_program,
+                '--token-swap-account',
+                self.token_swap_account,
+                '--token-a-client',
+                token_a_client,
+                '--token-b-client',
+                token_b_client,
+                '--pool-mint',
+                self.pool_mint_account,
+                '--pool-fee',
+                self.pool_fee_account,
+                '--amount',
+                str(amount),
+                '--minimum-amount-out',
+                str(minimum_amount_out),
+                '--reverse-amount-in',
+                str(reverse_amount_in),
+                '--reverse-minimum-amount-out',
+                str(reverse_minimum_amount_out),
+            )
+        )
+        return swap_json['RoundTripSwap']['hash']
 
 
 def deploy_token_pool(
@@ -171,6 +171,10 @@ pub struct ExecuteDetailsTimings {
     pub create_executor_load_elf_us: u64,
     pub create_executor_verify_code_us: u64,
     pub create_executor_jit_compile_us: u64,
+    /// Accounts locked readonly only because of the transactions' MEV keys.
+    pub mev_readonly_lock_count: u64,
+    /// Accounts locked writable only because of the transactions' MEV keys.
+    pub mev_writable_lock_count: u64,
     pub per_program_timings: HashMap<Pubkey, ProgramTiming>,
 }
 
@@ -204,6 +208,8 @@ impl ExecuteDetailsTimings {
             self.create_executor_jit_compile_us,
             other.create_executor_jit_compile_us
         );
+        saturating_add_assign!(self.mev_readonly_lock_count, other.mev_readonly_lock_count);
+        saturating_add_assign!(self.mev_writable_lock_count, other.mev_writable_lock_count);
         for (id, other) in &other.per_program_timings {
             let program_timing = self.per_program_timings.entry(*id).or_default();
             program_timing.accumulate_program_timings(other);
@@ -312,6 +318,8 @@ mod tests {
         other_execute_details_timings.total_account_count = account_count;
         other_execute_details_timings.total_data_size = data_size_changed;
         other_execute_details_timings.data_size_changed = data_size_changed;
+        other_execute_details_timings.mev_readonly_lock_count = account_count;
+        other_execute_details_timings.mev_writable_lock_count = account_count;
 
         // Accumulate the other instance into the current instance
         execute_details_timings.accumulate(&other_execute_details_timings);
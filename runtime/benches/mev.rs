@@ -0,0 +1,250 @@
+#![feature(test)]
+
+extern crate test;
+
+use {
+    crossbeam_channel::unbounded,
+    solana_runtime::mev::{
+        arbitrage::TradeDirection,
+        config::{MevConfig, TokenAmount, TokenRef},
+        engine::PathCache,
+        test_utils::{orca_accounts, path, pool, pubkey, ORCA_PROGRAM_ID},
+        Mev, PoolStates,
+    },
+    solana_sdk::{
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+        rent::Rent,
+        signature::Keypair,
+        transaction::{SanitizedTransaction, Transaction},
+    },
+    std::{collections::HashMap, sync::Arc},
+    test::Bencher,
+};
+
+const NUM_PATHS: usize = 100;
+const NUM_HOPS: usize = 4;
+
+/// A pool of `profitable_paths`: its address, mints, and our token accounts.
+struct ProfitablePool {
+    address: Pubkey,
+    mints: (Pubkey, Pubkey),
+    accounts: (Pubkey, Pubkey),
+}
+
+/// The states of `pools`, where the trigger `moved` a pool, if set. Every path
+/// is profitable either way.
+fn profitable_pool_states(pools: &[ProfitablePool], moved: Option<Pubkey>) -> PoolStates {
+    pools
+        .iter()
+        .map(|profitable_pool| {
+            let pool_a_balance = match moved == Some(profitable_pool.address) {
+                true => 1_000_001_000,
+                false => 1_000_000_000,
+            };
+            pool(profitable_pool.address)
+                .mints(profitable_pool.mints.0, profitable_pool.mints.1)
+                .balances(pool_a_balance, 1_100_000_000)
+                .source(profitable_pool.accounts.0)
+                .destination(profitable_pool.accounts.1)
+                .build()
+        })
+        .collect()
+}
+
+/// `NUM_PATHS` cycles of `NUM_HOPS` hops each, that are all profitable, with a
+/// user authority, so we simulate every hop of every path and build a
+/// transaction for each.
+fn profitable_paths() -> (Mev, PoolStates) {
+    let (mev, pools) = profitable_paths_with_pools();
+    (mev, profitable_pool_states(&pools, None))
+}
+
+/// Like `profitable_paths`, with the pools to build other states of them.
+fn profitable_paths_with_pools() -> (Mev, Vec<ProfitablePool>) {
+    let mut pools = Vec::with_capacity(NUM_PATHS * NUM_HOPS);
+    let mut mev_paths = Vec::with_capacity(NUM_PATHS);
+    let mut minimum_profit = HashMap::new();
+    for path_idx in 0..NUM_PATHS {
+        let mints: Vec<Pubkey> = (0..NUM_HOPS).map(|_| Pubkey::new_unique()).collect();
+        minimum_profit.insert(TokenRef::Mint(mints[0]), TokenAmount::Raw(0));
+        let mut mev_path = path(&format!("path {}", path_idx));
+        for hop in 0..NUM_HOPS {
+            let address = Pubkey::new_unique();
+            pools.push(ProfitablePool {
+                address,
+                mints: (mints[hop], mints[(hop + 1) % NUM_HOPS]),
+                accounts: (Pubkey::new_unique(), Pubkey::new_unique()),
+            });
+            mev_path = mev_path.hop(address, TradeDirection::AtoB);
+        }
+        mev_paths.push(mev_path.build());
+    }
+    let pool_states = profitable_pool_states(&pools, None);
+
+    let (log_send_channel, _log_receiver) = unbounded();
+    let mut mev = Mev::new(
+        log_send_channel,
+        MevConfig {
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths,
+            minimum_profit,
+            ..MevConfig::default()
+        },
+    );
+    mev.user_authority = Some(Arc::new(Keypair::new()));
+    (mev, pools)
+}
+
+/// Only the evaluation, which builds unsigned transactions.
+#[bench]
+fn bench_get_arbitrage_tx_outputs(bencher: &mut Bencher) {
+    let (mev, pool_states) = profitable_paths();
+    let rent = Rent::default();
+    bencher.iter(|| {
+        let outputs = mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &rent, None);
+        assert_eq!(outputs.len(), NUM_PATHS);
+        assert!(outputs.iter().all(|output| output.blueprint.is_some()));
+    });
+}
+
+/// The evaluation, and selecting and signing the most profitable transaction.
+#[bench]
+fn bench_get_and_take_max_profit_tx(bencher: &mut Bencher) {
+    let (mev, pool_states) = profitable_paths();
+    let rent = Rent::default();
+    bencher.iter(|| {
+        let mut outputs = mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &rent, None);
+        let transactions = mev.take_max_profit_tx(&mut outputs, 1);
+        assert_eq!(transactions.len(), 1);
+        for (transaction, _profit) in &transactions {
+            mev.release_inflight_exposure(transaction.signature());
+        }
+    });
+}
+
+/// Evaluating a trigger that moved one pool, which only needs to evaluate the
+/// path through it, compared with `bench_evaluate_trigger_all_moved`.
+#[bench]
+fn bench_evaluate_trigger_one_moved(bencher: &mut Bencher) {
+    let (mev, pools) = profitable_paths_with_pools();
+    let trigger = &orca_transactions(&mev)[0];
+    let rent = Rent::default();
+    bencher.iter(|| {
+        let transactions = mev.evaluate_trigger(
+            trigger,
+            1,
+            false,
+            profitable_pool_states(&pools, None),
+            profitable_pool_states(&pools, Some(pools[0].address)),
+            Hash::default(),
+            &rent,
+        );
+        assert_eq!(transactions.len(), 1);
+        for (transaction, _profit) in &transactions {
+            mev.release_inflight_exposure(transaction.signature());
+        }
+    });
+}
+
+/// Evaluating a trigger without the states before it, so that every path
+/// counts as moved, like before we pruned the paths.
+#[bench]
+fn bench_evaluate_trigger_all_moved(bencher: &mut Bencher) {
+    let (mev, pools) = profitable_paths_with_pools();
+    let trigger = &orca_transactions(&mev)[0];
+    let rent = Rent::default();
+    bencher.iter(|| {
+        let transactions = mev.evaluate_trigger(
+            trigger,
+            1,
+            false,
+            std::iter::empty().collect(),
+            profitable_pool_states(&pools, Some(pools[0].address)),
+            Hash::default(),
+            &rent,
+        );
+        assert_eq!(transactions.len(), 1);
+        for (transaction, _profit) in &transactions {
+            mev.release_inflight_exposure(transaction.signature());
+        }
+    });
+}
+
+const NUM_TRIGGERS: usize = 1_000;
+
+/// `NUM_TRIGGERS` transactions that call the Orca program, of which only one
+/// in ten references an account of the pools of `mev`, the others trade
+/// through pools that we do not monitor.
+fn orca_transactions(mev: &Mev) -> Vec<SanitizedTransaction> {
+    let monitored_accounts: Vec<Pubkey> = mev.trigger_accounts.iter().copied().collect();
+    (0..NUM_TRIGGERS)
+        .map(|idx| {
+            let account = match idx % 10 {
+                0 => monitored_accounts[idx % monitored_accounts.len()],
+                _ => Pubkey::new_unique(),
+            };
+            let instruction = Instruction::new_with_bytes(
+                pubkey(ORCA_PROGRAM_ID),
+                &[],
+                vec![AccountMeta::new(account, false)],
+            );
+            SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+                &[instruction],
+                Some(&Pubkey::new_unique()),
+            ))
+        })
+        .collect()
+}
+
+/// Attaching the MEV keys to a stream of Orca transactions. Only the ones that
+/// reference a monitored pool get them, and only those load the pool states
+/// afterwards. Watching only the program would give all of them the keys.
+#[bench]
+fn bench_fill_tx_mev_accounts(bencher: &mut Bencher) {
+    let (mut mev, _pool_states) = profitable_paths();
+    mev.watched_programs.insert(pubkey(ORCA_PROGRAM_ID));
+    let mut transactions = orca_transactions(&mev);
+    bencher.iter(|| {
+        let mut filled = 0;
+        for transaction in transactions.iter_mut() {
+            transaction.mev_keys = None;
+            mev.fill_tx_mev_accounts(transaction);
+            filled += transaction.mev_keys.is_some() as usize;
+        }
+        assert_eq!(filled, NUM_TRIGGERS / 10);
+    });
+}
+
+/// Evaluating `NUM_TRIGGERS` triggers that each moved one pool, without the
+/// states before them, so that we evaluate every path after every trigger.
+/// With `path_cache`, we only compute the quotes of the paths through the
+/// pools that changed since the trigger before, otherwise we clear the cache
+/// before every trigger.
+fn bench_triggers(bencher: &mut Bencher, path_cache: bool) {
+    let (mev, pools) = profitable_paths_with_pools();
+    let triggered_pool_states: Vec<PoolStates> = (0..NUM_TRIGGERS)
+        .map(|idx| profitable_pool_states(&pools, Some(pools[idx % pools.len()].address)))
+        .collect();
+    let rent = Rent::default();
+    bencher.iter(|| {
+        for pool_states in &triggered_pool_states {
+            if !path_cache {
+                *mev.path_cache.lock().unwrap() = PathCache::default();
+            }
+            let outputs = mev.get_arbitrage_tx_outputs(pool_states, Hash::default(), &rent, None);
+            assert_eq!(outputs.len(), NUM_PATHS);
+        }
+    });
+}
+
+#[bench]
+fn bench_triggers_with_path_cache(bencher: &mut Bencher) {
+    bench_triggers(bencher, true);
+}
+
+#[bench]
+fn bench_triggers_without_path_cache(bencher: &mut Bencher) {
+    bench_triggers(bencher, false);
+}
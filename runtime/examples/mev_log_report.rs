@@ -0,0 +1,95 @@
+//! Summarize MEV logs, of any validator version, see `mev::log_reader`:
+//!
+//!     cargo run -p solana-runtime --example mev_log_report -- mev.log [mev.log.1 ...]
+//!
+//! Prints, per log, how many triggers and opportunities it holds, and the
+//! executed transactions with their profit, and per path the opportunities.
+
+use {
+    solana_runtime::mev::searcher::{read_log_records, MevLogRecord},
+    std::{collections::BTreeMap, path::PathBuf, process::exit},
+};
+
+const USAGE: &str = "Usage: mev_log_report <mev log> [<mev log> ...]";
+
+#[derive(Default)]
+struct Report {
+    triggers: u64,
+    failed_triggers: u64,
+    opportunities_by_path: BTreeMap<String, u64>,
+    executed_transactions: u64,
+    successful_transactions: u64,
+    realized_profit: u64,
+    other_events: u64,
+}
+
+impl Report {
+    fn record(&mut self, record: MevLogRecord) {
+        match record {
+            MevLogRecord::Trigger(trigger) => {
+                self.triggers += 1;
+                self.failed_triggers += trigger.trigger_failed as u64;
+            }
+            MevLogRecord::Opportunities(opportunities) => {
+                for opportunity in opportunities {
+                    *self
+                        .opportunities_by_path
+                        .entry(opportunity.path)
+                        .or_default() += 1;
+                }
+            }
+            MevLogRecord::ExecutedTransaction(executed) => {
+                self.executed_transactions += 1;
+                if executed.is_successful {
+                    self.successful_transactions += 1;
+                    self.realized_profit += executed.possible_profit;
+                }
+            }
+            MevLogRecord::Other(_) => self.other_events += 1,
+        }
+    }
+
+    fn print(&self) {
+        println!(
+            "  triggers: {} ({} failed)",
+            self.triggers, self.failed_triggers
+        );
+        println!(
+            "  executed transactions: {} ({} successful), realized profit: {}",
+            self.executed_transactions, self.successful_transactions, self.realized_profit
+        );
+        println!("  other events: {}", self.other_events);
+        println!("  opportunities by path:");
+        for (path, opportunities) in &self.opportunities_by_path {
+            println!("    {}: {}", path, opportunities);
+        }
+    }
+}
+
+fn main() {
+    let paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+    let mut failed = false;
+    for path in &paths {
+        match read_log_records(path) {
+            Ok(records) => {
+                let mut report = Report::default();
+                for record in records {
+                    report.record(record);
+                }
+                println!("{}:", path.display());
+                report.print();
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
@@ -0,0 +1,194 @@
+//! Measure the compute units that the transactions of the MEV engine consume,
+//! by simulating them on a cluster, and write them to a calibration file for
+//! `cu_calibration_file`:
+//!
+//!     cargo run -p solana-runtime --example mev_measure_compute_units -- mev.toml \
+//!         [--url http://localhost:8899] [--output cu_calibration.json]
+//!
+//! The config needs a user authority, and the pools need an opportunity for
+//! the engine to craft a transaction, e.g. on a localnet where we just moved
+//! them. When the output file exists, we add to the measurements in it, so
+//! that runs against different imbalances accumulate. Prints the compute
+//! units of every hop, and the total, of every transaction that succeeded.
+
+use {
+    solana_client::{
+        rpc_client::RpcClient, rpc_config::RpcSimulateTransactionConfig,
+        rpc_request::MAX_MULTIPLE_ACCOUNTS,
+    },
+    solana_runtime::{
+        inline_spl_token,
+        mev::searcher::{get_mev_config_file, CuCalibration, MevEngine},
+    },
+    solana_sdk::{
+        account::{from_account, AccountSharedData},
+        commitment_config::CommitmentConfig,
+        rent::Rent,
+        sysvar,
+    },
+    std::{path::PathBuf, process::exit},
+};
+
+const USAGE: &str =
+    "Usage: mev_measure_compute_units <mev config> [--url <rpc url>] [--output <calibration file>]";
+
+struct Args {
+    config_path: PathBuf,
+    url: String,
+    output: PathBuf,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut config_path = None;
+    let mut url = "http://localhost:8899".to_owned();
+    let mut output = PathBuf::from("cu_calibration.json");
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = args.next().ok_or("--url needs a value")?,
+            "--output" => output = PathBuf::from(args.next().ok_or("--output needs a value")?),
+            _ if config_path.is_none() => config_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    Ok(Args {
+        config_path: config_path.ok_or("The MEV config is required")?,
+        url,
+        output,
+    })
+}
+
+/// The compute units that every swap in the transaction consumed, from the
+/// `Program <id> consumed <units> of <limit> compute units` lines of its logs.
+/// The token program logs those lines too, for the transfers of the swaps,
+/// we leave those out.
+fn swap_compute_units(logs: &[String]) -> Vec<u64> {
+    let token_program = inline_spl_token::id().to_string();
+    logs.iter()
+        .filter_map(|line| {
+            let mut words = line.split(' ');
+            match (words.next(), words.next(), words.next(), words.next()) {
+                (Some("Program"), Some(program), Some("consumed"), Some(units))
+                    if program != token_program =>
+                {
+                    units.parse().ok()
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2)
+    });
+    let config = get_mev_config_file(&args.config_path);
+    if config.user_authority_path.is_none() && config.signer.is_none() {
+        eprintln!("The config has no user authority, so we cannot craft transactions");
+        exit(2);
+    }
+    let mut calibration = if args.output.exists() {
+        CuCalibration::load(&args.output).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1)
+        })
+    } else {
+        CuCalibration::default()
+    };
+
+    // We only log to the channel, and drop what we log.
+    let (log_send_channel, _log_receiver) = crossbeam_channel::unbounded();
+    let mut engine = MevEngine::new(log_send_channel, config);
+    let rpc_client = RpcClient::new_with_commitment(args.url, CommitmentConfig::confirmed());
+    let rent = rpc_client
+        .get_account(&sysvar::rent::id())
+        .ok()
+        .and_then(|account| from_account::<Rent, _>(&account))
+        .unwrap_or_default();
+    let watched_accounts = engine.watched_accounts();
+    for pubkeys in watched_accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc_client
+            .get_multiple_accounts(pubkeys)
+            .unwrap_or_else(|err| panic!("Could not get the pools' accounts: {}", err));
+        for (pubkey, account) in pubkeys.iter().zip(accounts) {
+            if let Some(account) = account {
+                engine.update_account(*pubkey, AccountSharedData::from(account));
+            }
+        }
+    }
+
+    let blockhash = rpc_client
+        .get_latest_blockhash()
+        .unwrap_or_else(|err| panic!("Could not get the latest blockhash: {}", err));
+    let outputs = engine.evaluate(blockhash, &rent).unwrap_or_else(|errors| {
+        for error in errors {
+            eprintln!("Could not load pool: {}", error);
+        }
+        exit(1)
+    });
+    let mut measured = 0;
+    for output in outputs {
+        let path = &engine.mev_paths()[output.path_idx];
+        let transaction = match output
+            .blueprint
+            .and_then(|blueprint| blueprint.sign(None).ok())
+            .and_then(|tx| tx.to_versioned_transaction().into_legacy_transaction())
+        {
+            Some(transaction) => transaction,
+            None => continue,
+        };
+        let simulation = match rpc_client.simulate_transaction_with_config(
+            &transaction,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                ..RpcSimulateTransactionConfig::default()
+            },
+        ) {
+            Ok(response) => response.value,
+            Err(err) => {
+                eprintln!(
+                    "Could not simulate the transaction of {}: {}",
+                    path.name, err
+                );
+                continue;
+            }
+        };
+        let compute_units = match (simulation.err, simulation.units_consumed) {
+            (None, Some(compute_units)) => compute_units,
+            (Some(err), _) => {
+                eprintln!("The transaction of {} failed: {}", path.name, err);
+                continue;
+            }
+            (None, None) => {
+                eprintln!(
+                    "The cluster did not report the compute units of {}",
+                    path.name
+                );
+                continue;
+            }
+        };
+        let hops = swap_compute_units(&simulation.logs.unwrap_or_default());
+        println!(
+            "{}: {} hops, {} compute units, per hop {:?}",
+            path.name,
+            path.path.len(),
+            compute_units,
+            hops
+        );
+        calibration.record(path.path.len(), compute_units);
+        measured += 1;
+    }
+    if measured == 0 {
+        eprintln!("Found no transaction to measure, are the pools imbalanced?");
+        exit(1);
+    }
+    std::fs::write(&args.output, calibration.to_json())
+        .unwrap_or_else(|err| panic!("Could not write {}: {}", args.output.display(), err));
+    println!(
+        "Measured {} transactions, wrote {}",
+        measured,
+        args.output.display()
+    );
+}
@@ -0,0 +1,163 @@
+//! Evaluate the paths of a MEV config after trades that did not happen, to see
+//! which opportunities they would create:
+//!
+//!     cargo run -p solana-runtime --example mev_simulate -- mev.toml \
+//!         [--url http://localhost:8899] [--pools-json pools.json] \
+//!         --hypothetical-swap pool=<PUBKEY>,direction=AtoB,amount=<N> ...
+//!
+//! The swaps apply in the order given, each against the balances that the
+//! swaps before it left. With `--pools-json`, the pool states come from a file
+//! with a `checkpoint` event, e.g. one line copied from a MEV log, and we do
+//! not connect to a cluster. Otherwise we get the pools' accounts from the RPC
+//! node. We print what every swap got out, and for every path with an
+//! opportunity, its profit before and after the swaps.
+
+use {
+    solana_client::{rpc_client::RpcClient, rpc_request::MAX_MULTIPLE_ACCOUNTS},
+    solana_runtime::mev::searcher::{
+        get_mev_config_file, read_checkpoint_json, HypotheticalSwap, MevEngine, MevLog,
+    },
+    solana_sdk::{
+        account::{from_account, AccountSharedData},
+        commitment_config::CommitmentConfig,
+        hash::Hash,
+        rent::Rent,
+        sysvar,
+    },
+    std::{path::PathBuf, process::exit},
+};
+
+const USAGE: &str = "Usage: mev_simulate <mev config> [--url <rpc url>] \
+    [--pools-json <file>] --hypothetical-swap pool=<pubkey>,direction=<AtoB|BtoA>,amount=<n> ...";
+
+struct Args {
+    config_path: PathBuf,
+    url: String,
+    pools_json: Option<PathBuf>,
+    swaps: Vec<HypotheticalSwap>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut config_path = None;
+    let mut url = "http://localhost:8899".to_owned();
+    let mut pools_json = None;
+    let mut swaps = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = args.next().ok_or("--url needs a value")?,
+            "--pools-json" => {
+                pools_json = Some(PathBuf::from(
+                    args.next().ok_or("--pools-json needs a value")?,
+                ))
+            }
+            "--hypothetical-swap" => {
+                let swap = args.next().ok_or("--hypothetical-swap needs a value")?;
+                swaps.push(
+                    swap.parse()
+                        .map_err(|err| format!("Invalid swap {:?}: {}", swap, err))?,
+                );
+            }
+            _ if config_path.is_none() => config_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    if swaps.is_empty() {
+        return Err("At least one --hypothetical-swap is required".to_owned());
+    }
+    Ok(Args {
+        config_path: config_path.ok_or("The MEV config is required")?,
+        url,
+        pools_json,
+        swaps,
+    })
+}
+
+/// Feed `engine` the accounts of its pools from the RPC node, and return the
+/// latest blockhash and the rent.
+fn load_from_rpc(engine: &mut MevEngine, url: String) -> Result<(Hash, Rent), String> {
+    let rpc_client = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
+    for pubkeys in engine.watched_accounts().chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc_client
+            .get_multiple_accounts(pubkeys)
+            .map_err(|err| format!("Could not get the pools' accounts: {}", err))?;
+        for (pubkey, account) in pubkeys.iter().zip(accounts) {
+            match account {
+                Some(account) => {
+                    engine.update_account(*pubkey, AccountSharedData::from(account));
+                }
+                None => eprintln!("Account {} does not exist", pubkey),
+            }
+        }
+    }
+    let blockhash = rpc_client
+        .get_latest_blockhash()
+        .map_err(|err| format!("Could not get the latest blockhash: {}", err))?;
+    let rent = rpc_client
+        .get_account(&sysvar::rent::id())
+        .ok()
+        .and_then(|account| from_account::<Rent, _>(&account))
+        .unwrap_or_else(|| {
+            eprintln!("Could not get the rent sysvar, using the default rent");
+            Rent::default()
+        });
+    Ok((blockhash, rent))
+}
+
+fn format_profit(profit: Option<u64>) -> String {
+    profit.map_or_else(|| "-".to_owned(), |profit| profit.to_string())
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2)
+    });
+    let config = get_mev_config_file(&args.config_path);
+    let mev_log = MevLog::new(&config, None);
+    let mut engine = MevEngine::new(mev_log.log_send_channel.clone(), config);
+
+    let (blockhash, rent) = match &args.pools_json {
+        Some(path) => {
+            let checkpoint = read_checkpoint_json(path).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                exit(1)
+            });
+            if let Err(err) = engine.load_checkpoint(&checkpoint) {
+                eprintln!("Could not load the pools of {}: {}", path.display(), err);
+                exit(1);
+            }
+            // Offline, the transactions are only quotes.
+            (Hash::default(), Rent::default())
+        }
+        None => load_from_rpc(&mut engine, args.url).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            exit(1)
+        }),
+    };
+
+    let evaluation = engine
+        .evaluate_hypothetical_swaps(&args.swaps, blockhash, &rent)
+        .unwrap_or_else(|err| {
+            eprintln!("Could not simulate the swaps: {}", err);
+            exit(1)
+        });
+    for (swap, amount_out) in args.swaps.iter().zip(&evaluation.swap_outputs) {
+        println!(
+            "Swap {} into pool {} {:?}: got {} out",
+            swap.amount, swap.pool, swap.direction, amount_out
+        );
+    }
+    let profits = evaluation.profit_by_path();
+    if profits.is_empty() {
+        println!("No opportunities, before or after the swaps");
+    }
+    for (path_idx, (baseline, hypothetical)) in profits {
+        println!(
+            "{}: baseline profit {}, hypothetical profit {}",
+            engine.mev_paths()[path_idx].name,
+            format_profit(baseline),
+            format_profit(hypothetical),
+        );
+    }
+}
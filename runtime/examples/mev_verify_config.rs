@@ -0,0 +1,95 @@
+//! Check the pools of a MEV config against the cluster: that every pool exists
+//! and uses the token accounts, pool mint, and fee account of the config, and
+//! that our source and destination token accounts exist, hold the pool's
+//! mints, and belong to the user authority of the config:
+//!
+//!     cargo run -p solana-runtime --example mev_verify_config -- mev.toml \
+//!         [--url http://localhost:8899]
+//!
+//! Prints OK or FAIL for every pool, and exits with 1 when any pool failed.
+
+use {
+    solana_client::{rpc_client::RpcClient, rpc_request::MAX_MULTIPLE_ACCOUNTS},
+    solana_runtime::mev::searcher::{
+        accounts_to_verify, configured_pool_authorities, configured_user_authority,
+        get_mev_config_file, verify_pools,
+    },
+    solana_sdk::{account::AccountSharedData, commitment_config::CommitmentConfig},
+    std::{collections::HashMap, path::PathBuf, process::exit},
+};
+
+const USAGE: &str = "Usage: mev_verify_config <mev config> [--url <rpc url>]";
+
+struct Args {
+    config_path: PathBuf,
+    url: String,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut config_path = None;
+    let mut url = "http://localhost:8899".to_owned();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = args.next().ok_or("--url needs a value")?,
+            _ if config_path.is_none() => config_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    Ok(Args {
+        config_path: config_path.ok_or("The MEV config is required")?,
+        url,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2)
+    });
+    let config = get_mev_config_file(&args.config_path);
+    let user_authority = configured_user_authority(&config);
+    let pool_authorities = configured_pool_authorities(&config);
+    if user_authority.is_none() && pool_authorities.is_empty() {
+        println!("The config has no user authority, not checking who owns our token accounts");
+    }
+
+    let rpc_client = RpcClient::new_with_commitment(args.url, CommitmentConfig::confirmed());
+    let pubkeys = accounts_to_verify(&config.orca_accounts);
+    let mut accounts = HashMap::with_capacity(pubkeys.len());
+    for pubkeys in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let fetched = rpc_client
+            .get_multiple_accounts(pubkeys)
+            .unwrap_or_else(|err| panic!("Could not get the pools' accounts: {}", err));
+        for (pubkey, account) in pubkeys.iter().zip(fetched) {
+            if let Some(account) = account {
+                accounts.insert(*pubkey, AccountSharedData::from(account));
+            }
+        }
+    }
+
+    let verifications = verify_pools(
+        &config.orca_accounts,
+        user_authority.as_ref(),
+        &pool_authorities,
+        &accounts,
+    );
+    for verification in &verifications {
+        if verification.is_ok() {
+            println!("OK   {}", verification.pool);
+        } else {
+            println!("FAIL {}", verification.pool);
+            for failure in &verification.failures {
+                println!("     {}", failure);
+            }
+        }
+    }
+    let failed = verifications
+        .iter()
+        .filter(|verification| !verification.is_ok())
+        .count();
+    println!("{} of {} pools failed", failed, verifications.len());
+    if failed > 0 {
+        exit(1);
+    }
+}
@@ -0,0 +1,243 @@
+//! Follow the pools of a MEV config over RPC, and print the opportunities that
+//! the MEV engine finds in them, without running a validator:
+//!
+//!     cargo run -p solana-runtime --example standalone_searcher -- mev.toml \
+//!         [--url http://localhost:8899] [--ws-url ws://localhost:8900] [--send] \
+//!         [--checkpoint mev.log]
+//!
+//! With `--send`, and a user authority in the config, we also send the
+//! transactions of the most profitable opportunity to the RPC node. With
+//! `--checkpoint`, we start from the latest `checkpoint` event in the MEV log
+//! of a validator, see `checkpoint_interval_slots`, and evaluate it before we
+//! switch to the accounts from the RPC node.
+
+use {
+    crossbeam_channel::unbounded,
+    solana_account_decoder::UiAccountEncoding,
+    solana_client::{
+        pubsub_client::PubsubClient, rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig,
+        rpc_request::MAX_MULTIPLE_ACCOUNTS,
+    },
+    solana_runtime::mev::searcher::{
+        get_mev_config_file, read_latest_checkpoint, MevEngine, MevLog, MevTxOutput,
+        PoolLoadErrorKind,
+    },
+    solana_sdk::{
+        account::{from_account, Account, AccountSharedData},
+        commitment_config::CommitmentConfig,
+        rent::Rent,
+        sysvar,
+    },
+    std::{path::PathBuf, process::exit, thread},
+};
+
+const USAGE: &str = "Usage: standalone_searcher <mev config> [--url <rpc url>] \
+    [--ws-url <websocket url>] [--send] [--checkpoint <mev log>]";
+
+struct Args {
+    config_path: PathBuf,
+    url: String,
+    ws_url: String,
+    send: bool,
+    checkpoint: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut config_path = None;
+    let mut url = "http://localhost:8899".to_owned();
+    let mut ws_url = "ws://localhost:8900".to_owned();
+    let mut send = false;
+    let mut checkpoint = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = args.next().ok_or("--url needs a value")?,
+            "--ws-url" => ws_url = args.next().ok_or("--ws-url needs a value")?,
+            "--send" => send = true,
+            "--checkpoint" => {
+                checkpoint = Some(PathBuf::from(
+                    args.next().ok_or("--checkpoint needs a value")?,
+                ))
+            }
+            _ if config_path.is_none() => config_path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    Ok(Args {
+        config_path: config_path.ok_or("The MEV config is required")?,
+        url,
+        ws_url,
+        send,
+        checkpoint,
+    })
+}
+
+/// Evaluate the paths against the accounts that `engine` has, print the
+/// opportunities, and with `send`, send the most profitable one.
+fn evaluate(engine: &MevEngine, rpc_client: &RpcClient, rent: &Rent, send: bool) {
+    let blockhash = match rpc_client.get_latest_blockhash() {
+        Ok(blockhash) => blockhash,
+        Err(err) => {
+            eprintln!("Could not get the latest blockhash: {}", err);
+            return;
+        }
+    };
+    let outputs = match engine.evaluate(blockhash, rent) {
+        Ok(outputs) => outputs,
+        Err(errors) => {
+            // Until we got every account, some are missing, which is fine.
+            for error in errors {
+                if error.kind != PoolLoadErrorKind::MissingAccount {
+                    eprintln!("Could not load pool: {}", error);
+                }
+            }
+            return;
+        }
+    };
+    for output in &outputs {
+        println!(
+            "Opportunity on {}: profit {}, swaps {}",
+            engine.mev_paths()[output.path_idx].name,
+            output.profit,
+            serde_json::to_string(&output.input_output_pairs).unwrap(),
+        );
+    }
+    if send {
+        send_most_profitable(outputs, rpc_client);
+    }
+}
+
+/// Send the transactions of the path with the most profit, the outputs of a
+/// path that is split into a sequence are only profitable together.
+fn send_most_profitable(outputs: Vec<MevTxOutput>, rpc_client: &RpcClient) {
+    let best_path_idx = match outputs
+        .iter()
+        .map(|output| output.path_idx)
+        .max_by_key(|path_idx| {
+            outputs
+                .iter()
+                .filter(|output| output.path_idx == *path_idx)
+                .map(|output| output.profit)
+                .sum::<u64>()
+        }) {
+        Some(path_idx) => path_idx,
+        None => return,
+    };
+    let transactions = outputs
+        .into_iter()
+        .filter(|output| output.path_idx == best_path_idx)
+        .map(|output| output.blueprint)
+        .collect::<Option<Vec<_>>>();
+    // Without a user authority, we only get quotes.
+    for blueprint in transactions.unwrap_or_default() {
+        let transaction = match blueprint.sign(None) {
+            Ok(transaction) => transaction,
+            Err(err) => {
+                eprintln!("Could not sign transaction: {}", err);
+                continue;
+            }
+        };
+        let transaction = match transaction
+            .to_versioned_transaction()
+            .into_legacy_transaction()
+        {
+            Some(transaction) => transaction,
+            None => continue,
+        };
+        match rpc_client.send_transaction(&transaction) {
+            Ok(signature) => println!("Sent transaction {}", signature),
+            Err(err) => eprintln!("Could not send transaction: {}", err),
+        }
+    }
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        exit(2)
+    });
+    let config = get_mev_config_file(&args.config_path);
+    let mev_log = MevLog::new(&config, None);
+    let mut engine = MevEngine::new(mev_log.log_send_channel.clone(), config);
+    let rpc_client = RpcClient::new_with_commitment(args.url, CommitmentConfig::confirmed());
+    let rent = rpc_client
+        .get_account(&sysvar::rent::id())
+        .ok()
+        .and_then(|account| from_account::<Rent, _>(&account))
+        .unwrap_or_else(|| {
+            eprintln!("Could not get the rent sysvar, using the default rent");
+            Rent::default()
+        });
+
+    if let Some(log_path) = &args.checkpoint {
+        match read_latest_checkpoint(log_path) {
+            Ok(Some(checkpoint)) => {
+                if let Err(err) = engine.load_checkpoint(&checkpoint) {
+                    eprintln!("Could not load the checkpoint: {}", err);
+                    exit(1);
+                }
+                println!(
+                    "Starting from the checkpoint of slot {}, with {} pools",
+                    checkpoint.slot,
+                    checkpoint.pools.len()
+                );
+                evaluate(&engine, &rpc_client, &rent, args.send);
+            }
+            Ok(None) => eprintln!("{} has no checkpoint", log_path.display()),
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+    }
+
+    // Subscribe before we get the accounts, so that we do not miss the updates
+    // in between.
+    let watched_accounts = engine.watched_accounts();
+    let (update_sender, update_receiver) = unbounded();
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        ..RpcAccountInfoConfig::default()
+    };
+    let mut subscriptions = Vec::with_capacity(watched_accounts.len());
+    for pubkey in watched_accounts.iter().copied() {
+        let (subscription, receiver) =
+            PubsubClient::account_subscribe(&args.ws_url, &pubkey, Some(account_config.clone()))
+                .unwrap_or_else(|err| panic!("Could not subscribe to account {}: {}", pubkey, err));
+        subscriptions.push(subscription);
+        let update_sender = update_sender.clone();
+        thread::spawn(move || {
+            for response in receiver {
+                match response.value.decode::<Account>() {
+                    Some(account) => {
+                        if update_sender.send((pubkey, account)).is_err() {
+                            break;
+                        }
+                    }
+                    None => eprintln!("Could not decode an update of account {}", pubkey),
+                }
+            }
+        });
+    }
+
+    for pubkeys in watched_accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = rpc_client
+            .get_multiple_accounts(pubkeys)
+            .unwrap_or_else(|err| panic!("Could not get the pools' accounts: {}", err));
+        for (pubkey, account) in pubkeys.iter().zip(accounts) {
+            match account {
+                Some(account) => {
+                    engine.update_account(*pubkey, AccountSharedData::from(account));
+                }
+                None => eprintln!("Account {} does not exist", pubkey),
+            }
+        }
+    }
+    evaluate(&engine, &rpc_client, &rent, args.send);
+
+    for (pubkey, account) in update_receiver {
+        engine.update_account(pubkey, AccountSharedData::from(account));
+        evaluate(&engine, &rpc_client, &rent, args.send);
+    }
+}
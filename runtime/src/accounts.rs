@@ -72,6 +72,29 @@ use {
 pub struct AccountLocks {
     write_locks: HashSet<Pubkey>,
     readonly_locks: HashMap<Pubkey, u64>,
+    // The locks that transactions took because of their MEV keys, summed
+    // over the transactions locked since the last report.
+    mev_lock_footprint: MevLockFootprint,
+}
+
+/// The locks that a transaction takes only because of its `MevKeys`, on top
+/// of the locks for its own keys. A key of the transaction that the MEV keys
+/// make writable counts as an extra writable lock, and no longer as readonly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AbiExample)]
+pub struct MevLockFootprint {
+    pub readonly: u64,
+    pub writable: u64,
+}
+
+impl MevLockFootprint {
+    pub fn is_empty(&self) -> bool {
+        self.readonly == 0 && self.writable == 0
+    }
+
+    fn add(&mut self, other: MevLockFootprint) {
+        self.readonly = self.readonly.saturating_add(other.readonly);
+        self.writable = self.writable.saturating_add(other.writable);
+    }
 }
 
 impl AccountLocks {
@@ -140,14 +163,16 @@ pub struct MevPoolAccounts {
     pub pool_mint: Pubkey,
     pub pool_fee: Pubkey,
     pub pool_authority: Pubkey,
+    pub token_program: Pubkey,
+    pub token_a_mint: Option<Pubkey>,
+    pub token_b_mint: Option<Pubkey>,
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct MevAccounts {
     // All accounts are referenced by `Pubkey` inside `pubkey_account_map`.
     pub pool_accounts: Vec<MevPoolAccounts>,
-    pub token_program: Pubkey,
-    pub user_authority: Option<Pubkey>,
+    pub user_authorities: Vec<Pubkey>,
 
     // Stores all the accounts.
     pub pubkey_account_map: HashMap<Pubkey, MevAccountOrIdx>,
@@ -183,6 +208,7 @@ impl MevAccounts {
             insert_account_in_map(&pool_keys.pool_mint);
             insert_account_in_map(&pool_keys.pool_fee);
             insert_account_in_map(&pool_keys.pool_authority);
+            insert_account_in_map(&pool_keys.token_program);
 
             let source = pool_keys.source.map(|src| {
                 insert_account_in_map(&src);
@@ -194,6 +220,16 @@ impl MevAccounts {
                 dst
             });
 
+            let token_a_mint = pool_keys.token_a_mint.map(|mint| {
+                insert_account_in_map(&mint);
+                mint
+            });
+
+            let token_b_mint = pool_keys.token_b_mint.map(|mint| {
+                insert_account_in_map(&mint);
+                mint
+            });
+
             pool_accounts.push(MevPoolAccounts {
                 pool: pool_keys.pool,
                 source,
@@ -203,17 +239,22 @@ impl MevAccounts {
                 pool_mint: pool_keys.pool_mint,
                 pool_fee: pool_keys.pool_fee,
                 pool_authority: pool_keys.pool_authority,
+                token_program: pool_keys.token_program,
+                token_a_mint,
+                token_b_mint,
             });
         }
-        insert_account_in_map(&mev_keys.token_program);
 
         MevAccounts {
             pool_accounts,
-            token_program: mev_keys.token_program,
-            user_authority: mev_keys.user_authority.map(|user_authority| {
-                insert_account_in_map(&user_authority);
-                user_authority
-            }),
+            user_authorities: mev_keys
+                .user_authorities
+                .iter()
+                .map(|user_authority| {
+                    insert_account_in_map(user_authority);
+                    *user_authority
+                })
+                .collect(),
             pubkey_account_map,
         }
     }
@@ -1160,20 +1201,23 @@ impl Accounts {
         self.accounts_db.store_cached(slot, &[(pubkey, account)]);
     }
 
+    /// The keys to lock readonly and writable, and how many of them are only
+    /// there because of `mev_keys`. Without MEV keys, this does not allocate.
     fn get_readable_and_writable_locks_vec<'a>(
         &self,
         writable_keys: Vec<&'a Pubkey>,
         readonly_keys: Vec<&'a Pubkey>,
         mev_keys: Option<&'a MevKeys>,
-    ) -> (Vec<&'a Pubkey>, Vec<&'a Pubkey>) {
+    ) -> (Vec<&'a Pubkey>, Vec<&'a Pubkey>, MevLockFootprint) {
         if let Some(mev_keys) = mev_keys {
             let mut readable_set: HashSet<&Pubkey> = HashSet::with_capacity(readonly_keys.len());
-            readable_set.extend(readonly_keys.into_iter());
+            readable_set.extend(readonly_keys.iter().copied());
             // extend `readable_set` with the mev readable accounts.
             mev_keys.get_readonly_accounts(&mut readable_set);
 
             let mut writable_set: HashSet<&Pubkey> = HashSet::with_capacity(writable_keys.len());
             writable_set.extend(writable_keys.into_iter());
+            let tx_writable_count = writable_set.len();
             // extend `writable_set` with the mev writable accounts.
             mev_keys.get_write_accounts(&mut writable_set);
 
@@ -1182,12 +1226,24 @@ impl Accounts {
                 readable_set.remove(k);
             }
 
+            // The transaction's own readonly keys that are still readonly,
+            // every other readonly key is there because of the MEV keys.
+            let tx_readonly_count = readonly_keys
+                .iter()
+                .filter(|k| readable_set.contains(*k))
+                .count();
+            let footprint = MevLockFootprint {
+                readonly: readable_set.len().saturating_sub(tx_readonly_count) as u64,
+                writable: writable_set.len().saturating_sub(tx_writable_count) as u64,
+            };
+
             (
                 readable_set.into_iter().collect(),
                 writable_set.into_iter().collect(),
+                footprint,
             )
         } else {
-            (readonly_keys, writable_keys)
+            (readonly_keys, writable_keys, MevLockFootprint::default())
         }
     }
 
@@ -1198,7 +1254,7 @@ impl Accounts {
         readonly_keys: Vec<&Pubkey>,
         mev_keys: Option<&MevKeys>,
     ) -> Result<()> {
-        let (all_read_only_keys, all_writable_keys) =
+        let (all_read_only_keys, all_writable_keys, mev_lock_footprint) =
             self.get_readable_and_writable_locks_vec(writable_keys, readonly_keys, mev_keys);
 
         for k in all_writable_keys.iter() {
@@ -1224,6 +1280,14 @@ impl Accounts {
             }
         }
 
+        if !mev_lock_footprint.is_empty() {
+            debug!(
+                "[MEV] Locked {} readonly and {} writable accounts for MEV keys",
+                mev_lock_footprint.readonly, mev_lock_footprint.writable
+            );
+            account_locks.mev_lock_footprint.add(mev_lock_footprint);
+        }
+
         Ok(())
     }
 
@@ -1234,7 +1298,7 @@ impl Accounts {
         readonly_keys: Vec<&Pubkey>,
         mev_keys: Option<&MevKeys>,
     ) {
-        let (all_read_only_keys, all_writable_keys) =
+        let (all_read_only_keys, all_writable_keys, _mev_lock_footprint) =
             self.get_readable_and_writable_locks_vec(writable_keys, readonly_keys, mev_keys);
 
         for k in all_writable_keys {
@@ -1245,6 +1309,42 @@ impl Accounts {
         }
     }
 
+    /// The locks that `tx` takes only because of its MEV keys. Without MEV
+    /// keys, this does not allocate.
+    pub fn transaction_mev_lock_footprint(&self, tx: &SanitizedTransaction) -> MevLockFootprint {
+        if tx.mev_keys().is_none() {
+            return MevLockFootprint::default();
+        }
+        let tx_account_locks = tx.get_account_locks_unchecked();
+        let (_, _, footprint) = self.get_readable_and_writable_locks_vec(
+            tx_account_locks.writable,
+            tx_account_locks.readonly,
+            tx_account_locks.readonly_mev,
+        );
+        footprint
+    }
+
+    /// The locks that transactions took because of their MEV keys, summed
+    /// over the transactions locked since the last report.
+    pub fn mev_lock_footprint(&self) -> MevLockFootprint {
+        self.account_locks.lock().unwrap().mev_lock_footprint
+    }
+
+    /// Report the MEV lock footprint of `slot`, when MEV took any locks, and
+    /// start counting the next slot from zero. Returns the reported footprint.
+    pub fn report_mev_lock_footprint(&self, slot: Slot) -> MevLockFootprint {
+        let footprint = std::mem::take(&mut self.account_locks.lock().unwrap().mev_lock_footprint);
+        if !footprint.is_empty() {
+            datapoint_info!(
+                "mev-lock-footprint",
+                ("slot", slot as i64, i64),
+                ("readonly", footprint.readonly as i64, i64),
+                ("writable", footprint.writable as i64, i64),
+            );
+        }
+        footprint
+    }
+
     pub fn bank_hash_at(&self, slot: Slot) -> Hash {
         self.bank_hash_info_at(slot).hash
     }
@@ -1582,7 +1682,7 @@ mod tests {
             rent::Rent,
             signature::{keypair_from_seed, signers::Signers, Keypair, Signer},
             system_instruction, system_program,
-            transaction::{Transaction, MAX_TX_ACCOUNT_LOCKS},
+            transaction::{MevPoolKeys, Transaction, MAX_TX_ACCOUNT_LOCKS},
         },
         std::{
             borrow::Cow,
@@ -2673,6 +2773,182 @@ mod tests {
         assert_eq!(results[0], Err(TransactionError::AccountLoadedTwice));
     }
 
+    /// A transaction that writes to `payer` and `writable`, and reads
+    /// `readonly`.
+    fn new_mev_lock_tx(
+        payer: &Keypair,
+        writable: Pubkey,
+        readonly: Vec<Pubkey>,
+    ) -> SanitizedTransaction {
+        let mut account_keys = vec![payer.pubkey(), writable];
+        account_keys.extend(readonly);
+        account_keys.push(native_loader::id());
+        let num_readonly_unsigned = account_keys.len() as u8 - 2;
+        let instructions = vec![CompiledInstruction::new(
+            account_keys.len() as u8 - 1,
+            &(),
+            vec![0, 1],
+        )];
+        let message = Message::new_with_compiled_instructions(
+            1,
+            0,
+            num_readonly_unsigned,
+            account_keys,
+            Hash::default(),
+            instructions,
+        );
+        new_sanitized_tx(&[payer], message, Hash::default())
+    }
+
+    fn new_mev_pool_keys() -> MevPoolKeys {
+        MevPoolKeys::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        )
+    }
+
+    #[test]
+    fn test_mev_lock_footprint_without_mev_keys() {
+        let accounts = Accounts::default_for_tests();
+        let tx = new_mev_lock_tx(&Keypair::new(), Pubkey::new_unique(), vec![]);
+        let locks = tx.get_account_locks_unchecked();
+        let (_, _, footprint) =
+            accounts.get_readable_and_writable_locks_vec(locks.writable, locks.readonly, None);
+        assert_eq!(footprint, MevLockFootprint::default());
+        assert!(accounts.transaction_mev_lock_footprint(&tx).is_empty());
+
+        let results = accounts.lock_accounts([tx].iter(), MAX_TX_ACCOUNT_LOCKS);
+        assert_eq!(results[0], Ok(()));
+        assert!(accounts.mev_lock_footprint().is_empty());
+    }
+
+    #[test]
+    fn test_mev_lock_footprint_without_overlapping_keys() {
+        let accounts = Accounts::default_for_tests();
+        let mut tx = new_mev_lock_tx(&Keypair::new(), Pubkey::new_unique(), vec![]);
+        // A pool that we only read: all its accounts, and the token
+        // program, are extra readonly locks.
+        tx.mev_keys = Some(MevKeys::new(vec![new_mev_pool_keys()]));
+        let locks = tx.get_account_locks_unchecked();
+        let (readonly, writable, footprint) = accounts.get_readable_and_writable_locks_vec(
+            locks.writable,
+            locks.readonly,
+            locks.readonly_mev,
+        );
+        assert_eq!(readonly.len(), 8);
+        assert_eq!(writable.len(), 2);
+        assert_eq!(
+            footprint,
+            MevLockFootprint {
+                readonly: 7,
+                writable: 0,
+            }
+        );
+
+        let results = accounts.lock_accounts([tx].iter(), MAX_TX_ACCOUNT_LOCKS);
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(accounts.mev_lock_footprint(), footprint);
+    }
+
+    #[test]
+    fn test_mev_lock_footprint_with_mints() {
+        let accounts = Accounts::default_for_tests();
+        let mut tx = new_mev_lock_tx(&Keypair::new(), Pubkey::new_unique(), vec![]);
+        // The mints are read, also for a pool that we trade with.
+        let pool_keys = new_mev_pool_keys()
+            .with_trade_accounts(Pubkey::new_unique(), Pubkey::new_unique())
+            .with_mints(Pubkey::new_unique(), Pubkey::new_unique());
+        tx.mev_keys = Some(MevKeys::new(vec![pool_keys]));
+        let locks = tx.get_account_locks_unchecked();
+        let (_, _, footprint) = accounts.get_readable_and_writable_locks_vec(
+            locks.writable,
+            locks.readonly,
+            locks.readonly_mev,
+        );
+        assert_eq!(
+            footprint,
+            MevLockFootprint {
+                readonly: 5,
+                writable: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mev_lock_footprint_with_overlapping_keys() {
+        let accounts = Accounts::default_for_tests();
+        let source = Pubkey::new_unique();
+        let token_program = Pubkey::new_unique();
+        let pool_keys = MevPoolKeys {
+            token_program,
+            ..new_mev_pool_keys()
+        }
+        .with_trade_accounts(source, Pubkey::new_unique());
+        // The transaction writes to our source account, and reads the
+        // pool's token A account and the token program.
+        let mut tx = new_mev_lock_tx(
+            &Keypair::new(),
+            source,
+            vec![pool_keys.token_a, token_program],
+        );
+        tx.mev_keys = Some(MevKeys::new(vec![pool_keys]));
+        let locks = tx.get_account_locks_unchecked();
+        let (readonly, writable, footprint) = accounts.get_readable_and_writable_locks_vec(
+            locks.writable,
+            locks.readonly,
+            locks.readonly_mev,
+        );
+        // Token A becomes writable, the token program is already read.
+        assert_eq!(readonly.len(), 4);
+        assert_eq!(writable.len(), 7);
+        assert_eq!(
+            footprint,
+            MevLockFootprint {
+                readonly: 2,
+                writable: 5,
+            }
+        );
+        assert_eq!(accounts.transaction_mev_lock_footprint(&tx), footprint);
+
+        // The footprint adds up over transactions.
+        let other_tx = new_mev_lock_tx(&Keypair::new(), Pubkey::new_unique(), vec![]);
+        let results = accounts.lock_accounts([tx, other_tx].iter(), MAX_TX_ACCOUNT_LOCKS);
+        assert_eq!(results, vec![Ok(()), Ok(())]);
+        assert_eq!(accounts.mev_lock_footprint(), footprint);
+    }
+
+    #[test]
+    fn test_mev_lock_footprint_per_slot() {
+        let accounts = Accounts::default_for_tests();
+        let mut tx = new_mev_lock_tx(&Keypair::new(), Pubkey::new_unique(), vec![]);
+        tx.mev_keys = Some(MevKeys::new(vec![new_mev_pool_keys()]));
+        let first_footprint = accounts.transaction_mev_lock_footprint(&tx);
+        let results = accounts.lock_accounts([tx.clone()].iter(), MAX_TX_ACCOUNT_LOCKS);
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(accounts.report_mev_lock_footprint(1), first_footprint);
+        assert!(accounts.mev_lock_footprint().is_empty());
+        accounts.unlock_accounts([tx].iter(), &results);
+
+        // The next slot only reports its own transactions.
+        let mut tx = new_mev_lock_tx(&Keypair::new(), Pubkey::new_unique(), vec![]);
+        let pool_keys =
+            new_mev_pool_keys().with_trade_accounts(Pubkey::new_unique(), Pubkey::new_unique());
+        tx.mev_keys = Some(MevKeys::new(vec![pool_keys]));
+        let second_footprint = accounts.transaction_mev_lock_footprint(&tx);
+        assert_ne!(second_footprint, first_footprint);
+        let results = accounts.lock_accounts([tx].iter(), MAX_TX_ACCOUNT_LOCKS);
+        assert_eq!(results[0], Ok(()));
+        assert_eq!(accounts.report_mev_lock_footprint(2), second_footprint);
+
+        // A slot without MEV locks reports nothing.
+        assert!(accounts.report_mev_lock_footprint(3).is_empty());
+    }
+
     #[test]
     fn test_lock_accounts_with_too_many_accounts() {
         let accounts = Accounts::new_with_config_for_tests(
@@ -677,9 +677,9 @@ pub struct LoadAndExecuteTransactionsOutput {
     pub executed_with_successful_result_count: usize,
     pub signature_count: u64,
     pub error_counters: TransactionErrorMetrics,
-    /// A tuple with the MEV transaction to be included in the next batch and
-    /// the calculated profit from the transaction.
-    pub mev_sanitized_tx_profit: Option<(SanitizedTransaction, u64)>,
+    /// The MEV transactions to be executed after this batch, in order, with
+    /// the calculated profit of every transaction. Empty if there are none.
+    pub mev_sanitized_tx_profit: Vec<(SanitizedTransaction, u64)>,
 }
 
 #[derive(Debug, Clone)]
@@ -3062,6 +3062,7 @@ impl Bank {
             self.freeze_started.store(true, Relaxed);
             *hash = self.hash_internal_state();
             self.rc.accounts.accounts_db.mark_slot_frozen(self.slot());
+            self.rc.accounts.report_mev_lock_footprint(self.slot());
         }
     }
 
@@ -3965,6 +3966,16 @@ impl Bank {
             get_executors_time.as_us()
         );
 
+        let mev_lock_footprint = self.rc.accounts.transaction_mev_lock_footprint(tx);
+        saturating_add_assign!(
+            timings.details.mev_readonly_lock_count,
+            mev_lock_footprint.readonly
+        );
+        saturating_add_assign!(
+            timings.details.mev_writable_lock_count,
+            mev_lock_footprint.writable
+        );
+
         let mut transaction_accounts = Vec::new();
         std::mem::swap(&mut loaded_transaction.accounts, &mut transaction_accounts);
         let mut transaction_context = TransactionContext::new(
@@ -4156,7 +4167,7 @@ impl Bank {
         let mut execution_time = Measure::start("execution_time");
         let mut signature_count: u64 = 0;
         let mut execution_results = Vec::with_capacity(sanitized_txs.len());
-        let mut mev_sanitized_tx_profit: Option<(SanitizedTransaction, u64)> = None;
+        let mut mev_sanitized_tx_profit: Vec<(SanitizedTransaction, u64)> = Vec::new();
 
         for (accs, tx) in loaded_transactions.iter_mut().zip(sanitized_txs.iter()) {
             match accs {
@@ -4222,34 +4233,55 @@ impl Bank {
                         timings,
                         &mut error_counters,
                     );
-                    let tx_succeeded =
-                        if let TransactionExecutionResult::Executed { .. } = tx_result {
-                            true
-                        } else {
-                            false
-                        };
+                    // A transaction that failed during execution still wrote to
+                    // the loaded accounts, but those writes are rolled back.
+                    let tx_executed = tx_result.was_executed();
+                    let tx_failed = !tx_result.was_executed_successfully();
                     execution_results.push(tx_result);
-                    if let (Some(Ok(pre_pool_state)), true) = (pre_tx_pool_state, tx_succeeded) {
+                    if let (Some(Ok(pre_pool_state)), true) = (pre_tx_pool_state, tx_executed) {
                         let mev = mev
                             .as_ref()
                             .expect("Is Some because we have a pre pool state.");
 
-                        if let Some((sanitized_txs, profit)) = mev
-                            .log_mev_opportunities_get_max_profit_tx(
-                                tx,
+                        let sanitized_txs = mev.log_mev_opportunities_get_max_profit_tx(
+                            tx,
+                            self.slot,
+                            tx_failed,
+                            pre_pool_state,
+                            &loaded_transaction,
+                            *tx.message().recent_blockhash(),
+                            &self.rent_collector.rent,
+                        );
+                        let total_profit = |txs: &[(SanitizedTransaction, u64)]| -> u64 {
+                            txs.iter().map(|(_tx, profit)| profit).sum()
+                        };
+                        // We assume all paths begin and end at the same token.
+                        // TODO(81): Assert this is done by construction.
+                        // Only the most profitable opportunity of the batch
+                        // is executed, the other one will never be.
+                        let released = if sanitized_txs.is_empty() {
+                            Vec::new()
+                        } else if mev.confirm_trigger_before_craft {
+                            // The banking stage takes them once the trigger
+                            // is committed.
+                            mev.hold_until_trigger_committed(
+                                *tx.signature(),
                                 self.slot,
-                                pre_pool_state,
-                                &loaded_transaction,
-                                *tx.message().recent_blockhash(),
-                            )
+                                sanitized_txs,
+                            );
+                            Vec::new()
+                        } else if !mev_sanitized_tx_profit.is_empty()
+                            && total_profit(&mev_sanitized_tx_profit)
+                                >= total_profit(&sanitized_txs)
                         {
-                            // We assume all paths begin and end at the same token.
-                            // TODO(81): Assert this is done by construction.
-                            if !matches!(mev_sanitized_tx_profit, Some(ref tx_profit) if tx_profit.1 >= profit)
-                            {
-                                mev_sanitized_tx_profit.replace((sanitized_txs, profit));
-                            }
-                        }
+                            sanitized_txs
+                        } else {
+                            std::mem::replace(&mut mev_sanitized_tx_profit, sanitized_txs)
+                        };
+                        mev.release_txs(
+                            self.slot,
+                            released.iter().map(|(released_tx, _profit)| released_tx),
+                        );
                     }
                 }
             }
@@ -18242,4 +18274,166 @@ pub(crate) mod tests {
         // also be reclaimed by rent collection.
         assert!(bank.load_accounts_data_size_delta() <= -(data_size as i64));
     }
+
+    #[test]
+    fn test_discarded_mev_txs_release_slot_cap() {
+        use crate::{
+            inline_spl_token,
+            mev::{
+                arbitrage::SkipReason,
+                config::{AllOrcaPoolAddresses, MevConfig},
+                test_utils::{
+                    pool, pubkey, steth_stsol_usdc_path, STETH_USDC_POOL, STSOL_STETH_POOL,
+                    STSOL_USDC_POOL,
+                },
+                utils::B58Pubkey,
+                MevMsg,
+            },
+        };
+
+        // Stands in for the SPL token program: sets the amount of the token
+        // account to the amount in the instruction data.
+        fn mock_set_token_amount(
+            _first_instruction_account: usize,
+            data: &[u8],
+            invoke_context: &mut InvokeContext,
+        ) -> result::Result<(), InstructionError> {
+            let transaction_context = &invoke_context.transaction_context;
+            let instruction_context = transaction_context.get_current_instruction_context()?;
+            let mut token_account =
+                instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+            token_account.get_data_mut()[64..72].copy_from_slice(data);
+            Ok(())
+        }
+
+        let GenesisConfigInfo {
+            genesis_config,
+            mint_keypair,
+            ..
+        } = create_genesis_config_with_leader(
+            1_000_000 * LAMPORTS_PER_SOL,
+            &Pubkey::new_unique(),
+            42,
+        );
+        let mut bank = Bank::new_for_tests(&genesis_config);
+        bank.add_builtin(
+            "mock_spl_token",
+            &inline_spl_token::id(),
+            mock_set_token_amount,
+        );
+
+        let (usdc, steth, stsol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut orca_accounts = Vec::new();
+        let mut pool_a_accounts = Vec::new();
+        for (address, (mint_a, mint_b), (balance_a, balance_b)) in [
+            (STETH_USDC_POOL, (steth, usdc), (4618233234, 6400518033)),
+            (
+                STSOL_STETH_POOL,
+                (stsol, steth),
+                (54896627850684, 13408494240),
+            ),
+            (STSOL_USDC_POOL, (stsol, usdc), (400881658679, 138436018345)),
+        ] {
+            let (orca_pool, accounts) = pool(pubkey(address))
+                .mints(mint_a, mint_b)
+                .balances(balance_a, balance_b)
+                .source(Pubkey::new_unique())
+                .source_balance(u64::MAX / 2)
+                .destination(Pubkey::new_unique())
+                .destination_balance(u64::MAX / 2)
+                .build_accounts();
+            // After the swap state and the mints come the pool's token
+            // accounts.
+            pool_a_accounts.push(accounts[3].0);
+            for (pubkey, mut account) in accounts {
+                account.set_lamports(Rent::default().minimum_balance(account.data().len()));
+                bank.store_account(&pubkey, &account);
+            }
+            orca_accounts.push(orca_pool);
+        }
+        let steth_usdc_pool_a_account = pool_a_accounts[0];
+
+        let (log_send_channel, log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                watched_programs: vec![B58Pubkey(inline_spl_token::id())],
+                orca_accounts: AllOrcaPoolAddresses::new(orca_accounts),
+                mev_paths: vec![steth_stsol_usdc_path()],
+                max_mev_txs_per_slot: Some(2),
+                ..MevConfig::default()
+            },
+        );
+        let user_authority = Keypair::new();
+        bank.store_account(
+            &user_authority.pubkey(),
+            &AccountSharedData::new(LAMPORTS_PER_SOL, 0, &system_program::id()),
+        );
+        mev.user_authority = Some(Arc::new(user_authority));
+
+        // Three triggers in one batch, that each add stETH to the pool where
+        // we buy it: the second adds the most, the third less than that.
+        let triggers: Vec<_> = [4_700_000_000_u64, 5_000_000_000, 4_800_000_000]
+            .iter()
+            .map(|steth_balance| {
+                let instruction = Instruction::new_with_bytes(
+                    inline_spl_token::id(),
+                    &steth_balance.to_le_bytes(),
+                    vec![AccountMeta::new(steth_usdc_pool_a_account, false)],
+                );
+                let mut tx = SanitizedTransaction::from_transaction_for_tests(
+                    Transaction::new_signed_with_payer(
+                        &[instruction],
+                        Some(&mint_keypair.pubkey()),
+                        &[&mint_keypair],
+                        bank.last_blockhash(),
+                    ),
+                );
+                mev.fill_tx_mev_accounts(&mut tx);
+                assert!(tx.mev_keys().is_some());
+                tx
+            })
+            .collect();
+        // The triggers all write to our token accounts, we execute them
+        // without locks to get them into one batch.
+        let mut batch = TransactionBatch::new(
+            vec![Ok(()); triggers.len()],
+            &bank,
+            Cow::Borrowed(&triggers),
+        );
+        batch.set_needs_unlock(false);
+        let output = bank.load_and_execute_transactions(
+            &batch,
+            MAX_PROCESSING_AGE,
+            false,
+            false,
+            &mut ExecuteTimings::default(),
+            None,
+            Some(&mev),
+        );
+
+        let profits: Vec<u64> = log_receiver
+            .try_iter()
+            .filter_map(|msg| match msg {
+                MevMsg::Opportunities(outputs) => Some(outputs),
+                _ => None,
+            })
+            .map(|outputs| {
+                assert_eq!(outputs.len(), 1);
+                // The first opportunity, that the second replaced, no longer
+                // counts against `max_mev_txs_per_slot`, so the third trigger
+                // still gets a transaction.
+                assert_ne!(outputs[0].skip_reason, Some(SkipReason::SlotCap));
+                outputs[0].profit
+            })
+            .collect();
+        assert_eq!(profits.len(), 3);
+        assert!(profits[1] > profits[2] && profits[2] > profits[0]);
+        assert_eq!(output.mev_sanitized_tx_profit.len(), 1);
+        assert_eq!(output.mev_sanitized_tx_profit[0].1, profits[1]);
+    }
 }
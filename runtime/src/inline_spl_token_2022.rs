@@ -3,6 +3,9 @@ use crate::inline_spl_token::{self, GenericTokenAccount};
 
 solana_sdk::declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
 
+// `spl_token_program_2022::extension::AccountType::Mint` ordinal value
+pub const ACCOUNTTYPE_MINT: u8 = 1;
+
 // `spl_token_program_2022::extension::AccountType::Account` ordinal value
 pub const ACCOUNTTYPE_ACCOUNT: u8 = 2;
 
@@ -1,787 +1,152 @@
 pub mod arbitrage;
+pub mod audit;
+pub mod authority;
+pub mod checkpoint;
+pub mod clock_skew;
+pub mod compute_units;
+pub mod config;
+pub mod config_dump;
+pub mod debounce;
+pub mod discovery;
+pub mod engine;
+pub mod error;
+pub mod failures;
+pub mod forwarder;
+pub mod includes;
+pub mod lock_impact;
+pub mod log_channel;
+pub mod log_file;
+pub mod log_reader;
+pub mod logging;
+pub mod mint;
+pub mod pool_state;
+pub mod pool_stats;
+pub mod priority_fee;
+pub mod reporting;
+pub mod searcher;
+pub mod signer;
+pub mod simulate;
+pub mod status;
+#[cfg(any(test, feature = "testing"))]
+pub mod test_utils;
 pub mod utils;
+pub mod verify;
+pub mod watchdog;
 
-use std::{
-    collections::{HashMap, HashSet},
-    fs::{self, File},
-    io::{BufReader, Write},
-    sync::Arc,
-    thread::JoinHandle,
-};
-
-use crossbeam_channel::{unbounded, Sender};
-use log::{error, warn};
-use serde::{
-    ser::{SerializeMap, SerializeStruct},
-    Serialize, Serializer,
-};
-use solana_sdk::{
-    account::ReadableAccount,
-    clock::Slot,
-    hash::Hash,
-    pubkey::Pubkey,
-    signature::{Keypair, Signature},
-    signer::Signer,
-    transaction::{MevKeys, MevPoolKeys, SanitizedTransaction},
-};
-use spl_token::solana_program::{program_error::ProgramError, program_pack::Pack};
-use spl_token_swap::{
-    curve::calculator::{CurveCalculator, SwapWithoutFeesResult},
-    state::SwapVersion,
-};
-
-use crate::{
-    accounts::LoadedTransaction,
-    accounts::MevAccountOrIdx::{Idx, ReadAccount},
-    inline_spl_token,
-    mev::utils::{deserialize_b58, serialize_b58},
-};
+use std::time::Duration;
 
-use self::{
-    arbitrage::{
-        create_swap_tx, InputOutputPairs, MevOpportunityWithInput, MevPath, MevTxOutput,
-        SwapArguments, TradeDirection,
+pub use self::{
+    engine::{
+        EvaluatedPaths, InflightExposure, Mev, MinTriggerDelta, PendingOpportunities, SlotTxCount,
+    },
+    error::MevError,
+    logging::{
+        CompetitorArbitrage, DroppedTransaction, ExecutedTransactionOutput, MevEvent,
+        MevEventNotifier, MevLog, MevMsg, NoopMevEventNotifier, PathSkipped, PoolConfigMismatch,
+        PrePostPoolStates, ValidatorAttribution,
     },
-    utils::{deserialize_opt_b58, serialize_opt_b58, AllOrcaPoolAddresses, MevConfig},
+    pool_state::{OrcaPoolAddresses, OrcaPoolWithBalance, PoolStates},
 };
 
-/// MevLog saves the `log_send_channel` channel, where it can be passed and
-/// cloned in the `Bank` structure. We spawn a thread on the initialization of
-/// the struct to listen and log data in `log_path`.
-#[derive(Debug)]
-pub struct MevLog {
-    pub thread_handle: JoinHandle<()>,
-    pub log_send_channel: Sender<MevMsg>,
-}
-
-#[derive(Debug, Clone)]
-pub struct Mev {
-    pub log_send_channel: Sender<MevMsg>,
-    // A set of `Pubkey` for us to trigger MEV.
-    pub watched_programs: HashSet<Pubkey>,
-
-    // These public keys are going to be loaded so we can ensure no other thread
-    // modifies the data we are interested in.
-    // TODO: Change this to pairs we are willing to trade on.
-    pub orca_monitored_accounts: Arc<AllOrcaPoolAddresses>,
-
-    // MEV paths that we are interested on finding an opportunity
-    pub mev_paths: Vec<MevPath>,
-
-    // Key for the user authority for signing transactions.
-    // If `None`, we do not try to craft MEV txs.
-    pub user_authority: Arc<Option<Keypair>>,
-
-    // A mapping with the minimum profit to execute MEV transactions token per
-    // token address.
-    pub minimum_profit: HashMap<Pubkey, u64>,
-}
-
-#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
-pub struct OrcaPoolAddresses {
-    #[serde(skip_serializing)]
-    #[serde(skip_deserializing)]
-    program_id: Pubkey,
-
-    #[serde(serialize_with = "serialize_b58")]
-    #[serde(deserialize_with = "deserialize_b58")]
-    address: Pubkey,
-
-    /// Source address, owned by the pool.
-    #[serde(serialize_with = "serialize_b58")]
-    #[serde(deserialize_with = "deserialize_b58")]
-    pool_a_account: Pubkey,
-
-    /// Destination address, owned by the pool.
-    #[serde(serialize_with = "serialize_b58")]
-    #[serde(deserialize_with = "deserialize_b58")]
-    pool_b_account: Pubkey,
-
-    /// Source address, owned by us.
-    #[serde(default)]
-    #[serde(serialize_with = "serialize_opt_b58")]
-    #[serde(deserialize_with = "deserialize_opt_b58")]
-    pub source: Option<Pubkey>,
-
-    /// Destination address, owned by us.
-    #[serde(default)]
-    #[serde(serialize_with = "serialize_opt_b58")]
-    #[serde(deserialize_with = "deserialize_opt_b58")]
-    pub destination: Option<Pubkey>,
-
-    /// Pool's mint account.
-    #[serde(serialize_with = "serialize_b58")]
-    #[serde(deserialize_with = "deserialize_b58")]
-    pub pool_mint: Pubkey,
-
-    /// Pool's fee account.
-    #[serde(serialize_with = "serialize_b58")]
-    #[serde(deserialize_with = "deserialize_b58")]
-    pub pool_fee: Pubkey,
-
-    /// Calculated by us from the pool's data.
-    #[serde(skip_serializing)]
-    #[serde(skip_deserializing)]
-    pub pool_authority: Pubkey,
-
-    /// Mint of pool's a account.
-    #[serde(skip_serializing)]
-    #[serde(skip_deserializing)]
-    pub pool_a_mint: Pubkey,
-
-    /// Mint of pool's b account.
-    #[serde(skip_serializing)]
-    #[serde(skip_deserializing)]
-    pub pool_b_mint: Pubkey,
-}
-
-#[derive(Debug, Serialize)]
-pub struct OrcaPoolWithBalance {
-    pool: OrcaPoolAddresses,
-    pool_a_balance: u64,
-    pool_b_balance: u64,
-    source_balance: Option<u64>,
-    destination_balance: Option<u64>,
-    fees: Fees,
-
-    #[serde(skip_serializing)]
-    curve_calculator: Arc<dyn CurveCalculator + Sync + Send>,
-}
-
-#[derive(Debug)]
-struct Fees(spl_token_swap::curve::fees::Fees);
-
-impl Serialize for Fees {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let mut state = serializer.serialize_struct("Fees", 3)?;
-        state.serialize_field("host_fee_denominator", &self.0.host_fee_denominator)?;
-        state.serialize_field("host_fee_numerator", &self.0.host_fee_numerator)?;
-        state.serialize_field(
-            "owner_trade_fee_denominator",
-            &self.0.owner_trade_fee_denominator,
-        )?;
-        state.serialize_field(
-            "owner_trade_fee_numerator",
-            &self.0.owner_trade_fee_numerator,
-        )?;
-        state.serialize_field("trade_fee_denominator", &self.0.trade_fee_denominator)?;
-        state.serialize_field("trade_fee_numerator", &self.0.trade_fee_numerator)?;
-        state.end()
-    }
-}
-
-// A map from `Pubkey` as `String` to `OrcaPoolWithBalance` so it's easier to
-// serialize with `serde_json`
-#[derive(Debug)]
-pub struct PoolStates(HashMap<Pubkey, OrcaPoolWithBalance>);
-
-impl FromIterator<(Pubkey, OrcaPoolWithBalance)> for PoolStates {
-    fn from_iter<T: IntoIterator<Item = (Pubkey, OrcaPoolWithBalance)>>(iter: T) -> Self {
-        let hashmap = HashMap::from_iter(iter);
-        PoolStates(hashmap)
-    }
-}
-
-impl Serialize for PoolStates {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let mut map = serializer.serialize_map(Some(self.0.len()))?;
-        for (k, v) in &self.0 {
-            map.serialize_entry(&k.to_string(), &v)?;
-        }
-        map.end()
-    }
-}
-
-pub enum MevMsg {
-    Log(PrePostPoolStates),
-    Opportunities(Vec<MevTxOutput>),
-    ExecutedTransaction(ExecutedTransactionOutput),
-    Exit,
-}
-
-#[derive(Debug, Serialize)]
-pub struct ExecutedTransactionOutput {
-    #[serde(serialize_with = "serialize_b58")]
-    pub transaction_hash: Hash,
-    #[serde(serialize_with = "serialize_b58")]
-    pub transaction_signature: Signature,
-
-    pub is_successful: bool,
-    pub possible_profit: u64,
-}
-
-#[derive(Debug, Serialize)]
-pub struct PrePostPoolStates {
-    /// Transaction hash which triggered the MEV.
-    #[serde(serialize_with = "serialize_b58")]
-    transaction_hash: Hash,
-
-    /// The first signature of the transaction.
-    ///
-    /// Block explorers identify transactions by the first signature, not by the
-    /// transaction hash, so we also keep the signature for cross-referencing.
-    #[serde(serialize_with = "serialize_b58")]
-    transaction_signature: Signature,
-
-    slot: Slot,
-
-    orca_pre_tx_pool: PoolStates,
-    orca_post_tx_pool: PoolStates,
-}
-
-impl Mev {
-    pub fn new(log_send_channel: Sender<MevMsg>, config: MevConfig) -> Self {
-        let mev_paths = config
-            .mev_paths
-            .into_iter()
-            .map(|path| match (path.path.first(), path.path.last()) {
-                (None, _) | (_, None) => panic!("MEV paths should have at least 1 element"),
-                (Some(pair_a), Some(pair_b)) => {
-                    if pair_a == pair_b {
-                        panic!("MEV paths should not end in the same pool with the same direction of trade")
-                    }
-                    path
-                }
-            })
-            .collect();
-        Mev {
-            log_send_channel,
-            watched_programs: config
-                .watched_programs
-                .iter()
-                .map(|b58pubkey| b58pubkey.0)
-                .collect(),
-            orca_monitored_accounts: Arc::new(config.orca_accounts),
-            mev_paths,
-            user_authority: Arc::new(config.user_authority_path.map(|path| {
-                let file = File::open(path).expect("[MEV] Could not open path");
-                let reader = BufReader::new(file);
-                let secret_key_bytes: Vec<u8> =
-                    serde_json::from_reader(reader).expect("[MEV] Could not read authority path");
-                Keypair::from_bytes(&secret_key_bytes)
-                    .expect("[MEV] Could not generate Keypair from path")
-            })),
-            minimum_profit: config
-                .minimum_profit
-                .into_iter()
-                .map(|(b58_pubkey, min)| (b58_pubkey.0, min))
-                .collect(),
-        }
-    }
-
-    /// Fill the field of `transaction.mev_accounts` with accounts we are
-    /// interested in watching.
-    pub fn fill_tx_mev_accounts(&self, tx: &mut SanitizedTransaction) {
-        if self.is_monitored_account(tx) {
-            let pool_keys = self
-                .orca_monitored_accounts
-                .0
-                .iter()
-                .map(|orca_pool| MevPoolKeys {
-                    pool: orca_pool.address,
-                    source: orca_pool.source,
-                    destination: orca_pool.destination,
-                    token_a: orca_pool.pool_a_account,
-                    token_b: orca_pool.pool_b_account,
-                    pool_mint: orca_pool.pool_mint,
-                    pool_fee: orca_pool.pool_fee,
-                    pool_authority: orca_pool.pool_authority,
-                })
-                .collect();
-            tx.mev_keys = Some(MevKeys {
-                pool_keys,
-                // Use SPL token ID for all pools.
-                token_program: inline_spl_token::id(),
-                user_authority: (*self.user_authority).as_ref().map(|kp| kp.pubkey()),
-            })
-        }
-    }
-
-    /// Attempts to deserialize the Orca accounts MEV is interested in,
-    /// in case the deserialization fails for some reason, returns the error.
-    pub fn get_all_orca_monitored_accounts(
-        &self,
-        loaded_transaction: &LoadedTransaction,
-    ) -> Option<Result<PoolStates, ProgramError>> {
-        let pool_states = loaded_transaction
-            .mev_accounts
-            .as_ref()
-            .map(|mev_accounts| {
-                mev_accounts
-                    .pool_accounts
-                    .iter()
-                    .map(|mev_account| {
-                        let get_account =
-                            |pubkey: &Pubkey| match &mev_accounts.pubkey_account_map[pubkey] {
-                                Idx(idx) => &loaded_transaction.accounts[*idx],
-                                ReadAccount(acc) => &acc,
-                            };
-                        let pool_acc = get_account(&mev_account.pool);
-                        // Owner of the pool should be the `program_id`.
-                        let program_id = pool_acc.1.owner();
-
-                        let (pool_authority, _authority_bump_seed) = Pubkey::find_program_address(
-                            &[&mev_account.pool.to_bytes()[..]],
-                            &program_id,
-                        );
-                        let pool = SwapVersion::unpack(pool_acc.1.data())?;
-
-                        let pool_a_acc = get_account(&mev_account.token_a);
-                        let pool_a_account =
-                            spl_token::state::Account::unpack(pool_a_acc.1.data())?;
-
-                        let pool_b_acc = get_account(&mev_account.token_b);
-                        let pool_b_account =
-                            spl_token::state::Account::unpack(pool_b_acc.1.data())?;
-
-                        let pool_source_pubkey_amount = mev_account
-                            .source
-                            .as_ref()
-                            .map(|src| {
-                                let (source_pubkey, source_account) = get_account(src);
-                                let spl_acc =
-                                    spl_token::state::Account::unpack(source_account.data())?;
-                                Ok::<(&solana_sdk::pubkey::Pubkey, u64), ProgramError>((
-                                    source_pubkey,
-                                    spl_acc.amount,
-                                ))
-                            })
-                            .transpose()?;
-
-                        let pool_destination_pubkey_amount = mev_account
-                            .destination
-                            .as_ref()
-                            .map(|dst| {
-                                let (destination_pubkey, destination_account) = get_account(dst);
-                                let spl_acc =
-                                    spl_token::state::Account::unpack(destination_account.data())?;
-                                Ok::<(&solana_sdk::pubkey::Pubkey, u64), ProgramError>((
-                                    destination_pubkey,
-                                    spl_acc.amount,
-                                ))
-                            })
-                            .transpose()?;
-
-                        let pool_mint_pubkey = get_account(&mev_account.pool_mint).0;
-                        let pool_fee_pubkey = get_account(&mev_account.pool_fee).0;
-
-                        Ok((
-                            pool_acc.0,
-                            OrcaPoolWithBalance {
-                                pool: OrcaPoolAddresses {
-                                    program_id: *program_id,
-                                    address: pool_acc.0,
-                                    pool_a_account: pool_a_acc.0,
-                                    pool_b_account: pool_b_acc.0,
-                                    source: pool_source_pubkey_amount.map(|(src, _amount)| *src),
-                                    destination: pool_destination_pubkey_amount
-                                        .map(|(dst, _amount)| *dst),
-                                    pool_mint: pool_mint_pubkey,
-                                    pool_fee: pool_fee_pubkey,
-                                    pool_authority: pool_authority,
-                                    pool_a_mint: Pubkey::new(&pool_a_account.mint.to_bytes()),
-                                    pool_b_mint: Pubkey::new(&pool_b_account.mint.to_bytes()),
-                                },
-                                pool_a_balance: pool_a_account.amount,
-                                pool_b_balance: pool_b_account.amount,
-                                fees: Fees(pool.fees().clone()),
-                                curve_calculator: pool.swap_curve().calculator.clone(),
-                                source_balance: pool_source_pubkey_amount
-                                    .map(|(_src, amount)| amount),
-                                destination_balance: pool_destination_pubkey_amount
-                                    .map(|(_dst, amount)| amount),
-                            },
-                        ))
-                    })
-                    .collect::<Result<PoolStates, ProgramError>>()
-            });
-        pool_states
-    }
-
-    pub fn is_monitored_account(&self, tx: &SanitizedTransaction) -> bool {
-        tx.message()
-            .account_keys()
-            .iter()
-            .any(|account_key| self.watched_programs.contains(account_key))
-    }
-
-    /// Log the pool state after a transaction interacted with one or more
-    /// account from the pool
-    /// Returns a tuple with the most profitable MEV tx and the profit in the
-    /// token's unit.
-    pub fn log_mev_opportunities_get_max_profit_tx(
-        &self,
-        tx: &SanitizedTransaction,
-        slot: Slot,
-        pre_tx_pool_state: PoolStates,
-        loaded_tx: &LoadedTransaction,
-        blockhash: Hash,
-    ) -> Option<(SanitizedTransaction, u64)> {
-        let post_tx_pool_state = self.get_all_orca_monitored_accounts(loaded_tx)?.ok()?;
-        let mut mev_tx_outputs = self.get_arbitrage_tx_outputs(&post_tx_pool_state, blockhash);
-
-        if let Err(err) = self.log_send_channel.send(MevMsg::Log(PrePostPoolStates {
-            transaction_hash: *tx.message_hash(),
-            transaction_signature: *tx.signature(),
-            slot,
-            orca_pre_tx_pool: pre_tx_pool_state,
-            orca_post_tx_pool: post_tx_pool_state,
-        })) {
-            error!("[MEV] Could not log pool states, error: {}", err);
-        }
-
-        let mev_tx_output = mev_tx_outputs
-            .iter_mut()
-            .max_by(|a, b| a.profit.cmp(&b.profit))?;
-
-        let profit = mev_tx_output.profit;
-        let sanitized_tx = mev_tx_output.sanitized_tx.take();
-
-        if let Err(err) = self
-            .log_send_channel
-            .send(MevMsg::Opportunities(mev_tx_outputs))
-        {
-            error!("[MEV] Could not log arbitrage, error: {}", err);
-        }
-        Some((sanitized_tx?, profit))
-    }
-
-    pub fn get_arbitrage_tx_outputs(
-        &self,
-        pool_states: &PoolStates,
-        blockhash: Hash,
-    ) -> Vec<MevTxOutput> {
-        self.mev_paths
-            .iter()
-            .enumerate()
-            .filter_map(|(path_idx, mev_path)| {
-                let path_output = mev_path.get_path_calculation_output(pool_states)?;
-                let initial_amount = path_output.optimal_input.floor() as u128;
-
-                let first_pair_info = mev_path.path.first()?;
-
-                // Check the balance of which token initiates the path.
-                let initial_source_amount_opt = match first_pair_info.direction {
-                    TradeDirection::AtoB => pool_states.0.get(&first_pair_info.pool)?.source_balance,
-                    TradeDirection::BtoA => pool_states.0.get(&first_pair_info.pool)?.destination_balance,
-                };
-
-                let initial_amount = if let Some(source_token_balance) = initial_source_amount_opt {
-                    initial_amount.min(source_token_balance as u128)
-                } else {
-                    initial_amount
-                };
-
-                let mut amount_in = initial_amount;
-                let mut input_output_pairs = Vec::with_capacity(mev_path.path.len());
-
-                let mut swap_arguments_vec: Vec<SwapArguments> = Vec::with_capacity(mev_path.path.len());
-                for pair_info in &mev_path.path {
-                    let pool_state = pool_states.0.get(&pair_info.pool)?;
-
-                    let trade_fee = pool_state.fees.0.trading_fee(amount_in)?;
-                    let owner_fee = pool_state.fees.0.owner_trading_fee(amount_in)?;
-
-                    let total_fees = trade_fee.checked_add(owner_fee)?;
-                    let source_amount_less_fees = amount_in.checked_sub(total_fees)?;
-
-                    let (
-                        trade_direction,
-                        source_pubkey,
-                        swap_source_pubkey,
-                        destination_pubkey,
-                        swap_destination_pubkey,
-                        swap_source_amount,
-                        swap_destination_amount,
-                    ) = match pair_info.direction {
-                        TradeDirection::AtoB => (
-                            spl_token_swap::curve::calculator::TradeDirection::AtoB,
-                            pool_state.pool.source,
-                            pool_state.pool.pool_a_account,
-                            pool_state.pool.destination,
-                            pool_state.pool.pool_b_account,
-                            pool_state.pool_a_balance,
-                            pool_state.pool_b_balance,
-                        ),
-                        TradeDirection::BtoA => (
-                            spl_token_swap::curve::calculator::TradeDirection::BtoA,
-                            pool_state.pool.destination,
-                            pool_state.pool.pool_b_account,
-                            pool_state.pool.source,
-                            pool_state.pool.pool_a_account,
-                            pool_state.pool_b_balance,
-                            pool_state.pool_a_balance,
-                        ),
-                    };
-
-                    // For the Constant Product Curve the `trade_direction` is
-                    // ignored and it's our responsibility to provide the right
-                    // token's balance from the pool.
-                    let SwapWithoutFeesResult {
-                        source_amount_swapped: _,
-                        destination_amount_swapped,
-                    } = pool_state.curve_calculator.swap_without_fees(
-                        source_amount_less_fees,
-                        swap_source_amount as u128,
-                        swap_destination_amount as u128,
-                        // Again, this argument is useless!
-                        trade_direction,
-                    )?;
-
-                    input_output_pairs.push(InputOutputPairs {
-                        token_in: amount_in as u64,
-                        token_out: destination_amount_swapped as u64,
-                    });
-
-                    match (source_pubkey, destination_pubkey) {
-                        (Some(source), Some(destination)) => {
-                            let swap_args = SwapArguments {
-                                program_id: pool_state.pool.program_id,
-                                swap_pubkey: pair_info.pool,
-                                authority_pubkey: pool_state.pool.pool_authority,
-                                source_pubkey: source,
-                                swap_source_pubkey,
-                                swap_destination_pubkey,
-                                destination_pubkey: destination,
-                                pool_mint_pubkey: pool_state.pool.pool_mint,
-                                pool_fee_pubkey: pool_state.pool.pool_fee,
-                                token_program: inline_spl_token::id(),
-                                amount_in: amount_in as u64,
-                                minimum_amount_out: 0,
-                            };
-
-                            // If we are going to put `amount_in` in into this swap,
-                            // then the previous swap should have produced at least that
-                            // amount. Without this, we might make a profit in one token,
-                            // but at the cost of a loss in another token.
-                            if let Some(last_arg) = swap_arguments_vec.last_mut() {
-                                last_arg.minimum_amount_out = amount_in as u64;
-                            }
-                            swap_arguments_vec.push(swap_args);
-                        }
-                        _ => {}
-                    };
-
-                    amount_in = destination_amount_swapped;
-                }
-
-                // For the final swap, set min_out such that the combination of
-                // all swaps does not make a loss, i.e. we get at least as much
-                // out as we started with.
-                if let Some(last_arg) = swap_arguments_vec.last_mut() {
-                    last_arg.minimum_amount_out = initial_amount as u64;
-                }
-
-                let profit = amount_in.saturating_sub(initial_amount) as u64;
-                let mint_pubkey = match first_pair_info.direction {
-                    TradeDirection::AtoB => pool_states.0.get(&first_pair_info.pool)?.pool.pool_a_mint,
-                    TradeDirection::BtoA => pool_states.0.get(&first_pair_info.pool)?.pool.pool_b_mint,
-                };
-
-                let minimum_profit = match self.minimum_profit.get(&mint_pubkey) {
-                    Some(min_profit) => *min_profit,
-                    None => {
-                        warn!("[MEV] Token {} does not have a minimum profit set from config file.", mint_pubkey);
-                        0u64
-                    },
-                };
-
-                if profit < minimum_profit {
-                    None
-                } else if amount_in < initial_amount {
-                    // If the the `amount_in` is less than the initial amount, return
-                    // `None`.
-                    warn!("[MEV] The output amount is less than the initial amount, this shouldn't happen");
-                    None
-                } else {
-                    // Construct the transaction only if we have swaps for the entire path.
-                    let sanitized_tx_opt = if swap_arguments_vec.len() == mev_path.path.len() {
-                        Some(create_swap_tx(
-                            swap_arguments_vec,
-                            blockhash,
-                            self.user_authority.as_ref().as_ref()?,
-                        ))
-                    } else {
-                        None
-                    };
-
-                    Some(MevTxOutput {
-                        sanitized_tx: sanitized_tx_opt,
-                        path_idx,
-                        input_output_pairs,
-                        profit,
-                        marginal_price: path_output.marginal_price,
-                    })
-                }
-            })
-            .collect()
-    }
-}
-
-impl MevLog {
-    pub fn new(mev_config: &MevConfig) -> Self {
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .append(true)
-            .open(&mev_config.log_path)
-            .expect("Failed while creating/opening MEV log file");
-        let (log_send_channel, log_receiver) = unbounded();
-
-        let mev_paths = mev_config.mev_paths.clone();
-        let thread_handle = std::thread::spawn(move || loop {
-            match log_receiver.recv() {
-                Ok(MevMsg::Log(msg)) => writeln!(
-                    file,
-                    "{}",
-                    serde_json::to_string(&msg).expect("Constructed by us, should never fail")
-                )
-                .expect("[MEV] Could not write log to file"),
-
-                Ok(MevMsg::Opportunities(mev_tx_output)) => {
-                    let mev_paths_input: Vec<MevOpportunityWithInput> = mev_tx_output
-                        .into_iter()
-                        .map(|mev_tx_output| MevOpportunityWithInput {
-                            opportunity: &mev_paths[mev_tx_output.path_idx],
-                            input_output_pairs: mev_tx_output.input_output_pairs,
-                        })
-                        .collect();
-                    writeln!(
-                        file,
-                        "{{\"event\":\"opportunity\",\"data\":{}}}",
-                        serde_json::to_string(&mev_paths_input)
-                            .expect("Constructed by us, should never fail")
-                    )
-                    .expect("[MEV] Could not write log opportunity to file")
-                }
-
-                Ok(MevMsg::ExecutedTransaction(executed_tx_output)) => writeln!(
-                    file,
-                    "{{\"event\":\"executed_transaction\",\"data\":{}}}",
-                    serde_json::to_string(&executed_tx_output)
-                        .expect("Constructed by us, should never fail")
-                )
-                .expect("[MEV] Could not write log executed transaction to file"),
-
-                Ok(MevMsg::Exit) => break,
-                Err(err) => error!("[MEV] Could not log arbitrage on file, error: {}", err),
-            }
-        });
-
-        MevLog {
-            thread_handle,
-            log_send_channel,
-        }
-    }
-}
-
-#[test]
-fn test_log_serialization() {
-    use spl_token_swap::curve::constant_product::ConstantProductCurve;
-    use std::str::FromStr;
-
-    let curve_calculator = Arc::new(ConstantProductCurve::default());
-    let (authority_pubkey, _authority_bump_seed) = Pubkey::find_program_address(
-        &[
-            &Pubkey::from_str("4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM")
-                .unwrap()
-                .to_bytes()[..],
-        ],
-        &inline_spl_token::id(),
-    );
-
-    let opportunity = PrePostPoolStates {
-        transaction_hash: Hash::new(&[0; 32]),
-        transaction_signature: Signature::new(&[0; 64]),
-        slot: 1,
-        orca_pre_tx_pool: PoolStates(
-            vec![(
-                Pubkey::from_str("4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM").unwrap(),
-                OrcaPoolWithBalance {
-                    pool: OrcaPoolAddresses {
-                        program_id: Pubkey::from_str(
-                            "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                        )
-                        .unwrap(),
-                        address: Pubkey::from_str("4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM")
-                            .unwrap(),
-                        pool_a_account: Pubkey::from_str(
-                            "8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh",
-                        )
-                        .unwrap(),
-                        pool_b_account: Pubkey::from_str(
-                            "CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3",
-                        )
-                        .unwrap(),
-                        pool_mint: Pubkey::from_str("33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM")
-                            .unwrap(),
-                        pool_fee: Pubkey::from_str("GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr")
-                            .unwrap(),
-                        pool_authority: authority_pubkey,
-                        ..Default::default()
-                    },
-                    pool_a_balance: 1,
-                    pool_b_balance: 1,
-                    fees: Fees(spl_token_swap::curve::fees::Fees {
-                        trade_fee_numerator: 1,
-                        trade_fee_denominator: 10,
-                        owner_trade_fee_numerator: 1,
-                        owner_trade_fee_denominator: 10,
-                        owner_withdraw_fee_numerator: 1,
-                        owner_withdraw_fee_denominator: 10,
-                        host_fee_numerator: 1,
-                        host_fee_denominator: 10,
-                    }),
-                    curve_calculator,
-                    source_balance: None,
-                    destination_balance: None,
-                },
-            )]
-            .into_iter()
-            .collect(),
-        ),
-        orca_post_tx_pool: PoolStates(HashMap::new()),
+/// Minimum time between two identical warnings from the MEV hot path.
+const LOG_THROTTLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The paths that code outside of this module uses, they should keep resolving
+/// when items move between the submodules.
+#[cfg(test)]
+#[allow(unused_imports)]
+mod api_surface {
+    use crate::mev::{
+        arbitrage::{
+            create_swap_tx, encode_swap_tx, get_swap_pools, optimal_input_amount, InputOutputPairs,
+            MevOpportunityWithInput, MevPath, MevTxBlueprint, MevTxOutput, PairInfo,
+            PathSkipReason, SequencePart, SettingSource, SkipReason, SwapArguments, TradeDirection,
+            TriggerKind, TxSettingSources, TxSettings, LAMPORTS_PER_SIGNATURE, MAX_SLIPPAGE_BPS,
+        },
+        audit::{
+            CapKind, CapTrace, CompetingOpportunity, DecisionTrace, HopTrace, SelectionTrace,
+            ThresholdKind, ThresholdTrace,
+        },
+        authority::{PoolAuthorities, UserAuthorityConfig},
+        checkpoint::{
+            latest_checkpoint, read_checkpoint_json, read_latest_checkpoint, Checkpointer,
+            PoolStatesCheckpoint,
+        },
+        clock_skew::{
+            wall_clock_ms, ClockSkewEvent, ClockSkewMonitor, CLOCK_SKEW_CHECK_INTERVAL_SLOTS,
+        },
+        compute_units::{
+            ComputeUnitEstimator, CuCalibration, HopCountCalibration, CU_CALIBRATION_VERSION,
+            DEFAULT_COMPUTE_UNITS_PER_HOP,
+        },
+        config::{
+            get_mev_config_file, parse_mev_config, read_mev_config, AllOrcaPoolAddresses,
+            MevConfig, MinTriggerDeltaConfig, OpportunityConflict, ThresholdSummary, TokenAmount,
+            TokenRef, MEV_CONFIG_VERSION, MIN_MEV_CONFIG_VERSION,
+        },
+        config_dump::{sorted_b58, ConfigDump, PathDump, PoolDump},
+        debounce::{AggregatedOpportunity, OpportunityDebouncer, OPTIMAL_INPUT_QUANTUM},
+        discovery::{
+            configured_and_discovered_paths, discover_paths, AutoDiscoverConfig,
+            DISCOVERED_PATH_PREFIX, MAX_DISCOVERED_HOPS,
+        },
+        engine::{PathCache, MARGINAL_PRICE_QUANTUM},
+        error::MevError,
+        failures::{
+            ExecutionFailure, SystemicFailureConfig, SystemicFailureEvent, SystemicFailureMonitor,
+        },
+        forwarder::{
+            parse_http_url, ForwardError, ForwardedTransaction, HttpEndpoint, TransactionForwarder,
+            FORWARD_ATTEMPTS, FORWARD_QUEUE_SIZE, FORWARD_TIMEOUT,
+        },
+        includes::merge_includes,
+        lock_impact::{
+            LockImpactReport, LockImpactSampler, LockImpactStatus, LOCK_IMPACT_RECENT_REPORTS,
+        },
+        log_channel::{
+            dropped_log_messages_event, DroppedLogMessages, LogChannelPolicy, MevLogSender,
+        },
+        log_file::{read_mev_log, LogCompression, LogPathTemplate, MevLogFile, MevLogWriter},
+        log_reader::{
+            parse_log_records, read_log_records, ExecutedTransactionRecord, LogReadError,
+            MevLogRecord, OpportunityRecord, TriggerRecord,
+        },
+        logging::{MevSubscribers, SessionHeader, LOG_SCHEMA_VERSION, MEV_SUBSCRIBER_CAPACITY},
+        mint::{MintInfo, TransferFee, TransferFeeConfig},
+        pool_state::{
+            associated_token_address, loaded_user_authority_balances, FeeError, Fees, PoolBalances,
+            PoolLoadError, PoolLoadErrorCounters, PoolLoadErrorKind, PoolRole,
+            POOL_STATES_SNAPSHOT_VERSION,
+        },
+        pool_stats::{PoolContribution, PoolContributions, RankedPoolContribution},
+        priority_fee::{priority_fee_lamports, PriorityFeeConfig, PriorityFeePolicy},
+        reporting::{
+            PendingReportedProfits, ProfitReporter, ProfitSummary, ReportedProfit, ReportingConfig,
+        },
+        searcher::{HypotheticalEvaluation, MevEngine},
+        signer::{MevSigner, RemoteSigner, SignerConfig, SigningError, SigningThread},
+        simulate::{
+            apply_hypothetical_swaps, simulate_hops, simulate_path, to_token_amount, HopSimulation,
+            HypotheticalSwap, PathSimulation,
+        },
+        status::{
+            status_path, write_status_file, MevCounters, MevStatus, PathStatus, WatchdogStatus,
+        },
+        test_utils::{path, pool, transfer_fee_mint_info, PathBuilder, PoolBuilder},
+        utils::{
+            deserialize_b58, deserialize_opt_b58, expand_path, get_mev_config_file as _,
+            parse_b58_pubkey, parse_mev_config as _, serialize_b58, serialize_opt_b58,
+            AllOrcaPoolAddresses as _, B58Pubkey, LogThrottle, MevConfig as _,
+        },
+        verify::{
+            accounts_to_verify, configured_pool_authorities, configured_user_authority,
+            verify_pools, PoolVerification,
+        },
+        watchdog::{Watchdog, WatchdogCondition, WatchdogEvent},
+        CompetitorArbitrage, DroppedTransaction, EvaluatedPaths, ExecutedTransactionOutput,
+        InflightExposure, Mev, MevEvent, MevEventNotifier, MevLog, MevMsg, MinTriggerDelta,
+        NoopMevEventNotifier, OrcaPoolAddresses, OrcaPoolWithBalance, PathSkipped,
+        PendingOpportunities, PoolConfigMismatch, PoolStates, PrePostPoolStates, SlotTxCount,
+        ValidatorAttribution,
     };
-
-    let expected_result_str = "\
-    {\
-        'transaction_hash':'11111111111111111111111111111111',\
-        'transaction_signature':'1111111111111111111111111111111111111111111111111111111111111111',\
-        'slot':1,\
-        'orca_pre_tx_pool':{'4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM':\
-          {\
-            'pool':{\
-              'address':'4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM',\
-              'pool_a_account':'8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh',\
-              'pool_b_account':'CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3',\
-              'source':null,\
-              'destination':null,\
-              'pool_mint':'33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM',\
-              'pool_fee':'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'\
-            },\
-            'pool_a_balance':1,\
-            'pool_b_balance':1,\
-            'source_balance':null,\
-            'destination_balance':null,\
-            'fees':{\
-              'host_fee_denominator':10,\
-              'host_fee_numerator':1,\
-              'owner_trade_fee_denominator':10,\
-              'owner_trade_fee_numerator':1,\
-              'trade_fee_denominator':10,\
-              'trade_fee_numerator':1\
-            }\
-          }\
-        },\
-        'orca_post_tx_pool':{}\
-      }"
-    .replace("'", "\"");
-    let serialized_json = serde_json::to_string(&opportunity).expect("Serialization failed");
-    assert_eq!(serialized_json, expected_result_str);
 }
@@ -1,17 +1,33 @@
+use std::{collections::HashSet, convert::TryFrom, sync::Arc};
+
 use serde::Serialize;
+use solana_program_runtime::compute_budget::{
+    DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT, MAX_COMPUTE_UNIT_LIMIT,
+};
 use solana_sdk::{
+    compute_budget::{self, ComputeBudgetInstruction},
     hash::Hash,
     instruction::{AccountMeta, Instruction},
+    message::Message,
+    packet::PACKET_DATA_SIZE,
     pubkey::Pubkey,
-    signature::Keypair,
-    signer::Signer,
-    transaction::{SanitizedTransaction, Transaction},
+    signature::Signature,
+    transaction::{SanitizedTransaction, Transaction, MAX_TX_ACCOUNT_LOCKS},
+};
+use spl_token_swap::{
+    curve::base::CurveType, instruction::Swap, solana_program::pubkey::Pubkey as SplPubkey,
 };
-use spl_token_swap::instruction::{Swap, SwapInstruction};
 
 use super::{
-    utils::{deserialize_b58, serialize_b58},
-    PoolStates,
+    audit::{CapKind, DecisionTrace},
+    config::AllOrcaPoolAddresses,
+    mint::MintInfo,
+    pool_state::PoolRole,
+    priority_fee::priority_fee_lamports,
+    reporting::ReportedProfit,
+    signer::{MevSigner, SigningError, SigningThread},
+    utils::{serialize_b58, warn_throttled, B58Pubkey},
+    OrcaPoolWithBalance, PoolStates, LOG_THROTTLE_INTERVAL,
 };
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
@@ -21,118 +37,1464 @@ pub enum TradeDirection {
 }
 
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(try_from = "PairInfoConfig", into = "PairInfoConfig")]
 pub struct PairInfo {
-    #[serde(serialize_with = "serialize_b58")]
-    #[serde(deserialize_with = "deserialize_b58")]
     pub pool: Pubkey,
 
+    /// Other pools for the same token pair as `pool`, with the same token A
+    /// and token B. When evaluating the path we trade through whichever of the
+    /// candidates gives the best price for this hop.
+    pub alternative_pools: Vec<Pubkey>,
+
+    /// The name of the pool group that this hop trades through, see
+    /// `MevConfig::pool_groups`. Then `pool` and `alternative_pools` are the
+    /// pools of the group, and we split the input of the hop across them,
+    /// instead of trading through the best one.
+    pub group: Option<String>,
+
     pub direction: TradeDirection,
 }
 
+/// A hop as written in the config file, where `pool` is either a single pool
+/// address, or a list of candidate pools for the same token pair, and `group`
+/// is the name of a pool group instead.
+#[derive(Clone, Deserialize, Serialize)]
+struct PairInfoConfig {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool: Option<PoolCandidates>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group: Option<String>,
+    direction: TradeDirection,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+enum PoolCandidates {
+    Single(B58Pubkey),
+    Multiple(Vec<B58Pubkey>),
+}
+
+impl TryFrom<PairInfoConfig> for PairInfo {
+    type Error = String;
+
+    fn try_from(config: PairInfoConfig) -> Result<Self, Self::Error> {
+        let candidates = match (config.pool, config.group) {
+            (Some(candidates), None) => candidates,
+            // The config fills in the pools of the group, see
+            // `MevConfig::resolve_pool_groups`.
+            (None, Some(group)) => {
+                return Ok(PairInfo {
+                    pool: Pubkey::default(),
+                    alternative_pools: Vec::new(),
+                    group: Some(group),
+                    direction: config.direction,
+                })
+            }
+            (Some(_), Some(_)) => {
+                return Err("A hop should set either pool or group, not both".to_owned())
+            }
+            (None, None) => return Err("A hop should set a pool or a group".to_owned()),
+        };
+        let (pool, alternative_pools) = match candidates {
+            PoolCandidates::Single(pool) => (pool.0, Vec::new()),
+            PoolCandidates::Multiple(pools) => {
+                let mut pools = pools.into_iter().map(|b58_pubkey| b58_pubkey.0);
+                let pool = pools
+                    .next()
+                    .ok_or("A hop should have at least one candidate pool")?;
+                (pool, pools.collect::<Vec<Pubkey>>())
+            }
+        };
+        for (i, alternative) in alternative_pools.iter().enumerate() {
+            if *alternative == pool || alternative_pools[..i].contains(alternative) {
+                return Err(format!(
+                    "Pool {} is listed more than once as a candidate for the same hop",
+                    alternative
+                ));
+            }
+        }
+        Ok(PairInfo {
+            pool,
+            alternative_pools,
+            group: None,
+            direction: config.direction,
+        })
+    }
+}
+
+impl From<PairInfo> for PairInfoConfig {
+    fn from(pair_info: PairInfo) -> Self {
+        if let Some(group) = pair_info.group {
+            return PairInfoConfig {
+                pool: None,
+                group: Some(group),
+                direction: pair_info.direction,
+            };
+        }
+        let pool = if pair_info.alternative_pools.is_empty() {
+            PoolCandidates::Single(B58Pubkey(pair_info.pool))
+        } else {
+            PoolCandidates::Multiple(
+                pair_info
+                    .candidate_pools()
+                    .map(|pubkey| B58Pubkey(*pubkey))
+                    .collect(),
+            )
+        };
+        PairInfoConfig {
+            pool: Some(pool),
+            group: None,
+            direction: pair_info.direction,
+        }
+    }
+}
+
+impl PairInfo {
+    /// All pools this hop may trade through, `pool` first.
+    pub fn candidate_pools(&self) -> impl Iterator<Item = &Pubkey> {
+        std::iter::once(&self.pool).chain(self.alternative_pools.iter())
+    }
+
+    /// Get the state of every candidate pool, skipping those that are
+    /// missing, e.g. blocked, or that trade different mints than the first
+    /// one that is not. Returns `None` when all of them are missing.
+    pub fn get_candidate_states<'a>(
+        &'a self,
+        pool_states: &'a PoolStates,
+    ) -> Option<HopCandidates<'a>> {
+        let mut present_pools = self
+            .candidate_pools()
+            .filter_map(|pool| pool_states.0.get(pool).map(|pool_state| (pool, pool_state)));
+        let (first_pool, first_state) = present_pools.next()?;
+        let mut candidates = Vec::with_capacity(1 + self.alternative_pools.len());
+        candidates.push((first_pool, first_state));
+        for (alternative, alternative_state) in present_pools {
+            if alternative_state.pool.pool_a_mint == first_state.pool.pool_a_mint
+                && alternative_state.pool.pool_b_mint == first_state.pool.pool_b_mint
+            {
+                candidates.push((alternative, alternative_state));
+            } else {
+                warn_throttled!(
+                    &format!("mev_candidate_mints_{}_{}", first_pool, alternative),
+                    LOG_THROTTLE_INTERVAL,
+                    "[MEV] Pool {} does not trade the same mints as {}, ignoring it as a candidate.",
+                    alternative,
+                    first_pool
+                );
+            }
+        }
+        Some(candidates)
+    }
+
+    /// Trade `amount_in` through this hop, as `candidates`, its candidate
+    /// states. A hop through a pool group splits the input across the pools,
+    /// see `split_hop_input`, any other hop trades all of it through the
+    /// candidate that gives the most output, the first one on a tie. Returns
+    /// the trade of every pool that gets a share, in candidate order, or
+    /// `None` when no candidate can swap.
+    pub fn swap<'a>(
+        &self,
+        candidates: &HopCandidates<'a>,
+        amount_in: u128,
+    ) -> Option<Vec<PoolTrade<'a>>> {
+        if self.group.is_some() && candidates.len() > 1 {
+            return split_hop_input(candidates, &self.direction, amount_in);
+        }
+        // We iterate in reverse, so that on a tie the candidate listed first
+        // wins.
+        candidates
+            .iter()
+            .rev()
+            .filter_map(|&(pool, state)| {
+                let amount_out = state.get_swap_output(&self.direction, amount_in)?;
+                Some(PoolTrade {
+                    pool,
+                    state,
+                    amount_in,
+                    amount_out,
+                })
+            })
+            .max_by_key(|trade| trade.amount_out)
+            .map(|trade| vec![trade])
+    }
+
+    /// How many swaps a transaction for this hop has: one per pool of a pool
+    /// group, otherwise one.
+    pub fn swap_count(&self) -> usize {
+        if self.group.is_some() {
+            1 + self.alternative_pools.len()
+        } else {
+            1
+        }
+    }
+}
+
+/// The candidate pools of a hop with their states, the main pool first.
+pub type HopCandidates<'a> = Vec<(&'a Pubkey, &'a OrcaPoolWithBalance)>;
+
+/// What one pool of a hop trades, see `PairInfo::swap`.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolTrade<'a> {
+    pub pool: &'a Pubkey,
+    pub state: &'a OrcaPoolWithBalance,
+    pub amount_in: u128,
+    pub amount_out: u128,
+}
+
+/// We split the input of a hop across a pool group in this many equal steps.
+const POOL_GROUP_SPLIT_STEPS: u128 = 16;
+
+/// Split `amount_in` across the pools of a group, `candidates`, in
+/// `POOL_GROUP_SPLIT_STEPS` steps, where every step goes to the pool that
+/// gives the most output for it on top of what that pool trades already. The
+/// output of a pool is concave in its input, so this approaches the split
+/// where the marginal prices of the pools are equal, which leaves none of them
+/// more profitable than the others. Pools with the same price get shares in
+/// proportion to their depth. Returns the pools that get a share, in
+/// candidate order, or `None` when a step cannot be swapped, or when
+/// `amount_in` is 0.
+fn split_hop_input<'a>(
+    candidates: &HopCandidates<'a>,
+    direction: &TradeDirection,
+    amount_in: u128,
+) -> Option<Vec<PoolTrade<'a>>> {
+    let output = |state: &OrcaPoolWithBalance, amount_in: u128| {
+        if amount_in == 0 {
+            Some(0)
+        } else {
+            state.get_swap_output(direction, amount_in)
+        }
+    };
+    let mut shares = vec![0_u128; candidates.len()];
+    let mut outputs = vec![0_u128; candidates.len()];
+    for step in 0..POOL_GROUP_SPLIT_STEPS {
+        let step_amount = amount_in * (step + 1) / POOL_GROUP_SPLIT_STEPS
+            - amount_in * step / POOL_GROUP_SPLIT_STEPS;
+        if step_amount == 0 {
+            continue;
+        }
+        // On a tie, the pool listed first gets the step.
+        let (best, best_output) = candidates
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_pool, state))| Some((i, output(state, shares[i] + step_amount)?)))
+            .fold(None, |best: Option<(usize, u128)>, (i, amount_out)| {
+                let gain = amount_out.saturating_sub(outputs[i]);
+                match best {
+                    Some((best, best_output))
+                        if gain <= best_output.saturating_sub(outputs[best]) =>
+                    {
+                        Some((best, best_output))
+                    }
+                    _ => Some((i, amount_out)),
+                }
+            })?;
+        shares[best] += step_amount;
+        outputs[best] = best_output;
+    }
+    let trades: Vec<PoolTrade> = candidates
+        .iter()
+        .zip(shares.into_iter().zip(outputs))
+        .filter(|(_candidate, (share, _output))| *share > 0)
+        .map(|(&(pool, state), (amount_in, amount_out))| PoolTrade {
+            pool,
+            state,
+            amount_in,
+            amount_out,
+        })
+        .collect();
+    // Nothing to split is no trade.
+    Some(trades).filter(|trades| !trades.is_empty())
+}
+
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct MevPath {
     pub name: String,
     pub path: Vec<PairInfo>,
+
+    /// When set to more than 1, we also quote the opportunity as this many
+    /// transactions, that each trade an equal part of the input through the
+    /// path, one after the other. When that makes more profit than a single
+    /// transaction, we execute the parts in order.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_parts: Option<u8>,
+
+    /// When set to more than 0, we only craft a transaction for an
+    /// opportunity on this path after this many triggers after the first one,
+    /// in the same slot, showed the same imbalance. A trigger that loads the
+    /// path's pools without an opportunity on it starts the count over.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub require_confirmations: Option<u8>,
+
+    /// The most of the input token that we trade through this path in one
+    /// opportunity, even when the optimal input and our balance are larger.
+    /// With a split, this caps every part. A cap of 0 disables the path.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_input: Option<u64>,
+
+    /// Overrides the global `slippage_bps` for this path.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slippage_bps: Option<u16>,
+
+    /// A fixed compute unit price in micro-lamports for the transactions of
+    /// this path, instead of the one that the global `priority_fee` sets.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_unit_price: Option<u64>,
+
+    /// Overrides the global `compute_unit_limit` for this path.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_unit_limit: Option<u32>,
+
+    /// We do not evaluate a disabled path, but we still check it when we load
+    /// the config, so that it can be enabled again as is.
+    #[serde(default = "default_enabled")]
+    #[serde(skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
+
+    /// Also evaluate the path in reverse, see `MevPath::reversed`, so that one
+    /// entry covers a cycle in both orientations. At most one orientation of a
+    /// cycle has an opportunity at a time.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "is_false")]
+    pub probe_reverse: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Accounts that a transaction crafted for any path references: the user
+/// authority, the SPL token program, the token swap program, and the compute
+/// budget program. A path through Token-2022 pools also references that
+/// program, which the config does not tell, so it is not counted.
+const FIXED_TX_ACCOUNTS: usize = 4;
+
+/// Accounts that a swap instruction references in addition to the fixed ones:
+/// the pool, its authority, the pool's source and destination token accounts,
+/// the pool mint, the pool fee account, and our source and destination.
+const ACCOUNTS_PER_HOP: usize = 8;
+
+/// Serialized size of a swap instruction in a legacy message: program index,
+/// 10 account indices and 17 bytes of data, each with a 1-byte length prefix.
+const SWAP_INSTRUCTION_SIZE: usize = 1 + (1 + 10) + (1 + 17);
+
+/// Serialized size of a `SetComputeUnitPrice` instruction in a legacy message.
+const COMPUTE_BUDGET_INSTRUCTION_SIZE: usize = 1 + 1 + (1 + 9);
+
+/// Serialized size of a `SetComputeUnitLimit` instruction in a legacy message.
+const COMPUTE_UNIT_LIMIT_INSTRUCTION_SIZE: usize = 1 + 1 + (1 + 5);
+
+/// Estimate of the transaction we would craft for a path.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TxSizeEstimate {
+    /// Number of unique accounts the transaction references.
+    pub account_count: usize,
+
+    /// Size in bytes of the serialized legacy transaction.
+    pub serialized_size: usize,
+}
+
+impl TxSizeEstimate {
+    fn new(account_count: usize, num_swaps: usize) -> Self {
+        let compact_len = |len: usize| if len < 0x80 { 1 } else { 2 };
+        let num_instructions = num_swaps + 1;
+        let serialized_size =
+            // One signature, by the user authority.
+            compact_len(1) + 64
+            // Message header, account keys, and recent blockhash.
+            + 3 + compact_len(account_count) + 32 * account_count + 32
+            + compact_len(num_instructions)
+            + COMPUTE_BUDGET_INSTRUCTION_SIZE
+            + num_swaps * SWAP_INSTRUCTION_SIZE;
+        TxSizeEstimate {
+            account_count,
+            serialized_size,
+        }
+    }
+
+    /// The estimate for the same transaction, when it also sets a compute
+    /// unit limit.
+    pub fn with_compute_unit_limit(self) -> Self {
+        TxSizeEstimate {
+            account_count: self.account_count,
+            serialized_size: self.serialized_size + COMPUTE_UNIT_LIMIT_INSTRUCTION_SIZE,
+        }
+    }
+
+    /// Whether the transaction could never be executed, because it locks too
+    /// many accounts, or does not fit in a packet.
+    pub fn exceeds_limits(&self) -> bool {
+        self.account_count > MAX_TX_ACCOUNT_LOCKS || self.serialized_size > PACKET_DATA_SIZE
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct MevOpportunityWithInput<'a> {
     pub opportunity: &'a MevPath,
+
+    /// Whether we traded `opportunity` in reverse, see
+    /// `MevPath::probe_reverse`. The pairs and the selected pools are then in
+    /// the order of the reversed path.
+    #[serde(skip_serializing_if = "is_false")]
+    pub reversed: bool,
+
     pub input_output_pairs: Vec<InputOutputPairs>,
+
+    /// Pool that was selected for every hop, only present for paths that
+    /// have hops with alternative pools.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub selected_pools: Vec<B58Pubkey>,
+
+    /// Lamports spent outside of the input token, e.g. on rent for token
+    /// accounts created by the transaction and on the priority fee, when they
+    /// could not be deducted from the profit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub non_token_costs_lamports: Option<u64>,
+
+    /// Compute unit price in micro-lamports that the transaction which
+    /// triggered the opportunity paid, if it set one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_compute_unit_price: Option<u64>,
+
+    /// Compute unit price in micro-lamports that we set for our transaction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_unit_price: Option<u64>,
+
+    /// Compute units that we expect our transaction to consume, as measured
+    /// for paths with as many hops if we have a calibration.
+    pub estimated_compute_units: u64,
+
+    /// How far below the quoted output we accepted the output of the last
+    /// hop, in basis points.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slippage_bps: Option<u16>,
+
+    /// Compute unit limit that we requested for our transaction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_unit_limit: Option<u32>,
+
+    /// Whether the path, the global config, or the default supplied the
+    /// slippage, compute unit price, and compute unit limit.
+    pub setting_sources: TxSettingSources,
+
+    /// With `dry_run`, the transaction that we would have executed for the
+    /// opportunity, as `sendTransaction` takes it in base64.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<String>,
+
+    /// The profit in the reporting mint, when we could convert it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reported_value: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skip_reason: Option<SkipReason>,
+
+    /// For paths with `require_confirmations`, on how many triggers in a row
+    /// in this slot, including this one, we saw the same imbalance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observations: Option<u8>,
+
+    /// Set when a cap, rather than the optimal input, determined the input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_cap: Option<CapKind>,
+
+    pub trigger_kind: TriggerKind,
+
+    /// Set when the opportunity was split over multiple transactions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<SequencePart>,
+
+    /// With `audit_mode`, the inputs to our decision, for the opportunity
+    /// that we selected for the trigger.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision_trace: Option<DecisionTrace>,
+}
+
+/// Reason why we did not craft a transaction for an opportunity that was
+/// otherwise profitable.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Crafting the transaction would push the in-flight exposure of the input
+    /// token over the configured `max_inflight_exposure`.
+    ExposureCap,
+
+    /// The signer did not sign the transaction in time.
+    SigningTimeout,
+
+    /// The signer failed to sign the transaction.
+    SigningError,
+
+    /// With `confirm_trigger_before_craft`, the transaction that created the
+    /// opportunity failed, or did not make it into the block.
+    TriggerNotCommitted,
+
+    /// The path has `require_confirmations`, and we did not see the
+    /// imbalance on enough triggers yet.
+    Unconfirmed,
+
+    /// Our transactions keep failing for reasons unrelated to the path, so
+    /// we paused crafting, see `SystemicFailureMonitor`.
+    SystemicFailure,
+
+    /// We already selected `max_mev_txs_per_slot` transactions in this slot.
+    SlotCap,
+
+    /// The trigger did not move any pool of the path by `min_trigger_delta`,
+    /// so the opportunity is likely rounding noise.
+    NoMaterialMove,
+
+    /// The user authority that signs the transaction holds fewer lamports
+    /// than the transaction pays in fees, including the priority fee.
+    InsufficientFeeBalance,
+}
+
+/// Why a path has no opportunity, before we quote it, see
+/// `MevPath::get_path_calculation_output`, or when we quote it. We log it with
+/// `log_skipped_paths`.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case", tag = "reason")]
+pub enum PathSkipReason {
+    /// The path has no hops, which the config does not allow.
+    NoHops,
+
+    /// The state of the main pool of a hop is missing.
+    MissingPool {
+        #[serde(serialize_with = "serialize_b58")]
+        pool: Pubkey,
+    },
+
+    /// Every candidate of the hop has an empty reserve, or one below
+    /// `min_pool_reserve`.
+    DrainedPool { hop: usize },
+
+    /// The fees of the usable candidates of the hop are degenerate, e.g. with
+    /// a zero denominator, the fee arithmetic would overflow.
+    InvalidFees { hop: usize },
+
+    /// A swap of the simulated path failed, for paths through pools without
+    /// a closed form, or no candidate of a hop could swap what we quoted.
+    SwapFailed,
+
+    /// An amount that we quoted for the hop does not fit in a token amount,
+    /// so the swap instruction could not encode it.
+    AmountOverflow { hop: usize },
+
+    /// The output that we quoted for the hop takes `max_hop_output_bps` or
+    /// more of the reserve that `pool` pays it from, e.g. on a stale state,
+    /// so the swap would fail.
+    ExcessiveHopOutput {
+        hop: usize,
+        #[serde(serialize_with = "serialize_b58")]
+        pool: Pubkey,
+    },
+
+    /// The marginal price of the path, all fees included, is at most 1.
+    NotProfitable { marginal_price: f64 },
+
+    /// The float math of the optimal input gave no finite result.
+    NotFinite { marginal_price: f64 },
+}
+
+/// Which level of the config supplied a setting of the transaction that we
+/// craft for a path.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    /// The path overrides the setting.
+    Path,
+
+    /// The global setting, the path does not set it.
+    Global,
+
+    /// Neither the path nor the config sets it.
+    Default,
+}
+
+impl SettingSource {
+    /// Resolve a setting from the path's override and the global setting,
+    /// in that order, `None` means the default.
+    pub fn resolve<T>(path: Option<T>, global: Option<T>) -> (Option<T>, SettingSource) {
+        match (path, global) {
+            (Some(value), _) => (Some(value), SettingSource::Path),
+            (None, Some(value)) => (Some(value), SettingSource::Global),
+            (None, None) => (None, SettingSource::Default),
+        }
+    }
+}
+
+/// The settings of the transaction that we craft for a path, after resolving
+/// the path's overrides against the global config.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TxSettings {
+    pub slippage_bps: Option<u16>,
+    pub compute_unit_price: Option<u64>,
+    pub compute_unit_limit: Option<u32>,
+    pub sources: TxSettingSources,
+}
+
+/// Which level of the config supplied each of the `TxSettings`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct TxSettingSources {
+    pub slippage_bps: SettingSource,
+    pub compute_unit_price: SettingSource,
+    pub compute_unit_limit: SettingSource,
+}
+
+impl Default for TxSettingSources {
+    fn default() -> Self {
+        TxSettingSources {
+            slippage_bps: SettingSource::Default,
+            compute_unit_price: SettingSource::Default,
+            compute_unit_limit: SettingSource::Default,
+        }
+    }
+}
+
+impl TxSettings {
+    /// Resolve the settings of `path`, against the global `slippage_bps`,
+    /// the compute unit price that the global `priority_fee` chose, and the
+    /// global `compute_unit_limit`.
+    pub fn resolve(
+        path: &MevPath,
+        slippage_bps: Option<u16>,
+        compute_unit_price: Option<u64>,
+        compute_unit_limit: Option<u32>,
+    ) -> Self {
+        let (slippage_bps, slippage_bps_source) =
+            SettingSource::resolve(path.slippage_bps, slippage_bps);
+        let (compute_unit_price, compute_unit_price_source) =
+            SettingSource::resolve(path.compute_unit_price, compute_unit_price);
+        let (compute_unit_limit, compute_unit_limit_source) =
+            SettingSource::resolve(path.compute_unit_limit, compute_unit_limit);
+        TxSettings {
+            slippage_bps,
+            compute_unit_price,
+            compute_unit_limit,
+            sources: TxSettingSources {
+                slippage_bps: slippage_bps_source,
+                compute_unit_price: compute_unit_price_source,
+                compute_unit_limit: compute_unit_limit_source,
+            },
+        }
+    }
+
+    /// The minimum output of the last hop, for a quote from `amount_in` to
+    /// `amount_out`. Without slippage, that is what we put in plus
+    /// `minimum_profit`, so that the transaction fails rather than realize
+    /// less profit than we require. With slippage, we accept at most
+    /// `slippage_bps` less than the quoted output, but still not less than
+    /// that. We never require more than the quoted output.
+    pub fn minimum_amount_out(
+        &self,
+        amount_in: u128,
+        amount_out: u128,
+        minimum_profit: u64,
+    ) -> u64 {
+        let floor = amount_in
+            .saturating_add(minimum_profit as u128)
+            .min(amount_out);
+        let minimum = match self.slippage_bps {
+            Some(slippage_bps) => {
+                let max_bps = MAX_SLIPPAGE_BPS as u128;
+                let slippage_bps = (slippage_bps as u128).min(max_bps);
+                (amount_out * (max_bps - slippage_bps) / max_bps).max(floor)
+            }
+            None => floor,
+        };
+        minimum.min(u64::MAX as u128) as u64
+    }
+
+    /// The priority fee in lamports of a transaction with these settings
+    /// through `hops` pools. Without a compute unit limit, the fee is for the
+    /// limit that the runtime gives the swap instructions by default.
+    pub fn priority_fee_lamports(&self, hops: usize) -> u64 {
+        let compute_unit_limit = self.compute_unit_limit.unwrap_or_else(|| {
+            (DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT as u64)
+                .saturating_mul(hops as u64)
+                .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+        });
+        self.compute_unit_price
+            .map_or(0, |price| priority_fee_lamports(price, compute_unit_limit))
+    }
+
+    /// The fees in lamports of a transaction with these settings through
+    /// `hops` pools: the signature and the priority fee.
+    pub fn fee_lamports(&self, hops: usize) -> u64 {
+        LAMPORTS_PER_SIGNATURE.saturating_add(self.priority_fee_lamports(hops))
+    }
+}
+
+/// Slippage of the whole quoted output, in basis points.
+pub const MAX_SLIPPAGE_BPS: u16 = 10_000;
+
+/// The whole reserve of a pool, in basis points, the default and the most for
+/// `max_hop_output_bps`.
+pub const MAX_HOP_OUTPUT_BPS: u16 = 10_000;
+
+/// The fee per signature that clusters charge, our transactions have only
+/// the signature of the user authority.
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Which part of an opportunity that is split over `parts` transactions an
+/// output is. The parts trade against the balances that the parts before them
+/// leave, so they have to execute in order, starting at part 0.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct SequencePart {
+    pub part: u8,
+    pub parts: u8,
+}
+
+/// Kind of the transaction that triggered an evaluation, for breaking down
+/// opportunities by trigger.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerKind {
+    /// A simple vote transaction. We do not attach MEV keys to those, so they
+    /// only trigger an evaluation when the keys were attached elsewhere.
+    Vote,
+
+    /// Any other transaction.
+    User,
+}
+
+impl TriggerKind {
+    pub fn of(tx: &SanitizedTransaction) -> Self {
+        if tx.is_simple_vote_transaction() {
+            TriggerKind::Vote
+        } else {
+            TriggerKind::User
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct InputOutputPairs {
     pub token_in: u64,
     pub token_out: u64,
+    /// For a hop through a pool group whose input we split, the part of the
+    /// hop that went through every pool.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pool_splits: Vec<PoolSplit>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct PoolSplit {
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool: Pubkey,
+    pub token_in: u64,
+    pub token_out: u64,
 }
 
 #[derive(Debug)]
 pub struct MevTxOutput {
-    // Not every MevTxOutput carries transactions, but we still want to log
-    // them.
-    pub sanitized_tx: Option<SanitizedTransaction>,
+    // Not every MevTxOutput carries a transaction, but we still want to log
+    // them. It is unsigned, we only sign the one that we select.
+    pub blueprint: Option<MevTxBlueprint>,
     // Index from the Path vector.
     pub path_idx: usize,
+    // Whether we traded the path in reverse, see `MevPath::probe_reverse`.
+    // The pairs, pools and transaction are then in the reversed order.
+    pub reversed: bool,
     pub input_output_pairs: Vec<InputOutputPairs>,
+    // Pool that we trade through for every hop. Pools are selected greedily,
+    // per hop: the candidate that gives the most output for that hop's input,
+    // not the combination that is best for the path as a whole.
+    pub selected_pools: Vec<Pubkey>,
+    // Profit in the input token, after deducting costs we can express in it.
     pub profit: u64,
+    // Lamports spent on creating accounts and on the priority fee, when the
+    // input token is not wSOL.
+    pub non_token_costs_lamports: u64,
+    // Compute unit price of the trigger transaction, and the one we chose for
+    // ours, in micro-lamports.
+    pub trigger_compute_unit_price: Option<u64>,
+    pub compute_unit_price: Option<u64>,
+    // Compute units that we expect the transaction to consume.
+    pub estimated_compute_units: u64,
+    // The slippage and compute unit limit of the transaction, and which level
+    // of the config supplied them and `compute_unit_price`.
+    pub slippage_bps: Option<u16>,
+    pub compute_unit_limit: Option<u32>,
+    pub setting_sources: TxSettingSources,
+    // With `dry_run`, the transaction that we would have executed, see
+    // `encode_swap_tx`.
+    pub dry_run_transaction: Option<String>,
+    // The profit in the reporting mint, with a `reporting` config.
+    pub reported_profit: Option<ReportedProfit>,
     // Marginal price when calculating the path's input.
     pub marginal_price: f64,
+    // Input that the path calculation found optimal, before any cap.
+    pub optimal_input: f64,
+    // Mint of the token that goes into the first hop of the path.
+    pub input_mint: Pubkey,
+    // Set when we deliberately did not craft a transaction for this output.
+    pub skip_reason: Option<SkipReason>,
+    // For paths with `require_confirmations`, on how many triggers in a row we
+    // saw this imbalance.
+    pub observations: Option<u8>,
+    // The cap that limited the input below the optimal input, if any.
+    pub input_cap: Option<CapKind>,
+    // Whether our balance of the input token limited the input. The profit is
+    // then the one at the capped input, which is what we rank by.
+    pub capped: bool,
+    // Kind of the transaction that triggered the evaluation.
+    pub trigger_kind: TriggerKind,
+    // Set when the path's opportunity is split over multiple outputs, which
+    // are then next to each other, in order.
+    pub sequence: Option<SequencePart>,
+    // With `audit_mode`, the inputs to our decision. Only the output that we
+    // select keeps it.
+    pub decision_trace: Option<DecisionTrace>,
 }
 
+impl MevTxOutput {
+    /// The profit valued in the reporting mint, the common quote currency in
+    /// which the profits of paths that start with different mints compare.
+    pub fn profit_in_quote(&self) -> Option<u64> {
+        self.reported_profit
+            .and_then(|reported_profit| reported_profit.reported_value)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct PathCalculationOutput {
     pub optimal_input: f64,
     pub marginal_price: f64,
     pub source_token_balance: Option<u64>,
 }
 
+/// The integer input for the f64 `optimal_input`, rounded down, and clamped to
+/// the amounts that a token account can hold, so that an absurd estimate
+/// cannot overflow the integer math after it. `NaN` is 0.
+pub fn optimal_input_amount(optimal_input: f64) -> u128 {
+    // `u64::MAX as f64` rounds up to 2^64, one more than the maximum.
+    if optimal_input >= u64::MAX as f64 {
+        return u64::MAX as u128;
+    }
+    // Casts from float saturate at 0, also for `NaN`.
+    optimal_input.floor() as u128
+}
+
+/// For paths through pools without a closed form, we estimate the marginal
+/// price from a trade of this fraction of the first pool's reserve.
+const MARGINAL_PRICE_PROBE_FRACTION: u128 = 1_000_000;
+
+/// In a small pool, that trade is so small that the rounding of every hop
+/// dominates its output, so we probe with 10 times more until it gets at least
+/// this much out.
+const MIN_PROBE_OUTPUT: u128 = 1_000;
+
+/// With `exact_optimal_input`, we search for the integer optimum within this
+/// fraction of the f64 estimate on either side of it, and at least within
+/// `OPTIMAL_INPUT_MIN_WINDOW`.
+const OPTIMAL_INPUT_WINDOW_FRACTION: u128 = 1_000;
+const OPTIMAL_INPUT_MIN_WINDOW: u128 = 1_000;
+
 impl MevPath {
+    /// The path through the same pools in the other orientation of the cycle:
+    /// the hops in reverse order, each in the other direction. It starts and
+    /// ends in the same token, so the other settings apply as they are.
+    pub fn reversed(&self) -> MevPath {
+        MevPath {
+            path: self
+                .path
+                .iter()
+                .rev()
+                .map(|pair_info| PairInfo {
+                    direction: match pair_info.direction {
+                        TradeDirection::AtoB => TradeDirection::BtoA,
+                        TradeDirection::BtoA => TradeDirection::AtoB,
+                    },
+                    ..pair_info.clone()
+                })
+                .collect(),
+            probe_reverse: false,
+            ..self.clone()
+        }
+    }
+
+    /// If the pool balances changed from `pre_pool_states` to
+    /// `post_pool_states` as if someone traded through every hop of this path,
+    /// returns (`amount_in`, `amount_out`), where `amount_in` is what went into
+    /// the first hop, and `amount_out` is what came out of the last hop.
+    pub fn get_cycle_amounts(
+        &self,
+        pre_pool_states: &PoolStates,
+        post_pool_states: &PoolStates,
+    ) -> Option<(u64, u64)> {
+        let mut first_amount_in = None;
+        let mut last_amount_out = 0;
+        for pair_info in &self.path {
+            // The trade may have gone through any of the candidate pools, and
+            // it should have moved tokens into the pool on the `from` side and
+            // out of the pool on the `to` side.
+            let (amount_in, amount_out) = pair_info.candidate_pools().find_map(|pool| {
+                let (from_pre, to_pre) = pre_pool_states
+                    .0
+                    .get(pool)?
+                    .get_balances(&pair_info.direction);
+                let (from_post, to_post) = post_pool_states
+                    .0
+                    .get(pool)?
+                    .get_balances(&pair_info.direction);
+                let amount_in = from_post.checked_sub(from_pre).filter(|x| *x > 0)?;
+                let amount_out = to_pre.checked_sub(to_post).filter(|x| *x > 0)?;
+                Some((amount_in, amount_out))
+            })?;
+            first_amount_in.get_or_insert(amount_in);
+            last_amount_out = amount_out;
+        }
+        Some((first_amount_in?, last_amount_out))
+    }
+
+    /// The number of swap instructions of a transaction for this path, when
+    /// every hop through a pool group splits its input across all its pools.
+    pub fn swap_count(&self) -> usize {
+        self.path.iter().map(PairInfo::swap_count).sum()
+    }
+
+    /// Estimate the transaction we would craft for this path, assuming that
+    /// no account is shared between swaps.
+    pub fn max_tx_size(&self) -> TxSizeEstimate {
+        let num_swaps = self.swap_count();
+        TxSizeEstimate::new(FIXED_TX_ACCOUNTS + ACCOUNTS_PER_HOP * num_swaps, num_swaps)
+    }
+
+    /// Estimate the transaction we would craft for this path, counting the
+    /// accounts that hops share, e.g. our token account for a mint that one
+    /// hop outputs and the next one takes as input, only once. Hops whose pool
+    /// is not in `pools` count as if they share no accounts. For hops with
+    /// alternative pools, we count the accounts of all candidates, so then the
+    /// estimate is an upper bound. Hops through a pool group count a swap for
+    /// every pool of the group.
+    pub fn estimate_tx_size(&self, pools: &AllOrcaPoolAddresses) -> TxSizeEstimate {
+        let mut accounts = HashSet::new();
+        let mut unknown_accounts = 0;
+        for pair_info in &self.path {
+            for pool_address in pair_info.candidate_pools() {
+                match pools.by_address(pool_address) {
+                    Some(pool) => {
+                        // The pool authority is derived from the pool address
+                        // once we load the pool, so it is unique per pool.
+                        unknown_accounts += 1;
+                        accounts.extend([
+                            pool.address,
+                            pool.pool_a_account,
+                            pool.pool_b_account,
+                            pool.pool_mint,
+                            pool.pool_fee,
+                        ]);
+                        accounts.extend(pool.source);
+                        accounts.extend(pool.destination);
+                        accounts.extend(pool.host_fee);
+                    }
+                    None => unknown_accounts += ACCOUNTS_PER_HOP,
+                }
+            }
+        }
+        TxSizeEstimate::new(
+            FIXED_TX_ACCOUNTS + accounts.len() + unknown_accounts,
+            self.swap_count(),
+        )
+    }
+
+    /// Check that every hop takes the token that the previous hop outputs, and
+    /// that the last hop outputs the token that goes into the first hop, using
+    /// the mints of the pools in `pools`. Hops whose main pool has no mints in
+    /// the config are not checked.
+    pub fn check_mint_chain(&self, pools: &AllOrcaPoolAddresses) -> Result<(), String> {
+        let hop_mints: Vec<Option<(Pubkey, Pubkey)>> = self
+            .path
+            .iter()
+            .map(|pair_info| {
+                pools
+                    .by_address(&pair_info.pool)?
+                    .known_mints_in_direction(&pair_info.direction)
+            })
+            .collect();
+        for (i, hop) in hop_mints.iter().enumerate() {
+            let next_i = (i + 1) % hop_mints.len();
+            if let (Some((_from, to)), Some((next_from, _next_to))) = (hop, &hop_mints[next_i]) {
+                if to != next_from {
+                    return Err(format!(
+                        "hop {} of MEV path {} outputs {}, but hop {} takes {}",
+                        i, self.name, to, next_i, next_from
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every pool that a hop of the path can trade through is in
+    /// `pools`, otherwise we never load its state and the path never has an
+    /// opportunity.
+    pub fn check_pools_known(&self, pools: &AllOrcaPoolAddresses) -> Result<(), String> {
+        for (i, pair_info) in self.path.iter().enumerate() {
+            for pool in pair_info.candidate_pools() {
+                if pools.by_address(pool).is_none() {
+                    return Err(format!(
+                        "hop {} of MEV path {} uses pool {}, which is not an orca_account",
+                        i, self.name, pool
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that we have a `source` and `destination` token account for
+    /// every pool that a hop of the path can trade through, otherwise we
+    /// cannot craft a transaction for it.
+    pub fn check_trade_accounts(&self, pools: &AllOrcaPoolAddresses) -> Result<(), String> {
+        for (i, pair_info) in self.path.iter().enumerate() {
+            for pool in pair_info.candidate_pools() {
+                let has_trade_accounts = pools.by_address(pool).map_or(false, |pool| {
+                    pool.source.is_some() && pool.destination.is_some()
+                });
+                if !has_trade_accounts {
+                    return Err(format!(
+                        "hop {} of MEV path {} uses pool {}, which needs both a source and a \
+                        destination to trade through it",
+                        i, self.name, pool
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that no hop of the path can trade through a pool that has the
+    /// observe role in `pools`.
+    pub fn check_pool_roles(&self, pools: &AllOrcaPoolAddresses) -> Result<(), String> {
+        for (i, pair_info) in self.path.iter().enumerate() {
+            for pool in pair_info.candidate_pools() {
+                let role = pools.by_address(pool).map(|pool| pool.role);
+                if role == Some(PoolRole::Observe) {
+                    return Err(format!(
+                        "hop {} of MEV path {} uses pool {}, which has role \"observe\", \
+                        we never trade through observe pools",
+                        i, self.name, pool
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether any hop of the path has alternative pools to choose from.
+    pub fn has_alternative_pools(&self) -> bool {
+        self.path
+            .iter()
+            .any(|pair_info| !pair_info.alternative_pools.is_empty())
+    }
+
+    /// Look up the candidate states of every hop of the path, once per
+    /// trigger, so that both the calculation and the simulation of the path
+    /// can use them without looking them up again. Returns `MissingPool` when
+    /// no candidate pool of a hop has a state.
+    pub fn resolve_hops<'a>(
+        &'a self,
+        pool_states: &'a PoolStates,
+    ) -> Result<Vec<HopCandidates<'a>>, PathSkipReason> {
+        self.path
+            .iter()
+            .map(|pair_info| {
+                pair_info
+                    .get_candidate_states(pool_states)
+                    .ok_or(PathSkipReason::MissingPool {
+                        pool: pair_info.pool,
+                    })
+            })
+            .collect()
+    }
+
     /// Get (`input`, `marginal_price`), `input` is the input of the first hop
     /// of the path, and `marginal_price` is the multiplication of all fees and
-    /// ratios from the path.
+    /// ratios from the path. For hops with alternative pools, we use the
+    /// candidate with the best marginal price, and for hops through a pool
+    /// group, the combined reserves of the group. `hops` are the candidates of
+    /// every hop, as returned by `resolve_hops`. Candidates with a reserve
+    /// below `min_reserve`, or an empty one, are drained and not used, and
+    /// when a hop has no other candidate, the path has no output. Without an
+    /// output, returns why.
     pub fn get_path_calculation_output(
         &self,
-        pool_states: &PoolStates,
-    ) -> Option<PathCalculationOutput> {
-        let mut marginal_prices_acc = 1_f64;
+        hops: &[HopCandidates],
+        min_reserve: u64,
+    ) -> Result<PathCalculationOutput, PathSkipReason> {
+        let is_drained = |candidates: &HopCandidates| {
+            candidates
+                .iter()
+                .all(|(_pubkey, pool_state)| !pool_state.has_reserves(min_reserve))
+        };
+        if let Some(hop) = hops.iter().position(is_drained) {
+            return Err(PathSkipReason::DrainedPool { hop });
+        }
+        // The closed form below only holds for constant product curves.
+        let is_constant_product = hops
+            .iter()
+            .flatten()
+            .all(|(_pubkey, pool_state)| pool_state.curve_type == CurveType::ConstantProduct);
+        if !is_constant_product {
+            return self.search_path_calculation_output(hops);
+        }
+
+        // We accumulate the marginal price with twice the precision of an f64,
+        // so that whether it is above 1 does not depend on rounding errors.
+        let mut marginal_prices_acc = DoubleF64::ONE;
         let mut optimal_input_denominator = 0_f64;
         let mut previous_ratio = 1_f64;
         let mut total_fee_acc = 1_f64;
 
-        let source_amount = pool_states.0.get(&self.path.first()?.pool)?.source_balance;
-        for pair_info in &self.path {
-            let tokens_state = pool_states.0.get(&pair_info.pool)?;
-
-            let (token_balance_from, token_balance_to) = match pair_info.direction {
-                TradeDirection::AtoB => (
-                    tokens_state.pool_a_balance as f64,
-                    tokens_state.pool_b_balance as f64,
-                ),
-                TradeDirection::BtoA => (
-                    tokens_state.pool_b_balance as f64,
-                    tokens_state.pool_a_balance as f64,
-                ),
-            };
-            let fees = &tokens_state.fees.0;
-            let host_fee = if fees.host_fee_numerator == 0 {
-                0_f64
-            } else {
-                fees.host_fee_numerator as f64 / fees.host_fee_denominator as f64
-            };
-            let owner_fee = if fees.owner_trade_fee_numerator == 0 {
-                0_f64
-            } else {
-                fees.owner_trade_fee_numerator as f64 / fees.owner_trade_fee_denominator as f64
-            };
-            let trade_fee = if fees.trade_fee_numerator == 0 {
-                0_f64
+        let (_pubkey, first_pool_state) = hops
+            .first()
+            .and_then(|candidates| candidates.first())
+            .ok_or(PathSkipReason::NoHops)?;
+        let source_amount = first_pool_state.source_balance;
+        for (hop, (pair_info, candidates)) in self.path.iter().zip(hops).enumerate() {
+            let usable_parameters = candidates
+                .iter()
+                // The float math below does not cope with degenerate fees, or
+                // with empty reserves.
+                .filter(|(_pubkey, tokens_state)| {
+                    tokens_state.fees.validate().is_ok() && tokens_state.has_reserves(min_reserve)
+                })
+                .map(|(_pubkey, tokens_state)| {
+                    get_hop_parameters(tokens_state, &pair_info.direction)
+                });
+            let hop_parameters = if pair_info.group.is_some() {
+                // The pools of a group act as one pool with their combined
+                // reserves, which is exact when they have the same price and
+                // fees. The fee is weighted by the reserves.
+                usable_parameters
+                    .map(|parameters| {
+                        (
+                            parameters.token_balance_from,
+                            parameters.token_balance_to,
+                            parameters.total_fee * parameters.token_balance_from,
+                        )
+                    })
+                    .reduce(|a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2))
+                    .map(|(from, to, weighted_fee)| {
+                        let total_fee = weighted_fee / from;
+                        HopParameters {
+                            token_balance_from: from,
+                            token_balance_to: to,
+                            total_fee,
+                            marginal_price: DoubleF64::ratio(to, from)
+                                .mul(DoubleF64::ratio(total_fee, 1_f64)),
+                        }
+                    })
             } else {
-                fees.trade_fee_numerator as f64 / fees.trade_fee_denominator as f64
+                usable_parameters.max_by(|a, b| {
+                    (a.marginal_price.hi, a.marginal_price.lo)
+                        .partial_cmp(&(b.marginal_price.hi, b.marginal_price.lo))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
             };
+            // The drained candidates are gone already.
+            let HopParameters {
+                token_balance_from,
+                token_balance_to,
+                total_fee,
+                marginal_price,
+            } = hop_parameters.ok_or(PathSkipReason::InvalidFees { hop })?;
 
-            let total_fee = 1_f64 - (host_fee + owner_fee + trade_fee);
             let ratio = token_balance_to / token_balance_from;
-            marginal_prices_acc *= ratio;
-            marginal_prices_acc *= total_fee;
+            marginal_prices_acc = marginal_prices_acc.mul(marginal_price);
             total_fee_acc *= total_fee;
 
             optimal_input_denominator += total_fee_acc * (previous_ratio / token_balance_from);
             previous_ratio = previous_ratio * ratio;
         }
-        if marginal_prices_acc > 1_f64 {
-            let optimal_input_numerator = marginal_prices_acc.sqrt() - 1_f64;
+        let marginal_price = marginal_prices_acc.to_f64();
+        if marginal_prices_acc.is_above_one() {
+            // `sqrt(marginal_price) - 1`, without the cancellation near 1.
+            let optimal_input_numerator =
+                marginal_prices_acc.minus_one() / (marginal_price.sqrt() + 1_f64);
             let optimal_input = optimal_input_numerator / optimal_input_denominator;
-            Some(PathCalculationOutput {
+            // Reserves beyond what f64 represents exactly can still overflow
+            // the math, an input that is not finite is no opportunity.
+            if !optimal_input.is_finite() || !marginal_price.is_finite() {
+                return Err(PathSkipReason::NotFinite { marginal_price });
+            }
+            Ok(PathCalculationOutput {
                 optimal_input,
-                marginal_price: marginal_prices_acc,
+                marginal_price,
                 source_token_balance: source_amount,
             })
         } else {
-            None
+            Err(PathSkipReason::NotProfitable { marginal_price })
+        }
+    }
+
+    /// What comes out of the last hop when we put `amount_in` into the first,
+    /// through the candidate that gives the most output at every hop, or split
+    /// across a pool group, like `Mev::quote_path` does.
+    pub fn simulate_path(&self, hops: &[HopCandidates], amount_in: u128) -> Option<u128> {
+        self.path
+            .iter()
+            .zip(hops)
+            .try_fold(amount_in, |amount_in, (pair_info, candidates)| {
+                let trades = pair_info.swap(candidates, amount_in)?;
+                Some(trades.iter().map(|trade| trade.amount_out).sum())
+            })
+    }
+
+    /// Like `get_path_calculation_output`, for paths through pools whose curve
+    /// has no closed form for the optimal input, e.g. stable curves. The
+    /// profit of a path is concave in its input, so we find the optimal input
+    /// with a ternary search on the simulated profit, between nothing and the
+    /// reserve of the first pool, more than which no opportunity needs. The
+    /// marginal price is estimated from a small trade.
+    fn search_path_calculation_output(
+        &self,
+        hops: &[HopCandidates],
+    ) -> Result<PathCalculationOutput, PathSkipReason> {
+        let (first_pair_info, first_candidates) = self
+            .path
+            .first()
+            .zip(hops.first())
+            .ok_or(PathSkipReason::NoHops)?;
+        let (_pubkey, first_pool_state) = first_candidates.first().ok_or(PathSkipReason::NoHops)?;
+        let max_input = first_candidates
+            .iter()
+            .map(|(_pubkey, pool_state)| pool_state.get_balances(&first_pair_info.direction).0)
+            .max()
+            .ok_or(PathSkipReason::NoHops)? as u128;
+
+        let mut probe = (max_input / MARGINAL_PRICE_PROBE_FRACTION).max(1);
+        let probe_output = loop {
+            // A swap that rounds to nothing fails.
+            match self.simulate_path(hops, probe) {
+                Some(probe_output) if probe_output >= MIN_PROBE_OUTPUT || probe >= max_input => {
+                    break probe_output
+                }
+                _ if probe < max_input => probe = (probe * 10).min(max_input),
+                _ => return Err(PathSkipReason::SwapFailed),
+            }
+        };
+        let marginal_price = probe_output as f64 / probe as f64;
+        if marginal_price <= 1_f64 {
+            return Err(PathSkipReason::NotProfitable { marginal_price });
+        }
+
+        let (optimal_input, profit) = self.search_optimal_input(hops, 1, max_input);
+        if profit == i128::MIN {
+            return Err(PathSkipReason::SwapFailed);
+        }
+        // The probe can round in our favour, only the simulation is exact.
+        if profit <= 0 {
+            return Err(PathSkipReason::NotProfitable { marginal_price });
+        }
+        Ok(PathCalculationOutput {
+            optimal_input: optimal_input as f64,
+            marginal_price,
+            source_token_balance: first_pool_state.source_balance,
+        })
+    }
+
+    /// The profit of putting `amount_in` into the path, in the first token,
+    /// with the rounding of the pools. `i128::MIN` when a hop fails.
+    fn simulated_profit(&self, hops: &[HopCandidates], amount_in: u128) -> i128 {
+        match self.simulate_path(hops, amount_in) {
+            Some(amount_out) => amount_out as i128 - amount_in as i128,
+            None => i128::MIN,
+        }
+    }
+
+    /// The input between `low` and `high` inclusive with the most simulated
+    /// profit, and that profit, by ternary search. The profit is concave in
+    /// the input up to the rounding of the pools, which can make the search
+    /// end a few units off the best input.
+    fn search_optimal_input(&self, hops: &[HopCandidates], low: u128, high: u128) -> (u128, i128) {
+        let (mut low, mut high) = (low, high.max(low));
+        while high - low > 2 {
+            let third = (high - low) / 3;
+            let low_profit = self.simulated_profit(hops, low + third);
+            let high_profit = self.simulated_profit(hops, high - third);
+            if low_profit < high_profit {
+                low += third + 1;
+            } else {
+                high -= third;
+            }
+        }
+        (low..=high)
+            .map(|amount_in| (amount_in, self.simulated_profit(hops, amount_in)))
+            .max_by_key(|(_amount_in, profit)| *profit)
+            .expect("The range is not empty")
+    }
+
+    /// Refine the f64 `estimate` of the optimal input of
+    /// `get_path_calculation_output` to the integer input with the most
+    /// profit, with the rounding of the pools, by searching a window around
+    /// it. With large pool balances, the f64 math can be a few units off,
+    /// and its floor can even be more than the input that the pools accept.
+    /// The result never has less profit than the floor of `estimate`.
+    pub fn refine_optimal_input(&self, hops: &[HopCandidates], estimate: f64) -> u128 {
+        let floor = optimal_input_amount(estimate);
+        let window = (floor / OPTIMAL_INPUT_WINDOW_FRACTION).max(OPTIMAL_INPUT_MIN_WINDOW);
+        let (optimal_input, profit) = self.search_optimal_input(
+            hops,
+            floor.saturating_sub(window).max(1),
+            floor.saturating_add(window),
+        );
+        if profit > self.simulated_profit(hops, floor) {
+            optimal_input
+        } else {
+            floor
+        }
+    }
+}
+
+/// A number as the unevaluated sum `hi + lo` of two f64s, where `lo` is at
+/// most half an ulp of `hi`, which gives about twice the precision of an f64.
+/// The products of f64s lose a bit of precision in every step, and on long
+/// paths that can flip whether their marginal price is above 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DoubleF64 {
+    hi: f64,
+    lo: f64,
+}
+
+impl DoubleF64 {
+    const ONE: DoubleF64 = DoubleF64 {
+        hi: 1_f64,
+        lo: 0_f64,
+    };
+
+    /// `hi + lo`, where `lo` is small enough that `hi` does not change when we
+    /// add it.
+    fn normalize(hi: f64, lo: f64) -> Self {
+        let sum = hi + lo;
+        if !sum.is_finite() {
+            return DoubleF64 { hi: sum, lo: 0_f64 };
+        }
+        DoubleF64 {
+            hi: sum,
+            lo: lo - (sum - hi),
+        }
+    }
+
+    /// `numerator / denominator`, the remainder of an f64 division is exact.
+    fn ratio(numerator: f64, denominator: f64) -> Self {
+        let quotient = numerator / denominator;
+        if !quotient.is_finite() {
+            return DoubleF64 {
+                hi: quotient,
+                lo: 0_f64,
+            };
         }
+        let remainder = (-quotient).mul_add(denominator, numerator);
+        Self::normalize(quotient, remainder / denominator)
+    }
+
+    /// The rounding error of an f64 product is exact with a fused
+    /// multiply-add.
+    fn mul(self, other: Self) -> Self {
+        let product = self.hi * other.hi;
+        if !product.is_finite() {
+            return DoubleF64 {
+                hi: product,
+                lo: 0_f64,
+            };
+        }
+        let error = self.hi.mul_add(other.hi, -product) + (self.hi * other.lo + self.lo * other.hi);
+        Self::normalize(product, error)
+    }
+
+    fn is_above_one(&self) -> bool {
+        self.hi > 1_f64 || (self.hi == 1_f64 && self.lo > 0_f64)
+    }
+
+    /// `self - 1`, without losing the precision of `self` near 1.
+    fn minus_one(&self) -> f64 {
+        (self.hi - 1_f64) + self.lo
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
     }
 }
 
+/// What the closed form of the optimal input needs of a hop through a pool,
+/// see `get_hop_parameters`.
+struct HopParameters {
+    token_balance_from: f64,
+    token_balance_to: f64,
+    /// The fraction of the input that remains after fees.
+    total_fee: f64,
+    /// `token_balance_to / token_balance_from * total_fee`, from the exact
+    /// fractions of the fees.
+    marginal_price: DoubleF64,
+}
+
+/// Get the `HopParameters` of a hop through `tokens_state` in `direction`.
+/// The transfer fee of a Token-2022 input mint is a fee on the input like the
+/// pool's, and the one of the output mint takes a share of all that the pool
+/// sends, like a smaller `token_balance_to`.
+fn get_hop_parameters(
+    tokens_state: &OrcaPoolWithBalance,
+    direction: &TradeDirection,
+) -> HopParameters {
+    let (token_balance_from, token_balance_to) = match direction {
+        TradeDirection::AtoB => (
+            tokens_state.pool_a_balance as f64,
+            tokens_state.pool_b_balance as f64,
+        ),
+        TradeDirection::BtoA => (
+            tokens_state.pool_b_balance as f64,
+            tokens_state.pool_a_balance as f64,
+        ),
+    };
+    let (mint_in, mint_out) = tokens_state.get_mint_infos(direction);
+    let remainder = |mint_info: Option<MintInfo>| {
+        mint_info.map_or(1_f64, |mint_info| mint_info.transfer_fee_remainder())
+    };
+    let total_fee = (1_f64 - tokens_state.fees.total_fee_fraction()) * remainder(mint_in);
+
+    let pool_fee = match tokens_state.fees.remaining_fraction() {
+        Some((numerator, denominator)) => DoubleF64::ratio(numerator as f64, denominator as f64),
+        None => DoubleF64::ratio(1_f64 - tokens_state.fees.total_fee_fraction(), 1_f64),
+    };
+    let marginal_price = [mint_in, mint_out]
+        .into_iter()
+        .flatten()
+        .map(|mint_info| {
+            let (numerator, denominator) = mint_info.transfer_fee_remaining_fraction();
+            DoubleF64::ratio(numerator as f64, denominator as f64)
+        })
+        .fold(
+            DoubleF64::ratio(token_balance_to, token_balance_from).mul(pool_fee),
+            DoubleF64::mul,
+        );
+    HopParameters {
+        token_balance_from,
+        token_balance_to: token_balance_to * remainder(mint_out),
+        total_fee,
+        marginal_price,
+    }
+}
+
+#[derive(Clone)]
 pub struct SwapArguments {
     pub program_id: Pubkey,
     pub swap_pubkey: Pubkey,
@@ -143,6 +1505,9 @@ pub struct SwapArguments {
     pub destination_pubkey: Pubkey,
     pub pool_mint_pubkey: Pubkey,
     pub pool_fee_pubkey: Pubkey,
+    /// Our pool token account that receives the host fee, if we are the
+    /// registered host of the pool.
+    pub host_fee_pubkey: Option<Pubkey>,
     pub token_program: Pubkey,
     pub amount_in: u64,
     pub minimum_amount_out: u64,
@@ -151,267 +1516,660 @@ pub struct SwapArguments {
 pub fn create_swap_tx(
     swap_args_vec: Vec<SwapArguments>,
     blockhash: Hash,
-    user_transfer_authority: &Keypair,
-) -> SanitizedTransaction {
-    let instructions: Vec<Instruction> = swap_args_vec
-        .iter()
-        .map(|swap_args| {
-            let data = SwapInstruction::Swap(Swap {
+    user_transfer_authority: &dyn MevSigner,
+    compute_unit_price: Option<u64>,
+    compute_unit_limit: Option<u32>,
+) -> Result<SanitizedTransaction, SigningError> {
+    let instructions = swap_instructions(
+        &swap_args_vec,
+        user_transfer_authority.pubkey(),
+        compute_unit_price,
+        compute_unit_limit,
+    );
+    let message = swap_message(&instructions, user_transfer_authority.pubkey(), blockhash);
+    let signature = user_transfer_authority.sign_message(&message.serialize())?;
+    Ok(signed_swap_tx(message, signature))
+}
+
+fn swap_instructions(
+    swap_args_vec: &[SwapArguments],
+    user_transfer_authority: Pubkey,
+    compute_unit_price: Option<u64>,
+    compute_unit_limit: Option<u32>,
+) -> Vec<Instruction> {
+    let swap_instructions = swap_args_vec.iter().map(|swap_args| {
+        // Build the instruction like the swap program expects it, and convert
+        // it, since `spl_token_swap` has its own `Pubkey` and `Instruction`.
+        let instruction = spl_token_swap::instruction::swap(
+            &to_spl(&swap_args.program_id),
+            &to_spl(&swap_args.token_program),
+            &to_spl(&swap_args.swap_pubkey),
+            &to_spl(&swap_args.authority_pubkey),
+            &to_spl(&user_transfer_authority),
+            &to_spl(&swap_args.source_pubkey),
+            &to_spl(&swap_args.swap_source_pubkey),
+            &to_spl(&swap_args.swap_destination_pubkey),
+            &to_spl(&swap_args.destination_pubkey),
+            &to_spl(&swap_args.pool_mint_pubkey),
+            &to_spl(&swap_args.pool_fee_pubkey),
+            swap_args.host_fee_pubkey.as_ref().map(to_spl).as_ref(),
+            Swap {
                 amount_in: swap_args.amount_in,
                 minimum_amount_out: swap_args.minimum_amount_out,
-            })
-            .pack();
-
-            let is_signer = false;
-            let accounts = vec![
-                AccountMeta::new_readonly(swap_args.swap_pubkey, is_signer),
-                AccountMeta::new_readonly(swap_args.authority_pubkey, is_signer),
-                AccountMeta::new_readonly(user_transfer_authority.pubkey(), true),
-                AccountMeta::new(swap_args.source_pubkey, is_signer),
-                AccountMeta::new(swap_args.swap_source_pubkey, is_signer),
-                AccountMeta::new(swap_args.swap_destination_pubkey, is_signer),
-                AccountMeta::new(swap_args.destination_pubkey, is_signer),
-                AccountMeta::new(swap_args.pool_mint_pubkey, is_signer),
-                AccountMeta::new(swap_args.pool_fee_pubkey, is_signer),
-                AccountMeta::new_readonly(swap_args.token_program, is_signer),
-            ];
-
-            Instruction {
-                program_id: swap_args.program_id,
-                accounts,
-                data,
-            }
-        })
-        .collect();
+            },
+        )
+        .expect("Building a swap instruction does not fail.");
+
+        Instruction {
+            program_id: from_spl(&instruction.program_id),
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: from_spl(&account.pubkey),
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: instruction.data,
+        }
+    });
+    compute_unit_limit
+        .map(ComputeBudgetInstruction::set_compute_unit_limit)
+        .into_iter()
+        .chain(compute_unit_price.map(ComputeBudgetInstruction::set_compute_unit_price))
+        .chain(swap_instructions)
+        .collect()
+}
+
+fn to_spl(pubkey: &Pubkey) -> SplPubkey {
+    SplPubkey::new(&pubkey.to_bytes())
+}
+
+fn from_spl(pubkey: &SplPubkey) -> Pubkey {
+    Pubkey::new(&pubkey.to_bytes())
+}
+
+fn swap_message(
+    instructions: &[Instruction],
+    user_transfer_authority: Pubkey,
+    blockhash: Hash,
+) -> Message {
+    Message::new_with_blockhash(instructions, Some(&user_transfer_authority), &blockhash)
+}
 
-    let signed_tx = Transaction::new_signed_with_payer(
-        &instructions,
-        Some(&user_transfer_authority.pubkey()),
-        &[user_transfer_authority],
-        blockhash,
-    );
+fn signed_swap_tx(message: Message, signature: Signature) -> SanitizedTransaction {
+    // The user transfer authority is also the fee payer, so it is the only
+    // signer.
+    let mut signed_tx = Transaction::new_unsigned(message);
+    signed_tx.signatures = vec![signature];
 
     SanitizedTransaction::try_from_legacy_transaction(signed_tx)
         .expect("Built by us, shouldn't fail.")
 }
 
+/// The transaction of a `MevTxOutput`, before it is signed. Evaluating the
+/// paths only builds blueprints, and we sign the one that we select, so that
+/// signing stays out of the evaluation. Signing a blueprint gives the same
+/// transaction as `create_swap_tx` with the same arguments.
+#[derive(Debug, Clone)]
+pub struct MevTxBlueprint {
+    pub instructions: Vec<Instruction>,
+    pub blockhash: Hash,
+
+    /// The user transfer authority, that signs the transaction and pays its
+    /// fee.
+    pub signer: Arc<dyn MevSigner>,
+}
+
+impl MevTxBlueprint {
+    pub fn new(
+        swap_args_vec: &[SwapArguments],
+        blockhash: Hash,
+        signer: Arc<dyn MevSigner>,
+        compute_unit_price: Option<u64>,
+        compute_unit_limit: Option<u32>,
+    ) -> Self {
+        MevTxBlueprint {
+            instructions: swap_instructions(
+                swap_args_vec,
+                signer.pubkey(),
+                compute_unit_price,
+                compute_unit_limit,
+            ),
+            blockhash,
+            signer,
+        }
+    }
+
+    /// Sign the transaction, on `signing_thread` if there is one, see
+    /// `SigningThread::sign`, and on the calling thread otherwise.
+    pub fn sign(
+        &self,
+        signing_thread: Option<&SigningThread>,
+    ) -> Result<SanitizedTransaction, SigningError> {
+        let message = swap_message(&self.instructions, self.signer.pubkey(), self.blockhash);
+        let serialized = message.serialize();
+        let signature = match signing_thread {
+            Some(signing_thread) => signing_thread.sign(&self.signer, serialized)?,
+            None => self.signer.sign_message(&serialized)?,
+        };
+        Ok(signed_swap_tx(message, signature))
+    }
+}
+
+/// The pools that a transaction from `create_swap_tx` trades through, in the
+/// order of its swaps.
+pub fn get_swap_pools(tx: &SanitizedTransaction) -> Vec<Pubkey> {
+    let account_keys = tx.message().account_keys();
+    tx.message()
+        .program_instructions_iter()
+        .filter(|(program_id, _instruction)| **program_id != compute_budget::id())
+        .filter_map(|(_program_id, instruction)| {
+            // The pool is the first account of a swap.
+            let pool_index = *instruction.accounts.first()?;
+            account_keys.get(pool_index as usize).copied()
+        })
+        .collect()
+}
+
+/// A transaction from `create_swap_tx` as we log it with `dry_run`: its
+/// `VersionedTransaction` in bincode and base64, as `sendTransaction` takes
+/// it.
+pub fn encode_swap_tx(tx: &SanitizedTransaction) -> String {
+    let serialized =
+        bincode::serialize(&tx.to_versioned_transaction()).expect("Built by us, shouldn't fail.");
+    base64::encode(serialized)
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc};
+    use std::{collections::HashMap, path::PathBuf, str::FromStr};
 
-    use spl_token_swap::curve::constant_product::ConstantProductCurve;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaChaRng;
+    use solana_sdk::{
+        rent::Rent,
+        signature::{Keypair, Signature},
+    };
     use tempfile::NamedTempFile;
 
     use super::*;
-    use crate::mev::{
-        utils::{AllOrcaPoolAddresses, MevConfig},
-        Fees, Mev, MevLog, OrcaPoolAddresses, OrcaPoolWithBalance, PoolStates,
+    use crate::{
+        inline_spl_token,
+        mev::{
+            config::MevConfig,
+            pool_state::Fees,
+            test_utils::{
+                orca_accounts, path, pool, pubkey, steth_stsol_usdc_path,
+                steth_stsol_usdc_pool_states, transfer_fee_mint_info, PoolBuilder, STETH_USDC_POOL,
+                STSOL_STETH_POOL, STSOL_USDC_POOL,
+            },
+            CompetitorArbitrage, Mev, MevLog, OrcaPoolAddresses, PoolStates,
+        },
     };
 
     #[test]
     fn test_get_arbitrage() {
-        let curve_calculator = Arc::new(ConstantProductCurve::default());
-        let mut pool_states = PoolStates(
-            vec![
-                (
-                    Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG").unwrap(),
-                    OrcaPoolWithBalance {
-                        pool: OrcaPoolAddresses {
-                            program_id: Pubkey::from_str(
-                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                            )
-                            .unwrap(),
-                            address: Pubkey::from_str(
-                                "v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG",
-                            )
-                            .unwrap(),
-                            ..Default::default()
-                        },
-                        pool_a_balance: 4618233234,
-                        pool_b_balance: 6400518033,
-                        fees: Fees(spl_token_swap::curve::fees::Fees {
-                            trade_fee_numerator: 25,
-                            trade_fee_denominator: 10_000,
-                            owner_trade_fee_numerator: 5,
-                            owner_trade_fee_denominator: 10_000,
-                            owner_withdraw_fee_numerator: 0,
-                            owner_withdraw_fee_denominator: 1,
-                            host_fee_numerator: 0,
-                            host_fee_denominator: 1,
-                        }),
-                        curve_calculator: curve_calculator.clone(),
-                        source_balance: None,
-                        destination_balance: None,
-                    },
-                ),
-                (
-                    Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy").unwrap(),
-                    OrcaPoolWithBalance {
-                        pool: OrcaPoolAddresses {
-                            program_id: Pubkey::from_str(
-                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                            )
-                            .unwrap(),
-                            address: Pubkey::from_str(
-                                "B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy",
-                            )
-                            .unwrap(),
-                            ..Default::default()
-                        },
-                        pool_a_balance: 54896627850684,
-                        pool_b_balance: 13408494240,
-                        fees: Fees(spl_token_swap::curve::fees::Fees {
-                            trade_fee_numerator: 25,
-                            trade_fee_denominator: 10_000,
-                            owner_trade_fee_numerator: 5,
-                            owner_trade_fee_denominator: 10_000,
-                            owner_withdraw_fee_numerator: 0,
-                            owner_withdraw_fee_denominator: 1,
-                            host_fee_numerator: 0,
-                            host_fee_denominator: 1,
-                        }),
-                        curve_calculator: curve_calculator.clone(),
-                        source_balance: None,
-                        destination_balance: None,
-                    },
-                ),
-                (
-                    Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL").unwrap(),
-                    OrcaPoolWithBalance {
-                        pool: OrcaPoolAddresses {
-                            program_id: Pubkey::from_str(
-                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                            )
-                            .unwrap(),
-                            address: Pubkey::from_str(
-                                "EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL",
-                            )
-                            .unwrap(),
-                            ..Default::default()
-                        },
-                        pool_a_balance: 400881658679,
-                        pool_b_balance: 138436018345,
-                        fees: Fees(spl_token_swap::curve::fees::Fees {
-                            trade_fee_numerator: 25,
-                            trade_fee_denominator: 10_000,
-                            owner_trade_fee_numerator: 5,
-                            owner_trade_fee_denominator: 10_000,
-                            owner_withdraw_fee_numerator: 0,
-                            owner_withdraw_fee_denominator: 1,
-                            host_fee_numerator: 0,
-                            host_fee_denominator: 1,
-                        }),
-                        curve_calculator,
-                        source_balance: None,
-                        destination_balance: None,
-                    },
-                ),
-            ]
-            .into_iter()
-            .collect(),
-        );
-        let path = MevPath {
-            name: "USDC->stETH->stSOL->USDC".to_owned(),
-            path: vec![
-                PairInfo {
-                    pool: Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG")
-                        .expect("stETH/USDC"),
-                    direction: TradeDirection::BtoA,
-                },
-                PairInfo {
-                    pool: Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy")
-                        .expect("stSOL/stETH"),
-                    direction: TradeDirection::BtoA,
-                },
-                PairInfo {
-                    pool: Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL")
-                        .expect("stSOL/USDC"),
-                    direction: TradeDirection::AtoB,
-                },
-            ],
-        };
+        let mut pool_states = steth_stsol_usdc_pool_states();
+        let path = steth_stsol_usdc_path();
         let mev_config = MevConfig {
             log_path: PathBuf::from(NamedTempFile::new().unwrap().path().to_str().unwrap()),
             watched_programs: vec![],
-            orca_accounts: AllOrcaPoolAddresses(vec![]),
+            orca_accounts: orca_accounts(&pool_states),
             mev_paths: vec![path],
             user_authority_path: None,
             minimum_profit: HashMap::new(),
+            ..Default::default()
         };
-        let mev_log = MevLog::new(&mev_config);
+        let mev_log = MevLog::new(&mev_config, None);
         let mev = Mev::new(mev_log.log_send_channel.clone(), mev_config);
-        let arbs = mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique());
+        let arbs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique(), &Rent::default(), None);
         assert_eq!(arbs[0].path_idx, 0);
         assert_eq!(
             arbs[0].input_output_pairs,
             vec![
                 InputOutputPairs {
                     token_in: 4099483579,
-                    token_out: 1799781506
+                    token_out: 1799781506,
+                    pool_splits: vec![],
                 },
                 InputOutputPairs {
                     token_in: 1799781506,
-                    token_out: 6479400819484
+                    token_out: 6479400819484,
+                    pool_splits: vec![],
                 },
                 InputOutputPairs {
                     token_in: 6479400819484,
-                    token_out: 130347150790
+                    token_out: 130347150790,
+                    pool_splits: vec![],
                 },
             ],
         );
         assert_eq!(arbs[0].marginal_price, 1010.9851646730779);
         assert_eq!(arbs[0].profit, 126247667211);
 
-        let path_output = mev
-            .mev_paths
-            .first()
-            .unwrap()
-            .get_path_calculation_output(&pool_states)
+        let path = mev.mev_paths.first().unwrap();
+        let path_output = path
+            .get_path_calculation_output(&path.resolve_hops(&pool_states).unwrap(), 0)
             .unwrap();
         assert_eq!(path_output.marginal_price, 1010.9851646730779);
-        assert_eq!(path_output.optimal_input, 4099483579.109189);
+        assert_eq!(path_output.optimal_input, 4099483579.1091895);
 
         pool_states
             .0
-            .get_mut(&Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG").unwrap())
+            .get_mut(&pubkey(STETH_USDC_POOL))
             .unwrap()
             .pool_a_balance = 461823;
         pool_states
             .0
-            .get_mut(&Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG").unwrap())
+            .get_mut(&pubkey(STETH_USDC_POOL))
             .unwrap()
             .pool_a_balance = 64005199;
         pool_states
             .0
-            .get_mut(&Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy").unwrap())
+            .get_mut(&pubkey(STSOL_STETH_POOL))
             .unwrap()
             .pool_a_balance = 5489662785068;
         pool_states
             .0
-            .get_mut(&Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy").unwrap())
+            .get_mut(&pubkey(STSOL_STETH_POOL))
             .unwrap()
             .pool_a_balance = 13408494240;
         pool_states
             .0
-            .get_mut(&Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL").unwrap())
+            .get_mut(&pubkey(STSOL_USDC_POOL))
             .unwrap()
             .pool_a_balance = 40088165867986;
         pool_states
             .0
-            .get_mut(&Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL").unwrap())
+            .get_mut(&pubkey(STSOL_USDC_POOL))
             .unwrap()
             .pool_a_balance = 1384360183450;
 
-        let path_output = mev
-            .mev_paths
-            .first()
-            .unwrap()
-            .get_path_calculation_output(&pool_states);
-        assert!(path_output.is_none());
-        let arbs = mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique());
+        let path = mev.mev_paths.first().unwrap();
+        let path_output =
+            path.get_path_calculation_output(&path.resolve_hops(&pool_states).unwrap(), 0);
+        assert!(matches!(
+            path_output,
+            Err(PathSkipReason::NotProfitable { .. })
+        ));
+        let arbs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique(), &Rent::default(), None);
         assert!(arbs.is_empty());
     }
 
+    #[test]
+    fn test_stable_pool_in_path() {
+        let (usdc, usdt, ust) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        // USDT is cheap in the first pool, and trades at par in the others.
+        let pool_states: PoolStates = [
+            pool(usdc).balances(100_000, 110_000),
+            pool(usdt).balances(100_000, 100_000).stable(100),
+            pool(ust).balances(100_000, 100_000),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let path = path("USDC->USDT->UST->USDC")
+            .hop(usdc, TradeDirection::AtoB)
+            .hop(usdt, TradeDirection::AtoB)
+            .hop(ust, TradeDirection::AtoB)
+            .build();
+        let hops = path.resolve_hops(&pool_states).unwrap();
+
+        let path_output = path.get_path_calculation_output(&hops, 0).unwrap();
+        assert!(path_output.marginal_price > 1_f64);
+        let optimal_input = path_output.optimal_input as u128;
+        let profit =
+            path.simulate_path(&hops, optimal_input).unwrap() as i128 - optimal_input as i128;
+
+        // The rounding of the pools makes the profit only nearly concave, so
+        // the search may end a little off the best input.
+        // Inputs that round to nothing in a hop fail, and are no candidates.
+        let (best_input, best_profit) = (1..=100_000_u128)
+            .filter_map(|amount_in| {
+                let amount_out = path.simulate_path(&hops, amount_in)?;
+                Some((amount_in, amount_out as i128 - amount_in as i128))
+            })
+            .max_by_key(|(_amount_in, profit)| *profit)
+            .unwrap();
+        assert!(
+            profit + 2 >= best_profit,
+            "Search found {} for input {}, brute force {} for input {}",
+            profit,
+            optimal_input,
+            best_profit,
+            best_input
+        );
+    }
+
+    #[test]
+    fn test_refine_optimal_input() {
+        let mut rng = ChaChaRng::seed_from_u64(0);
+        let pools = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let path = path("random")
+            .hop(pools[0], TradeDirection::AtoB)
+            .hop(pools[1], TradeDirection::AtoB)
+            .hop(pools[2], TradeDirection::AtoB)
+            .build();
+        let mut opportunities = 0;
+        for _ in 0..200 {
+            // Balances up to those of the stSOL/stETH pool, and prices that
+            // leave an opportunity on about half of the paths.
+            let pool_states: PoolStates = pools
+                .iter()
+                .map(|pool_address| {
+                    let balance_a = rng.gen_range(1_000_000, 60_000_000_000_000_u64);
+                    let price = rng.gen_range(0.95, 1.05);
+                    pool(*pool_address)
+                        .balances(balance_a, (balance_a as f64 * price) as u64)
+                        .build()
+                })
+                .collect();
+            let hops = path.resolve_hops(&pool_states).unwrap();
+            let path_output = match path.get_path_calculation_output(&hops, 0) {
+                Ok(path_output) => path_output,
+                Err(_) => continue,
+            };
+            opportunities += 1;
+
+            let floor = path_output.optimal_input.floor() as u128;
+            let refined = path.refine_optimal_input(&hops, path_output.optimal_input);
+            assert!(
+                path.simulated_profit(&hops, refined) >= path.simulated_profit(&hops, floor),
+                "Refined input {} has less profit than {}",
+                refined,
+                floor
+            );
+            assert!(path.simulate_path(&hops, refined).is_some());
+        }
+        assert!(opportunities > 0);
+    }
+
+    #[test]
+    fn test_drained_pools() {
+        let pools = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let path = path("A->B->A")
+            .hop(pools[0], TradeDirection::AtoB)
+            .hop(pools[1], TradeDirection::BtoA)
+            .build();
+        let pool_states = |balances: [(u64, u64); 2]| -> PoolStates {
+            pools
+                .iter()
+                .zip(balances)
+                .map(|(pool_address, (balance_a, balance_b))| {
+                    pool(*pool_address).balances(balance_a, balance_b).build()
+                })
+                .collect()
+        };
+        let optimal_input = |balances: [(u64, u64); 2], min_reserve: u64| {
+            let pool_states = pool_states(balances);
+            let hops = path.resolve_hops(&pool_states).unwrap();
+            path.get_path_calculation_output(&hops, min_reserve)
+                .map(|path_output| path_output.optimal_input)
+        };
+
+        // B is cheap in the first pool, so there is an opportunity.
+        let input = optimal_input([(1_000_000, 2_000_000), (1_000_000, 1_000_000)], 0).unwrap();
+        assert!(input.is_finite() && input > 0_f64);
+
+        // An empty reserve on either side of either pool.
+        for (balances, hop) in [
+            ([(0, 2_000_000), (1_000_000, 1_000_000)], 0),
+            ([(1_000_000, 0), (1_000_000, 1_000_000)], 0),
+            ([(1_000_000, 2_000_000), (0, 1_000_000)], 1),
+            ([(1_000_000, 2_000_000), (1_000_000, 0)], 1),
+        ] {
+            assert_eq!(
+                optimal_input(balances, 0),
+                Err(PathSkipReason::DrainedPool { hop }),
+                "{:?}",
+                balances
+            );
+        }
+
+        // One unit in each reserve is not drained by default, but there is
+        // nothing to trade, and below a `min_reserve` the pool is drained.
+        let balances = [(1_000_000, 2_000_000), (1, 1)];
+        let input = optimal_input(balances, 0).unwrap();
+        assert!(input.is_finite() && input < 1_f64);
+        assert_eq!(optimal_input_amount(input), 0);
+        assert_eq!(
+            optimal_input(balances, 1_000),
+            Err(PathSkipReason::DrainedPool { hop: 1 })
+        );
+
+        // Beyond 2^53, f64 does not represent the reserves exactly, but the
+        // optimal input is still sensible, and profitable with the integer
+        // math of the pools.
+        let balances = [(1 << 60, (1 << 61) + 1), ((1 << 60) + 1, 1 << 60)];
+        let input = optimal_input(balances, 0).unwrap();
+        assert!(input.is_finite() && input < (1_u64 << 60) as f64);
+        let amount_in = optimal_input_amount(input);
+        let pool_states = pool_states(balances);
+        let hops = path.resolve_hops(&pool_states).unwrap();
+        assert!(path.simulate_path(&hops, amount_in).unwrap() > amount_in);
+    }
+
+    /// The product of `factors` with exact integer math, as little-endian
+    /// `u32` limbs.
+    fn exact_product(factors: impl IntoIterator<Item = u64>) -> Vec<u32> {
+        let mut product = vec![1_u32];
+        for factor in factors {
+            let mut carry = 0_u128;
+            for limb in product.iter_mut() {
+                let value = *limb as u128 * factor as u128 + carry;
+                *limb = value as u32;
+                carry = value >> 32;
+            }
+            while carry > 0 {
+                product.push(carry as u32);
+                carry >>= 32;
+            }
+        }
+        product
+    }
+
+    #[test]
+    fn test_marginal_price_near_one() {
+        // Paths of four hops, with ratios across ten orders of magnitude, whose
+        // marginal price is within 1e-16 of 1, the first one above, the
+        // second one below.
+        let cases = [
+            (
+                [
+                    (2811180649, 26079646702282),
+                    (9026421533917, 6454347649),
+                    (847769684908357, 168307439048370),
+                    (7067009822327664, 5431001724181255),
+                ],
+                true,
+            ),
+            (
+                [
+                    (4755228983, 30476815818780),
+                    (9342390558888, 1527603371),
+                    (649518941875750, 640875822929367),
+                    (7312514611975819, 7157367248666412),
+                ],
+                false,
+            ),
+        ];
+        let fee = 1_f64 - Fees::from_trade_fees(25, 10_000, 5, 10_000).total_fee_fraction();
+        for (balances, is_profitable) in cases {
+            // The usual fees leave 997/1000 of the input, so the exact price is
+            // above 1 when the product of the outputs times 997 per hop is
+            // larger than the product of the inputs times 1000 per hop.
+            let outputs = exact_product(balances.iter().map(|(_from, to)| to * 997));
+            let inputs = exact_product(balances.iter().map(|(from, _to)| from * 1000));
+            let exact_order = outputs
+                .len()
+                .cmp(&inputs.len())
+                .then_with(|| outputs.iter().rev().cmp(inputs.iter().rev()));
+            assert_eq!(exact_order == std::cmp::Ordering::Greater, is_profitable);
+
+            // Multiplying the ratios and fees as f64s gets it the wrong way
+            // round.
+            let f64_price = balances.iter().fold(1_f64, |price, (from, to)| {
+                price * (*to as f64 / *from as f64) * fee
+            });
+            assert_eq!(f64_price > 1_f64, !is_profitable);
+
+            let pools: Vec<Pubkey> = balances.iter().map(|_| Pubkey::new_unique()).collect();
+            let pool_states: PoolStates = pools
+                .iter()
+                .zip(balances)
+                .map(|(address, (from, to))| pool(*address).balances(from, to).build())
+                .collect();
+            let mev_path = pools
+                .iter()
+                .fold(path("near one"), |mev_path, address| {
+                    mev_path.hop(*address, TradeDirection::AtoB)
+                })
+                .build();
+            let hops = mev_path.resolve_hops(&pool_states).unwrap();
+            match mev_path.get_path_calculation_output(&hops, 0) {
+                Ok(path_output) => {
+                    assert!(is_profitable);
+                    assert!(path_output.optimal_input.is_finite());
+                    assert!(path_output.optimal_input >= 0_f64);
+                }
+                Err(PathSkipReason::NotProfitable { .. }) => assert!(!is_profitable),
+                Err(reason) => panic!("Expected an output, got {:?}", reason),
+            }
+        }
+    }
+
+    #[test]
+    fn test_path_skip_reasons() {
+        let pools = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let path = path("A->B->A")
+            .hop(pools[0], TradeDirection::AtoB)
+            .hop(pools[1], TradeDirection::BtoA)
+            .build();
+        let skip_reason = |pool_builders: [PoolBuilder; 2]| {
+            let pool_states: PoolStates =
+                pool_builders.into_iter().map(PoolBuilder::build).collect();
+            let hops = path.resolve_hops(&pool_states).unwrap();
+            path.get_path_calculation_output(&hops, 0).err()
+        };
+
+        // The same price in both pools, the fees eat any profit.
+        let reason = skip_reason([
+            pool(pools[0]).balances(1_000_000, 1_000_000),
+            pool(pools[1]).balances(1_000_000, 1_000_000),
+        ]);
+        match reason {
+            Some(PathSkipReason::NotProfitable { marginal_price }) => {
+                assert!(marginal_price < 1_f64)
+            }
+            reason => panic!("Expected not_profitable, got {:?}", reason),
+        }
+
+        let reason = skip_reason([
+            pool(pools[0]).balances(1_000_000, 2_000_000),
+            pool(pools[1])
+                .balances(1_000_000, 1_000_000)
+                .fees(Fees::from_trade_fees(25, 0, 5, 10_000)),
+        ]);
+        assert_eq!(reason, Some(PathSkipReason::InvalidFees { hop: 1 }));
+
+        let reason = skip_reason([
+            pool(pools[0]).balances(1_000_000, 2_000_000),
+            pool(pools[1]).balances(1_000_000, 0),
+        ]);
+        assert_eq!(reason, Some(PathSkipReason::DrainedPool { hop: 1 }));
+
+        let empty_path = MevPath {
+            path: vec![],
+            ..path.clone()
+        };
+        assert_eq!(
+            empty_path.get_path_calculation_output(&[], 0).err(),
+            Some(PathSkipReason::NoHops)
+        );
+
+        assert_eq!(
+            serde_json::to_string(&PathSkipReason::NotProfitable {
+                marginal_price: 0.98
+            })
+            .unwrap(),
+            r#"{"reason":"not_profitable","marginal_price":0.98}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&PathSkipReason::NoHops).unwrap(),
+            r#"{"reason":"no_hops"}"#
+        );
+    }
+
+    #[test]
+    fn test_transfer_fees() {
+        let pools = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let path = path("A->B->A")
+            .hop(pools[0], TradeDirection::AtoB)
+            .hop(pools[1], TradeDirection::BtoA)
+            .build();
+        // B is 2% cheap in the first pool, and token B takes a transfer fee
+        // of `basis_points`, on the way out of the first pool and on the way
+        // into the second.
+        let pool_states = |basis_points: u16| -> PoolStates {
+            let mint_b = (basis_points > 0).then(|| transfer_fee_mint_info(basis_points));
+            [
+                pool(pools[0]).balances(1_000_000, 1_020_000),
+                pool(pools[1]).balances(1_000_000, 1_000_000),
+            ]
+            .into_iter()
+            .map(|pool_builder| pool_builder.mint_infos(None, mint_b).build())
+            .collect()
+        };
+
+        let without_fees = pool_states(0);
+        let hops = path.resolve_hops(&without_fees).unwrap();
+        let output = path.get_path_calculation_output(&hops, 0).unwrap();
+        let amount_in = optimal_input_amount(output.optimal_input);
+        assert!(path.simulate_path(&hops, amount_in).unwrap() > amount_in);
+
+        // Twice 1% of the trade is more than the opportunity.
+        let with_fees = pool_states(100);
+        let hops = path.resolve_hops(&with_fees).unwrap();
+        match path.get_path_calculation_output(&hops, 0) {
+            Err(PathSkipReason::NotProfitable { marginal_price }) => {
+                assert!(marginal_price < 1_f64)
+            }
+            output => panic!("Expected not_profitable, got {:?}", output),
+        }
+        assert!(path.simulate_path(&hops, amount_in).unwrap() < amount_in);
+
+        // A smaller fee leaves an opportunity, and the optimal input that
+        // accounts for it is profitable with the integer math of the pools.
+        let with_fees = pool_states(10);
+        let hops = path.resolve_hops(&with_fees).unwrap();
+        let output = path.get_path_calculation_output(&hops, 0).unwrap();
+        let amount_in_with_fees = optimal_input_amount(output.optimal_input);
+        assert!(amount_in_with_fees < amount_in);
+        assert!(path.simulate_path(&hops, amount_in_with_fees).unwrap() > amount_in_with_fees);
+    }
+
+    #[test]
+    fn test_optimal_input_amount() {
+        assert_eq!(optimal_input_amount(2.7), 2);
+        assert_eq!(optimal_input_amount(-1_f64), 0);
+        assert_eq!(optimal_input_amount(f64::NAN), 0);
+        assert_eq!(optimal_input_amount(1e30), u64::MAX as u128);
+        assert_eq!(optimal_input_amount(f64::INFINITY), u64::MAX as u128);
+        assert_eq!(optimal_input_amount(u64::MAX as f64), u64::MAX as u128);
+    }
+
     #[test]
     fn test_serialize() {
         let path = MevPath {
@@ -420,29 +2178,47 @@ mod tests {
                 PairInfo {
                     pool: Pubkey::from_str("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U")
                         .expect("Known SOL/USDC pool address"),
+                    alternative_pools: vec![],
+                    group: None,
                     direction: TradeDirection::AtoB,
                 },
                 PairInfo {
                     pool: Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG")
                         .expect("Known wstETH/USDC address"),
+                    alternative_pools: vec![],
+                    group: None,
                     direction: TradeDirection::BtoA,
                 },
                 PairInfo {
                     pool: Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy")
                         .expect("Known stSOL/wstETH address"),
+                    alternative_pools: vec![],
+                    group: None,
                     direction: TradeDirection::BtoA,
                 },
                 PairInfo {
                     pool: Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL")
                         .expect("Known stSOL/USDC address"),
+                    alternative_pools: vec![],
+                    group: None,
                     direction: TradeDirection::AtoB,
                 },
                 PairInfo {
                     pool: Pubkey::from_str("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U")
                         .expect("Known SOL/USDC pool address"),
+                    alternative_pools: vec![],
+                    group: None,
                     direction: TradeDirection::BtoA,
                 },
             ],
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
         };
         let expected_result = "{\
             'name':'SOL->USDC->wstETH->stSOL->stSOL->USDC->SOL',\
@@ -458,221 +2234,74 @@ mod tests {
 
     #[test]
     fn get_opportunity_with_empty_paths() {
-        let curve_calculator = Arc::new(ConstantProductCurve::default());
-        let pool_states = PoolStates(
-            vec![(
-                Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG").unwrap(),
-                OrcaPoolWithBalance {
-                    pool: OrcaPoolAddresses {
-                        program_id: Pubkey::from_str(
-                            "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                        )
-                        .unwrap(),
-                        address: Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG")
-                            .unwrap(),
-                        ..Default::default()
-                    },
-                    pool_a_balance: 4618233234,
-                    pool_b_balance: 6400518033,
-                    fees: Fees(spl_token_swap::curve::fees::Fees {
-                        trade_fee_numerator: 25,
-                        trade_fee_denominator: 10_000,
-                        owner_trade_fee_numerator: 5,
-                        owner_trade_fee_denominator: 10_000,
-                        owner_withdraw_fee_numerator: 0,
-                        owner_withdraw_fee_denominator: 1,
-                        host_fee_numerator: 0,
-                        host_fee_denominator: 1,
-                    }),
-                    curve_calculator,
-                    source_balance: None,
-                    destination_balance: None,
-                },
-            )]
-            .into_iter()
-            .collect(),
-        );
+        let pool_states: PoolStates = [pool(pubkey(STETH_USDC_POOL))
+            .balances(4618233234, 6400518033)
+            .build()]
+        .into_iter()
+        .collect();
         let mev_config = MevConfig {
             log_path: PathBuf::from(NamedTempFile::new().unwrap().path().to_str().unwrap()),
             watched_programs: vec![],
-            orca_accounts: AllOrcaPoolAddresses(vec![]),
+            orca_accounts: AllOrcaPoolAddresses::default(),
             mev_paths: vec![],
             user_authority_path: None,
             minimum_profit: HashMap::new(),
+            ..Default::default()
         };
-        let mev_log = MevLog::new(&mev_config);
+        let mev_log = MevLog::new(&mev_config, None);
         let mev = Mev::new(mev_log.log_send_channel.clone(), mev_config);
-        let arbs = mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique());
+        let arbs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique(), &Rent::default(), None);
         assert!(arbs.is_empty());
     }
 
     #[test]
     fn get_opportunity_exists_when_other_does_not() {
-        let curve_calculator = Arc::new(ConstantProductCurve::default());
-        let pool_states = PoolStates(
-            vec![
-                (
-                    Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG").unwrap(),
-                    OrcaPoolWithBalance {
-                        pool: OrcaPoolAddresses {
-                            program_id: Pubkey::from_str(
-                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                            )
-                            .unwrap(),
-                            address: Pubkey::from_str(
-                                "v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG",
-                            )
-                            .unwrap(),
-                            ..Default::default()
-                        },
-                        pool_a_balance: 4618233234,
-                        pool_b_balance: 6400518033,
-                        fees: Fees(spl_token_swap::curve::fees::Fees {
-                            trade_fee_numerator: 25,
-                            trade_fee_denominator: 10_000,
-                            owner_trade_fee_numerator: 5,
-                            owner_trade_fee_denominator: 10_000,
-                            owner_withdraw_fee_numerator: 0,
-                            owner_withdraw_fee_denominator: 1,
-                            host_fee_numerator: 0,
-                            host_fee_denominator: 1,
-                        }),
-                        curve_calculator: curve_calculator.clone(),
-                        source_balance: None,
-                        destination_balance: None,
-                    },
-                ),
-                (
-                    Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy").unwrap(),
-                    OrcaPoolWithBalance {
-                        pool: OrcaPoolAddresses {
-                            program_id: Pubkey::from_str(
-                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                            )
-                            .unwrap(),
-                            address: Pubkey::from_str(
-                                "B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy",
-                            )
-                            .unwrap(),
-                            ..Default::default()
-                        },
-                        pool_a_balance: 54896627850684,
-                        pool_b_balance: 13408494240,
-                        fees: Fees(spl_token_swap::curve::fees::Fees {
-                            trade_fee_numerator: 25,
-                            trade_fee_denominator: 10_000,
-                            owner_trade_fee_numerator: 5,
-                            owner_trade_fee_denominator: 10_000,
-                            owner_withdraw_fee_numerator: 0,
-                            owner_withdraw_fee_denominator: 1,
-                            host_fee_numerator: 0,
-                            host_fee_denominator: 1,
-                        }),
-                        curve_calculator: curve_calculator.clone(),
-                        source_balance: None,
-                        destination_balance: None,
-                    },
-                ),
-                (
-                    Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL").unwrap(),
-                    OrcaPoolWithBalance {
-                        pool: OrcaPoolAddresses {
-                            program_id: Pubkey::from_str(
-                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
-                            )
-                            .unwrap(),
-                            address: Pubkey::from_str(
-                                "EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL",
-                            )
-                            .unwrap(),
-                            ..Default::default()
-                        },
-                        pool_a_balance: 400881658679,
-                        pool_b_balance: 138436018345,
-                        fees: Fees(spl_token_swap::curve::fees::Fees {
-                            trade_fee_numerator: 25,
-                            trade_fee_denominator: 10_000,
-                            owner_trade_fee_numerator: 5,
-                            owner_trade_fee_denominator: 10_000,
-                            owner_withdraw_fee_numerator: 0,
-                            owner_withdraw_fee_denominator: 1,
-                            host_fee_numerator: 0,
-                            host_fee_denominator: 1,
-                        }),
-                        curve_calculator,
-                        source_balance: None,
-                        destination_balance: None,
-                    },
-                ),
-            ]
-            .into_iter()
-            .collect(),
-        );
+        let pool_states = steth_stsol_usdc_pool_states();
         let paths = vec![
-            MevPath {
-                name: "stETH->USDC->wstETH->stSOL->stSOL->USDC".to_owned(),
-                path: vec![
-                    PairInfo {
-                        pool: Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG")
-                            .expect("wstETH/USDC"),
-                        direction: TradeDirection::BtoA,
-                    },
-                    PairInfo {
-                        pool: Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy")
-                            .expect("stSOL/wstETH"),
-                        direction: TradeDirection::BtoA,
-                    },
-                    PairInfo {
-                        pool: Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL")
-                            .expect("stSOL/USDC"),
-                        direction: TradeDirection::AtoB,
-                    },
-                ],
-            },
-            MevPath {
-                name: "stSOL->USDC".to_owned(),
-                path: vec![
-                    PairInfo {
-                        pool: Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL")
-                            .expect("stSOL/USDC"),
-                        direction: TradeDirection::AtoB,
-                    },
-                    PairInfo {
-                        pool: Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL")
-                            .expect("stSOL/USDC"),
-                        direction: TradeDirection::BtoA,
-                    },
-                ],
-            },
+            path("stETH->USDC->wstETH->stSOL->stSOL->USDC")
+                .hop(pubkey(STETH_USDC_POOL), TradeDirection::BtoA)
+                .hop(pubkey(STSOL_STETH_POOL), TradeDirection::BtoA)
+                .hop(pubkey(STSOL_USDC_POOL), TradeDirection::AtoB)
+                .build(),
+            path("stSOL->USDC")
+                .hop(pubkey(STSOL_USDC_POOL), TradeDirection::AtoB)
+                .hop(pubkey(STSOL_USDC_POOL), TradeDirection::BtoA)
+                .build(),
         ];
 
         let mev_config = MevConfig {
             log_path: PathBuf::from(NamedTempFile::new().unwrap().path().to_str().unwrap()),
             watched_programs: vec![],
-            orca_accounts: AllOrcaPoolAddresses(vec![]),
+            orca_accounts: orca_accounts(&pool_states),
             mev_paths: paths,
             user_authority_path: None,
             minimum_profit: HashMap::new(),
+            ..Default::default()
         };
-        let mev_log = MevLog::new(&mev_config);
+        let mev_log = MevLog::new(&mev_config, None);
         let mev = Mev::new(mev_log.log_send_channel.clone(), mev_config);
 
-        let arbs = mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique());
+        let arbs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique(), &Rent::default(), None);
         assert_eq!(arbs[0].path_idx, 0);
         assert_eq!(
             arbs[0].input_output_pairs,
             vec![
                 InputOutputPairs {
                     token_in: 4099483579,
-                    token_out: 1799781506
+                    token_out: 1799781506,
+                    pool_splits: vec![],
                 },
                 InputOutputPairs {
                     token_in: 1799781506,
-                    token_out: 6479400819484
+                    token_out: 6479400819484,
+                    pool_splits: vec![],
                 },
                 InputOutputPairs {
                     token_in: 6479400819484,
-                    token_out: 130347150790
+                    token_out: 130347150790,
+                    pool_splits: vec![],
                 }
             ]
         );
@@ -683,31 +2312,443 @@ mod tests {
     #[test]
     #[should_panic]
     fn path_with_one_pool_with_same_direction_should_panic() {
-        let paths = vec![MevPath {
-            name: "stETH->USDC->wstETH".to_owned(),
-            path: vec![
-                PairInfo {
-                    pool: Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG")
-                        .expect("wstETH/USDC"),
-                    direction: TradeDirection::BtoA,
-                },
-                PairInfo {
-                    pool: Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG")
-                        .expect("wstETH/USDC"),
-                    direction: TradeDirection::BtoA,
-                },
-            ],
-        }];
+        let paths = vec![path("stETH->USDC->wstETH")
+            .hop(pubkey(STETH_USDC_POOL), TradeDirection::BtoA)
+            .hop(pubkey(STETH_USDC_POOL), TradeDirection::BtoA)
+            .build()];
 
         let mev_config = MevConfig {
             log_path: PathBuf::from(NamedTempFile::new().unwrap().path().to_str().unwrap()),
             watched_programs: vec![],
-            orca_accounts: AllOrcaPoolAddresses(vec![]),
+            orca_accounts: AllOrcaPoolAddresses::default(),
             mev_paths: paths,
             user_authority_path: None,
             minimum_profit: HashMap::new(),
+            ..Default::default()
         };
-        let mev_log = MevLog::new(&mev_config);
+        let mev_log = MevLog::new(&mev_config, None);
         let _mev = Mev::new(mev_log.log_send_channel.clone(), mev_config);
     }
+
+    #[test]
+    fn test_select_deepest_candidate_pool() {
+        // The same stSOL/stETH pair is available in a deep and in a shallow
+        // pool at the same price, the deep one gives the better output.
+        let pool_states: PoolStates = [
+            pool(pubkey(STETH_USDC_POOL)).balances(4618233234, 6400518033),
+            pool(pubkey(STSOL_STETH_POOL)).balances(54896627850684, 13408494240),
+            pool(pubkey("71zvJycCiY2JRRwKr27oiu48mFzrstCoP6riGEyCyEB2"))
+                .balances(27448313925342, 6704247120),
+            pool(pubkey(STSOL_USDC_POOL)).balances(400881658679, 138436018345),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+
+        let path: MevPath = toml::from_str(
+            r#"
+            name = "USDC->stETH->stSOL->USDC"
+            path = [
+                { pool = "v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG", direction = "BtoA" },
+                { pool = [
+                    "71zvJycCiY2JRRwKr27oiu48mFzrstCoP6riGEyCyEB2",
+                    "B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy",
+                ], direction = "BtoA" },
+                { pool = "EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL", direction = "AtoB" },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            path.path[1].alternative_pools,
+            vec![Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy").unwrap()],
+        );
+        assert!(path.has_alternative_pools());
+
+        let mev_config = MevConfig {
+            log_path: PathBuf::from(NamedTempFile::new().unwrap().path().to_str().unwrap()),
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths: vec![path],
+            ..Default::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let mev = Mev::new(mev_log.log_send_channel, mev_config);
+        let arbs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique(), &Rent::default(), None);
+        assert_eq!(
+            arbs[0].selected_pools,
+            vec![
+                Pubkey::from_str("v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG").unwrap(),
+                Pubkey::from_str("B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy").unwrap(),
+                Pubkey::from_str("EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL").unwrap(),
+            ],
+        );
+
+        let duplicate: Result<MevPath, _> = toml::from_str(
+            r#"
+            name = "duplicate"
+            path = [
+                { pool = [
+                    "B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy",
+                    "B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy",
+                ], direction = "BtoA" },
+            ]
+            "#,
+        );
+        assert!(duplicate.is_err());
+    }
+
+    #[test]
+    fn test_detect_competitor_arbitrage() {
+        let pre_pool_states = steth_stsol_usdc_pool_states();
+        // Someone else took the opportunity from `test_get_arbitrage`, with
+        // the same amounts as we would have.
+        let post_pool_states: PoolStates = [
+            pool(pubkey(STETH_USDC_POOL))
+                .balances(4618233234 - 1799781506, 6400518033 + 4099483579),
+            pool(pubkey(STSOL_STETH_POOL))
+                .balances(54896627850684 - 6479400819484, 13408494240 + 1799781506),
+            pool(pubkey(STSOL_USDC_POOL))
+                .balances(400881658679 + 6479400819484, 138436018345 - 130347150790),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let path = steth_stsol_usdc_path();
+        assert_eq!(
+            path.get_cycle_amounts(&pre_pool_states, &post_pool_states),
+            Some((4099483579, 130347150790))
+        );
+        // Trading the other way around is not this path.
+        assert_eq!(
+            path.get_cycle_amounts(&post_pool_states, &pre_pool_states),
+            None
+        );
+
+        let mev_config = MevConfig {
+            log_path: PathBuf::from(NamedTempFile::new().unwrap().path().to_str().unwrap()),
+            orca_accounts: orca_accounts(&pre_pool_states),
+            mev_paths: vec![path],
+            ..Default::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let mev = Mev::new(mev_log.log_send_channel, mev_config);
+        let signature = Signature::new_unique();
+        let expected = CompetitorArbitrage {
+            transaction_signature: signature,
+            slot: 7,
+            path_idx: 0,
+            input_mint: Pubkey::default(),
+            estimated_profit: 126247667211,
+            evaluated_path: false,
+        };
+        assert_eq!(
+            mev.detect_competitor_arbitrages(signature, 7, &pre_pool_states, &post_pool_states),
+            vec![expected],
+        );
+
+        // Once we found an opportunity on the path in the same slot, we
+        // should know that we missed it.
+        mev.evaluated_paths.lock().unwrap().insert(7, 0);
+        let arbitrages =
+            mev.detect_competitor_arbitrages(signature, 7, &pre_pool_states, &post_pool_states);
+        assert!(arbitrages[0].evaluated_path);
+        let arbitrages =
+            mev.detect_competitor_arbitrages(signature, 8, &pre_pool_states, &post_pool_states);
+        assert!(!arbitrages[0].evaluated_path);
+    }
+
+    /// A cycle through `num_hops` pools, where every hop takes our token
+    /// account that the previous hop output to.
+    fn cycle(num_hops: usize) -> (MevPath, AllOrcaPoolAddresses) {
+        let our_accounts: Vec<Pubkey> = (0..num_hops).map(|_| Pubkey::new_unique()).collect();
+        let pools: Vec<OrcaPoolAddresses> = (0..num_hops)
+            .map(|i| OrcaPoolAddresses {
+                address: Pubkey::new_unique(),
+                pool_a_account: Pubkey::new_unique(),
+                pool_b_account: Pubkey::new_unique(),
+                source: Some(our_accounts[i]),
+                destination: Some(our_accounts[(i + 1) % num_hops]),
+                pool_mint: Pubkey::new_unique(),
+                pool_fee: Pubkey::new_unique(),
+                ..Default::default()
+            })
+            .collect();
+        let path = MevPath {
+            name: format!("{}-hop cycle", num_hops),
+            path: pools
+                .iter()
+                .map(|pool| PairInfo {
+                    pool: pool.address,
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: TradeDirection::AtoB,
+                })
+                .collect(),
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        (path, AllOrcaPoolAddresses::new(pools))
+    }
+
+    #[test]
+    fn test_estimate_tx_size() {
+        // Three hops fit, even when no accounts are shared.
+        let (path, pools) = cycle(3);
+        let estimate = path.estimate_tx_size(&pools);
+        assert_eq!(estimate.account_count, 4 + 3 * 7);
+        assert!(!estimate.exceeds_limits());
+        assert!(!path.max_tx_size().exceeds_limits());
+        assert!(path.max_tx_size().account_count > estimate.account_count);
+
+        // Check the estimate against a transaction that we actually craft.
+        let user_authority = Keypair::new();
+        let program_id = Pubkey::new_unique();
+        let swap_args = pools
+            .iter()
+            .map(|pool| SwapArguments {
+                program_id,
+                swap_pubkey: pool.address,
+                authority_pubkey: Pubkey::new_unique(),
+                source_pubkey: pool.source.unwrap(),
+                swap_source_pubkey: pool.pool_a_account,
+                swap_destination_pubkey: pool.pool_b_account,
+                destination_pubkey: pool.destination.unwrap(),
+                pool_mint_pubkey: pool.pool_mint,
+                pool_fee_pubkey: pool.pool_fee,
+                host_fee_pubkey: None,
+                token_program: inline_spl_token::id(),
+                amount_in: 1,
+                minimum_amount_out: 1,
+            })
+            .collect::<Vec<_>>();
+        let swap_args_with_limit = swap_args.clone();
+        let tx = create_swap_tx(
+            swap_args,
+            Hash::new_unique(),
+            &user_authority,
+            Some(1),
+            None,
+        )
+        .unwrap();
+        assert_eq!(tx.message().account_keys().len(), estimate.account_count);
+        assert_eq!(
+            bincode::serialized_size(&tx.to_versioned_transaction()).unwrap() as usize,
+            estimate.serialized_size
+        );
+        assert_eq!(
+            get_swap_pools(&tx),
+            pools.iter().map(|pool| pool.address).collect::<Vec<_>>()
+        );
+
+        // A compute unit limit adds an instruction, but no account.
+        let tx = create_swap_tx(
+            swap_args_with_limit,
+            Hash::new_unique(),
+            &user_authority,
+            Some(1),
+            Some(300_000),
+        )
+        .unwrap();
+        assert_eq!(tx.message().account_keys().len(), estimate.account_count);
+        assert_eq!(
+            bincode::serialized_size(&tx.to_versioned_transaction()).unwrap() as usize,
+            estimate.with_compute_unit_limit().serialized_size
+        );
+
+        // Four hops do not fit in a packet anymore.
+        let (path, pools) = cycle(4);
+        let estimate = path.estimate_tx_size(&pools);
+        assert!(estimate.account_count < MAX_TX_ACCOUNT_LOCKS);
+        assert!(estimate.serialized_size > PACKET_DATA_SIZE);
+        assert!(estimate.exceeds_limits());
+
+        // Without the pool addresses, we cannot tell which accounts are shared.
+        assert_eq!(
+            path.estimate_tx_size(&AllOrcaPoolAddresses::default()),
+            path.max_tx_size()
+        );
+    }
+
+    #[test]
+    fn test_swap_instruction_host_fee() {
+        let user_authority = Pubkey::new_unique();
+        let without_host_fee = SwapArguments {
+            program_id: Pubkey::new_unique(),
+            swap_pubkey: Pubkey::new_unique(),
+            authority_pubkey: Pubkey::new_unique(),
+            source_pubkey: Pubkey::new_unique(),
+            swap_source_pubkey: Pubkey::new_unique(),
+            swap_destination_pubkey: Pubkey::new_unique(),
+            destination_pubkey: Pubkey::new_unique(),
+            pool_mint_pubkey: Pubkey::new_unique(),
+            pool_fee_pubkey: Pubkey::new_unique(),
+            host_fee_pubkey: None,
+            token_program: inline_spl_token::id(),
+            amount_in: 100,
+            minimum_amount_out: 110,
+        };
+        let host_fee = Pubkey::new_unique();
+        let with_host_fee = SwapArguments {
+            host_fee_pubkey: Some(host_fee),
+            ..without_host_fee.clone()
+        };
+        let instructions = swap_instructions(
+            &[without_host_fee.clone(), with_host_fee],
+            user_authority,
+            None,
+            None,
+        );
+
+        let accounts = &instructions[0].accounts;
+        assert_eq!(
+            accounts,
+            &vec![
+                AccountMeta::new_readonly(without_host_fee.swap_pubkey, false),
+                AccountMeta::new_readonly(without_host_fee.authority_pubkey, false),
+                AccountMeta::new_readonly(user_authority, true),
+                AccountMeta::new(without_host_fee.source_pubkey, false),
+                AccountMeta::new(without_host_fee.swap_source_pubkey, false),
+                AccountMeta::new(without_host_fee.swap_destination_pubkey, false),
+                AccountMeta::new(without_host_fee.destination_pubkey, false),
+                AccountMeta::new(without_host_fee.pool_mint_pubkey, false),
+                AccountMeta::new(without_host_fee.pool_fee_pubkey, false),
+                AccountMeta::new_readonly(inline_spl_token::id(), false),
+            ]
+        );
+        assert_eq!(instructions[0].program_id, without_host_fee.program_id);
+
+        // The host fee account comes last, and is written to.
+        let (host_fee_account, accounts_with_host_fee) =
+            instructions[1].accounts.split_last().unwrap();
+        assert_eq!(accounts_with_host_fee, &accounts[..]);
+        assert_eq!(host_fee_account, &AccountMeta::new(host_fee, false));
+        assert_eq!(instructions[1].data, instructions[0].data);
+
+        // The transaction size estimate counts the host fee accounts.
+        let (path, pools) = cycle(2);
+        let without_host_fee = path.estimate_tx_size(&pools);
+        let pools = AllOrcaPoolAddresses::new(
+            pools
+                .iter()
+                .map(|pool| OrcaPoolAddresses {
+                    host_fee: Some(Pubkey::new_unique()),
+                    ..pool.clone()
+                })
+                .collect(),
+        );
+        assert_eq!(
+            path.estimate_tx_size(&pools).account_count,
+            without_host_fee.account_count + 2
+        );
+    }
+
+    #[test]
+    fn test_check_mint_chain() {
+        let mints: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        // Pool `i` trades `mints[i]` for `mints[i + 1]`, when traded from A to B.
+        let pools: Vec<OrcaPoolAddresses> = (0..3)
+            .map(|i| OrcaPoolAddresses {
+                address: Pubkey::new_unique(),
+                pool_a_mint: mints[i],
+                pool_b_mint: mints[(i + 1) % 3],
+                ..Default::default()
+            })
+            .collect();
+        let hop = |pool: &OrcaPoolAddresses, direction| PairInfo {
+            pool: pool.address,
+            alternative_pools: vec![],
+            group: None,
+            direction,
+        };
+        let forward = MevPath {
+            name: "forward".to_owned(),
+            path: pools
+                .iter()
+                .map(|pool| hop(pool, TradeDirection::AtoB))
+                .collect(),
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let backward = MevPath {
+            name: "backward".to_owned(),
+            path: pools
+                .iter()
+                .rev()
+                .map(|pool| hop(pool, TradeDirection::BtoA))
+                .collect(),
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let broken = MevPath {
+            name: "broken".to_owned(),
+            path: vec![
+                hop(&pools[0], TradeDirection::AtoB),
+                hop(&pools[1], TradeDirection::BtoA),
+            ],
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let unknown = MevPath {
+            name: "unknown".to_owned(),
+            path: vec![
+                hop(&pools[0], TradeDirection::AtoB),
+                PairInfo {
+                    pool: Pubkey::new_unique(),
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: TradeDirection::AtoB,
+                },
+            ],
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let pools = AllOrcaPoolAddresses::new(pools);
+
+        assert_eq!(forward.check_mint_chain(&pools), Ok(()));
+        assert_eq!(backward.check_mint_chain(&pools), Ok(()));
+        assert_eq!(
+            broken.check_mint_chain(&pools),
+            Err(format!(
+                "hop 0 of MEV path broken outputs {}, but hop 1 takes {}",
+                mints[1], mints[2]
+            ))
+        );
+        // Without the mints of a hop, we cannot tell whether it chains.
+        assert_eq!(unknown.check_mint_chain(&pools), Ok(()));
+        assert_eq!(
+            forward.check_mint_chain(&AllOrcaPoolAddresses::default()),
+            Ok(())
+        );
+    }
 }
@@ -0,0 +1,267 @@
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use spl_token_swap::curve::calculator::{CurveCalculator, SwapWithoutFeesResult};
+
+use super::{arbitrage::TradeDirection, pool_state::Fees, utils::serialize_b58};
+
+/// The inputs to our decision to trade an opportunity, so that the decision
+/// can be reconstructed afterwards. We only build it with `audit_mode`, and
+/// only keep it for the opportunity that we select for a trigger.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DecisionTrace {
+    /// Input of the first hop that the path calculation found optimal,
+    /// before any cap.
+    pub optimal_input: f64,
+
+    /// Every hop with the pool that we selected for it.
+    pub hops: Vec<HopTrace>,
+
+    /// The caps that we applied, in order.
+    pub caps: Vec<CapTrace>,
+
+    /// The thresholds that the opportunity was compared against.
+    pub thresholds: Vec<ThresholdTrace>,
+
+    /// Costs that we deducted from the profit, in the input token.
+    pub deducted_costs: u64,
+
+    pub amount_in: u64,
+    pub profit: u64,
+
+    /// How the opportunity compared to the others for the same trigger.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<SelectionTrace>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HopTrace {
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool: Pubkey,
+
+    pub direction: TradeDirection,
+
+    /// Balances of the pool's token accounts on the `from` and `to` side of
+    /// the trade.
+    pub balance_from: u64,
+    pub balance_to: u64,
+
+    pub fees: Fees,
+    pub fee_fraction: f64,
+
+    pub amount_in: u64,
+    pub amount_out: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CapKind {
+    /// Our balance of the input token limits the input.
+    SourceBalance,
+
+    /// `max_input` of the path limits the input.
+    MaxInput,
+
+    /// `max_inflight_exposure` of the input token limits the total in flight.
+    MaxInflightExposure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CapTrace {
+    pub cap: CapKind,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u64>,
+
+    /// The capped value before and after we applied the cap.
+    pub before: u64,
+    pub after: u64,
+
+    /// Whether the opportunity got through the cap.
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdKind {
+    /// `minimum_profit` of the input token, compared with the profit.
+    MinimumProfit,
+
+    /// The input amount, compared with the output of the last hop.
+    OutputCoversInput,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ThresholdTrace {
+    pub threshold: ThresholdKind,
+    pub limit: u64,
+    pub value: u64,
+    pub passed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SelectionTrace {
+    /// The other opportunities for the same trigger, which lost to this one.
+    pub competing: Vec<CompetingOpportunity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CompetingOpportunity {
+    pub path_idx: usize,
+    pub profit: u64,
+}
+
+impl DecisionTrace {
+    /// Evaluate the trade again from the trace alone, assuming that every pool
+    /// uses `curve_calculator`. Returns (`amount_in`, `profit`), which should
+    /// equal the ones in the trace.
+    pub fn replay(&self, curve_calculator: &dyn CurveCalculator) -> Option<(u64, u64)> {
+        let amount_in = self
+            .caps
+            .iter()
+            .filter(|cap| matches!(cap.cap, CapKind::SourceBalance | CapKind::MaxInput))
+            .last()
+            .map_or(self.optimal_input.floor() as u64, |cap| cap.after);
+        let mut amount = amount_in as u128;
+        for hop in &self.hops {
+            let trade_direction = match hop.direction {
+                TradeDirection::AtoB => spl_token_swap::curve::calculator::TradeDirection::AtoB,
+                TradeDirection::BtoA => spl_token_swap::curve::calculator::TradeDirection::BtoA,
+            };
+            let SwapWithoutFeesResult {
+                source_amount_swapped: _,
+                destination_amount_swapped,
+            } = curve_calculator.swap_without_fees(
+                hop.fees.apply_fees(amount)?,
+                hop.balance_from as u128,
+                hop.balance_to as u128,
+                trade_direction,
+            )?;
+            amount = destination_amount_swapped;
+        }
+        let profit =
+            (amount.saturating_sub(amount_in as u128) as u64).saturating_sub(self.deducted_costs);
+        Some((amount_in, profit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+    use solana_sdk::{hash::Hash, rent::Rent};
+    use spl_token_swap::curve::{base::SwapCurve, constant_product::ConstantProductCurve};
+
+    use super::*;
+    use crate::mev::{
+        arbitrage::{MevPath, PairInfo},
+        config::MevConfig,
+        test_utils::orca_accounts,
+        Mev, OrcaPoolWithBalance, PoolStates,
+    };
+
+    #[test]
+    fn test_replay_decision_trace() {
+        // Pool 1 gives two B for an A, pool 2 gives an A for a B, so trading
+        // A -> B -> A through them is profitable.
+        let (pool_1, pool_2) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            (pool_1, 1_000_000_000, 2_000_000_000),
+            (pool_2, 1_000_000_000, 1_000_000_000),
+        ]
+        .into_iter()
+        .map(|(address, pool_a_balance, pool_b_balance)| {
+            let mut pool_state = OrcaPoolWithBalance::new(
+                Pubkey::default(),
+                Pubkey::default(),
+                pool_a_balance,
+                pool_b_balance,
+                Fees::from_trade_fees(25, 10_000, 5, 10_000),
+                SwapCurve::default(),
+            );
+            pool_state.pool.address = address;
+            pool_state.source_balance = Some(50_000_000);
+            (address, pool_state)
+        })
+        .collect();
+        let path = MevPath {
+            name: "A->B->A".to_owned(),
+            path: vec![
+                PairInfo {
+                    pool: pool_1,
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: TradeDirection::AtoB,
+                },
+                PairInfo {
+                    pool: pool_2,
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: TradeDirection::BtoA,
+                },
+            ],
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let mev_config = MevConfig {
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths: vec![path.clone(), path],
+            audit_mode: true,
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+
+        let mut outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::new_unique(), &Rent::default(), None);
+        assert_eq!(outputs.len(), 2);
+        for output in &outputs {
+            let trace = output.decision_trace.as_ref().unwrap();
+            assert_eq!(
+                trace.replay(&ConstantProductCurve::default()),
+                Some((output.input_output_pairs[0].token_in, output.profit))
+            );
+            // Our balance is less than the optimal input.
+            assert_eq!(
+                trace.caps,
+                vec![CapTrace {
+                    cap: CapKind::SourceBalance,
+                    limit: Some(50_000_000),
+                    before: trace.optimal_input.floor() as u64,
+                    after: 50_000_000,
+                    passed: true,
+                }]
+            );
+            assert_eq!(trace.hops.len(), 2);
+            assert_eq!(trace.hops[0].balance_from, 1_000_000_000);
+            assert_eq!(trace.hops[0].balance_to, 2_000_000_000);
+            assert_eq!(
+                trace.hops[1].amount_out,
+                output.input_output_pairs[1].token_out
+            );
+        }
+
+        // Without a user authority there is no transaction to take, but only
+        // the selected opportunity keeps its trace.
+        assert!(mev.take_max_profit_tx(&mut outputs, 1).is_empty());
+        assert!(outputs[0].decision_trace.is_none());
+        let trace = outputs[1].decision_trace.as_ref().unwrap();
+        assert_eq!(
+            trace.selection,
+            Some(SelectionTrace {
+                competing: vec![CompetingOpportunity {
+                    path_idx: 0,
+                    profit: outputs[0].profit,
+                }],
+            })
+        );
+        assert_eq!(
+            trace.replay(&ConstantProductCurve::default()),
+            Some((outputs[1].input_output_pairs[0].token_in, outputs[1].profit))
+        );
+    }
+}
@@ -0,0 +1,170 @@
+//! The `[[user_authority]]` entries of the config: user authorities next to
+//! the one from `user_authority_path` or `signer`, each of which owns the
+//! token accounts of some of the pools, and signs and pays for the
+//! transactions through them.
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use solana_sdk::pubkey::Pubkey;
+
+use super::{
+    arbitrage::MevPath,
+    config::{load_user_authority, MevConfig, TokenRef},
+    signer::{MevSigner, SignerConfig},
+    utils::B58Pubkey,
+};
+
+/// A user authority for some of the pools, in the config.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+pub struct UserAuthorityConfig {
+    /// Path to the keypair, like `user_authority_path`.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Sign with another signer than a keypair, like `signer`. Only one of
+    /// `path` and `signer` may be set.
+    #[serde(default)]
+    pub signer: Option<SignerConfig>,
+
+    /// Pools whose `source` and `destination` this authority owns.
+    #[serde(default)]
+    pub pools: Vec<B58Pubkey>,
+
+    /// Mints, or symbols from `tokens`, whose pools this authority owns the
+    /// token accounts of, for the pools that no entry lists in `pools`. Needs
+    /// the mints of the pools in the config.
+    #[serde(default)]
+    pub mints: Vec<TokenRef>,
+}
+
+/// The user authorities from `[[user_authority]]`, and the pools that they
+/// sign for. Pools that none of them claims are signed for by the default
+/// user authority, from `user_authority_path` or `signer`.
+#[derive(Debug, Clone, Default)]
+pub struct PoolAuthorities {
+    signers: Vec<Arc<dyn MevSigner>>,
+
+    // Index into `signers`.
+    by_pool: HashMap<Pubkey, usize>,
+}
+
+impl PoolAuthorities {
+    pub fn load(config: &MevConfig) -> Result<Self, String> {
+        let mut signers = Vec::with_capacity(config.user_authorities.len());
+        for (idx, entry) in config.user_authorities.iter().enumerate() {
+            let signer = load_user_authority(entry.path.clone(), entry.signer.clone())
+                .map_err(|err| format!("user_authority {}: {}", idx, err))?
+                .ok_or_else(|| format!("user_authority {}: set either path or signer", idx))?;
+            signers.push(signer);
+        }
+
+        let mut by_pool = HashMap::new();
+        for (idx, entry) in config.user_authorities.iter().enumerate() {
+            for pool in &entry.pools {
+                if let Some(other) = by_pool.insert(pool.0, idx) {
+                    if other != idx {
+                        return Err(format!(
+                            "Pool {} is listed by user_authority {} and {}",
+                            pool.0, other, idx
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut by_mint = HashMap::new();
+        for (idx, entry) in config.user_authorities.iter().enumerate() {
+            for token in &entry.mints {
+                let mint = config.resolve_token(&format!("user_authority {}", idx), token)?;
+                if let Some(other) = by_mint.insert(mint, idx) {
+                    if other != idx {
+                        return Err(format!(
+                            "Mint {} is listed by user_authority {} and {}",
+                            mint, other, idx
+                        ));
+                    }
+                }
+            }
+        }
+        if !by_mint.is_empty() {
+            for pool in config.orca_accounts.unique() {
+                if by_pool.contains_key(&pool.address) {
+                    continue;
+                }
+                let (mint_a, mint_b) = match pool.known_mints() {
+                    Some(mints) => mints,
+                    None => continue,
+                };
+                match (by_mint.get(&mint_a), by_mint.get(&mint_b)) {
+                    (Some(idx_a), Some(idx_b)) if idx_a != idx_b => {
+                        return Err(format!(
+                            "Pool {} trades mint {} of user_authority {} and mint {} of \
+                            user_authority {}, list it in the pools of one of them",
+                            pool.address, mint_a, idx_a, mint_b, idx_b
+                        ));
+                    }
+                    (Some(idx), _) | (None, Some(idx)) => {
+                        by_pool.insert(pool.address, *idx);
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+
+        Ok(PoolAuthorities { signers, by_pool })
+    }
+
+    pub fn signers(&self) -> &[Arc<dyn MevSigner>] {
+        &self.signers
+    }
+
+    /// The public keys of the authorities of the pools that are not signed for
+    /// by the default user authority.
+    pub fn pubkeys_by_pool(&self) -> HashMap<Pubkey, Pubkey> {
+        self.by_pool
+            .iter()
+            .map(|(pool, idx)| (*pool, self.signers[*idx].pubkey()))
+            .collect()
+    }
+
+    /// The authority that signs for `pool`, when it is not the default one.
+    pub fn for_pool(&self, pool: &Pubkey) -> Option<&Arc<dyn MevSigner>> {
+        self.by_pool.get(pool).map(|idx| &self.signers[*idx])
+    }
+
+    /// The authority that signs for all pools of `path`, including the
+    /// alternative pools, when it is not the default one. A path whose pools
+    /// have different authorities cannot be signed for, and is an error.
+    pub fn for_path(&self, path: &MevPath) -> Result<Option<&Arc<dyn MevSigner>>, String> {
+        let describe = |idx: Option<usize>| match idx {
+            Some(idx) => format!("user_authority {}", idx),
+            None => "the default user authority".to_owned(),
+        };
+        let mut first: Option<(&Pubkey, Option<usize>)> = None;
+        for pool in path
+            .path
+            .iter()
+            .flat_map(|pair_info| pair_info.candidate_pools())
+        {
+            let idx = self.by_pool.get(pool).copied();
+            match first {
+                None => first = Some((pool, idx)),
+                Some((first_pool, first_idx)) if first_idx != idx => {
+                    return Err(format!(
+                        "MEV path {} mixes user authorities: pool {} is signed for by {}, \
+                        and pool {} by {}",
+                        path.name,
+                        first_pool,
+                        describe(first_idx),
+                        pool,
+                        describe(idx)
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(first
+            .and_then(|(_pool, idx)| idx)
+            .map(|idx| &self.signers[idx]))
+    }
+}
@@ -0,0 +1,251 @@
+//! Checkpoints of the pool states in the MEV log, so that a searcher can start
+//! from a recent view of all pools, rather than replay the log, see
+//! `examples/standalone_searcher.rs`.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::clock::Slot;
+
+use super::{log_file::read_mev_log, logging::PrePostPoolStates, pool_state::PoolStates};
+
+/// The latest state of every pool that we observed, as we log it in a
+/// `checkpoint` event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStatesCheckpoint {
+    /// The slot at whose tick we wrote the checkpoint.
+    pub slot: Slot,
+
+    /// The slot of the latest trigger that the pool states reflect. In a
+    /// quiet period, this stays behind `slot`.
+    pub observed_slot: Slot,
+
+    /// By pool address, the base64 of a snapshot of that pool alone, see
+    /// `PoolStates::to_snapshot_bytes`.
+    pub pools: BTreeMap<String, String>,
+}
+
+/// A `PoolStatesCheckpoint` as we write it to the log.
+#[derive(Serialize)]
+struct CheckpointEvent<'a> {
+    event: &'static str,
+
+    #[serde(flatten)]
+    checkpoint: &'a PoolStatesCheckpoint,
+}
+
+impl PoolStatesCheckpoint {
+    pub fn new(slot: Slot, observed_slot: Slot, pool_states: &PoolStates) -> Self {
+        let pools = pool_states
+            .0
+            .iter()
+            .map(|(address, pool_state)| {
+                let pool = PoolStates([(*address, pool_state.clone())].into_iter().collect());
+                (
+                    address.to_string(),
+                    base64::encode(pool.to_snapshot_bytes()),
+                )
+            })
+            .collect();
+        PoolStatesCheckpoint {
+            slot,
+            observed_slot,
+            pools,
+        }
+    }
+
+    /// The pool states in the checkpoint, that the engine can evaluate as if
+    /// it loaded them from their accounts.
+    pub fn pool_states(&self) -> Result<PoolStates, String> {
+        let mut pool_states = PoolStates(Default::default());
+        for (address, snapshot) in &self.pools {
+            let bytes = base64::decode(snapshot)
+                .map_err(|err| format!("pool {} is not valid base64: {}", address, err))?;
+            let pool = PoolStates::from_snapshot_bytes(&bytes)
+                .map_err(|err| format!("pool {}: {}", address, err))?;
+            pool_states.0.extend(pool.0);
+        }
+        Ok(pool_states)
+    }
+
+    pub fn to_event(&self) -> String {
+        serde_json::to_string(&CheckpointEvent {
+            event: "checkpoint",
+            checkpoint: self,
+        })
+        .expect("Constructed by us, should never fail")
+    }
+}
+
+/// Keeps the latest state of every pool from the triggers that we log, and
+/// tells when the next checkpoint is due.
+#[derive(Debug)]
+pub struct Checkpointer {
+    interval_slots: Option<u64>,
+    pool_states: PoolStates,
+    observed_slot: Slot,
+    last_checkpoint_slot: Option<Slot>,
+}
+
+impl Checkpointer {
+    /// Write a checkpoint at most once every `interval_slots` slots, or never
+    /// when it is `None`.
+    pub fn new(interval_slots: Option<u64>) -> Self {
+        Checkpointer {
+            interval_slots,
+            pool_states: PoolStates(Default::default()),
+            observed_slot: 0,
+            last_checkpoint_slot: None,
+        }
+    }
+
+    /// Keep the pool states after the trigger. The writes of a failed trigger
+    /// are rolled back, so of those we keep the states before it.
+    pub fn record_trigger(&mut self, msg: &PrePostPoolStates) {
+        if self.interval_slots.is_none() {
+            return;
+        }
+        let pool_states = if msg.trigger_failed {
+            &msg.orca_pre_tx_pool
+        } else {
+            &msg.orca_post_tx_pool
+        };
+        for (address, pool_state) in &pool_states.0 {
+            self.pool_states.0.insert(*address, pool_state.clone());
+        }
+        self.observed_slot = self.observed_slot.max(msg.slot);
+    }
+
+    /// On the tick of `slot`, the checkpoint to write, if one is due. Once we
+    /// observed a pool, we write checkpoints also when no trigger came since
+    /// the last one.
+    pub fn tick(&mut self, slot: Slot) -> Option<PoolStatesCheckpoint> {
+        let interval_slots = self.interval_slots?;
+        if self.pool_states.0.is_empty() {
+            return None;
+        }
+        if let Some(last_checkpoint_slot) = self.last_checkpoint_slot {
+            if slot < last_checkpoint_slot.saturating_add(interval_slots) {
+                return None;
+            }
+        }
+        self.last_checkpoint_slot = Some(slot);
+        Some(PoolStatesCheckpoint::new(
+            slot,
+            self.observed_slot,
+            &self.pool_states,
+        ))
+    }
+}
+
+/// The last checkpoint in `log`, the lines of a MEV log. Lines that are not a
+/// checkpoint are skipped, a checkpoint that we cannot parse is an error.
+pub fn latest_checkpoint(log: &str) -> Result<Option<PoolStatesCheckpoint>, String> {
+    log.lines()
+        .rev()
+        .find(|line| line.starts_with("{\"event\":\"checkpoint\""))
+        .map(|line| {
+            serde_json::from_str(line).map_err(|err| format!("Invalid checkpoint: {}", err))
+        })
+        .transpose()
+}
+
+/// Read the last checkpoint from the MEV log at `path`, which may be
+/// compressed, see `read_mev_log`.
+pub fn read_latest_checkpoint(path: &Path) -> Result<Option<PoolStatesCheckpoint>, String> {
+    let log = read_mev_log(path)
+        .map_err(|err| format!("Could not read MEV log {}: {}", path.display(), err))?;
+    latest_checkpoint(&log).map_err(|err| format!("{} in {}", err, path.display()))
+}
+
+/// Read a checkpoint from the JSON file at `path`, e.g. a `checkpoint` event
+/// copied from a MEV log, or pool states for a simulation that we wrote
+/// ourselves, see `examples/mev_simulate.rs`.
+pub fn read_checkpoint_json(path: &Path) -> Result<PoolStatesCheckpoint, String> {
+    let json = fs::read_to_string(path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    serde_json::from_str(&json)
+        .map_err(|err| format!("Invalid checkpoint in {}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature};
+
+    use super::*;
+    use crate::mev::{
+        arbitrage::TriggerKind,
+        test_utils::{pool, steth_stsol_usdc_pool_states, PoolBuilder},
+    };
+
+    fn trigger(slot: Slot, pre: PoolStates, post: PoolStates) -> PrePostPoolStates {
+        PrePostPoolStates {
+            transaction_hash: Hash::new_unique(),
+            transaction_signature: Signature::new_unique(),
+            slot,
+            trigger_kind: TriggerKind::User,
+            stale_generation: None,
+            trigger_failed: false,
+            orca_pre_tx_pool: pre,
+            orca_post_tx_pool: post,
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let pool_states = steth_stsol_usdc_pool_states();
+        let checkpoint = PoolStatesCheckpoint::new(12, 10, &pool_states);
+        let event = checkpoint.to_event();
+        assert!(event.starts_with("{\"event\":\"checkpoint\",\"slot\":12,"));
+
+        let log = format!("{{\"event\":\"watchdog\",\"data\":{{}}}}\n{}\n", event);
+        let parsed = latest_checkpoint(&log).unwrap().unwrap();
+        assert_eq!(parsed, checkpoint);
+        let loaded = parsed.pool_states().unwrap();
+        assert_eq!(loaded.to_snapshot_bytes(), pool_states.to_snapshot_bytes());
+
+        assert_eq!(latest_checkpoint("{\"event\":\"watchdog\"}\n"), Ok(None));
+        assert!(latest_checkpoint("{\"event\":\"checkpoint\",\"slot\":1}\n").is_err());
+    }
+
+    #[test]
+    fn test_checkpoints_continue_in_quiet_periods() {
+        let pool_a = Pubkey::new_unique();
+        let states = |balance: u64| -> PoolStates {
+            [pool(pool_a).balances(balance, 1_000)]
+                .into_iter()
+                .map(PoolBuilder::build)
+                .collect()
+        };
+        let mut checkpointer = Checkpointer::new(Some(2));
+        // Nothing to write before we observed a pool.
+        assert_eq!(checkpointer.tick(1), None);
+
+        checkpointer.record_trigger(&trigger(1, states(1_000), states(1_500)));
+        let checkpoint = checkpointer.tick(2).unwrap();
+        assert_eq!((checkpoint.slot, checkpoint.observed_slot), (2, 1));
+        assert_eq!(
+            checkpoint.pool_states().unwrap().0[&pool_a].pool_a_balance,
+            1_500
+        );
+        assert_eq!(checkpointer.tick(3), None);
+
+        // A failed trigger did not change the pool.
+        let mut failed = trigger(3, states(1_500), states(2_000));
+        failed.trigger_failed = true;
+        checkpointer.record_trigger(&failed);
+
+        // No trigger in slot 4, we still write the states that we know.
+        let checkpoint = checkpointer.tick(4).unwrap();
+        assert_eq!((checkpoint.slot, checkpoint.observed_slot), (4, 3));
+        assert_eq!(
+            checkpoint.pool_states().unwrap().0[&pool_a].pool_a_balance,
+            1_500
+        );
+
+        // Without an interval, we never write checkpoints.
+        let mut checkpointer = Checkpointer::new(None);
+        checkpointer.record_trigger(&trigger(1, states(1_000), states(1_500)));
+        assert_eq!(checkpointer.tick(10), None);
+    }
+}
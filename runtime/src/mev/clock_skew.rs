@@ -0,0 +1,136 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+use solana_sdk::clock::{Slot, UnixTimestamp};
+
+/// How many slots we wait between two comparisons of the wall clock with the
+/// slot time, about a minute. The first comparison is at the first slot that
+/// we are told about.
+pub const CLOCK_SKEW_CHECK_INTERVAL_SLOTS: u64 = 150;
+
+/// Written to the log when our wall clock is further off from the bank's
+/// estimated slot time than the configured threshold. Latencies that mix wall
+/// clock and slot times are off by `skew_ms` then.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ClockSkewEvent {
+    pub slot: Slot,
+
+    /// Our wall clock, in milliseconds since the Unix epoch.
+    pub wall_clock_ms: i64,
+
+    /// The bank's estimated time of `slot`, in seconds since the Unix epoch.
+    pub slot_time: UnixTimestamp,
+
+    /// `wall_clock_ms` minus `slot_time`, in milliseconds. The slot time only
+    /// has a resolution of a second.
+    pub skew_ms: i64,
+
+    pub threshold_ms: u64,
+}
+
+/// Compares the wall clock of the host with the slot time, at the start of the
+/// session and then every `CLOCK_SKEW_CHECK_INTERVAL_SLOTS` slots.
+#[derive(Debug)]
+pub struct ClockSkewMonitor {
+    threshold_ms: Option<u64>,
+    last_check_slot: Option<Slot>,
+    last_skew_ms: Option<i64>,
+}
+
+impl ClockSkewMonitor {
+    /// Without `threshold_ms`, we still measure the skew, but never warn.
+    pub fn new(threshold_ms: Option<u64>) -> Self {
+        ClockSkewMonitor {
+            threshold_ms,
+            last_check_slot: None,
+            last_skew_ms: None,
+        }
+    }
+
+    /// Compare `wall_clock_ms` with the `slot_time` of `slot`, when a check is
+    /// due. Returns an event when the skew exceeds the threshold.
+    pub fn check(
+        &mut self,
+        slot: Slot,
+        slot_time: UnixTimestamp,
+        wall_clock_ms: i64,
+    ) -> Option<ClockSkewEvent> {
+        let is_due = self.last_check_slot.map_or(true, |last_check_slot| {
+            slot.saturating_sub(last_check_slot) >= CLOCK_SKEW_CHECK_INTERVAL_SLOTS
+        });
+        if !is_due {
+            return None;
+        }
+        self.last_check_slot = Some(slot);
+        let skew_ms = wall_clock_ms.saturating_sub(slot_time.saturating_mul(1000));
+        self.last_skew_ms = Some(skew_ms);
+        let threshold_ms = self.threshold_ms?;
+        if skew_ms.unsigned_abs() <= threshold_ms {
+            return None;
+        }
+        Some(ClockSkewEvent {
+            slot,
+            wall_clock_ms,
+            slot_time,
+            skew_ms,
+            threshold_ms,
+        })
+    }
+
+    /// The skew that we measured last, if any.
+    pub fn last_skew_ms(&self) -> Option<i64> {
+        self.last_skew_ms
+    }
+
+    pub fn report_metrics(&self) {
+        if let Some(skew_ms) = self.last_skew_ms {
+            datapoint_info!("mev-clock-skew", ("skew_ms", skew_ms, i64));
+        }
+    }
+}
+
+/// Our wall clock, in milliseconds since the Unix epoch.
+pub fn wall_clock_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_millis() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_skew_checks() {
+        let mut monitor = ClockSkewMonitor::new(Some(2_000));
+
+        // At the start of the session, the clocks agree up to the resolution
+        // of the slot time.
+        assert_eq!(monitor.check(100, 1_000, 1_000_900), None);
+        assert_eq!(monitor.last_skew_ms(), Some(900));
+
+        // Between checks, we do not compare the clocks.
+        assert_eq!(monitor.check(101, 1_000, 1_010_000), None);
+        assert_eq!(monitor.last_skew_ms(), Some(900));
+
+        // Our wall clock runs behind.
+        let slot = 100 + CLOCK_SKEW_CHECK_INTERVAL_SLOTS;
+        assert_eq!(
+            monitor.check(slot, 1_060, 1_055_000),
+            Some(ClockSkewEvent {
+                slot,
+                wall_clock_ms: 1_055_000,
+                slot_time: 1_060,
+                skew_ms: -5_000,
+                threshold_ms: 2_000,
+            })
+        );
+    }
+
+    #[test]
+    fn test_clock_skew_without_threshold() {
+        let mut monitor = ClockSkewMonitor::new(None);
+        assert_eq!(monitor.check(1, 1_000, 2_000_000), None);
+        assert_eq!(monitor.last_skew_ms(), Some(1_000_000));
+    }
+}
@@ -0,0 +1,196 @@
+//! Estimating the compute units that our transactions consume. By default we
+//! assume a fixed amount per hop, but the token swap program changes, so we
+//! can instead use what we measured on a cluster, see
+//! `examples/mev_measure_compute_units.rs`.
+
+use std::{collections::BTreeMap, fs::read_to_string, path::Path};
+
+use serde::{Deserialize, Serialize};
+use solana_program_runtime::compute_budget::MAX_COMPUTE_UNIT_LIMIT;
+
+/// Compute units that we assume one swap of the token swap program takes,
+/// when we have no measurement for the number of hops of a path.
+pub const DEFAULT_COMPUTE_UNITS_PER_HOP: u64 = 40_000;
+
+/// Version of the calibration file format, a file of another version is
+/// rejected.
+pub const CU_CALIBRATION_VERSION: u32 = 1;
+
+/// What we measured for transactions with a given number of hops.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HopCountCalibration {
+    /// The most compute units that one of the transactions consumed.
+    pub compute_units: u64,
+
+    /// How many transactions we measured.
+    pub samples: u64,
+}
+
+/// The compute units that our transactions consumed, per number of hops,
+/// as written to and read from a JSON calibration file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CuCalibration {
+    pub version: u32,
+    pub by_hop_count: BTreeMap<usize, HopCountCalibration>,
+}
+
+impl Default for CuCalibration {
+    fn default() -> Self {
+        CuCalibration {
+            version: CU_CALIBRATION_VERSION,
+            by_hop_count: BTreeMap::new(),
+        }
+    }
+}
+
+impl CuCalibration {
+    /// Record that a transaction with `hops` hops consumed `compute_units`.
+    pub fn record(&mut self, hops: usize, compute_units: u64) {
+        let calibration = self
+            .by_hop_count
+            .entry(hops)
+            .or_insert(HopCountCalibration {
+                compute_units: 0,
+                samples: 0,
+            });
+        calibration.compute_units = calibration.compute_units.max(compute_units);
+        calibration.samples = calibration.samples.saturating_add(1);
+    }
+
+    /// Parse and check a calibration file.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let calibration: CuCalibration =
+            serde_json::from_str(json).map_err(|err| err.to_string())?;
+        if calibration.version != CU_CALIBRATION_VERSION {
+            return Err(format!(
+                "version is {}, but we only read version {}",
+                calibration.version, CU_CALIBRATION_VERSION
+            ));
+        }
+        for (hops, hop_count_calibration) in &calibration.by_hop_count {
+            if *hops == 0 {
+                return Err("a path has at least 1 hop, not 0".to_owned());
+            }
+            let compute_units = hop_count_calibration.compute_units;
+            if compute_units == 0 || compute_units > MAX_COMPUTE_UNIT_LIMIT as u64 {
+                return Err(format!(
+                    "{} compute units for {} hops is not between 1 and {}",
+                    compute_units, hops, MAX_COMPUTE_UNIT_LIMIT
+                ));
+            }
+        }
+        Ok(calibration)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+        CuCalibration::parse(&json).map_err(|err| format!("Invalid {}: {}", path.display(), err))
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Constructed by us, should never fail")
+    }
+}
+
+/// Estimates the compute units of a transaction that trades through a path,
+/// from the calibration if it measured paths with as many hops, and from
+/// `DEFAULT_COMPUTE_UNITS_PER_HOP` otherwise.
+#[derive(Debug, Default, Clone)]
+pub struct ComputeUnitEstimator {
+    calibration: Option<CuCalibration>,
+}
+
+impl ComputeUnitEstimator {
+    pub fn new(calibration: Option<CuCalibration>) -> Self {
+        ComputeUnitEstimator { calibration }
+    }
+
+    pub fn estimate(&self, hops: usize) -> u64 {
+        self.calibration
+            .as_ref()
+            .and_then(|calibration| calibration.by_hop_count.get(&hops))
+            .map_or(DEFAULT_COMPUTE_UNITS_PER_HOP * hops as u64, |calibration| {
+                calibration.compute_units
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibration_round_trip() {
+        let mut calibration = CuCalibration::default();
+        calibration.record(2, 61_000);
+        calibration.record(2, 63_500);
+        calibration.record(3, 92_000);
+        assert_eq!(
+            calibration.by_hop_count[&2],
+            HopCountCalibration {
+                compute_units: 63_500,
+                samples: 2,
+            }
+        );
+        assert_eq!(
+            CuCalibration::parse(&calibration.to_json()),
+            Ok(calibration)
+        );
+    }
+
+    #[test]
+    fn test_estimate_prefers_calibration() {
+        let calibration = CuCalibration::parse(
+            r#"{"version": 1, "by_hop_count": {"2": {"compute_units": 63500, "samples": 2}}}"#,
+        )
+        .unwrap();
+        let estimator = ComputeUnitEstimator::new(Some(calibration));
+        assert_eq!(estimator.estimate(2), 63_500);
+        // No measurement for 3 hops, we fall back to the constant.
+        assert_eq!(estimator.estimate(3), 3 * DEFAULT_COMPUTE_UNITS_PER_HOP);
+        assert_eq!(
+            ComputeUnitEstimator::default().estimate(2),
+            2 * DEFAULT_COMPUTE_UNITS_PER_HOP
+        );
+    }
+
+    #[test]
+    fn test_load_calibration_file() {
+        let mut calibration = CuCalibration::default();
+        calibration.record(1, 35_000);
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), calibration.to_json()).unwrap();
+        assert_eq!(CuCalibration::load(file.path()), Ok(calibration));
+
+        std::fs::write(file.path(), "{}").unwrap();
+        let err = CuCalibration::load(file.path()).unwrap_err();
+        assert!(err.starts_with(&format!("Invalid {}: ", file.path().display())));
+    }
+
+    #[test]
+    fn test_malformed_calibration_is_rejected() {
+        assert!(CuCalibration::parse("{").is_err());
+        assert!(CuCalibration::parse(r#"{"version": 1}"#).is_err());
+        assert_eq!(
+            CuCalibration::parse(r#"{"version": 2, "by_hop_count": {}}"#),
+            Err("version is 2, but we only read version 1".to_owned())
+        );
+        assert_eq!(
+            CuCalibration::parse(
+                r#"{"version": 1, "by_hop_count": {"0": {"compute_units": 1, "samples": 1}}}"#
+            ),
+            Err("a path has at least 1 hop, not 0".to_owned())
+        );
+        assert_eq!(
+            CuCalibration::parse(
+                r#"{"version": 1, "by_hop_count": {"2": {"compute_units": 0, "samples": 1}}}"#
+            ),
+            Err("0 compute units for 2 hops is not between 1 and 1400000".to_owned())
+        );
+        assert!(CuCalibration::parse(
+            r#"{"version": 1, "by_hop_count": {"x": {"compute_units": 1, "samples": 1}}}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,2255 @@
+use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::{read_to_string, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::warn;
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use solana_program_runtime::compute_budget::MAX_COMPUTE_UNIT_LIMIT;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+
+use super::{
+    arbitrage::{MevPath, MAX_HOP_OUTPUT_BPS, MAX_SLIPPAGE_BPS},
+    authority::UserAuthorityConfig,
+    discovery::{AutoDiscoverConfig, MAX_DISCOVERED_HOPS},
+    failures::SystemicFailureConfig,
+    includes::merge_includes,
+    log_channel::LogChannelPolicy,
+    log_file::LogCompression,
+    pool_state::OrcaPoolAddresses,
+    priority_fee::{PriorityFeeConfig, PriorityFeePolicy},
+    reporting::ReportingConfig,
+    signer::{MevSigner, SignerConfig},
+    utils::{expand_path, parse_b58_pubkey, B58Pubkey},
+};
+
+/// The Orca pools from the config, in config order, with indices to look them
+/// up by pool address, by token account, and by token pair.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "Vec<OrcaPoolAddresses>")]
+pub struct AllOrcaPoolAddresses {
+    pools: Vec<OrcaPoolAddresses>,
+    by_address: HashMap<Pubkey, usize>,
+    by_token_account: HashMap<Pubkey, Vec<usize>>,
+    by_mint_pair: HashMap<(Pubkey, Pubkey), Vec<usize>>,
+}
+
+/// The key for a token pair, the same in both directions.
+fn mint_pair_key(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey) {
+    if mint_a <= mint_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    }
+}
+
+impl AllOrcaPoolAddresses {
+    pub fn new(pools: Vec<OrcaPoolAddresses>) -> Self {
+        let mut by_address = HashMap::new();
+        let mut by_token_account: HashMap<Pubkey, Vec<usize>> = HashMap::new();
+        let mut by_mint_pair: HashMap<(Pubkey, Pubkey), Vec<usize>> = HashMap::new();
+        for (idx, pool) in pools.iter().enumerate() {
+            // When a pool is listed twice, the first one wins.
+            by_address.entry(pool.address).or_insert(idx);
+
+            // Our own token accounts are shared between the pools that trade
+            // the same token.
+            let token_accounts = [pool.pool_a_account, pool.pool_b_account]
+                .into_iter()
+                .chain(pool.source)
+                .chain(pool.destination);
+            for token_account in token_accounts {
+                let indices = by_token_account.entry(token_account).or_default();
+                if !indices.contains(&idx) {
+                    indices.push(idx);
+                }
+            }
+
+            // The mints are optional in the config, we can only index the
+            // pools that have them.
+            if let Some((mint_a, mint_b)) = pool.known_mints() {
+                by_mint_pair
+                    .entry(mint_pair_key(mint_a, mint_b))
+                    .or_default()
+                    .push(idx);
+            }
+        }
+        AllOrcaPoolAddresses {
+            pools,
+            by_address,
+            by_token_account,
+            by_mint_pair,
+        }
+    }
+
+    /// The pools in config order.
+    pub fn iter(&self) -> std::slice::Iter<'_, OrcaPoolAddresses> {
+        self.pools.iter()
+    }
+
+    /// The pools in config order, skipping pools that are listed again after
+    /// their first occurrence.
+    pub fn unique(&self) -> impl Iterator<Item = &OrcaPoolAddresses> {
+        self.pools
+            .iter()
+            .enumerate()
+            .filter(move |(idx, pool)| self.by_address.get(&pool.address) == Some(idx))
+            .map(|(_idx, pool)| pool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// The pool with address `address`.
+    pub fn by_address(&self, address: &Pubkey) -> Option<&OrcaPoolAddresses> {
+        self.by_address.get(address).map(|idx| &self.pools[*idx])
+    }
+
+    /// The pools that use `token_account`, either as one of the pool's token
+    /// accounts, or as our source or destination, in config order.
+    pub fn by_token_account<'a>(
+        &'a self,
+        token_account: &Pubkey,
+    ) -> impl Iterator<Item = &'a OrcaPoolAddresses> + 'a {
+        self.lookup(self.by_token_account.get(token_account))
+    }
+
+    /// The pools that trade `mint_a` against `mint_b`, in either direction, in
+    /// config order. Only pools whose mints are in the config are found.
+    pub fn by_mint_pair<'a>(
+        &'a self,
+        mint_a: &Pubkey,
+        mint_b: &Pubkey,
+    ) -> impl Iterator<Item = &'a OrcaPoolAddresses> + 'a {
+        self.lookup(self.by_mint_pair.get(&mint_pair_key(*mint_a, *mint_b)))
+    }
+
+    fn lookup<'a>(
+        &'a self,
+        indices: Option<&'a Vec<usize>>,
+    ) -> impl Iterator<Item = &'a OrcaPoolAddresses> + 'a {
+        indices
+            .into_iter()
+            .flatten()
+            .map(move |idx| &self.pools[*idx])
+    }
+
+    /// The pools with the `source` and `destination` that the config does not
+    /// set derived from the user authority that `authority_of` returns for the
+    /// pool, see `OrcaPoolAddresses::derive_token_accounts`. Observe pools,
+    /// and pools without an authority, are left as they are.
+    pub fn with_derived_token_accounts<F>(self, authority_of: F) -> Result<Self, String>
+    where
+        F: Fn(&OrcaPoolAddresses) -> Option<Pubkey>,
+    {
+        let mut pools = self.pools;
+        for pool in pools.iter_mut().filter(|pool| pool.role.is_trade()) {
+            if let Some(authority) = authority_of(pool) {
+                pool.derive_token_accounts(&authority);
+            }
+        }
+        AllOrcaPoolAddresses::try_from(pools)
+    }
+}
+
+/// Check that every pool is listed once, and that a pool's token accounts are
+/// not used by any other entry, which happens when an entry is copied from
+/// another one and only partly edited. Our own token accounts may be the
+/// source or destination of several pools, those that trade the same token.
+fn check_duplicate_pools(pools: &[OrcaPoolAddresses]) -> Result<(), String> {
+    let mut duplicates = Vec::new();
+
+    let mut times_listed: HashMap<Pubkey, usize> = HashMap::with_capacity(pools.len());
+    for pool in pools {
+        *times_listed.entry(pool.address).or_default() += 1;
+    }
+    let mut seen = HashSet::with_capacity(pools.len());
+    let mut first_entries: Vec<&OrcaPoolAddresses> = Vec::with_capacity(pools.len());
+    for pool in pools {
+        if !seen.insert(pool.address) {
+            continue;
+        }
+        let times = times_listed[&pool.address];
+        if times > 1 {
+            duplicates.push(format!("pool {} is listed {} times", pool.address, times));
+        }
+        first_entries.push(pool);
+    }
+
+    // The pool that owns each pool token account, from its first entry.
+    let mut owners: HashMap<Pubkey, Pubkey> = HashMap::with_capacity(2 * pools.len());
+    for pool in &first_entries {
+        for token_account in [pool.pool_a_account, pool.pool_b_account] {
+            match owners.get(&token_account) {
+                Some(owner) => duplicates.push(format!(
+                    "token account {} belongs to both pool {} and pool {}",
+                    token_account, owner, pool.address
+                )),
+                None => {
+                    owners.insert(token_account, pool.address);
+                }
+            }
+        }
+    }
+    for pool in &first_entries {
+        for (field, token_account) in [("source", pool.source), ("destination", pool.destination)] {
+            if let Some(owner) = token_account.and_then(|account| owners.get(&account)) {
+                duplicates.push(format!(
+                    "the {} of pool {} is token account {} of pool {}",
+                    field,
+                    pool.address,
+                    token_account.unwrap(),
+                    owner
+                ));
+            }
+        }
+    }
+
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(duplicates.join("; "))
+    }
+}
+
+impl TryFrom<Vec<OrcaPoolAddresses>> for AllOrcaPoolAddresses {
+    type Error = String;
+
+    fn try_from(pools: Vec<OrcaPoolAddresses>) -> Result<Self, String> {
+        check_duplicate_pools(&pools)?;
+        Ok(AllOrcaPoolAddresses::new(pools))
+    }
+}
+
+impl<'a> IntoIterator for &'a AllOrcaPoolAddresses {
+    type Item = &'a OrcaPoolAddresses;
+    type IntoIter = std::slice::Iter<'a, OrcaPoolAddresses>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Serialize for AllOrcaPoolAddresses {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(&self.pools)
+    }
+}
+
+/// The version of the config schema that we write, and the newest that we
+/// read. Bump it when a change to `MevConfig` changes the meaning of existing
+/// configs, and describe the migration under "Config versions" in the README.
+pub const MEV_CONFIG_VERSION: u32 = 1;
+
+/// The oldest version of the config schema that we still read.
+pub const MIN_MEV_CONFIG_VERSION: u32 = 1;
+
+/// Where operators find how to migrate a config to `MEV_CONFIG_VERSION`.
+const MIGRATION_NOTES: &str = "the \"Config versions\" section of the README";
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MevConfig {
+    /// The version of the schema that the config was written for, see
+    /// `MEV_CONFIG_VERSION`.
+    pub config_version: u32,
+
+    pub log_path: PathBuf,
+
+    /// Compress the log at `log_path`. Read a compressed log with
+    /// `log_file::read_mev_log`.
+    #[serde(default)]
+    pub log_compression: LogCompression,
+
+    /// At most how many messages wait for the log thread. If not set, any
+    /// number can wait.
+    #[serde(default)]
+    pub log_channel_capacity: Option<usize>,
+
+    /// What we do with a message for the log thread when
+    /// `log_channel_capacity` messages already wait.
+    #[serde(default)]
+    pub log_channel_policy: LogChannelPolicy,
+
+    /// The fraction of transactions, between 0 and 1, whose lock impact we
+    /// keep for the status, see `Mev::lock_impact`. If not set, none.
+    #[serde(default)]
+    pub lock_impact_sampling_rate: Option<f64>,
+
+    pub watched_programs: Vec<B58Pubkey>,
+
+    /// A transaction that interacts with the `watched_programs` only triggers
+    /// us when it references the accounts of one of the pools, or one of these
+    /// accounts.
+    #[serde(default)]
+    pub watched_accounts: Vec<B58Pubkey>,
+
+    /// Transactions signed by any of these keys never trigger us, even when
+    /// they interact with the `watched_programs`.
+    #[serde(default)]
+    pub blocked_signers: Vec<B58Pubkey>,
+
+    /// Pools that we never trade through. Paths through them find no
+    /// opportunity, unless a hop has an alternative pool that is not blocked.
+    #[serde(default)]
+    pub blocked_pools: Vec<B58Pubkey>,
+
+    #[serde(rename(deserialize = "orca_account"))]
+    pub orca_accounts: AllOrcaPoolAddresses,
+
+    /// Specify paths to look for MEV opportunities.
+    // #[serde(rename(deserialize = "mev_path"))]
+    #[serde(rename(deserialize = "mev_path"))]
+    pub mev_paths: Vec<MevPath>,
+
+    /// Named groups of pools for the same pair, that a hop of a path can trade
+    /// through together, splitting its input across them, see
+    /// `MevConfig::resolve_pool_groups`.
+    #[serde(default)]
+    #[serde(rename(deserialize = "pool_group"))]
+    pub pool_groups: Vec<PoolGroupConfig>,
+
+    /// Also evaluate the cycles through the pools of the config that no
+    /// `mev_path` lists, see `discover_paths`. If not set, we only evaluate
+    /// the configured paths.
+    #[serde(default)]
+    pub auto_discover_paths: Option<AutoDiscoverConfig>,
+
+    pub user_authority_path: Option<PathBuf>,
+
+    /// Sign transactions with another signer than the keypair at
+    /// `user_authority_path`. Only one of the two may be set.
+    #[serde(default)]
+    pub signer: Option<SignerConfig>,
+
+    /// User authorities for some of the pools, that sign and pay for the
+    /// transactions through them, instead of the default one above.
+    #[serde(default)]
+    #[serde(rename(deserialize = "user_authority"))]
+    pub user_authorities: Vec<UserAuthorityConfig>,
+
+    /// Sign the selected transaction on a dedicated thread, and wait at most
+    /// this long for the signature. If not set, we sign on the banking thread.
+    #[serde(default)]
+    pub signing_thread_wait_ms: Option<u64>,
+
+    /// Use the associated token accounts of the user authority for the
+    /// `pool_a_mint` and `pool_b_mint` of a pool as its `source` and
+    /// `destination`, where the config does not set them. Needs the mints in
+    /// the config.
+    #[serde(default)]
+    pub derive_token_accounts_from_authority: bool,
+
+    /// Per token, the minimum profit before we craft a transaction, see
+    /// `TokenAmount`. A token is its mint, or a symbol from `tokens`. For now
+    /// we also accept the array of `[token, minimum_profit]` pairs that this
+    /// used to be, see `deserialize_minimum_profit`.
+    #[serde(deserialize_with = "deserialize_minimum_profit")]
+    pub minimum_profit: HashMap<TokenRef, TokenAmount>,
+
+    /// Symbols for token mints, e.g. `USDC`, that `minimum_profit` can use
+    /// instead of the mint.
+    #[serde(default)]
+    pub tokens: HashMap<String, B58Pubkey>,
+
+    /// Per token, its number of decimals, for the amounts in the config that
+    /// are given in whole tokens.
+    #[serde(default)]
+    pub decimals: HashMap<TokenRef, u8>,
+
+    /// The minimum profit for token mints that are not in `minimum_profit`.
+    /// If not set, we warn about such mints and take any profit on them.
+    #[serde(default)]
+    pub default_minimum_profit: Option<u64>,
+
+    /// Per token mint address, the maximum amount of that token that may be
+    /// committed to MEV transactions that were crafted but not executed yet,
+    /// see `TokenAmount`. Mints that are not listed are not capped.
+    #[serde(default)]
+    pub max_inflight_exposure: HashMap<B58Pubkey, TokenAmount>,
+
+    /// At most how many MEV transactions we execute in one slot. Once we
+    /// reached it, we still log opportunities, but do not execute them. If not
+    /// set, the number is not capped.
+    #[serde(default)]
+    pub max_mev_txs_per_slot: Option<u64>,
+
+    /// At most how many opportunities we execute after one trigger, the most
+    /// profitable first, skipping those that conflict with one we took
+    /// already, see `opportunity_conflict`. If not set, we execute only the
+    /// most profitable one.
+    #[serde(default)]
+    pub max_opportunities_per_trigger: Option<u8>,
+
+    /// When two opportunities of the same trigger conflict.
+    #[serde(default)]
+    pub opportunity_conflict: OpportunityConflict,
+
+    /// How much a trigger has to move the pools of a path, for us to craft a
+    /// transaction for the opportunity after it. If not set, any move counts.
+    #[serde(default)]
+    pub min_trigger_delta: Option<MinTriggerDeltaConfig>,
+
+    /// How to set the compute unit price of our transactions. If not set, we
+    /// do not pay a priority fee.
+    #[serde(default)]
+    pub priority_fee: Option<PriorityFeeConfig>,
+
+    /// How far below the quoted output we accept the output of the last hop,
+    /// in basis points. We never accept less than the input, and if not set,
+    /// we accept any output that is not a loss. A path can override this.
+    #[serde(default)]
+    pub slippage_bps: Option<u16>,
+
+    /// The compute unit limit that our transactions request. If not set, they
+    /// do not request one, and get the runtime's default. A path can override
+    /// this.
+    #[serde(default)]
+    pub compute_unit_limit: Option<u32>,
+
+    /// Also pass every event that we log on to the Geyser plugins that are
+    /// interested in MEV events.
+    #[serde(default)]
+    pub geyser_notifications: bool,
+
+    /// Log a watchdog event when we did not see a transaction that touches the
+    /// watched programs for this many slots in which we were leader.
+    #[serde(default)]
+    pub watchdog_trigger_slots: Option<u64>,
+
+    /// Log a watchdog event when we did not find an opportunity for this many
+    /// slots in which we were leader.
+    #[serde(default)]
+    pub watchdog_opportunity_slots: Option<u64>,
+
+    /// Only execute a MEV transaction once the transaction that created the
+    /// opportunity is committed, so that we never trade against a trigger that
+    /// did not make it into the block. We still log the opportunity right away.
+    #[serde(default)]
+    pub confirm_trigger_before_craft: bool,
+
+    /// Attach a trace of the inputs to our decision to every opportunity that
+    /// we select, so that executed transactions can be audited.
+    #[serde(default)]
+    pub audit_mode: bool,
+
+    /// Craft and sign transactions as usual, but log the ones that we select
+    /// with their opportunity, instead of executing them.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Refine the optimal input, which we compute with floating point math,
+    /// to the integer input with the most profit with the rounding of the
+    /// pools, by simulating the path for inputs close to it.
+    #[serde(default)]
+    pub exact_optimal_input: bool,
+
+    /// A pool with a reserve below this, in the smallest unit of its token,
+    /// counts as drained, and paths do not trade through it. Pools with an
+    /// empty reserve are always drained.
+    #[serde(default)]
+    pub min_pool_reserve: Option<u64>,
+
+    /// The output of a hop has to stay below this share, in basis points, of
+    /// the reserve that the pool pays it from, otherwise we do not trade the
+    /// path. If not set, the output has to stay below the whole reserve.
+    #[serde(default)]
+    pub max_hop_output_bps: Option<u16>,
+
+    /// Log a `path_skipped` event with the reason when a path has no
+    /// opportunity after a trigger, e.g. because it is not profitable. This
+    /// logs an event for most paths on every trigger, so it is meant for
+    /// tuning a config, not for production.
+    #[serde(default)]
+    pub log_skipped_paths: bool,
+
+    /// Log a `checkpoint` event with the latest state of every pool that we
+    /// observed, at most once every this many slots, so that a searcher can
+    /// start from it. If not set, we do not write checkpoints.
+    #[serde(default)]
+    pub checkpoint_interval_slots: Option<u64>,
+
+    /// Log a `clock_skew` event when our wall clock is further off than this
+    /// many milliseconds from the bank's estimated slot time.
+    #[serde(default)]
+    pub clock_skew_threshold_ms: Option<u64>,
+
+    /// Log an opportunity on a path only once when the opportunities after it
+    /// in this many slots fall in the same bucket of marginal price and
+    /// optimal input, and log how many there were when the bucket ends.
+    #[serde(default)]
+    pub opportunity_debounce_slots: Option<u64>,
+
+    /// An `http://` RPC to send our transactions to with `sendTransaction`.
+    #[serde(default)]
+    pub fallback_rpc_url: Option<String>,
+
+    /// Send the transactions that we crafted, but could not record because
+    /// our leader slot ended, to `fallback_rpc_url` instead of dropping them.
+    #[serde(default)]
+    pub fallback_when_not_leader: bool,
+
+    /// Pause crafting when our transactions keep failing in a way that has
+    /// nothing to do with the path, e.g. with a stale blockhash. If not set,
+    /// we never pause.
+    #[serde(default)]
+    pub systemic_failure: Option<SystemicFailureConfig>,
+
+    /// A JSON file with the compute units that our transactions consumed,
+    /// per number of hops, as written by `mev_measure_compute_units`. Paths
+    /// whose number of hops it has no measurement for, or all paths if not
+    /// set, are estimated with `DEFAULT_COMPUTE_UNITS_PER_HOP`.
+    #[serde(default)]
+    pub cu_calibration_file: Option<PathBuf>,
+
+    /// Convert the profits of our transactions into one mint, so that they
+    /// can be summed. If not set, we report profits in their own mint only.
+    #[serde(default)]
+    pub reporting: Option<ReportingConfig>,
+}
+
+/// When two opportunities of the same trigger conflict, so that we execute at
+/// most one of them, see `max_opportunities_per_trigger`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpportunityConflict {
+    /// They trade through a common pool.
+    Pool,
+
+    /// Their transactions write a common account: a pool's, or one of our
+    /// token accounts, whose balance both would spend.
+    Account,
+}
+
+impl Default for OpportunityConflict {
+    fn default() -> Self {
+        OpportunityConflict::Pool
+    }
+}
+
+/// How much a reserve of a pool has to move between the states before and
+/// after a trigger, to count as a material move. A reserve has to reach every
+/// threshold that applies to it. Triggers that arbitrage their own swap leave
+/// the pools balanced up to rounding, which can still show a tiny opportunity.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MinTriggerDeltaConfig {
+    /// The move in basis points of the reserve before the trigger.
+    #[serde(default)]
+    pub bps: Option<u16>,
+
+    /// Per token, the move, see `TokenAmount`.
+    #[serde(default)]
+    pub amounts: HashMap<TokenRef, TokenAmount>,
+}
+
+/// Pools that trade the same pair, that a hop can set as its `group`, to split
+/// its input across them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolGroupConfig {
+    pub name: String,
+    pub pools: Vec<B58Pubkey>,
+}
+
+/// An amount of a token in the config: an integer in the token's smallest
+/// unit, a decimal string in whole tokens, e.g. `"1.5"`, which needs the
+/// token's `decimals`, or an amount in whole tokens with the decimals of the
+/// token, e.g. `{ ui_amount = 1.5, decimals = 6 }`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum TokenAmount {
+    Raw(u64),
+    Ui(String),
+    UiWithDecimals { ui_amount: String, decimals: u8 },
+}
+
+impl From<u64> for TokenAmount {
+    fn from(amount: u64) -> Self {
+        TokenAmount::Raw(amount)
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenAmount::Raw(amount) => write!(f, "{}", amount),
+            TokenAmount::Ui(amount) => write!(f, "{:?}", amount),
+            TokenAmount::UiWithDecimals {
+                ui_amount,
+                decimals,
+            } => write!(f, "{:?} with {} decimals", ui_amount, decimals),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TokenAmountVisitor;
+
+        impl<'de> Visitor<'de> for TokenAmountVisitor {
+            type Value = TokenAmount;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str(
+                    "an integer amount in the token's smallest unit, a decimal string in \
+                    whole tokens, or a table with ui_amount and decimals",
+                )
+            }
+
+            fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Self::Value, E> {
+                Ok(TokenAmount::Raw(amount))
+            }
+
+            fn visit_i64<E: de::Error>(self, amount: i64) -> Result<Self::Value, E> {
+                u64::try_from(amount)
+                    .map(TokenAmount::Raw)
+                    .map_err(|_| E::custom(format!("{} is negative", amount)))
+            }
+
+            fn visit_str<E: de::Error>(self, amount: &str) -> Result<Self::Value, E> {
+                // Check the format already, the decimals come later.
+                split_ui_amount(amount).map_err(E::custom)?;
+                Ok(TokenAmount::Ui(amount.to_owned()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let amount =
+                    UiAmountWithDecimals::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(TokenAmount::UiWithDecimals {
+                    ui_amount: amount.ui_amount.0,
+                    decimals: amount.decimals,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(TokenAmountVisitor)
+    }
+}
+
+/// An amount in whole tokens, a decimal string or a number, e.g. `0.5`, which
+/// we keep as the shortest decimal string that reads back as the same number.
+struct UiAmount(String);
+
+impl<'de> Deserialize<'de> for UiAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct UiAmountVisitor;
+
+        impl<'de> Visitor<'de> for UiAmountVisitor {
+            type Value = UiAmount;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an amount in whole tokens")
+            }
+
+            fn visit_u64<E: de::Error>(self, amount: u64) -> Result<Self::Value, E> {
+                Ok(UiAmount(amount.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, amount: i64) -> Result<Self::Value, E> {
+                u64::try_from(amount)
+                    .map(|amount| UiAmount(amount.to_string()))
+                    .map_err(|_| E::custom(format!("{} is negative", amount)))
+            }
+
+            fn visit_f64<E: de::Error>(self, amount: f64) -> Result<Self::Value, E> {
+                // `Display` of a float never uses an exponent.
+                self.visit_str(&amount.to_string())
+            }
+
+            fn visit_str<E: de::Error>(self, amount: &str) -> Result<Self::Value, E> {
+                split_ui_amount(amount).map_err(E::custom)?;
+                Ok(UiAmount(amount.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_any(UiAmountVisitor)
+    }
+}
+
+/// `TokenAmount::UiWithDecimals` as written in the config.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UiAmountWithDecimals {
+    ui_amount: UiAmount,
+    decimals: u8,
+}
+
+impl TokenAmount {
+    /// The amount in the smallest unit of a token with `decimals`, which an
+    /// amount in whole tokens needs, unless it has its own decimals.
+    pub fn to_raw(&self, decimals: Option<u8>) -> Result<u64, String> {
+        match (self, decimals) {
+            (TokenAmount::Raw(amount), _) => Ok(*amount),
+            (
+                TokenAmount::UiWithDecimals {
+                    ui_amount,
+                    decimals,
+                },
+                _,
+            ) => parse_ui_amount(ui_amount, *decimals),
+            (TokenAmount::Ui(amount), Some(decimals)) => parse_ui_amount(amount, decimals),
+            (TokenAmount::Ui(amount), None) => Err(format!(
+                "{:?} is in whole tokens, but the decimals of the token are unknown",
+                amount
+            )),
+        }
+    }
+}
+
+/// The whole and the fractional digits of `amount`, a decimal string.
+fn split_ui_amount(amount: &str) -> Result<(&str, &str), String> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let is_digits = |digits: &str| digits.chars().all(|c| c.is_ascii_digit());
+    if whole.is_empty() || !is_digits(whole) || !is_digits(fraction) {
+        return Err(format!("{:?} is not a decimal amount", amount));
+    }
+    Ok((whole, fraction))
+}
+
+/// `amount`, a decimal string in whole tokens, in the smallest unit of a token
+/// with `decimals`.
+fn parse_ui_amount(amount: &str, decimals: u8) -> Result<u64, String> {
+    let (whole, fraction) = split_ui_amount(amount)?;
+    if fraction.len() > decimals as usize {
+        return Err(format!("{:?} has more than {} decimals", amount, decimals));
+    }
+    format!("{}{:0<width$}", whole, fraction, width = decimals as usize)
+        .parse()
+        .map_err(|_| format!("{:?} is too large", amount))
+}
+
+/// The per-token thresholds of the config in the smallest unit of each token,
+/// by mint, as we log them at startup.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ThresholdSummary {
+    pub minimum_profit: BTreeMap<String, u64>,
+    pub default_minimum_profit: Option<u64>,
+    pub max_inflight_exposure: BTreeMap<String, u64>,
+    pub min_trigger_delta_amounts: BTreeMap<String, u64>,
+}
+
+/// A token in the config: its mint, or a symbol that `MevConfig::tokens` maps
+/// to a mint. What is not a valid mint, is a symbol.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(from = "String", into = "String")]
+pub enum TokenRef {
+    Mint(Pubkey),
+    Symbol(String),
+}
+
+impl From<String> for TokenRef {
+    fn from(token: String) -> Self {
+        match parse_b58_pubkey(&token) {
+            Ok(mint) => TokenRef::Mint(mint),
+            Err(_) => TokenRef::Symbol(token),
+        }
+    }
+}
+
+impl From<TokenRef> for String {
+    fn from(token: TokenRef) -> Self {
+        match token {
+            TokenRef::Mint(mint) => mint.to_string(),
+            TokenRef::Symbol(symbol) => symbol,
+        }
+    }
+}
+
+/// An element of `minimum_profit` as an array: a `[token, minimum_profit]`
+/// pair, which is deprecated, or a table like
+/// `{ mint = "<mint>", ui_amount = 0.5, decimals = 6 }`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MinimumProfitElement {
+    Pair(TokenRef, TokenAmount),
+    Entry {
+        mint: TokenRef,
+        ui_amount: UiAmount,
+        decimals: u8,
+    },
+}
+
+/// Deserialize `minimum_profit` from a table of token to minimum profit, or
+/// from an array of `MinimumProfitElement`s. A token that is listed twice in
+/// the array is an error, like it is for a key in a table.
+fn deserialize_minimum_profit<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<TokenRef, TokenAmount>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct MinimumProfitVisitor;
+
+    impl MinimumProfitVisitor {
+        fn insert<E: de::Error>(
+            minimum_profit: &mut HashMap<TokenRef, TokenAmount>,
+            token: TokenRef,
+            profit: TokenAmount,
+        ) -> Result<(), E> {
+            match minimum_profit.entry(token) {
+                Entry::Occupied(entry) => Err(E::custom(format!(
+                    "duplicate token {}",
+                    String::from(entry.key().clone())
+                ))),
+                Entry::Vacant(entry) => {
+                    entry.insert(profit);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    impl<'de> Visitor<'de> for MinimumProfitVisitor {
+        type Value = HashMap<TokenRef, TokenAmount>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a table of token to minimum profit")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut minimum_profit = HashMap::new();
+            while let Some((token, profit)) = map.next_entry()? {
+                Self::insert(&mut minimum_profit, token, profit)?;
+            }
+            Ok(minimum_profit)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut minimum_profit = HashMap::new();
+            let mut has_pairs = false;
+            while let Some(element) = seq.next_element()? {
+                let (token, profit) = match element {
+                    MinimumProfitElement::Pair(token, profit) => {
+                        has_pairs = true;
+                        (token, profit)
+                    }
+                    MinimumProfitElement::Entry {
+                        mint,
+                        ui_amount,
+                        decimals,
+                    } => (
+                        mint,
+                        TokenAmount::UiWithDecimals {
+                            ui_amount: ui_amount.0,
+                            decimals,
+                        },
+                    ),
+                };
+                Self::insert(&mut minimum_profit, token, profit)?;
+            }
+            if has_pairs {
+                warn!(
+                    "[MEV] minimum_profit as an array of [token, minimum_profit] pairs is \
+                    deprecated, use a [minimum_profit] table of token = minimum_profit instead"
+                );
+            }
+            Ok(minimum_profit)
+        }
+    }
+
+    deserializer.deserialize_any(MinimumProfitVisitor)
+}
+
+impl MevConfig {
+    /// Fill in the pools of the hops that set a `group`: the first pool of
+    /// the group as `pool`, the others as `alternative_pools`. A group has to
+    /// be defined in `pool_groups`, have pools, each only once, and all of
+    /// them with the same mints, where the config knows them.
+    pub fn resolve_pool_groups(&mut self) -> Result<(), String> {
+        let mut groups = HashMap::new();
+        for group in &self.pool_groups {
+            let pools: Vec<Pubkey> = group.pools.iter().map(|pool| pool.0).collect();
+            let (first, others) = pools
+                .split_first()
+                .ok_or_else(|| format!("pool_group {}: it has no pools", group.name))?;
+            for (i, pool) in others.iter().enumerate() {
+                if pool == first || others[..i].contains(pool) {
+                    return Err(format!(
+                        "pool_group {}: pool {} is listed more than once",
+                        group.name, pool
+                    ));
+                }
+            }
+            let mut known_mints = pools.iter().filter_map(|pool| {
+                let mints = self.orca_accounts.by_address(pool)?.known_mints()?;
+                Some((pool, mints))
+            });
+            if let Some((first_pool, first_mints)) = known_mints.next() {
+                if let Some((pool, _mints)) =
+                    known_mints.find(|(_pool, mints)| *mints != first_mints)
+                {
+                    return Err(format!(
+                        "pool_group {}: pool {} trades other mints than {}",
+                        group.name, pool, first_pool
+                    ));
+                }
+            }
+            if groups.insert(group.name.as_str(), pools).is_some() {
+                return Err(format!(
+                    "pool_group {} is defined more than once",
+                    group.name
+                ));
+            }
+        }
+        for mev_path in &mut self.mev_paths {
+            for pair_info in &mut mev_path.path {
+                if let Some(group) = &pair_info.group {
+                    let pools = groups.get(group.as_str()).ok_or_else(|| {
+                        format!(
+                            "MEV path {} trades through pool group {}, which is not defined",
+                            mev_path.name, group
+                        )
+                    })?;
+                    pair_info.pool = pools[0];
+                    pair_info.alternative_pools = pools[1..].to_vec();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The mint of `token`, which is in the config under `field`. A symbol
+    /// that is not in `tokens` is an error.
+    pub fn resolve_token(&self, field: &str, token: &TokenRef) -> Result<Pubkey, String> {
+        match token {
+            TokenRef::Mint(mint) => Ok(*mint),
+            TokenRef::Symbol(symbol) => match self.tokens.get(symbol) {
+                Some(mint) => Ok(mint.0),
+                None => {
+                    let mut known: Vec<&str> = self.tokens.keys().map(|s| s.as_str()).collect();
+                    known.sort_unstable();
+                    Err(format!(
+                        "{}: {:?} is neither a mint nor a symbol in tokens, \
+                        the known symbols are [{}]",
+                        field,
+                        symbol,
+                        known.join(", ")
+                    ))
+                }
+            },
+        }
+    }
+
+    /// `amount` of `token`, which is in the config under `field`, in the
+    /// token's smallest unit. An amount in whole tokens needs the decimals of
+    /// the token in `decimals`.
+    pub fn raw_amount(
+        &self,
+        field: &str,
+        token: &TokenRef,
+        amount: &TokenAmount,
+    ) -> Result<u64, String> {
+        let mint = self.resolve_token(field, token)?;
+        let mut decimals = None;
+        for (decimals_token, token_decimals) in &self.decimals {
+            if self.resolve_token("decimals", decimals_token)? == mint {
+                decimals = Some(*token_decimals);
+            }
+        }
+        if let (
+            TokenAmount::UiWithDecimals {
+                decimals: own_decimals,
+                ..
+            },
+            Some(decimals),
+        ) = (amount, decimals)
+        {
+            if *own_decimals != decimals {
+                return Err(format!(
+                    "{}: {} for {} does not match the {} decimals of mint {} in [decimals]",
+                    field,
+                    amount,
+                    String::from(token.clone()),
+                    decimals,
+                    mint
+                ));
+            }
+        }
+        amount
+            .to_raw(decimals)
+            .map_err(|err| match (amount, decimals) {
+                // An amount with its own decimals does not need `decimals`.
+                (TokenAmount::Ui(_), None) => format!(
+                    "{}: {} for {} is in whole tokens, but the decimals of mint {} are unknown, \
+                set them in [decimals]",
+                    field,
+                    amount,
+                    String::from(token.clone()),
+                    mint
+                ),
+                _ => format!("{}: {}", field, err),
+            })
+    }
+
+    /// `minimum_profit` per mint, with the symbols resolved through `tokens`.
+    /// A symbol that is not in `tokens`, or two entries for the same mint, are
+    /// an error.
+    pub fn minimum_profit_by_mint(&self) -> Result<HashMap<Pubkey, u64>, String> {
+        let mut by_mint: HashMap<Pubkey, (&TokenRef, u64)> = HashMap::new();
+        for (token, minimum_profit) in &self.minimum_profit {
+            let mint = self.resolve_token("minimum_profit", token)?;
+            let minimum_profit = self.raw_amount("minimum_profit", token, minimum_profit)?;
+            if let Some((other, _minimum_profit)) = by_mint.insert(mint, (token, minimum_profit)) {
+                let mut tokens = [String::from(other.clone()), String::from(token.clone())];
+                tokens.sort();
+                return Err(format!(
+                    "minimum_profit: {} and {} are both mint {}",
+                    tokens[0], tokens[1], mint
+                ));
+            }
+        }
+        Ok(by_mint
+            .into_iter()
+            .map(|(mint, (_token, minimum_profit))| (mint, minimum_profit))
+            .collect())
+    }
+
+    /// `max_inflight_exposure` per mint, in the smallest unit of each token.
+    pub fn max_inflight_exposure_by_mint(&self) -> Result<HashMap<Pubkey, u64>, String> {
+        self.max_inflight_exposure
+            .iter()
+            .map(|(mint, max)| {
+                let max = self.raw_amount("max_inflight_exposure", &TokenRef::Mint(mint.0), max)?;
+                Ok((mint.0, max))
+            })
+            .collect()
+    }
+
+    /// The per-token thresholds in the smallest unit of each token, which
+    /// fails like the thresholds themselves when an amount cannot be resolved.
+    pub fn threshold_summary(&self) -> Result<ThresholdSummary, String> {
+        let by_mint = |amounts: HashMap<Pubkey, u64>| -> BTreeMap<String, u64> {
+            amounts
+                .into_iter()
+                .map(|(mint, amount)| (mint.to_string(), amount))
+                .collect()
+        };
+        let min_trigger_delta_amounts = match &self.min_trigger_delta {
+            Some(min_trigger_delta) => min_trigger_delta
+                .amounts
+                .iter()
+                .map(|(token, amount)| {
+                    let field = "min_trigger_delta.amounts";
+                    let mint = self.resolve_token(field, token)?;
+                    Ok((mint, self.raw_amount(field, token, amount)?))
+                })
+                .collect::<Result<_, String>>()?,
+            None => HashMap::new(),
+        };
+        for token in self.decimals.keys() {
+            self.resolve_token("decimals", token)?;
+        }
+        Ok(ThresholdSummary {
+            minimum_profit: by_mint(self.minimum_profit_by_mint()?),
+            default_minimum_profit: self.default_minimum_profit,
+            max_inflight_exposure: by_mint(self.max_inflight_exposure_by_mint()?),
+            min_trigger_delta_amounts: by_mint(min_trigger_delta_amounts),
+        })
+    }
+
+    /// Check the global `slippage_bps` and `compute_unit_limit`, and the
+    /// overrides of the paths.
+    pub fn check_tx_settings(&self) -> Result<(), String> {
+        let check = |location: &str, slippage_bps: Option<u16>, compute_unit_limit: Option<u32>| {
+            if let Some(slippage_bps) = slippage_bps.filter(|bps| *bps > MAX_SLIPPAGE_BPS) {
+                return Err(format!(
+                    "{}slippage_bps: {} is more than {}",
+                    location, slippage_bps, MAX_SLIPPAGE_BPS
+                ));
+            }
+            match compute_unit_limit {
+                Some(limit) if limit == 0 || limit > MAX_COMPUTE_UNIT_LIMIT => Err(format!(
+                    "{}compute_unit_limit: {} is not between 1 and {}",
+                    location, limit, MAX_COMPUTE_UNIT_LIMIT
+                )),
+                _ => Ok(()),
+            }
+        };
+        check("", self.slippage_bps, self.compute_unit_limit)?;
+        if let Some(bps) = self
+            .max_hop_output_bps
+            .filter(|bps| *bps == 0 || *bps > MAX_HOP_OUTPUT_BPS)
+        {
+            return Err(format!(
+                "max_hop_output_bps: {} is not between 1 and {}",
+                bps, MAX_HOP_OUTPUT_BPS
+            ));
+        }
+        if let Some(PriorityFeePolicy::ProfitShare(share)) = self
+            .priority_fee
+            .as_ref()
+            .map(|priority_fee| &priority_fee.policy)
+        {
+            // Also rejects NaN.
+            if !(*share > 0.0 && *share <= 1.0) {
+                return Err(format!(
+                    "priority_fee.policy.profit_share: {} is not more than 0 and at most 1",
+                    share
+                ));
+            }
+        }
+        for (i, path) in self.mev_paths.iter().enumerate() {
+            check(
+                &format!("mev_path[{}].", i),
+                path.slippage_bps,
+                path.compute_unit_limit,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the MEV config file, on error, the message includes the location of
+/// the field in the file, e.g. `orca_account[3].pool_a_account`.
+/// Check the `config_version` of the config in `config_str`, before we
+/// deserialize the rest of it: an older config may fail to deserialize because
+/// of renamed fields, and then the version is the more useful error. A config
+/// that is not valid TOML is left to the deserializer, which locates the error.
+fn check_config_version(config_str: &str) -> Result<(), String> {
+    let config = match config_str.parse::<toml::Value>() {
+        Ok(config) => config,
+        Err(_) => return Ok(()),
+    };
+    let supported = if MIN_MEV_CONFIG_VERSION == MEV_CONFIG_VERSION {
+        format!("version {}", MEV_CONFIG_VERSION)
+    } else {
+        format!(
+            "versions {} to {}",
+            MIN_MEV_CONFIG_VERSION, MEV_CONFIG_VERSION
+        )
+    };
+    match config.get("config_version") {
+        None => Err(format!(
+            "config_version: missing, this validator reads MEV config {}, \
+            see {} for how to migrate the config",
+            supported, MIGRATION_NOTES
+        )),
+        Some(toml::Value::Integer(version))
+            if (i64::from(MIN_MEV_CONFIG_VERSION)..=i64::from(MEV_CONFIG_VERSION))
+                .contains(version) =>
+        {
+            Ok(())
+        }
+        Some(toml::Value::Integer(version)) if *version > i64::from(MEV_CONFIG_VERSION) => {
+            Err(format!(
+                "config_version: {} is newer than this validator, which reads MEV config {}, \
+                upgrade the validator or see {}",
+                version, supported, MIGRATION_NOTES
+            ))
+        }
+        Some(version) => Err(format!(
+            "config_version: {} is not supported, this validator reads MEV config {}, \
+            see {} for how to migrate the config",
+            version, supported, MIGRATION_NOTES
+        )),
+    }
+}
+
+pub fn parse_mev_config(config_str: &str) -> Result<MevConfig, String> {
+    check_config_version(config_str)?;
+    let mut deserializer = toml::Deserializer::new(config_str);
+    let mut config: MevConfig = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|err| format!("{}: {}", err.path(), err.inner()))?;
+    config.resolve_pool_groups()?;
+    config.threshold_summary()?;
+    config.check_tx_settings()?;
+    if config.max_opportunities_per_trigger == Some(0) {
+        return Err("max_opportunities_per_trigger: 0 would never execute anything".to_owned());
+    }
+    if let Some(auto_discover_paths) = &config.auto_discover_paths {
+        if !(2..=MAX_DISCOVERED_HOPS).contains(&auto_discover_paths.max_hops) {
+            return Err(format!(
+                "auto_discover_paths.max_hops: {} is not between 2 and {}",
+                auto_discover_paths.max_hops, MAX_DISCOVERED_HOPS
+            ));
+        }
+    }
+    if config.log_channel_capacity == Some(0) {
+        return Err("log_channel_capacity: 0 leaves no room for any message".to_owned());
+    }
+    if let Some(rate) = config.lock_impact_sampling_rate {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(format!(
+                "lock_impact_sampling_rate: {} is not between 0 and 1",
+                rate
+            ));
+        }
+    }
+    Ok(config)
+}
+
+/// Read the config at `config_path`, with the pools of the files in its
+/// `include`, see `merge_includes`.
+pub fn read_mev_config(config_path: &Path) -> Result<MevConfig, String> {
+    let config_str = read_to_string(config_path)
+        .map_err(|err| format!("Could not open {}: {}", config_path.display(), err))?;
+    match merge_includes(config_path, &config_str)? {
+        Some(merged_str) => parse_mev_config(&merged_str),
+        None => parse_mev_config(&config_str),
+    }
+}
+
+pub fn get_mev_config_file(config_path: &PathBuf) -> MevConfig {
+    match read_mev_config(config_path) {
+        Ok(config_file) => config_file,
+        Err(err) => panic!("Could not deserialize MEV config file: {}", err),
+    }
+}
+
+/// Load the signer for MEV transactions, from either the keypair file at
+/// `user_authority_path`, or the `signer` config. Returns `None` when neither
+/// is set, then we only log opportunities. We expand `~` and `${VAR}` in the
+/// path, see `expand_path`, and errors name the expanded path.
+pub fn load_user_authority(
+    user_authority_path: Option<PathBuf>,
+    signer: Option<SignerConfig>,
+) -> Result<Option<Arc<dyn MevSigner>>, String> {
+    match (user_authority_path, signer) {
+        (Some(_), Some(_)) => {
+            Err("Only one of user_authority_path and signer should be set".to_owned())
+        }
+        (Some(path), None) => {
+            let path = expand_path(&path)
+                .map_err(|err| format!("Invalid user_authority_path: {}", err))?;
+            let file = File::open(&path).map_err(|err| {
+                format!("Could not open user authority {}: {}", path.display(), err)
+            })?;
+            let reader = BufReader::new(file);
+            let secret_key_bytes: Vec<u8> = serde_json::from_reader(reader).map_err(|err| {
+                format!("Could not read user authority {}: {}", path.display(), err)
+            })?;
+            let keypair = Keypair::from_bytes(&secret_key_bytes).map_err(|err| {
+                format!(
+                    "Could not generate Keypair from user authority {}: {}",
+                    path.display(),
+                    err
+                )
+            })?;
+            Ok(Some(Arc::new(keypair)))
+        }
+        (None, Some(signer_config)) => Ok(Some(Arc::from(signer_config.into_signer()))),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use solana_sdk::signature::write_keypair_file;
+
+    use super::*;
+    use crate::mev::{
+        arbitrage::{PairInfo, TradeDirection},
+        pool_state::PoolRole,
+    };
+
+    #[test]
+    fn test_deserialization() {
+        let sample_config: MevConfig = toml::from_str(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = ['9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP']
+
+    [minimum_profit]
+        'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' = 101
+
+    [[orca_account]]
+        _id = 'USDC/USDT[stable]'
+        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+        pool_a_mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v'
+        pool_b_mint = 'Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB'
+
+    [[orca_account]]
+        _id = 'SOL/USDC[aquafarm]'
+        address = 'EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U'
+        pool_a_account = 'ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHoNd13Lg'
+        pool_b_account = '75HgnSvXbWKZBpZHveX68ZzAhDqMzNDS29X6BGLtxMo1'
+        pool_mint = 'APDFRM3HMr8CAGXwKHiu2f5ePSpaiEJhaURwhsRrUUt9'
+        pool_fee = '8JnSiuvQq3BVuCU3n4DrSTw9chBSPvEMswrhtifVkr1o'
+    
+    [[mev_path]]
+        name = "USDT->USDC->SOL"
+        path = [
+            { pool = "FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ", direction = "BtoA" },
+            { pool = "EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U", direction = "BtoA" },
+        ]
+    "#,
+        )
+        .expect("Failed to deserialize");
+
+        let expected_mev_config = MevConfig {
+            config_version: 1,
+            log_path: PathBuf::from_str("/tmp/mev.log").unwrap(),
+            log_compression: LogCompression::None,
+            log_channel_capacity: None,
+            log_channel_policy: LogChannelPolicy::Block,
+            lock_impact_sampling_rate: None,
+            watched_programs: vec![B58Pubkey(
+                Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap(),
+            )],
+            watched_accounts: Vec::new(),
+            blocked_signers: Vec::new(),
+            blocked_pools: Vec::new(),
+            orca_accounts: AllOrcaPoolAddresses::new(vec![
+                OrcaPoolAddresses {
+                    program_id: Pubkey::default(),
+                    address: Pubkey::from_str("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ")
+                        .unwrap(),
+                    pool_a_account: Pubkey::from_str(
+                        "EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82",
+                    )
+                    .unwrap(),
+                    pool_b_account: Pubkey::from_str(
+                        "C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe",
+                    )
+                    .unwrap(),
+                    pool_mint: Pubkey::from_str("33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM")
+                        .unwrap(),
+                    pool_fee: Pubkey::from_str("GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr")
+                        .unwrap(),
+                    pool_a_mint: Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v")
+                        .unwrap(),
+                    pool_b_mint: Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB")
+                        .unwrap(),
+                    ..Default::default()
+                },
+                OrcaPoolAddresses {
+                    program_id: Pubkey::default(),
+                    address: Pubkey::from_str("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U")
+                        .unwrap(),
+                    pool_a_account: Pubkey::from_str(
+                        "ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHoNd13Lg",
+                    )
+                    .unwrap(),
+                    pool_b_account: Pubkey::from_str(
+                        "75HgnSvXbWKZBpZHveX68ZzAhDqMzNDS29X6BGLtxMo1",
+                    )
+                    .unwrap(),
+                    pool_mint: Pubkey::from_str("APDFRM3HMr8CAGXwKHiu2f5ePSpaiEJhaURwhsRrUUt9")
+                        .unwrap(),
+                    pool_fee: Pubkey::from_str("8JnSiuvQq3BVuCU3n4DrSTw9chBSPvEMswrhtifVkr1o")
+                        .unwrap(),
+                    ..Default::default()
+                },
+            ]),
+            mev_paths: vec![MevPath {
+                name: "USDT->USDC->SOL".to_owned(),
+                path: vec![
+                    PairInfo {
+                        pool: Pubkey::from_str("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ")
+                            .unwrap(),
+                        alternative_pools: vec![],
+                        group: None,
+                        direction: TradeDirection::BtoA,
+                    },
+                    PairInfo {
+                        pool: Pubkey::from_str("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U")
+                            .unwrap(),
+                        alternative_pools: vec![],
+                        group: None,
+                        direction: TradeDirection::BtoA,
+                    },
+                ],
+                split_parts: None,
+                require_confirmations: None,
+                max_input: None,
+                slippage_bps: None,
+                compute_unit_price: None,
+                compute_unit_limit: None,
+                enabled: true,
+                probe_reverse: false,
+            }],
+            pool_groups: Vec::new(),
+            auto_discover_paths: None,
+            user_authority_path: None,
+            signer: None,
+            user_authorities: Vec::new(),
+            signing_thread_wait_ms: None,
+            derive_token_accounts_from_authority: false,
+            minimum_profit: vec![(
+                TokenRef::Mint(
+                    Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap(),
+                ),
+                TokenAmount::Raw(101),
+            )]
+            .into_iter()
+            .collect(),
+            tokens: HashMap::new(),
+            decimals: HashMap::new(),
+            default_minimum_profit: None,
+            max_inflight_exposure: HashMap::new(),
+            max_mev_txs_per_slot: None,
+            max_opportunities_per_trigger: None,
+            opportunity_conflict: OpportunityConflict::Pool,
+            min_trigger_delta: None,
+            priority_fee: None,
+            slippage_bps: None,
+            compute_unit_limit: None,
+            geyser_notifications: false,
+            watchdog_trigger_slots: None,
+            watchdog_opportunity_slots: None,
+            confirm_trigger_before_craft: false,
+            audit_mode: false,
+            dry_run: false,
+            exact_optimal_input: false,
+            min_pool_reserve: None,
+            max_hop_output_bps: None,
+            log_skipped_paths: false,
+            checkpoint_interval_slots: None,
+            clock_skew_threshold_ms: None,
+            opportunity_debounce_slots: None,
+            fallback_rpc_url: None,
+            fallback_when_not_leader: false,
+            systemic_failure: None,
+            cu_calibration_file: None,
+            reporting: None,
+        };
+        assert_eq!(sample_config, expected_mev_config);
+    }
+
+    #[test]
+    fn test_config_error_location() {
+        let err = parse_mev_config(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = ['9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP']
+    minimum_profit = {}
+    mev_path = []
+
+    [[orca_account]]
+        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+
+    [[orca_account]]
+        address = 'EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U'
+        pool_a_account = 'ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHo'
+        pool_b_account = '75HgnSvXbWKZBpZHveX68ZzAhDqMzNDS29X6BGLtxMo1'
+        pool_mint = 'APDFRM3HMr8CAGXwKHiu2f5ePSpaiEJhaURwhsRrUUt9'
+        pool_fee = '8JnSiuvQq3BVuCU3n4DrSTw9chBSPvEMswrhtifVkr1o'
+    "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "orca_account[1].pool_a_account: public key \
+            \"ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHo\" should be 32 bytes, but it is 28 bytes \
+            for key `orca_account.pool_a_account` at line 15 column 5"
+        );
+    }
+
+    #[test]
+    fn test_unknown_field_is_rejected() {
+        let err = parse_mev_config(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+    minimun_profit = {}
+    "#,
+        )
+        .unwrap_err();
+        assert!(err.contains("unknown field `minimun_profit`"), "{}", err);
+    }
+
+    #[test]
+    fn test_config_version() {
+        let parse = |version: &str| {
+            parse_mev_config(&format!(
+                r#"
+    {}
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+    minimum_profit = {{}}
+    "#,
+                version
+            ))
+        };
+        assert!(parse("config_version = 1").is_ok());
+
+        let err = parse("").unwrap_err();
+        assert!(err.starts_with("config_version: missing"), "{}", err);
+        assert!(err.contains("reads MEV config version 1"), "{}", err);
+        assert!(err.contains("Config versions"), "{}", err);
+
+        let err = parse("config_version = 0").unwrap_err();
+        assert!(
+            err.starts_with("config_version: 0 is not supported"),
+            "{}",
+            err
+        );
+        assert!(err.contains("Config versions"), "{}", err);
+
+        let err = parse("config_version = 2").unwrap_err();
+        assert!(
+            err.starts_with("config_version: 2 is newer than this validator"),
+            "{}",
+            err
+        );
+
+        let err = parse("config_version = '1'").unwrap_err();
+        assert!(
+            err.starts_with("config_version: \"1\" is not supported"),
+            "{}",
+            err
+        );
+
+        // The version is checked first, an older config that also uses a
+        // field that no longer exists gets the version error.
+        let err = parse("config_version = 0\norca_accounts = []").unwrap_err();
+        assert!(err.starts_with("config_version: 0"), "{}", err);
+    }
+
+    #[test]
+    fn test_pool_role() {
+        let config = parse_mev_config(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    minimum_profit = {}
+    mev_path = []
+
+    [[orca_account]]
+        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+
+    [[orca_account]]
+        address = 'EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U'
+        pool_a_account = 'ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHoNd13Lg'
+        pool_b_account = '75HgnSvXbWKZBpZHveX68ZzAhDqMzNDS29X6BGLtxMo1'
+        pool_mint = 'APDFRM3HMr8CAGXwKHiu2f5ePSpaiEJhaURwhsRrUUt9'
+        pool_fee = '8JnSiuvQq3BVuCU3n4DrSTw9chBSPvEMswrhtifVkr1o'
+        role = 'observe'
+    "#,
+        )
+        .unwrap();
+        let roles: Vec<PoolRole> = config.orca_accounts.iter().map(|pool| pool.role).collect();
+        assert_eq!(roles, vec![PoolRole::Trade, PoolRole::Observe]);
+    }
+
+    #[test]
+    fn test_orca_pool_lookups() {
+        let (usdc, usdt, sol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let our_usdc = Pubkey::new_unique();
+        let pool = |pool_a_mint, pool_b_mint, source| OrcaPoolAddresses {
+            address: Pubkey::new_unique(),
+            pool_a_account: Pubkey::new_unique(),
+            pool_b_account: Pubkey::new_unique(),
+            source,
+            pool_a_mint,
+            pool_b_mint,
+            ..OrcaPoolAddresses::default()
+        };
+        // Two pools share the USDC/USDT pair, and three pools share USDC.
+        let pools = AllOrcaPoolAddresses::new(vec![
+            pool(usdc, usdt, Some(our_usdc)),
+            pool(sol, usdc, None),
+            pool(usdt, usdc, None),
+            pool(usdc, sol, Some(our_usdc)),
+            pool(Pubkey::default(), Pubkey::default(), None),
+        ]);
+        let addresses = |pools: Vec<&OrcaPoolAddresses>| -> Vec<Pubkey> {
+            pools.iter().map(|pool| pool.address).collect()
+        };
+        let all: Vec<&OrcaPoolAddresses> = pools.iter().collect();
+        assert_eq!(pools.len(), 5);
+
+        assert_eq!(pools.by_address(&all[2].address), Some(all[2]));
+        assert_eq!(pools.by_address(&Pubkey::new_unique()), None);
+
+        assert_eq!(
+            addresses(pools.by_token_account(&all[1].pool_b_account).collect()),
+            vec![all[1].address]
+        );
+        assert_eq!(
+            addresses(pools.by_token_account(&our_usdc).collect()),
+            vec![all[0].address, all[3].address]
+        );
+        assert_eq!(pools.by_token_account(&usdc).count(), 0);
+
+        // The pair is the same in both directions.
+        assert_eq!(
+            addresses(pools.by_mint_pair(&usdc, &usdt).collect()),
+            vec![all[0].address, all[2].address]
+        );
+        assert_eq!(
+            addresses(pools.by_mint_pair(&usdt, &usdc).collect()),
+            vec![all[0].address, all[2].address]
+        );
+        assert_eq!(
+            addresses(pools.by_mint_pair(&sol, &usdc).collect()),
+            vec![all[1].address, all[3].address]
+        );
+        assert_eq!(pools.by_mint_pair(&sol, &usdt).count(), 0);
+        // Pools without mints in the config are not indexed by mint.
+        assert_eq!(
+            pools
+                .by_mint_pair(&Pubkey::default(), &Pubkey::default())
+                .count(),
+            0
+        );
+
+        // A pool that is listed twice is found once, as its first occurrence.
+        let first = pool(usdc, usdt, Some(our_usdc));
+        let duplicate = OrcaPoolAddresses {
+            address: first.address,
+            ..pool(usdc, usdt, None)
+        };
+        let address = first.address;
+        let pools = AllOrcaPoolAddresses::new(vec![first, pool(sol, usdc, None), duplicate]);
+        assert_eq!(pools.len(), 3);
+        assert_eq!(pools.unique().count(), 2);
+        assert_eq!(pools.by_address(&address).unwrap().source, Some(our_usdc));
+    }
+
+    /// A config with the `[[orca_account]]`s `first` and `second`.
+    fn config_with_pools(first: &str, second: &str) -> Result<MevConfig, String> {
+        parse_mev_config(&format!(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    minimum_profit = {{}}
+    mev_path = []
+
+    [[orca_account]]
+    {}
+
+    [[orca_account]]
+    {}
+    "#,
+            first, second
+        ))
+    }
+
+    const POOL: &str = r#"
+        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+    "#;
+
+    #[test]
+    fn test_duplicate_pools_are_rejected() {
+        // The `_id` is only a comment, the entries are the same pool.
+        let err = config_with_pools(POOL, &format!("_id = 'again'\n{}", POOL)).unwrap_err();
+        assert!(
+            err.starts_with(
+                "orca_account: pool FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ is listed 2 times"
+            ),
+            "{}",
+            err
+        );
+
+        // Entries for the same pool that differ are rejected alike.
+        let conflicting = format!(
+            "{}\nsource = '7kbnvuGBxxj8AG9qp8Scn56muWGaRaFqxg1FsRp3PaFT'\nrole = 'observe'",
+            POOL
+        );
+        let err = config_with_pools(POOL, &conflicting).unwrap_err();
+        assert!(
+            err.starts_with(
+                "orca_account: pool FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ is listed 2 times"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_shared_pool_token_accounts_are_rejected() {
+        // Another pool, copied from `POOL` with only its address changed.
+        let copied = POOL.replace(
+            "FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ",
+            "71zvJycCiY2JRRwKr27oiu48mFzrstCoP6riGEyCyEB2",
+        );
+        let err = config_with_pools(POOL, &copied).unwrap_err();
+        assert!(
+            err.starts_with(
+                "orca_account: token account EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82 \
+                belongs to both pool FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ and pool \
+                71zvJycCiY2JRRwKr27oiu48mFzrstCoP6riGEyCyEB2; token account \
+                C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe belongs to both"
+            ),
+            "{}",
+            err
+        );
+
+        const OTHER_POOL: &str = r#"
+        address = '71zvJycCiY2JRRwKr27oiu48mFzrstCoP6riGEyCyEB2'
+        pool_a_account = 'HQ2XUmQefvBdpN8nseBSWNP2D1crncodLL73AWnYBiSy'
+        pool_b_account = '8y8X4JuZn1MckRo5J6rirpr2Dxj1RKQshj7VzuX6dMUw'
+        pool_mint = '4jjQSgFx33DUb1a7pgPsi3FbtZXDQ94b6QywjNK3NtZw'
+        pool_fee = '7nxYhYUaD7og4rYce263CCPh9pPTnGixfBtQrXE7UUvZ'
+    "#;
+        // Our own token account as the source of both pools is fine.
+        let our_source = "source = '7kbnvuGBxxj8AG9qp8Scn56muWGaRaFqxg1FsRp3PaFT'";
+        let config = config_with_pools(
+            &format!("{}\n{}", POOL, our_source),
+            &format!("{}\n{}", OTHER_POOL, our_source),
+        )
+        .unwrap();
+        assert_eq!(config.orca_accounts.len(), 2);
+
+        // A token account of one pool as the source of the other is not.
+        let err = config_with_pools(
+            POOL,
+            &format!(
+                "{}\nsource = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'",
+                OTHER_POOL
+            ),
+        )
+        .unwrap_err();
+        assert!(
+            err.starts_with(
+                "orca_account: the source of pool 71zvJycCiY2JRRwKr27oiu48mFzrstCoP6riGEyCyEB2 \
+                is token account EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82 of pool \
+                FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_deserialize_orca_pool_mints() {
+        let config = parse_mev_config(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    minimum_profit = {}
+    mev_path = []
+
+    [[orca_account]]
+        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+        pool_a_mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v'
+        pool_b_mint = 'Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB'
+    "#,
+        )
+        .unwrap();
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let usdt = Pubkey::from_str("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB").unwrap();
+        let pool = config
+            .orca_accounts
+            .by_mint_pair(&usdt, &usdc)
+            .next()
+            .unwrap();
+        assert_eq!(
+            pool.address,
+            Pubkey::from_str("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_minimum_profit_token_symbols() {
+        let parse = |tokens: &str, minimum_profit: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+
+    [tokens]
+    {}
+
+    [minimum_profit]
+    {}
+    "#,
+                tokens, minimum_profit
+            ))
+        };
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+        let tokens = "
+    USDC = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v'
+    SOL = 'So11111111111111111111111111111111111111112'
+    stSOL = '7dHbWXmci3dT8UFYWYZweBLXgycu7Y3iL6trKn1Y7ARj'";
+
+        // Symbols and mints can be mixed.
+        let config = parse(
+            tokens,
+            "
+    USDC = 101
+    'So11111111111111111111111111111111111111112' = 2501",
+        )
+        .unwrap();
+        assert_eq!(
+            config.minimum_profit_by_mint(),
+            Ok(vec![(usdc, 101), (sol, 2501)].into_iter().collect())
+        );
+
+        assert_eq!(
+            parse(tokens, "USDT = 101").unwrap_err(),
+            "minimum_profit: \"USDT\" is neither a mint nor a symbol in tokens, \
+            the known symbols are [SOL, USDC, stSOL]"
+        );
+        // The same mint twice, by symbol and by mint.
+        assert_eq!(
+            parse(
+                tokens,
+                "
+    SOL = 1
+    'So11111111111111111111111111111111111111112' = 2"
+            )
+            .unwrap_err(),
+            "minimum_profit: SOL and So11111111111111111111111111111111111111112 are both mint \
+            So11111111111111111111111111111111111111112"
+        );
+    }
+
+    #[test]
+    fn test_thresholds_in_whole_tokens() {
+        let parse = |thresholds: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+    {}
+
+    [tokens]
+    USDC = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v'
+    SOL = 'So11111111111111111111111111111111111111112'
+    "#,
+                thresholds
+            ))
+        };
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let sol = Pubkey::from_str("So11111111111111111111111111111111111111112").unwrap();
+
+        // Integers stay in the smallest unit, and need no decimals.
+        let config = parse("[minimum_profit]\nUSDC = 1000000").unwrap();
+        assert_eq!(
+            config.minimum_profit_by_mint(),
+            Ok([(usdc, 1_000_000)].into_iter().collect())
+        );
+
+        // Strings are in whole tokens, with the decimals by symbol or mint.
+        let config = parse(
+            "
+    max_inflight_exposure = { 'So11111111111111111111111111111111111111112' = '2.5' }
+
+    [decimals]
+    USDC = 6
+    'So11111111111111111111111111111111111111112' = 9
+
+    [minimum_profit]
+    USDC = '1.0'
+    SOL = '0.000000001'
+
+    [min_trigger_delta.amounts]
+    USDC = '0.25'",
+        )
+        .unwrap();
+        let summary = config.threshold_summary().unwrap();
+        let by_mint = |amounts: &[(Pubkey, u64)]| -> BTreeMap<String, u64> {
+            amounts
+                .iter()
+                .map(|(mint, amount)| (mint.to_string(), *amount))
+                .collect()
+        };
+        assert_eq!(
+            summary,
+            ThresholdSummary {
+                minimum_profit: by_mint(&[(usdc, 1_000_000), (sol, 1)]),
+                default_minimum_profit: None,
+                max_inflight_exposure: by_mint(&[(sol, 2_500_000_000)]),
+                min_trigger_delta_amounts: by_mint(&[(usdc, 250_000)]),
+            }
+        );
+        // What we log at startup reads back the same.
+        let logged = serde_json::to_string(&summary).unwrap();
+        assert!(
+            logged.contains(&format!("\"{}\":1000000", usdc)),
+            "{}",
+            logged
+        );
+        assert_eq!(
+            serde_json::from_str::<ThresholdSummary>(&logged).unwrap(),
+            summary
+        );
+
+        assert_eq!(
+            parse("[minimum_profit]\nUSDC = '1.5'").unwrap_err(),
+            "minimum_profit: \"1.5\" for USDC is in whole tokens, but the decimals of mint \
+            EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v are unknown, set them in [decimals]"
+        );
+        assert_eq!(
+            parse("[decimals]\nUSDC = 6\n[minimum_profit]\nUSDC = '0.0000001'").unwrap_err(),
+            "minimum_profit: \"0.0000001\" has more than 6 decimals"
+        );
+        assert!(parse("[minimum_profit]\nUSDC = '1,5'")
+            .unwrap_err()
+            .contains("\"1,5\" is not a decimal amount"));
+        assert!(parse("[minimum_profit]\nUSDC = -1").is_err());
+    }
+
+    #[test]
+    fn test_legacy_minimum_profit_array() {
+        let parse = |minimum_profit: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+    minimum_profit = {}
+    "#,
+                minimum_profit
+            ))
+        };
+        let usdc = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+        let sol = "So11111111111111111111111111111111111111112";
+
+        // The array of pairs reads the same as the table.
+        let legacy = parse(&format!("[['{}', 101], ['{}', 2501]]", usdc, sol)).unwrap();
+        let table = parse(&format!("{{ '{}' = 101, '{}' = 2501 }}", usdc, sol)).unwrap();
+        assert_eq!(legacy.minimum_profit, table.minimum_profit);
+        assert_eq!(
+            legacy.minimum_profit_by_mint(),
+            Ok(vec![
+                (Pubkey::from_str(usdc).unwrap(), 101),
+                (Pubkey::from_str(sol).unwrap(), 2501)
+            ]
+            .into_iter()
+            .collect())
+        );
+
+        // In the array, the same token twice used to silently keep the last.
+        let err = parse(&format!("[['{}', 101], ['{}', 5]]", usdc, usdc)).unwrap_err();
+        assert!(
+            err.starts_with(&format!("minimum_profit: duplicate token {}", usdc)),
+            "{}",
+            err
+        );
+        // In the table, TOML already rejects the duplicate key.
+        assert!(parse(&format!("{{ '{}' = 101, '{}' = 5 }}", usdc, usdc)).is_err());
+        assert!(parse(&format!("[['{}']]", usdc)).is_err());
+        assert!(parse("5").is_err());
+    }
+
+    #[test]
+    fn test_minimum_profit_with_decimals() {
+        let parse = |minimum_profit: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+    minimum_profit = {}
+
+    [tokens]
+    USDC = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v'
+    "#,
+                minimum_profit
+            ))
+        };
+        let usdc = Pubkey::from_str("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap();
+        let half_usdc: Result<HashMap<Pubkey, u64>, String> =
+            Ok([(usdc, 500_000)].into_iter().collect());
+
+        let config = parse(
+            "[{ mint = 'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v', ui_amount = 0.5, \
+            decimals = 6 }]",
+        )
+        .unwrap();
+        assert_eq!(config.minimum_profit_by_mint(), half_usdc);
+        // The same as an entry of the table, by symbol, and in a string.
+        let config = parse("{ USDC = { ui_amount = 0.5, decimals = 6 } }").unwrap();
+        assert_eq!(config.minimum_profit_by_mint(), half_usdc);
+        let config = parse("{ USDC = { ui_amount = '0.5', decimals = 6 } }").unwrap();
+        assert_eq!(config.minimum_profit_by_mint(), half_usdc);
+        let config = parse("{ USDC = { ui_amount = 2, decimals = 6 } }").unwrap();
+        assert_eq!(
+            config.minimum_profit_by_mint(),
+            Ok([(usdc, 2_000_000)].into_iter().collect())
+        );
+
+        assert_eq!(
+            parse("{ USDC = { ui_amount = 0.0000001, decimals = 6 } }").unwrap_err(),
+            "minimum_profit: \"0.0000001\" has more than 6 decimals"
+        );
+        assert!(parse("{ USDC = { ui_amount = -0.5, decimals = 6 } }").is_err());
+        assert!(parse("{ USDC = { ui_amount = 0.5 } }").is_err());
+        assert!(parse("{ USDC = { ui_amount = 0.5, decimals = 6, mint = 'x' } }").is_err());
+    }
+
+    #[test]
+    fn test_decimals_must_agree() {
+        let err = parse_mev_config(
+            r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    mev_path = []
+    orca_account = []
+
+    [decimals]
+    'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' = 9
+
+    [minimum_profit]
+    'EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v' = { ui_amount = 0.5, decimals = 6 }
+    "#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "minimum_profit: \"0.5\" with 6 decimals for \
+            EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v does not match the 9 decimals of \
+            mint EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v in [decimals]"
+        );
+    }
+
+    #[test]
+    fn test_path_tx_settings() {
+        let parse = |settings: &str, path_settings: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    minimum_profit = {{}}
+    orca_account = []
+    {}
+
+    [[mev_path]]
+        name = "stable"
+        path = [
+            {{ pool = "FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ", direction = "BtoA" }},
+        ]
+        {}
+    "#,
+                settings, path_settings
+            ))
+        };
+
+        let config = parse(
+            "slippage_bps = 100\n    compute_unit_limit = 300000",
+            "slippage_bps = 5\n        compute_unit_price = 2000",
+        )
+        .unwrap();
+        assert_eq!(config.slippage_bps, Some(100));
+        assert_eq!(config.compute_unit_limit, Some(300_000));
+        let path = &config.mev_paths[0];
+        assert_eq!(path.slippage_bps, Some(5));
+        assert_eq!(path.compute_unit_price, Some(2_000));
+        assert_eq!(path.compute_unit_limit, None);
+
+        // Without overrides, the path leaves everything to the global config.
+        let config = parse("", "").unwrap();
+        assert_eq!(config.slippage_bps, None);
+        assert_eq!(config.compute_unit_limit, None);
+        let path = &config.mev_paths[0];
+        assert_eq!(
+            (
+                path.slippage_bps,
+                path.compute_unit_price,
+                path.compute_unit_limit
+            ),
+            (None, None, None)
+        );
+
+        assert_eq!(
+            parse("slippage_bps = 10001", "").unwrap_err(),
+            "slippage_bps: 10001 is more than 10000"
+        );
+        assert_eq!(
+            parse("", "compute_unit_limit = 0").unwrap_err(),
+            "mev_path[0].compute_unit_limit: 0 is not between 1 and 1400000"
+        );
+        assert!(parse("", "slippage_bps = -1").is_err());
+        assert_eq!(
+            parse("max_hop_output_bps = 9000", "")
+                .unwrap()
+                .max_hop_output_bps,
+            Some(9_000)
+        );
+        assert_eq!(
+            parse("max_hop_output_bps = 0", "").unwrap_err(),
+            "max_hop_output_bps: 0 is not between 1 and 10000"
+        );
+
+        let profit_share = |share: &str| {
+            parse(
+                &format!(
+                    "[priority_fee]\n    policy = {{ profit_share = {} }}\n    max_compute_unit_price = 100",
+                    share
+                ),
+                "",
+            )
+        };
+        assert!(profit_share("0.5").is_ok());
+        assert_eq!(
+            profit_share("1.5").unwrap_err(),
+            "priority_fee.policy.profit_share: 1.5 is not more than 0 and at most 1"
+        );
+        assert!(profit_share("0.0").is_err());
+    }
+
+    #[test]
+    fn test_pool_groups() {
+        let parse = |groups: &str, hop: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = []
+    minimum_profit = {{}}
+    orca_account = []
+    {}
+
+    [[mev_path]]
+        name = "stable"
+        path = [
+            {},
+            {{ pool = "EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U", direction = "BtoA" }},
+        ]
+    "#,
+                groups, hop
+            ))
+        };
+        let group = r#"
+    [[pool_group]]
+        name = "usdc-usdt"
+        pools = [
+            "FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ",
+            "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
+        ]"#;
+        let group_hop = r#"{ group = "usdc-usdt", direction = "AtoB" }"#;
+
+        let config = parse(group, group_hop).unwrap();
+        let hop = &config.mev_paths[0].path[0];
+        assert_eq!(hop.group.as_deref(), Some("usdc-usdt"));
+        assert_eq!(
+            hop.pool,
+            Pubkey::from_str("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ").unwrap()
+        );
+        assert_eq!(
+            hop.alternative_pools,
+            vec![Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap()]
+        );
+        assert_eq!(config.mev_paths[0].swap_count(), 3);
+        // Hops that set a pool are left alone.
+        assert_eq!(config.mev_paths[0].path[1].group, None);
+
+        assert_eq!(
+            parse("", group_hop).unwrap_err(),
+            "MEV path stable trades through pool group usdc-usdt, which is not defined"
+        );
+        let err = parse(
+            group,
+            r#"{ pool = "FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ", group = "usdc-usdt", direction = "AtoB" }"#,
+        )
+        .unwrap_err();
+        assert!(
+            err.contains("A hop should set either pool or group, not both"),
+            "{}",
+            err
+        );
+        let err = parse(group, r#"{ direction = "AtoB" }"#).unwrap_err();
+        assert!(
+            err.contains("A hop should set a pool or a group"),
+            "{}",
+            err
+        );
+        assert_eq!(
+            parse(&format!("{}\n{}", group, group), group_hop).unwrap_err(),
+            "pool_group usdc-usdt is defined more than once"
+        );
+        assert_eq!(
+            parse(
+                "[[pool_group]]\n    name = \"usdc-usdt\"\n    pools = []",
+                group_hop
+            )
+            .unwrap_err(),
+            "pool_group usdc-usdt: it has no pools"
+        );
+        assert_eq!(
+            parse(
+                "[[pool_group]]\n    name = \"usdc-usdt\"\n    pools = [\
+                \"FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ\", \
+                \"FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ\"]",
+                group_hop
+            )
+            .unwrap_err(),
+            "pool_group usdc-usdt: pool FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ is listed \
+            more than once"
+        );
+    }
+
+    #[test]
+    fn test_load_user_authority() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let keypair = Keypair::new();
+        let path = dir.path().join("authority.json");
+        write_keypair_file(&keypair, &path).unwrap();
+        let signer = load_user_authority(Some(path), None).unwrap().unwrap();
+        assert_eq!(signer.pubkey(), keypair.pubkey());
+        assert!(load_user_authority(None, None).unwrap().is_none());
+
+        // Errors name the path that we tried to open.
+        let missing = dir.path().join("missing.json");
+        let err = load_user_authority(Some(missing.clone()), None).unwrap_err();
+        assert!(
+            err.starts_with(&format!(
+                "Could not open user authority {}: ",
+                missing.display()
+            )),
+            "{}",
+            err
+        );
+        let err = load_user_authority(Some(PathBuf::from("${MEV_TEST_UNSET_DIR}/a.json")), None)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid user_authority_path: path ${MEV_TEST_UNSET_DIR}/a.json uses the \
+            environment variable \"MEV_TEST_UNSET_DIR\", but it is not set"
+        );
+    }
+}
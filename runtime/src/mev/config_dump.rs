@@ -0,0 +1,223 @@
+//! The config that a `Mev` runs with, after defaults and what we derive from
+//! the config, as we log it in the `config` event, see `Mev::dump_config`.
+
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use super::{
+    arbitrage::MevPath,
+    config::{OpportunityConflict, ThresholdSummary},
+    discovery::AutoDiscoverConfig,
+    pool_state::OrcaPoolAddresses,
+    priority_fee::PriorityFeeConfig,
+    utils::{serialize_opt_b58, B58Pubkey},
+};
+
+/// Everything that decides what a `Mev` does with a trigger, so that the
+/// log alone tells which config a validator ran with, also after a reload.
+/// Sets of keys are sorted, so that equal configs give equal dumps.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConfigDump {
+    /// The reload that this config came from, 0 for the config at startup.
+    pub config_generation: u64,
+
+    pub watched_programs: Vec<B58Pubkey>,
+
+    /// The accounts of the pools, and the `watched_accounts`.
+    pub trigger_accounts: Vec<B58Pubkey>,
+
+    pub blocked_signers: Vec<B58Pubkey>,
+    pub blocked_pools: Vec<B58Pubkey>,
+
+    /// The default user authority, `null` when we only log opportunities.
+    #[serde(serialize_with = "serialize_opt_b58")]
+    pub user_authority: Option<Pubkey>,
+
+    /// The thresholds in the smallest unit of each token.
+    pub thresholds: ThresholdSummary,
+    pub min_trigger_delta_bps: Option<u16>,
+
+    pub priority_fee: Option<PriorityFeeConfig>,
+    pub slippage_bps: Option<u16>,
+    pub compute_unit_limit: Option<u32>,
+    pub max_mev_txs_per_slot: Option<u64>,
+    pub max_opportunities_per_trigger: u8,
+    pub opportunity_conflict: OpportunityConflict,
+    pub confirm_trigger_before_craft: bool,
+    pub fallback_when_not_leader: bool,
+    pub audit_mode: bool,
+    pub dry_run: bool,
+    pub exact_optimal_input: bool,
+    pub min_pool_reserve: u64,
+    pub max_hop_output_bps: u16,
+    pub log_skipped_paths: bool,
+    pub auto_discover_paths: Option<AutoDiscoverConfig>,
+
+    /// In config order, with the token accounts that we derived.
+    pub pools: Vec<PoolDump>,
+
+    /// In config order, then the paths that we discovered.
+    pub paths: Vec<PathDump>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoolDump {
+    #[serde(flatten)]
+    pub pool: OrcaPoolAddresses,
+
+    /// The authority that signs for the pool, from `[[user_authority]]` or
+    /// the default one.
+    #[serde(serialize_with = "serialize_opt_b58")]
+    pub user_authority: Option<Pubkey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PathDump {
+    pub path: MevPath,
+
+    /// The authority that signs the transactions of the path.
+    #[serde(serialize_with = "serialize_opt_b58")]
+    pub user_authority: Option<Pubkey>,
+
+    /// The path's overrides, or the global settings.
+    pub slippage_bps: Option<u16>,
+    pub compute_unit_limit: Option<u32>,
+}
+
+/// `pubkeys` sorted as they read in the dump, for the sets in the dump.
+pub fn sorted_b58<'a>(pubkeys: impl IntoIterator<Item = &'a Pubkey>) -> Vec<B58Pubkey> {
+    let mut pubkeys: Vec<B58Pubkey> = pubkeys
+        .into_iter()
+        .map(|pubkey| B58Pubkey(*pubkey))
+        .collect();
+    pubkeys.sort_by_cached_key(|pubkey| pubkey.0.to_string());
+    pubkeys
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+
+    use crate::mev::{config::parse_mev_config, Mev};
+
+    #[test]
+    fn test_config_dump_serialization() {
+        let config = parse_mev_config(
+            r#"
+            config_version = 1
+            log_path = '/tmp/mev.log'
+            watched_programs = ['9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP']
+            blocked_signers = ['DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1']
+            default_minimum_profit = 1
+            slippage_bps = 50
+
+            [minimum_profit]
+            'So11111111111111111111111111111111111111112' = 2501
+
+            [[orca_account]]
+            address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+            pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+            pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+            pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+            pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+
+            [[orca_account]]
+            address = '4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM'
+            pool_a_account = '8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh'
+            pool_b_account = 'CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3'
+            pool_mint = '7kbnvuGBxxj8AG9qp8Scn56muWGaRaFqxg1FsRp3PaFT'
+            pool_fee = 'B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy'
+
+            [[mev_path]]
+            name = 'round trip'
+            slippage_bps = 10
+            path = [
+                { pool = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ', direction = 'AtoB' },
+                { pool = '4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM', direction = 'BtoA' },
+            ]
+            "#,
+        )
+        .unwrap();
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, config);
+
+        let expected_result_str = "\
+        {\
+            'config_generation':0,\
+            'watched_programs':['9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP'],\
+            'trigger_accounts':[\
+              '4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM',\
+              '8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh',\
+              'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe',\
+              'CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3',\
+              'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82',\
+              'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'\
+            ],\
+            'blocked_signers':['DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1'],\
+            'blocked_pools':[],\
+            'user_authority':null,\
+            'thresholds':{\
+              'minimum_profit':{'So11111111111111111111111111111111111111112':2501},\
+              'default_minimum_profit':1,\
+              'max_inflight_exposure':{},\
+              'min_trigger_delta_amounts':{}\
+            },\
+            'min_trigger_delta_bps':null,\
+            'priority_fee':null,\
+            'slippage_bps':50,\
+            'compute_unit_limit':null,\
+            'max_mev_txs_per_slot':null,\
+            'max_opportunities_per_trigger':1,\
+            'opportunity_conflict':'pool',\
+            'confirm_trigger_before_craft':false,\
+            'fallback_when_not_leader':false,\
+            'audit_mode':false,\
+            'dry_run':false,\
+            'exact_optimal_input':false,\
+            'min_pool_reserve':0,\
+            'max_hop_output_bps':10000,\
+            'log_skipped_paths':false,\
+            'auto_discover_paths':null,\
+            'pools':[\
+              {\
+                'address':'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ',\
+                'pool_a_account':'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82',\
+                'pool_b_account':'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe',\
+                'source':null,\
+                'destination':null,\
+                'pool_mint':'33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM',\
+                'pool_fee':'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr',\
+                'user_authority':null\
+              },\
+              {\
+                'address':'4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM',\
+                'pool_a_account':'8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh',\
+                'pool_b_account':'CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3',\
+                'source':null,\
+                'destination':null,\
+                'pool_mint':'7kbnvuGBxxj8AG9qp8Scn56muWGaRaFqxg1FsRp3PaFT',\
+                'pool_fee':'B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy',\
+                'user_authority':null\
+              }\
+            ],\
+            'paths':[\
+              {\
+                'path':{\
+                  'name':'round trip',\
+                  'path':[\
+                    {'pool':'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ','direction':'AtoB'},\
+                    {'pool':'4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM','direction':'BtoA'}\
+                  ],\
+                  'slippage_bps':10\
+                },\
+                'user_authority':null,\
+                'slippage_bps':10,\
+                'compute_unit_limit':null\
+              }\
+            ]\
+          }"
+        .replace('\'', "\"");
+        let serialized_json = serde_json::to_string(&mev.dump_config()).unwrap();
+        assert_eq!(serialized_json, expected_result_str);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use solana_sdk::clock::Slot;
+
+use super::engine::MARGINAL_PRICE_QUANTUM;
+
+/// We compare the optimal inputs of opportunities in steps of this fraction of
+/// the input, so that the bucket of a large input is as wide as that of a
+/// small one, relatively.
+pub const OPTIMAL_INPUT_QUANTUM: f64 = 0.01;
+
+/// Opportunities on a path with the same quantized marginal price and optimal
+/// input are in the same bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bucket {
+    marginal_price: i64,
+    optimal_input: i64,
+}
+
+impl Bucket {
+    fn new(marginal_price: f64, optimal_input: f64) -> Self {
+        Bucket {
+            marginal_price: (marginal_price / MARGINAL_PRICE_QUANTUM).round() as i64,
+            optimal_input: (optimal_input.max(1.0).ln() / OPTIMAL_INPUT_QUANTUM.ln_1p()).round()
+                as i64,
+        }
+    }
+}
+
+/// Written to the log when a bucket with more than one opportunity ends. We
+/// logged the first opportunity in the bucket as usual, and not the others.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AggregatedOpportunity {
+    // Index from the Path vector.
+    #[serde(skip)]
+    pub path_idx: usize,
+
+    /// The slots of the first and the last opportunity in the bucket.
+    pub first_slot: Slot,
+    pub last_slot: Slot,
+
+    /// How many opportunities were in the bucket, including the first one.
+    pub occurrences: u64,
+
+    /// The marginal price and the optimal input of the first opportunity.
+    pub marginal_price: f64,
+    pub optimal_input: f64,
+}
+
+#[derive(Debug)]
+struct PendingBucket {
+    bucket: Bucket,
+    aggregate: AggregatedOpportunity,
+}
+
+impl PendingBucket {
+    /// The aggregate to log when the bucket ends, if we left out any
+    /// opportunity.
+    fn into_aggregate(self) -> Option<AggregatedOpportunity> {
+        (self.aggregate.occurrences > 1).then(|| self.aggregate)
+    }
+}
+
+/// Leaves out the opportunities on a path that are in the same bucket as the
+/// one before it, for `window_slots` slots after the first one in the bucket.
+/// This only affects what we log, we craft transactions as usual.
+#[derive(Debug)]
+pub struct OpportunityDebouncer {
+    window_slots: Option<u64>,
+
+    // The bucket that the last opportunity on a path was in, at most one per
+    // path, so this is bounded by the paths in the config.
+    by_path: HashMap<usize, PendingBucket>,
+}
+
+impl OpportunityDebouncer {
+    /// Without `window_slots`, or with 0, we log every opportunity.
+    pub fn new(window_slots: Option<u64>) -> Self {
+        OpportunityDebouncer {
+            window_slots: window_slots.filter(|window_slots| *window_slots > 0),
+            by_path: HashMap::new(),
+        }
+    }
+
+    /// Record an opportunity on `path_idx` in `slot`. Returns whether to log
+    /// it, and the aggregate of the bucket that it ends, if any.
+    pub fn observe(
+        &mut self,
+        slot: Slot,
+        path_idx: usize,
+        marginal_price: f64,
+        optimal_input: f64,
+    ) -> (bool, Option<AggregatedOpportunity>) {
+        let window_slots = match self.window_slots {
+            Some(window_slots) => window_slots,
+            None => return (true, None),
+        };
+        let bucket = Bucket::new(marginal_price, optimal_input);
+        if let Some(pending) = self.by_path.get_mut(&path_idx) {
+            let aggregate = &mut pending.aggregate;
+            if pending.bucket == bucket && slot < aggregate.first_slot.saturating_add(window_slots)
+            {
+                aggregate.occurrences += 1;
+                aggregate.last_slot = slot;
+                return (false, None);
+            }
+        }
+        let pending = PendingBucket {
+            bucket,
+            aggregate: AggregatedOpportunity {
+                path_idx,
+                first_slot: slot,
+                last_slot: slot,
+                occurrences: 1,
+                marginal_price,
+                optimal_input,
+            },
+        };
+        let ended = self
+            .by_path
+            .insert(path_idx, pending)
+            .and_then(PendingBucket::into_aggregate);
+        (true, ended)
+    }
+
+    /// End the buckets whose window is over in `slot`, and return the
+    /// aggregates to log, ordered by path.
+    pub fn expire(&mut self, slot: Slot) -> Vec<AggregatedOpportunity> {
+        let window_slots = match self.window_slots {
+            Some(window_slots) => window_slots,
+            None => return Vec::new(),
+        };
+        let expired: Vec<usize> = self
+            .by_path
+            .iter()
+            .filter(|(_path_idx, pending)| {
+                slot >= pending.aggregate.first_slot.saturating_add(window_slots)
+            })
+            .map(|(path_idx, _pending)| *path_idx)
+            .collect();
+        let mut aggregates: Vec<AggregatedOpportunity> = expired
+            .into_iter()
+            .filter_map(|path_idx| self.by_path.remove(&path_idx))
+            .filter_map(PendingBucket::into_aggregate)
+            .collect();
+        aggregates.sort_by_key(|aggregate| aggregate.path_idx);
+        aggregates
+    }
+
+    /// End all buckets, e.g. when we shut down, and return the aggregates to
+    /// log, ordered by path.
+    pub fn flush(&mut self) -> Vec<AggregatedOpportunity> {
+        let mut aggregates: Vec<AggregatedOpportunity> = self
+            .by_path
+            .drain()
+            .filter_map(|(_path_idx, pending)| pending.into_aggregate())
+            .collect();
+        aggregates.sort_by_key(|aggregate| aggregate.path_idx);
+        aggregates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_without_window_logs_everything() {
+        let mut debouncer = OpportunityDebouncer::new(None);
+        for slot in 0..3 {
+            assert_eq!(debouncer.observe(slot, 0, 1.1, 1000.0), (true, None));
+        }
+        assert!(debouncer.flush().is_empty());
+    }
+
+    #[test]
+    fn test_same_bucket_is_aggregated() {
+        let mut debouncer = OpportunityDebouncer::new(Some(10));
+        assert_eq!(debouncer.observe(5, 0, 1.1, 1000.0), (true, None));
+        // Within the quanta of the first one.
+        assert_eq!(debouncer.observe(5, 0, 1.1002, 1001.0), (false, None));
+        assert_eq!(debouncer.observe(6, 0, 1.1, 1000.0), (false, None));
+        // Paths have their own buckets.
+        assert_eq!(debouncer.observe(6, 1, 1.1, 1000.0), (true, None));
+        assert!(debouncer.expire(14).is_empty());
+
+        let aggregate = AggregatedOpportunity {
+            path_idx: 0,
+            first_slot: 5,
+            last_slot: 6,
+            occurrences: 3,
+            marginal_price: 1.1,
+            optimal_input: 1000.0,
+        };
+        assert_eq!(debouncer.expire(15), vec![aggregate]);
+        // The path with a single opportunity has nothing to aggregate.
+        assert!(debouncer.flush().is_empty());
+    }
+
+    #[test]
+    fn test_new_bucket_ends_the_previous_one() {
+        let mut debouncer = OpportunityDebouncer::new(Some(10));
+        assert_eq!(debouncer.observe(5, 0, 1.1, 1000.0), (true, None));
+        assert_eq!(debouncer.observe(5, 0, 1.1, 1000.0), (false, None));
+
+        let (is_logged, ended) = debouncer.observe(6, 0, 1.1, 2000.0);
+        assert!(is_logged);
+        assert_eq!(ended.unwrap().occurrences, 2);
+
+        let (is_logged, ended) = debouncer.observe(6, 0, 1.2, 2000.0);
+        assert!(is_logged);
+        assert_eq!(ended, None);
+    }
+}
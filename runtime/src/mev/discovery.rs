@@ -0,0 +1,351 @@
+//! Paths that we find ourselves: the cycles through the pools of the config,
+//! with the token mints as nodes and the pools as edges, see
+//! `auto_discover_paths`.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use super::{
+    arbitrage::{MevPath, PairInfo, TradeDirection},
+    config::MevConfig,
+    pool_state::PoolRole,
+};
+
+/// The most hops that `max_hops` may ask for. Longer cycles do not fit in a
+/// transaction anyway, and the number of cycles grows quickly with the length.
+pub const MAX_DISCOVERED_HOPS: u8 = 4;
+
+/// The prefix of the names of the paths that we discover.
+pub const DISCOVERED_PATH_PREFIX: &str = "auto:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AutoDiscoverConfig {
+    /// The most hops of a discovered cycle, from 2 to `MAX_DISCOVERED_HOPS`.
+    pub max_hops: u8,
+}
+
+/// A trade through a pool in one direction.
+#[derive(Debug, Clone, Copy)]
+struct Hop {
+    /// The index of the pool in `Graph::pools`.
+    pool: usize,
+    a_to_b: bool,
+    from: Pubkey,
+    to: Pubkey,
+}
+
+/// The pools that paths may trade through and whose mints the config sets,
+/// in config order.
+struct Graph {
+    pools: Vec<Pubkey>,
+    hops_from: HashMap<Pubkey, Vec<Hop>>,
+}
+
+impl Graph {
+    fn new(config: &MevConfig) -> Self {
+        let mut graph = Graph {
+            pools: Vec::new(),
+            hops_from: HashMap::new(),
+        };
+        for pool in config.orca_accounts.unique() {
+            if pool.role != PoolRole::Trade {
+                continue;
+            }
+            let (mint_a, mint_b) = match pool.known_mints() {
+                Some((mint_a, mint_b)) if mint_a != mint_b => (mint_a, mint_b),
+                _ => continue,
+            };
+            let idx = graph.pools.len();
+            graph.pools.push(pool.address);
+            for (a_to_b, from, to) in [(true, mint_a, mint_b), (false, mint_b, mint_a)] {
+                graph.hops_from.entry(from).or_default().push(Hop {
+                    pool: idx,
+                    a_to_b,
+                    from,
+                    to,
+                });
+            }
+        }
+        graph
+    }
+
+    /// Every simple cycle of at most `max_hops` hops, through distinct pools
+    /// and distinct mints. We find a cycle once per orientation, starting at
+    /// the first of its pools in config order.
+    fn cycles(&self, max_hops: usize) -> Vec<Vec<Hop>> {
+        let mut cycles = Vec::new();
+        for hops in self.hops_from.values() {
+            for hop in hops {
+                self.extend_cycle(max_hops, &mut vec![*hop], &mut cycles);
+            }
+        }
+        // `hops_from` is a `HashMap`, sort for a stable order of the paths.
+        cycles.sort_by_key(|cycle| {
+            cycle
+                .iter()
+                .map(|hop| (hop.pool, !hop.a_to_b))
+                .collect::<Vec<_>>()
+        });
+        cycles
+    }
+
+    fn extend_cycle(&self, max_hops: usize, hops: &mut Vec<Hop>, cycles: &mut Vec<Vec<Hop>>) {
+        let first = hops[0];
+        let last = *hops.last().expect("Starts with one hop");
+        for next in self.hops_from.get(&last.to).into_iter().flatten() {
+            if next.pool <= first.pool || hops.iter().any(|hop| hop.pool == next.pool) {
+                continue;
+            }
+            if next.to == first.from {
+                let mut cycle = hops.clone();
+                cycle.push(*next);
+                cycles.push(cycle);
+                continue;
+            }
+            if hops.len() + 1 >= max_hops || hops.iter().any(|hop| hop.from == next.to) {
+                continue;
+            }
+            hops.push(*next);
+            self.extend_cycle(max_hops, hops, cycles);
+            hops.pop();
+        }
+    }
+}
+
+/// The hops of a cycle as (pool, whether A to B), rotated to start at its
+/// smallest hop, so that rotations of the same cycle compare equal.
+fn cycle_key(hops: impl Iterator<Item = (Pubkey, bool)>) -> Vec<(Pubkey, bool)> {
+    let hops: Vec<(Pubkey, bool)> = hops.collect();
+    (0..hops.len())
+        .map(|start| {
+            hops[start..]
+                .iter()
+                .chain(&hops[..start])
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+fn path_key(path: &MevPath) -> Vec<(Pubkey, bool)> {
+    cycle_key(
+        path.path
+            .iter()
+            .map(|pair_info| (pair_info.pool, pair_info.direction == TradeDirection::AtoB)),
+    )
+}
+
+/// The cycles of at most `auto_discover.max_hops` hops through the pools of
+/// `config` that trade and whose `pool_a_mint` and `pool_b_mint` are set, that
+/// no path of `config` already covers, also in reverse with `probe_reverse`.
+/// Only the main pool of a configured hop counts, not its alternatives. The
+/// paths are named after their mints, by their symbol in `tokens` where there
+/// is one, e.g. `auto:USDC>SOL>stSOL>USDC`, with `#2`, `#3`, ... after names
+/// that another path has already.
+pub fn discover_paths(auto_discover: &AutoDiscoverConfig, config: &MevConfig) -> Vec<MevPath> {
+    let mut symbols: HashMap<Pubkey, &str> = HashMap::new();
+    for (symbol, mint) in &config.tokens {
+        let entry = symbols.entry(mint.0).or_insert(symbol.as_str());
+        // Of several symbols for a mint, take the same one every time.
+        if symbol.as_str() < *entry {
+            *entry = symbol.as_str();
+        }
+    }
+    let mut covered: HashSet<Vec<(Pubkey, bool)>> = HashSet::new();
+    for path in &config.mev_paths {
+        covered.insert(path_key(path));
+        if path.probe_reverse {
+            covered.insert(path_key(&path.reversed()));
+        }
+    }
+    let mut names: HashSet<String> = config
+        .mev_paths
+        .iter()
+        .map(|path| path.name.clone())
+        .collect();
+
+    let graph = Graph::new(config);
+    let mut paths = Vec::new();
+    for cycle in graph.cycles(auto_discover.max_hops as usize) {
+        let key = cycle_key(cycle.iter().map(|hop| (graph.pools[hop.pool], hop.a_to_b)));
+        if !covered.insert(key) {
+            continue;
+        }
+        let mints: Vec<String> = cycle
+            .iter()
+            .map(|hop| hop.from)
+            .chain(std::iter::once(cycle[0].from))
+            .map(|mint| match symbols.get(&mint) {
+                Some(symbol) => symbol.to_string(),
+                None => mint.to_string(),
+            })
+            .collect();
+        let base_name = format!("{}{}", DISCOVERED_PATH_PREFIX, mints.join(">"));
+        let mut name = base_name.clone();
+        let mut n = 1;
+        while names.contains(&name) {
+            n += 1;
+            name = format!("{}#{}", base_name, n);
+        }
+        names.insert(name.clone());
+        paths.push(MevPath {
+            name,
+            path: cycle
+                .iter()
+                .map(|hop| PairInfo {
+                    pool: graph.pools[hop.pool],
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: if hop.a_to_b {
+                        TradeDirection::AtoB
+                    } else {
+                        TradeDirection::BtoA
+                    },
+                })
+                .collect(),
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        });
+    }
+    paths
+}
+
+/// The paths of `config`, followed by the ones that we discover with its
+/// `auto_discover_paths`, in the order of `Mev::mev_paths`.
+pub fn configured_and_discovered_paths(config: &MevConfig) -> Vec<MevPath> {
+    let mut paths = config.mev_paths.clone();
+    if let Some(auto_discover) = &config.auto_discover_paths {
+        paths.extend(discover_paths(auto_discover, config));
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mev::{
+        config::AllOrcaPoolAddresses, pool_state::OrcaPoolAddresses, utils::B58Pubkey,
+    };
+
+    fn pool(address: Pubkey, mint_a: Pubkey, mint_b: Pubkey) -> OrcaPoolAddresses {
+        OrcaPoolAddresses {
+            address,
+            pool_a_mint: mint_a,
+            pool_b_mint: mint_b,
+            ..Default::default()
+        }
+    }
+
+    fn hops(path: &MevPath) -> Vec<(Pubkey, TradeDirection)> {
+        path.path
+            .iter()
+            .map(|pair_info| (pair_info.pool, pair_info.direction.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_discover_paths() {
+        let usdc = Pubkey::new_unique();
+        let sol = Pubkey::new_unique();
+        let stsol = Pubkey::new_unique();
+        let usdc_sol = Pubkey::new_unique();
+        let stsol_sol = Pubkey::new_unique();
+        let stsol_usdc = Pubkey::new_unique();
+        let observed = Pubkey::new_unique();
+        let config = MevConfig {
+            orca_accounts: AllOrcaPoolAddresses::new(vec![
+                pool(usdc_sol, usdc, sol),
+                pool(stsol_sol, stsol, sol),
+                pool(stsol_usdc, stsol, usdc),
+                OrcaPoolAddresses {
+                    role: PoolRole::Observe,
+                    ..pool(observed, usdc, sol)
+                },
+                // Without mints, the pool is not part of the graph.
+                pool(Pubkey::new_unique(), Pubkey::default(), Pubkey::default()),
+            ]),
+            tokens: vec![
+                ("USDC".to_owned(), B58Pubkey(usdc)),
+                ("SOL".to_owned(), B58Pubkey(sol)),
+            ]
+            .into_iter()
+            .collect(),
+            ..MevConfig::default()
+        };
+
+        let paths = discover_paths(&AutoDiscoverConfig { max_hops: 3 }, &config);
+        let names: Vec<&str> = paths.iter().map(|path| path.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                format!("auto:USDC>SOL>{}>USDC", stsol),
+                format!("auto:SOL>USDC>{}>SOL", stsol),
+            ]
+        );
+        assert_eq!(
+            hops(&paths[0]),
+            [
+                (usdc_sol, TradeDirection::AtoB),
+                (stsol_sol, TradeDirection::BtoA),
+                (stsol_usdc, TradeDirection::AtoB),
+            ]
+        );
+        // The other orientation, starting at the first pool.
+        let mut reversed = paths[0].reversed();
+        reversed.path.rotate_right(1);
+        assert_eq!(hops(&paths[1]), hops(&reversed));
+
+        // Two hops are not enough for a cycle through three pools.
+        assert!(discover_paths(&AutoDiscoverConfig { max_hops: 2 }, &config).is_empty());
+    }
+
+    #[test]
+    fn test_discover_paths_skips_configured_cycles() {
+        let usdc = Pubkey::new_unique();
+        let sol = Pubkey::new_unique();
+        let usdc_sol_1 = Pubkey::new_unique();
+        let usdc_sol_2 = Pubkey::new_unique();
+        let usdc_sol_3 = Pubkey::new_unique();
+        let mut config = MevConfig {
+            orca_accounts: AllOrcaPoolAddresses::new(vec![
+                pool(usdc_sol_1, usdc, sol),
+                pool(usdc_sol_2, usdc, sol),
+                pool(usdc_sol_3, usdc, sol),
+            ]),
+            ..MevConfig::default()
+        };
+        let auto_discover = AutoDiscoverConfig { max_hops: 2 };
+        let all_paths = discover_paths(&auto_discover, &config);
+        // Both orientations of every pair of pools.
+        assert_eq!(all_paths.len(), 6);
+        // The same mints, so the names only differ in their suffix.
+        assert_eq!(all_paths[0].name, format!("auto:{}>{}>{}", usdc, sol, usdc));
+        assert_eq!(
+            all_paths[1].name,
+            format!("auto:{}>{}>{}#2", usdc, sol, usdc)
+        );
+        assert_eq!(all_paths[2].name, format!("auto:{}>{}>{}", sol, usdc, sol));
+
+        // A configured path covers its rotations, and its reverse when it
+        // probes it. Rotated, the reverse of the first path is the third.
+        let mut configured = all_paths[0].reversed();
+        configured.path.rotate_left(1);
+        configured.name = "configured".to_owned();
+        configured.probe_reverse = true;
+        config.mev_paths = vec![configured, all_paths[1].clone()];
+        let paths = discover_paths(&auto_discover, &config);
+        let expected: Vec<_> = all_paths[3..].iter().map(hops).collect();
+        assert_eq!(paths.iter().map(hops).collect::<Vec<_>>(), expected);
+        // The name of a configured path is taken.
+        assert!(paths.iter().all(|path| path.name != all_paths[1].name));
+    }
+}
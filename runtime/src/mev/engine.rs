@@ -0,0 +1,6456 @@
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash as _, Hasher},
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use log::{info, warn};
+use solana_program_runtime::compute_budget::MAX_COMPUTE_UNIT_LIMIT;
+use solana_sdk::{
+    clock::{Slot, UnixTimestamp},
+    hash::Hash,
+    packet::PACKET_DATA_SIZE,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signature,
+    transaction::{MevKeys, MevPoolKeys, SanitizedTransaction, MAX_TX_ACCOUNT_LOCKS},
+};
+use spl_token::solana_program::program_pack::Pack;
+
+use crate::{accounts::LoadedTransaction, inline_spl_token};
+
+use super::{
+    arbitrage::{
+        encode_swap_tx, optimal_input_amount, HopCandidates, InputOutputPairs, MevPath,
+        MevTxBlueprint, MevTxOutput, PathCalculationOutput, PathSkipReason, SequencePart,
+        SettingSource, SkipReason, SwapArguments, TradeDirection, TriggerKind, TxSettings,
+        MAX_HOP_OUTPUT_BPS, MAX_SLIPPAGE_BPS,
+    },
+    audit::{
+        CapKind, CapTrace, CompetingOpportunity, DecisionTrace, HopTrace, SelectionTrace,
+        ThresholdKind, ThresholdTrace,
+    },
+    authority::PoolAuthorities,
+    compute_units::{ComputeUnitEstimator, CuCalibration},
+    config::{
+        load_user_authority, AllOrcaPoolAddresses, MevConfig, MinTriggerDeltaConfig,
+        OpportunityConflict, ThresholdSummary,
+    },
+    config_dump::{sorted_b58, ConfigDump, PathDump, PoolDump},
+    discovery::{discover_paths, AutoDiscoverConfig},
+    error::MevError,
+    failures::{ExecutionFailure, SystemicFailureEvent, SystemicFailureMonitor},
+    forwarder::{parse_http_url, TransactionForwarder},
+    lock_impact::{LockImpactReport, LockImpactSampler},
+    log_channel::MevLogSender,
+    logging::{
+        CompetitorArbitrage, DroppedTransaction, MevMsg, PathSkipped, PoolConfigMismatch,
+        PrePostPoolStates,
+    },
+    pool_state::{
+        loaded_user_authority_balances, PoolLoadError, PoolLoadErrorCounters, PoolRole, PoolStates,
+    },
+    priority_fee::{get_compute_unit_price, priority_fee_lamports, PriorityFeeConfig},
+    reporting::{PendingReportedProfits, ProfitReporter, ReportedProfit},
+    signer::{MevSigner, SigningError, SigningThread},
+    simulate::{simulate_hops, to_token_amount},
+    utils::{error_throttled, expand_path, warn_throttled},
+    LOG_THROTTLE_INTERVAL,
+};
+
+#[derive(Debug, Clone)]
+pub struct Mev {
+    pub log_send_channel: MevLogSender,
+    // A set of `Pubkey` for us to trigger MEV.
+    pub watched_programs: HashSet<Pubkey>,
+
+    // A transaction that references a watched program only triggers us when
+    // it also references one of these: the accounts of the pools, and the
+    // `watched_accounts` from the config.
+    pub trigger_accounts: HashSet<Pubkey>,
+
+    // Transactions signed by any of these never trigger us.
+    pub blocked_signers: HashSet<Pubkey>,
+
+    // Pools that we treat as missing from the pool states.
+    pub blocked_pools: HashSet<Pubkey>,
+
+    // These public keys are going to be loaded so we can ensure no other thread
+    // modifies the data we are interested in.
+    // TODO: Change this to pairs we are willing to trade on.
+    pub orca_monitored_accounts: Arc<AllOrcaPoolAddresses>,
+
+    // MEV paths that we are interested on finding an opportunity
+    pub mev_paths: Vec<MevPath>,
+
+    // Signer for the user authority, that signs the transactions.
+    // If `None`, we do not try to craft MEV txs.
+    pub user_authority: Option<Arc<dyn MevSigner>>,
+
+    // The user authorities from `[[user_authority]]`, for the pools that the
+    // default `user_authority` does not sign for.
+    pub pool_authorities: Arc<PoolAuthorities>,
+
+    // Per path in `mev_paths`, the authority from `pool_authorities` that
+    // signs for it, or `None` for the default `user_authority`.
+    pub path_authorities: Vec<Option<Arc<dyn MevSigner>>>,
+
+    // Per path in `mev_paths`, the path in reverse for the paths with
+    // `probe_reverse`, see `MevPath::reversed`.
+    pub reversed_paths: Vec<Option<MevPath>>,
+
+    // The indices in `mev_paths` of the paths that trade through each pool,
+    // also as an alternative pool, see `touched_paths`.
+    pub paths_by_pool: HashMap<Pubkey, Vec<usize>>,
+
+    // A mapping with the minimum profit to execute MEV transactions token per
+    // token address.
+    pub minimum_profit: HashMap<Pubkey, u64>,
+
+    // The minimum profit for tokens that are not in `minimum_profit`.
+    pub default_minimum_profit: Option<u64>,
+
+    // Per token mint, the maximum amount that may be committed to MEV
+    // transactions that did not execute yet.
+    pub max_inflight_exposure: HashMap<Pubkey, u64>,
+
+    // How to set the compute unit price of our transactions, if at all.
+    pub priority_fee: Option<PriorityFeeConfig>,
+
+    // The slippage and compute unit limit of our transactions, for paths that
+    // do not override them.
+    pub slippage_bps: Option<u16>,
+    pub compute_unit_limit: Option<u32>,
+
+    // Amounts committed to crafted MEV transactions, shared between all
+    // banking threads.
+    pub inflight_exposure: Arc<Mutex<InflightExposure>>,
+
+    // Paths on which we found opportunities in the current slot, to tell
+    // whether we saw the opportunities that others take.
+    pub evaluated_paths: Arc<Mutex<EvaluatedPaths>>,
+
+    // Latest slot that we sent a `MevMsg::Tick` for, shared between all
+    // banking threads so that we send one per slot.
+    pub last_tick_slot: Arc<AtomicU64>,
+
+    // Whether we hold MEV transactions back until their trigger is committed.
+    pub confirm_trigger_before_craft: bool,
+
+    // MEV transactions that wait for their trigger to be committed, shared
+    // between all banking threads.
+    pub pending_opportunities: Arc<Mutex<PendingOpportunities>>,
+
+    // Pools whose loaded mints we compared with the mints in the config, and
+    // whether they matched. Pools that did not match are disabled.
+    pub checked_pool_mints: Arc<Mutex<HashMap<Pubkey, bool>>>,
+
+    // The mints of the pools that we loaded, for the pools whose mints are not
+    // in the config, so that `fill_tx_mev_accounts` can load the mint accounts
+    // from then on.
+    pub loaded_pool_mints: Arc<Mutex<HashMap<Pubkey, (Pubkey, Pubkey)>>>,
+
+    // The token programs that own the token accounts of the pools that we
+    // loaded. Until we loaded a pool, `fill_tx_mev_accounts` locks SPL Token
+    // for it.
+    pub loaded_pool_token_programs: Arc<Mutex<HashMap<Pubkey, Pubkey>>>,
+
+    // The lamports of the user authorities, as of the last trigger that
+    // loaded them, to check that they can pay the fees of our transactions.
+    pub fee_payer_balances: Arc<Mutex<HashMap<Pubkey, u64>>>,
+
+    // Whether we trace the inputs to our decisions, see `DecisionTrace`.
+    pub audit_mode: bool,
+
+    // Whether we log the transactions that we select instead of executing
+    // them.
+    pub dry_run: bool,
+
+    // Whether we refine the f64 optimal input to the best integer input.
+    pub exact_optimal_input: bool,
+
+    // Pools with a reserve below this are drained, paths do not trade
+    // through them.
+    pub min_pool_reserve: u64,
+
+    // The share of its reserve, in basis points, that a pool may not pay out
+    // or more in a hop, see `PathSkipReason::ExcessiveHopOutput`.
+    pub max_hop_output_bps: u16,
+
+    // Whether we log why a path has no opportunity, see `PathSkipReason`.
+    pub log_skipped_paths: bool,
+
+    // The settings that the `auto:` paths in `mev_paths` were discovered with.
+    pub auto_discover_paths: Option<AutoDiscoverConfig>,
+
+    // Vote transactions that touched a watched account, which we did not
+    // attach MEV keys to, since the last time we reported metrics.
+    pub skipped_vote_triggers: Arc<AtomicU64>,
+
+    // Opportunities that we dropped, because a more profitable one of another
+    // path traded through the same pools in the same directions, since the
+    // last time we reported metrics.
+    pub duplicate_opportunities: Arc<AtomicU64>,
+
+    // Pools that we could not load from the accounts of a transaction, since
+    // the last time we reported metrics.
+    pub pool_load_errors: Arc<PoolLoadErrorCounters>,
+
+    // Sends the transactions that we could not record because our leader slot
+    // ended to the fallback RPC. Only set with `fallback_when_not_leader`.
+    pub forwarder: Option<TransactionForwarder>,
+
+    // The imbalances that we saw on the paths with `require_confirmations`,
+    // in the current slot.
+    pub imbalance_observations: Arc<Mutex<ImbalanceObservations>>,
+
+    // Incremented on every reload of the config. `fill_tx_mev_accounts`
+    // stamps it into the `MevKeys`, so that we can tell which transactions
+    // carry the pools of an earlier config.
+    pub config_generation: u64,
+
+    // Estimates the compute units of our transactions, from
+    // `cu_calibration_file` if set.
+    pub compute_unit_estimator: ComputeUnitEstimator,
+
+    // Whether we paused crafting because our transactions keep failing for
+    // reasons unrelated to their path, shared between all banking threads.
+    pub systemic_failures: Arc<Mutex<SystemicFailureMonitor>>,
+
+    // Converts our profits into the reporting mint, with a `reporting` config.
+    pub profit_reporter: Option<ProfitReporter>,
+
+    // What we report for the transactions that we crafted, until they
+    // execute, shared between all banking threads.
+    pub pending_reported_profits: Arc<Mutex<PendingReportedProfits>>,
+
+    // At most how many MEV transactions we select in one slot.
+    pub max_mev_txs_per_slot: Option<u64>,
+
+    // At most how many opportunities we execute after one trigger, and when
+    // two of them conflict, see `take_top_profit_txs`.
+    pub max_opportunities_per_trigger: u8,
+    pub opportunity_conflict: OpportunityConflict,
+
+    // How many MEV transactions we selected in the current slot, shared
+    // between all banking threads.
+    pub slot_tx_count: Arc<Mutex<SlotTxCount>>,
+
+    // How much a trigger has to move the pools of a path, for us to craft a
+    // transaction on it.
+    pub min_trigger_delta: Option<MinTriggerDelta>,
+
+    // Picks the transactions whose lock impact we send to the log thread, for
+    // the status, with a `lock_impact_sampling_rate`.
+    pub lock_impact_sampler: Option<Arc<LockImpactSampler>>,
+
+    // Signs the transactions that we select, with a `signing_thread_wait_ms`.
+    // Without one, we sign on the banking thread.
+    pub signing_thread: Option<SigningThread>,
+
+    // The last quotes of every path, shared between all banking threads, but
+    // not with the `Mev` of a reloaded config.
+    pub path_cache: Arc<Mutex<PathCache>>,
+}
+
+/// Indices of the paths on which we found an opportunity, in `slot`.
+#[derive(Debug, Default)]
+pub struct EvaluatedPaths {
+    slot: Slot,
+    path_indices: HashSet<usize>,
+}
+
+impl EvaluatedPaths {
+    pub fn insert(&mut self, slot: Slot, path_idx: usize) {
+        if slot != self.slot {
+            self.slot = slot;
+            self.path_indices.clear();
+        }
+        self.path_indices.insert(path_idx);
+    }
+
+    pub fn contains(&self, slot: Slot, path_idx: usize) -> bool {
+        slot == self.slot && self.path_indices.contains(&path_idx)
+    }
+}
+
+/// How many MEV transactions we selected in `slot`, for
+/// `max_mev_txs_per_slot`.
+#[derive(Debug, Default)]
+pub struct SlotTxCount {
+    slot: Slot,
+    count: u64,
+}
+
+impl SlotTxCount {
+    /// Count `n` more transactions in `slot`, unless that would take the
+    /// count over `max`. The count starts over in a new slot.
+    pub fn try_add(&mut self, slot: Slot, n: u64, max: Option<u64>) -> bool {
+        if slot != self.slot {
+            self.slot = slot;
+            self.count = 0;
+        }
+        let count = self.count.saturating_add(n);
+        if max.map_or(false, |max| count > max) {
+            return false;
+        }
+        self.count = count;
+        true
+    }
+
+    /// Undo `try_add` for transactions that we dropped after all.
+    pub fn remove(&mut self, slot: Slot, n: u64) {
+        if slot == self.slot {
+            self.count = self.count.saturating_sub(n);
+        }
+    }
+}
+
+/// We compare the marginal prices of opportunities rounded to this step, so
+/// that a trigger which barely moves the pools still confirms the imbalance.
+pub const MARGINAL_PRICE_QUANTUM: f64 = 0.001;
+
+/// Per path that has `require_confirmations`, the imbalance that the last
+/// trigger in `slot` showed on it, and on how many triggers in a row we saw
+/// it.
+#[derive(Debug, Default)]
+pub struct ImbalanceObservations {
+    slot: Slot,
+    by_path: HashMap<usize, (i64, u8)>,
+}
+
+impl ImbalanceObservations {
+    /// Record that a trigger in `slot` shows an opportunity on `path_idx` at
+    /// `marginal_price`. Returns on how many triggers in a row, including this
+    /// one, we saw the same imbalance.
+    pub fn observe(&mut self, slot: Slot, path_idx: usize, marginal_price: f64) -> u8 {
+        if slot != self.slot {
+            self.slot = slot;
+            self.by_path.clear();
+        }
+        let quantized_price = (marginal_price / MARGINAL_PRICE_QUANTUM).round() as i64;
+        let (last_price, observations) = self.by_path.entry(path_idx).or_insert((0, 0));
+        if *last_price == quantized_price {
+            *observations = observations.saturating_add(1);
+        } else {
+            *last_price = quantized_price;
+            *observations = 1;
+        }
+        *observations
+    }
+
+    /// Record that a trigger in `slot` loaded the pools of `path_idx`, but
+    /// shows no opportunity on it: the imbalance is gone, or reversed.
+    pub fn clear(&mut self, slot: Slot, path_idx: usize) {
+        if slot == self.slot {
+            self.by_path.remove(&path_idx);
+        }
+    }
+}
+
+/// The quotes that we trade a path with, see `PathCache`.
+#[derive(Clone)]
+struct PathQuotes {
+    path_output: PathCalculationOutput,
+    // The input before we capped it, and the cap that determined the input.
+    uncapped_amount: u128,
+    input_cap: Option<CapKind>,
+    // One quote, or one per part of a split.
+    quotes: Vec<PathQuote>,
+}
+
+/// Per path, and per direction for paths with `probe_reverse`, the quotes that
+/// we computed for it last, or why it had none, with the `balances_key` of the
+/// pool states that we computed them from. A trigger often leaves the pools of
+/// most paths as they were, then we reuse their quotes instead of computing
+/// them again. The cache belongs to the config, a reload starts empty.
+#[derive(Default)]
+pub struct PathCache {
+    entries: HashMap<(usize, bool), (u64, Result<PathQuotes, PathSkipReason>)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl PathCache {
+    /// A hash of what the quotes of `mev_path` depend on in `pool_states`:
+    /// which of its candidate pools are there, their balances, and our
+    /// balances of their tokens. Every swap changes the balances. The fees
+    /// and curves only change with an admin instruction, so we do not hash
+    /// them.
+    pub fn balances_key(mev_path: &MevPath, pool_states: &PoolStates) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for pool in mev_path
+            .path
+            .iter()
+            .flat_map(|pair_info| pair_info.candidate_pools())
+        {
+            pool_states
+                .0
+                .get(pool)
+                .map(|pool_state| {
+                    (
+                        pool_state.pool_a_balance,
+                        pool_state.pool_b_balance,
+                        pool_state.source_balance,
+                        pool_state.destination_balance,
+                    )
+                })
+                .hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// The quotes of path `path_idx`, in reverse when `reversed`, when we
+    /// cached them for the same `key`.
+    fn get(
+        &mut self,
+        path_idx: usize,
+        reversed: bool,
+        key: u64,
+    ) -> Option<Result<PathQuotes, PathSkipReason>> {
+        match self.entries.get(&(path_idx, reversed)) {
+            Some((cached_key, path_quotes)) if *cached_key == key => {
+                self.hits += 1;
+                Some(path_quotes.clone())
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        path_idx: usize,
+        reversed: bool,
+        key: u64,
+        path_quotes: Result<PathQuotes, PathSkipReason>,
+    ) {
+        self.entries
+            .insert((path_idx, reversed), (key, path_quotes));
+    }
+
+    /// How often we reused the quotes of a path.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// How often we computed the quotes of a path.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+impl std::fmt::Debug for PathCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PathCache")
+            .field("paths", &self.entries.len())
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+/// `min_trigger_delta` of the config, with its tokens resolved to mints.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MinTriggerDelta {
+    bps: Option<u16>,
+    amounts: HashMap<Pubkey, u64>,
+}
+
+impl MinTriggerDelta {
+    pub fn new(config: &MinTriggerDeltaConfig, mev_config: &MevConfig) -> Result<Self, String> {
+        if let Some(bps) = config.bps.filter(|bps| *bps > MAX_SLIPPAGE_BPS) {
+            return Err(format!(
+                "min_trigger_delta.bps: {} is more than {}",
+                bps, MAX_SLIPPAGE_BPS
+            ));
+        }
+        let amounts = config
+            .amounts
+            .iter()
+            .map(|(token, amount)| {
+                let field = "min_trigger_delta.amounts";
+                let mint = mev_config.resolve_token(field, token)?;
+                Ok((mint, mev_config.raw_amount(field, token, amount)?))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(MinTriggerDelta {
+            bps: config.bps,
+            amounts,
+        })
+    }
+
+    /// Whether a reserve of `mint` moved materially from `pre` to `post`.
+    fn is_material(&self, mint: &Pubkey, pre: u64, post: u64) -> bool {
+        let delta = pre.max(post) - pre.min(post);
+        let min_bps_delta = self.bps.map_or(0, |bps| {
+            (pre as u128 * bps as u128 / MAX_SLIPPAGE_BPS as u128) as u64
+        });
+        let min_amount_delta = self.amounts.get(mint).copied().unwrap_or(0);
+        delta > 0 && delta >= min_bps_delta && delta >= min_amount_delta
+    }
+
+    /// Whether the trigger moved any pool in `hops`, the pools of a path in
+    /// `post_pool_states`, materially from `pre_pool_states`. A pool that is
+    /// not in `pre_pool_states` counts as moved, we cannot tell.
+    pub fn path_moved(&self, hops: &[HopCandidates], pre_pool_states: &PoolStates) -> bool {
+        hops.iter().flatten().any(|(address, post)| {
+            let pre = match pre_pool_states.0.get(address) {
+                Some(pre) => pre,
+                None => return true,
+            };
+            let (mint_a, mint_b) = (&post.pool.pool_a_mint, &post.pool.pool_b_mint);
+            self.is_material(mint_a, pre.pool_a_balance, post.pool_a_balance)
+                || self.is_material(mint_b, pre.pool_b_balance, post.pool_b_balance)
+        })
+    }
+}
+
+/// Tracks, per token mint, the amount committed to MEV transactions that we
+/// crafted but whose execution we did not observe yet.
+#[derive(Debug, Default)]
+pub struct InflightExposure {
+    transactions: HashMap<Signature, InflightTransaction>,
+    totals: HashMap<Pubkey, u64>,
+}
+
+#[derive(Debug)]
+struct InflightTransaction {
+    slot: Slot,
+    mint: Pubkey,
+    amount: u64,
+}
+
+impl InflightExposure {
+    /// Reserve `amount` of `mint` for the transaction with `signature`, unless
+    /// that would push the total for `mint` over `cap`. Returns whether the
+    /// amount was reserved.
+    ///
+    /// MEV transactions are executed right after the batch that created the
+    /// opportunity, so transactions crafted in an earlier slot than `slot` will
+    /// never execute, and their exposure is released first.
+    pub fn try_reserve(
+        &mut self,
+        signature: Signature,
+        slot: Slot,
+        mint: Pubkey,
+        amount: u64,
+        cap: Option<u64>,
+    ) -> bool {
+        self.release_before(slot);
+        let total = self
+            .totals
+            .get(&mint)
+            .copied()
+            .unwrap_or(0)
+            .saturating_add(amount);
+        if matches!(cap, Some(cap) if total > cap) {
+            return false;
+        }
+        self.totals.insert(mint, total);
+        self.transactions
+            .insert(signature, InflightTransaction { slot, mint, amount });
+        true
+    }
+
+    /// Release the exposure of a transaction that executed, or that will not
+    /// be executed.
+    pub fn release(&mut self, signature: &Signature) {
+        if let Some(tx) = self.transactions.remove(signature) {
+            if let Some(total) = self.totals.get_mut(&tx.mint) {
+                *total = total.saturating_sub(tx.amount);
+                if *total == 0 {
+                    self.totals.remove(&tx.mint);
+                }
+            }
+        }
+    }
+
+    fn release_before(&mut self, slot: Slot) {
+        let expired: Vec<Signature> = self
+            .transactions
+            .iter()
+            .filter(|(_signature, tx)| tx.slot < slot)
+            .map(|(signature, _tx)| *signature)
+            .collect();
+        for signature in &expired {
+            self.release(signature);
+        }
+    }
+
+    /// The amount in flight per token mint.
+    pub fn totals(&self) -> &HashMap<Pubkey, u64> {
+        &self.totals
+    }
+}
+
+/// MEV transactions that wait until the transaction that created their
+/// opportunity is committed, keyed by the signature of that trigger.
+#[derive(Debug, Default)]
+pub struct PendingOpportunities {
+    transactions: HashMap<Signature, PendingOpportunity>,
+}
+
+#[derive(Debug)]
+struct PendingOpportunity {
+    slot: Slot,
+    // The transactions of the opportunity in execution order, with their
+    // profit.
+    transactions: Vec<(SanitizedTransaction, u64)>,
+}
+
+impl PendingOpportunities {
+    /// Hold `transactions` until the trigger with `trigger_signature` is
+    /// committed or dropped. Triggers are committed in the slot in which they
+    /// execute, so transactions that wait since an earlier slot than `slot`
+    /// will never be released, they are returned with their trigger and the
+    /// slot in which we crafted them.
+    pub fn insert(
+        &mut self,
+        trigger_signature: Signature,
+        slot: Slot,
+        transactions: Vec<(SanitizedTransaction, u64)>,
+    ) -> Vec<(Signature, Slot, SanitizedTransaction)> {
+        let expired = self.expire_before(slot);
+        self.transactions
+            .insert(trigger_signature, PendingOpportunity { slot, transactions });
+        expired
+    }
+
+    /// Take the transactions and their profit that wait for the trigger with
+    /// `trigger_signature`, if any, with the slot in which we crafted them.
+    pub fn take(
+        &mut self,
+        trigger_signature: &Signature,
+    ) -> Option<(Slot, Vec<(SanitizedTransaction, u64)>)> {
+        self.transactions
+            .remove(trigger_signature)
+            .map(|pending| (pending.slot, pending.transactions))
+    }
+
+    /// Remove the transactions that wait since an earlier slot than `slot`,
+    /// and return them with their trigger and the slot in which we crafted
+    /// them.
+    pub fn expire_before(&mut self, slot: Slot) -> Vec<(Signature, Slot, SanitizedTransaction)> {
+        let expired: Vec<Signature> = self
+            .transactions
+            .iter()
+            .filter(|(_trigger_signature, pending)| pending.slot < slot)
+            .map(|(trigger_signature, _pending)| *trigger_signature)
+            .collect();
+        expired
+            .into_iter()
+            .flat_map(|trigger_signature| {
+                self.take(&trigger_signature)
+                    .into_iter()
+                    .flat_map(move |(slot, transactions)| {
+                        transactions.into_iter().map(move |(transaction, _profit)| {
+                            (trigger_signature, slot, transaction)
+                        })
+                    })
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+}
+
+/// What we get out of trading an amount through a path, and the swaps for it.
+#[derive(Clone)]
+struct PathQuote {
+    amount_in: u128,
+    amount_out: u128,
+    input_output_pairs: Vec<InputOutputPairs>,
+    selected_pools: Vec<Pubkey>,
+    // Only holds swaps for the hops whose token accounts we know.
+    swap_arguments: Vec<SwapArguments>,
+    // The number of swaps that trading the path takes, one per pool that a
+    // hop trades through, whether we know its token accounts or not.
+    swap_count: usize,
+    // How many of the swaps at the end of `swap_arguments` belong to the last
+    // hop, more than one when it splits its input across a pool group.
+    last_hop_swaps: usize,
+    // Only filled with `audit_mode`.
+    hop_traces: Vec<HopTrace>,
+}
+
+impl PathQuote {
+    fn gross_profit(&self) -> u64 {
+        self.amount_out.saturating_sub(self.amount_in) as u64
+    }
+
+    /// Whether we have the swaps for the entire path.
+    fn has_all_swaps(&self) -> bool {
+        self.swap_arguments.len() == self.swap_count
+    }
+
+    /// Require the last hop to output at least `minimum` in total. When it
+    /// splits its input, every swap gets a share of `minimum` in proportion
+    /// to its quoted output, and the last one the remainder of the rounding.
+    fn set_minimum_amount_out(&mut self, minimum: u64) {
+        let last_hop_start = self.swap_arguments.len() - self.last_hop_swaps;
+        let last_hop_swaps = &mut self.swap_arguments[last_hop_start..];
+        let quoted_out: u128 = last_hop_swaps
+            .iter()
+            .map(|swap_args| swap_args.minimum_amount_out as u128)
+            .sum();
+        let mut remaining = minimum;
+        let last_index = last_hop_swaps.len().saturating_sub(1);
+        for (i, swap_args) in last_hop_swaps.iter_mut().enumerate() {
+            let share = if i == last_index || quoted_out == 0 {
+                remaining
+            } else {
+                (minimum as u128 * swap_args.minimum_amount_out as u128 / quoted_out) as u64
+            };
+            swap_args.minimum_amount_out = share;
+            remaining -= share;
+        }
+    }
+}
+
+/// Check that `path` has hops, that all of its pools are in `pools`, and that
+/// it ends where it starts.
+fn check_path_shape(path: &MevPath, pools: &AllOrcaPoolAddresses) -> Result<(), MevError> {
+    let (first, last) = match (path.path.first(), path.path.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            return Err(MevError::EmptyPath {
+                path: path.name.clone(),
+            })
+        }
+    };
+    for (hop, pair_info) in path.path.iter().enumerate() {
+        if let Some(pool) = pair_info
+            .candidate_pools()
+            .find(|pool| pools.by_address(pool).is_none())
+        {
+            return Err(MevError::UnknownPool {
+                path: path.name.clone(),
+                hop,
+                pool: *pool,
+            });
+        }
+    }
+    if first.pool == last.pool && first.direction == last.direction {
+        return Err(MevError::SamePoolSameDirection {
+            path: path.name.clone(),
+            pool: first.pool,
+            direction: first.direction.clone(),
+        });
+    }
+    let first_mints = pools
+        .by_address(&first.pool)
+        .and_then(|pool| pool.known_mints_in_direction(&first.direction));
+    let last_mints = pools
+        .by_address(&last.pool)
+        .and_then(|pool| pool.known_mints_in_direction(&last.direction));
+    if let (Some((input_mint, _)), Some((_, output_mint))) = (first_mints, last_mints) {
+        if input_mint != output_mint {
+            return Err(MevError::MismatchedEndpoints {
+                path: path.name.clone(),
+                first_pool: first.pool,
+                input_mint,
+                last_pool: last.pool,
+                output_mint,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check `path` against the pools, the authorities and the transaction
+/// limits. A path whose transaction would not fit only fails the check when
+/// we can trade it, without a user authority it still logs opportunities.
+fn check_path(
+    path: &MevPath,
+    config: &MevConfig,
+    pool_authorities: &PoolAuthorities,
+    has_user_authority: bool,
+) -> Result<(), MevError> {
+    check_path_shape(path, &config.orca_accounts)?;
+    let invalid = |reason: String| MevError::InvalidPath {
+        path: path.name.clone(),
+        reason,
+    };
+    let path_authority = path
+        .check_mint_chain(&config.orca_accounts)
+        .and_then(|()| path.check_pool_roles(&config.orca_accounts))
+        .and_then(|()| pool_authorities.for_path(path))
+        .map_err(invalid)?;
+    // Without a user authority we only log opportunities, we do not need our
+    // token accounts then.
+    let can_trade = path_authority.is_some() || has_user_authority;
+    if can_trade {
+        path.check_trade_accounts(&config.orca_accounts)
+            .map_err(invalid)?;
+    }
+
+    let mut estimate = path.estimate_tx_size(&config.orca_accounts);
+    // With a profit share, we set a limit when the path and the config do not.
+    let compute_unit_limit = path.compute_unit_limit.or(config.compute_unit_limit);
+    let is_profit_share = config
+        .priority_fee
+        .as_ref()
+        .map_or(false, PriorityFeeConfig::is_profit_share);
+    if compute_unit_limit.is_some() || is_profit_share {
+        estimate = estimate.with_compute_unit_limit();
+    }
+    if estimate.exceeds_limits() {
+        let message = format!(
+            "MEV path {} needs a transaction with {} accounts and {} bytes, but \
+            transactions can lock at most {} accounts and be at most {} bytes",
+            path.name,
+            estimate.account_count,
+            estimate.serialized_size,
+            MAX_TX_ACCOUNT_LOCKS,
+            PACKET_DATA_SIZE,
+        );
+        // Without a user authority the path is still useful to log
+        // opportunities.
+        if can_trade {
+            return Err(invalid(message));
+        }
+        warn!("[MEV] {}, we will not be able to execute it.", message);
+    }
+    Ok(())
+}
+
+impl Mev {
+    /// Like `try_new`, but panics when `config` is not valid.
+    pub fn new(log_send_channel: impl Into<MevLogSender>, config: MevConfig) -> Self {
+        Mev::try_new(log_send_channel, config).unwrap_or_else(|err| panic!("[MEV] {}", err))
+    }
+
+    /// Build the `Mev` for `config`. When the config is not valid, the caller
+    /// decides whether to stop, or to continue without MEV.
+    pub fn try_new(
+        log_send_channel: impl Into<MevLogSender>,
+        mut config: MevConfig,
+    ) -> Result<Self, MevError> {
+        let log_send_channel = log_send_channel.into();
+        let threshold_summary = config.threshold_summary().map_err(MevError::Config)?;
+        info!(
+            "[MEV] Thresholds in the smallest unit of each token: {}",
+            serde_json::to_string(&threshold_summary)
+                .expect("Constructed by us, should never fail")
+        );
+        let minimum_profit = config.minimum_profit_by_mint().map_err(MevError::Config)?;
+        let max_inflight_exposure = config
+            .max_inflight_exposure_by_mint()
+            .map_err(MevError::Config)?;
+        let min_trigger_delta = config
+            .min_trigger_delta
+            .as_ref()
+            .map(|min_trigger_delta| MinTriggerDelta::new(min_trigger_delta, &config))
+            .transpose()
+            .map_err(MevError::Config)?;
+        let user_authority =
+            load_user_authority(config.user_authority_path.clone(), config.signer.clone())
+                .map_err(MevError::Config)?;
+        let pool_authorities = PoolAuthorities::load(&config).map_err(MevError::Config)?;
+        if config.derive_token_accounts_from_authority {
+            if user_authority.is_none() && pool_authorities.signers().is_empty() {
+                warn!(
+                    "[MEV] derive_token_accounts_from_authority is set, but there is no user \
+                    authority to derive the token accounts from."
+                );
+            }
+            let default_authority = user_authority.as_ref().map(|signer| signer.pubkey());
+            config.orca_accounts = std::mem::take(&mut config.orca_accounts)
+                .with_derived_token_accounts(|pool| {
+                    pool_authorities
+                        .for_pool(&pool.address)
+                        .map(|signer| signer.pubkey())
+                        .or(default_authority)
+                })
+                .map_err(|err| MevError::Config(format!("Invalid orca_account: {}", err)))?;
+        }
+        let discovered_paths = config
+            .auto_discover_paths
+            .as_ref()
+            .map(|auto_discover| discover_paths(auto_discover, &config))
+            .unwrap_or_default();
+        let mut mev_paths = Vec::with_capacity(config.mev_paths.len() + discovered_paths.len());
+        for path in std::mem::take(&mut config.mev_paths) {
+            check_path(&path, &config, &pool_authorities, user_authority.is_some())?;
+            mev_paths.push(path);
+        }
+        if config.auto_discover_paths.is_some() {
+            let mut names = Vec::with_capacity(discovered_paths.len());
+            for mut path in discovered_paths {
+                // A discovered path that we cannot trade, e.g. because a pool
+                // lacks our token accounts, should not stop the validator. We
+                // keep it disabled, so that the log thread, which discovers
+                // the same paths, agrees with us on the index of every path.
+                match check_path(&path, &config, &pool_authorities, user_authority.is_some()) {
+                    Ok(()) => names.push(path.name.clone()),
+                    Err(err) => {
+                        warn!("[MEV] Disabling discovered path: {}", err);
+                        path.enabled = false;
+                    }
+                }
+                mev_paths.push(path);
+            }
+            info!(
+                "[MEV] Discovered {} paths through the configured pools: {}",
+                names.len(),
+                names.join(", ")
+            );
+        }
+        let path_authorities = mev_paths
+            .iter()
+            .map(|path| {
+                pool_authorities
+                    .for_path(path)
+                    // Only the discovered paths that we disabled can fail.
+                    .unwrap_or_else(|_| {
+                        assert!(!path.enabled, "Checked above");
+                        None
+                    })
+                    .cloned()
+            })
+            .collect();
+        let reversed_paths = mev_paths
+            .iter()
+            .map(|path| path.probe_reverse.then(|| path.reversed()))
+            .collect();
+        let mut paths_by_pool: HashMap<Pubkey, Vec<usize>> = HashMap::new();
+        for (path_idx, path) in mev_paths.iter().enumerate() {
+            for pool in path
+                .path
+                .iter()
+                .flat_map(|pair_info| pair_info.candidate_pools())
+            {
+                let path_indices = paths_by_pool.entry(*pool).or_default();
+                if path_indices.last() != Some(&path_idx) {
+                    path_indices.push(path_idx);
+                }
+            }
+        }
+        let cu_calibration = config
+            .cu_calibration_file
+            .as_ref()
+            .map(|path| expand_path(path).and_then(|path| CuCalibration::load(&path)))
+            .transpose()
+            .map_err(|err| MevError::Config(format!("Invalid cu_calibration_file: {}", err)))?;
+        let profit_reporter = config
+            .reporting
+            .as_ref()
+            .map(|reporting| ProfitReporter::new(reporting, &config.orca_accounts))
+            .transpose()
+            .map_err(|err| MevError::Config(format!("Invalid reporting: {}", err)))?;
+        let trigger_accounts = config
+            .orca_accounts
+            .unique()
+            .flat_map(|pool| [pool.address, pool.pool_a_account, pool.pool_b_account])
+            .chain(config.watched_accounts.iter().map(|b58pubkey| b58pubkey.0))
+            .collect();
+        let forwarder = config
+            .fallback_rpc_url
+            .filter(|_url| config.fallback_when_not_leader)
+            .map(|url| parse_http_url(&url))
+            .transpose()
+            .map_err(|err| MevError::Config(format!("Invalid fallback_rpc_url: {}", err)))?
+            .map(|endpoint| TransactionForwarder::new(endpoint, log_send_channel.clone()));
+        Ok(Mev {
+            log_send_channel,
+            watched_programs: config
+                .watched_programs
+                .iter()
+                .map(|b58pubkey| b58pubkey.0)
+                .collect(),
+            trigger_accounts,
+            blocked_signers: config
+                .blocked_signers
+                .iter()
+                .map(|b58pubkey| b58pubkey.0)
+                .collect(),
+            blocked_pools: config
+                .blocked_pools
+                .iter()
+                .map(|b58pubkey| b58pubkey.0)
+                .collect(),
+            orca_monitored_accounts: Arc::new(config.orca_accounts),
+            mev_paths,
+            user_authority,
+            pool_authorities: Arc::new(pool_authorities),
+            path_authorities,
+            reversed_paths,
+            paths_by_pool,
+            minimum_profit,
+            default_minimum_profit: config.default_minimum_profit,
+            max_inflight_exposure,
+            inflight_exposure: Arc::new(Mutex::new(InflightExposure::default())),
+            priority_fee: config.priority_fee,
+            slippage_bps: config.slippage_bps,
+            compute_unit_limit: config.compute_unit_limit,
+            evaluated_paths: Arc::new(Mutex::new(EvaluatedPaths::default())),
+            last_tick_slot: Arc::new(AtomicU64::new(0)),
+            confirm_trigger_before_craft: config.confirm_trigger_before_craft,
+            pending_opportunities: Arc::new(Mutex::new(PendingOpportunities::default())),
+            checked_pool_mints: Arc::new(Mutex::new(HashMap::new())),
+            loaded_pool_mints: Arc::new(Mutex::new(HashMap::new())),
+            loaded_pool_token_programs: Arc::new(Mutex::new(HashMap::new())),
+            fee_payer_balances: Arc::new(Mutex::new(HashMap::new())),
+            audit_mode: config.audit_mode,
+            dry_run: config.dry_run,
+            exact_optimal_input: config.exact_optimal_input,
+            min_pool_reserve: config.min_pool_reserve.unwrap_or(0),
+            max_hop_output_bps: config.max_hop_output_bps.unwrap_or(MAX_HOP_OUTPUT_BPS),
+            log_skipped_paths: config.log_skipped_paths,
+            auto_discover_paths: config.auto_discover_paths,
+            skipped_vote_triggers: Arc::new(AtomicU64::new(0)),
+            duplicate_opportunities: Arc::new(AtomicU64::new(0)),
+            pool_load_errors: Arc::new(PoolLoadErrorCounters::default()),
+            forwarder,
+            imbalance_observations: Arc::new(Mutex::new(ImbalanceObservations::default())),
+            config_generation: 0,
+            compute_unit_estimator: ComputeUnitEstimator::new(cu_calibration),
+            systemic_failures: Arc::new(Mutex::new(SystemicFailureMonitor::new(
+                config.systemic_failure,
+            ))),
+            profit_reporter,
+            pending_reported_profits: Arc::new(Mutex::new(PendingReportedProfits::default())),
+            max_mev_txs_per_slot: config.max_mev_txs_per_slot,
+            max_opportunities_per_trigger: config.max_opportunities_per_trigger.unwrap_or(1),
+            opportunity_conflict: config.opportunity_conflict,
+            slot_tx_count: Arc::new(Mutex::new(SlotTxCount::default())),
+            min_trigger_delta,
+            lock_impact_sampler: config
+                .lock_impact_sampling_rate
+                .map(|rate| Arc::new(LockImpactSampler::new(rate))),
+            signing_thread: config.signing_thread_wait_ms.map(|wait_ms| {
+                // The thread exits once the last `Mev` that uses it is dropped.
+                let (signing_thread, _handle) =
+                    SigningThread::spawn(Duration::from_millis(wait_ms));
+                signing_thread
+            }),
+            path_cache: Arc::new(Mutex::new(PathCache::default())),
+        })
+    }
+
+    /// Build the `Mev` for a reloaded `config`. It shares the log channel and
+    /// the state of our in-flight transactions with `self`, and has the next
+    /// config generation, so transactions that got their MEV keys from `self`
+    /// are not evaluated against the new config. A pause because of systemic
+    /// failures carries over, unless the new config no longer pauses.
+    pub fn reload(&self, config: MevConfig) -> Self {
+        self.try_reload(config)
+            .unwrap_or_else(|err| panic!("[MEV] {}", err))
+    }
+
+    /// Like `reload`, but returns the error when `config` is not valid, and
+    /// then leaves `self` as it is.
+    pub fn try_reload(&self, config: MevConfig) -> Result<Self, MevError> {
+        let systemic_failure = config.systemic_failure;
+        let mev = Mev::try_new(self.log_send_channel.clone(), config)?;
+        self.systemic_failures
+            .lock()
+            .unwrap()
+            .set_config(systemic_failure);
+        // The log thread names the paths of what we send it by their index.
+        if let Err(err) = self
+            .log_send_channel
+            .send(MevMsg::Paths(mev.mev_paths.clone()))
+        {
+            error_throttled!(
+                "mev_log_paths",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not send the reloaded paths, error: {}",
+                err
+            );
+        }
+        Ok(Mev {
+            inflight_exposure: self.inflight_exposure.clone(),
+            evaluated_paths: self.evaluated_paths.clone(),
+            last_tick_slot: self.last_tick_slot.clone(),
+            pending_opportunities: self.pending_opportunities.clone(),
+            skipped_vote_triggers: self.skipped_vote_triggers.clone(),
+            duplicate_opportunities: self.duplicate_opportunities.clone(),
+            pool_load_errors: self.pool_load_errors.clone(),
+            systemic_failures: self.systemic_failures.clone(),
+            pending_reported_profits: self.pending_reported_profits.clone(),
+            slot_tx_count: self.slot_tx_count.clone(),
+            config_generation: self.config_generation + 1,
+            ..mev
+        })
+    }
+
+    /// Tell the log thread that we are processing transactions in `slot`, for
+    /// its watchdog, with the bank's estimated `slot_time`. Only the first
+    /// call for a slot sends a message.
+    pub fn tick(&self, slot: Slot, slot_time: UnixTimestamp) {
+        if self.last_tick_slot.fetch_max(slot, Ordering::Relaxed) >= slot {
+            return;
+        }
+        // `datapoint_info!` only evaluates its fields when metrics are
+        // enabled, so we reset the counters outside of it.
+        let skipped_vote_triggers = self.skipped_vote_triggers.swap(0, Ordering::Relaxed);
+        let duplicate_opportunities = self.duplicate_opportunities.swap(0, Ordering::Relaxed);
+        datapoint_info!(
+            "mev-triggers",
+            ("skipped_vote", skipped_vote_triggers as i64, i64),
+        );
+        datapoint_info!(
+            "mev-opportunities",
+            ("duplicate", duplicate_opportunities as i64, i64),
+        );
+        self.pool_load_errors.report_metrics();
+        // Triggers of earlier slots were committed or dropped by now.
+        let expired = self
+            .pending_opportunities
+            .lock()
+            .unwrap()
+            .expire_before(slot);
+        self.drop_pending_transactions(expired);
+        if let Err(err) = self.log_send_channel.send(MevMsg::Tick(slot, slot_time)) {
+            error_throttled!(
+                "mev_log_tick",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not send tick, error: {}",
+                err
+            );
+        }
+    }
+
+    /// Snapshot of the amount per token mint that is committed to MEV
+    /// transactions which did not execute yet.
+    pub fn get_inflight_exposure(&self) -> HashMap<Pubkey, u64> {
+        self.inflight_exposure.lock().unwrap().totals().clone()
+    }
+
+    /// Ask the log thread to write the current status next to the log, e.g.
+    /// when the operator sends SIGUSR1 to the validator.
+    pub fn write_status(&self) {
+        if let Err(err) = self
+            .log_send_channel
+            .send(MevMsg::WriteStatus(self.get_inflight_exposure()))
+        {
+            error_throttled!(
+                "mev_log_write_status",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not request status, error: {}",
+                err
+            );
+        }
+    }
+
+    /// The config that we run with, after defaults, with the thresholds in the
+    /// smallest unit of each token, and the authority that signs for every pool
+    /// and path.
+    pub fn dump_config(&self) -> ConfigDump {
+        let by_mint = |amounts: &HashMap<Pubkey, u64>| {
+            amounts
+                .iter()
+                .map(|(mint, amount)| (mint.to_string(), *amount))
+                .collect()
+        };
+        let default_authority = self.user_authority.as_ref().map(|signer| signer.pubkey());
+        ConfigDump {
+            config_generation: self.config_generation,
+            watched_programs: sorted_b58(&self.watched_programs),
+            trigger_accounts: sorted_b58(&self.trigger_accounts),
+            blocked_signers: sorted_b58(&self.blocked_signers),
+            blocked_pools: sorted_b58(&self.blocked_pools),
+            user_authority: default_authority,
+            thresholds: ThresholdSummary {
+                minimum_profit: by_mint(&self.minimum_profit),
+                default_minimum_profit: self.default_minimum_profit,
+                max_inflight_exposure: by_mint(&self.max_inflight_exposure),
+                min_trigger_delta_amounts: self
+                    .min_trigger_delta
+                    .as_ref()
+                    .map(|min_trigger_delta| by_mint(&min_trigger_delta.amounts))
+                    .unwrap_or_default(),
+            },
+            min_trigger_delta_bps: self
+                .min_trigger_delta
+                .as_ref()
+                .and_then(|min_trigger_delta| min_trigger_delta.bps),
+            priority_fee: self.priority_fee.clone(),
+            slippage_bps: self.slippage_bps,
+            compute_unit_limit: self.compute_unit_limit,
+            max_mev_txs_per_slot: self.max_mev_txs_per_slot,
+            max_opportunities_per_trigger: self.max_opportunities_per_trigger,
+            opportunity_conflict: self.opportunity_conflict,
+            confirm_trigger_before_craft: self.confirm_trigger_before_craft,
+            fallback_when_not_leader: self.forwarder.is_some(),
+            audit_mode: self.audit_mode,
+            dry_run: self.dry_run,
+            exact_optimal_input: self.exact_optimal_input,
+            min_pool_reserve: self.min_pool_reserve,
+            max_hop_output_bps: self.max_hop_output_bps,
+            log_skipped_paths: self.log_skipped_paths,
+            auto_discover_paths: self.auto_discover_paths,
+            pools: self
+                .orca_monitored_accounts
+                .unique()
+                .map(|pool| PoolDump {
+                    pool: pool.clone(),
+                    user_authority: self
+                        .pool_authorities
+                        .for_pool(&pool.address)
+                        .map(|signer| signer.pubkey())
+                        .or(default_authority),
+                })
+                .collect(),
+            paths: self
+                .mev_paths
+                .iter()
+                .zip(&self.path_authorities)
+                .map(|(path, path_authority)| PathDump {
+                    path: path.clone(),
+                    user_authority: path_authority
+                        .as_ref()
+                        .map(|signer| signer.pubkey())
+                        .or(default_authority),
+                    slippage_bps: path.slippage_bps.or(self.slippage_bps),
+                    compute_unit_limit: path.compute_unit_limit.or(self.compute_unit_limit),
+                })
+                .collect(),
+        }
+    }
+
+    /// Log the config that we run with as a `config` event, see
+    /// `dump_config`. The validator does so at startup and on SIGUSR1.
+    pub fn log_config(&self) {
+        if let Err(err) = self
+            .log_send_channel
+            .send(MevMsg::Config(Box::new(self.dump_config())))
+        {
+            error_throttled!(
+                "mev_log_config",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log the config, error: {}",
+                err
+            );
+        }
+    }
+
+    /// Send `tx` to the fallback RPC, when configured, because we could not
+    /// record it ourselves. Returns whether a fallback RPC is configured.
+    pub fn forward_transaction(&self, tx: &SanitizedTransaction) -> bool {
+        match &self.forwarder {
+            Some(forwarder) => {
+                forwarder.forward(tx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Release the in-flight exposure of a MEV transaction once it executed,
+    /// or once it is clear that it will not be executed.
+    pub fn release_inflight_exposure(&self, signature: &Signature) {
+        self.inflight_exposure.lock().unwrap().release(signature);
+    }
+
+    /// Release `transactions`, that we crafted in `slot` but will not
+    /// execute: their in-flight exposure, and their share of
+    /// `max_mev_txs_per_slot`, so that a later trigger in the slot can use it.
+    pub fn release_txs<'a>(
+        &self,
+        slot: Slot,
+        transactions: impl IntoIterator<Item = &'a SanitizedTransaction>,
+    ) {
+        let mut tx_count = 0;
+        for transaction in transactions {
+            self.release_inflight_exposure(transaction.signature());
+            tx_count += 1;
+        }
+        self.slot_tx_count.lock().unwrap().remove(slot, tx_count);
+    }
+
+    /// Record how one of our transactions ended in `slot`, with `failure` set
+    /// when it failed, and pause or resume crafting accordingly.
+    pub fn record_execution(&self, slot: Slot, failure: Option<&ExecutionFailure>) {
+        let event = self.systemic_failures.lock().unwrap().record(slot, failure);
+        if let Some(event) = event {
+            self.send_systemic_failure_event(event);
+        }
+    }
+
+    /// Resume crafting after systemic failures paused it, e.g. once the
+    /// operator fixed the cause, without waiting for our transactions to
+    /// execute again.
+    pub fn resume_crafting(&self) {
+        let slot = self.last_tick_slot.load(Ordering::Relaxed);
+        let event = self.systemic_failures.lock().unwrap().resume_manually(slot);
+        if let Some(event) = event {
+            self.send_systemic_failure_event(event);
+        }
+    }
+
+    fn send_systemic_failure_event(&self, event: SystemicFailureEvent) {
+        if let Err(err) = self.log_send_channel.send(MevMsg::SystemicFailure(event)) {
+            error_throttled!(
+                "mev_log_systemic_failure",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log systemic failure, error: {}",
+                err
+            );
+        }
+    }
+
+    /// With `confirm_trigger_before_craft`, hold `transactions` back until we
+    /// know whether the trigger with `trigger_signature` was committed, see
+    /// `take_committed_trigger_tx`.
+    pub fn hold_until_trigger_committed(
+        &self,
+        trigger_signature: Signature,
+        slot: Slot,
+        transactions: Vec<(SanitizedTransaction, u64)>,
+    ) {
+        let expired = self.pending_opportunities.lock().unwrap().insert(
+            trigger_signature,
+            slot,
+            transactions,
+        );
+        self.drop_pending_transactions(expired);
+    }
+
+    /// Given the triggers of a batch as (`signature`, `committed`), where
+    /// `committed` is whether the trigger executed successfully and was
+    /// committed, return the transactions of the most profitable opportunity
+    /// that waited for a committed trigger. Like without
+    /// `confirm_trigger_before_craft`, we execute at most one MEV opportunity
+    /// per batch, the others are released. Transactions whose trigger was not
+    /// committed are dropped and logged.
+    pub fn take_committed_trigger_tx(
+        &self,
+        triggers: impl IntoIterator<Item = (Signature, bool)>,
+    ) -> Vec<(SanitizedTransaction, u64)> {
+        let total_profit = |transactions: &[(SanitizedTransaction, u64)]| -> u64 {
+            transactions
+                .iter()
+                .map(|(_transaction, profit)| profit)
+                .sum()
+        };
+        let mut best: (Slot, Vec<(SanitizedTransaction, u64)>) = (0, Vec::new());
+        let mut not_committed = Vec::new();
+        {
+            let mut pending_opportunities = self.pending_opportunities.lock().unwrap();
+            for (trigger_signature, committed) in triggers {
+                let (slot, transactions) = match pending_opportunities.take(&trigger_signature) {
+                    Some(pending) => pending,
+                    None => continue,
+                };
+                let (released_slot, released) = if !committed {
+                    not_committed.extend(
+                        transactions
+                            .into_iter()
+                            .map(|(transaction, _profit)| (trigger_signature, slot, transaction)),
+                    );
+                    continue;
+                } else if !best.1.is_empty() && total_profit(&best.1) >= total_profit(&transactions)
+                {
+                    (slot, transactions)
+                } else {
+                    std::mem::replace(&mut best, (slot, transactions))
+                };
+                self.release_txs(
+                    released_slot,
+                    released.iter().map(|(transaction, _profit)| transaction),
+                );
+            }
+        }
+        self.drop_pending_transactions(not_committed);
+        best.1
+    }
+
+    /// Release transactions that will not be executed because their trigger
+    /// was not committed, see `release_txs`, and log them.
+    fn drop_pending_transactions(&self, dropped: Vec<(Signature, Slot, SanitizedTransaction)>) {
+        for (trigger_signature, slot, transaction) in dropped {
+            self.release_txs(slot, [&transaction]);
+            let dropped_transaction = DroppedTransaction {
+                transaction_signature: *transaction.signature(),
+                trigger_signature,
+                reason: SkipReason::TriggerNotCommitted,
+            };
+            if let Err(err) = self
+                .log_send_channel
+                .send(MevMsg::DroppedTransaction(dropped_transaction))
+            {
+                error_throttled!(
+                    "mev_log_dropped_transaction",
+                    LOG_THROTTLE_INTERVAL,
+                    "[MEV] Could not log dropped transaction, error: {}",
+                    err
+                );
+            }
+        }
+    }
+
+    /// Fill the field of `transaction.mev_accounts` with accounts we are
+    /// interested in watching. Simple vote transactions cannot trade through
+    /// the pools, so they never get them.
+    pub fn fill_tx_mev_accounts(&self, tx: &mut SanitizedTransaction) {
+        if let Some(sampler) = &self.lock_impact_sampler {
+            if sampler.sample() {
+                self.report_lock_impact(self.lock_impact(tx));
+            }
+        }
+        if !self.is_monitored_account(tx) {
+            return;
+        }
+        if tx.is_simple_vote_transaction() {
+            self.skipped_vote_triggers.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        tx.mev_keys = Some(self.mev_keys());
+    }
+
+    /// The locks that `fill_tx_mev_accounts` would add to `tx`, without
+    /// changing `tx` or counting it as a trigger.
+    pub fn lock_impact(&self, tx: &SanitizedTransaction) -> LockImpactReport {
+        let mev_keys = (self.is_monitored_account(tx) && !tx.is_simple_vote_transaction())
+            .then(|| self.mev_keys());
+        LockImpactReport::new(tx, mev_keys.as_ref())
+    }
+
+    fn report_lock_impact(&self, report: LockImpactReport) {
+        if let Err(err) = self.log_send_channel.send(MevMsg::LockImpact(report)) {
+            error_throttled!(
+                "mev_log_lock_impact",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log lock impact, error: {}",
+                err
+            );
+        }
+    }
+
+    /// The user authority that signs the transactions of the path at
+    /// `path_idx`, if any.
+    fn path_authority(&self, path_idx: usize) -> Option<&Arc<dyn MevSigner>> {
+        self.path_authorities
+            .get(path_idx)
+            .and_then(Option::as_ref)
+            .or_else(|| self.user_authority.as_ref())
+    }
+
+    /// Whether `fee_payer` can pay `fee_lamports`, as far as we know its
+    /// balance. Before a trigger loaded it, we assume that it can.
+    fn can_pay_fee(&self, fee_payer: &Pubkey, fee_lamports: u64) -> bool {
+        self.fee_payer_balances
+            .lock()
+            .unwrap()
+            .get(fee_payer)
+            .map_or(true, |balance| *balance >= fee_lamports)
+    }
+
+    /// The MEV keys for all pools, which we load with every transaction that
+    /// triggers us.
+    fn mev_keys(&self) -> MevKeys {
+        let loaded_pool_mints = self.loaded_pool_mints.lock().unwrap();
+        let loaded_pool_token_programs = self.loaded_pool_token_programs.lock().unwrap();
+        let pool_keys = self
+            .orca_monitored_accounts
+            .unique()
+            .map(|orca_pool| {
+                // Without our token accounts, the pool's accounts are only
+                // locked for reading.
+                let (source, destination) = match orca_pool.role {
+                    PoolRole::Trade => (orca_pool.source, orca_pool.destination),
+                    PoolRole::Observe => (None, None),
+                };
+                // Until we know the mints, we do not load them.
+                let (token_a_mint, token_b_mint) = match orca_pool
+                    .known_mints()
+                    .or_else(|| loaded_pool_mints.get(&orca_pool.address).copied())
+                {
+                    Some((mint_a, mint_b)) => (Some(mint_a), Some(mint_b)),
+                    None => (None, None),
+                };
+                MevPoolKeys {
+                    pool: orca_pool.address,
+                    source,
+                    destination,
+                    token_a: orca_pool.pool_a_account,
+                    token_b: orca_pool.pool_b_account,
+                    pool_mint: orca_pool.pool_mint,
+                    pool_fee: orca_pool.pool_fee,
+                    pool_authority: orca_pool.pool_authority,
+                    token_program: loaded_pool_token_programs
+                        .get(&orca_pool.address)
+                        .copied()
+                        .unwrap_or_else(inline_spl_token::id),
+                    token_a_mint,
+                    token_b_mint,
+                }
+            })
+            .collect();
+        MevKeys {
+            pool_keys,
+            user_authorities: self
+                .user_authority
+                .iter()
+                .chain(self.pool_authorities.signers())
+                .map(|signer| signer.pubkey())
+                .collect(),
+            config_generation: self.config_generation,
+        }
+    }
+
+    /// Attempts to deserialize the Orca accounts MEV is interested in,
+    /// in case the deserialization fails for some pools, reports and returns
+    /// the errors.
+    pub fn get_all_orca_monitored_accounts(
+        &self,
+        loaded_transaction: &LoadedTransaction,
+    ) -> Option<Result<PoolStates, Vec<PoolLoadError>>> {
+        let pool_states = PoolStates::from_loaded_transaction(loaded_transaction);
+        match &pool_states {
+            Some(Ok(pool_states)) => {
+                self.remember_loaded_pool_mints(pool_states);
+                self.remember_loaded_pool_token_programs(pool_states);
+            }
+            Some(Err(errors)) => self.report_pool_load_errors(errors),
+            None => {}
+        }
+        pool_states
+    }
+
+    /// Remember the mints of the pools in `pool_states` whose mints are not in
+    /// the config, see `loaded_pool_mints`.
+    fn remember_loaded_pool_mints(&self, pool_states: &PoolStates) {
+        let mut loaded_pool_mints = self.loaded_pool_mints.lock().unwrap();
+        for (address, pool_state) in &pool_states.0 {
+            let has_config_mints = self
+                .orca_monitored_accounts
+                .by_address(address)
+                .map_or(false, |orca_pool| orca_pool.known_mints().is_some());
+            if !has_config_mints && !loaded_pool_mints.contains_key(address) {
+                loaded_pool_mints.insert(
+                    *address,
+                    (pool_state.pool.pool_a_mint, pool_state.pool.pool_b_mint),
+                );
+            }
+        }
+    }
+
+    /// Remember the token programs of the pools in `pool_states`, see
+    /// `loaded_pool_token_programs`.
+    fn remember_loaded_pool_token_programs(&self, pool_states: &PoolStates) {
+        let mut loaded_pool_token_programs = self.loaded_pool_token_programs.lock().unwrap();
+        for (address, pool_state) in &pool_states.0 {
+            loaded_pool_token_programs.insert(*address, pool_state.pool.token_program);
+        }
+    }
+
+    fn report_pool_load_errors(&self, errors: &[PoolLoadError]) {
+        self.pool_load_errors.record(errors);
+        warn_throttled!(
+            "mev_pool_load_error",
+            LOG_THROTTLE_INTERVAL,
+            "[MEV] Could not load {} pool(s), not evaluating the transaction, first error: {}",
+            errors.len(),
+            errors[0]
+        );
+        if let Err(err) = self
+            .log_send_channel
+            .send(MevMsg::PoolLoadErrors(errors.to_vec()))
+        {
+            error_throttled!(
+                "mev_log_pool_load_errors",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log pool load errors, error: {}",
+                err
+            );
+        }
+    }
+
+    /// Whether `tx` interacts with the `watched_programs`, references one of
+    /// the `trigger_accounts`, and is not signed by any of the
+    /// `blocked_signers`.
+    pub fn is_monitored_account(&self, tx: &SanitizedTransaction) -> bool {
+        let message = tx.message();
+        let account_keys = message.account_keys();
+        let mut references_program = false;
+        let mut references_account = false;
+        for (idx, account_key) in account_keys.iter().enumerate() {
+            if message.is_signer(idx) && self.blocked_signers.contains(account_key) {
+                return false;
+            }
+            references_program |= self.watched_programs.contains(account_key);
+            references_account |= self.trigger_accounts.contains(account_key);
+        }
+        references_program && references_account
+    }
+
+    /// Log the pool state after a transaction interacted with one or more
+    /// account from the pool
+    /// Returns the transactions of the most profitable MEV opportunity, in the
+    /// order in which to execute them, with their profit in the token's unit.
+    #[allow(clippy::too_many_arguments)]
+    pub fn log_mev_opportunities_get_max_profit_tx(
+        &self,
+        tx: &SanitizedTransaction,
+        slot: Slot,
+        trigger_failed: bool,
+        pre_tx_pool_state: PoolStates,
+        loaded_tx: &LoadedTransaction,
+        blockhash: Hash,
+        rent: &Rent,
+    ) -> Vec<(SanitizedTransaction, u64)> {
+        let post_tx_pool_state = match self.get_all_orca_monitored_accounts(loaded_tx) {
+            Some(Ok(post_tx_pool_state)) => post_tx_pool_state,
+            _ => return Vec::new(),
+        };
+        self.fee_payer_balances
+            .lock()
+            .unwrap()
+            .extend(loaded_user_authority_balances(loaded_tx));
+        self.evaluate_trigger(
+            tx,
+            slot,
+            trigger_failed,
+            pre_tx_pool_state,
+            post_tx_pool_state,
+            blockhash,
+            rent,
+        )
+    }
+
+    /// Like `log_mev_opportunities_get_max_profit_tx`, with the pool states
+    /// after `tx` already loaded.
+    #[allow(clippy::too_many_arguments)]
+    pub fn evaluate_trigger(
+        &self,
+        tx: &SanitizedTransaction,
+        slot: Slot,
+        trigger_failed: bool,
+        mut pre_tx_pool_state: PoolStates,
+        mut post_tx_pool_state: PoolStates,
+        blockhash: Hash,
+        rent: &Rent,
+    ) -> Vec<(SanitizedTransaction, u64)> {
+        // The pool states come from the keys and accounts that were loaded
+        // with the transaction, but our paths and indices belong to the
+        // current config, which may no longer know those pools.
+        let stale_generation = tx
+            .mev_keys()
+            .map(|mev_keys| mev_keys.config_generation)
+            .filter(|generation| *generation != self.config_generation);
+        if let Some(generation) = stale_generation {
+            warn_throttled!(
+                "mev_stale_generation",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Transaction {} carries pools of config generation {}, but we are at {}, not evaluating it",
+                tx.signature(),
+                generation,
+                self.config_generation
+            );
+            self.log_pool_states(PrePostPoolStates {
+                stale_generation,
+                ..PrePostPoolStates::new(tx, slot, pre_tx_pool_state, post_tx_pool_state)
+            });
+            return Vec::new();
+        }
+        self.set_pool_config(&mut pre_tx_pool_state);
+        self.set_pool_config(&mut post_tx_pool_state);
+
+        // A failed transaction still hands us the accounts that it wrote to,
+        // but those writes never commit, so any opportunity in them is not
+        // real.
+        if trigger_failed {
+            self.log_pool_states(PrePostPoolStates {
+                trigger_failed,
+                ..PrePostPoolStates::new(tx, slot, pre_tx_pool_state, post_tx_pool_state)
+            });
+            return Vec::new();
+        }
+
+        self.disable_mismatched_pools(&mut pre_tx_pool_state, &mut post_tx_pool_state);
+        let mut mev_tx_outputs = self.get_arbitrage_tx_outputs_in_slot(
+            &post_tx_pool_state,
+            blockhash,
+            rent,
+            get_compute_unit_price(tx),
+            Some(slot),
+            Some(&pre_tx_pool_state),
+        );
+        self.clear_vanished_imbalances(
+            slot,
+            &pre_tx_pool_state,
+            &post_tx_pool_state,
+            &mev_tx_outputs,
+        );
+        let trigger_kind = TriggerKind::of(tx);
+        for mev_tx_output in &mut mev_tx_outputs {
+            mev_tx_output.trigger_kind = trigger_kind;
+        }
+
+        // Our own transactions are not competitors.
+        let is_own_tx = self
+            .user_authority
+            .iter()
+            .chain(self.pool_authorities.signers())
+            .any(|signer| signer.pubkey() == *tx.message().fee_payer());
+        if !is_own_tx {
+            let competitor_arbitrages = self.detect_competitor_arbitrages(
+                *tx.signature(),
+                slot,
+                &pre_tx_pool_state,
+                &post_tx_pool_state,
+            );
+            if !competitor_arbitrages.is_empty() {
+                if let Err(err) = self
+                    .log_send_channel
+                    .send(MevMsg::CompetitorArbitrages(competitor_arbitrages))
+                {
+                    error_throttled!(
+                        "mev_log_competitor_arbitrages",
+                        LOG_THROTTLE_INTERVAL,
+                        "[MEV] Could not log competitor arbitrages, error: {}",
+                        err
+                    );
+                }
+            }
+        }
+        {
+            let mut evaluated_paths = self.evaluated_paths.lock().unwrap();
+            for mev_tx_output in &mev_tx_outputs {
+                evaluated_paths.insert(slot, mev_tx_output.path_idx);
+            }
+        }
+
+        self.log_pool_states(PrePostPoolStates::new(
+            tx,
+            slot,
+            pre_tx_pool_state,
+            post_tx_pool_state,
+        ));
+
+        if mev_tx_outputs.is_empty() {
+            return Vec::new();
+        }
+        let mut mev_tx_outputs = self.dedup_opportunities(mev_tx_outputs);
+        let max_profit_tx = self.take_top_profit_txs(
+            &mut mev_tx_outputs,
+            slot,
+            self.max_opportunities_per_trigger as usize,
+            self.opportunity_conflict,
+        );
+
+        if let Err(err) = self
+            .log_send_channel
+            .send(MevMsg::Opportunities(mev_tx_outputs))
+        {
+            error_throttled!(
+                "mev_log_arbitrage",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log arbitrage, error: {}",
+                err
+            );
+        }
+        max_profit_tx
+    }
+
+    /// Set the roles and host fee accounts from the config on the pool states,
+    /// which are loaded from the pools' accounts and do not know them.
+    pub(super) fn set_pool_config(&self, pool_states: &mut PoolStates) {
+        for (address, pool_state) in pool_states.0.iter_mut() {
+            if let Some(pool) = self.orca_monitored_accounts.by_address(address) {
+                pool_state.pool.role = pool.role;
+                pool_state.pool.host_fee = pool.host_fee;
+            }
+        }
+    }
+
+    /// The indices of the paths through a pool whose balances differ between
+    /// `pre_pool_states` and `post_pool_states`, the only paths where a
+    /// trigger can create an opportunity. A pool that is not in
+    /// `pre_pool_states` counts as changed, we cannot tell.
+    pub fn touched_paths(
+        &self,
+        pre_pool_states: &PoolStates,
+        post_pool_states: &PoolStates,
+    ) -> HashSet<usize> {
+        post_pool_states
+            .0
+            .iter()
+            .filter(|(address, post)| match pre_pool_states.0.get(address) {
+                Some(pre) => {
+                    pre.pool_a_balance != post.pool_a_balance
+                        || pre.pool_b_balance != post.pool_b_balance
+                }
+                None => true,
+            })
+            .filter_map(|(address, _post)| self.paths_by_pool.get(address))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Start the count over for the paths with `require_confirmations` whose
+    /// pools are all in `post_pool_states`, and that the trigger touched, but
+    /// that have no opportunity in `mev_tx_outputs`.
+    fn clear_vanished_imbalances(
+        &self,
+        slot: Slot,
+        pre_pool_states: &PoolStates,
+        post_pool_states: &PoolStates,
+        mev_tx_outputs: &[MevTxOutput],
+    ) {
+        let touched_paths = self.touched_paths(pre_pool_states, post_pool_states);
+        let mut imbalance_observations = self.imbalance_observations.lock().unwrap();
+        for (path_idx, mev_path) in self.mev_paths.iter().enumerate() {
+            if mev_path.require_confirmations.unwrap_or(0) == 0
+                || !touched_paths.contains(&path_idx)
+                || mev_tx_outputs
+                    .iter()
+                    .any(|mev_tx_output| mev_tx_output.path_idx == path_idx)
+                || mev_path.resolve_hops(post_pool_states).is_err()
+            {
+                continue;
+            }
+            imbalance_observations.clear(slot, path_idx);
+        }
+    }
+
+    fn log_pool_states(&self, pool_states: PrePostPoolStates) {
+        if let Err(err) = self.log_send_channel.send(MevMsg::Log(pool_states)) {
+            error_throttled!(
+                "mev_log_pool_states",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log pool states, error: {}",
+                err
+            );
+        }
+    }
+
+    /// Compare the mints of the pools in `post_pool_states` with the mints in
+    /// the config, the first time we load each pool. A pool that holds other
+    /// mints than the config says is logged once and disabled: from then on we
+    /// remove it from both `pre_pool_states` and `post_pool_states`, so that
+    /// no path trades through it. Pools without mints in the config are not
+    /// checked.
+    pub fn disable_mismatched_pools(
+        &self,
+        pre_pool_states: &mut PoolStates,
+        post_pool_states: &mut PoolStates,
+    ) {
+        let mut mismatches = Vec::new();
+        {
+            let mut checked_pool_mints = self.checked_pool_mints.lock().unwrap();
+            for (address, pool_state) in &post_pool_states.0 {
+                if checked_pool_mints.contains_key(address) {
+                    continue;
+                }
+                let config_mints = match self
+                    .orca_monitored_accounts
+                    .by_address(address)
+                    .and_then(|pool| pool.known_mints())
+                {
+                    Some(config_mints) => config_mints,
+                    None => continue,
+                };
+                let loaded_mints = (pool_state.pool.pool_a_mint, pool_state.pool.pool_b_mint);
+                checked_pool_mints.insert(*address, config_mints == loaded_mints);
+                if config_mints != loaded_mints {
+                    mismatches.push(PoolConfigMismatch {
+                        pool: *address,
+                        config_pool_a_mint: config_mints.0,
+                        config_pool_b_mint: config_mints.1,
+                        pool_a_mint: loaded_mints.0,
+                        pool_b_mint: loaded_mints.1,
+                    });
+                }
+            }
+            for pool_states in [&mut *pre_pool_states, &mut *post_pool_states] {
+                pool_states
+                    .0
+                    .retain(|address, _pool_state| checked_pool_mints.get(address) != Some(&false));
+            }
+        }
+        for mismatch in mismatches {
+            if let Err(err) = self
+                .log_send_channel
+                .send(MevMsg::PoolConfigMismatch(mismatch))
+            {
+                error_throttled!(
+                    "mev_log_pool_config_mismatch",
+                    LOG_THROTTLE_INTERVAL,
+                    "[MEV] Could not log pool config mismatch, error: {}",
+                    err
+                );
+            }
+        }
+    }
+
+    /// Take the transactions out of the most profitable opportunity, see
+    /// `take_top_profit_txs`.
+    pub fn take_max_profit_tx(
+        &self,
+        mev_tx_outputs: &mut [MevTxOutput],
+        slot: Slot,
+    ) -> Vec<(SanitizedTransaction, u64)> {
+        self.take_top_profit_txs(mev_tx_outputs, slot, 1, OpportunityConflict::Pool)
+    }
+
+    /// Take the transactions out of up to `max_opportunities` opportunities,
+    /// greedily by profit, skipping the opportunities that `conflict` with
+    /// one that we selected before. An opportunity is either a single output,
+    /// or the outputs of a path that we split into a sequence of transactions,
+    /// which are only executed together and compete with their summed profit.
+    /// A selected opportunity without transactions still counts, so that we
+    /// never execute an opportunity that a better one, which we could not
+    /// execute, conflicts with. With `audit_mode`, only the outputs of the
+    /// selected opportunities keep their decision trace, completed with how
+    /// they compared to the other outputs.
+    /// The profits are those at the inputs that we trade, so when our balance
+    /// caps the input of a path, it ranks by what it makes with that balance,
+    /// not by what it would make at its optimal input.
+    /// Returns the transactions to execute in order, the most profitable
+    /// opportunity first, with their profit.
+    pub fn take_top_profit_txs(
+        &self,
+        mev_tx_outputs: &mut [MevTxOutput],
+        slot: Slot,
+        max_opportunities: usize,
+        conflict: OpportunityConflict,
+    ) -> Vec<(SanitizedTransaction, u64)> {
+        let opportunities = Mev::get_opportunities(mev_tx_outputs);
+        // Profits in different mints only compare in the quote mint, so we
+        // rank by the value in it when every opportunity has one, and by the
+        // profit in the input mint otherwise.
+        let profits_in_quote: Option<Vec<u64>> = opportunities
+            .iter()
+            .map(|opportunity| Mev::profit_in_quote(&mev_tx_outputs[opportunity.clone()]))
+            .collect();
+        let mut opportunities: Vec<(Range<usize>, u64)> = match profits_in_quote {
+            Some(profits_in_quote) => opportunities.into_iter().zip(profits_in_quote).collect(),
+            None => opportunities
+                .into_iter()
+                .map(|opportunity| {
+                    let profit = mev_tx_outputs[opportunity.clone()]
+                        .iter()
+                        .map(|mev_tx_output| mev_tx_output.profit)
+                        .sum::<u64>();
+                    (opportunity, profit)
+                })
+                .collect(),
+        };
+        // The sort is stable, on a tie the opportunity that comes last wins.
+        opportunities.reverse();
+        opportunities.sort_by_key(|(_opportunity, profit)| Reverse(*profit));
+
+        let mut selected: Vec<Range<usize>> = Vec::new();
+        let mut taken_accounts = HashSet::new();
+        for (opportunity, _profit) in opportunities {
+            if selected.len() >= max_opportunities {
+                break;
+            }
+            let accounts = Mev::conflict_accounts(&mev_tx_outputs[opportunity.clone()], conflict);
+            if !taken_accounts.is_disjoint(&accounts) {
+                continue;
+            }
+            taken_accounts.extend(accounts);
+            selected.push(opportunity);
+        }
+        if self.audit_mode {
+            Mev::trace_selection(mev_tx_outputs, &selected);
+        }
+        let mut transactions = Vec::new();
+        for opportunity in selected {
+            transactions.extend(self.take_opportunity_txs(&mut mev_tx_outputs[opportunity], slot));
+        }
+        transactions
+    }
+
+    /// The accounts that `mev_tx_outputs`, the outputs of one opportunity,
+    /// conflict with other opportunities over: the pools that they trade
+    /// through, and with `OpportunityConflict::Account`, every account that
+    /// their transactions write.
+    fn conflict_accounts(
+        mev_tx_outputs: &[MevTxOutput],
+        conflict: OpportunityConflict,
+    ) -> HashSet<Pubkey> {
+        let mut accounts = HashSet::new();
+        for mev_tx_output in mev_tx_outputs {
+            accounts.extend(mev_tx_output.selected_pools.iter().copied());
+            accounts.extend(
+                mev_tx_output
+                    .input_output_pairs
+                    .iter()
+                    .flat_map(|pair| &pair.pool_splits)
+                    .map(|split| split.pool),
+            );
+            if let (OpportunityConflict::Account, Some(blueprint)) =
+                (conflict, &mev_tx_output.blueprint)
+            {
+                accounts.extend(
+                    blueprint
+                        .instructions
+                        .iter()
+                        .flat_map(|instruction| &instruction.accounts)
+                        .filter(|account| account.is_writable)
+                        .map(|account| account.pubkey),
+                );
+            }
+        }
+        accounts
+    }
+
+    /// Take the transactions out of the outputs of one opportunity, if it has
+    /// them, and reserve their input amounts as in-flight exposure. If
+    /// reserving would exceed `max_inflight_exposure` for the input token, the
+    /// transactions are dropped. Only the transactions that we take are
+    /// signed, if signing one fails, we take none.
+    fn take_opportunity_txs(
+        &self,
+        mev_tx_outputs: &mut [MevTxOutput],
+        slot: Slot,
+    ) -> Vec<(SanitizedTransaction, u64)> {
+        if mev_tx_outputs
+            .iter()
+            .any(|mev_tx_output| mev_tx_output.blueprint.is_none())
+        {
+            return Vec::new();
+        }
+        if !self.systemic_failures.lock().unwrap().may_craft(slot) {
+            for mev_tx_output in mev_tx_outputs.iter_mut() {
+                mev_tx_output.skip_reason = Some(SkipReason::SystemicFailure);
+            }
+            return Vec::new();
+        }
+        let tx_count = mev_tx_outputs.len() as u64;
+        if !self
+            .slot_tx_count
+            .lock()
+            .unwrap()
+            .try_add(slot, tx_count, self.max_mev_txs_per_slot)
+        {
+            for mev_tx_output in mev_tx_outputs.iter_mut() {
+                mev_tx_output.blueprint = None;
+                mev_tx_output.skip_reason = Some(SkipReason::SlotCap);
+            }
+            return Vec::new();
+        }
+
+        let mut signed_txs = Vec::with_capacity(mev_tx_outputs.len());
+        for mev_tx_output in mev_tx_outputs.iter_mut() {
+            let blueprint = mev_tx_output
+                .blueprint
+                .take()
+                .expect("Checked that all outputs have a transaction");
+            match blueprint.sign(self.signing_thread.as_ref()) {
+                Ok(sanitized_tx) => signed_txs.push(sanitized_tx),
+                Err(err) => {
+                    mev_tx_output.skip_reason = Some(match err {
+                        SigningError::Timeout => SkipReason::SigningTimeout,
+                        err => {
+                            error_throttled!(
+                                "mev_signing_error",
+                                LOG_THROTTLE_INTERVAL,
+                                "[MEV] Could not sign transaction, error: {}",
+                                err
+                            );
+                            SkipReason::SigningError
+                        }
+                    });
+                    // A sequence is only profitable as a whole.
+                    self.slot_tx_count.lock().unwrap().remove(slot, tx_count);
+                    return Vec::new();
+                }
+            }
+        }
+
+        let mut transactions: Vec<(SanitizedTransaction, u64)> =
+            Vec::with_capacity(mev_tx_outputs.len());
+        for (sanitized_tx, mev_tx_output) in signed_txs.into_iter().zip(mev_tx_outputs.iter_mut()) {
+            let amount_in = mev_tx_output
+                .input_output_pairs
+                .first()
+                .map_or(0, |pair| pair.token_in);
+            let cap = self
+                .max_inflight_exposure
+                .get(&mev_tx_output.input_mint)
+                .copied();
+            let (is_reserved, total_after) = {
+                let mut inflight_exposure = self.inflight_exposure.lock().unwrap();
+                let is_reserved = inflight_exposure.try_reserve(
+                    *sanitized_tx.signature(),
+                    slot,
+                    mev_tx_output.input_mint,
+                    amount_in,
+                    cap,
+                );
+                let total_after = inflight_exposure
+                    .totals()
+                    .get(&mev_tx_output.input_mint)
+                    .copied()
+                    .unwrap_or(0);
+                (is_reserved, total_after)
+            };
+            if let Some(decision_trace) = &mut mev_tx_output.decision_trace {
+                decision_trace.caps.push(CapTrace {
+                    cap: CapKind::MaxInflightExposure,
+                    limit: cap,
+                    before: if is_reserved {
+                        total_after.saturating_sub(amount_in)
+                    } else {
+                        total_after
+                    },
+                    after: total_after,
+                    passed: is_reserved,
+                });
+            }
+            if !is_reserved {
+                // A sequence is only profitable as a whole.
+                for (transaction, _profit) in &transactions {
+                    self.release_inflight_exposure(transaction.signature());
+                }
+                self.slot_tx_count.lock().unwrap().remove(slot, tx_count);
+                mev_tx_output.skip_reason = Some(SkipReason::ExposureCap);
+                return Vec::new();
+            }
+            transactions.push((sanitized_tx, mev_tx_output.profit));
+        }
+        if self.dry_run {
+            // We never execute the transactions, so we log them instead, and
+            // release their exposure right away.
+            for ((transaction, _profit), mev_tx_output) in
+                transactions.iter().zip(mev_tx_outputs.iter_mut())
+            {
+                self.release_inflight_exposure(transaction.signature());
+                mev_tx_output.dry_run_transaction = Some(encode_swap_tx(transaction));
+            }
+            return Vec::new();
+        }
+        if self.profit_reporter.is_some() {
+            let mut pending_reported_profits = self.pending_reported_profits.lock().unwrap();
+            for ((transaction, _profit), mev_tx_output) in
+                transactions.iter().zip(mev_tx_outputs.iter())
+            {
+                if let Some(reported_profit) = mev_tx_output.reported_profit {
+                    pending_reported_profits.insert(
+                        *transaction.signature(),
+                        slot,
+                        reported_profit,
+                    );
+                }
+            }
+        }
+        transactions
+    }
+
+    /// What we report for the profit of the transaction with `signature`,
+    /// once it executed. `None` without a `reporting` config.
+    pub fn take_reported_profit(&self, signature: &Signature) -> Option<ReportedProfit> {
+        self.pending_reported_profits
+            .lock()
+            .unwrap()
+            .take(signature)
+    }
+
+    /// Group `mev_tx_outputs` into opportunities: the parts of a sequence
+    /// follow each other, every other output is an opportunity of its own.
+    fn get_opportunities(mev_tx_outputs: &[MevTxOutput]) -> Vec<Range<usize>> {
+        let mut opportunities: Vec<Range<usize>> = Vec::new();
+        for (idx, mev_tx_output) in mev_tx_outputs.iter().enumerate() {
+            match (mev_tx_output.sequence, opportunities.last_mut()) {
+                (Some(sequence), Some(opportunity)) if sequence.part > 0 => {
+                    opportunity.end = idx + 1;
+                }
+                _ => opportunities.push(idx..idx + 1),
+            }
+        }
+        opportunities
+    }
+
+    /// The hops that the outputs of one opportunity trade through, as a sorted
+    /// set of (pool, whether A to B). Paths that are rotations of the same
+    /// cycle have the same key.
+    fn opportunity_key(&self, mev_tx_outputs: &[MevTxOutput]) -> Vec<(Pubkey, bool)> {
+        let mut key: Vec<(Pubkey, bool)> = mev_tx_outputs
+            .iter()
+            .flat_map(|mev_tx_output| {
+                let mev_path = if mev_tx_output.reversed {
+                    self.reversed_paths[mev_tx_output.path_idx].as_ref()
+                } else {
+                    self.mev_paths.get(mev_tx_output.path_idx)
+                };
+                mev_tx_output
+                    .selected_pools
+                    .iter()
+                    .zip(mev_path.map_or(&[][..], |mev_path| &mev_path.path[..]))
+                    .map(|(pool, pair_info)| (*pool, pair_info.direction == TradeDirection::AtoB))
+            })
+            .collect();
+        key.sort_unstable();
+        key.dedup();
+        key
+    }
+
+    /// The profit of the outputs of one opportunity in the quote mint, when we
+    /// could value all of them, see `MevTxOutput::profit_in_quote`.
+    fn profit_in_quote(mev_tx_outputs: &[MevTxOutput]) -> Option<u64> {
+        mev_tx_outputs
+            .iter()
+            .map(MevTxOutput::profit_in_quote)
+            .sum()
+    }
+
+    /// Keep only the most profitable opportunity of those that trade through
+    /// the same pools in the same directions, such as the opportunities of two
+    /// paths that are rotations of the same cycle. Their profits are in the
+    /// mints that their paths start with, so we compare their value in the
+    /// reporting mint when we have it for all their outputs. On a tie, the
+    /// opportunity of the path that comes first wins. We count the dropped
+    /// opportunities in a metric rather than logging them.
+    fn dedup_opportunities(&self, mev_tx_outputs: Vec<MevTxOutput>) -> Vec<MevTxOutput> {
+        let opportunities = Mev::get_opportunities(&mev_tx_outputs);
+        let value = |opportunity: &Range<usize>| -> u64 {
+            let outputs = &mev_tx_outputs[opportunity.clone()];
+            Mev::profit_in_quote(outputs).unwrap_or_else(|| {
+                outputs
+                    .iter()
+                    .map(|mev_tx_output| mev_tx_output.profit)
+                    .sum()
+            })
+        };
+        let mut best: HashMap<Vec<(Pubkey, bool)>, (usize, u64)> = HashMap::new();
+        for (idx, opportunity) in opportunities.iter().enumerate() {
+            let key = self.opportunity_key(&mev_tx_outputs[opportunity.clone()]);
+            let value = value(opportunity);
+            let entry = best.entry(key).or_insert((idx, value));
+            if value > entry.1 {
+                *entry = (idx, value);
+            }
+        }
+        let duplicates = opportunities.len() - best.len();
+        if duplicates == 0 {
+            return mev_tx_outputs;
+        }
+        self.duplicate_opportunities
+            .fetch_add(duplicates as u64, Ordering::Relaxed);
+        let kept: HashSet<usize> = best.values().map(|(idx, _value)| *idx).collect();
+        let kept_outputs: HashSet<usize> = opportunities
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _opportunity)| kept.contains(idx))
+            .flat_map(|(_idx, opportunity)| opportunity)
+            .collect();
+        mev_tx_outputs
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _mev_tx_output)| kept_outputs.contains(idx))
+            .map(|(_idx, mev_tx_output)| mev_tx_output)
+            .collect()
+    }
+
+    /// Drop the decision traces of all outputs but the ones of the `selected`
+    /// opportunities, and record in their traces how they compared to the
+    /// others.
+    fn trace_selection(mev_tx_outputs: &mut [MevTxOutput], selected: &[Range<usize>]) {
+        let is_selected = |idx: &usize| selected.iter().any(|range| range.contains(idx));
+        let competing: Vec<CompetingOpportunity> = mev_tx_outputs
+            .iter()
+            .enumerate()
+            .filter(|(idx, _mev_tx_output)| !is_selected(idx))
+            .map(|(_idx, mev_tx_output)| CompetingOpportunity {
+                path_idx: mev_tx_output.path_idx,
+                profit: mev_tx_output.profit,
+            })
+            .collect();
+        for (idx, mev_tx_output) in mev_tx_outputs.iter_mut().enumerate() {
+            if !is_selected(&idx) {
+                mev_tx_output.decision_trace = None;
+            } else if let Some(decision_trace) = &mut mev_tx_output.decision_trace {
+                decision_trace.selection = Some(SelectionTrace {
+                    competing: competing.clone(),
+                });
+            }
+        }
+    }
+
+    /// Find the paths that the transaction with `transaction_signature` traded
+    /// through as a full cycle, at a profit, judging from the pool balances
+    /// before and after it.
+    pub fn detect_competitor_arbitrages(
+        &self,
+        transaction_signature: Signature,
+        slot: Slot,
+        pre_pool_states: &PoolStates,
+        post_pool_states: &PoolStates,
+    ) -> Vec<CompetitorArbitrage> {
+        let evaluated_paths = self.evaluated_paths.lock().unwrap();
+        self.mev_paths
+            .iter()
+            .enumerate()
+            .filter_map(|(path_idx, mev_path)| {
+                let (amount_in, amount_out) =
+                    mev_path.get_cycle_amounts(pre_pool_states, post_pool_states)?;
+                let estimated_profit = amount_out.checked_sub(amount_in).filter(|x| *x > 0)?;
+                let first_pair_info = mev_path.path.first()?;
+                let first_pool = &post_pool_states.0.get(&first_pair_info.pool)?.pool;
+                let input_mint = match first_pair_info.direction {
+                    TradeDirection::AtoB => first_pool.pool_a_mint,
+                    TradeDirection::BtoA => first_pool.pool_b_mint,
+                };
+                Some(CompetitorArbitrage {
+                    transaction_signature,
+                    slot,
+                    path_idx,
+                    input_mint,
+                    estimated_profit,
+                    evaluated_path: evaluated_paths.contains(slot, path_idx),
+                })
+            })
+            .collect()
+    }
+
+    /// With a `ProfitShare` priority fee, set the compute unit price of
+    /// `tx_settings` to the share of `profit`, and the compute unit limit to
+    /// our estimate for the path, unless the path or the config set one.
+    /// Returns the settings, or `None` when we pay no profit share: for other
+    /// policies, for paths that set their own price, and for profits not in
+    /// wSOL, which are not in lamports.
+    fn profit_share_fee(
+        &self,
+        mut tx_settings: TxSettings,
+        mev_path: &MevPath,
+        input_mint: &Pubkey,
+        profit: u64,
+    ) -> Option<TxSettings> {
+        let priority_fee = self
+            .priority_fee
+            .as_ref()
+            .filter(|priority_fee| priority_fee.is_profit_share())?;
+        if tx_settings.compute_unit_price.is_some()
+            || *input_mint != inline_spl_token::native_mint::id()
+        {
+            return None;
+        }
+        let compute_unit_limit = tx_settings.compute_unit_limit.unwrap_or_else(|| {
+            self.compute_unit_estimator
+                .estimate(mev_path.swap_count())
+                .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+        });
+        let compute_unit_price =
+            priority_fee.get_profit_share_compute_unit_price(profit, compute_unit_limit)?;
+        tx_settings.compute_unit_price = Some(compute_unit_price);
+        tx_settings.compute_unit_limit = Some(compute_unit_limit);
+        tx_settings.sources.compute_unit_price = SettingSource::Global;
+        Some(tx_settings)
+    }
+
+    /// Deduct the priority fee of a transaction from its `profit` when the
+    /// profit is in wSOL, like `deduct_account_creation_costs` does with the
+    /// rent. For other tokens, the fee adds to `non_token_costs_lamports`.
+    /// Returns (`profit`, `non_token_costs_lamports`).
+    fn deduct_priority_fee(
+        profit: u64,
+        non_token_costs_lamports: u64,
+        input_mint: &Pubkey,
+        priority_fee_lamports: u64,
+    ) -> (u64, u64) {
+        if *input_mint == inline_spl_token::native_mint::id() {
+            (
+                profit.saturating_sub(priority_fee_lamports),
+                non_token_costs_lamports,
+            )
+        } else {
+            (
+                profit,
+                non_token_costs_lamports.saturating_add(priority_fee_lamports),
+            )
+        }
+    }
+
+    /// Deduct the rent of the `created_accounts` token accounts that a
+    /// transaction creates from its `profit`. The rent is locked in the new
+    /// accounts, so it is not lost, but it is not recovered by the transaction
+    /// either. We can only deduct it when the profit is in wSOL, for other
+    /// tokens the cost is returned separately, in lamports.
+    /// Returns (`profit`, `non_token_costs_lamports`).
+    pub fn deduct_account_creation_costs(
+        profit: u64,
+        input_mint: &Pubkey,
+        created_accounts: u64,
+        rent: &Rent,
+    ) -> (u64, u64) {
+        let costs_lamports = rent
+            .minimum_balance(spl_token::state::Account::LEN)
+            .saturating_mul(created_accounts);
+        if *input_mint == inline_spl_token::native_mint::id() {
+            (profit.saturating_sub(costs_lamports), 0)
+        } else {
+            (profit, costs_lamports)
+        }
+    }
+
+    /// Quote trading `amount_in` through `mev_path`, where `hops` holds the
+    /// candidate pools of every hop, and build the swaps for it. Fails when a
+    /// hop cannot swap, or when an amount exceeds what a token account holds,
+    /// rather than truncating it.
+    fn quote_path(
+        &self,
+        mev_path: &MevPath,
+        hops: &[HopCandidates],
+        initial_amount: u128,
+    ) -> Result<PathQuote, PathSkipReason> {
+        let initial_token_amount = to_token_amount(mev_path, initial_amount, 0)?;
+        let hop_simulations = simulate_hops(mev_path, hops, initial_amount)?;
+        let mut amount_out = initial_amount;
+        let mut hop_traces = Vec::new();
+        let mut input_output_pairs = Vec::with_capacity(mev_path.path.len());
+
+        let mut swap_arguments_vec: Vec<SwapArguments> = Vec::with_capacity(mev_path.path.len());
+        let mut selected_pools = Vec::with_capacity(mev_path.path.len());
+        let mut swap_count = 0;
+        let mut last_hop_swaps = 0;
+        for (hop, (pair_info, hop_simulation)) in
+            mev_path.path.iter().zip(hop_simulations).enumerate()
+        {
+            let trades = hop_simulation.trades;
+            // The pool that takes the largest share of a split stands for the
+            // hop, the first one on a tie.
+            let selected_pool = trades
+                .iter()
+                .rev()
+                .max_by_key(|trade| trade.amount_in)
+                .map(|trade| *trade.pool)
+                .ok_or(PathSkipReason::SwapFailed)?;
+            selected_pools.push(selected_pool);
+
+            last_hop_swaps = 0;
+            for trade in &trades {
+                let pool_state = trade.state;
+                let trade_in = to_token_amount(mev_path, trade.amount_in, hop)?;
+                let trade_out = to_token_amount(mev_path, trade.amount_out, hop)?;
+                // Our math can quote an output that the pool cannot pay, e.g.
+                // on a stale state, and the swap would only fail on-chain.
+                let (_balance_from, balance_to) = pool_state.get_balances(&pair_info.direction);
+                if trade.amount_out * MAX_HOP_OUTPUT_BPS as u128
+                    >= balance_to as u128 * self.max_hop_output_bps as u128
+                {
+                    warn_throttled!(
+                        "mev_excessive_hop_output",
+                        LOG_THROTTLE_INTERVAL,
+                        "[MEV] Path {} would take {} of the {} tokens of pool {} in hop {}, skipping it",
+                        mev_path.name,
+                        trade.amount_out,
+                        balance_to,
+                        trade.pool,
+                        hop
+                    );
+                    return Err(PathSkipReason::ExcessiveHopOutput {
+                        hop,
+                        pool: *trade.pool,
+                    });
+                }
+                if self.audit_mode {
+                    let (balance_from, balance_to) = pool_state.get_balances(&pair_info.direction);
+                    hop_traces.push(HopTrace {
+                        pool: *trade.pool,
+                        direction: pair_info.direction.clone(),
+                        balance_from,
+                        balance_to,
+                        fees: pool_state.fees.clone(),
+                        fee_fraction: pool_state.fees.total_fee_fraction(),
+                        amount_in: trade_in,
+                        amount_out: trade_out,
+                    });
+                }
+
+                let (
+                    source_pubkey,
+                    swap_source_pubkey,
+                    destination_pubkey,
+                    swap_destination_pubkey,
+                ) = match pair_info.direction {
+                    TradeDirection::AtoB => (
+                        pool_state.pool.source,
+                        pool_state.pool.pool_a_account,
+                        pool_state.pool.destination,
+                        pool_state.pool.pool_b_account,
+                    ),
+                    TradeDirection::BtoA => (
+                        pool_state.pool.destination,
+                        pool_state.pool.pool_b_account,
+                        pool_state.pool.source,
+                        pool_state.pool.pool_a_account,
+                    ),
+                };
+
+                swap_count += 1;
+                if let (Some(source), Some(destination)) = (source_pubkey, destination_pubkey) {
+                    // Every swap should produce at least what we quoted for
+                    // it, because that is what the next hop puts in. Without
+                    // this, we might make a profit in one token, but at the
+                    // cost of a loss in another token.
+                    swap_arguments_vec.push(SwapArguments {
+                        program_id: pool_state.pool.program_id,
+                        swap_pubkey: *trade.pool,
+                        authority_pubkey: pool_state.pool.pool_authority,
+                        source_pubkey: source,
+                        swap_source_pubkey,
+                        swap_destination_pubkey,
+                        destination_pubkey: destination,
+                        pool_mint_pubkey: pool_state.pool.pool_mint,
+                        pool_fee_pubkey: pool_state.pool.pool_fee,
+                        host_fee_pubkey: pool_state.pool.host_fee,
+                        token_program: pool_state.pool.token_program,
+                        amount_in: trade_in,
+                        minimum_amount_out: trade_out,
+                    });
+                    last_hop_swaps += 1;
+                }
+            }
+
+            amount_out = u128::from(hop_simulation.input_output_pair.token_out);
+            input_output_pairs.push(hop_simulation.input_output_pair);
+        }
+
+        let mut quote = PathQuote {
+            amount_in: initial_amount,
+            amount_out,
+            input_output_pairs,
+            selected_pools,
+            swap_arguments: swap_arguments_vec,
+            swap_count,
+            last_hop_swaps,
+            hop_traces,
+        };
+        // For the final swaps, set min_out such that the combination of all
+        // swaps does not make a loss, i.e. we get at least as much out as we
+        // started with.
+        quote.set_minimum_amount_out(initial_token_amount);
+        Ok(quote)
+    }
+
+    /// Quote trading `part_amount` through `mev_path` `parts` times in a row,
+    /// where every part trades against the pool states that the parts before
+    /// it leave behind.
+    fn quote_split_path(
+        &self,
+        mev_path: &MevPath,
+        pool_states: &PoolStates,
+        part_amount: u128,
+        parts: u8,
+    ) -> Option<Vec<PathQuote>> {
+        let mut virtual_states: PoolStates = mev_path
+            .path
+            .iter()
+            .flat_map(|pair_info| pair_info.candidate_pools())
+            .filter_map(|pool| Some((*pool, pool_states.0.get(pool)?.clone())))
+            .collect();
+        let mut quotes = Vec::with_capacity(parts as usize);
+        for _ in 0..parts {
+            let hops = mev_path.resolve_hops(&virtual_states).ok()?;
+            let quote = self.quote_path(mev_path, &hops, part_amount).ok()?;
+            for ((pair_info, pool), pair) in mev_path
+                .path
+                .iter()
+                .zip(&quote.selected_pools)
+                .zip(&quote.input_output_pairs)
+            {
+                if pair.pool_splits.is_empty() {
+                    virtual_states
+                        .0
+                        .get_mut(pool)?
+                        .apply_swap(&pair_info.direction, pair.token_in)?;
+                }
+                for split in &pair.pool_splits {
+                    virtual_states
+                        .0
+                        .get_mut(&split.pool)?
+                        .apply_swap(&pair_info.direction, split.token_in)?;
+                }
+            }
+            quotes.push(quote);
+        }
+        Some(quotes)
+    }
+
+    pub fn get_arbitrage_tx_outputs(
+        &self,
+        pool_states: &PoolStates,
+        blockhash: Hash,
+        rent: &Rent,
+        trigger_compute_unit_price: Option<u64>,
+    ) -> Vec<MevTxOutput> {
+        self.get_arbitrage_tx_outputs_in_slot(
+            pool_states,
+            blockhash,
+            rent,
+            trigger_compute_unit_price,
+            None,
+            None,
+        )
+    }
+
+    /// Like `get_arbitrage_tx_outputs`, for a trigger in `slot`. With a slot,
+    /// opportunities on paths with `require_confirmations` count as
+    /// observations of their imbalance, and we only craft transactions for
+    /// them once the imbalance is confirmed. Without one, we do not gate.
+    /// With the pool states before the trigger, we only craft transactions
+    /// for paths that the trigger moved by `min_trigger_delta`.
+    fn get_arbitrage_tx_outputs_in_slot(
+        &self,
+        pool_states: &PoolStates,
+        blockhash: Hash,
+        rent: &Rent,
+        trigger_compute_unit_price: Option<u64>,
+        slot: Option<Slot>,
+        pre_pool_states: Option<&PoolStates>,
+    ) -> Vec<MevTxOutput> {
+        // Blocked pools are as good as missing, so that hops fall back to
+        // their alternative pools, and other paths find nothing.
+        let unblocked_pool_states: PoolStates;
+        let pool_states = if self.blocked_pools.is_empty() {
+            pool_states
+        } else {
+            unblocked_pool_states = pool_states
+                .0
+                .iter()
+                .filter(|(pool, _pool_state)| !self.blocked_pools.contains(pool))
+                .map(|(pool, pool_state)| (*pool, pool_state.clone()))
+                .collect();
+            &unblocked_pool_states
+        };
+        let compute_unit_price = self.priority_fee.as_ref().and_then(|priority_fee| {
+            priority_fee.get_compute_unit_price(trigger_compute_unit_price)
+        });
+        // After a trigger, we only evaluate the paths through the pools that it
+        // changed, the others have no new opportunity.
+        let touched_paths =
+            pre_pool_states.map(|pre_pool_states| self.touched_paths(pre_pool_states, pool_states));
+        let mut skipped_paths = Vec::new();
+        let mev_tx_outputs = self
+            .mev_paths
+            .iter()
+            .enumerate()
+            .filter(|(path_idx, _mev_path)| {
+                touched_paths
+                    .as_ref()
+                    .map_or(true, |touched_paths| touched_paths.contains(path_idx))
+            })
+            // A path with `probe_reverse` is followed by its reversed path,
+            // under the same index.
+            .flat_map(|(path_idx, mev_path)| {
+                let reversed_path = self.reversed_paths[path_idx]
+                    .as_ref()
+                    .map(|reversed_path| (path_idx, reversed_path, true));
+                std::iter::once((path_idx, mev_path, false)).chain(reversed_path)
+            })
+            .filter_map(|(path_idx, mev_path, reversed)| {
+                if !mev_path.enabled || mev_path.max_input == Some(0) {
+                    return None;
+                }
+                let mut skip_path = |reason| {
+                    if self.log_skipped_paths {
+                        skipped_paths.push(PathSkipped {
+                            path_idx,
+                            reversed,
+                            reason,
+                        });
+                    }
+                };
+                let hops = match mev_path.resolve_hops(pool_states) {
+                    Ok(hops) => hops,
+                    Err(reason) => {
+                        skip_path(reason);
+                        return None;
+                    }
+                };
+
+                let first_pair_info = mev_path.path.first()?;
+                let (_pubkey, first_pool_state) = hops.first()?.first()?;
+
+                // Check the balance of which token initiates the path.
+                let initial_source_amount_opt = match first_pair_info.direction {
+                    TradeDirection::AtoB => first_pool_state.source_balance,
+                    TradeDirection::BtoA => first_pool_state.destination_balance,
+                };
+                // Cap the input to our balance, and then to the path's
+                // `max_input`. Returns the capped input, and the cap that
+                // determined it, if any.
+                let input_caps = [
+                    (initial_source_amount_opt, CapKind::SourceBalance),
+                    (mev_path.max_input, CapKind::MaxInput),
+                ];
+                let cap_input = |amount: u128| {
+                    input_caps.into_iter().fold(
+                        (amount, None),
+                        |(amount, cap), (limit, cap_kind)| match limit {
+                            Some(limit) if (limit as u128) < amount => {
+                                (limit as u128, Some(cap_kind))
+                            }
+                            _ => (amount, cap),
+                        },
+                    )
+                };
+
+                let mint_pubkey = match first_pair_info.direction {
+                    TradeDirection::AtoB => first_pool_state.pool.pool_a_mint,
+                    TradeDirection::BtoA => first_pool_state.pool.pool_b_mint,
+                };
+
+                // We do not bundle the creation of token accounts into MEV
+                // transactions yet, all token accounts have to exist up front.
+                let created_accounts = 0;
+                let get_profit = |quote: &PathQuote| {
+                    Mev::deduct_account_creation_costs(
+                        quote.gross_profit(),
+                        &mint_pubkey,
+                        created_accounts,
+                        rent,
+                    )
+                };
+
+                let compute_quotes = || -> Result<PathQuotes, PathSkipReason> {
+                    let mut path_output =
+                        mev_path.get_path_calculation_output(&hops, self.min_pool_reserve)?;
+                    let optimal_amount = if self.exact_optimal_input {
+                        let optimal_amount =
+                            mev_path.refine_optimal_input(&hops, path_output.optimal_input);
+                        path_output.optimal_input = optimal_amount as f64;
+                        optimal_amount
+                    } else {
+                        optimal_input_amount(path_output.optimal_input)
+                    };
+
+                    let (single_amount, single_input_cap) = cap_input(optimal_amount);
+                    let single_quote = self.quote_path(mev_path, &hops, single_amount)?;
+
+                    // A part only needs its own input in the source account,
+                    // the parts before it return at least their input to it.
+                    // So when the source balance caps the input, a split can
+                    // trade more. The same goes for `max_input`, which caps
+                    // every part.
+                    let split = mev_path
+                        .split_parts
+                        .filter(|parts| *parts > 1)
+                        .and_then(|parts| {
+                            let uncapped_part_amount = optimal_amount / parts as u128;
+                            let (part_amount, part_input_cap) = cap_input(uncapped_part_amount);
+                            let quotes =
+                                self.quote_split_path(mev_path, pool_states, part_amount, parts)?;
+                            Some((uncapped_part_amount, part_input_cap, quotes))
+                        })
+                        .filter(|(_uncapped_part_amount, _part_input_cap, quotes)| {
+                            let split_profit: u64 =
+                                quotes.iter().map(|quote| get_profit(quote).0).sum();
+                            quotes.iter().all(|quote| quote.amount_out >= quote.amount_in)
+                                && split_profit > get_profit(&single_quote).0
+                        });
+                    let (uncapped_amount, input_cap, quotes) = match split {
+                        Some(split) => split,
+                        None => (optimal_amount, single_input_cap, vec![single_quote]),
+                    };
+                    Ok(PathQuotes {
+                        path_output,
+                        uncapped_amount,
+                        input_cap,
+                        quotes,
+                    })
+                };
+                // A trigger often leaves the pools of a path as they were, then
+                // we reuse its quotes, or why it had none.
+                let key = PathCache::balances_key(mev_path, pool_states);
+                let cached = self.path_cache.lock().unwrap().get(path_idx, reversed, key);
+                let path_quotes = match cached {
+                    Some(path_quotes) => path_quotes,
+                    None => {
+                        let path_quotes = compute_quotes();
+                        self.path_cache.lock().unwrap().insert(
+                            path_idx,
+                            reversed,
+                            key,
+                            path_quotes.clone(),
+                        );
+                        path_quotes
+                    }
+                };
+                let PathQuotes {
+                    path_output,
+                    uncapped_amount,
+                    input_cap,
+                    quotes,
+                } = match path_quotes {
+                    Ok(path_quotes) => path_quotes,
+                    Err(reason) => {
+                        skip_path(reason);
+                        return None;
+                    }
+                };
+                let tx_settings = TxSettings::resolve(
+                    mev_path,
+                    self.slippage_bps,
+                    compute_unit_price,
+                    self.compute_unit_limit,
+                );
+                // The profit of every part, after the priority fee. A profit
+                // share sets the price of the part from its profit.
+                let quotes: Vec<_> = quotes
+                    .into_iter()
+                    .map(|quote| {
+                        let (profit, non_token_costs_lamports) = get_profit(&quote);
+                        let tx_settings = self
+                            .profit_share_fee(tx_settings, mev_path, &mint_pubkey, profit)
+                            .unwrap_or(tx_settings);
+                        let (profit, non_token_costs_lamports) = Mev::deduct_priority_fee(
+                            profit,
+                            non_token_costs_lamports,
+                            &mint_pubkey,
+                            tx_settings.priority_fee_lamports(quote.swap_count),
+                        );
+                        (quote, tx_settings, profit, non_token_costs_lamports)
+                    })
+                    .collect();
+                let total_profit: u64 = quotes
+                    .iter()
+                    .map(|(_quote, _tx_settings, profit, _costs)| profit)
+                    .sum();
+
+                let minimum_profit = match (
+                    self.minimum_profit.get(&mint_pubkey),
+                    self.default_minimum_profit,
+                ) {
+                    (Some(min_profit), _) => *min_profit,
+                    (None, Some(default_min_profit)) => default_min_profit,
+                    (None, None) => {
+                        warn_throttled!(
+                            &format!("mev_minimum_profit_{}", mint_pubkey),
+                            LOG_THROTTLE_INTERVAL,
+                            "[MEV] Token {} does not have a minimum profit set from config file.",
+                            mint_pubkey
+                        );
+                        0u64
+                    }
+                };
+
+                if total_profit < minimum_profit {
+                    return None;
+                }
+
+                let require_confirmations = mev_path.require_confirmations.unwrap_or(0);
+                let observations = slot.filter(|_| require_confirmations > 0).map(|slot| {
+                    self.imbalance_observations.lock().unwrap().observe(
+                        slot,
+                        path_idx,
+                        path_output.marginal_price,
+                    )
+                });
+                let is_confirmed =
+                    observations.map_or(true, |observations| observations > require_confirmations);
+                // A trigger that arbitraged its own swap leaves the pools
+                // balanced, but rounding may still show a dust opportunity.
+                let is_material = match (&self.min_trigger_delta, pre_pool_states) {
+                    (Some(min_trigger_delta), Some(pre_pool_states)) => {
+                        min_trigger_delta.path_moved(&hops, pre_pool_states)
+                    }
+                    _ => true,
+                };
+
+                let mut cap_traces = Vec::new();
+                if self.audit_mode {
+                    let mut amount = u64::try_from(uncapped_amount).unwrap_or(u64::MAX);
+                    for (limit, cap) in input_caps {
+                        if let Some(limit) = limit {
+                            cap_traces.push(CapTrace {
+                                cap,
+                                limit: Some(limit),
+                                before: amount,
+                                after: amount.min(limit),
+                                passed: true,
+                            });
+                            amount = amount.min(limit);
+                        }
+                    }
+                }
+
+                let parts = quotes.len() as u8;
+                quotes
+                    .into_iter()
+                    .enumerate()
+                    .map(|(part, (mut quote, tx_settings, profit, non_token_costs_lamports))| {
+                        if quote.amount_out < quote.amount_in {
+                            // If the output is less than the input, return `None`.
+                            warn_throttled!(
+                                "mev_output_less_than_input",
+                                LOG_THROTTLE_INTERVAL,
+                                "[MEV] The output amount is less than the initial amount, this shouldn't happen"
+                            );
+                            return None;
+                        }
+                        let gross_profit = quote.gross_profit();
+                        // Every part of a split has to make its share of
+                        // the minimum profit.
+                        quote.set_minimum_amount_out(tx_settings.minimum_amount_out(
+                            quote.amount_in,
+                            quote.amount_out,
+                            minimum_profit / parts as u64,
+                        ));
+
+                        // Construct the transaction only if we have swaps for the entire path.
+                        // We sign it once we selected it.
+                        let (blueprint, skip_reason) =
+                            if quote.has_all_swaps() {
+                                let user_authority = self.path_authority(path_idx)?;
+                                if !is_material {
+                                    (None, Some(SkipReason::NoMaterialMove))
+                                } else if !is_confirmed {
+                                    (None, Some(SkipReason::Unconfirmed))
+                                } else if !self.can_pay_fee(
+                                    &user_authority.pubkey(),
+                                    tx_settings.fee_lamports(quote.swap_count),
+                                ) {
+                                    (None, Some(SkipReason::InsufficientFeeBalance))
+                                } else {
+                                    let blueprint = MevTxBlueprint::new(
+                                        &quote.swap_arguments,
+                                        blockhash,
+                                        user_authority.clone(),
+                                        tx_settings.compute_unit_price,
+                                        tx_settings.compute_unit_limit,
+                                    );
+                                    (Some(blueprint), None)
+                                }
+                            } else {
+                                (None, None)
+                            };
+
+                        let decision_trace = self.audit_mode.then(|| DecisionTrace {
+                            optimal_input: path_output.optimal_input,
+                            hops: quote.hop_traces,
+                            caps: cap_traces.clone(),
+                            thresholds: vec![
+                                ThresholdTrace {
+                                    threshold: ThresholdKind::MinimumProfit,
+                                    limit: minimum_profit,
+                                    value: total_profit,
+                                    passed: true,
+                                },
+                                ThresholdTrace {
+                                    threshold: ThresholdKind::OutputCoversInput,
+                                    limit: quote.amount_in as u64,
+                                    value: quote.amount_out as u64,
+                                    passed: true,
+                                },
+                            ],
+                            deducted_costs: gross_profit - profit,
+                            amount_in: quote.amount_in as u64,
+                            profit,
+                            selection: None,
+                        });
+
+                        Some(MevTxOutput {
+                            blueprint,
+                            path_idx,
+                            reversed,
+                            input_output_pairs: quote.input_output_pairs,
+                            selected_pools: quote.selected_pools,
+                            profit,
+                            non_token_costs_lamports,
+                            trigger_compute_unit_price,
+                            compute_unit_price: tx_settings.compute_unit_price,
+                            estimated_compute_units: self
+                                .compute_unit_estimator
+                                .estimate(quote.swap_count),
+                            slippage_bps: tx_settings.slippage_bps,
+                            compute_unit_limit: tx_settings.compute_unit_limit,
+                            setting_sources: tx_settings.sources,
+                            dry_run_transaction: None,
+                            reported_profit: self.profit_reporter.as_ref().map(|reporter| {
+                                reporter.report(profit, mint_pubkey, pool_states)
+                            }),
+                            marginal_price: path_output.marginal_price,
+                            optimal_input: path_output.optimal_input,
+                            input_mint: mint_pubkey,
+                            skip_reason,
+                            observations,
+                            input_cap,
+                            capped: input_cap == Some(CapKind::SourceBalance),
+                            // The caller knows the trigger and overrides this.
+                            trigger_kind: TriggerKind::User,
+                            sequence: (parts > 1).then(|| SequencePart {
+                                part: part as u8,
+                                parts,
+                            }),
+                            decision_trace,
+                        })
+                    })
+                    .collect::<Option<Vec<MevTxOutput>>>()
+            })
+            .flatten()
+            .collect();
+        self.report_skipped_paths(skipped_paths);
+        mev_tx_outputs
+    }
+
+    /// Log why we found no opportunity on the paths in `skipped_paths`, with
+    /// `log_skipped_paths`.
+    fn report_skipped_paths(&self, skipped_paths: Vec<PathSkipped>) {
+        if skipped_paths.is_empty() {
+            return;
+        }
+        if let Err(err) = self
+            .log_send_channel
+            .send(MevMsg::PathsSkipped(skipped_paths))
+        {
+            error_throttled!(
+                "mev_log_paths_skipped",
+                LOG_THROTTLE_INTERVAL,
+                "[MEV] Could not log skipped paths, error: {}",
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::{unbounded, Receiver};
+    use solana_sdk::{
+        borsh::try_from_slice_unchecked,
+        compute_budget::ComputeBudgetInstruction,
+        instruction::{AccountMeta, Instruction},
+        signature::{write_keypair_file, Keypair},
+        transaction::{MessageHash, SimpleAddressLoader, Transaction, VersionedTransaction},
+    };
+    use solana_vote_program::vote_transaction;
+    use spl_token_swap::{
+        curve::{
+            base::{CurveType, SwapCurve},
+            constant_product::ConstantProductCurve,
+            offset::OffsetCurve,
+        },
+        instruction::{Swap, SwapInstruction},
+    };
+
+    use super::*;
+    use crate::{
+        accounts::MevAccounts,
+        bank::RentDebits,
+        inline_spl_token_2022,
+        mev::{
+            arbitrage::{
+                create_swap_tx, PairInfo, PathSkipReason, SettingSource, TxSettingSources,
+                LAMPORTS_PER_SIGNATURE,
+            },
+            authority::UserAuthorityConfig,
+            compute_units::DEFAULT_COMPUTE_UNITS_PER_HOP,
+            config::{parse_mev_config, TokenAmount, TokenRef},
+            failures::SystemicFailureConfig,
+            pool_state::{associated_token_address, Fees, OrcaPoolAddresses},
+            priority_fee::PriorityFeePolicy,
+            reporting::ReportingConfig,
+            test_utils::{
+                orca_accounts, path, pool, pubkey, steth_stsol_usdc_path,
+                steth_stsol_usdc_pool_states, PoolBuilder, ORCA_PROGRAM_ID,
+            },
+            utils::B58Pubkey,
+            OrcaPoolWithBalance,
+        },
+    };
+
+    /// A swap of 100 through a pool that does not exist.
+    fn swap_arguments() -> SwapArguments {
+        SwapArguments {
+            program_id: Pubkey::new_unique(),
+            swap_pubkey: Pubkey::new_unique(),
+            authority_pubkey: Pubkey::new_unique(),
+            source_pubkey: Pubkey::new_unique(),
+            swap_source_pubkey: Pubkey::new_unique(),
+            swap_destination_pubkey: Pubkey::new_unique(),
+            destination_pubkey: Pubkey::new_unique(),
+            pool_mint_pubkey: Pubkey::new_unique(),
+            pool_fee_pubkey: Pubkey::new_unique(),
+            host_fee_pubkey: None,
+            token_program: inline_spl_token::id(),
+            amount_in: 100,
+            minimum_amount_out: 0,
+        }
+    }
+
+    /// A crafted transaction that puts 100 of `mint` into a single swap, for
+    /// a profit of 10.
+    fn swap_tx_output(user_authority: &Arc<dyn MevSigner>, mint: Pubkey) -> MevTxOutput {
+        MevTxOutput {
+            blueprint: Some(MevTxBlueprint::new(
+                &[swap_arguments()],
+                Hash::new_unique(),
+                user_authority.clone(),
+                None,
+                None,
+            )),
+            path_idx: 0,
+            reversed: false,
+            input_output_pairs: vec![InputOutputPairs {
+                token_in: 100,
+                token_out: 110,
+                pool_splits: vec![],
+            }],
+            selected_pools: vec![],
+            profit: 10,
+            non_token_costs_lamports: 0,
+            trigger_compute_unit_price: None,
+            compute_unit_price: None,
+            estimated_compute_units: 40_000,
+            slippage_bps: None,
+            compute_unit_limit: None,
+            setting_sources: TxSettingSources::default(),
+            dry_run_transaction: None,
+            reported_profit: None,
+            marginal_price: 1.1,
+            optimal_input: 100.0,
+            input_mint: mint,
+            skip_reason: None,
+            observations: None,
+            input_cap: None,
+            capped: false,
+            trigger_kind: TriggerKind::User,
+            sequence: None,
+            decision_trace: None,
+        }
+    }
+
+    #[test]
+    fn test_inflight_exposure_cap() {
+        let mint = Pubkey::new_unique();
+        let mev_config = MevConfig {
+            max_inflight_exposure: vec![(B58Pubkey(mint), TokenAmount::Raw(150))]
+                .into_iter()
+                .collect(),
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let craft = || vec![swap_tx_output(&user_authority, mint)];
+
+        // Both transactions are in flight at the same time, only the first one
+        // fits under the cap.
+        let mut first_outputs = craft();
+        let (first_tx, _profit) = mev.take_max_profit_tx(&mut first_outputs, 1).remove(0);
+        let mut second_outputs = craft();
+        assert!(mev.take_max_profit_tx(&mut second_outputs, 1).is_empty());
+        assert_eq!(second_outputs[0].skip_reason, Some(SkipReason::ExposureCap));
+        assert_eq!(mev.get_inflight_exposure()[&mint], 100);
+
+        // Once the first one executed, there is room again.
+        mev.release_inflight_exposure(first_tx.signature());
+        assert!(mev.get_inflight_exposure().is_empty());
+        let mut third_outputs = craft();
+        assert_eq!(mev.take_max_profit_tx(&mut third_outputs, 1).len(), 1);
+
+        // Transactions from a previous slot never execute, so they expire.
+        let mut fourth_outputs = craft();
+        assert_eq!(mev.take_max_profit_tx(&mut fourth_outputs, 2).len(), 1);
+        assert_eq!(mev.get_inflight_exposure()[&mint], 100);
+    }
+
+    #[test]
+    fn test_take_top_profit_txs() {
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, MevConfig::default());
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let pools: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let output = |path_idx, selected_pools: &[Pubkey], profit| MevTxOutput {
+            path_idx,
+            selected_pools: selected_pools.to_vec(),
+            profit,
+            ..swap_tx_output(&user_authority, Pubkey::new_unique())
+        };
+        let take = |outputs: &mut Vec<MevTxOutput>, max_opportunities, conflict| {
+            mev.take_top_profit_txs(outputs, 1, max_opportunities, conflict)
+                .into_iter()
+                .map(|(_transaction, profit)| profit)
+                .collect::<Vec<u64>>()
+        };
+
+        // Two paths that share no pool, e.g. a USDC and a SOL cycle, both
+        // execute, the more profitable one first.
+        let disjoint = || vec![output(0, &pools[0..2], 10), output(1, &pools[2..4], 20)];
+        assert_eq!(
+            take(&mut disjoint(), 2, OpportunityConflict::Pool),
+            vec![20, 10]
+        );
+        assert_eq!(
+            take(&mut disjoint(), 1, OpportunityConflict::Pool),
+            vec![20]
+        );
+
+        // Of two paths through the same pool, only the better one executes.
+        let mut overlapping = vec![
+            output(0, &pools[0..2], 10),
+            output(1, &pools[1..3], 20),
+            output(2, &pools[3..4], 5),
+        ];
+        assert_eq!(
+            take(&mut overlapping, 3, OpportunityConflict::Pool),
+            vec![20, 5]
+        );
+        assert!(overlapping[0].blueprint.is_some());
+        assert!(overlapping[1].blueprint.is_none());
+
+        // Paths through other pools, that spend from the same token account,
+        // only conflict over accounts.
+        let shared_source = swap_arguments();
+        let spend_shared_source = |path_idx, pool: Pubkey, profit| MevTxOutput {
+            blueprint: Some(MevTxBlueprint::new(
+                &[SwapArguments {
+                    swap_pubkey: pool,
+                    ..shared_source.clone()
+                }],
+                Hash::new_unique(),
+                user_authority.clone(),
+                None,
+                None,
+            )),
+            ..output(path_idx, &[pool], profit)
+        };
+        let sharing_account = || {
+            vec![
+                spend_shared_source(0, pools[0], 10),
+                spend_shared_source(1, pools[1], 20),
+            ]
+        };
+        assert_eq!(
+            take(&mut sharing_account(), 2, OpportunityConflict::Pool),
+            vec![20, 10]
+        );
+        assert_eq!(
+            take(&mut sharing_account(), 2, OpportunityConflict::Account),
+            vec![20]
+        );
+    }
+
+    #[test]
+    fn test_rank_by_profit_in_quote() {
+        let (sol, usdc) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                reporting: Some(ReportingConfig {
+                    mint: B58Pubkey(usdc),
+                    routes: HashMap::new(),
+                    // 150 USDC per SOL, with 9 and 6 decimals.
+                    quote_prices: vec![(B58Pubkey(sol), 0.15)].into_iter().collect(),
+                }),
+                ..MevConfig::default()
+            },
+        );
+        let reporter = mev.profit_reporter.as_ref().unwrap();
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let output = |path_idx, mint, profit| MevTxOutput {
+            path_idx,
+            selected_pools: vec![Pubkey::new_unique()],
+            profit,
+            reported_profit: Some(reporter.report(profit, mint, &PoolStates(HashMap::new()))),
+            ..swap_tx_output(&user_authority, mint)
+        };
+        let take = |outputs: &mut Vec<MevTxOutput>| {
+            mev.take_top_profit_txs(outputs, 1, 1, OpportunityConflict::Pool)
+                .into_iter()
+                .map(|(_transaction, profit)| profit)
+                .collect::<Vec<u64>>()
+        };
+
+        // 1 SOL is worth more than 126 USDC.
+        let mut outputs = vec![output(0, usdc, 126_000_000), output(1, sol, 1_000_000_000)];
+        assert_eq!(outputs[1].profit_in_quote(), Some(150_000_000));
+        assert_eq!(take(&mut outputs), vec![1_000_000_000]);
+
+        // 0.5 SOL is not, even though more lamports than micro-USDC.
+        let mut outputs = vec![output(0, usdc, 126_000_000), output(1, sol, 500_000_000)];
+        assert_eq!(take(&mut outputs), vec![126_000_000]);
+
+        // Without a value for every opportunity, we compare the raw profits.
+        let mut outputs = vec![
+            output(0, usdc, 126_000_000),
+            MevTxOutput {
+                reported_profit: None,
+                ..output(1, sol, 500_000_000)
+            },
+        ];
+        assert_eq!(take(&mut outputs), vec![500_000_000]);
+    }
+
+    /// Signs with its keypair, or fails with `fail`, and counts the messages
+    /// that it was asked to sign.
+    #[derive(Debug)]
+    struct CountingSigner {
+        keypair: Keypair,
+        fail: bool,
+        signed: AtomicU64,
+    }
+
+    impl MevSigner for CountingSigner {
+        fn pubkey(&self) -> Pubkey {
+            self.keypair.pubkey()
+        }
+
+        fn sign_message(&self, message: &[u8]) -> Result<Signature, SigningError> {
+            self.signed.fetch_add(1, Ordering::Relaxed);
+            if self.fail {
+                return Err(SigningError::Remote("locked".to_owned()));
+            }
+            self.keypair.sign_message(message)
+        }
+    }
+
+    #[test]
+    fn test_only_the_selected_blueprint_is_signed() {
+        let mint = Pubkey::new_unique();
+        let keypair = Keypair::new();
+        let signer = Arc::new(CountingSigner {
+            keypair: Keypair::from_bytes(&keypair.to_bytes()).unwrap(),
+            fail: false,
+            signed: AtomicU64::new(0),
+        });
+        let user_authority: Arc<dyn MevSigner> = signer.clone();
+        let (swap_args, blockhash) = (swap_arguments(), Hash::new_unique());
+        let expected = create_swap_tx(vec![swap_args.clone()], blockhash, &keypair, None, None)
+            .unwrap()
+            .to_versioned_transaction();
+
+        for signing_thread_wait_ms in [None, Some(1_000)] {
+            let mev_config = MevConfig {
+                signing_thread_wait_ms,
+                ..MevConfig::default()
+            };
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mev = Mev::new(log_send_channel, mev_config);
+            signer.signed.store(0, Ordering::Relaxed);
+
+            let mut outputs = vec![
+                swap_tx_output(&user_authority, mint),
+                MevTxOutput {
+                    blueprint: Some(MevTxBlueprint::new(
+                        &[swap_args.clone()],
+                        blockhash,
+                        user_authority.clone(),
+                        None,
+                        None,
+                    )),
+                    profit: 20,
+                    ..swap_tx_output(&user_authority, mint)
+                },
+            ];
+            let (transaction, profit) = mev.take_max_profit_tx(&mut outputs, 1).remove(0);
+            assert_eq!(profit, 20);
+            assert_eq!(signer.signed.load(Ordering::Relaxed), 1);
+            // The transaction is the one that we signed before blueprints.
+            assert_eq!(transaction.to_versioned_transaction(), expected);
+            mev.release_inflight_exposure(transaction.signature());
+        }
+
+        // When signing fails, we take nothing, and it does not count towards
+        // the slot cap.
+        let failing_signer: Arc<dyn MevSigner> = Arc::new(CountingSigner {
+            keypair: Keypair::new(),
+            fail: true,
+            signed: AtomicU64::new(0),
+        });
+        let mev_config = MevConfig {
+            max_mev_txs_per_slot: Some(1),
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let mut outputs = vec![swap_tx_output(&failing_signer, mint)];
+        assert!(mev.take_max_profit_tx(&mut outputs, 1).is_empty());
+        assert_eq!(outputs[0].skip_reason, Some(SkipReason::SigningError));
+        let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+        assert_eq!(mev.take_max_profit_tx(&mut outputs, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_reported_profit_follows_transaction() {
+        let mint = Pubkey::new_unique();
+        let mev_config = MevConfig {
+            reporting: Some(ReportingConfig {
+                mint: B58Pubkey(mint),
+                routes: HashMap::new(),
+                quote_prices: HashMap::new(),
+            }),
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let reported_profit = ReportedProfit {
+            profit_mint: B58Pubkey(mint),
+            reported_value: Some(10),
+        };
+
+        let mut outputs = vec![MevTxOutput {
+            reported_profit: Some(reported_profit),
+            ..swap_tx_output(&user_authority, mint)
+        }];
+        let (transaction, _profit) = mev.take_max_profit_tx(&mut outputs, 1).remove(0);
+        assert_eq!(
+            mev.take_reported_profit(transaction.signature()),
+            Some(reported_profit)
+        );
+        assert_eq!(mev.take_reported_profit(transaction.signature()), None);
+    }
+
+    #[test]
+    fn test_dry_run_logs_instead_of_executing() {
+        let mint = Pubkey::new_unique();
+        let mev_config = MevConfig {
+            dry_run: true,
+            max_inflight_exposure: vec![(B58Pubkey(mint), TokenAmount::Raw(150))]
+                .into_iter()
+                .collect(),
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+
+        for _ in 0..2 {
+            let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+            let expected_tx = outputs[0].blueprint.as_ref().unwrap().sign(None).unwrap();
+            assert!(mev.take_max_profit_tx(&mut outputs, 1).is_empty());
+            assert_eq!(outputs[0].skip_reason, None);
+
+            // What we log is the transaction that we would have executed.
+            let encoded = outputs[0].dry_run_transaction.as_ref().unwrap();
+            let logged_tx: VersionedTransaction =
+                bincode::deserialize(&base64::decode(encoded).unwrap()).unwrap();
+            assert_eq!(logged_tx, expected_tx.to_versioned_transaction());
+
+            // Nothing stays in flight, so the second one fits under the cap too.
+            assert!(mev.get_inflight_exposure().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_systemic_failures_pause_crafting() {
+        let mint = Pubkey::new_unique();
+        let systemic_failure = Some(SystemicFailureConfig {
+            threshold: 2,
+            window_slots: 10,
+            recovery_executions: 1,
+        });
+        let mev_config = MevConfig {
+            systemic_failure,
+            ..MevConfig::default()
+        };
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let craft = |slot| {
+            let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+            let crafted = mev.take_max_profit_tx(&mut outputs, slot);
+            for (transaction, _profit) in &crafted {
+                mev.release_inflight_exposure(transaction.signature());
+            }
+            (crafted.len(), outputs[0].skip_reason)
+        };
+
+        // Failures of the path do not stop us.
+        let path_failure = ExecutionFailure::InstructionError {
+            index: 0,
+            error: "custom program error: 0x10".to_owned(),
+        };
+        mev.record_execution(1, Some(&path_failure));
+        mev.record_execution(1, Some(&path_failure));
+        assert_eq!(craft(1), (1, None));
+        assert_eq!(craft(1), (1, None));
+        assert!(log_receiver.try_recv().is_err());
+
+        // Systemic ones do, except for one transaction per slot.
+        mev.record_execution(2, Some(&ExecutionFailure::BlockhashNotFound));
+        mev.record_execution(2, Some(&ExecutionFailure::BlockhashNotFound));
+        assert!(matches!(
+            log_receiver.try_recv(),
+            Ok(MevMsg::SystemicFailure(SystemicFailureEvent::Paused { .. }))
+        ));
+        assert_eq!(craft(2), (0, Some(SkipReason::SystemicFailure)));
+        assert_eq!(craft(3), (1, None));
+        assert_eq!(craft(3), (0, Some(SkipReason::SystemicFailure)));
+
+        // A reload does not lift the pause, that probe executing does.
+        let mev = mev.reload(MevConfig {
+            systemic_failure,
+            ..MevConfig::default()
+        });
+        assert!(mev.systemic_failures.lock().unwrap().is_paused());
+        assert!(matches!(log_receiver.try_recv(), Ok(MevMsg::Paths(_))));
+        mev.record_execution(3, Some(&path_failure));
+        assert!(matches!(
+            log_receiver.try_recv(),
+            Ok(MevMsg::SystemicFailure(SystemicFailureEvent::Resumed {
+                manual: false,
+                ..
+            }))
+        ));
+        assert!(!mev.systemic_failures.lock().unwrap().is_paused());
+    }
+
+    #[test]
+    fn test_max_mev_txs_per_slot() {
+        let mint = Pubkey::new_unique();
+        let mev_config = MevConfig {
+            max_mev_txs_per_slot: Some(1),
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let user_authority: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let trigger = |slot| {
+            let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+            let crafted = mev.take_max_profit_tx(&mut outputs, slot);
+            for (transaction, _profit) in &crafted {
+                mev.release_inflight_exposure(transaction.signature());
+            }
+            assert!(outputs[0].blueprint.is_none());
+            (crafted.len(), outputs[0].skip_reason)
+        };
+
+        // Three triggers in one slot, only the first gets a transaction.
+        assert_eq!(trigger(1), (1, None));
+        assert_eq!(trigger(1), (0, Some(SkipReason::SlotCap)));
+        assert_eq!(trigger(1), (0, Some(SkipReason::SlotCap)));
+
+        // The next slot starts over, also after a reload.
+        let mev = mev.reload(MevConfig {
+            max_mev_txs_per_slot: Some(1),
+            ..MevConfig::default()
+        });
+        let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+        assert_eq!(mev.take_max_profit_tx(&mut outputs, 2).len(), 1);
+        let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+        assert!(mev.take_max_profit_tx(&mut outputs, 2).is_empty());
+        assert_eq!(outputs[0].skip_reason, Some(SkipReason::SlotCap));
+
+        // Transactions that we will not execute after all give their share
+        // back, whether we discard them or their trigger was not committed.
+        let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+        let crafted = mev.take_max_profit_tx(&mut outputs, 3);
+        mev.release_txs(3, crafted.iter().map(|(transaction, _profit)| transaction));
+        let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+        let crafted = mev.take_max_profit_tx(&mut outputs, 3);
+        assert_eq!(crafted.len(), 1);
+        let trigger_signature = Signature::new_unique();
+        mev.hold_until_trigger_committed(trigger_signature, 3, crafted);
+        assert!(mev
+            .take_committed_trigger_tx(vec![(trigger_signature, false)])
+            .is_empty());
+        let mut outputs = vec![swap_tx_output(&user_authority, mint)];
+        assert_eq!(mev.take_max_profit_tx(&mut outputs, 3).len(), 1);
+    }
+
+    #[test]
+    fn test_confirm_trigger_before_craft() {
+        let mint = Pubkey::new_unique();
+        let mev_config = MevConfig {
+            confirm_trigger_before_craft: true,
+            ..MevConfig::default()
+        };
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+
+        let user_authority = Keypair::new();
+        // Craft a transaction and reserve its exposure, like
+        // `take_max_profit_tx` does.
+        let craft = |slot: Slot| {
+            let swap_args = SwapArguments {
+                program_id: Pubkey::new_unique(),
+                swap_pubkey: Pubkey::new_unique(),
+                authority_pubkey: Pubkey::new_unique(),
+                source_pubkey: Pubkey::new_unique(),
+                swap_source_pubkey: Pubkey::new_unique(),
+                swap_destination_pubkey: Pubkey::new_unique(),
+                destination_pubkey: Pubkey::new_unique(),
+                pool_mint_pubkey: Pubkey::new_unique(),
+                pool_fee_pubkey: Pubkey::new_unique(),
+                host_fee_pubkey: None,
+                token_program: inline_spl_token::id(),
+                amount_in: 100,
+                minimum_amount_out: 0,
+            };
+            let tx = create_swap_tx(
+                vec![swap_args],
+                Hash::new_unique(),
+                &user_authority,
+                None,
+                None,
+            )
+            .unwrap();
+            assert!(mev.inflight_exposure.lock().unwrap().try_reserve(
+                *tx.signature(),
+                slot,
+                mint,
+                100,
+                None
+            ));
+            tx
+        };
+        let dropped = || match log_receiver.try_recv() {
+            Ok(MevMsg::DroppedTransaction(dropped)) => Some(dropped),
+            _ => None,
+        };
+
+        // The trigger is committed, the most profitable transaction of the
+        // batch is released, the other one is not executed.
+        let (trigger_a, trigger_b, trigger_c) = (
+            Signature::new_unique(),
+            Signature::new_unique(),
+            Signature::new_unique(),
+        );
+        let tx_a = craft(1);
+        mev.hold_until_trigger_committed(trigger_a, 1, vec![(tx_a.clone(), 10)]);
+        mev.hold_until_trigger_committed(trigger_b, 1, vec![(craft(1), 5)]);
+        let (released_tx, profit) = mev
+            .take_committed_trigger_tx(vec![
+                (trigger_a, true),
+                (trigger_b, true),
+                (trigger_c, true),
+            ])
+            .remove(0);
+        assert_eq!(released_tx.signature(), tx_a.signature());
+        assert_eq!(profit, 10);
+        assert!(mev.pending_opportunities.lock().unwrap().is_empty());
+        assert_eq!(mev.get_inflight_exposure()[&mint], 100);
+        mev.release_inflight_exposure(tx_a.signature());
+        assert_eq!(dropped(), None);
+
+        // The trigger failed, the transaction is dropped.
+        let tx_b = craft(1);
+        mev.hold_until_trigger_committed(trigger_b, 1, vec![(tx_b.clone(), 10)]);
+        assert!(mev
+            .take_committed_trigger_tx(vec![(trigger_b, false)])
+            .is_empty());
+        assert_eq!(
+            dropped(),
+            Some(DroppedTransaction {
+                transaction_signature: *tx_b.signature(),
+                trigger_signature: trigger_b,
+                reason: SkipReason::TriggerNotCommitted,
+            })
+        );
+        assert!(mev.get_inflight_exposure().is_empty());
+
+        // The slot ended before we heard about the trigger.
+        let tx_c = craft(1);
+        mev.hold_until_trigger_committed(trigger_c, 1, vec![(tx_c.clone(), 10)]);
+        mev.tick(2, 0);
+        assert_eq!(
+            dropped().map(|dropped| dropped.transaction_signature),
+            Some(*tx_c.signature())
+        );
+        assert!(matches!(log_receiver.try_recv(), Ok(MevMsg::Tick(2, 0))));
+        assert!(mev.pending_opportunities.lock().unwrap().is_empty());
+        assert!(mev.get_inflight_exposure().is_empty());
+        assert!(mev
+            .take_committed_trigger_tx(vec![(trigger_c, true)])
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pool_config_mismatch() {
+        let (usdc, usdt, sol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (matching, mismatching, unconfigured) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let config_pool = |address, pool_a_mint, pool_b_mint| OrcaPoolAddresses {
+            address,
+            pool_a_mint,
+            pool_b_mint,
+            ..OrcaPoolAddresses::default()
+        };
+        let mev_config = MevConfig {
+            orca_accounts: AllOrcaPoolAddresses::new(vec![
+                config_pool(matching, usdc, usdt),
+                config_pool(mismatching, usdc, usdt),
+                config_pool(unconfigured, Pubkey::default(), Pubkey::default()),
+            ]),
+            ..MevConfig::default()
+        };
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+
+        let pool_states = || -> PoolStates {
+            [
+                (matching, usdc, usdt),
+                (mismatching, usdc, sol),
+                (unconfigured, sol, usdt),
+            ]
+            .into_iter()
+            .map(|(address, pool_a_mint, pool_b_mint)| {
+                let mut pool_state = OrcaPoolWithBalance::new(
+                    pool_a_mint,
+                    pool_b_mint,
+                    1_000,
+                    1_000,
+                    Fees::from_trade_fees(0, 0, 0, 0),
+                    SwapCurve::default(),
+                );
+                pool_state.pool.address = address;
+                (address, pool_state)
+            })
+            .collect()
+        };
+
+        // The mismatch is logged on first load only, but the pool stays
+        // disabled.
+        for expect_event in [true, false] {
+            let (mut pre_pool_states, mut post_pool_states) = (pool_states(), pool_states());
+            mev.disable_mismatched_pools(&mut pre_pool_states, &mut post_pool_states);
+            for pool_states in [&pre_pool_states, &post_pool_states] {
+                assert!(pool_states.0.contains_key(&matching));
+                assert!(!pool_states.0.contains_key(&mismatching));
+                assert!(pool_states.0.contains_key(&unconfigured));
+            }
+            match log_receiver.try_recv() {
+                Ok(MevMsg::PoolConfigMismatch(mismatch)) => {
+                    assert!(expect_event);
+                    assert_eq!(
+                        mismatch,
+                        PoolConfigMismatch {
+                            pool: mismatching,
+                            config_pool_a_mint: usdc,
+                            config_pool_b_mint: usdt,
+                            pool_a_mint: usdc,
+                            pool_b_mint: sol,
+                        }
+                    );
+                }
+                Ok(_) => panic!("Expected a pool config mismatch"),
+                Err(_) => assert!(!expect_event),
+            }
+        }
+        assert_eq!(
+            *mev.checked_pool_mints.lock().unwrap(),
+            vec![(matching, true), (mismatching, false)]
+                .into_iter()
+                .collect::<HashMap<Pubkey, bool>>()
+        );
+    }
+
+    #[test]
+    fn test_try_new_path_errors() {
+        let (usdc, usdt, sol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (usdc_usdt, usdt_sol) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(usdc_usdt).mints(usdc, usdt).build(),
+            pool(usdt_sol).mints(usdt, sol).build(),
+        ]
+        .into_iter()
+        .collect();
+        let unknown_pool = Pubkey::new_unique();
+        let try_new = |mev_path: MevPath| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let config = MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![mev_path],
+                ..MevConfig::default()
+            };
+            let err = Mev::try_new(log_send_channel, config).unwrap_err();
+            assert_eq!(err.path(), Some("the path"));
+            assert!(err.to_string().contains("MEV path the path"), "{}", err);
+            err
+        };
+
+        assert_eq!(
+            try_new(path("the path").build()),
+            MevError::EmptyPath {
+                path: "the path".to_owned()
+            }
+        );
+        assert_eq!(
+            try_new(
+                path("the path")
+                    .hop(usdc_usdt, TradeDirection::AtoB)
+                    .hop(unknown_pool, TradeDirection::BtoA)
+                    .build()
+            ),
+            MevError::UnknownPool {
+                path: "the path".to_owned(),
+                hop: 1,
+                pool: unknown_pool,
+            }
+        );
+        assert_eq!(
+            try_new(
+                path("the path")
+                    .hop(usdc_usdt, TradeDirection::AtoB)
+                    .hop(usdt_sol, TradeDirection::AtoB)
+                    .hop(usdc_usdt, TradeDirection::AtoB)
+                    .build()
+            ),
+            MevError::SamePoolSameDirection {
+                path: "the path".to_owned(),
+                pool: usdc_usdt,
+                direction: TradeDirection::AtoB,
+            }
+        );
+        // USDC -> USDT -> SOL does not return to USDC.
+        assert_eq!(
+            try_new(
+                path("the path")
+                    .hop(usdc_usdt, TradeDirection::AtoB)
+                    .hop(usdt_sol, TradeDirection::AtoB)
+                    .build()
+            ),
+            MevError::MismatchedEndpoints {
+                path: "the path".to_owned(),
+                first_pool: usdc_usdt,
+                input_mint: usdc,
+                last_pool: usdt_sol,
+                output_mint: sol,
+            }
+        );
+
+        // Other errors are not about a path.
+        let (log_send_channel, _log_receiver) = unbounded();
+        let err = Mev::try_new(
+            log_send_channel,
+            MevConfig {
+                fallback_rpc_url: Some("ftp://localhost".to_owned()),
+                fallback_when_not_leader: true,
+                ..MevConfig::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.path(), None);
+        assert!(
+            err.to_string().starts_with("Invalid fallback_rpc_url"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid MEV path")]
+    fn test_mint_chain_checked_at_startup() {
+        let (usdc, usdt, sol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let pools: Vec<OrcaPoolAddresses> = [(usdc, usdt), (sol, usdc)]
+            .into_iter()
+            .map(|(pool_a_mint, pool_b_mint)| OrcaPoolAddresses {
+                address: Pubkey::new_unique(),
+                pool_a_mint,
+                pool_b_mint,
+                ..OrcaPoolAddresses::default()
+            })
+            .collect();
+        // USDC -> USDT, then SOL -> USDC does not chain.
+        let path = MevPath {
+            name: "usdc-usdt-sol".to_owned(),
+            path: pools
+                .iter()
+                .map(|pool| PairInfo {
+                    pool: pool.address,
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: TradeDirection::AtoB,
+                })
+                .collect(),
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let mev_config = MevConfig {
+            orca_accounts: AllOrcaPoolAddresses::new(pools),
+            mev_paths: vec![path],
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        Mev::new(log_send_channel, mev_config);
+    }
+
+    #[test]
+    fn test_account_creation_costs() {
+        let rent = Rent::default();
+        let account_rent = rent.minimum_balance(spl_token::state::Account::LEN);
+        let wsol = inline_spl_token::native_mint::id();
+        let minimum_profit = 2_501;
+
+        // A marginal wSOL opportunity that can pay for the rent of one new token
+        // account, but not for two.
+        let profit = minimum_profit + account_rent;
+        let (profit_one, costs_one) = Mev::deduct_account_creation_costs(profit, &wsol, 1, &rent);
+        assert_eq!(profit_one, minimum_profit);
+        assert_eq!(costs_one, 0);
+        assert!(profit_one >= minimum_profit);
+        let (profit_two, costs_two) = Mev::deduct_account_creation_costs(profit, &wsol, 2, &rent);
+        assert_eq!(profit_two, minimum_profit.saturating_sub(account_rent));
+        assert_eq!(costs_two, 0);
+        assert!(profit_two < minimum_profit);
+
+        // For other tokens, the profit is untouched and the rent is reported in
+        // lamports instead.
+        let usdc = Pubkey::new_unique();
+        assert_eq!(
+            Mev::deduct_account_creation_costs(profit, &usdc, 2, &rent),
+            (profit, 2 * account_rent),
+        );
+        assert_eq!(
+            Mev::deduct_account_creation_costs(profit, &usdc, 0, &rent),
+            (profit, 0),
+        );
+    }
+
+    #[test]
+    fn test_vote_triggers_are_skipped() {
+        let node_keypair = Keypair::new();
+        let vote_keypair = Keypair::new();
+        let vote_tx = vote_transaction::new_vote_transaction(
+            vec![42],
+            Hash::default(),
+            Hash::default(),
+            &node_keypair,
+            &vote_keypair,
+            &vote_keypair,
+            None,
+        );
+        let sanitize = |tx: Transaction, is_simple_vote_tx: Option<bool>| {
+            SanitizedTransaction::try_create(
+                VersionedTransaction::from(tx),
+                MessageHash::Compute,
+                is_simple_vote_tx,
+                SimpleAddressLoader::Disabled,
+                true, // require_static_program_ids
+            )
+            .unwrap()
+        };
+
+        // The vote account happens to be watched.
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mut mev = Mev::new(log_send_channel, MevConfig::default());
+        mev.watched_programs.insert(vote_keypair.pubkey());
+        mev.trigger_accounts.insert(vote_keypair.pubkey());
+
+        let mut tx = sanitize(vote_tx.clone(), None);
+        assert_eq!(TriggerKind::of(&tx), TriggerKind::Vote);
+        mev.fill_tx_mev_accounts(&mut tx);
+        assert!(tx.mev_keys().is_none());
+        assert_eq!(mev.skipped_vote_triggers.load(Ordering::Relaxed), 1);
+
+        // The same accounts in a transaction that is not a simple vote.
+        let mut tx = sanitize(vote_tx, Some(false));
+        assert_eq!(TriggerKind::of(&tx), TriggerKind::User);
+        mev.fill_tx_mev_accounts(&mut tx);
+        assert!(tx.mev_keys().is_some());
+        assert_eq!(mev.skipped_vote_triggers.load(Ordering::Relaxed), 1);
+
+        // Reporting the metrics resets the count.
+        mev.tick(1, 0);
+        assert_eq!(mev.skipped_vote_triggers.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_triggers_need_a_watched_program_and_account() {
+        let (program_id, watched_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool = OrcaPoolAddresses {
+            program_id,
+            address: Pubkey::new_unique(),
+            pool_a_account: Pubkey::new_unique(),
+            pool_b_account: Pubkey::new_unique(),
+            ..OrcaPoolAddresses::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                watched_programs: vec![B58Pubkey(program_id)],
+                watched_accounts: vec![B58Pubkey(watched_account)],
+                orca_accounts: AllOrcaPoolAddresses::new(vec![pool.clone()]),
+                ..MevConfig::default()
+            },
+        );
+        let triggers = |program_id: Pubkey, account: Pubkey| {
+            let tx = tx_with_accounts(program_id, vec![AccountMeta::new(account, false)]);
+            mev.is_monitored_account(&tx)
+        };
+
+        for account in [
+            pool.address,
+            pool.pool_a_account,
+            pool.pool_b_account,
+            watched_account,
+        ] {
+            assert!(triggers(program_id, account));
+            // The account alone is not enough.
+            assert!(!triggers(Pubkey::new_unique(), account));
+        }
+        // The program alone is not enough either, e.g. a swap through a pool
+        // that we do not monitor.
+        assert!(!triggers(program_id, Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_blocked_signers_do_not_trigger() {
+        let (program_id, watched_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (blocked_payer, blocked_cosigner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                watched_programs: vec![B58Pubkey(program_id)],
+                watched_accounts: vec![B58Pubkey(watched_account)],
+                blocked_signers: vec![B58Pubkey(blocked_payer), B58Pubkey(blocked_cosigner)],
+                ..MevConfig::default()
+            },
+        );
+        let fill = |payer: Pubkey, account: AccountMeta| {
+            let accounts = vec![AccountMeta::new(watched_account, false), account];
+            let instruction = Instruction::new_with_bytes(program_id, &[], accounts);
+            let mut tx = SanitizedTransaction::from_transaction_for_tests(
+                Transaction::new_with_payer(&[instruction], Some(&payer)),
+            );
+            mev.fill_tx_mev_accounts(&mut tx);
+            tx.mev_keys().is_some()
+        };
+        let other = Pubkey::new_unique();
+
+        assert!(fill(other, AccountMeta::new(Pubkey::new_unique(), false)));
+        // The fee payer is blocked.
+        assert!(!fill(blocked_payer, AccountMeta::new(other, false)));
+        // Another required signer is blocked.
+        assert!(!fill(other, AccountMeta::new(blocked_cosigner, true)));
+        // A blocked key that does not sign, e.g. the token account of a
+        // transfer to it, does not block the transaction.
+        assert!(fill(other, AccountMeta::new(blocked_cosigner, false)));
+    }
+
+    #[test]
+    fn test_config_reload_between_fill_and_evaluate() {
+        let program_id = Pubkey::new_unique();
+        let config = |pool: Pubkey| MevConfig {
+            watched_programs: vec![B58Pubkey(program_id)],
+            orca_accounts: AllOrcaPoolAddresses::new(vec![OrcaPoolAddresses {
+                program_id,
+                address: pool,
+                ..Default::default()
+            }]),
+            ..MevConfig::default()
+        };
+        let (old_pool, new_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, config(old_pool));
+
+        let fill = |mev: &Mev, pool: Pubkey| {
+            let payer = Pubkey::new_unique();
+            let accounts = vec![AccountMeta::new(pool, false)];
+            let instruction = Instruction::new_with_bytes(program_id, &[], accounts);
+            let mut tx = SanitizedTransaction::from_transaction_for_tests(
+                Transaction::new_with_payer(&[instruction], Some(&payer)),
+            );
+            mev.fill_tx_mev_accounts(&mut tx);
+            tx
+        };
+        // The pool states only depend on the accounts loaded for the keys,
+        // none of them in this case.
+        let evaluate = |mev: &Mev, tx: &SanitizedTransaction| {
+            let loaded_tx = LoadedTransaction {
+                accounts: vec![],
+                mev_accounts: Some(MevAccounts {
+                    pool_accounts: vec![],
+                    user_authorities: Vec::new(),
+                    pubkey_account_map: HashMap::new(),
+                }),
+                program_indices: vec![],
+                rent: 0,
+                rent_debits: RentDebits::default(),
+            };
+            let result = mev.log_mev_opportunities_get_max_profit_tx(
+                tx,
+                1,
+                false,
+                PoolStates(HashMap::new()),
+                &loaded_tx,
+                Hash::default(),
+                &Rent::default(),
+            );
+            assert!(result.is_empty());
+            match log_receiver.try_recv() {
+                Ok(MevMsg::Log(pool_states)) => pool_states.stale_generation,
+                _ => panic!("Expected the pool states to be logged"),
+            }
+        };
+
+        // The config is reloaded after the transaction got its keys.
+        let tx = fill(&mev, old_pool);
+        let mev = mev.reload(config(new_pool));
+        assert_eq!(mev.config_generation, 1);
+        // The log thread gets the new paths before anything that refers to them.
+        match log_receiver.try_recv() {
+            Ok(MevMsg::Paths(paths)) => assert_eq!(paths, mev.mev_paths),
+            _ => panic!("Expected the reloaded paths to be sent"),
+        }
+        assert_eq!(tx.mev_keys().unwrap().config_generation, 0);
+        assert_eq!(tx.mev_keys().unwrap().pool_keys[0].pool, old_pool);
+        assert_eq!(evaluate(&mev, &tx), Some(0));
+
+        let tx = fill(&mev, new_pool);
+        assert_eq!(tx.mev_keys().unwrap().pool_keys[0].pool, new_pool);
+        assert_eq!(evaluate(&mev, &tx), None);
+    }
+
+    #[test]
+    fn test_fill_tx_mev_accounts_mints() {
+        let program_id = Pubkey::new_unique();
+        let (config_pool, other_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                watched_programs: vec![B58Pubkey(program_id)],
+                orca_accounts: AllOrcaPoolAddresses::new(vec![
+                    OrcaPoolAddresses {
+                        program_id,
+                        address: config_pool,
+                        pool_a_mint: mint_a,
+                        pool_b_mint: mint_b,
+                        ..Default::default()
+                    },
+                    OrcaPoolAddresses {
+                        program_id,
+                        address: other_pool,
+                        ..Default::default()
+                    },
+                ]),
+                ..MevConfig::default()
+            },
+        );
+        let mints = |mev: &Mev| -> Vec<(Option<Pubkey>, Option<Pubkey>)> {
+            let payer = Pubkey::new_unique();
+            let accounts = vec![AccountMeta::new(config_pool, false)];
+            let instruction = Instruction::new_with_bytes(program_id, &[], accounts);
+            let mut tx = SanitizedTransaction::from_transaction_for_tests(
+                Transaction::new_with_payer(&[instruction], Some(&payer)),
+            );
+            mev.fill_tx_mev_accounts(&mut tx);
+            tx.mev_keys()
+                .unwrap()
+                .pool_keys
+                .iter()
+                .map(|pool_keys| (pool_keys.token_a_mint, pool_keys.token_b_mint))
+                .collect()
+        };
+
+        // The mints in the config are loaded from the start, the others only
+        // once we loaded the pool.
+        assert_eq!(
+            mints(&mev),
+            vec![(Some(mint_a), Some(mint_b)), (None, None)]
+        );
+        let (other_mint_a, other_mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(config_pool).mints(mint_a, mint_b).build(),
+            pool(other_pool).mints(other_mint_a, other_mint_b).build(),
+        ]
+        .into_iter()
+        .collect();
+        mev.remember_loaded_pool_mints(&pool_states);
+        assert_eq!(
+            mints(&mev),
+            vec![
+                (Some(mint_a), Some(mint_b)),
+                (Some(other_mint_a), Some(other_mint_b)),
+            ]
+        );
+        assert_eq!(mev.loaded_pool_mints.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_failed_trigger_is_not_evaluated() {
+        // We have token accounts for every pool, so we would craft a
+        // transaction for the opportunity.
+        let pool_states = || -> PoolStates {
+            steth_stsol_usdc_pool_states()
+                .0
+                .into_iter()
+                .map(|(address, mut pool_state)| {
+                    pool_state.pool.source = Some(Pubkey::new_unique());
+                    pool_state.pool.destination = Some(Pubkey::new_unique());
+                    (address, pool_state)
+                })
+                .collect()
+        };
+        let (log_send_channel, log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states()),
+                mev_paths: vec![steth_stsol_usdc_path()],
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let tx = SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&payer),
+        ));
+        let evaluate = |trigger_failed| {
+            mev.evaluate_trigger(
+                &tx,
+                1,
+                trigger_failed,
+                PoolStates(HashMap::new()),
+                pool_states(),
+                Hash::default(),
+                &Rent::default(),
+            )
+        };
+
+        assert!(evaluate(true).is_empty());
+        match log_receiver.try_recv() {
+            Ok(MevMsg::Log(pool_states)) => {
+                assert!(pool_states.trigger_failed);
+                assert_eq!(pool_states.orca_post_tx_pool.0.len(), 3);
+            }
+            _ => panic!("Expected the pool states to be logged"),
+        }
+        assert!(log_receiver.try_recv().is_err());
+
+        assert_eq!(evaluate(false).len(), 1);
+        assert!(matches!(
+            log_receiver.try_recv(),
+            Ok(MevMsg::Log(PrePostPoolStates {
+                trigger_failed: false,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            log_receiver.try_recv(),
+            Ok(MevMsg::Opportunities(_))
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "which has role \"observe\"")]
+    fn test_path_through_observe_pool_is_rejected() {
+        let (trade_pool, observe_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let orca_accounts = AllOrcaPoolAddresses::new(vec![
+            OrcaPoolAddresses {
+                address: trade_pool,
+                ..OrcaPoolAddresses::default()
+            },
+            OrcaPoolAddresses {
+                address: observe_pool,
+                role: PoolRole::Observe,
+                ..OrcaPoolAddresses::default()
+            },
+        ]);
+        // Also as an alternative, we could pick it when it has the best price.
+        let path = MevPath {
+            name: "through observe pool".to_owned(),
+            path: vec![
+                PairInfo {
+                    pool: trade_pool,
+                    alternative_pools: vec![],
+                    group: None,
+                    direction: TradeDirection::AtoB,
+                },
+                PairInfo {
+                    pool: trade_pool,
+                    alternative_pools: vec![observe_pool],
+                    group: None,
+                    direction: TradeDirection::BtoA,
+                },
+            ],
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts,
+                mev_paths: vec![path],
+                ..MevConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "which is not an orca_account")]
+    fn test_path_through_unknown_pool_is_rejected() {
+        let (known_pool, unknown_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let orca_accounts = AllOrcaPoolAddresses::new(vec![OrcaPoolAddresses {
+            address: known_pool,
+            ..OrcaPoolAddresses::default()
+        }]);
+        let (log_send_channel, _log_receiver) = unbounded();
+        Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts,
+                mev_paths: vec![path("A->B->A")
+                    .hop(known_pool, TradeDirection::AtoB)
+                    .hop(unknown_pool, TradeDirection::BtoA)
+                    .build()],
+                ..MevConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_path_without_trade_accounts_is_quoted() {
+        // Without a user authority, we only quote, so we do not need our
+        // token accounts.
+        let (log_send_channel, _log_receiver) = unbounded();
+        Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&steth_stsol_usdc_pool_states()),
+                mev_paths: vec![steth_stsol_usdc_path()],
+                ..MevConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "which needs both a source and a destination")]
+    fn test_path_without_trade_accounts_is_rejected_when_trading() {
+        let keypair_file = tempfile::NamedTempFile::new().unwrap();
+        write_keypair_file(&Keypair::new(), keypair_file.path()).unwrap();
+        let (log_send_channel, _log_receiver) = unbounded();
+        Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&steth_stsol_usdc_pool_states()),
+                mev_paths: vec![steth_stsol_usdc_path()],
+                user_authority_path: Some(keypair_file.path().to_owned()),
+                ..MevConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_discovered_paths_are_appended() {
+        let (usdc, sol, stsol) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (usdc_sol_1, usdc_sol_2) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let with_accounts = |pool: PoolBuilder| {
+            pool.source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+        };
+        let pools: Vec<OrcaPoolAddresses> = vec![
+            with_accounts(pool(usdc_sol_1).mints(usdc, sol)),
+            with_accounts(pool(usdc_sol_2).mints(usdc, sol)),
+            // Without our token accounts, we cannot trade through this one.
+            pool(Pubkey::new_unique()).mints(stsol, usdc),
+            with_accounts(pool(Pubkey::new_unique()).mints(stsol, sol)),
+        ]
+        .into_iter()
+        .map(|pool| pool.build().1.pool)
+        .collect();
+        let config = |user_authority_path: Option<std::path::PathBuf>| MevConfig {
+            orca_accounts: AllOrcaPoolAddresses::new(pools.clone()),
+            mev_paths: vec![path("configured")
+                .hop(usdc_sol_1, TradeDirection::AtoB)
+                .hop(usdc_sol_2, TradeDirection::BtoA)
+                .build()],
+            auto_discover_paths: Some(AutoDiscoverConfig { max_hops: 3 }),
+            tokens: vec![
+                ("USDC".to_owned(), B58Pubkey(usdc)),
+                ("SOL".to_owned(), B58Pubkey(sol)),
+            ]
+            .into_iter()
+            .collect(),
+            user_authority_path,
+            ..MevConfig::default()
+        };
+        let names = |mev: &Mev| -> Vec<String> {
+            mev.mev_paths.iter().map(|path| path.name.clone()).collect()
+        };
+
+        // Without a user authority we only quote, so every cycle is kept.
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel.clone(), config(None));
+        assert_eq!(
+            names(&mev),
+            [
+                "configured".to_owned(),
+                format!("auto:USDC>SOL>{}>USDC", stsol),
+                "auto:SOL>USDC>SOL".to_owned(),
+                format!("auto:SOL>USDC>{}>SOL", stsol),
+                format!("auto:USDC>SOL>{}>USDC#2", stsol),
+                format!("auto:SOL>USDC>{}>SOL#2", stsol),
+            ]
+        );
+
+        // When trading, the cycles through the pool without our token
+        // accounts are disabled, rather than stopping MEV.
+        let keypair_file = tempfile::NamedTempFile::new().unwrap();
+        write_keypair_file(&Keypair::new(), keypair_file.path()).unwrap();
+        let mev = Mev::new(
+            log_send_channel,
+            config(Some(keypair_file.path().to_owned())),
+        );
+        assert_eq!(names(&mev).len(), 6);
+        let enabled: Vec<&str> = mev
+            .mev_paths
+            .iter()
+            .filter(|path| path.enabled)
+            .map(|path| path.name.as_str())
+            .collect();
+        assert_eq!(enabled, ["configured", "auto:SOL>USDC>SOL"]);
+    }
+
+    #[test]
+    fn test_skipped_paths_are_logged() {
+        let (pool_ab, pool_ba, gone) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mut pool_states: PoolStates = [pool_ab, pool_ba, gone]
+            .iter()
+            .map(|address| {
+                pool(*address)
+                    .balances(1_000_000, 1_000_000)
+                    .source(Pubkey::new_unique())
+                    .destination(Pubkey::new_unique())
+                    .build()
+            })
+            .collect();
+        let config = |log_skipped_paths: bool| MevConfig {
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths: vec![
+                path("balanced")
+                    .hop(pool_ab, TradeDirection::AtoB)
+                    .hop(pool_ba, TradeDirection::BtoA)
+                    .build(),
+                path("missing")
+                    .hop(pool_ab, TradeDirection::AtoB)
+                    .hop(gone, TradeDirection::BtoA)
+                    .build(),
+            ],
+            log_skipped_paths,
+            ..MevConfig::default()
+        };
+        let (mev_quiet, mev_verbose) = (config(false), config(true));
+        pool_states.0.remove(&gone);
+
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel.clone(), mev_quiet);
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        assert!(outputs.is_empty());
+        assert!(log_receiver.try_recv().is_err());
+
+        let mev = Mev::new(log_send_channel, mev_verbose);
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        assert!(outputs.is_empty());
+        match log_receiver.try_recv() {
+            Ok(MevMsg::PathsSkipped(skipped)) => {
+                assert_eq!(skipped.len(), 2);
+                assert_eq!((skipped[0].path_idx, skipped[0].reversed), (0, false));
+                assert!(matches!(
+                    skipped[0].reason,
+                    PathSkipReason::NotProfitable { marginal_price } if marginal_price < 1.0
+                ));
+                assert_eq!(
+                    skipped[1],
+                    PathSkipped {
+                        path_idx: 1,
+                        reversed: false,
+                        reason: PathSkipReason::MissingPool { pool: gone },
+                    }
+                );
+            }
+            _ => panic!("Expected the skipped paths"),
+        }
+        assert!(log_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_quote_amount_overflow() {
+        let (offset_pool, other_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // With its offset, the first pool quotes more of token B than it holds,
+        // up to more than a token account can hold.
+        let (_, mut offset_state) = pool(offset_pool).balances(1_000, u64::MAX).build();
+        offset_state.curve_type = CurveType::Offset;
+        offset_state.curve_calculator = Arc::new(OffsetCurve {
+            token_b_offset: u64::MAX,
+        });
+        // The second pool is deep enough to swap what the first one quotes.
+        let pool_states: PoolStates = [
+            (offset_pool, offset_state),
+            pool(other_pool)
+                .balances(u64::MAX / 2, u64::MAX / 2)
+                .build(),
+        ]
+        .into_iter()
+        .collect();
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![path("offset")
+                    .hop(offset_pool, TradeDirection::AtoB)
+                    .hop(other_pool, TradeDirection::BtoA)
+                    .build()],
+                ..MevConfig::default()
+            },
+        );
+        let mev_path = &mev.mev_paths[0];
+        let hops = mev_path.resolve_hops(&pool_states).unwrap();
+
+        let quote = mev.quote_path(mev_path, &hops, 100).unwrap();
+        assert!(quote.input_output_pairs[0].token_out > 100);
+
+        // We used to truncate the output of the first hop, and trade the
+        // wrapped-around amount in the second.
+        assert_eq!(
+            mev.quote_path(mev_path, &hops, 1_000_000_000_000).err(),
+            Some(PathSkipReason::AmountOverflow { hop: 0 })
+        );
+        assert_eq!(
+            mev.quote_path(mev_path, &hops, u64::MAX as u128 + 1).err(),
+            Some(PathSkipReason::AmountOverflow { hop: 0 })
+        );
+    }
+
+    #[test]
+    fn test_hop_output_must_stay_below_the_reserve() {
+        let (offset_pool, other_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // With its offset, the first pool quotes token B as if it held a
+        // million, while its reserve is nearly drained.
+        let (_, mut offset_state) = pool(offset_pool).balances(1_000_000, 1_000).build();
+        offset_state.curve_type = CurveType::Offset;
+        offset_state.curve_calculator = Arc::new(OffsetCurve {
+            token_b_offset: 1_000_000,
+        });
+        let pool_states: PoolStates = [
+            (offset_pool, offset_state),
+            pool(other_pool).balances(1_000_000, 1_000_000).build(),
+        ]
+        .into_iter()
+        .collect();
+        let new_mev = |max_hop_output_bps: Option<u16>| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![path("offset")
+                        .hop(offset_pool, TradeDirection::AtoB)
+                        .hop(other_pool, TradeDirection::BtoA)
+                        .build()],
+                    max_hop_output_bps,
+                    ..MevConfig::default()
+                },
+            )
+        };
+        let mev = new_mev(None);
+        let mev_path = &mev.mev_paths[0];
+        let hops = mev_path.resolve_hops(&pool_states).unwrap();
+        let excessive = Some(PathSkipReason::ExcessiveHopOutput {
+            hop: 0,
+            pool: offset_pool,
+        });
+
+        // A small trade leaves most of the reserve, a larger one would take
+        // more than the pool holds.
+        let quote = mev.quote_path(mev_path, &hops, 10).unwrap();
+        assert!(quote.input_output_pairs[0].token_out < 1_000);
+        assert_eq!(mev.quote_path(mev_path, &hops, 2_000).err(), excessive);
+
+        // With a smaller share, the small trade takes too much as well.
+        let strict_mev = new_mev(Some(50));
+        assert_eq!(
+            strict_mev
+                .quote_path(&strict_mev.mev_paths[0], &hops, 10)
+                .err(),
+            excessive
+        );
+    }
+
+    #[test]
+    fn test_dedup_rotated_paths() {
+        let (mint_a, mint_b, mint_c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let pools = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        // B is cheap in the first pool, so A->B->C->A has an opportunity, and
+        // so do its rotations.
+        let pool_states: PoolStates = [
+            pool(pools[0])
+                .mints(mint_a, mint_b)
+                .balances(1_000_000, 1_100_000),
+            pool(pools[1])
+                .mints(mint_b, mint_c)
+                .balances(1_000_000, 1_000_000),
+            pool(pools[2])
+                .mints(mint_c, mint_a)
+                .balances(1_000_000, 1_000_000),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let cycle = |name: &str, start: usize| {
+            (0..3).fold(path(name), |path, hop| {
+                path.hop(pools[(start + hop) % 3], TradeDirection::AtoB)
+            })
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![
+                    cycle("A->B->C->A", 0).build(),
+                    cycle("B->C->A->B", 1).build(),
+                    cycle("A->B->C->A again", 0).build(),
+                ],
+                ..MevConfig::default()
+            },
+        );
+
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        let profits: Vec<(usize, u64)> = outputs
+            .iter()
+            .map(|output| (output.path_idx, output.profit))
+            .collect();
+        assert_eq!(profits.len(), 3);
+        assert_eq!(profits[0].1, profits[2].1);
+        assert_ne!(profits[0].1, profits[1].1);
+
+        // Only the most profitable opportunity is left, and of the two equal
+        // ones, the first.
+        let kept = if profits[1].1 > profits[0].1 { 1 } else { 0 };
+        let outputs = mev.dedup_opportunities(outputs);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].path_idx, kept);
+        assert_eq!(mev.duplicate_opportunities.load(Ordering::Relaxed), 2);
+
+        let mut expected_key = vec![(pools[0], true), (pools[1], true), (pools[2], true)];
+        expected_key.sort_unstable();
+        assert_eq!(mev.opportunity_key(&outputs), expected_key);
+
+        mev.tick(1, 0);
+        assert_eq!(mev.duplicate_opportunities.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_derive_token_accounts_from_authority() {
+        let authority = Keypair::new();
+        let keypair_file = tempfile::NamedTempFile::new().unwrap();
+        write_keypair_file(&authority, keypair_file.path()).unwrap();
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let our_source = Pubkey::new_unique();
+        let pool_with_source = pool(Pubkey::new_unique())
+            .mints(mint_a, mint_b)
+            .source(our_source)
+            .build();
+        // Without the mints we cannot derive the token accounts.
+        let pool_without_mints = pool(Pubkey::new_unique()).build();
+        // The pools' own token accounts must differ, like in a valid config.
+        let pools = [&pool_with_source, &pool_without_mints].map(|(_address, pool_state)| {
+            OrcaPoolAddresses {
+                pool_a_account: Pubkey::new_unique(),
+                pool_b_account: Pubkey::new_unique(),
+                ..pool_state.pool.clone()
+            }
+        });
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: AllOrcaPoolAddresses::new(pools.to_vec()),
+                user_authority_path: Some(keypair_file.path().to_owned()),
+                derive_token_accounts_from_authority: true,
+                ..MevConfig::default()
+            },
+        );
+
+        let derived_destination = associated_token_address(&authority.pubkey(), &mint_b);
+        let pools = &mev.orca_monitored_accounts;
+        let pool = pools.by_address(&pool_with_source.0).unwrap();
+        // The source from the config wins.
+        assert_eq!(pool.source, Some(our_source));
+        assert_eq!(pool.destination, Some(derived_destination));
+        assert_eq!(
+            pools
+                .by_token_account(&derived_destination)
+                .map(|pool| pool.address)
+                .collect::<Vec<_>>(),
+            vec![pool_with_source.0]
+        );
+        let pool = pools.by_address(&pool_without_mints.0).unwrap();
+        assert_eq!(pool.source, None);
+        assert_eq!(pool.destination, None);
+    }
+
+    #[test]
+    fn test_user_authorities_per_mint() {
+        let keypair_file = |keypair: &Keypair| {
+            let file = tempfile::NamedTempFile::new().unwrap();
+            write_keypair_file(keypair, file.path()).unwrap();
+            file
+        };
+        let (default_authority, stable_authority) = (Keypair::new(), Keypair::new());
+        let default_file = keypair_file(&default_authority);
+        let stable_file = keypair_file(&stable_authority);
+        let (usdc, usdt) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (sol, stsol) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (stable_ab, stable_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (sol_ab, sol_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(stable_ab)
+                .mints(usdc, usdt)
+                .balances(1_000_000, 2_000_000),
+            pool(stable_ba)
+                .mints(usdc, usdt)
+                .balances(1_000_000, 1_000_000),
+            pool(sol_ab)
+                .mints(sol, stsol)
+                .balances(1_000_000, 2_000_000),
+            pool(sol_ba)
+                .mints(sol, stsol)
+                .balances(1_000_000, 1_000_000),
+        ]
+        .into_iter()
+        .map(|pool| {
+            pool.source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .build()
+        })
+        .collect();
+        let config = |user_authorities: Vec<UserAuthorityConfig>| MevConfig {
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths: vec![
+                path("stable")
+                    .hop(stable_ab, TradeDirection::AtoB)
+                    .hop(stable_ba, TradeDirection::BtoA)
+                    .build(),
+                path("sol")
+                    .hop(sol_ab, TradeDirection::AtoB)
+                    .hop(sol_ba, TradeDirection::BtoA)
+                    .build(),
+            ],
+            user_authority_path: Some(default_file.path().to_owned()),
+            user_authorities,
+            ..MevConfig::default()
+        };
+        let stable = UserAuthorityConfig {
+            path: Some(stable_file.path().to_owned()),
+            signer: None,
+            pools: vec![],
+            mints: vec![TokenRef::Mint(usdc)],
+        };
+
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, config(vec![stable.clone()]));
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        let signer_of = |path_idx: usize| {
+            let output = outputs.iter().find(|output| output.path_idx == path_idx);
+            output.unwrap().blueprint.as_ref().unwrap().signer.pubkey()
+        };
+        assert_eq!(signer_of(0), stable_authority.pubkey());
+        assert_eq!(signer_of(1), default_authority.pubkey());
+        // Both authorities get locked along with the pools.
+        assert_eq!(
+            mev.mev_keys().user_authorities,
+            vec![default_authority.pubkey(), stable_authority.pubkey()]
+        );
+
+        // Listing one pool of the stable path by itself splits the path over
+        // two authorities, which no transaction can sign for.
+        let mixed = UserAuthorityConfig {
+            pools: vec![B58Pubkey(stable_ab)],
+            mints: vec![],
+            ..stable
+        };
+        let config = config(vec![mixed]);
+        let pool_authorities = PoolAuthorities::load(&config).unwrap();
+        let err = pool_authorities.for_path(&config.mev_paths[0]).unwrap_err();
+        assert!(err.contains("mixes user authorities"), "{}", err);
+        let authority = pool_authorities.for_path(&config.mev_paths[1]).unwrap();
+        assert!(authority.is_none());
+    }
+
+    #[test]
+    fn test_observe_pools_are_loaded_read_only_and_logged() {
+        let program_id = Pubkey::new_unique();
+        let observe_pool = OrcaPoolAddresses {
+            program_id,
+            address: Pubkey::new_unique(),
+            pool_a_account: Pubkey::new_unique(),
+            pool_b_account: Pubkey::new_unique(),
+            // Ignored, we never trade through the pool.
+            source: Some(Pubkey::new_unique()),
+            destination: Some(Pubkey::new_unique()),
+            role: PoolRole::Observe,
+            ..OrcaPoolAddresses::default()
+        };
+        let observe_pool_address = observe_pool.address;
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                watched_programs: vec![B58Pubkey(program_id)],
+                orca_accounts: AllOrcaPoolAddresses::new(vec![observe_pool]),
+                ..MevConfig::default()
+            },
+        );
+
+        let payer = Pubkey::new_unique();
+        let accounts = vec![AccountMeta::new_readonly(observe_pool_address, false)];
+        let instruction = Instruction::new_with_bytes(program_id, &[], accounts);
+        let mut tx = SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&payer),
+        ));
+        mev.fill_tx_mev_accounts(&mut tx);
+        let mev_keys = tx.mev_keys().unwrap();
+        assert_eq!(mev_keys.pool_keys[0].pool, observe_pool_address);
+        assert_eq!(mev_keys.pool_keys[0].source, None);
+        assert_eq!(mev_keys.pool_keys[0].destination, None);
+        let mut write_accounts = HashSet::new();
+        mev_keys.get_write_accounts(&mut write_accounts);
+        assert!(write_accounts.is_empty());
+
+        // The loaded state does not know the role, the log does.
+        let pool_states = || -> PoolStates {
+            [pool(observe_pool_address).balances(1_000, 2_000).build()]
+                .into_iter()
+                .collect()
+        };
+        let result = mev.evaluate_trigger(
+            &tx,
+            1,
+            false,
+            pool_states(),
+            pool_states(),
+            Hash::default(),
+            &Rent::default(),
+        );
+        assert!(result.is_empty());
+        match log_receiver.try_recv() {
+            Ok(MevMsg::Log(pool_states)) => {
+                let logged = &pool_states.orca_post_tx_pool.0[&observe_pool_address];
+                assert_eq!(logged.pool.role, PoolRole::Observe);
+                assert!(serde_json::to_string(&pool_states)
+                    .unwrap()
+                    .contains("\"role\":\"observe\""));
+            }
+            _ => panic!("Expected the pool states to be logged"),
+        }
+    }
+
+    /// `Mev` that trades through `pool_count` pools of the program at
+    /// `program_id`, and samples the lock impact of every transaction.
+    fn lock_impact_mev(
+        program_id: Pubkey,
+        pool_count: usize,
+    ) -> (Mev, Vec<OrcaPoolAddresses>, Receiver<MevMsg>) {
+        let pools: Vec<OrcaPoolAddresses> = (0..pool_count)
+            .map(|_| OrcaPoolAddresses {
+                program_id,
+                address: Pubkey::new_unique(),
+                pool_a_account: Pubkey::new_unique(),
+                pool_b_account: Pubkey::new_unique(),
+                pool_mint: Pubkey::new_unique(),
+                pool_fee: Pubkey::new_unique(),
+                source: Some(Pubkey::new_unique()),
+                destination: Some(Pubkey::new_unique()),
+                ..OrcaPoolAddresses::default()
+            })
+            .collect();
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                watched_programs: vec![B58Pubkey(program_id)],
+                orca_accounts: AllOrcaPoolAddresses::new(pools.clone()),
+                lock_impact_sampling_rate: Some(1.0),
+                ..MevConfig::default()
+            },
+        );
+        (mev, pools, log_receiver)
+    }
+
+    fn tx_with_accounts(program_id: Pubkey, accounts: Vec<AccountMeta>) -> SanitizedTransaction {
+        let instruction = Instruction::new_with_bytes(program_id, &[], accounts);
+        SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&Pubkey::new_unique()),
+        ))
+    }
+
+    #[test]
+    fn test_lock_impact_without_overlap() {
+        let program_id = Pubkey::new_unique();
+        let (mut mev, _pools, log_receiver) = lock_impact_mev(program_id, 1);
+        let watched_account = Pubkey::new_unique();
+        mev.trigger_accounts.insert(watched_account);
+
+        // The payer, the program and the watched account.
+        let tx = tx_with_accounts(
+            program_id,
+            vec![AccountMeta::new_readonly(watched_account, false)],
+        );
+        let report = mev.lock_impact(&tx);
+        assert!(report.has_mev_keys);
+        assert_eq!(report.tx_locks, 3);
+        // Our token accounts and the pool's token accounts, pool mint and
+        // fee account.
+        assert_eq!(report.additional_writable, 6);
+        // The pool, its authority and the token program.
+        assert_eq!(report.additional_readonly, 3);
+        assert!(report.overlapping_keys.is_empty());
+        assert!(report.promoted_to_writable.is_empty());
+        assert_eq!(report.total_locks(), 12);
+        assert!(!report.exceeds_account_lock_limit);
+        // The dry run changes nothing, and is not reported.
+        assert!(tx.mev_keys().is_none());
+        assert!(log_receiver.try_recv().is_err());
+
+        // A transaction that does not trigger us only gets its own locks.
+        let other_tx = tx_with_accounts(
+            Pubkey::new_unique(),
+            vec![AccountMeta::new_readonly(watched_account, false)],
+        );
+        let report = mev.lock_impact(&other_tx);
+        assert!(!report.has_mev_keys);
+        assert_eq!(report.total_locks(), 3);
+
+        // With sampling, filling the MEV keys reports the same impact.
+        let mut tx = tx;
+        mev.fill_tx_mev_accounts(&mut tx);
+        assert!(tx.mev_keys().is_some());
+        match log_receiver.try_recv() {
+            Ok(MevMsg::LockImpact(sampled)) => assert_eq!(sampled, mev.lock_impact(&tx)),
+            _ => panic!("Expected the lock impact to be reported"),
+        }
+    }
+
+    #[test]
+    fn test_lock_impact_with_partial_overlap() {
+        let program_id = Pubkey::new_unique();
+        let (mev, pools, _log_receiver) = lock_impact_mev(program_id, 1);
+        let pool = &pools[0];
+
+        let tx = tx_with_accounts(
+            program_id,
+            vec![
+                AccountMeta::new_readonly(pool.address, false),
+                AccountMeta::new_readonly(pool.pool_a_account, false),
+                AccountMeta::new(pool.source.unwrap(), false),
+            ],
+        );
+        let report = mev.lock_impact(&tx);
+        assert_eq!(report.tx_locks, 5);
+        assert_eq!(report.additional_writable, 5);
+        assert_eq!(report.additional_readonly, 2);
+        let mut overlapping_keys = vec![
+            B58Pubkey(pool.address),
+            B58Pubkey(pool.pool_a_account),
+            B58Pubkey(pool.source.unwrap()),
+        ];
+        overlapping_keys.sort_by_key(|key| key.0);
+        assert_eq!(report.overlapping_keys, overlapping_keys);
+        // We trade through the pool, so its token account is locked for
+        // writing now.
+        assert_eq!(
+            report.promoted_to_writable,
+            vec![B58Pubkey(pool.pool_a_account)]
+        );
+        assert_eq!(report.total_locks(), 11);
+        assert!(!report.exceeds_account_lock_limit);
+    }
+
+    #[test]
+    fn test_lock_impact_exceeding_account_lock_limit() {
+        let program_id = Pubkey::new_unique();
+        // Every pool adds 7 locks, and all pools share the authority, which
+        // we do not know before loading them, and the token program.
+        let (mev, pools, _log_receiver) = lock_impact_mev(program_id, 18);
+        let tx = tx_with_accounts(
+            program_id,
+            vec![AccountMeta::new_readonly(pools[0].address, false)],
+        );
+        let report = mev.lock_impact(&tx);
+        assert_eq!(report.tx_locks, 3);
+        assert_eq!(report.overlapping_keys, vec![B58Pubkey(pools[0].address)]);
+        assert_eq!(report.total_locks(), 3 + 18 * 7 - 1 + 2);
+        assert!(report.total_locks() > MAX_TX_ACCOUNT_LOCKS);
+        assert!(report.exceeds_account_lock_limit);
+    }
+
+    #[test]
+    fn test_duplicate_pools_are_in_mev_keys_once() {
+        let pool = r#"
+    [[orca_account]]
+        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
+        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
+        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
+        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
+        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
+        "#;
+        let parse = |pools: &str| {
+            parse_mev_config(&format!(
+                r#"
+    config_version = 1
+    log_path = '/tmp/mev.log'
+    watched_programs = ['9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP']
+    minimum_profit = {{}}
+    mev_path = []
+    {}
+    "#,
+                pools
+            ))
+        };
+        // A config rejects the duplicate, but the pools can come from
+        // elsewhere, e.g. a discovery.
+        let err = parse(&format!("{}{}", pool, pool)).unwrap_err();
+        assert!(err.contains("is listed 2 times"), "{}", err);
+        let mut config = parse(pool).unwrap();
+        let pool_addresses = config.orca_accounts.iter().next().unwrap().clone();
+        config.orca_accounts =
+            AllOrcaPoolAddresses::new(vec![pool_addresses.clone(), pool_addresses]);
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, config);
+
+        let payer = Pubkey::new_unique();
+        let accounts = vec![AccountMeta::new(
+            pubkey("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ"),
+            false,
+        )];
+        let instruction = Instruction::new_with_bytes(pubkey(ORCA_PROGRAM_ID), &[], accounts);
+        let mut tx = SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&payer),
+        ));
+        mev.fill_tx_mev_accounts(&mut tx);
+        let pool_keys = &tx.mev_keys().unwrap().pool_keys;
+        assert_eq!(pool_keys.len(), 1);
+        assert_eq!(
+            pool_keys[0].pool,
+            pubkey("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ")
+        );
+    }
+
+    #[test]
+    fn test_require_confirmations_before_craft() {
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (source_a, destination_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // With `b_per_a` of 2, B is twice as cheap in the first pool as in the
+        // second, with 1/2 the imbalance is reversed.
+        let pool_states = |pool_ab_b_balance: u64| -> PoolStates {
+            [
+                pool(pool_ab)
+                    .balances(1_000_000, pool_ab_b_balance)
+                    .source(source_a)
+                    .destination(destination_b),
+                pool(pool_ba)
+                    .balances(1_000_000, 1_000_000)
+                    .source(source_a)
+                    .destination(destination_b),
+            ]
+            .into_iter()
+            .map(PoolBuilder::build)
+            .collect()
+        };
+        let imbalanced = || pool_states(2_000_000);
+        let reversed = || pool_states(500_000);
+        let (log_send_channel, log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&imbalanced()),
+                mev_paths: vec![path("A->B->A")
+                    .hop(pool_ab, TradeDirection::AtoB)
+                    .hop(pool_ba, TradeDirection::BtoA)
+                    .require_confirmations(1)
+                    .build()],
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let tx = SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&payer),
+        ));
+        // Returns how many transactions we crafted, and the skip reason and
+        // observations of the logged opportunity, if there was one.
+        let evaluate = |slot: Slot, post_pool_states: PoolStates| {
+            let transactions = mev.evaluate_trigger(
+                &tx,
+                slot,
+                false,
+                PoolStates(HashMap::new()),
+                post_pool_states,
+                Hash::default(),
+                &Rent::default(),
+            );
+            let logged = log_receiver.try_iter().find_map(|msg| match msg {
+                MevMsg::Opportunities(outputs) => {
+                    Some((outputs[0].skip_reason, outputs[0].observations))
+                }
+                _ => None,
+            });
+            (transactions.len(), logged)
+        };
+
+        // The first observation only defers, the second one confirms.
+        assert_eq!(
+            evaluate(1, imbalanced()),
+            (0, Some((Some(SkipReason::Unconfirmed), Some(1))))
+        );
+        assert_eq!(evaluate(1, imbalanced()), (1, Some((None, Some(2)))));
+
+        // Observations do not carry over to the next slot.
+        assert_eq!(
+            evaluate(2, imbalanced()),
+            (0, Some((Some(SkipReason::Unconfirmed), Some(1))))
+        );
+        // A reversed imbalance has no opportunity on the path, and starts the
+        // count over.
+        assert_eq!(evaluate(2, reversed()), (0, None));
+        assert_eq!(
+            evaluate(2, imbalanced()),
+            (0, Some((Some(SkipReason::Unconfirmed), Some(1))))
+        );
+        assert_eq!(evaluate(2, imbalanced()), (1, Some((None, Some(2)))));
+
+        // Without a slot, e.g. for a searcher, we do not gate.
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&imbalanced(), Hash::default(), &Rent::default(), None);
+        assert_eq!(outputs[0].skip_reason, None);
+        assert_eq!(outputs[0].observations, None);
+        assert!(outputs[0].blueprint.is_some());
+    }
+
+    #[test]
+    fn test_only_touched_paths_are_evaluated() {
+        let pools: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        // Both cycles are profitable, whichever pool the trigger moved.
+        let pool_states = |moved: Option<Pubkey>| -> PoolStates {
+            pools
+                .iter()
+                .enumerate()
+                .map(|(idx, address)| {
+                    let b_balance = match (idx % 2, moved == Some(*address)) {
+                        (0, false) => 2_000_000,
+                        (0, true) => 2_100_000,
+                        (_, false) => 1_000_000,
+                        (_, true) => 1_100_000,
+                    };
+                    pool(*address).balances(1_000_000, b_balance).build()
+                })
+                .collect()
+        };
+        let (log_send_channel, log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states(None)),
+                mev_paths: vec![
+                    path("first")
+                        .hop(pools[0], TradeDirection::AtoB)
+                        .hop(pools[1], TradeDirection::BtoA)
+                        .build(),
+                    path("second")
+                        .hop(pools[2], TradeDirection::AtoB)
+                        .hop_with_alternatives(pools[3], vec![pools[4]], TradeDirection::BtoA)
+                        .build(),
+                ],
+                ..MevConfig::default()
+            },
+        );
+        assert_eq!(mev.paths_by_pool[&pools[4]], vec![1]);
+
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let tx = SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&payer),
+        ));
+        // Returns the paths of the logged opportunities.
+        let evaluate = |pre_pool_states: PoolStates, post_pool_states: PoolStates| {
+            mev.evaluate_trigger(
+                &tx,
+                1,
+                false,
+                pre_pool_states,
+                post_pool_states,
+                Hash::default(),
+                &Rent::default(),
+            );
+            log_receiver
+                .try_iter()
+                .find_map(|msg| match msg {
+                    MevMsg::Opportunities(outputs) => Some(
+                        outputs
+                            .iter()
+                            .map(|output| output.path_idx)
+                            .collect::<Vec<usize>>(),
+                    ),
+                    _ => None,
+                })
+                .unwrap_or_default()
+        };
+
+        // Without the states before, every pool counts as moved.
+        assert_eq!(
+            evaluate(PoolStates(HashMap::new()), pool_states(None)),
+            vec![0, 1]
+        );
+        // A trigger that moved nothing has no new opportunity.
+        assert_eq!(
+            evaluate(pool_states(None), pool_states(None)),
+            Vec::<usize>::new()
+        );
+        // The second path is still profitable, but only evaluated once the
+        // trigger moves one of its pools, also an alternative one.
+        for (moved, path_idx) in [(0, 0), (1, 0), (2, 1), (3, 1), (4, 1)] {
+            assert_eq!(
+                evaluate(pool_states(None), pool_states(Some(pools[moved]))),
+                vec![path_idx]
+            );
+        }
+    }
+
+    #[test]
+    fn test_min_trigger_delta_skips_self_balanced_trigger() {
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (source_a, destination_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states = |pool_ab_a_balance: u64, pool_ab_b_balance: u64| -> PoolStates {
+            [
+                pool(pool_ab)
+                    .balances(pool_ab_a_balance, pool_ab_b_balance)
+                    .source(source_a)
+                    .destination(destination_b),
+                pool(pool_ba)
+                    .balances(1_000_000, 1_000_000)
+                    .source(source_a)
+                    .destination(destination_b),
+            ]
+            .into_iter()
+            .map(PoolBuilder::build)
+            .collect()
+        };
+        let (log_send_channel, log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states(1_000_000, 2_000_000)),
+                mev_paths: vec![path("A->B->A")
+                    .hop(pool_ab, TradeDirection::AtoB)
+                    .hop(pool_ba, TradeDirection::BtoA)
+                    .build()],
+                // 100 of the reserves of 1M.
+                min_trigger_delta: Some(MinTriggerDeltaConfig {
+                    bps: Some(1),
+                    amounts: HashMap::new(),
+                }),
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+        let tx = SanitizedTransaction::from_transaction_for_tests(Transaction::new_with_payer(
+            &[instruction],
+            Some(&payer),
+        ));
+        // Returns how many transactions we crafted, and the skip reason of the
+        // logged opportunity, if there was one.
+        let evaluate = |pre_pool_states: PoolStates, post_pool_states: PoolStates| {
+            let transactions = mev.evaluate_trigger(
+                &tx,
+                1,
+                false,
+                pre_pool_states,
+                post_pool_states,
+                Hash::default(),
+                &Rent::default(),
+            );
+            let logged = log_receiver.try_iter().find_map(|msg| match msg {
+                MevMsg::Opportunities(outputs) => Some(outputs[0].skip_reason),
+                _ => None,
+            });
+            (transactions.len(), logged)
+        };
+
+        // The trigger swapped and arbitraged its own swap, which left the
+        // pools where they were up to rounding. What still looks like an
+        // opportunity after it was there before, and it is not ours to take.
+        assert_eq!(
+            evaluate(
+                pool_states(1_000_003, 1_999_995),
+                pool_states(1_000_000, 2_000_000)
+            ),
+            (0, Some(Some(SkipReason::NoMaterialMove)))
+        );
+        // A trigger that moved the pool creates the opportunity.
+        assert_eq!(
+            evaluate(
+                pool_states(1_000_000, 1_000_000),
+                pool_states(1_000_000, 2_000_000)
+            ),
+            (1, Some(None))
+        );
+
+        // Without the states before the trigger, e.g. for a searcher, we do
+        // not gate.
+        let outputs = mev.get_arbitrage_tx_outputs(
+            &pool_states(1_000_000, 2_000_000),
+            Hash::default(),
+            &Rent::default(),
+            None,
+        );
+        assert!(outputs[0].blueprint.is_some());
+    }
+
+    #[test]
+    fn test_min_trigger_delta_amounts() {
+        let usdc = Pubkey::new_unique();
+        let mev_config = MevConfig {
+            tokens: [("USDC".to_owned(), B58Pubkey(usdc))].into_iter().collect(),
+            ..MevConfig::default()
+        };
+        let config = MinTriggerDeltaConfig {
+            bps: Some(10),
+            amounts: [(TokenRef::Symbol("USDC".to_owned()), TokenAmount::Raw(5_000))]
+                .into_iter()
+                .collect(),
+        };
+        let min_trigger_delta = MinTriggerDelta::new(&config, &mev_config).unwrap();
+        // Every threshold that applies has to be met.
+        assert!(min_trigger_delta.is_material(&usdc, 1_000_000, 1_005_000));
+        assert!(!min_trigger_delta.is_material(&usdc, 1_000_000, 1_004_999));
+        assert!(!min_trigger_delta.is_material(&usdc, 10_000_000, 10_005_000));
+        // Other mints only have the bps.
+        let other = Pubkey::new_unique();
+        assert!(min_trigger_delta.is_material(&other, 1_000_000, 999_000));
+        assert!(!min_trigger_delta.is_material(&other, 1_000_000, 999_001));
+        assert!(!min_trigger_delta.is_material(&other, 0, 0));
+
+        let unknown = MinTriggerDeltaConfig {
+            bps: None,
+            amounts: [(TokenRef::Symbol("USDT".to_owned()), TokenAmount::Raw(1))]
+                .into_iter()
+                .collect(),
+        };
+        assert!(MinTriggerDelta::new(&unknown, &mev_config)
+            .unwrap_err()
+            .starts_with("min_trigger_delta.amounts: \"USDT\" is neither"));
+    }
+
+    #[test]
+    fn test_split_when_source_balance_caps_input() {
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // B is twice as cheap in the first pool as in the second, but we only
+        // hold a fraction of the optimal input.
+        let pool_states: PoolStates = [
+            pool(pool_ab)
+                .balances(1_000_000, 2_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .source_balance(10_000),
+            pool(pool_ba)
+                .balances(1_000_000, 1_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique()),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let new_mev = |split_parts: Option<u8>| {
+            let mut mev_path = path("A->B->A")
+                .hop(pool_ab, TradeDirection::AtoB)
+                .hop(pool_ba, TradeDirection::BtoA);
+            if let Some(split_parts) = split_parts {
+                mev_path = mev_path.split_parts(split_parts);
+            }
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![mev_path.build()],
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(Arc::new(Keypair::new()));
+            mev
+        };
+
+        let single = new_mev(None).get_arbitrage_tx_outputs(
+            &pool_states,
+            Hash::default(),
+            &Rent::default(),
+            None,
+        );
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].input_output_pairs[0].token_in, 10_000);
+        assert_eq!(single[0].sequence, None);
+
+        // Every part trades our whole balance, against the pools as the parts
+        // before it leave them.
+        let mev = new_mev(Some(3));
+        let mut outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        assert_eq!(outputs.len(), 3);
+        for (part, output) in outputs.iter().enumerate() {
+            assert_eq!(output.path_idx, 0);
+            assert_eq!(
+                output.sequence,
+                Some(SequencePart {
+                    part: part as u8,
+                    parts: 3
+                })
+            );
+            assert_eq!(output.input_output_pairs[0].token_in, 10_000);
+            assert!(output.blueprint.is_some());
+        }
+        assert!(outputs[0].profit > outputs[1].profit);
+        assert!(outputs[1].profit > outputs[2].profit);
+        let total_profit: u64 = outputs.iter().map(|output| output.profit).sum();
+        assert!(total_profit > single[0].profit);
+
+        // The parts are taken together, in order.
+        let transactions = mev.take_max_profit_tx(&mut outputs, 1);
+        let profits: Vec<u64> = transactions.iter().map(|(_tx, profit)| *profit).collect();
+        assert_eq!(
+            profits,
+            vec![outputs[0].profit, outputs[1].profit, outputs[2].profit]
+        );
+        assert_eq!(mev.get_inflight_exposure()[&Pubkey::default()], 30_000);
+    }
+
+    #[test]
+    fn test_default_minimum_profit_for_unlisted_mint() {
+        // The input mint of the path is not in `minimum_profit`.
+        let pool_states = steth_stsol_usdc_pool_states();
+        let new_mev = |default_minimum_profit: Option<u64>| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![steth_stsol_usdc_path()],
+                    default_minimum_profit,
+                    ..MevConfig::default()
+                },
+            )
+        };
+        let get_outputs = |mev: &Mev| {
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None)
+        };
+
+        // Without a default, any profit will do.
+        let outputs = get_outputs(&new_mev(None));
+        assert_eq!(outputs.len(), 1);
+        let profit = outputs[0].profit;
+        assert!(profit > 0);
+
+        assert_eq!(get_outputs(&new_mev(Some(profit))).len(), 1);
+        assert!(get_outputs(&new_mev(Some(profit + 1))).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_gives_the_same_outputs() {
+        let pool_states: PoolStates = steth_stsol_usdc_pool_states()
+            .0
+            .into_iter()
+            .map(|(address, mut pool_state)| {
+                pool_state.pool.source = Some(Pubkey::new_unique());
+                pool_state.pool.destination = Some(Pubkey::new_unique());
+                pool_state.source_balance = Some(1_000_000_000);
+                (address, pool_state)
+            })
+            .collect();
+        let loaded = PoolStates::from_snapshot_bytes(&pool_states.to_snapshot_bytes()).unwrap();
+
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![steth_stsol_usdc_path()],
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+        let get_outputs = |pool_states: &PoolStates| {
+            mev.get_arbitrage_tx_outputs(pool_states, Hash::default(), &Rent::default(), None)
+        };
+        let (outputs, loaded_outputs) = (get_outputs(&pool_states), get_outputs(&loaded));
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(loaded_outputs.len(), 1);
+        let (output, loaded_output) = (&outputs[0], &loaded_outputs[0]);
+        assert_eq!(loaded_output.path_idx, output.path_idx);
+        assert_eq!(loaded_output.profit, output.profit);
+        assert_eq!(loaded_output.input_output_pairs, output.input_output_pairs);
+        assert_eq!(loaded_output.selected_pools, output.selected_pools);
+        assert_eq!(loaded_output.marginal_price, output.marginal_price);
+        let message_hash = |output: &MevTxOutput| {
+            output
+                .blueprint
+                .as_ref()
+                .map(|blueprint| *blueprint.sign(None).unwrap().message_hash())
+        };
+        assert!(message_hash(output).is_some());
+        assert_eq!(message_hash(loaded_output), message_hash(output));
+    }
+
+    #[test]
+    fn test_max_input_caps_the_input() {
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab)
+                .balances(1_000_000, 2_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .source_balance(10_000),
+            pool(pool_ba)
+                .balances(1_000_000, 1_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique()),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let get_outputs = |max_input: u64| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![path("A->B->A")
+                        .hop(pool_ab, TradeDirection::AtoB)
+                        .hop(pool_ba, TradeDirection::BtoA)
+                        .max_input(max_input)
+                        .build()],
+                    audit_mode: true,
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(Arc::new(Keypair::new()));
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None)
+        };
+
+        // The cap is below our balance, which is below the optimal input.
+        let outputs = get_outputs(5_000);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].input_output_pairs[0].token_in, 5_000);
+        assert_eq!(outputs[0].input_cap, Some(CapKind::MaxInput));
+        assert!(!outputs[0].capped);
+        let trace = outputs[0].decision_trace.as_ref().unwrap();
+        assert_eq!(
+            trace.caps.iter().map(|cap| cap.cap).collect::<Vec<_>>(),
+            vec![CapKind::SourceBalance, CapKind::MaxInput]
+        );
+        assert_eq!(trace.caps[1].before, 10_000);
+        assert_eq!(trace.caps[1].after, 5_000);
+        assert_eq!(
+            trace.replay(&ConstantProductCurve::default()),
+            Some((5_000, outputs[0].profit))
+        );
+
+        // A cap above our balance does not bind.
+        let outputs = get_outputs(20_000);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].input_output_pairs[0].token_in, 10_000);
+        assert_eq!(outputs[0].input_cap, Some(CapKind::SourceBalance));
+        assert!(outputs[0].capped);
+
+        // A cap of zero disables the path.
+        assert!(get_outputs(0).is_empty());
+    }
+
+    #[test]
+    fn test_capped_profits_rank_the_paths() {
+        // The first path has a small imbalance between deep pools, the second
+        // one a large imbalance between shallow pools. At their optimal inputs
+        // the first one makes more profit, but per token of input, the second
+        // one does.
+        let pools: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let pool_states = |source_balance: Option<u64>| -> PoolStates {
+            [
+                pool(pools[0]).balances(10_000_000, 11_000_000),
+                pool(pools[1]).balances(10_000_000, 10_000_000),
+                pool(pools[2]).balances(100_000, 200_000),
+                pool(pools[3]).balances(100_000, 100_000),
+            ]
+            .into_iter()
+            .map(|pool| {
+                let pool = pool
+                    .source(Pubkey::new_unique())
+                    .destination(Pubkey::new_unique());
+                match source_balance {
+                    Some(source_balance) => pool.source_balance(source_balance),
+                    None => pool,
+                }
+                .build()
+            })
+            .collect()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states(None)),
+                mev_paths: vec![
+                    path("deep")
+                        .hop(pools[0], TradeDirection::AtoB)
+                        .hop(pools[1], TradeDirection::BtoA)
+                        .build(),
+                    path("shallow")
+                        .hop(pools[2], TradeDirection::AtoB)
+                        .hop(pools[3], TradeDirection::BtoA)
+                        .build(),
+                ],
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+        // Returns the path of the transaction that we take, and per output
+        // whether our balance capped it.
+        let select = |source_balance: Option<u64>| {
+            let mut outputs = mev.get_arbitrage_tx_outputs(
+                &pool_states(source_balance),
+                Hash::default(),
+                &Rent::default(),
+                None,
+            );
+            let capped: Vec<bool> = outputs.iter().map(|output| output.capped).collect();
+            let taken = mev.take_max_profit_tx(&mut outputs, 1);
+            assert_eq!(taken.len(), 1);
+            let profit = taken[0].1;
+            let selected = outputs
+                .iter()
+                .find(|output| output.profit == profit)
+                .unwrap();
+            (selected.path_idx, capped)
+        };
+
+        assert_eq!(select(None), (0, vec![false, false]));
+        // With a small balance, both paths trade it all, and the second path
+        // makes more of it.
+        assert_eq!(select(Some(1_000)), (1, vec![true, true]));
+    }
+
+    #[test]
+    fn test_blocked_pools_are_treated_as_missing() {
+        let (pool_ab, pool_ba, alternative_ba) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (other_ab, other_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab).balances(1_000_000, 2_000_000),
+            pool(pool_ba).balances(1_000_000, 1_000_000),
+            pool(alternative_ba).balances(1_000_000, 1_100_000),
+            pool(other_ab).balances(1_000_000, 2_000_000),
+            pool(other_ba).balances(1_000_000, 1_000_000),
+        ]
+        .into_iter()
+        .map(|pool| {
+            pool.source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .build()
+        })
+        .collect();
+        let get_outputs = |blocked_pools: &[Pubkey]| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![
+                        path("with alternative")
+                            .hop(pool_ab, TradeDirection::AtoB)
+                            .hop_with_alternatives(
+                                pool_ba,
+                                vec![alternative_ba],
+                                TradeDirection::BtoA,
+                            )
+                            .build(),
+                        path("other")
+                            .hop(other_ab, TradeDirection::AtoB)
+                            .hop(other_ba, TradeDirection::BtoA)
+                            .build(),
+                    ],
+                    blocked_pools: blocked_pools.iter().copied().map(B58Pubkey).collect(),
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(Arc::new(Keypair::new()));
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None)
+                .into_iter()
+                .map(|output| (output.path_idx, output.selected_pools))
+                .collect::<Vec<_>>()
+        };
+
+        // The second hop prefers the pool with the better price.
+        let outputs = get_outputs(&[]);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0], (0, vec![pool_ab, pool_ba]));
+
+        // Blocking it falls back to the alternative pool, and does not affect
+        // the other path.
+        let outputs = get_outputs(&[pool_ba]);
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0], (0, vec![pool_ab, alternative_ba]));
+        assert_eq!(outputs[1].0, 1);
+
+        // Without a pool for the first hop, the path finds nothing, while the
+        // other path still does.
+        let outputs = get_outputs(&[pool_ab]);
+        assert_eq!(outputs, vec![(1, vec![other_ab, other_ba])]);
+    }
+
+    #[test]
+    fn test_path_tx_settings_land_in_signed_tx() {
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab)
+                .balances(1_000_000, 2_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .source_balance(10_000),
+            pool(pool_ba)
+                .balances(1_000_000, 1_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique()),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let cycle = |name: &str| {
+            path(name)
+                .hop(pool_ab, TradeDirection::AtoB)
+                .hop(pool_ba, TradeDirection::BtoA)
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![
+                    cycle("overridden")
+                        .slippage_bps(50)
+                        .compute_unit_price(5_000)
+                        .compute_unit_limit(250_000)
+                        .build(),
+                    cycle("global").build(),
+                ],
+                priority_fee: Some(PriorityFeeConfig {
+                    policy: PriorityFeePolicy::Fixed(1_000),
+                    max_compute_unit_price: 10_000,
+                }),
+                slippage_bps: Some(10),
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        assert_eq!(outputs.len(), 2);
+
+        // The compute unit limit, and the minimum output of the last swap, as
+        // the signed transaction sets them.
+        let signed_settings = |output: &MevTxOutput| {
+            let tx = output.blueprint.as_ref().unwrap().sign(None).unwrap();
+            let instructions: Vec<_> = tx.message().program_instructions_iter().collect();
+            let compute_unit_limit = instructions.iter().find_map(|(_program_id, instruction)| {
+                match try_from_slice_unchecked(&instruction.data) {
+                    Ok(ComputeBudgetInstruction::SetComputeUnitLimit(limit)) => Some(limit),
+                    _ => None,
+                }
+            });
+            let (_program_id, last_swap) = instructions.last().unwrap();
+            let minimum_amount_out = match SwapInstruction::unpack(&last_swap.data).unwrap() {
+                SwapInstruction::Swap(swap) => swap.minimum_amount_out,
+                _ => panic!("The last instruction should be a swap"),
+            };
+            (
+                get_compute_unit_price(&tx),
+                compute_unit_limit,
+                minimum_amount_out,
+            )
+        };
+        let with_slippage = |output: &MevTxOutput, slippage_bps: u128| {
+            let amount_out = output.input_output_pairs.last().unwrap().token_out as u128;
+            (amount_out * (10_000 - slippage_bps) / 10_000) as u64
+        };
+
+        let overridden = &outputs[0];
+        assert_eq!(
+            signed_settings(overridden),
+            (Some(5_000), Some(250_000), with_slippage(overridden, 50))
+        );
+        assert_eq!(
+            overridden.setting_sources,
+            TxSettingSources {
+                slippage_bps: SettingSource::Path,
+                compute_unit_price: SettingSource::Path,
+                compute_unit_limit: SettingSource::Path,
+            }
+        );
+
+        let global = &outputs[1];
+        assert_eq!(
+            signed_settings(global),
+            (Some(1_000), None, with_slippage(global, 10))
+        );
+        assert_eq!(
+            global.setting_sources,
+            TxSettingSources {
+                slippage_bps: SettingSource::Global,
+                compute_unit_price: SettingSource::Global,
+                compute_unit_limit: SettingSource::Default,
+            }
+        );
+        // We never accept a loss, however much slippage we tolerate.
+        assert!(with_slippage(global, 10) > global.input_output_pairs[0].token_in);
+    }
+
+    #[test]
+    fn test_profit_share_priority_fee() {
+        let (wsol, usdc) = (inline_spl_token::native_mint::id(), Pubkey::new_unique());
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab).balances(1_000_000, 2_000_000),
+            pool(pool_ba).balances(1_000_000, 1_000_000),
+        ]
+        .into_iter()
+        .map(|pool| {
+            pool.mints(wsol, usdc)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .build()
+        })
+        .collect();
+        let priority_fee = PriorityFeeConfig {
+            policy: PriorityFeePolicy::ProfitShare(0.5),
+            max_compute_unit_price: u64::MAX,
+        };
+        let authority = Arc::new(Keypair::new());
+        let new_mev = |priority_fee: Option<PriorityFeeConfig>| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![path("SOL->USDC->SOL")
+                        .hop(pool_ab, TradeDirection::AtoB)
+                        .hop(pool_ba, TradeDirection::BtoA)
+                        .build()],
+                    priority_fee,
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(authority.clone());
+            mev
+        };
+        let evaluate = |mev: &Mev| {
+            let mut outputs =
+                mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+            assert_eq!(outputs.len(), 1);
+            outputs.remove(0)
+        };
+
+        let gross_profit = evaluate(&new_mev(None)).profit;
+        let mev = new_mev(Some(priority_fee.clone()));
+        let output = evaluate(&mev);
+
+        // Without a compute unit limit in the config, the limit is our
+        // estimate for two hops, and the price pays half the profit over it.
+        let compute_unit_limit = 2 * DEFAULT_COMPUTE_UNITS_PER_HOP as u32;
+        let compute_unit_price = priority_fee
+            .get_profit_share_compute_unit_price(gross_profit, compute_unit_limit)
+            .unwrap();
+        let fee = priority_fee_lamports(compute_unit_price, compute_unit_limit);
+        assert!(fee > 0);
+        assert_eq!(output.compute_unit_price, Some(compute_unit_price));
+        assert_eq!(output.compute_unit_limit, Some(compute_unit_limit));
+        assert_eq!(
+            output.setting_sources.compute_unit_price,
+            SettingSource::Global
+        );
+        assert_eq!(output.profit, gross_profit - fee);
+
+        // Both compute budget instructions precede the swaps.
+        let tx = output.blueprint.as_ref().unwrap().sign(None).unwrap();
+        let instructions: Vec<_> = tx.message().program_instructions_iter().collect();
+        assert_eq!(instructions.len(), 4);
+        let compute_budget_instruction = |i: usize| {
+            let (program_id, instruction) = instructions[i];
+            assert!(solana_sdk::compute_budget::check_id(program_id));
+            try_from_slice_unchecked::<ComputeBudgetInstruction>(&instruction.data).unwrap()
+        };
+        assert_eq!(
+            compute_budget_instruction(0),
+            ComputeBudgetInstruction::SetComputeUnitLimit(compute_unit_limit)
+        );
+        assert_eq!(
+            compute_budget_instruction(1),
+            ComputeBudgetInstruction::SetComputeUnitPrice(compute_unit_price)
+        );
+
+        // The user authority has to pay for the signature and the priority
+        // fee, we do not craft what it cannot pay.
+        let required = LAMPORTS_PER_SIGNATURE + fee;
+        mev.fee_payer_balances
+            .lock()
+            .unwrap()
+            .insert(authority.pubkey(), required - 1);
+        let output = evaluate(&mev);
+        assert!(output.blueprint.is_none());
+        assert_eq!(output.skip_reason, Some(SkipReason::InsufficientFeeBalance));
+        mev.fee_payer_balances
+            .lock()
+            .unwrap()
+            .insert(authority.pubkey(), required);
+        assert!(evaluate(&mev).blueprint.is_some());
+    }
+
+    #[test]
+    fn test_minimum_profit_is_net_of_priority_fee() {
+        let (wsol, usdc) = (inline_spl_token::native_mint::id(), Pubkey::new_unique());
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab).balances(1_000_000, 2_000_000),
+            pool(pool_ba).balances(1_000_000, 1_000_000),
+        ]
+        .into_iter()
+        .map(|pool| {
+            pool.mints(wsol, usdc)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .build()
+        })
+        .collect();
+        let new_mev = |policy: Option<PriorityFeePolicy>, default_minimum_profit: Option<u64>| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![path("SOL->USDC->SOL")
+                        .hop(pool_ab, TradeDirection::AtoB)
+                        .hop(pool_ba, TradeDirection::BtoA)
+                        .build()],
+                    default_minimum_profit,
+                    priority_fee: policy.map(|policy| PriorityFeeConfig {
+                        policy,
+                        max_compute_unit_price: u64::MAX,
+                    }),
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(Arc::new(Keypair::new()));
+            mev
+        };
+        let get_outputs = |mev: &Mev| {
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None)
+        };
+
+        let outputs = get_outputs(&new_mev(None, None));
+        assert_eq!(outputs.len(), 1);
+        let gross_profit = outputs[0].profit;
+
+        for policy in [
+            PriorityFeePolicy::Fixed(10_000),
+            PriorityFeePolicy::ProfitShare(0.5),
+        ] {
+            let outputs = get_outputs(&new_mev(Some(policy.clone()), None));
+            assert_eq!(outputs.len(), 1);
+            let net_profit = outputs[0].profit;
+            assert!(net_profit + 1 < gross_profit);
+
+            // The gross profit makes the minimum, but what is left after the
+            // priority fee does not.
+            let minimum_profit = net_profit + 1;
+            assert!(get_outputs(&new_mev(Some(policy.clone()), Some(minimum_profit))).is_empty());
+            assert_eq!(
+                get_outputs(&new_mev(Some(policy), Some(net_profit))).len(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn test_minimum_profit_is_the_floor_of_minimum_amount_out() {
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab)
+                .balances(1_000_000, 2_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .source_balance(10_000),
+            pool(pool_ba)
+                .balances(1_000_000, 1_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique()),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let signed_minimum_amount_out = |slippage_bps: Option<u16>| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![path("A->B->A")
+                        .hop(pool_ab, TradeDirection::AtoB)
+                        .hop(pool_ba, TradeDirection::BtoA)
+                        .build()],
+                    default_minimum_profit: Some(5_000),
+                    slippage_bps,
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(Arc::new(Keypair::new()));
+            let outputs =
+                mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+            assert_eq!(outputs.len(), 1);
+            let tx = outputs[0].blueprint.as_ref().unwrap().sign(None).unwrap();
+            let swaps: Vec<Swap> = tx
+                .message()
+                .program_instructions_iter()
+                .filter_map(|(_program_id, instruction)| {
+                    match SwapInstruction::unpack(&instruction.data) {
+                        Ok(SwapInstruction::Swap(swap)) => Some(swap),
+                        _ => None,
+                    }
+                })
+                .collect();
+            assert_eq!(swaps.len(), 2);
+            let pairs = &outputs[0].input_output_pairs;
+            // The first hop has to produce what the last hop takes, not to
+            // make a loss in the intermediate token.
+            assert_eq!(swaps[0].minimum_amount_out, pairs[1].token_in);
+            (
+                pairs[0].token_in,
+                pairs.last().unwrap().token_out,
+                swaps[1].minimum_amount_out,
+            )
+        };
+
+        // The source balance caps the input, for a quoted output of about
+        // 19_300. Without slippage, we require the minimum profit on top of
+        // the input.
+        let (amount_in, amount_out, minimum_amount_out) = signed_minimum_amount_out(None);
+        assert_eq!(amount_in, 10_000);
+        assert!(amount_out > 19_000);
+        assert_eq!(minimum_amount_out, 15_000);
+
+        // A tolerance within the profit margin is what limits the output.
+        let (_, amount_out, minimum_amount_out) = signed_minimum_amount_out(Some(100));
+        assert_eq!(minimum_amount_out, amount_out * 9_900 / 10_000);
+        assert!(minimum_amount_out > 15_000);
+
+        // A wider tolerance still requires the minimum profit.
+        let (_, _, minimum_amount_out) = signed_minimum_amount_out(Some(5_000));
+        assert_eq!(minimum_amount_out, 15_000);
+    }
+
+    #[test]
+    fn test_swaps_use_the_token_program_of_their_pool() {
+        // The intermediate token of the path is a Token-2022 token, so the
+        // second pool holds its tokens in Token-2022 accounts.
+        let (pool_ab, pool_ba) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool_states: PoolStates = [
+            pool(pool_ab)
+                .balances(1_000_000, 2_000_000)
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .source_balance(10_000),
+            pool(pool_ba)
+                .balances(1_000_000, 1_000_000)
+                .token_program(inline_spl_token_2022::id())
+                .source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique()),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build)
+        .collect();
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mut mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![path("A->B->A")
+                    .hop(pool_ab, TradeDirection::AtoB)
+                    .hop(pool_ba, TradeDirection::BtoA)
+                    .build()],
+                ..MevConfig::default()
+            },
+        );
+        mev.user_authority = Some(Arc::new(Keypair::new()));
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        assert_eq!(outputs.len(), 1);
+
+        // The token program is the last account of a swap without host fee.
+        let blueprint = outputs[0].blueprint.as_ref().unwrap();
+        let token_programs: Vec<Pubkey> = blueprint
+            .instructions
+            .iter()
+            .filter(|instruction| instruction.program_id == pubkey(ORCA_PROGRAM_ID))
+            .map(|instruction| instruction.accounts.last().unwrap().pubkey)
+            .collect();
+        assert_eq!(
+            token_programs,
+            vec![inline_spl_token::id(), inline_spl_token_2022::id()]
+        );
+        let tx = blueprint.sign(None).unwrap();
+        let account_keys: Vec<&Pubkey> = tx.message().account_keys().iter().collect();
+        assert!(account_keys.contains(&&inline_spl_token::id()));
+        assert!(account_keys.contains(&&inline_spl_token_2022::id()));
+
+        // Until we loaded the pools we lock SPL Token for them, then the token
+        // program of each pool.
+        let token_programs = |mev: &Mev| -> Vec<(Pubkey, Pubkey)> {
+            mev.mev_keys()
+                .pool_keys
+                .iter()
+                .map(|pool_keys| (pool_keys.pool, pool_keys.token_program))
+                .collect()
+        };
+        assert!(token_programs(&mev)
+            .iter()
+            .all(|(_pool, token_program)| *token_program == inline_spl_token::id()));
+        mev.remember_loaded_pool_token_programs(&pool_states);
+        for (pool, token_program) in token_programs(&mev) {
+            assert_eq!(token_program, pool_states.0[&pool].pool.token_program);
+        }
+    }
+
+    #[test]
+    fn test_group_hop_splits_its_input_across_the_pools() {
+        // The pools of the second hop have the same price, and the first one
+        // is twice as deep as the second one.
+        let (pool_ab, deep_ba, shallow_ba) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let pool_states: PoolStates = [
+            pool(pool_ab).balances(1_000_000, 2_000_000),
+            pool(deep_ba).balances(1_000_000, 1_000_000),
+            pool(shallow_ba).balances(500_000, 500_000),
+        ]
+        .into_iter()
+        .map(|pool| {
+            pool.source(Pubkey::new_unique())
+                .destination(Pubkey::new_unique())
+                .build()
+        })
+        .collect();
+        let get_output = |mev_path: MevPath| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mut mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![mev_path],
+                    ..MevConfig::default()
+                },
+            );
+            mev.user_authority = Some(Arc::new(Keypair::new()));
+            let mut outputs =
+                mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+            assert_eq!(outputs.len(), 1);
+            outputs.remove(0)
+        };
+        let best_pool = get_output(
+            path("alternatives")
+                .hop(pool_ab, TradeDirection::AtoB)
+                .hop_with_alternatives(deep_ba, vec![shallow_ba], TradeDirection::BtoA)
+                .build(),
+        );
+        let split = get_output(
+            path("group")
+                .hop(pool_ab, TradeDirection::AtoB)
+                .group_hop("b-a", &[deep_ba, shallow_ba], TradeDirection::BtoA)
+                .build(),
+        );
+
+        // Splitting the second hop moves both prices less than trading all of
+        // it through the best pool, so it makes more profit.
+        assert!(split.profit > best_pool.profit);
+        assert!(best_pool.input_output_pairs[1].pool_splits.is_empty());
+        assert_eq!(split.selected_pools, vec![pool_ab, deep_ba]);
+        let pair = &split.input_output_pairs[1];
+        assert_eq!(
+            pair.pool_splits
+                .iter()
+                .map(|split| split.pool)
+                .collect::<Vec<Pubkey>>(),
+            vec![deep_ba, shallow_ba]
+        );
+        assert_eq!(
+            pair.pool_splits
+                .iter()
+                .map(|split| split.token_in)
+                .sum::<u64>(),
+            pair.token_in
+        );
+        assert_eq!(
+            pair.pool_splits
+                .iter()
+                .map(|split| split.token_out)
+                .sum::<u64>(),
+            pair.token_out
+        );
+        // The shares follow the depth of the pools.
+        let (deep_in, shallow_in) = (pair.pool_splits[0].token_in, pair.pool_splits[1].token_in);
+        assert!(
+            deep_in > shallow_in * 3 / 2 && deep_in < shallow_in * 5 / 2,
+            "{} {}",
+            deep_in,
+            shallow_in
+        );
+
+        // Every pool gets a swap, and together the swaps of the last hop
+        // return at least the input of the path.
+        let swaps: Vec<(u64, u64)> = split
+            .blueprint
+            .as_ref()
+            .unwrap()
+            .instructions
+            .iter()
+            .filter(|instruction| instruction.program_id == pubkey(ORCA_PROGRAM_ID))
+            .map(
+                |instruction| match SwapInstruction::unpack(&instruction.data).unwrap() {
+                    SwapInstruction::Swap(swap) => (swap.amount_in, swap.minimum_amount_out),
+                    _ => panic!("Every instruction for the pools should be a swap"),
+                },
+            )
+            .collect();
+        assert_eq!(swaps.len(), 3);
+        assert_eq!(swaps[1].0 + swaps[2].0, pair.token_in);
+        assert!(swaps[1].1 + swaps[2].1 >= split.input_output_pairs[0].token_in);
+    }
+
+    #[test]
+    fn test_disabled_path_is_not_evaluated() {
+        let pool_states = steth_stsol_usdc_pool_states();
+        let disabled_path = MevPath {
+            name: "disabled".to_owned(),
+            enabled: false,
+            ..steth_stsol_usdc_path()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts: orca_accounts(&pool_states),
+                mev_paths: vec![disabled_path, steth_stsol_usdc_path()],
+                ..MevConfig::default()
+            },
+        );
+        // Both paths are the same, only the enabled one has an opportunity.
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].path_idx, 1);
+    }
+
+    #[test]
+    fn test_probe_reverse() {
+        let pool_states = steth_stsol_usdc_pool_states();
+        let profitable_path = steth_stsol_usdc_path();
+        // The other orientation of the cycle, which has no opportunity.
+        let unprofitable_path = profitable_path.reversed();
+        assert_eq!(unprofitable_path.reversed(), profitable_path);
+        let outputs = |mev_path: MevPath| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            let mev = Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(&pool_states),
+                    mev_paths: vec![mev_path],
+                    ..MevConfig::default()
+                },
+            );
+            mev.get_arbitrage_tx_outputs(&pool_states, Hash::default(), &Rent::default(), None)
+        };
+
+        let forward_outputs = outputs(profitable_path);
+        assert_eq!(forward_outputs.len(), 1);
+        assert!(!forward_outputs[0].reversed);
+        assert!(outputs(unprofitable_path.clone()).is_empty());
+
+        // Probing the reverse finds the opportunity of the profitable path,
+        // under the index of the path as declared.
+        let reversed_outputs = outputs(MevPath {
+            probe_reverse: true,
+            ..unprofitable_path
+        });
+        assert_eq!(reversed_outputs.len(), 1);
+        let output = &reversed_outputs[0];
+        assert!(output.reversed);
+        assert_eq!(output.path_idx, 0);
+        assert_eq!(output.profit, forward_outputs[0].profit);
+        assert_eq!(
+            output.input_output_pairs,
+            forward_outputs[0].input_output_pairs
+        );
+        assert_eq!(output.input_mint, forward_outputs[0].input_mint);
+    }
+
+    #[test]
+    #[should_panic(expected = "which is not an orca_account")]
+    fn test_disabled_path_is_validated() {
+        let known_pool = Pubkey::new_unique();
+        let orca_accounts = AllOrcaPoolAddresses::new(vec![OrcaPoolAddresses {
+            address: known_pool,
+            ..OrcaPoolAddresses::default()
+        }]);
+        let (log_send_channel, _log_receiver) = unbounded();
+        Mev::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts,
+                mev_paths: vec![path("A->B->A")
+                    .hop(known_pool, TradeDirection::AtoB)
+                    .hop(Pubkey::new_unique(), TradeDirection::BtoA)
+                    .disabled()
+                    .build()],
+                ..MevConfig::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_path_cache_busts_on_every_balance_change() {
+        let mut pool_states = steth_stsol_usdc_pool_states();
+        let new_mev = |pool_states: &PoolStates| {
+            let (log_send_channel, _log_receiver) = unbounded();
+            Mev::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: orca_accounts(pool_states),
+                    mev_paths: vec![steth_stsol_usdc_path()],
+                    ..MevConfig::default()
+                },
+            )
+        };
+        let evaluate = |mev: &Mev, pool_states: &PoolStates| {
+            mev.get_arbitrage_tx_outputs(pool_states, Hash::default(), &Rent::default(), None)
+                .into_iter()
+                .map(|output| (output.input_output_pairs, output.profit))
+                .collect::<Vec<(Vec<InputOutputPairs>, u64)>>()
+        };
+        let mev = new_mev(&pool_states);
+        let counts = || {
+            let path_cache = mev.path_cache.lock().unwrap();
+            (path_cache.hits(), path_cache.misses())
+        };
+
+        // The same balances reuse the quotes.
+        let outputs = evaluate(&mev, &pool_states);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(evaluate(&mev, &pool_states), outputs);
+        assert_eq!(counts(), (1, 1));
+
+        // Any change to the balances of a pool of the path, or to ours,
+        // computes the quotes again, and they match those of an empty cache.
+        let changes: [fn(&mut OrcaPoolWithBalance); 4] = [
+            |pool_state| pool_state.pool_a_balance += 1_000,
+            |pool_state| pool_state.pool_b_balance -= 1_000,
+            |pool_state| pool_state.source_balance = Some(1_000_000),
+            |pool_state| pool_state.destination_balance = Some(1_000_000),
+        ];
+        let pools: Vec<Pubkey> = pool_states.0.keys().copied().collect();
+        let mut expected_misses = 1;
+        for pool in &pools {
+            for change in changes {
+                change(pool_states.0.get_mut(pool).unwrap());
+                assert_eq!(
+                    evaluate(&mev, &pool_states),
+                    evaluate(&new_mev(&pool_states), &pool_states)
+                );
+                expected_misses += 1;
+                assert_eq!(counts(), (1, expected_misses));
+            }
+        }
+
+        // Without an opportunity, we reuse that too.
+        let balanced_states: PoolStates = pools
+            .iter()
+            .map(|address| pool(*address).balances(1_000_000, 1_000_000).build())
+            .collect();
+        assert!(evaluate(&mev, &balanced_states).is_empty());
+        assert!(evaluate(&mev, &balanced_states).is_empty());
+        assert_eq!(counts(), (2, expected_misses + 1));
+
+        // A reloaded config starts with an empty cache.
+        let reloaded = mev.reload(MevConfig {
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths: vec![steth_stsol_usdc_path()],
+            ..MevConfig::default()
+        });
+        assert!(evaluate(&reloaded, &balanced_states).is_empty());
+        let path_cache = reloaded.path_cache.lock().unwrap();
+        assert_eq!((path_cache.hits(), path_cache.misses()), (0, 1));
+    }
+}
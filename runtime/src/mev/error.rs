@@ -0,0 +1,71 @@
+//! Why we cannot build a `Mev` from a config, see `Mev::try_new`.
+
+use solana_sdk::pubkey::Pubkey;
+use thiserror::Error;
+
+use super::arbitrage::TradeDirection;
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum MevError {
+    #[error("Invalid MEV path: MEV path {path} has no hops")]
+    EmptyPath { path: String },
+
+    /// The first and the last hop trade through the same pool in the same
+    /// direction, so the path cannot be a cycle.
+    #[error(
+        "Invalid MEV path: MEV path {path} starts and ends in pool {pool} with the same \
+        direction of trade {direction:?}"
+    )]
+    SamePoolSameDirection {
+        path: String,
+        pool: Pubkey,
+        direction: TradeDirection,
+    },
+
+    /// The last hop outputs another token than the first hop takes, so the
+    /// path does not end where it starts.
+    #[error(
+        "Invalid MEV path: MEV path {path} does not end in the token that it starts with, \
+        its last hop through pool {last_pool} outputs {output_mint}, but its first hop \
+        through pool {first_pool} takes {input_mint}"
+    )]
+    MismatchedEndpoints {
+        path: String,
+        first_pool: Pubkey,
+        input_mint: Pubkey,
+        last_pool: Pubkey,
+        output_mint: Pubkey,
+    },
+
+    #[error(
+        "Invalid MEV path: hop {hop} of MEV path {path} uses pool {pool}, which is not an \
+        orca_account"
+    )]
+    UnknownPool {
+        path: String,
+        hop: usize,
+        pool: Pubkey,
+    },
+
+    /// Any other check of the path failed, `reason` names the path.
+    #[error("Invalid MEV path: {reason}")]
+    InvalidPath { path: String, reason: String },
+
+    /// Another part of the config than the paths is invalid.
+    #[error("{0}")]
+    Config(String),
+}
+
+impl MevError {
+    /// The name of the path that the error is about, if any.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            MevError::EmptyPath { path }
+            | MevError::SamePoolSameDirection { path, .. }
+            | MevError::MismatchedEndpoints { path, .. }
+            | MevError::UnknownPool { path, .. }
+            | MevError::InvalidPath { path, .. } => Some(path),
+            MevError::Config(_) => None,
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Telling failures of our transactions that are specific to a path, e.g. a
+//! swap that got less than its minimum out, apart from failures that every
+//! path would run into, e.g. a stale blockhash. When the latter keep coming,
+//! crafting more transactions does not help, so we pause until they stop.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{clock::Slot, transaction::TransactionError};
+
+/// Why one of our transactions failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionFailure {
+    /// The blockhash that we signed with is not one of the bank's recent
+    /// blockhashes.
+    BlockhashNotFound,
+
+    /// The user authority cannot pay the fee, or does not exist.
+    InsufficientFundsForFee,
+
+    /// A signature of the transaction does not verify.
+    SignatureFailure,
+
+    /// An instruction failed, e.g. a swap that would get less than its
+    /// minimum out, with the index of the instruction.
+    InstructionError { index: u8, error: String },
+
+    /// Any other error.
+    Other(String),
+}
+
+impl ExecutionFailure {
+    pub fn from_transaction_error(err: &TransactionError) -> Self {
+        match err {
+            TransactionError::BlockhashNotFound => ExecutionFailure::BlockhashNotFound,
+            TransactionError::InsufficientFundsForFee | TransactionError::AccountNotFound => {
+                ExecutionFailure::InsufficientFundsForFee
+            }
+            TransactionError::SignatureFailure => ExecutionFailure::SignatureFailure,
+            TransactionError::InstructionError(index, error) => {
+                ExecutionFailure::InstructionError {
+                    index: *index,
+                    error: error.to_string(),
+                }
+            }
+            err => ExecutionFailure::Other(err.to_string()),
+        }
+    }
+
+    /// Whether every transaction that we craft would fail the same way,
+    /// whatever path it trades through. Such transactions are not executed,
+    /// so they do not tell us anything about the path.
+    pub fn is_systemic(&self) -> bool {
+        matches!(
+            self,
+            ExecutionFailure::BlockhashNotFound
+                | ExecutionFailure::InsufficientFundsForFee
+                | ExecutionFailure::SignatureFailure
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SystemicFailureConfig {
+    /// Pause crafting after this many systemic failures ...
+    pub threshold: u32,
+
+    /// ... within this many slots.
+    pub window_slots: u64,
+
+    /// Resume crafting after this many of our transactions in a row executed,
+    /// successfully or not, without a systemic failure.
+    pub recovery_executions: u32,
+}
+
+/// Written to the log when we pause crafting, and again when we resume it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum SystemicFailureEvent {
+    Paused {
+        slot: Slot,
+
+        /// The failure that reached the threshold.
+        failure: ExecutionFailure,
+
+        /// The systemic failures in the window, including this one.
+        failures: u32,
+    },
+    Resumed {
+        slot: Slot,
+
+        /// Whether the operator resumed crafting, rather than our
+        /// transactions executing again.
+        manual: bool,
+    },
+}
+
+/// Counts the systemic failures of our transactions, and pauses crafting when
+/// there are too many of them in a window. While paused, we still craft one
+/// transaction per slot, so that we notice when the failures stop. Systemic
+/// failures are not charged a fee, so that costs us nothing.
+#[derive(Debug)]
+pub struct SystemicFailureMonitor {
+    config: Option<SystemicFailureConfig>,
+
+    // Slots of the latest systemic failures, oldest first, at most
+    // `threshold` of them.
+    recent_failures: VecDeque<Slot>,
+
+    is_paused: bool,
+
+    // While paused, the slot in which we last let a transaction through, and
+    // how many executed in a row since we paused.
+    last_probe_slot: Option<Slot>,
+    recovered_executions: u32,
+}
+
+impl SystemicFailureMonitor {
+    /// Without `config`, we never pause.
+    pub fn new(config: Option<SystemicFailureConfig>) -> Self {
+        SystemicFailureMonitor {
+            config,
+            recent_failures: VecDeque::new(),
+            is_paused: false,
+            last_probe_slot: None,
+            recovered_executions: 0,
+        }
+    }
+
+    /// Apply the config of a reload. Failures that we already counted keep
+    /// counting, but without a config, we resume right away.
+    pub fn set_config(&mut self, config: Option<SystemicFailureConfig>) {
+        self.config = config;
+        if config.is_none() {
+            self.resume();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// Whether we may craft a transaction in `slot`. While paused, only the
+    /// first transaction in a slot may be crafted.
+    pub fn may_craft(&mut self, slot: Slot) -> bool {
+        if !self.is_paused {
+            return true;
+        }
+        if matches!(self.last_probe_slot, Some(probe_slot) if probe_slot >= slot) {
+            return false;
+        }
+        self.last_probe_slot = Some(slot);
+        true
+    }
+
+    /// Record how one of our transactions ended in `slot`, `failure` is
+    /// `None` when it succeeded. Returns the event to log when we pause or
+    /// resume because of it.
+    pub fn record(
+        &mut self,
+        slot: Slot,
+        failure: Option<&ExecutionFailure>,
+    ) -> Option<SystemicFailureEvent> {
+        let config = self.config?;
+        match failure {
+            Some(failure) if failure.is_systemic() => {
+                self.recovered_executions = 0;
+                let window_start = slot.saturating_sub(config.window_slots);
+                self.recent_failures
+                    .retain(|failure_slot| *failure_slot > window_start);
+                self.recent_failures.push_back(slot);
+                while self.recent_failures.len() > config.threshold as usize {
+                    self.recent_failures.pop_front();
+                }
+                let failures = self.recent_failures.len() as u32;
+                if self.is_paused || failures < config.threshold {
+                    return None;
+                }
+                self.is_paused = true;
+                self.last_probe_slot = Some(slot);
+                Some(SystemicFailureEvent::Paused {
+                    slot,
+                    failure: failure.clone(),
+                    failures,
+                })
+            }
+            _ if self.is_paused => {
+                self.recovered_executions = self.recovered_executions.saturating_add(1);
+                if self.recovered_executions < config.recovery_executions {
+                    return None;
+                }
+                self.resume();
+                Some(SystemicFailureEvent::Resumed {
+                    slot,
+                    manual: false,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Resume crafting on the operator's request. Returns the event to log,
+    /// if we were paused.
+    pub fn resume_manually(&mut self, slot: Slot) -> Option<SystemicFailureEvent> {
+        if !self.is_paused {
+            return None;
+        }
+        self.resume();
+        Some(SystemicFailureEvent::Resumed { slot, manual: true })
+    }
+
+    fn resume(&mut self) {
+        self.is_paused = false;
+        self.recent_failures.clear();
+        self.last_probe_slot = None;
+        self.recovered_executions = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_sdk::instruction::InstructionError};
+
+    fn monitor() -> SystemicFailureMonitor {
+        SystemicFailureMonitor::new(Some(SystemicFailureConfig {
+            threshold: 3,
+            window_slots: 10,
+            recovery_executions: 2,
+        }))
+    }
+
+    #[test]
+    fn test_classify_transaction_errors() {
+        for err in [
+            TransactionError::BlockhashNotFound,
+            TransactionError::InsufficientFundsForFee,
+            TransactionError::AccountNotFound,
+            TransactionError::SignatureFailure,
+        ] {
+            assert!(ExecutionFailure::from_transaction_error(&err).is_systemic());
+        }
+        let failure = ExecutionFailure::from_transaction_error(
+            &TransactionError::InstructionError(1, InstructionError::Custom(16)),
+        );
+        assert_eq!(
+            failure,
+            ExecutionFailure::InstructionError {
+                index: 1,
+                error: "custom program error: 0x10".to_owned(),
+            }
+        );
+        assert!(!failure.is_systemic());
+        assert!(
+            !ExecutionFailure::from_transaction_error(&TransactionError::AccountInUse)
+                .is_systemic()
+        );
+    }
+
+    #[test]
+    fn test_path_failures_do_not_pause() {
+        let mut monitor = monitor();
+        let failure = ExecutionFailure::InstructionError {
+            index: 0,
+            error: "custom program error: 0x10".to_owned(),
+        };
+        for slot in 0..10 {
+            assert_eq!(monitor.record(slot, Some(&failure)), None);
+        }
+        assert!(!monitor.is_paused());
+        assert!(monitor.may_craft(10));
+        assert!(monitor.may_craft(10));
+    }
+
+    #[test]
+    fn test_systemic_failures_pause_until_recovered() {
+        let mut monitor = monitor();
+        let failure = ExecutionFailure::BlockhashNotFound;
+        assert_eq!(monitor.record(1, Some(&failure)), None);
+        // The first failure falls out of the window.
+        assert_eq!(monitor.record(11, Some(&failure)), None);
+        assert_eq!(monitor.record(12, Some(&failure)), None);
+        assert_eq!(
+            monitor.record(12, Some(&failure)),
+            Some(SystemicFailureEvent::Paused {
+                slot: 12,
+                failure: ExecutionFailure::BlockhashNotFound,
+                failures: 3,
+            })
+        );
+        assert!(monitor.is_paused());
+        assert_eq!(monitor.record(12, Some(&failure)), None);
+
+        // One probe per slot.
+        assert!(!monitor.may_craft(12));
+        assert!(monitor.may_craft(13));
+        assert!(!monitor.may_craft(13));
+
+        // A systemic failure starts the recovery over, a failure of the path
+        // still shows that our transactions execute.
+        assert_eq!(monitor.record(13, None), None);
+        assert_eq!(monitor.record(14, Some(&failure)), None);
+        let path_failure = ExecutionFailure::Other("AccountInUse".to_owned());
+        assert_eq!(monitor.record(15, Some(&path_failure)), None);
+        assert_eq!(
+            monitor.record(16, None),
+            Some(SystemicFailureEvent::Resumed {
+                slot: 16,
+                manual: false,
+            })
+        );
+        assert!(!monitor.is_paused());
+        assert!(monitor.may_craft(16));
+        assert!(monitor.may_craft(16));
+    }
+
+    #[test]
+    fn test_resume_manually() {
+        let mut monitor = monitor();
+        assert_eq!(monitor.resume_manually(1), None);
+        for _ in 0..3 {
+            monitor.record(1, Some(&ExecutionFailure::SignatureFailure));
+        }
+        assert!(monitor.is_paused());
+        assert_eq!(
+            monitor.resume_manually(2),
+            Some(SystemicFailureEvent::Resumed {
+                slot: 2,
+                manual: true,
+            })
+        );
+        assert!(monitor.may_craft(2));
+        // The failures before the resume no longer count.
+        assert_eq!(
+            monitor.record(2, Some(&ExecutionFailure::SignatureFailure)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_without_config_never_pauses() {
+        let mut monitor = SystemicFailureMonitor::new(None);
+        for slot in 0..10 {
+            assert_eq!(
+                monitor.record(slot, Some(&ExecutionFailure::BlockhashNotFound)),
+                None
+            );
+        }
+        assert!(monitor.may_craft(10));
+    }
+}
@@ -0,0 +1,441 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+use crossbeam_channel::{bounded, Sender, TrySendError};
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_sdk::{bs58, signature::Signature, transaction::SanitizedTransaction};
+use thiserror::Error;
+
+use super::{log_channel::MevLogSender, logging::MevMsg, utils::serialize_b58};
+
+/// How many transactions may wait for the forwarder thread. When the queue is
+/// full, we drop the transaction rather than block the banking stage.
+pub const FORWARD_QUEUE_SIZE: usize = 64;
+
+/// How many requests we send for a transaction before we give up on it.
+pub const FORWARD_ATTEMPTS: u32 = 3;
+
+/// Timeout for each of connecting, writing the request, and every read of the
+/// response.
+pub const FORWARD_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Written to the log for every transaction that we tried to forward.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ForwardedTransaction {
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_signature: Signature,
+
+    /// How many requests we sent, zero when we could not queue the
+    /// transaction.
+    pub attempts: u32,
+
+    pub is_successful: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ForwardError {
+    #[error("could not reach the RPC: {0}")]
+    Io(String),
+
+    #[error("the RPC responded with HTTP status {0}")]
+    Http(u16),
+
+    #[error("the RPC rejected the transaction: {0}")]
+    Rejected(String),
+
+    #[error("the RPC returned an invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl ForwardError {
+    /// Whether another attempt may succeed where this one failed.
+    fn is_transient(&self) -> bool {
+        match self {
+            ForwardError::Io(_) => true,
+            ForwardError::Http(status) => *status >= 500,
+            ForwardError::Rejected(_) | ForwardError::InvalidResponse(_) => false,
+        }
+    }
+}
+
+impl From<std::io::Error> for ForwardError {
+    fn from(err: std::io::Error) -> Self {
+        ForwardError::Io(err.to_string())
+    }
+}
+
+/// Where we send `sendTransaction` requests to. We only speak plain HTTP, the
+/// RPC is expected to be our own forwarder, close to the validator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpEndpoint {
+    /// `host:port`, also sent as the `Host` header.
+    pub authority: String,
+    pub path: String,
+}
+
+/// Parse an `http://host[:port][/path]` URL.
+pub fn parse_http_url(url: &str) -> Result<HttpEndpoint, String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("{} is not an http:// URL", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => rest.split_at(idx),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(format!("{} has no host", url));
+    }
+    let authority = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    Ok(HttpEndpoint {
+        authority,
+        path: path.to_string(),
+    })
+}
+
+/// Sends MEV transactions to an external RPC from a thread of its own, so
+/// that the banking stage never waits for the network. The thread exits once
+/// all clones of the forwarder are dropped and the queue is empty.
+#[derive(Debug, Clone)]
+pub struct TransactionForwarder {
+    sender: Sender<(Signature, Vec<u8>)>,
+    log_send_channel: MevLogSender,
+}
+
+impl TransactionForwarder {
+    pub fn new(endpoint: HttpEndpoint, log_send_channel: impl Into<MevLogSender>) -> Self {
+        let log_send_channel = log_send_channel.into();
+        let (sender, receiver) = bounded::<(Signature, Vec<u8>)>(FORWARD_QUEUE_SIZE);
+        let thread_log_send_channel = log_send_channel.clone();
+        thread::Builder::new()
+            .name("solMevForward".to_string())
+            .spawn(move || {
+                for (transaction_signature, transaction) in receiver.iter() {
+                    let forwarded =
+                        forward_transaction(&endpoint, transaction_signature, &transaction);
+                    if thread_log_send_channel
+                        .send(MevMsg::ForwardedTransaction(forwarded))
+                        .is_err()
+                    {
+                        // The log thread exited, we are shutting down.
+                        break;
+                    }
+                }
+            })
+            .expect("Failed to spawn MEV forwarder thread");
+        TransactionForwarder {
+            sender,
+            log_send_channel,
+        }
+    }
+
+    /// Queue `transaction` to be sent to the RPC. This does not block, when
+    /// the queue is full we log the transaction as not forwarded.
+    pub fn forward(&self, transaction: &SanitizedTransaction) {
+        let transaction_signature = *transaction.signature();
+        let serialized = bincode::serialize(&transaction.to_versioned_transaction())
+            .expect("Constructed by us, should never fail");
+        let error = match self.sender.try_send((transaction_signature, serialized)) {
+            Ok(()) => return,
+            Err(TrySendError::Full(_)) => "the forward queue is full",
+            Err(TrySendError::Disconnected(_)) => "the forwarder thread exited",
+        };
+        // Unless the log channel is bounded and blocks when full, see
+        // `log_channel_policy`, this does not block either.
+        let _ = self
+            .log_send_channel
+            .send(MevMsg::ForwardedTransaction(ForwardedTransaction {
+                transaction_signature,
+                attempts: 0,
+                is_successful: false,
+                error: Some(error.to_string()),
+            }));
+    }
+}
+
+/// Send the serialized `transaction` to `endpoint`, retrying on transient
+/// errors.
+fn forward_transaction(
+    endpoint: &HttpEndpoint,
+    transaction_signature: Signature,
+    transaction: &[u8],
+) -> ForwardedTransaction {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "sendTransaction",
+        "params": [
+            bs58::encode(transaction).into_string(),
+            {"encoding": "base58", "skipPreflight": true},
+        ],
+    })
+    .to_string();
+    let mut attempts = 0;
+    let result = loop {
+        attempts += 1;
+        match send_request(endpoint, &request) {
+            Err(err) if err.is_transient() && attempts < FORWARD_ATTEMPTS => continue,
+            result => break result,
+        }
+    };
+    ForwardedTransaction {
+        transaction_signature,
+        attempts,
+        is_successful: result.is_ok(),
+        error: result.err().map(|err| err.to_string()),
+    }
+}
+
+/// POST the JSON-RPC `request` to `endpoint`, on a new connection.
+fn send_request(endpoint: &HttpEndpoint, request: &str) -> Result<(), ForwardError> {
+    let addr = endpoint
+        .authority
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| ForwardError::Io(format!("could not resolve {}", endpoint.authority)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, FORWARD_TIMEOUT)?;
+    stream.set_write_timeout(Some(FORWARD_TIMEOUT))?;
+    stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        endpoint.path,
+        endpoint.authority,
+        request.len(),
+        request
+    )?;
+    // We ask the server to close the connection, so the response ends there.
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    parse_response(&response)
+}
+
+fn parse_response(response: &[u8]) -> Result<(), ForwardError> {
+    let response = String::from_utf8_lossy(response);
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| ForwardError::InvalidResponse("incomplete headers".to_string()))?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| ForwardError::InvalidResponse(format!("invalid status line {:?}", head)))?;
+    if status != 200 {
+        return Err(ForwardError::Http(status));
+    }
+    let body: Value =
+        serde_json::from_str(body).map_err(|err| ForwardError::InvalidResponse(err.to_string()))?;
+    if let Some(error) = body.get("error") {
+        return Err(ForwardError::Rejected(error.to_string()));
+    }
+    match body.get("result").and_then(Value::as_str) {
+        Some(_signature) => Ok(()),
+        None => Err(ForwardError::InvalidResponse(body.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        time::Instant,
+    };
+
+    use crossbeam_channel::{unbounded, Receiver};
+    use solana_sdk::{
+        hash::Hash, pubkey::Pubkey, signature::Keypair, system_transaction,
+        transaction::VersionedTransaction,
+    };
+
+    use super::*;
+
+    fn transaction() -> SanitizedTransaction {
+        SanitizedTransaction::from_transaction_for_tests(system_transaction::transfer(
+            &Keypair::new(),
+            &Pubkey::new_unique(),
+            1,
+            Hash::default(),
+        ))
+    }
+
+    /// Read one HTTP request from `stream`, returns the request line, the
+    /// headers, and the body.
+    fn read_request(stream: &TcpStream) -> (String, Vec<String>, String) {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut headers = Vec::new();
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            if header == "\r\n" {
+                break;
+            }
+            headers.push(header.trim_end().to_string());
+        }
+        let content_length: usize = headers
+            .iter()
+            .find_map(|header| header.strip_prefix("Content-Length: "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        (
+            request_line.trim_end().to_string(),
+            headers,
+            String::from_utf8(body).unwrap(),
+        )
+    }
+
+    /// Start an RPC that answers requests with the `responses`, in order,
+    /// and passes the requests that it received on to the returned channel.
+    fn mock_rpc(responses: Vec<(u16, Value)>) -> (String, Receiver<(String, Vec<String>, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/forward", listener.local_addr().unwrap());
+        let (sender, receiver) = unbounded();
+        thread::spawn(move || {
+            for (status, response) in responses {
+                let (mut stream, _addr) = listener.accept().unwrap();
+                let (request_line, headers, body) = read_request(&stream);
+                sender
+                    .send((request_line, headers, serde_json::from_str(&body).unwrap()))
+                    .unwrap();
+                let response = response.to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        (url, receiver)
+    }
+
+    fn forwarded(log_receiver: &Receiver<MevMsg>) -> ForwardedTransaction {
+        match log_receiver.recv_timeout(Duration::from_secs(10)) {
+            Ok(MevMsg::ForwardedTransaction(forwarded)) => forwarded,
+            _ => panic!("Expected a forwarded transaction"),
+        }
+    }
+
+    #[test]
+    fn test_parse_http_url() {
+        assert_eq!(
+            parse_http_url("http://localhost:8899"),
+            Ok(HttpEndpoint {
+                authority: "localhost:8899".to_string(),
+                path: "/".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_http_url("http://forwarder/rpc"),
+            Ok(HttpEndpoint {
+                authority: "forwarder:80".to_string(),
+                path: "/rpc".to_string(),
+            })
+        );
+        assert!(parse_http_url("https://forwarder").is_err());
+        assert!(parse_http_url("http:///rpc").is_err());
+    }
+
+    #[test]
+    fn test_forward_transaction() {
+        let (url, requests) = mock_rpc(vec![
+            (503, json!({})),
+            (200, json!({"jsonrpc": "2.0", "id": 1, "result": "sig"})),
+            (
+                200,
+                json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32002, "message": "Blockhash not found"}}),
+            ),
+        ]);
+        let (log_send_channel, log_receiver) = unbounded();
+        let forwarder = TransactionForwarder::new(parse_http_url(&url).unwrap(), log_send_channel);
+
+        // The first attempt fails with a server error, we retry.
+        let tx = transaction();
+        forwarder.forward(&tx);
+        assert_eq!(
+            forwarded(&log_receiver),
+            ForwardedTransaction {
+                transaction_signature: *tx.signature(),
+                attempts: 2,
+                is_successful: true,
+                error: None,
+            }
+        );
+        for _ in 0..2 {
+            let (request_line, headers, body) = requests.recv().unwrap();
+            assert_eq!(request_line, "POST /forward HTTP/1.1");
+            assert!(headers.contains(&"Content-Type: application/json".to_string()));
+            assert_eq!(body["method"], "sendTransaction");
+            assert_eq!(body["params"][1]["encoding"], "base58");
+            let sent: VersionedTransaction = bincode::deserialize(
+                &bs58::decode(body["params"][0].as_str().unwrap())
+                    .into_vec()
+                    .unwrap(),
+            )
+            .unwrap();
+            assert_eq!(sent.signatures, vec![*tx.signature()]);
+        }
+
+        // A transaction that the RPC rejects is not retried.
+        let tx = transaction();
+        forwarder.forward(&tx);
+        let forwarded = forwarded(&log_receiver);
+        assert_eq!(forwarded.attempts, 1);
+        assert!(!forwarded.is_successful);
+        assert!(forwarded.error.unwrap().contains("Blockhash not found"));
+    }
+
+    #[test]
+    fn test_forward_does_not_block_when_rpc_stalls() {
+        // The RPC accepts connections, but never responds.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut streams = Vec::new();
+            for stream in listener.incoming() {
+                streams.push(stream);
+            }
+        });
+        let (log_send_channel, log_receiver) = unbounded();
+        let forwarder = TransactionForwarder::new(parse_http_url(&url).unwrap(), log_send_channel);
+
+        let start = Instant::now();
+        for _ in 0..FORWARD_QUEUE_SIZE + 10 {
+            forwarder.forward(&transaction());
+        }
+        assert!(start.elapsed() < FORWARD_TIMEOUT);
+
+        // The thread is busy with at most one transaction, so at least nine
+        // did not fit in the queue.
+        let dropped: Vec<ForwardedTransaction> = log_receiver
+            .try_iter()
+            .map(|msg| match msg {
+                MevMsg::ForwardedTransaction(forwarded) => forwarded,
+                _ => panic!("Expected a forwarded transaction"),
+            })
+            .collect();
+        assert!(dropped.len() >= 9);
+        for forwarded in dropped {
+            assert_eq!(forwarded.attempts, 0);
+            assert_eq!(forwarded.error.unwrap(), "the forward queue is full");
+        }
+    }
+}
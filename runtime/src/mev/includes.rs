@@ -0,0 +1,335 @@
+//! The `include` directive of the config: files with `[[orca_account]]`
+//! entries, e.g. a pool list that several validators share, that we merge into
+//! the config before deserializing it.
+
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use toml::{value::Table, Value};
+
+use super::utils::expand_path;
+
+/// The `[[orca_account]]` entries that we collected so far.
+#[derive(Default)]
+struct IncludedPools {
+    // The files that include the one we are reading, the config first, to
+    // detect cycles.
+    stack: Vec<PathBuf>,
+
+    // The file that each pool address is listed in.
+    files_by_address: HashMap<String, PathBuf>,
+
+    pools: Vec<Value>,
+}
+
+impl IncludedPools {
+    /// Take the `orca_account` entries out of `table`, which is `file`.
+    fn take_pools(&mut self, file: &Path, table: &mut Table) -> Result<(), String> {
+        let pools = match table.remove("orca_account") {
+            Some(Value::Array(pools)) => pools,
+            Some(_) => return Err(format!("{}: orca_account is not a list", file.display())),
+            None => return Ok(()),
+        };
+        for pool in &pools {
+            // A pool without an address is left to the deserializer.
+            let address = match pool.get("address").and_then(Value::as_str) {
+                Some(address) => address,
+                None => continue,
+            };
+            match self.files_by_address.get(address) {
+                Some(other_file) if other_file != file => {
+                    return Err(format!(
+                        "Pool {} is listed in both {} and {}",
+                        address,
+                        other_file.display(),
+                        file.display()
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    self.files_by_address
+                        .insert(address.to_owned(), file.to_owned());
+                }
+            }
+        }
+        self.pools.extend(pools);
+        Ok(())
+    }
+
+    /// Collect the pools of the file at `path`, and of the files that it
+    /// includes, depth first.
+    fn include(&mut self, path: &Path) -> Result<(), String> {
+        let file = path
+            .canonicalize()
+            .map_err(|err| format!("Could not open include {}: {}", path.display(), err))?;
+        if let Some(idx) = self.stack.iter().position(|other| *other == file) {
+            let cycle: Vec<String> = self.stack[idx..]
+                .iter()
+                .chain(std::iter::once(&file))
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(format!("Include cycle: {}", cycle.join(" -> ")));
+        }
+        let included_str = read_to_string(&file)
+            .map_err(|err| format!("Could not read include {}: {}", file.display(), err))?;
+        let mut table: Table = toml::from_str(&included_str)
+            .map_err(|err| format!("Could not parse include {}: {}", file.display(), err))?;
+        let includes = table
+            .remove("include")
+            .map(|includes| include_paths(&file, includes))
+            .transpose()?;
+        self.take_pools(&file, &mut table)?;
+        if let Some(key) = table.keys().next() {
+            return Err(format!(
+                "{}: {} is not allowed in an include, only orca_account and include are",
+                file.display(),
+                key
+            ));
+        }
+
+        self.stack.push(file);
+        for include in includes.into_iter().flatten() {
+            self.include(&include)?;
+        }
+        self.stack.pop();
+        Ok(())
+    }
+}
+
+/// The paths in the `include` of `file`. Relative paths are relative to the
+/// directory of `file`, and we expand `~` and `${VAR}` like in `log_path`.
+fn include_paths(file: &Path, includes: Value) -> Result<Vec<PathBuf>, String> {
+    let includes = match includes {
+        Value::Array(includes) => includes,
+        _ => return Err(format!("{}: include is not a list", file.display())),
+    };
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+    includes
+        .into_iter()
+        .map(|include| {
+            let include = include
+                .as_str()
+                .ok_or_else(|| format!("{}: include {} is not a path", file.display(), include))?;
+            let include = expand_path(Path::new(include))
+                .map_err(|err| format!("{}: invalid include: {}", file.display(), err))?;
+            Ok(dir.join(include))
+        })
+        .collect()
+}
+
+/// Merge the `[[orca_account]]` entries of the files in the `include` of the
+/// config in `config_str`, which was read from `config_path`, into the config.
+/// The entries of the config come first, then those of its includes in order,
+/// where an include is followed by what it includes itself. A pool address may
+/// only be in one file. Returns `None` when the config includes nothing, or
+/// is not valid TOML, then `config_str` is the config as it is.
+pub fn merge_includes(config_path: &Path, config_str: &str) -> Result<Option<String>, String> {
+    let mut config: Table = match toml::from_str(config_str) {
+        Ok(config) => config,
+        Err(_) => return Ok(None),
+    };
+    let includes = match config.remove("include") {
+        Some(includes) => includes,
+        None => return Ok(None),
+    };
+    let file = config_path
+        .canonicalize()
+        .map_err(|err| format!("Could not open config {}: {}", config_path.display(), err))?;
+    let includes = include_paths(&file, includes)?;
+
+    let mut included = IncludedPools::default();
+    included.take_pools(&file, &mut config)?;
+    included.stack.push(file);
+    for include in includes {
+        included.include(&include)?;
+    }
+    config.insert("orca_account".to_owned(), Value::Array(included.pools));
+    // As a `Value`, the tables go after the other values, as TOML needs them.
+    toml::to_string(&Value::Table(config))
+        .map(Some)
+        .map_err(|err| format!("Could not merge the includes: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir, write};
+
+    use solana_sdk::pubkey::Pubkey;
+
+    use crate::mev::config::read_mev_config;
+
+    /// An `[[orca_account]]` entry for `address`.
+    fn orca_account(address: &Pubkey) -> String {
+        format!(
+            r#"
+[[orca_account]]
+address = '{}'
+pool_a_account = '{}'
+pool_b_account = '{}'
+pool_mint = '{}'
+pool_fee = '{}'
+"#,
+            address,
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique()
+        )
+    }
+
+    /// A config that includes `includes`, with the pool `address`.
+    fn config(includes: &[&str], address: &Pubkey) -> String {
+        let includes: Vec<String> = includes
+            .iter()
+            .map(|include| format!("'{}'", include))
+            .collect();
+        format!(
+            r#"
+config_version = 1
+log_path = '/tmp/mev.log'
+watched_programs = []
+mev_path = []
+minimum_profit = {{}}
+include = [{}]
+{}
+"#,
+            includes.join(", "),
+            orca_account(address)
+        )
+    }
+
+    #[test]
+    fn test_nested_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let pools = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        create_dir(dir.path().join("pools")).unwrap();
+        let config_path = dir.path().join("mev.toml");
+        write(
+            &config_path,
+            config(&["pools/orca.toml", "pools/custom.toml"], &pools[0]),
+        )
+        .unwrap();
+        // Relative to the directory of the including file.
+        write(
+            dir.path().join("pools/orca.toml"),
+            format!(
+                "include = ['nested/more.toml']\n{}",
+                orca_account(&pools[1])
+            ),
+        )
+        .unwrap();
+        create_dir(dir.path().join("pools/nested")).unwrap();
+        write(
+            dir.path().join("pools/nested/more.toml"),
+            orca_account(&pools[2]),
+        )
+        .unwrap();
+        write(
+            dir.path().join("pools/custom.toml"),
+            orca_account(&pools[3]),
+        )
+        .unwrap();
+
+        let config = read_mev_config(&config_path).unwrap();
+        let addresses: Vec<Pubkey> = config
+            .orca_accounts
+            .iter()
+            .map(|pool| pool.address)
+            .collect();
+        assert_eq!(addresses, pools);
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("mev.toml");
+        write(&config_path, config(&["a.toml"], &Pubkey::new_unique())).unwrap();
+        write(
+            dir.path().join("a.toml"),
+            format!(
+                "include = ['b.toml']\n{}",
+                orca_account(&Pubkey::new_unique())
+            ),
+        )
+        .unwrap();
+        write(
+            dir.path().join("b.toml"),
+            format!(
+                "include = ['a.toml']\n{}",
+                orca_account(&Pubkey::new_unique())
+            ),
+        )
+        .unwrap();
+
+        // The files are named by their canonical path.
+        let dir = dir.path().canonicalize().unwrap();
+        let file = |name: &str| dir.join(name).display().to_string();
+        let err = read_mev_config(&config_path).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Include cycle: {} -> {} -> {}",
+                file("a.toml"),
+                file("b.toml"),
+                file("a.toml")
+            )
+        );
+
+        // Including the config itself is a cycle too.
+        write(&config_path, config(&["mev.toml"], &Pubkey::new_unique())).unwrap();
+        let err = read_mev_config(&config_path).unwrap_err();
+        assert_eq!(
+            err,
+            format!(
+                "Include cycle: {} -> {}",
+                file("mev.toml"),
+                file("mev.toml")
+            )
+        );
+    }
+
+    #[test]
+    fn test_duplicate_pool_across_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = Pubkey::new_unique();
+        let config_path = dir.path().join("mev.toml");
+        write(&config_path, config(&["pools.toml"], &pool)).unwrap();
+        write(dir.path().join("pools.toml"), orca_account(&pool)).unwrap();
+
+        let err = read_mev_config(&config_path).unwrap_err();
+        assert!(
+            err.starts_with(&format!("Pool {} is listed in both ", pool)),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_include_only_has_pools() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("mev.toml");
+        write(&config_path, config(&["pools.toml"], &Pubkey::new_unique())).unwrap();
+        write(
+            dir.path().join("pools.toml"),
+            format!("mev_path = []\n{}", orca_account(&Pubkey::new_unique())),
+        )
+        .unwrap();
+
+        let err = read_mev_config(&config_path).unwrap_err();
+        assert!(
+            err.ends_with(
+                "mev_path is not allowed in an include, only orca_account and include are"
+            ),
+            "{}",
+            err
+        );
+    }
+}
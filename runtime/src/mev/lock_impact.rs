@@ -0,0 +1,198 @@
+//! How many locks `Mev::fill_tx_mev_accounts` adds to a transaction, see
+//! `Mev::lock_impact`, and the summary of the sampled transactions that we
+//! keep for the status, see `lock_impact_sampling_rate`.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::Serialize;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{MevKeys, SanitizedTransaction, MAX_TX_ACCOUNT_LOCKS},
+};
+
+use super::utils::{serialize_b58, B58Pubkey};
+
+/// How many of the most recent reports the status keeps.
+pub const LOCK_IMPACT_RECENT_REPORTS: usize = 16;
+
+/// The locks that the MEV keys of a transaction add to the locks of the
+/// transaction itself, counted like `Accounts::lock_accounts` does: a key that
+/// is locked for writing by either is not also locked read-only.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LockImpactReport {
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_signature: Signature,
+
+    /// Whether we add MEV keys to the transaction at all. When not, all other
+    /// fields only describe the transaction's own locks.
+    pub has_mev_keys: bool,
+
+    /// The keys that the transaction locks itself.
+    pub tx_locks: usize,
+
+    pub additional_readonly: usize,
+    pub additional_writable: usize,
+
+    /// Keys that the transaction locks itself, and that the MEV keys lock too,
+    /// sorted.
+    pub overlapping_keys: Vec<B58Pubkey>,
+
+    /// Keys that the transaction locks read-only, but that the MEV keys lock
+    /// for writing, sorted. These conflict with more transactions than before.
+    pub promoted_to_writable: Vec<B58Pubkey>,
+
+    /// Whether all locks together are more than `MAX_TX_ACCOUNT_LOCKS`, the
+    /// limit for the keys of a transaction itself.
+    pub exceeds_account_lock_limit: bool,
+}
+
+impl LockImpactReport {
+    /// The impact of `mev_keys` on the locks of `tx`. The MEV keys that `tx`
+    /// may already have are ignored.
+    pub fn new(tx: &SanitizedTransaction, mev_keys: Option<&MevKeys>) -> Self {
+        let tx_locks = tx.get_account_locks_unchecked();
+        let tx_readonly: HashSet<&Pubkey> = tx_locks.readonly.iter().copied().collect();
+        let tx_writable: HashSet<&Pubkey> = tx_locks.writable.iter().copied().collect();
+
+        let mut mev_readonly = HashSet::new();
+        let mut mev_writable = HashSet::new();
+        if let Some(mev_keys) = mev_keys {
+            mev_keys.get_readonly_accounts(&mut mev_readonly);
+            mev_keys.get_write_accounts(&mut mev_writable);
+        }
+
+        let writable: HashSet<&Pubkey> = tx_writable.union(&mev_writable).copied().collect();
+        let readonly: HashSet<&Pubkey> = tx_readonly
+            .union(&mev_readonly)
+            .filter(|key| !writable.contains(*key))
+            .copied()
+            .collect();
+        let still_readonly = tx_readonly
+            .iter()
+            .filter(|key| readonly.contains(*key))
+            .count();
+
+        let sorted = |keys: HashSet<&Pubkey>| {
+            let mut keys: Vec<B58Pubkey> = keys.into_iter().map(|key| B58Pubkey(*key)).collect();
+            keys.sort_by_key(|key| key.0);
+            keys
+        };
+        let overlapping_keys = sorted(
+            tx_readonly
+                .union(&tx_writable)
+                .filter(|key| mev_readonly.contains(*key) || mev_writable.contains(*key))
+                .copied()
+                .collect(),
+        );
+        let promoted_to_writable = sorted(
+            tx_readonly
+                .iter()
+                .filter(|key| !tx_writable.contains(*key) && mev_writable.contains(*key))
+                .copied()
+                .collect(),
+        );
+
+        LockImpactReport {
+            transaction_signature: *tx.signature(),
+            has_mev_keys: mev_keys.is_some(),
+            tx_locks: tx_readonly.union(&tx_writable).count(),
+            additional_readonly: readonly.len().saturating_sub(still_readonly),
+            additional_writable: writable.len().saturating_sub(tx_writable.len()),
+            overlapping_keys,
+            promoted_to_writable,
+            exceeds_account_lock_limit: readonly.len() + writable.len() > MAX_TX_ACCOUNT_LOCKS,
+        }
+    }
+
+    /// All keys that we lock for the transaction, with its MEV keys.
+    pub fn total_locks(&self) -> usize {
+        self.tx_locks + self.additional_readonly + self.additional_writable
+            - self.promoted_to_writable.len()
+    }
+}
+
+/// The lock impact of the sampled transactions, since the log thread started.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LockImpactStatus {
+    pub sampled_transactions: u64,
+
+    /// The sampled transactions that we add MEV keys to.
+    pub with_mev_keys: u64,
+
+    /// The sampled transactions whose locks exceed the account lock limit with
+    /// their MEV keys.
+    pub exceeding_account_lock_limit: u64,
+
+    /// Sums over the sampled transactions.
+    pub additional_readonly: u64,
+    pub additional_writable: u64,
+
+    pub max_total_locks: usize,
+
+    /// The most recent reports, oldest first.
+    pub recent: VecDeque<LockImpactReport>,
+}
+
+impl LockImpactStatus {
+    pub fn record(&mut self, report: LockImpactReport) {
+        self.sampled_transactions += 1;
+        if report.has_mev_keys {
+            self.with_mev_keys += 1;
+        }
+        if report.exceeds_account_lock_limit {
+            self.exceeding_account_lock_limit += 1;
+        }
+        self.additional_readonly += report.additional_readonly as u64;
+        self.additional_writable += report.additional_writable as u64;
+        self.max_total_locks = self.max_total_locks.max(report.total_locks());
+        if self.recent.len() == LOCK_IMPACT_RECENT_REPORTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(report);
+    }
+}
+
+/// Picks `rate` of the transactions that it sees, evenly spread, e.g. every
+/// tenth one for a rate of 0.1.
+#[derive(Debug)]
+pub struct LockImpactSampler {
+    rate: f64,
+    seen: AtomicU64,
+}
+
+impl LockImpactSampler {
+    pub fn new(rate: f64) -> Self {
+        LockImpactSampler {
+            rate,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether to sample the next transaction.
+    pub fn sample(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        ((seen + 1) as f64 * self.rate) as u64 > (seen as f64 * self.rate) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampler_spreads_samples_evenly() {
+        let sampled = |rate: f64| -> Vec<bool> {
+            let sampler = LockImpactSampler::new(rate);
+            (0..10).map(|_| sampler.sample()).collect()
+        };
+        assert!(sampled(0.0).iter().all(|sampled| !sampled));
+        assert!(sampled(1.0).iter().all(|sampled| *sampled));
+        let every_fifth = sampled(0.2);
+        assert_eq!(every_fifth.iter().filter(|sampled| **sampled).count(), 2);
+        assert!(every_fifth[4] && every_fifth[9]);
+    }
+}
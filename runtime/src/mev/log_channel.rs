@@ -0,0 +1,230 @@
+//! The channel to the MEV log thread. By default it is unbounded, with
+//! `log_channel_capacity` it is bounded, and `log_channel_policy` decides what
+//! happens to a message when it is full.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crossbeam_channel::{bounded, unbounded, Receiver, SendError, Sender, TrySendError};
+use serde::{Deserialize, Serialize};
+
+use super::logging::MevMsg;
+
+/// What we do with a message for the log thread when its channel is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogChannelPolicy {
+    /// Wait until the log thread made room, which stalls the banking thread
+    /// that logs.
+    Block,
+
+    /// Drop the oldest message in the channel to make room.
+    DropOldest,
+
+    /// Drop the message that does not fit.
+    DropNewest,
+}
+
+impl Default for LogChannelPolicy {
+    fn default() -> Self {
+        LogChannelPolicy::Block
+    }
+}
+
+/// Sends messages to the log thread, following the `LogChannelPolicy` when the
+/// channel is full. `MevMsg::Exit` and `MevMsg::Paths` are never dropped, they
+/// always wait for room.
+#[derive(Debug, Clone)]
+pub struct MevLogSender {
+    sender: Sender<MevMsg>,
+
+    // Only with `DropOldest`, to take the oldest message out of the channel.
+    receiver: Option<Receiver<MevMsg>>,
+
+    policy: LogChannelPolicy,
+
+    dropped: DroppedLogMessages,
+}
+
+/// The messages that we dropped since the log thread last reported them,
+/// shared between all clones of a `MevLogSender`.
+#[derive(Debug, Clone, Default)]
+pub struct DroppedLogMessages(Arc<AtomicU64>);
+
+impl DroppedLogMessages {
+    fn record(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of messages that we dropped since the last call.
+    pub fn take(&self) -> u64 {
+        self.0.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// The log thread must see the exit, and the paths of a reload, which the
+/// messages after it refer to.
+fn is_never_dropped(msg: &MevMsg) -> bool {
+    matches!(msg, MevMsg::Exit | MevMsg::Paths(_))
+}
+
+impl From<Sender<MevMsg>> for MevLogSender {
+    /// Send to a channel of our own, e.g. in tests, blocking when it is full.
+    fn from(sender: Sender<MevMsg>) -> Self {
+        MevLogSender {
+            sender,
+            receiver: None,
+            policy: LogChannelPolicy::Block,
+            dropped: DroppedLogMessages::default(),
+        }
+    }
+}
+
+impl MevLogSender {
+    /// A channel to the log thread that holds at most `capacity` messages, or
+    /// any number without a capacity, in which case the policy never applies.
+    pub fn channel(
+        capacity: Option<usize>,
+        policy: LogChannelPolicy,
+    ) -> (MevLogSender, Receiver<MevMsg>) {
+        let (sender, receiver) = match capacity {
+            Some(capacity) => bounded(capacity),
+            None => unbounded(),
+        };
+        let log_sender = MevLogSender {
+            sender,
+            // Holding a receiver keeps the channel open after the log thread
+            // exits, so we only do so when we have to.
+            receiver: (policy == LogChannelPolicy::DropOldest).then(|| receiver.clone()),
+            policy,
+            dropped: DroppedLogMessages::default(),
+        };
+        (log_sender, receiver)
+    }
+
+    /// Send `msg` to the log thread. A dropped message is not an error, it is
+    /// counted, see `dropped`.
+    pub fn send(&self, msg: MevMsg) -> Result<(), SendError<MevMsg>> {
+        if self.policy == LogChannelPolicy::Block || is_never_dropped(&msg) {
+            return self.sender.send(msg);
+        }
+        let mut msg = msg;
+        loop {
+            match self.sender.try_send(msg) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Disconnected(msg)) => return Err(SendError(msg)),
+                Err(TrySendError::Full(rejected)) => {
+                    // We only hold a receiver with `DropOldest`.
+                    let receiver = match &self.receiver {
+                        Some(receiver) => receiver,
+                        None => {
+                            self.dropped.record();
+                            return Ok(());
+                        }
+                    };
+                    match receiver.try_recv() {
+                        // We must not lose these, so we put it back and drop
+                        // our message instead.
+                        Ok(oldest) if is_never_dropped(&oldest) => {
+                            self.dropped.record();
+                            return self.sender.send(oldest);
+                        }
+                        Ok(_oldest) => {
+                            self.dropped.record();
+                        }
+                        // The log thread, or another sender, emptied the
+                        // channel in the meantime.
+                        Err(_) => {}
+                    }
+                    msg = rejected;
+                }
+            }
+        }
+    }
+
+    pub fn dropped(&self) -> DroppedLogMessages {
+        self.dropped.clone()
+    }
+}
+
+/// The line that we log for messages that we had to drop.
+pub fn dropped_log_messages_event(count: u64) -> String {
+    format!("{{\"event\":\"dropped_log_messages\",\"count\":{}}}", count)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::clock::Slot;
+
+    use super::*;
+
+    /// The slots of the ticks in the channel, in order.
+    fn received_ticks(receiver: &Receiver<MevMsg>) -> Vec<Slot> {
+        receiver
+            .try_iter()
+            .map(|msg| match msg {
+                MevMsg::Tick(slot, _slot_time) => slot,
+                _ => panic!("Expected only ticks"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_drop_newest() {
+        // Nobody receives until we are done, like a log thread stuck on a
+        // slow disk.
+        let (sender, receiver) = MevLogSender::channel(Some(2), LogChannelPolicy::DropNewest);
+        for slot in 1..=5 {
+            sender.send(MevMsg::Tick(slot, 0)).unwrap();
+        }
+        assert_eq!(received_ticks(&receiver), vec![1, 2]);
+        assert_eq!(sender.clone().dropped().take(), 3);
+        assert_eq!(sender.dropped().take(), 0);
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let (sender, receiver) = MevLogSender::channel(Some(2), LogChannelPolicy::DropOldest);
+        for slot in 1..=5 {
+            sender.send(MevMsg::Tick(slot, 0)).unwrap();
+        }
+        assert_eq!(received_ticks(&receiver), vec![4, 5]);
+        assert_eq!(sender.dropped().take(), 3);
+
+        // The exit survives a full channel, at the cost of the newer message.
+        sender.send(MevMsg::Exit).unwrap();
+        sender.send(MevMsg::Tick(6, 0)).unwrap();
+        sender.send(MevMsg::Tick(7, 0)).unwrap();
+        assert!(matches!(receiver.try_recv(), Ok(MevMsg::Tick(6, 0))));
+        assert!(matches!(receiver.try_recv(), Ok(MevMsg::Exit)));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(sender.dropped().take(), 1);
+
+        // So do the paths of a reload.
+        sender.send(MevMsg::Paths(vec![])).unwrap();
+        sender.send(MevMsg::Tick(8, 0)).unwrap();
+        sender.send(MevMsg::Tick(9, 0)).unwrap();
+        assert!(matches!(receiver.try_recv(), Ok(MevMsg::Tick(8, 0))));
+        assert!(matches!(receiver.try_recv(), Ok(MevMsg::Paths(_))));
+        assert_eq!(sender.dropped().take(), 1);
+    }
+
+    #[test]
+    fn test_block() {
+        let (sender, receiver) = MevLogSender::channel(Some(1), LogChannelPolicy::Block);
+        sender.send(MevMsg::Tick(1, 0)).unwrap();
+        let blocked_sender = sender.clone();
+        let blocked = std::thread::spawn(move || blocked_sender.send(MevMsg::Tick(2, 0)).unwrap());
+        // The second tick waits for the first one to be received.
+        assert!(matches!(receiver.recv(), Ok(MevMsg::Tick(1, 0))));
+        blocked.join().unwrap();
+        assert_eq!(received_ticks(&receiver), vec![2]);
+        assert_eq!(sender.dropped().take(), 0);
+
+        // Without a log thread, sending fails rather than blocks.
+        drop(receiver);
+        assert!(sender.send(MevMsg::Tick(3, 0)).is_err());
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Utc,
+};
+use serde::{Deserialize, Serialize};
+use zstd::{stream::read::Decoder, zstd_safe, Encoder};
+
+use super::utils::expand_path;
+
+/// First bytes of every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogCompression {
+    /// Write JSON lines as they are.
+    None,
+
+    /// Write the JSON lines as a sequence of zstd frames.
+    Zstd,
+}
+
+impl Default for LogCompression {
+    fn default() -> Self {
+        LogCompression::None
+    }
+}
+
+/// The MEV log file that the log thread writes to.
+pub enum MevLogWriter {
+    Plain(File),
+
+    /// We end the current frame at every flush point, so that a reader can
+    /// decompress everything up to the last flush point, even when we crash
+    /// before we finish the frame that follows it. `encoder` is only `None`
+    /// while we replace it.
+    Zstd {
+        encoder: Option<Encoder<'static, File>>,
+        has_pending_lines: bool,
+    },
+}
+
+impl MevLogWriter {
+    /// Open the log at `path` to append to it, and create it and its parent
+    /// directories if they do not exist.
+    pub fn open(path: &Path, compression: LogCompression) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        match compression {
+            LogCompression::None => Ok(MevLogWriter::Plain(file)),
+            LogCompression::Zstd => Ok(MevLogWriter::Zstd {
+                encoder: Some(Encoder::new(file, 0)?),
+                has_pending_lines: false,
+            }),
+        }
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        match self {
+            MevLogWriter::Plain(file) => writeln!(file, "{}", line),
+            MevLogWriter::Zstd {
+                encoder,
+                has_pending_lines,
+            } => {
+                let encoder = encoder.as_mut().expect("Only taken in flush_point");
+                writeln!(encoder, "{}", line)?;
+                *has_pending_lines = true;
+                Ok(())
+            }
+        }
+    }
+
+    /// Make everything written so far readable from the file. With zstd, this
+    /// ends the current frame, so avoid doing it for every line.
+    pub fn flush_point(&mut self) -> io::Result<()> {
+        match self {
+            MevLogWriter::Plain(file) => file.flush(),
+            MevLogWriter::Zstd {
+                encoder,
+                has_pending_lines,
+            } => {
+                if !*has_pending_lines {
+                    return Ok(());
+                }
+                let file = encoder.take().expect("Only taken here").finish()?;
+                *encoder = Some(Encoder::new(file, 0)?);
+                *has_pending_lines = false;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The `log_path` from the config. It may contain strftime-style fields, e.g.
+/// `/var/log/mev/%Y-%m-%d.log`, which we render in UTC, after we expanded `~`
+/// and `${VAR}`, see `expand_path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogPathTemplate {
+    template: PathBuf,
+    is_templated: bool,
+}
+
+impl LogPathTemplate {
+    pub fn new(template: &Path) -> Result<Self, String> {
+        let template =
+            &expand_path(template).map_err(|err| format!("Invalid log path: {}", err))?;
+        let is_templated = template.to_string_lossy().contains('%');
+        if is_templated {
+            let template_str = template
+                .to_str()
+                .ok_or_else(|| format!("log path {:?} with fields is not valid UTF-8", template))?;
+            if StrftimeItems::new(template_str).any(|item| matches!(item, Item::Error)) {
+                return Err(format!("log path {} has an invalid field", template_str));
+            }
+        }
+        Ok(LogPathTemplate {
+            template: template.to_owned(),
+            is_templated,
+        })
+    }
+
+    /// The path of the log at `now`.
+    pub fn render(&self, now: DateTime<Utc>) -> PathBuf {
+        match self.template.to_str() {
+            Some(template) if self.is_templated => PathBuf::from(now.format(template).to_string()),
+            _ => self.template.clone(),
+        }
+    }
+}
+
+/// A `MevLogWriter` for the file that a `LogPathTemplate` renders to. When the
+/// rendered path changes, e.g. at the start of a new day, `roll_over` moves on
+/// to the new file.
+pub struct MevLogFile {
+    template: LogPathTemplate,
+    compression: LogCompression,
+    path: PathBuf,
+    writer: MevLogWriter,
+}
+
+impl MevLogFile {
+    pub fn open(
+        template: &Path,
+        compression: LogCompression,
+        now: DateTime<Utc>,
+    ) -> Result<Self, String> {
+        let template = LogPathTemplate::new(template)?;
+        let path = template.render(now);
+        let writer = open_writer(&path, compression)?;
+        Ok(MevLogFile {
+            template,
+            compression,
+            path,
+            writer,
+        })
+    }
+
+    /// The file that we currently write to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_line(line)
+    }
+
+    pub fn flush_point(&mut self) -> io::Result<()> {
+        self.writer.flush_point()
+    }
+
+    /// Switch to the file that the template renders to at `now`, if that is
+    /// another one than the current file. Returns whether we switched. On
+    /// error, we keep writing to the current file.
+    pub fn roll_over(&mut self, now: DateTime<Utc>) -> Result<bool, String> {
+        let path = self.template.render(now);
+        if path == self.path {
+            return Ok(false);
+        }
+        let writer = open_writer(&path, self.compression)?;
+        self.writer.flush_point().map_err(|err| {
+            format!(
+                "Could not flush MEV log file {}: {}",
+                self.path.display(),
+                err
+            )
+        })?;
+        self.writer = writer;
+        self.path = path;
+        Ok(true)
+    }
+}
+
+fn open_writer(path: &Path, compression: LogCompression) -> Result<MevLogWriter, String> {
+    MevLogWriter::open(path, compression)
+        .map_err(|err| format!("Could not open MEV log file {}: {}", path.display(), err))
+}
+
+/// Read the MEV log at `path`, and decompress it when it is a zstd log. Of a
+/// zstd log we recover every frame that we can. Of a frame that we did not
+/// finish writing, e.g. because the validator crashed, we keep the complete
+/// lines that it holds.
+pub fn read_mev_log(path: &Path) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    let text = if bytes.starts_with(&ZSTD_MAGIC) {
+        decompress_frames(&bytes)
+    } else {
+        bytes
+    };
+    String::from_utf8(text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn decompress_frames(mut bytes: &[u8]) -> Vec<u8> {
+    let mut text = Vec::new();
+    while !bytes.is_empty() {
+        match zstd_safe::find_frame_compressed_size(bytes) {
+            Ok(frame_size) => {
+                // A complete frame can still be corrupt, in which case we keep
+                // what it decodes to until that point, like for an incomplete
+                // frame.
+                if Decoder::new(&bytes[..frame_size])
+                    .and_then(|mut decoder| decoder.read_to_end(&mut text))
+                    .is_err()
+                {
+                    truncate_to_last_line(&mut text);
+                }
+                bytes = &bytes[frame_size..];
+            }
+            Err(_) => {
+                // The frame is incomplete. The ones after it can be complete
+                // again, when we appended to the log after a crash.
+                let next_frame = bytes[1..]
+                    .windows(ZSTD_MAGIC.len())
+                    .position(|window| window == ZSTD_MAGIC)
+                    .map_or(bytes.len(), |position| position + 1);
+                // Reading fails at the end of the incomplete frame, but what
+                // we read until then is in `text`.
+                let _ = Decoder::new(&bytes[..next_frame])
+                    .and_then(|decoder| decoder.single_frame().read_to_end(&mut text));
+                truncate_to_last_line(&mut text);
+                bytes = &bytes[next_frame..];
+            }
+        }
+    }
+    text
+}
+
+/// Drop the incomplete line at the end of `text`, if any.
+fn truncate_to_last_line(text: &mut Vec<u8>) {
+    let len = text
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |i| i + 1);
+    text.truncate(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{NamedTempFile, TempDir};
+
+    use super::*;
+
+    #[test]
+    fn test_plain_log() {
+        let log_file = NamedTempFile::new().unwrap();
+        let mut writer = MevLogWriter::open(log_file.path(), LogCompression::None).unwrap();
+        writer.write_line("{\"event\":\"a\"}").unwrap();
+        writer.flush_point().unwrap();
+        assert_eq!(
+            fs::read_to_string(log_file.path()).unwrap(),
+            "{\"event\":\"a\"}\n"
+        );
+        assert_eq!(
+            read_mev_log(log_file.path()).unwrap(),
+            "{\"event\":\"a\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_create_parent_directories() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("mev/logs/mev.log");
+        let mut file = MevLogFile::open(&path, LogCompression::None, Utc::now()).unwrap();
+        assert_eq!(file.path(), path);
+        file.write_line("a").unwrap();
+        file.flush_point().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+    }
+
+    #[test]
+    fn test_templated_log_path_rolls_over() {
+        let dir = TempDir::new().unwrap();
+        let template = dir.path().join("mev/%Y-%m-%d.log");
+        let at = |date: &str| {
+            DateTime::parse_from_rfc3339(date)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let mut file =
+            MevLogFile::open(&template, LogCompression::Zstd, at("2022-10-01T23:59:00Z")).unwrap();
+        file.write_line("a").unwrap();
+        assert_eq!(file.roll_over(at("2022-10-01T23:59:59Z")), Ok(false));
+        file.write_line("b").unwrap();
+
+        // The day changes.
+        assert_eq!(file.roll_over(at("2022-10-02T00:00:01Z")), Ok(true));
+        file.write_line("c").unwrap();
+        file.flush_point().unwrap();
+        assert_eq!(file.path(), dir.path().join("mev/2022-10-02.log"));
+        assert_eq!(
+            read_mev_log(&dir.path().join("mev/2022-10-01.log")).unwrap(),
+            "a\nb\n"
+        );
+        assert_eq!(read_mev_log(file.path()).unwrap(), "c\n");
+    }
+
+    #[test]
+    fn test_log_path_errors() {
+        let dir = TempDir::new().unwrap();
+
+        // The parent directory cannot be created, because it is a file.
+        let parent = dir.path().join("mev");
+        fs::write(&parent, "").unwrap();
+        let path = parent.join("mev.log");
+        let err = MevLogFile::open(&path, LogCompression::None, Utc::now())
+            .err()
+            .unwrap();
+        assert!(
+            err.starts_with(&format!("Could not open MEV log file {}: ", path.display())),
+            "{}",
+            err
+        );
+
+        // The rendered path is named in the error.
+        let template = parent.join("%Y.log");
+        let now = Utc::now();
+        let err = MevLogFile::open(&template, LogCompression::None, now)
+            .err()
+            .unwrap();
+        assert!(
+            err.contains(&format!("mev/{}.log", now.format("%Y"))),
+            "{}",
+            err
+        );
+
+        let err = LogPathTemplate::new(Path::new("/var/log/mev/%Q.log")).unwrap_err();
+        assert_eq!(err, "log path /var/log/mev/%Q.log has an invalid field");
+    }
+
+    #[test]
+    fn test_recover_zstd_log_after_crash() {
+        let log_file = NamedTempFile::new().unwrap();
+        let mut writer = MevLogWriter::open(log_file.path(), LogCompression::Zstd).unwrap();
+        writer.write_line("a").unwrap();
+        writer.write_line("b").unwrap();
+        writer.flush_point().unwrap();
+        writer.write_line("c").unwrap();
+        writer.flush_point().unwrap();
+        // Flush points without lines do not add empty frames.
+        let len = fs::metadata(log_file.path()).unwrap().len();
+        writer.flush_point().unwrap();
+        assert_eq!(fs::metadata(log_file.path()).unwrap().len(), len);
+
+        // We crash in the middle of a frame, with enough data that part of it
+        // made it to the file.
+        for i in 0..100_000 {
+            writer
+                .write_line(&format!("{{\"event\":\"opportunity\",\"i\":{}}}", i))
+                .unwrap();
+        }
+        std::mem::forget(writer);
+        assert!(fs::metadata(log_file.path()).unwrap().len() > len);
+
+        let recovered = read_mev_log(log_file.path()).unwrap();
+        assert!(recovered.starts_with("a\nb\nc\n"));
+        assert!(recovered.ends_with('\n'));
+
+        // After a restart, we append frames to the log again.
+        let mut writer = MevLogWriter::open(log_file.path(), LogCompression::Zstd).unwrap();
+        writer.write_line("d").unwrap();
+        writer.flush_point().unwrap();
+        let recovered = read_mev_log(log_file.path()).unwrap();
+        assert!(recovered.starts_with("a\nb\nc\n"));
+        assert!(recovered.ends_with("}\nd\n"));
+        for line in recovered.lines().skip(3) {
+            assert!(
+                line == "d"
+                    || (line.starts_with("{\"event\":\"opportunity\"") && line.ends_with('}')),
+                "unexpected line {:?}",
+                line
+            );
+        }
+    }
+}
@@ -0,0 +1,475 @@
+//! Reads MEV logs that validators of any version wrote, for tools that analyze
+//! them, e.g. `examples/mev_log_report.rs`.
+//!
+//! Every session in a log starts with a `SessionHeader`, whose
+//! `log_schema_version` tells how to read the events that follow, up to the
+//! next header. Logs from before we wrote headers are version 1. We read every
+//! version into its own structs, and upgrade those to the records that tools
+//! work with, so that the tools do not need to know which validator wrote a
+//! log. Fields that we do not know are ignored, so that we can read logs with
+//! fields that were added without bumping the version.
+
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::Value;
+use solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature};
+use thiserror::Error;
+
+use super::{
+    arbitrage::TriggerKind,
+    log_file::read_mev_log,
+    logging::{SessionHeader, LOG_SCHEMA_VERSION},
+    utils::parse_b58_pubkey,
+};
+
+/// The schema version of logs, or the part of a log, before the first
+/// session header.
+const UNVERSIONED_LOG_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum LogReadError {
+    #[error(
+        "line {line}: the log has schema version {version}, but we can only read versions \
+        {UNVERSIONED_LOG_SCHEMA_VERSION} to {LOG_SCHEMA_VERSION}, a newer tool may read it"
+    )]
+    UnsupportedSchemaVersion { line: usize, version: u32 },
+
+    #[error("line {line}: invalid {event} event for schema version {version}: {reason}")]
+    InvalidEvent {
+        line: usize,
+        event: String,
+        version: u32,
+        reason: String,
+    },
+}
+
+/// An event of the MEV log, in the shape of the latest schema version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MevLogRecord {
+    Trigger(TriggerRecord),
+    Opportunities(Vec<OpportunityRecord>),
+    ExecutedTransaction(ExecutedTransactionRecord),
+
+    /// An event that we do not read into a record, by its name.
+    Other(String),
+}
+
+/// A transaction that touched the pools of a path, see `PrePostPoolStates`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TriggerRecord {
+    pub transaction_signature: Signature,
+    pub slot: Slot,
+    pub trigger_kind: TriggerKind,
+    pub trigger_failed: bool,
+
+    /// By pool, the balances of token A and token B before and after the
+    /// transaction.
+    pub pre_balances: BTreeMap<Pubkey, (u64, u64)>,
+    pub post_balances: BTreeMap<Pubkey, (u64, u64)>,
+}
+
+/// An opportunity on a path, see `MevOpportunityWithInput`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpportunityRecord {
+    pub path: String,
+    pub reversed: bool,
+
+    /// Per hop, the amount that went in and the amount that came out.
+    pub input_output_pairs: Vec<(u64, u64)>,
+}
+
+/// A transaction of ours that executed, see `ExecutedTransactionOutput`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutedTransactionRecord {
+    pub transaction_signature: Signature,
+    pub is_successful: bool,
+    pub possible_profit: u64,
+}
+
+fn deserialize_signature<'de, D>(deserializer: D) -> Result<Signature, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buf = String::deserialize(deserializer)?;
+    Signature::from_str(&buf).map_err(serde::de::Error::custom)
+}
+
+fn upgrade_pairs(pairs: Vec<v1::InputOutputPair>) -> Vec<(u64, u64)> {
+    pairs
+        .into_iter()
+        .map(|pair| (pair.token_in, pair.token_out))
+        .collect()
+}
+
+fn upgrade_balances(
+    pool_states: BTreeMap<String, v1::PoolState>,
+) -> Result<BTreeMap<Pubkey, (u64, u64)>, String> {
+    pool_states
+        .into_iter()
+        .map(|(address, pool_state)| {
+            Ok((
+                parse_b58_pubkey(&address)?,
+                (pool_state.pool_a_balance, pool_state.pool_b_balance),
+            ))
+        })
+        .collect()
+}
+
+/// The events as the first validators wrote them, before we wrote session
+/// headers.
+mod v1 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct PoolState {
+        pub pool_a_balance: u64,
+        pub pool_b_balance: u64,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Trigger {
+        #[serde(deserialize_with = "deserialize_signature")]
+        pub transaction_signature: Signature,
+        pub slot: Slot,
+        pub orca_pre_tx_pool: BTreeMap<String, PoolState>,
+        pub orca_post_tx_pool: BTreeMap<String, PoolState>,
+    }
+
+    impl Trigger {
+        /// We did not tell vote transactions apart yet, and they carried no
+        /// MEV keys, so every trigger was a user transaction. We did not tell
+        /// failed transactions apart either.
+        pub fn upgrade(self) -> Result<TriggerRecord, String> {
+            Ok(TriggerRecord {
+                transaction_signature: self.transaction_signature,
+                slot: self.slot,
+                trigger_kind: TriggerKind::User,
+                trigger_failed: false,
+                pre_balances: upgrade_balances(self.orca_pre_tx_pool)?,
+                post_balances: upgrade_balances(self.orca_post_tx_pool)?,
+            })
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct Path {
+        pub name: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct InputOutputPair {
+        pub token_in: u64,
+        pub token_out: u64,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Opportunity {
+        pub opportunity: Path,
+        pub input_output_pairs: Vec<InputOutputPair>,
+    }
+
+    impl Opportunity {
+        /// We only traded paths in the configured direction.
+        pub fn upgrade(self) -> OpportunityRecord {
+            OpportunityRecord {
+                path: self.opportunity.name,
+                reversed: false,
+                input_output_pairs: upgrade_pairs(self.input_output_pairs),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    pub struct ExecutedTransaction {
+        #[serde(deserialize_with = "deserialize_signature")]
+        pub transaction_signature: Signature,
+        pub is_successful: bool,
+        pub possible_profit: u64,
+    }
+
+    impl ExecutedTransaction {
+        pub fn upgrade(self) -> ExecutedTransactionRecord {
+            ExecutedTransactionRecord {
+                transaction_signature: self.transaction_signature,
+                is_successful: self.is_successful,
+                possible_profit: self.possible_profit,
+            }
+        }
+    }
+}
+
+/// The events since the first session header. Executed transactions kept the
+/// shape of version 1, apart from fields that we do not read.
+mod v2 {
+    use super::*;
+
+    pub use super::v1::ExecutedTransaction;
+
+    #[derive(Deserialize)]
+    pub struct Trigger {
+        #[serde(deserialize_with = "deserialize_signature")]
+        pub transaction_signature: Signature,
+        pub slot: Slot,
+        pub trigger_kind: TriggerKind,
+        pub trigger_failed: bool,
+        pub orca_pre_tx_pool: BTreeMap<String, v1::PoolState>,
+        pub orca_post_tx_pool: BTreeMap<String, v1::PoolState>,
+    }
+
+    impl Trigger {
+        pub fn upgrade(self) -> Result<TriggerRecord, String> {
+            Ok(TriggerRecord {
+                transaction_signature: self.transaction_signature,
+                slot: self.slot,
+                trigger_kind: self.trigger_kind,
+                trigger_failed: self.trigger_failed,
+                pre_balances: upgrade_balances(self.orca_pre_tx_pool)?,
+                post_balances: upgrade_balances(self.orca_post_tx_pool)?,
+            })
+        }
+    }
+
+    /// Only opportunities on reversed paths have `reversed`.
+    #[derive(Deserialize)]
+    pub struct Opportunity {
+        pub opportunity: v1::Path,
+        #[serde(default)]
+        pub reversed: bool,
+        pub input_output_pairs: Vec<v1::InputOutputPair>,
+    }
+
+    impl Opportunity {
+        pub fn upgrade(self) -> OpportunityRecord {
+            OpportunityRecord {
+                path: self.opportunity.name,
+                reversed: self.reversed,
+                input_output_pairs: upgrade_pairs(self.input_output_pairs),
+            }
+        }
+    }
+}
+
+fn parse<T: DeserializeOwned>(value: Value) -> Result<T, String> {
+    serde_json::from_value(value).map_err(|err| err.to_string())
+}
+
+/// Read one event of the log, whose schema `version` we support. Events other
+/// than triggers have their fields under `data`.
+fn read_event(version: u32, event: Option<&str>, mut line: Value) -> Result<MevLogRecord, String> {
+    let data = line.get_mut("data").map_or(Value::Null, Value::take);
+    let record = match (version, event) {
+        (1, None) => MevLogRecord::Trigger(parse::<v1::Trigger>(line)?.upgrade()?),
+        (_, None) => MevLogRecord::Trigger(parse::<v2::Trigger>(line)?.upgrade()?),
+        (1, Some("opportunity")) => MevLogRecord::Opportunities(
+            parse::<Vec<v1::Opportunity>>(data)?
+                .into_iter()
+                .map(v1::Opportunity::upgrade)
+                .collect(),
+        ),
+        (_, Some("opportunity")) => MevLogRecord::Opportunities(
+            parse::<Vec<v2::Opportunity>>(data)?
+                .into_iter()
+                .map(v2::Opportunity::upgrade)
+                .collect(),
+        ),
+        (1, Some("executed_transaction")) => {
+            MevLogRecord::ExecutedTransaction(parse::<v1::ExecutedTransaction>(data)?.upgrade())
+        }
+        (_, Some("executed_transaction")) => {
+            MevLogRecord::ExecutedTransaction(parse::<v2::ExecutedTransaction>(data)?.upgrade())
+        }
+        (_, Some(event)) => MevLogRecord::Other(event.to_owned()),
+    };
+    Ok(record)
+}
+
+/// The records of `log`, the lines of a MEV log, which may hold sessions of
+/// different schema versions. Session headers are not records.
+pub fn parse_log_records(log: &str) -> Result<Vec<MevLogRecord>, LogReadError> {
+    let mut version = UNVERSIONED_LOG_SCHEMA_VERSION;
+    let mut records = Vec::new();
+    for (index, text) in log.lines().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let line = index + 1;
+        let invalid =
+            |event: Option<&str>, version: u32, reason: String| LogReadError::InvalidEvent {
+                line,
+                event: event.unwrap_or("trigger").to_owned(),
+                version,
+                reason,
+            };
+        let value: Value =
+            serde_json::from_str(text).map_err(|err| invalid(None, version, err.to_string()))?;
+        let event = value
+            .get("event")
+            .and_then(Value::as_str)
+            .map(str::to_owned);
+        if event.as_deref() == Some("session") {
+            let header = value
+                .get("data")
+                .ok_or_else(|| "missing data".to_owned())
+                .and_then(|data| parse::<SessionHeader>(data.clone()))
+                .map_err(|reason| invalid(Some("session"), version, reason))?;
+            if !(UNVERSIONED_LOG_SCHEMA_VERSION..=LOG_SCHEMA_VERSION)
+                .contains(&header.log_schema_version)
+            {
+                return Err(LogReadError::UnsupportedSchemaVersion {
+                    line,
+                    version: header.log_schema_version,
+                });
+            }
+            version = header.log_schema_version;
+            continue;
+        }
+        records.push(
+            read_event(version, event.as_deref(), value)
+                .map_err(|reason| invalid(event.as_deref(), version, reason))?,
+        );
+    }
+    Ok(records)
+}
+
+/// Read the records of the MEV log at `path`, which may be compressed, see
+/// `read_mev_log`.
+pub fn read_log_records(path: &Path) -> Result<Vec<MevLogRecord>, String> {
+    let log = read_mev_log(path)
+        .map_err(|err| format!("Could not read MEV log {}: {}", path.display(), err))?;
+    parse_log_records(&log).map_err(|err| format!("{} in {}", err, path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mev::test_utils::{STETH_USDC_POOL, STSOL_USDC_POOL};
+
+    const LOG_V1: &str = include_str!("fixtures/mev_log_v1.jsonl");
+    const LOG_V2: &str = include_str!("fixtures/mev_log_v2.jsonl");
+
+    #[test]
+    fn test_schema_versions_read_into_same_records() {
+        let records = parse_log_records(LOG_V1).unwrap();
+        assert_eq!(parse_log_records(LOG_V2).unwrap(), records);
+
+        assert_eq!(records.len(), 3);
+        let trigger = match &records[0] {
+            MevLogRecord::Trigger(trigger) => trigger,
+            record => panic!("Expected a trigger, got {:?}", record),
+        };
+        assert_eq!(trigger.slot, 151_000_000);
+        assert_eq!(trigger.trigger_kind, TriggerKind::User);
+        assert!(!trigger.trigger_failed);
+        let pool = parse_b58_pubkey(STETH_USDC_POOL).unwrap();
+        assert_eq!(trigger.pre_balances[&pool], (4618233234, 6400518033));
+        assert_eq!(trigger.post_balances[&pool], (4618234234, 6400517036));
+        assert_eq!(trigger.pre_balances.len(), 3);
+        assert_eq!(
+            records[1],
+            MevLogRecord::Opportunities(vec![OpportunityRecord {
+                path: "USDC->stETH->stSOL->USDC".to_owned(),
+                reversed: false,
+                input_output_pairs: vec![
+                    (1_000_000, 721_000),
+                    (721_000, 2_950_000_000),
+                    (2_950_000_000, 1_012_345)
+                ],
+            }])
+        );
+        match &records[2] {
+            MevLogRecord::ExecutedTransaction(executed) => {
+                assert!(executed.is_successful);
+                assert_eq!(executed.possible_profit, 12_345);
+            }
+            record => panic!("Expected an executed transaction, got {:?}", record),
+        }
+    }
+
+    #[test]
+    fn test_sessions_of_different_versions() {
+        // A validator that was upgraded appends a new session to the log.
+        let log = format!("{}{}", LOG_V1, LOG_V2);
+        let records = parse_log_records(&log).unwrap();
+        assert_eq!(records.len(), 6);
+        assert_eq!(records[..3], records[3..]);
+
+        // After a header, triggers need the fields of its version.
+        let header = LOG_V2.lines().next().unwrap();
+        let trigger_v1 = LOG_V1.lines().next().unwrap();
+        assert_eq!(
+            parse_log_records(&format!("{}\n{}\n", header, trigger_v1)),
+            Err(LogReadError::InvalidEvent {
+                line: 2,
+                event: "trigger".to_owned(),
+                version: 2,
+                reason: "missing field `trigger_kind`".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields_and_events_are_tolerated() {
+        let log = format!(
+            "{}\n{}\n{}\n",
+            "{'event':'session','data':{'log_schema_version':2,'started_at':1,'host':'a'}}",
+            "{'transaction_signature':'1111111111111111111111111111111111111111111111111111111111111111',\
+            'slot':7,'trigger_kind':'vote','trigger_failed':true,'priority':3,\
+            'orca_pre_tx_pool':{},'orca_post_tx_pool':{}}",
+            "{'event':'future_event','data':{'anything':[1,2]}}",
+        )
+        .replace('\'', "\"");
+        assert_eq!(
+            parse_log_records(&log).unwrap(),
+            vec![
+                MevLogRecord::Trigger(TriggerRecord {
+                    transaction_signature: Signature::default(),
+                    slot: 7,
+                    trigger_kind: TriggerKind::Vote,
+                    trigger_failed: true,
+                    pre_balances: BTreeMap::new(),
+                    post_balances: BTreeMap::new(),
+                }),
+                MevLogRecord::Other("future_event".to_owned()),
+            ]
+        );
+        // Pools are keyed by their address.
+        let pool = parse_b58_pubkey(STSOL_USDC_POOL).unwrap();
+        let log = format!(
+            "{{\"transaction_signature\":\"{}\",\"slot\":1,\"orca_pre_tx_pool\":\
+            {{\"{}\":{{\"pool_a_balance\":1,\"pool_b_balance\":2}}}},\"orca_post_tx_pool\":{{}}}}\n",
+            Signature::default(),
+            pool
+        );
+        match &parse_log_records(&log).unwrap()[0] {
+            MevLogRecord::Trigger(trigger) => assert_eq!(trigger.pre_balances[&pool], (1, 2)),
+            record => panic!("Expected a trigger, got {:?}", record),
+        }
+    }
+
+    #[test]
+    fn test_future_schema_version() {
+        let log = format!(
+            "{}{{\"event\":\"session\",\"data\":{{\"log_schema_version\":{}}}}}\n",
+            LOG_V2,
+            LOG_SCHEMA_VERSION + 1
+        );
+        let err = parse_log_records(&log).unwrap_err();
+        assert_eq!(
+            err,
+            LogReadError::UnsupportedSchemaVersion {
+                line: 5,
+                version: LOG_SCHEMA_VERSION + 1,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "line 5: the log has schema version {}, but we can only read versions 1 to {}, \
+                a newer tool may read it",
+                LOG_SCHEMA_VERSION + 1,
+                LOG_SCHEMA_VERSION
+            )
+        );
+    }
+}
@@ -0,0 +1,2002 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use chrono::Utc;
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    clock::{Slot, UnixTimestamp},
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::SanitizedTransaction,
+};
+
+use super::{
+    arbitrage::{
+        MevOpportunityWithInput, MevPath, MevTxOutput, PathSkipReason, SkipReason, TriggerKind,
+    },
+    checkpoint::Checkpointer,
+    clock_skew::{wall_clock_ms, ClockSkewMonitor},
+    config::MevConfig,
+    config_dump::ConfigDump,
+    debounce::{AggregatedOpportunity, OpportunityDebouncer},
+    discovery::configured_and_discovered_paths,
+    failures::{ExecutionFailure, SystemicFailureEvent},
+    forwarder::ForwardedTransaction,
+    lock_impact::LockImpactReport,
+    log_channel::{dropped_log_messages_event, DroppedLogMessages, MevLogSender},
+    log_file::MevLogFile,
+    pool_state::{PoolLoadError, PoolStates},
+    pool_stats::PoolContributions,
+    reporting::{ProfitSummary, ReportedProfit},
+    status::{status_path, write_status_file, MevStatus},
+    utils::{deserialize_opt_b58, serialize_b58, serialize_opt_b58, warn_throttled, B58Pubkey},
+    watchdog::{Watchdog, WatchdogEvent},
+    LOG_THROTTLE_INTERVAL,
+};
+
+/// How many events a subscriber can lag behind before we drop events for it.
+pub const MEV_SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Version of the schema of the events that we write to the MEV log. Bump it
+/// when an event changes in a way that readers need to know about, and teach
+/// `log_reader` to upgrade the previous version.
+pub const LOG_SCHEMA_VERSION: u32 = 2;
+
+/// The first line that we write to a log file when we start logging to it, so
+/// that readers know how to read the events that follow, see `log_reader`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SessionHeader {
+    pub log_schema_version: u32,
+
+    /// When we started logging to the file, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub started_at: UnixTimestamp,
+
+    /// The validator that writes the log, when we know it, see
+    /// `ValidatorAttribution`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    #[serde(deserialize_with = "deserialize_opt_b58")]
+    pub validator_identity: Option<Pubkey>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    #[serde(deserialize_with = "deserialize_opt_b58")]
+    pub vote_account: Option<Pubkey>,
+}
+
+impl SessionHeader {
+    pub fn new(started_at: UnixTimestamp, validator: Option<&ValidatorAttribution>) -> Self {
+        SessionHeader {
+            log_schema_version: LOG_SCHEMA_VERSION,
+            started_at,
+            validator_identity: validator.map(|validator| validator.identity),
+            vote_account: validator.and_then(|validator| validator.vote_account),
+        }
+    }
+
+    pub fn to_event(&self) -> String {
+        format!(
+            "{{\"event\":\"session\",\"data\":{}}}",
+            serde_json::to_string(self).expect("Constructed by us, should never fail")
+        )
+    }
+}
+
+/// MevLog saves the `log_send_channel` channel, where it can be passed and
+/// cloned in the `Bank` structure. We spawn a thread on the initialization of
+/// the struct to listen and log data in `log_path`.
+#[derive(Debug)]
+pub struct MevLog {
+    pub thread_handle: JoinHandle<()>,
+    pub log_send_channel: MevLogSender,
+    subscribers: Arc<MevSubscribers>,
+}
+
+pub enum MevMsg {
+    Log(PrePostPoolStates),
+    Opportunities(Vec<MevTxOutput>),
+    ExecutedTransaction(ExecutedTransactionOutput),
+    CompetitorArbitrages(Vec<CompetitorArbitrage>),
+    /// Paths that had no opportunity after a trigger, with the reason, see
+    /// `log_skipped_paths`.
+    PathsSkipped(Vec<PathSkipped>),
+    DroppedTransaction(DroppedTransaction),
+    PoolConfigMismatch(PoolConfigMismatch),
+    /// Pools that we could not load from the accounts of a transaction, so we
+    /// did not evaluate it.
+    PoolLoadErrors(Vec<PoolLoadError>),
+    /// A transaction that we sent, or failed to send, to the fallback RPC.
+    ForwardedTransaction(ForwardedTransaction),
+    /// We paused crafting because of systemic failures, or resumed it.
+    SystemicFailure(SystemicFailureEvent),
+    /// Sent by the banking stage once per slot in which we are leader, with
+    /// the bank's estimated time of the slot, so the watchdog can tell how
+    /// long it has been silent, and we can compare the slot time with our
+    /// wall clock.
+    Tick(Slot, UnixTimestamp),
+    /// Write the status to `<log_path>.status.json`, with the in-flight
+    /// exposure per token mint at the time of the request, see
+    /// `Mev::write_status`.
+    WriteStatus(HashMap<Pubkey, u64>),
+    /// The lock impact of a sampled transaction, for the status, see
+    /// `lock_impact_sampling_rate`.
+    LockImpact(LockImpactReport),
+    /// The config that a `Mev` runs with, see `Mev::log_config`.
+    Config(Box<ConfigDump>),
+    /// The paths of a reloaded config, see `Mev::try_reload`. The messages
+    /// that follow refer to paths by their index in these.
+    Paths(Vec<MevPath>),
+    Exit,
+}
+
+/// A transaction by someone else that traded through all pools of one of our
+/// paths, with a profit.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct CompetitorArbitrage {
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_signature: Signature,
+
+    pub slot: Slot,
+
+    // Index from the Path vector.
+    #[serde(skip)]
+    pub path_idx: usize,
+
+    /// Mint of the token that goes into the first hop of the path, the profit
+    /// is in this token.
+    #[serde(serialize_with = "serialize_b58")]
+    pub input_mint: Pubkey,
+
+    /// Profit of the transaction, estimated from the pool balances before and
+    /// after it.
+    pub estimated_profit: u64,
+
+    /// Whether we found an opportunity on the same path earlier in the slot.
+    pub evaluated_path: bool,
+}
+
+/// A `CompetitorArbitrage` as we write it to the log.
+#[derive(Serialize)]
+struct CompetitorArbitrageEvent<'a> {
+    opportunity: &'a MevPath,
+
+    #[serde(flatten)]
+    arbitrage: &'a CompetitorArbitrage,
+}
+
+/// A path that had no opportunity after a trigger.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub struct PathSkipped {
+    // Index from the Path vector.
+    #[serde(skip)]
+    pub path_idx: usize,
+
+    /// Whether we evaluated the path in the reverse direction.
+    pub reversed: bool,
+
+    #[serde(flatten)]
+    pub reason: PathSkipReason,
+}
+
+/// A `PathSkipped` as we write it to the log.
+#[derive(Serialize)]
+struct PathSkippedEvent<'a> {
+    opportunity: &'a MevPath,
+
+    #[serde(flatten)]
+    skipped: &'a PathSkipped,
+}
+
+/// An `AggregatedOpportunity` as we write it to the log.
+#[derive(Serialize)]
+struct AggregatedOpportunityEvent<'a> {
+    opportunity: &'a MevPath,
+
+    #[serde(flatten)]
+    aggregate: &'a AggregatedOpportunity,
+}
+
+/// A MEV transaction that we crafted, but then decided not to execute.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct DroppedTransaction {
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_signature: Signature,
+
+    /// The first signature of the transaction that created the opportunity.
+    #[serde(serialize_with = "serialize_b58")]
+    pub trigger_signature: Signature,
+
+    pub reason: SkipReason,
+}
+
+/// A pool whose token accounts hold other mints than the config says. We do
+/// not trust the config for such a pool, so we stop trading through it.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct PoolConfigMismatch {
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub config_pool_a_mint: Pubkey,
+    #[serde(serialize_with = "serialize_b58")]
+    pub config_pool_b_mint: Pubkey,
+
+    /// The mints of the pool's token accounts, as loaded from the chain.
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool_a_mint: Pubkey,
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool_b_mint: Pubkey,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExecutedTransactionOutput {
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_hash: Hash,
+    #[serde(serialize_with = "serialize_b58")]
+    pub transaction_signature: Signature,
+
+    pub is_successful: bool,
+    pub possible_profit: u64,
+
+    /// The pools that the transaction traded through, in path order.
+    pub pools: Vec<B58Pubkey>,
+
+    /// Time from when the batch with the trigger was executed, at which point
+    /// we detected the opportunity, until this transaction was executed. It is
+    /// measured with a monotonic clock, so clock skew does not affect it.
+    pub detection_to_execution_us: u64,
+
+    /// The bank's estimated time of the slot that the transaction executed
+    /// in, in seconds since the Unix epoch.
+    pub slot_time: UnixTimestamp,
+
+    /// Why the transaction failed, when the bank tells us.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ExecutionFailure>,
+
+    /// The mint of `possible_profit`, and its value in the reporting mint,
+    /// with a `reporting` config.
+    #[serde(flatten)]
+    pub reported_profit: Option<ReportedProfit>,
+}
+
+/// The validator that runs the MEV module, so that logs from different
+/// validators can be told apart when aggregating them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidatorAttribution {
+    pub identity: Pubkey,
+    pub vote_account: Option<Pubkey>,
+}
+
+/// An `ExecutedTransactionOutput` as we write it to the log, attributed to the
+/// validator that executed it, if we know it.
+#[derive(Serialize)]
+struct ExecutedTransactionEvent<'a> {
+    #[serde(flatten)]
+    output: &'a ExecutedTransactionOutput,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    validator_identity: Option<Pubkey>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    vote_account: Option<Pubkey>,
+}
+
+impl<'a> ExecutedTransactionEvent<'a> {
+    fn new(
+        output: &'a ExecutedTransactionOutput,
+        validator: Option<&ValidatorAttribution>,
+    ) -> Self {
+        ExecutedTransactionEvent {
+            output,
+            validator_identity: validator.map(|validator| validator.identity),
+            vote_account: validator.and_then(|validator| validator.vote_account),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrePostPoolStates {
+    /// Transaction hash which triggered the MEV.
+    #[serde(serialize_with = "serialize_b58")]
+    pub(super) transaction_hash: Hash,
+
+    /// The first signature of the transaction.
+    ///
+    /// Block explorers identify transactions by the first signature, not by the
+    /// transaction hash, so we also keep the signature for cross-referencing.
+    #[serde(serialize_with = "serialize_b58")]
+    pub(super) transaction_signature: Signature,
+
+    pub(super) slot: Slot,
+
+    pub(super) trigger_kind: TriggerKind,
+
+    /// Set when the transaction carried the pools of an earlier generation of
+    /// the config than the one we evaluate with. We log the states of those
+    /// pools, but do not evaluate them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) stale_generation: Option<u64>,
+
+    /// Whether the transaction failed. Its writes to the pools are rolled
+    /// back, so we log the states, but do not evaluate them.
+    pub(super) trigger_failed: bool,
+
+    pub(super) orca_pre_tx_pool: PoolStates,
+    pub(super) orca_post_tx_pool: PoolStates,
+}
+
+impl PrePostPoolStates {
+    pub(super) fn new(
+        tx: &SanitizedTransaction,
+        slot: Slot,
+        orca_pre_tx_pool: PoolStates,
+        orca_post_tx_pool: PoolStates,
+    ) -> Self {
+        PrePostPoolStates {
+            transaction_hash: *tx.message_hash(),
+            transaction_signature: *tx.signature(),
+            slot,
+            trigger_kind: TriggerKind::of(tx),
+            stale_generation: None,
+            trigger_failed: false,
+            orca_pre_tx_pool,
+            orca_post_tx_pool,
+        }
+    }
+}
+
+/// Receives every event that the MEV log thread writes to the log file, in the
+/// same order, e.g. to pass them on to Geyser plugins.
+pub trait MevEventNotifier: Send + Sync {
+    /// `event` is the JSON line as written to the log file, without newline.
+    fn notify_mev_event(&self, event: &str);
+}
+
+/// Notifier for when the events only go to the log file.
+#[derive(Debug, Default)]
+pub struct NoopMevEventNotifier;
+
+impl MevEventNotifier for NoopMevEventNotifier {
+    fn notify_mev_event(&self, _event: &str) {}
+}
+
+/// An event of the MEV log, for in-process subscribers, see
+/// `MevLog::subscribe`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MevEvent {
+    /// The JSON line as written to the log file, without newline.
+    pub line: String,
+}
+
+#[derive(Debug)]
+struct MevSubscriber {
+    sender: Sender<Arc<MevEvent>>,
+    // Events that we dropped because the subscriber lagged behind.
+    dropped_events: u64,
+    // Cleared once the receiver is dropped, then we remove the subscriber.
+    connected: bool,
+}
+
+/// The in-process subscribers of a `MevLog`. The log thread publishes to them
+/// under the same lock that `subscribe` takes, so a subscriber receives every
+/// event after the one that was being published when it subscribed.
+#[derive(Debug, Default)]
+pub struct MevSubscribers {
+    subscribers: Mutex<Vec<MevSubscriber>>,
+}
+
+impl MevSubscribers {
+    fn subscribe(&self) -> Receiver<Arc<MevEvent>> {
+        let (sender, receiver) = bounded(MEV_SUBSCRIBER_CAPACITY);
+        self.subscribers.lock().unwrap().push(MevSubscriber {
+            sender,
+            dropped_events: 0,
+            connected: true,
+        });
+        receiver
+    }
+
+    fn dropped_events(&self) -> Vec<u64> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|subscriber| subscriber.dropped_events)
+            .collect()
+    }
+
+    /// Offer `event` to every subscriber, without waiting for any of them.
+    fn publish(&self, event: &str) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        let event = Arc::new(MevEvent {
+            line: event.to_owned(),
+        });
+        let mut any_disconnected = false;
+        for (idx, subscriber) in subscribers.iter_mut().enumerate() {
+            match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    subscriber.dropped_events += 1;
+                    warn_throttled!(
+                        &format!("mev_subscriber_full_{}", idx),
+                        LOG_THROTTLE_INTERVAL,
+                        "[MEV] Subscriber {} lags behind, dropped {} events so far",
+                        idx,
+                        subscriber.dropped_events
+                    );
+                }
+                Err(TrySendError::Disconnected(_)) => {
+                    subscriber.connected = false;
+                    any_disconnected = true;
+                }
+            }
+        }
+        if any_disconnected {
+            subscribers.retain(|subscriber| subscriber.connected);
+        }
+    }
+}
+
+/// Passes the events to the notifier of a `MevLog`, and then to its
+/// subscribers.
+struct FanOutNotifier {
+    notifier: Arc<dyn MevEventNotifier>,
+    subscribers: Arc<MevSubscribers>,
+}
+
+impl MevEventNotifier for FanOutNotifier {
+    fn notify_mev_event(&self, event: &str) {
+        self.notifier.notify_mev_event(event);
+        self.subscribers.publish(event);
+    }
+}
+
+/// Write `event` as a line to the MEV log, and pass it on to `notifier`.
+fn emit_event(file: &mut MevLogFile, notifier: &dyn MevEventNotifier, event: String) {
+    file.write_line(&event)
+        .expect("[MEV] Could not write log to file");
+    notifier.notify_mev_event(&event);
+}
+
+/// Log how many messages we dropped because the log channel was full, if
+/// any, since the last time.
+fn emit_dropped_messages(
+    file: &mut MevLogFile,
+    notifier: &dyn MevEventNotifier,
+    dropped_messages: &DroppedLogMessages,
+) {
+    let count = dropped_messages.take();
+    if count > 0 {
+        warn!(
+            "[MEV] Dropped {} messages because the log channel was full",
+            count
+        );
+        emit_event(file, notifier, dropped_log_messages_event(count));
+    }
+}
+
+/// Start a session in the log file that we just opened. Subscribers only see
+/// the events of the session, so we do not pass the header on to them.
+fn write_session_header(file: &mut MevLogFile, validator: Option<&ValidatorAttribution>) {
+    file.write_line(&SessionHeader::new(Utc::now().timestamp(), validator).to_event())
+        .expect("[MEV] Could not write log to file");
+}
+
+fn emit_watchdog_event(
+    file: &mut MevLogFile,
+    notifier: &dyn MevEventNotifier,
+    event: &WatchdogEvent,
+) {
+    if event.stale {
+        warn!(
+            "[MEV] Watchdog: {:?} for {} slots",
+            event.condition, event.stale_slots
+        );
+    } else {
+        info!(
+            "[MEV] Watchdog: recovered from {:?} after {} slots",
+            event.condition, event.stale_slots
+        );
+    }
+    emit_event(
+        file,
+        notifier,
+        format!(
+            "{{\"event\":\"watchdog\",\"data\":{}}}",
+            serde_json::to_string(event).expect("Constructed by us, should never fail")
+        ),
+    )
+}
+
+fn emit_aggregated_opportunity(
+    file: &mut MevLogFile,
+    notifier: &dyn MevEventNotifier,
+    mev_paths: &[MevPath],
+    aggregate: &AggregatedOpportunity,
+) {
+    emit_event(
+        file,
+        notifier,
+        format!(
+            "{{\"event\":\"aggregated_opportunity\",\"data\":{}}}",
+            serde_json::to_string(&AggregatedOpportunityEvent {
+                opportunity: &mev_paths[aggregate.path_idx],
+                aggregate,
+            })
+            .expect("Constructed by us, should never fail")
+        ),
+    )
+}
+
+impl MevLog {
+    /// Spawn the thread that writes to the MEV log. When `validator` is
+    /// provided, the session header and every executed transaction are
+    /// attributed to it in the log.
+    pub fn new(mev_config: &MevConfig, validator: Option<ValidatorAttribution>) -> Self {
+        Self::with_notifier(mev_config, validator, Arc::new(NoopMevEventNotifier))
+    }
+
+    /// Like `new`, but every event is also passed to `notifier`, after it is
+    /// written to the log file.
+    pub fn with_notifier(
+        mev_config: &MevConfig,
+        validator: Option<ValidatorAttribution>,
+        notifier: Arc<dyn MevEventNotifier>,
+    ) -> Self {
+        let mut file =
+            MevLogFile::open(&mev_config.log_path, mev_config.log_compression, Utc::now())
+                .unwrap_or_else(|err| panic!("[MEV] {}", err));
+        write_session_header(&mut file, validator.as_ref());
+        let (log_send_channel, log_receiver) = MevLogSender::channel(
+            mev_config.log_channel_capacity,
+            mev_config.log_channel_policy,
+        );
+        let dropped_messages = log_send_channel.dropped();
+        let subscribers = Arc::new(MevSubscribers::default());
+        let notifier: Arc<dyn MevEventNotifier> = Arc::new(FanOutNotifier {
+            notifier,
+            subscribers: subscribers.clone(),
+        });
+
+        // The opportunities refer to paths by their index, which includes the
+        // paths that the `Mev` discovered.
+        let mut mev_paths = configured_and_discovered_paths(mev_config);
+        let mut watchdog = Watchdog::new(
+            mev_config.watchdog_trigger_slots,
+            mev_config.watchdog_opportunity_slots,
+        );
+        let mut pool_contributions = PoolContributions::default();
+        let mut profit_summary = mev_config
+            .reporting
+            .as_ref()
+            .map(|reporting| ProfitSummary::new(reporting.mint.0));
+        let mut clock_skew_monitor = ClockSkewMonitor::new(mev_config.clock_skew_threshold_ms);
+        let mut status = MevStatus::new(&mev_paths);
+        let mut debouncer = OpportunityDebouncer::new(mev_config.opportunity_debounce_slots);
+        let mut checkpointer = Checkpointer::new(mev_config.checkpoint_interval_slots);
+        let thread_handle = std::thread::spawn(move || loop {
+            match log_receiver.recv() {
+                Ok(MevMsg::Log(msg)) => {
+                    let recovery = watchdog.on_trigger(msg.slot);
+                    pool_contributions.record_trigger(&msg);
+                    checkpointer.record_trigger(&msg);
+                    status.counters.triggers += 1;
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        serde_json::to_string(&msg).expect("Constructed by us, should never fail"),
+                    );
+                    if let Some(event) = recovery {
+                        emit_watchdog_event(&mut file, notifier.as_ref(), &event);
+                    }
+                }
+
+                Ok(MevMsg::Tick(slot, slot_time)) => {
+                    emit_dropped_messages(&mut file, notifier.as_ref(), &dropped_messages);
+                    for event in watchdog.tick(slot) {
+                        emit_watchdog_event(&mut file, notifier.as_ref(), &event);
+                    }
+                    for aggregate in debouncer.expire(slot) {
+                        emit_aggregated_opportunity(
+                            &mut file,
+                            notifier.as_ref(),
+                            &mev_paths,
+                            &aggregate,
+                        );
+                    }
+                    if let Some(checkpoint) = checkpointer.tick(slot) {
+                        emit_event(&mut file, notifier.as_ref(), checkpoint.to_event());
+                    }
+                    watchdog.report_metrics();
+                    if let Some(event) = clock_skew_monitor.check(slot, slot_time, wall_clock_ms())
+                    {
+                        warn!(
+                            "[MEV] Wall clock is {} ms off from the slot time of slot {}, \
+                            latencies that mix both are off by as much",
+                            event.skew_ms, event.slot
+                        );
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"clock_skew\",\"data\":{}}}",
+                                serde_json::to_string(&event)
+                                    .expect("Constructed by us, should never fail")
+                            ),
+                        );
+                    }
+                    clock_skew_monitor.report_metrics();
+                    // With compression, this ends the frame, so what we logged
+                    // so far survives a crash.
+                    if let Err(err) = file.flush_point() {
+                        error!("[MEV] Could not flush log file, error: {}", err);
+                    }
+                    // With a date in the log path, a new day starts a new file.
+                    match file.roll_over(Utc::now()) {
+                        Ok(true) => {
+                            info!("[MEV] Logging to {}", file.path().display());
+                            write_session_header(&mut file, validator.as_ref());
+                        }
+                        Ok(false) => {}
+                        Err(err) => error!("[MEV] {}, we keep logging to the current file", err),
+                    }
+                }
+
+                Ok(MevMsg::Opportunities(mev_tx_output)) => {
+                    // Opportunities follow the trigger that created them.
+                    let recovery = match watchdog.slot() {
+                        Some(slot) if !mev_tx_output.is_empty() => watchdog.on_opportunity(slot),
+                        _ => None,
+                    };
+                    for mev_tx_output in &mev_tx_output {
+                        status.record_opportunity(
+                            mev_tx_output.path_idx,
+                            watchdog.slot(),
+                            mev_tx_output.capped,
+                        );
+                    }
+                    // The outputs of a path, e.g. the parts of a split, are
+                    // one opportunity, we log all or none of them.
+                    let mut ended_aggregates = Vec::new();
+                    let mut is_logged_by_path = HashMap::new();
+                    let opportunity_count = mev_tx_output.len();
+                    let mev_tx_output: Vec<MevTxOutput> = match watchdog.slot() {
+                        Some(slot) => mev_tx_output
+                            .into_iter()
+                            .filter(|mev_tx_output| {
+                                *is_logged_by_path
+                                    .entry(mev_tx_output.path_idx)
+                                    .or_insert_with(|| {
+                                        let (is_logged, ended) = debouncer.observe(
+                                            slot,
+                                            mev_tx_output.path_idx,
+                                            mev_tx_output.marginal_price,
+                                            mev_tx_output.optimal_input,
+                                        );
+                                        ended_aggregates.extend(ended);
+                                        is_logged
+                                    })
+                            })
+                            .collect(),
+                        None => mev_tx_output,
+                    };
+                    for aggregate in &ended_aggregates {
+                        emit_aggregated_opportunity(
+                            &mut file,
+                            notifier.as_ref(),
+                            &mev_paths,
+                            aggregate,
+                        );
+                    }
+                    let mev_paths_input: Vec<MevOpportunityWithInput> = mev_tx_output
+                        .into_iter()
+                        .map(|mev_tx_output| {
+                            let opportunity = &mev_paths[mev_tx_output.path_idx];
+                            let selected_pools = if opportunity.has_alternative_pools() {
+                                mev_tx_output
+                                    .selected_pools
+                                    .iter()
+                                    .map(|pubkey| B58Pubkey(*pubkey))
+                                    .collect()
+                            } else {
+                                Vec::new()
+                            };
+                            MevOpportunityWithInput {
+                                opportunity,
+                                reversed: mev_tx_output.reversed,
+                                input_output_pairs: mev_tx_output.input_output_pairs,
+                                selected_pools,
+                                non_token_costs_lamports: Some(
+                                    mev_tx_output.non_token_costs_lamports,
+                                )
+                                .filter(|costs| *costs > 0),
+                                trigger_compute_unit_price: mev_tx_output
+                                    .trigger_compute_unit_price,
+                                compute_unit_price: mev_tx_output.compute_unit_price,
+                                estimated_compute_units: mev_tx_output.estimated_compute_units,
+                                slippage_bps: mev_tx_output.slippage_bps,
+                                compute_unit_limit: mev_tx_output.compute_unit_limit,
+                                setting_sources: mev_tx_output.setting_sources,
+                                transaction: mev_tx_output.dry_run_transaction,
+                                reported_value: mev_tx_output
+                                    .reported_profit
+                                    .and_then(|reported_profit| reported_profit.reported_value),
+                                skip_reason: mev_tx_output.skip_reason,
+                                observations: mev_tx_output.observations,
+                                input_cap: mev_tx_output.input_cap,
+                                trigger_kind: mev_tx_output.trigger_kind,
+                                sequence: mev_tx_output.sequence,
+                                decision_trace: mev_tx_output.decision_trace,
+                            }
+                        })
+                        .collect();
+                    // When we left out every opportunity, there is nothing
+                    // to log.
+                    if !mev_paths_input.is_empty() || opportunity_count == 0 {
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"opportunity\",\"data\":{}}}",
+                                serde_json::to_string(&mev_paths_input)
+                                    .expect("Constructed by us, should never fail")
+                            ),
+                        );
+                    }
+                    if let Some(event) = recovery {
+                        emit_watchdog_event(&mut file, notifier.as_ref(), &event);
+                    }
+                }
+
+                Ok(MevMsg::ExecutedTransaction(executed_tx_output)) => {
+                    let pools: Vec<Pubkey> =
+                        executed_tx_output.pools.iter().map(|pool| pool.0).collect();
+                    pool_contributions.record_execution(
+                        &pools,
+                        executed_tx_output.possible_profit,
+                        executed_tx_output.is_successful,
+                    );
+                    status.record_execution(executed_tx_output.is_successful);
+                    if let (Some(profit_summary), Some(reported_profit), true) = (
+                        &mut profit_summary,
+                        &executed_tx_output.reported_profit,
+                        executed_tx_output.is_successful,
+                    ) {
+                        profit_summary.record(executed_tx_output.possible_profit, reported_profit);
+                    }
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        format!(
+                            "{{\"event\":\"executed_transaction\",\"data\":{}}}",
+                            serde_json::to_string(&ExecutedTransactionEvent::new(
+                                &executed_tx_output,
+                                validator.as_ref()
+                            ))
+                            .expect("Constructed by us, should never fail")
+                        ),
+                    )
+                }
+
+                Ok(MevMsg::DroppedTransaction(dropped_transaction)) => {
+                    status.counters.dropped_transactions += 1;
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        format!(
+                            "{{\"event\":\"dropped_transaction\",\"data\":{}}}",
+                            serde_json::to_string(&dropped_transaction)
+                                .expect("Constructed by us, should never fail")
+                        ),
+                    )
+                }
+
+                Ok(MevMsg::PoolConfigMismatch(mismatch)) => {
+                    error!(
+                        "[MEV] Pool {} holds mints {} and {}, but the config says {} and {}, \
+                        we will not trade through it.",
+                        mismatch.pool,
+                        mismatch.pool_a_mint,
+                        mismatch.pool_b_mint,
+                        mismatch.config_pool_a_mint,
+                        mismatch.config_pool_b_mint,
+                    );
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        format!(
+                            "{{\"event\":\"pool_config_mismatch\",\"data\":{}}}",
+                            serde_json::to_string(&mismatch)
+                                .expect("Constructed by us, should never fail")
+                        ),
+                    )
+                }
+
+                Ok(MevMsg::PoolLoadErrors(errors)) => {
+                    for error in errors {
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"pool_load_error\",\"data\":{}}}",
+                                serde_json::to_string(&error)
+                                    .expect("Constructed by us, should never fail")
+                            ),
+                        )
+                    }
+                }
+
+                Ok(MevMsg::ForwardedTransaction(forwarded)) => {
+                    if let Some(error) = &forwarded.error {
+                        warn!(
+                            "[MEV] Could not forward transaction {}: {}",
+                            forwarded.transaction_signature, error
+                        );
+                    }
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        format!(
+                            "{{\"event\":\"forwarded_transaction\",\"data\":{}}}",
+                            serde_json::to_string(&forwarded)
+                                .expect("Constructed by us, should never fail")
+                        ),
+                    )
+                }
+
+                Ok(MevMsg::SystemicFailure(event)) => {
+                    match &event {
+                        SystemicFailureEvent::Paused {
+                            failure, failures, ..
+                        } => error!(
+                            "[MEV] {} of our transactions failed with systemic errors, the \
+                            last one with {:?}, we stop crafting transactions until they \
+                            execute again",
+                            failures, failure
+                        ),
+                        SystemicFailureEvent::Resumed { manual, .. } => info!(
+                            "[MEV] Resumed crafting transactions after systemic failures{}",
+                            if *manual { ", on request" } else { "" }
+                        ),
+                    }
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        format!(
+                            "{{\"event\":\"systemic_failure\",\"data\":{}}}",
+                            serde_json::to_string(&event)
+                                .expect("Constructed by us, should never fail")
+                        ),
+                    )
+                }
+
+                Ok(MevMsg::CompetitorArbitrages(competitor_arbitrages)) => {
+                    status.counters.competitor_arbitrages += competitor_arbitrages.len() as u64;
+                    for arbitrage in &competitor_arbitrages {
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"competitor_arbitrage\",\"data\":{}}}",
+                                serde_json::to_string(&CompetitorArbitrageEvent {
+                                    opportunity: &mev_paths[arbitrage.path_idx],
+                                    arbitrage,
+                                })
+                                .expect("Constructed by us, should never fail")
+                            ),
+                        )
+                    }
+                }
+
+                Ok(MevMsg::PathsSkipped(skipped_paths)) => {
+                    for skipped in &skipped_paths {
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"path_skipped\",\"data\":{}}}",
+                                serde_json::to_string(&PathSkippedEvent {
+                                    opportunity: &mev_paths[skipped.path_idx],
+                                    skipped,
+                                })
+                                .expect("Constructed by us, should never fail")
+                            ),
+                        )
+                    }
+                }
+
+                Ok(MevMsg::LockImpact(report)) => status.record_lock_impact(report),
+
+                Ok(MevMsg::Config(config_dump)) => {
+                    emit_event(
+                        &mut file,
+                        notifier.as_ref(),
+                        format!(
+                            "{{\"event\":\"config\",\"data\":{}}}",
+                            serde_json::to_string(&config_dump)
+                                .expect("Constructed by us, should never fail")
+                        ),
+                    );
+                }
+
+                Ok(MevMsg::Paths(reloaded_paths)) => {
+                    // The pending aggregates refer to the paths that we had.
+                    for aggregate in debouncer.flush() {
+                        emit_aggregated_opportunity(
+                            &mut file,
+                            notifier.as_ref(),
+                            &mev_paths,
+                            &aggregate,
+                        );
+                    }
+                    status.set_paths(&reloaded_paths);
+                    mev_paths = reloaded_paths;
+                }
+
+                Ok(MevMsg::WriteStatus(inflight_exposure)) => {
+                    status.update(&watchdog, &inflight_exposure);
+                    // Next to the file that we currently log to.
+                    let status_file = status_path(file.path());
+                    match write_status_file(&status_file, &status) {
+                        Ok(()) => info!("[MEV] Wrote status to {:?}", status_file),
+                        Err(err) => error!(
+                            "[MEV] Could not write status to {:?}, error: {}",
+                            status_file, err
+                        ),
+                    }
+                }
+
+                Ok(MevMsg::Exit) => {
+                    emit_dropped_messages(&mut file, notifier.as_ref(), &dropped_messages);
+                    for aggregate in debouncer.flush() {
+                        emit_aggregated_opportunity(
+                            &mut file,
+                            notifier.as_ref(),
+                            &mev_paths,
+                            &aggregate,
+                        );
+                    }
+                    // We do not keep epoch summaries, so we write the table
+                    // once, when we shut down.
+                    if !pool_contributions.is_empty() {
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"pool_contributions\",\"data\":{}}}",
+                                serde_json::to_string(&pool_contributions.ranked())
+                                    .expect("Constructed by us, should never fail")
+                            ),
+                        );
+                    }
+                    // Likewise for the profits in the reporting mint.
+                    if let Some(profit_summary) = profit_summary
+                        .as_ref()
+                        .filter(|summary| !summary.is_empty())
+                    {
+                        emit_event(
+                            &mut file,
+                            notifier.as_ref(),
+                            format!(
+                                "{{\"event\":\"profit_summary\",\"data\":{}}}",
+                                serde_json::to_string(profit_summary)
+                                    .expect("Constructed by us, should never fail")
+                            ),
+                        );
+                    }
+                    if let Err(err) = file.flush_point() {
+                        error!("[MEV] Could not flush log file, error: {}", err);
+                    }
+                    break;
+                }
+                Err(err) => error!("[MEV] Could not log arbitrage on file, error: {}", err),
+            }
+        });
+
+        MevLog {
+            thread_handle,
+            log_send_channel,
+            subscribers,
+        }
+    }
+
+    /// Receive every event that the log thread writes after this call, in the
+    /// same order, e.g. for a strategy that runs in the validator. When the
+    /// receiver lags `MEV_SUBSCRIBER_CAPACITY` events behind, we drop events
+    /// for it, rather than slow down the log or other subscribers. Drop the
+    /// receiver to unsubscribe.
+    pub fn subscribe(&self) -> Receiver<Arc<MevEvent>> {
+        self.subscribers.subscribe()
+    }
+
+    /// Per subscriber that is still subscribed, in the order in which they
+    /// subscribed, how many events we dropped for it.
+    pub fn dropped_events(&self) -> Vec<u64> {
+        self.subscribers.dropped_events()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use super::*;
+    use crate::{
+        inline_spl_token,
+        mev::{
+            arbitrage::TxSettingSources,
+            checkpoint::latest_checkpoint,
+            log_file::{read_mev_log, LogCompression},
+            pool_state::{Fees, OrcaPoolAddresses, OrcaPoolWithBalance},
+            reporting::ReportingConfig,
+            test_utils::{path, steth_stsol_usdc_pool_states},
+        },
+    };
+
+    /// The events in the log at `path`, after the session header that we
+    /// start it with.
+    fn read_events(path: &std::path::Path) -> String {
+        let log = read_mev_log(path).unwrap();
+        let (header, events) = log.split_once('\n').unwrap();
+        let header: serde_json::Value = serde_json::from_str(header).unwrap();
+        assert_eq!(header["event"], "session");
+        assert_eq!(header["data"]["log_schema_version"], LOG_SCHEMA_VERSION);
+        events.to_owned()
+    }
+
+    #[test]
+    fn test_log_serialization() {
+        use spl_token_swap::curve::{base::CurveType, constant_product::ConstantProductCurve};
+        use std::str::FromStr;
+
+        let curve_calculator = Arc::new(ConstantProductCurve::default());
+        let (authority_pubkey, _authority_bump_seed) = Pubkey::find_program_address(
+            &[
+                &Pubkey::from_str("4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM")
+                    .unwrap()
+                    .to_bytes()[..],
+            ],
+            &inline_spl_token::id(),
+        );
+
+        let opportunity = PrePostPoolStates {
+            transaction_hash: Hash::new(&[0; 32]),
+            transaction_signature: Signature::new(&[0; 64]),
+            slot: 1,
+            trigger_kind: TriggerKind::User,
+            stale_generation: None,
+            trigger_failed: false,
+            orca_pre_tx_pool: PoolStates(
+                vec![(
+                    Pubkey::from_str("4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM").unwrap(),
+                    OrcaPoolWithBalance {
+                        pool: OrcaPoolAddresses {
+                            program_id: Pubkey::from_str(
+                                "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP",
+                            )
+                            .unwrap(),
+                            address: Pubkey::from_str(
+                                "4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM",
+                            )
+                            .unwrap(),
+                            pool_a_account: Pubkey::from_str(
+                                "8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh",
+                            )
+                            .unwrap(),
+                            pool_b_account: Pubkey::from_str(
+                                "CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3",
+                            )
+                            .unwrap(),
+                            pool_mint: Pubkey::from_str(
+                                "33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM",
+                            )
+                            .unwrap(),
+                            pool_fee: Pubkey::from_str(
+                                "GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr",
+                            )
+                            .unwrap(),
+                            pool_authority: authority_pubkey,
+                            ..Default::default()
+                        },
+                        pool_a_balance: 1,
+                        pool_b_balance: 1,
+                        fees: Fees(spl_token_swap::curve::fees::Fees {
+                            trade_fee_numerator: 1,
+                            trade_fee_denominator: 10,
+                            owner_trade_fee_numerator: 1,
+                            owner_trade_fee_denominator: 10,
+                            owner_withdraw_fee_numerator: 1,
+                            owner_withdraw_fee_denominator: 10,
+                            host_fee_numerator: 1,
+                            host_fee_denominator: 10,
+                        }),
+                        curve_type: CurveType::ConstantProduct,
+                        curve_calculator,
+                        source_balance: None,
+                        destination_balance: None,
+                        pool_a_mint_info: None,
+                        pool_b_mint_info: None,
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            ),
+            orca_post_tx_pool: PoolStates(HashMap::new()),
+        };
+
+        let expected_result_str = "\
+        {\
+            'transaction_hash':'11111111111111111111111111111111',\
+            'transaction_signature':'1111111111111111111111111111111111111111111111111111111111111111',\
+            'slot':1,\
+            'trigger_kind':'user',\
+            'trigger_failed':false,\
+            'orca_pre_tx_pool':{'4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM':\
+              {\
+                'pool':{\
+                  'address':'4uQeVj5tqViQh7yWWGStvkEG1Zmhx6uasJtWCJziofM',\
+                  'pool_a_account':'8opHzTAnfzRpPEx21XtnrVTX28YQuCpAjcn1PczScKh',\
+                  'pool_b_account':'CiDwVBFgWV9E5MvXWoLgnEgn2hK7rJikbvfWavzAQz3',\
+                  'source':null,\
+                  'destination':null,\
+                  'pool_mint':'33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM',\
+                  'pool_fee':'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'\
+                },\
+                'pool_a_balance':1,\
+                'pool_b_balance':1,\
+                'source_balance':null,\
+                'destination_balance':null,\
+                'fees':{\
+                  'host_fee_denominator':10,\
+                  'host_fee_numerator':1,\
+                  'owner_trade_fee_denominator':10,\
+                  'owner_trade_fee_numerator':1,\
+                  'trade_fee_denominator':10,\
+                  'trade_fee_numerator':1\
+                }\
+              }\
+            },\
+            'orca_post_tx_pool':{}\
+          }"
+        .replace("'", "\"");
+        let serialized_json = serde_json::to_string(&opportunity).expect("Serialization failed");
+        assert_eq!(serialized_json, expected_result_str);
+    }
+
+    #[test]
+    fn test_executed_transaction_attribution() {
+        use std::str::FromStr;
+
+        let output = ExecutedTransactionOutput {
+            transaction_hash: Hash::default(),
+            transaction_signature: Signature::default(),
+            is_successful: true,
+            possible_profit: 42,
+            pools: vec![],
+            detection_to_execution_us: 1_500,
+            slot_time: 1_650_000_000,
+            error: None,
+            reported_profit: None,
+        };
+        let expected_without_validator = "{\
+            'transaction_hash':'11111111111111111111111111111111',\
+            'transaction_signature':'1111111111111111111111111111111111111111111111111111111111111111',\
+            'is_successful':true,\
+            'possible_profit':42,\
+            'pools':[],\
+            'detection_to_execution_us':1500,\
+            'slot_time':1650000000}"
+            .replace('\'', "\"");
+        assert_eq!(
+            serde_json::to_string(&ExecutedTransactionEvent::new(&output, None)).unwrap(),
+            expected_without_validator
+        );
+
+        let validator = ValidatorAttribution {
+            identity: Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap(),
+            vote_account: Some(
+                Pubkey::from_str("DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1").unwrap(),
+            ),
+        };
+        let expected_with_validator = "{\
+            'transaction_hash':'11111111111111111111111111111111',\
+            'transaction_signature':'1111111111111111111111111111111111111111111111111111111111111111',\
+            'is_successful':true,\
+            'possible_profit':42,\
+            'pools':[],\
+            'detection_to_execution_us':1500,\
+            'slot_time':1650000000,\
+            'validator_identity':'9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP',\
+            'vote_account':'DjVE6JNiYqPL2QXyCUUh8rNjHrbz9hXHNYt99MQ59qw1'}"
+            .replace('\'', "\"");
+        assert_eq!(
+            serde_json::to_string(&ExecutedTransactionEvent::new(&output, Some(&validator)))
+                .unwrap(),
+            expected_with_validator
+        );
+    }
+
+    #[test]
+    fn test_session_header_attribution() {
+        use std::str::FromStr;
+
+        let validator = ValidatorAttribution {
+            identity: Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap(),
+            vote_account: None,
+        };
+        assert_eq!(
+            SessionHeader::new(7, Some(&validator)).to_event(),
+            format!(
+                "{{'event':'session','data':{{'log_schema_version':{},'started_at':7,\
+                'validator_identity':'9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP'}}}}",
+                LOG_SCHEMA_VERSION
+            )
+            .replace('\'', "\"")
+        );
+
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, Some(validator));
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let log = read_mev_log(log_file.path()).unwrap();
+        let header: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+        let header: SessionHeader = serde_json::from_value(header["data"].clone()).unwrap();
+        assert_eq!(header.validator_identity, Some(validator.identity));
+        assert_eq!(header.vote_account, None);
+    }
+
+    #[derive(Default)]
+    struct RecordingNotifier {
+        events: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl MevEventNotifier for RecordingNotifier {
+        fn notify_mev_event(&self, event: &str) {
+            self.events.lock().unwrap().push(event.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_event_notifier_order() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            ..MevConfig::default()
+        };
+        let notifier = Arc::new(RecordingNotifier::default());
+        let mev_log = MevLog::with_notifier(&mev_config, None, notifier.clone());
+
+        for possible_profit in 0..3 {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
+                    transaction_hash: Hash::new_unique(),
+                    transaction_signature: Signature::new_unique(),
+                    is_successful: true,
+                    possible_profit,
+                    pools: vec![],
+                    detection_to_execution_us: 0,
+                    slot_time: 0,
+                    error: None,
+                    reported_profit: None,
+                }))
+                .unwrap();
+        }
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged: Vec<String> = read_events(log_file.path())
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(logged.len(), 3);
+        assert_eq!(*notifier.events.lock().unwrap(), logged);
+    }
+
+    #[test]
+    fn test_subscribers() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let subscribers = mev_log.subscribers.clone();
+        let slow = mev_log.subscribe();
+        let fast = mev_log.subscribe();
+        drop(mev_log.subscribe());
+        let executed = |possible_profit| {
+            MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
+                transaction_hash: Hash::new_unique(),
+                transaction_signature: Signature::new_unique(),
+                is_successful: true,
+                possible_profit,
+                pools: vec![],
+                detection_to_execution_us: 0,
+                slot_time: 0,
+                error: None,
+                reported_profit: None,
+            })
+        };
+
+        // The slow subscriber never reads, so once its queue is full, we drop
+        // the events for it, but not for the fast one.
+        let mut fast_events = Vec::new();
+        for batch in [0..MEV_SUBSCRIBER_CAPACITY, 0..10] {
+            let batch_len = batch.len();
+            for possible_profit in batch {
+                mev_log
+                    .log_send_channel
+                    .send(executed(possible_profit as u64))
+                    .unwrap();
+            }
+            fast_events.extend(fast.iter().take(batch_len));
+        }
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        // Dropping does not affect the file either.
+        let logged: Vec<String> = read_events(log_file.path())
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(logged.len(), MEV_SUBSCRIBER_CAPACITY + 10);
+        let lines = |events: Vec<Arc<MevEvent>>| -> Vec<String> {
+            events.iter().map(|event| event.line.clone()).collect()
+        };
+        assert_eq!(lines(fast_events), logged);
+        assert_eq!(
+            lines(slow.try_iter().collect()),
+            logged[..MEV_SUBSCRIBER_CAPACITY]
+        );
+        // The subscriber that dropped its receiver is gone.
+        assert_eq!(subscribers.dropped_events(), vec![10, 0]);
+    }
+
+    #[test]
+    fn test_compressed_log() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            log_compression: LogCompression::Zstd,
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let executed_tx_output = ExecutedTransactionOutput {
+            transaction_hash: Hash::new_unique(),
+            transaction_signature: Signature::new_unique(),
+            is_successful: true,
+            possible_profit: 7,
+            pools: vec![],
+            detection_to_execution_us: 0,
+            slot_time: 0,
+            error: None,
+            reported_profit: None,
+        };
+        let expected = format!(
+            "{{\"event\":\"executed_transaction\",\"data\":{}}}",
+            serde_json::to_string(&ExecutedTransactionEvent::new(&executed_tx_output, None))
+                .unwrap()
+        );
+        mev_log
+            .log_send_channel
+            .send(MevMsg::ExecutedTransaction(executed_tx_output))
+            .unwrap();
+        mev_log.log_send_channel.send(MevMsg::Tick(1, 0)).unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        assert!(fs::read_to_string(log_file.path()).is_err());
+        assert_eq!(read_events(log_file.path()), format!("{}\n", expected));
+    }
+
+    #[test]
+    fn test_pool_contributions_on_exit() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let (shared, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        for (pools, possible_profit) in [(vec![shared, other], 10), (vec![shared], 5)] {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
+                    transaction_hash: Hash::new_unique(),
+                    transaction_signature: Signature::new_unique(),
+                    is_successful: true,
+                    possible_profit,
+                    pools: pools.into_iter().map(B58Pubkey).collect(),
+                    detection_to_execution_us: 0,
+                    slot_time: 0,
+                    error: None,
+                    reported_profit: None,
+                }))
+                .unwrap();
+        }
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        assert_eq!(
+            logged.lines().last().unwrap(),
+            format!(
+                "{{'event':'pool_contributions','data':[\
+                {{'pool':'{}','realized_profit':10,'executions':2,'triggers':0}},\
+                {{'pool':'{}','realized_profit':5,'executions':1,'triggers':0}}]}}",
+                shared, other
+            )
+            .replace('\'', "\"")
+        );
+    }
+
+    #[test]
+    fn test_profit_summary_on_exit() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let (usdc, stsol, steth) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            reporting: Some(ReportingConfig {
+                mint: B58Pubkey(usdc),
+                routes: HashMap::new(),
+                quote_prices: HashMap::new(),
+            }),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let executions = [
+            (true, 3, stsol, Some(60)),
+            (true, 5, steth, None),
+            // Failed transactions made no profit.
+            (false, 4, stsol, Some(80)),
+        ];
+        for (is_successful, possible_profit, mint, reported_value) in executions {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
+                    transaction_hash: Hash::new_unique(),
+                    transaction_signature: Signature::new_unique(),
+                    is_successful,
+                    possible_profit,
+                    pools: vec![],
+                    detection_to_execution_us: 0,
+                    slot_time: 0,
+                    error: None,
+                    reported_profit: Some(ReportedProfit {
+                        profit_mint: B58Pubkey(mint),
+                        reported_value,
+                    }),
+                }))
+                .unwrap();
+        }
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged[0]["data"]["profit_mint"], stsol.to_string());
+        assert_eq!(logged[0]["data"]["reported_value"], 60);
+        assert!(logged[1]["data"].get("reported_value").is_none());
+        let summary = logged.last().unwrap();
+        assert_eq!(summary["event"], "profit_summary");
+        assert_eq!(
+            summary["data"],
+            serde_json::json!({
+                "reporting_mint": usdc.to_string(),
+                "reported_value": 60,
+                "converted": { stsol.to_string(): 3 },
+                "unconverted": { steth.to_string(): 5 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_events() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            checkpoint_interval_slots: Some(1),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        mev_log
+            .log_send_channel
+            .send(MevMsg::Log(PrePostPoolStates {
+                transaction_hash: Hash::default(),
+                transaction_signature: Signature::default(),
+                slot: 10,
+                trigger_kind: TriggerKind::User,
+                stale_generation: None,
+                trigger_failed: false,
+                orca_pre_tx_pool: PoolStates(HashMap::new()),
+                orca_post_tx_pool: steth_stsol_usdc_pool_states(),
+            }))
+            .unwrap();
+        // No trigger in slot 11, we still write a checkpoint for it.
+        for slot in 10..12 {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::Tick(slot, 0))
+                .unwrap();
+        }
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let checkpoints: Vec<&str> = logged
+            .lines()
+            .filter(|line| line.starts_with("{\"event\":\"checkpoint\""))
+            .collect();
+        assert_eq!(checkpoints.len(), 2);
+        let checkpoint = latest_checkpoint(&logged).unwrap().unwrap();
+        assert_eq!((checkpoint.slot, checkpoint.observed_slot), (11, 10));
+        assert_eq!(
+            checkpoint.pool_states().unwrap().to_snapshot_bytes(),
+            steth_stsol_usdc_pool_states().to_snapshot_bytes()
+        );
+    }
+
+    #[test]
+    fn test_watchdog_events() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            watchdog_trigger_slots: Some(2),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+
+        for slot in 10..13 {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::Tick(slot, 0))
+                .unwrap();
+        }
+        mev_log
+            .log_send_channel
+            .send(MevMsg::Log(PrePostPoolStates {
+                transaction_hash: Hash::default(),
+                transaction_signature: Signature::default(),
+                slot: 13,
+                trigger_kind: TriggerKind::User,
+                stale_generation: None,
+                trigger_failed: false,
+                orca_pre_tx_pool: PoolStates(HashMap::new()),
+                orca_post_tx_pool: PoolStates(HashMap::new()),
+            }))
+            .unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<&str> = logged.lines().collect();
+        assert_eq!(logged.len(), 3);
+        assert_eq!(
+            logged[0],
+            "{'event':'watchdog','data':{'condition':'no_triggers','stale':true,'slot':12,'stale_slots':2}}"
+                .replace('\'', "\"")
+        );
+        assert!(logged[1].starts_with("{\"transaction_hash\""));
+        assert_eq!(
+            logged[2],
+            "{'event':'watchdog','data':{'condition':'no_triggers','stale':false,'slot':13,'stale_slots':3}}"
+                .replace('\'', "\"")
+        );
+    }
+
+    #[test]
+    fn test_clock_skew_event() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            clock_skew_threshold_ms: Some(2_000),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+
+        // The slot time is an hour behind our wall clock.
+        let slot_time = wall_clock_ms() / 1000 - 3600;
+        mev_log
+            .log_send_channel
+            .send(MevMsg::Tick(10, slot_time))
+            .unwrap();
+        mev_log
+            .log_send_channel
+            .send(MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
+                transaction_hash: Hash::new_unique(),
+                transaction_signature: Signature::new_unique(),
+                is_successful: true,
+                possible_profit: 7,
+                pools: vec![],
+                detection_to_execution_us: 1_500,
+                slot_time,
+                error: None,
+                reported_profit: None,
+            }))
+            .unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged[0]["event"], "clock_skew");
+        assert_eq!(logged[0]["data"]["slot"], 10);
+        assert_eq!(logged[0]["data"]["threshold_ms"], 2_000);
+        let skew_ms = logged[0]["data"]["skew_ms"].as_i64().unwrap();
+        assert!((3_600_000..3_600_000 + 60_000).contains(&skew_ms));
+
+        // Durations are measured with a monotonic clock, the skew does not
+        // affect them.
+        assert_eq!(logged[1]["event"], "executed_transaction");
+        assert_eq!(logged[1]["data"]["detection_to_execution_us"], 1_500);
+        assert_eq!(logged[1]["data"]["slot_time"], slot_time);
+    }
+
+    #[test]
+    fn test_write_status() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            mev_paths: vec![path("USDC->stSOL->USDC").build()],
+            watchdog_trigger_slots: Some(2),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let mint = Pubkey::new_unique();
+
+        for slot in 10..13 {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::Tick(slot, 0))
+                .unwrap();
+        }
+        for is_successful in [true, false] {
+            mev_log
+                .log_send_channel
+                .send(MevMsg::ExecutedTransaction(ExecutedTransactionOutput {
+                    transaction_hash: Hash::new_unique(),
+                    transaction_signature: Signature::new_unique(),
+                    is_successful,
+                    possible_profit: 7,
+                    pools: vec![],
+                    detection_to_execution_us: 0,
+                    slot_time: 0,
+                    error: None,
+                    reported_profit: None,
+                }))
+                .unwrap();
+        }
+        mev_log
+            .log_send_channel
+            .send(MevMsg::DroppedTransaction(DroppedTransaction {
+                transaction_signature: Signature::new_unique(),
+                trigger_signature: Signature::new_unique(),
+                reason: SkipReason::TriggerNotCommitted,
+            }))
+            .unwrap();
+        mev_log
+            .log_send_channel
+            .send(MevMsg::WriteStatus([(mint, 100)].into_iter().collect()))
+            .unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let status: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(status_path(log_file.path())).unwrap())
+                .unwrap();
+        assert_eq!(
+            status,
+            serde_json::json!({
+                "slot": 12,
+                "counters": {
+                    "triggers": 0,
+                    "opportunities": 0,
+                    "capped_opportunities": 0,
+                    "executed_transactions": 2,
+                    "successful_transactions": 1,
+                    "dropped_transactions": 1,
+                    "competitor_arbitrages": 0,
+                },
+                "paths": [{
+                    "name": "USDC->stSOL->USDC",
+                    "opportunities": 0,
+                    "last_opportunity_slot": null,
+                }],
+                "inflight_exposure": { mint.to_string(): 100 },
+                "watchdog": {
+                    "trigger_stale": true,
+                    "opportunity_stale": false,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_repeated_opportunities_are_aggregated() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            mev_paths: vec![path("USDC->stSOL->USDC").build()],
+            opportunity_debounce_slots: Some(10),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let opportunity = |optimal_input: f64| {
+            MevMsg::Opportunities(vec![MevTxOutput {
+                blueprint: None,
+                path_idx: 0,
+                reversed: false,
+                input_output_pairs: vec![],
+                selected_pools: vec![],
+                profit: 10,
+                non_token_costs_lamports: 0,
+                trigger_compute_unit_price: None,
+                compute_unit_price: None,
+                estimated_compute_units: 80_000,
+                slippage_bps: None,
+                compute_unit_limit: None,
+                setting_sources: TxSettingSources::default(),
+                dry_run_transaction: None,
+                reported_profit: None,
+                marginal_price: 1.1,
+                optimal_input,
+                input_mint: Pubkey::new_unique(),
+                skip_reason: None,
+                observations: None,
+                input_cap: None,
+                capped: false,
+                trigger_kind: TriggerKind::User,
+                sequence: None,
+                decision_trace: None,
+            }])
+        };
+
+        mev_log.log_send_channel.send(MevMsg::Tick(5, 0)).unwrap();
+        for _ in 0..3 {
+            mev_log.log_send_channel.send(opportunity(1000.0)).unwrap();
+        }
+        mev_log.log_send_channel.send(MevMsg::Tick(6, 0)).unwrap();
+        mev_log.log_send_channel.send(opportunity(1000.0)).unwrap();
+        // A larger imbalance ends the bucket.
+        mev_log.log_send_channel.send(opportunity(2000.0)).unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged.len(), 3);
+        assert_eq!(logged[0]["event"], "opportunity");
+        assert_eq!(logged[1]["event"], "aggregated_opportunity");
+        assert_eq!(
+            logged[1]["data"]["opportunity"]["name"],
+            "USDC->stSOL->USDC"
+        );
+        assert_eq!(logged[1]["data"]["first_slot"], 5);
+        assert_eq!(logged[1]["data"]["last_slot"], 6);
+        assert_eq!(logged[1]["data"]["occurrences"], 4);
+        assert_eq!(logged[2]["event"], "opportunity");
+    }
+
+    #[test]
+    fn test_opportunities_after_paths_reload() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            mev_paths: vec![path("USDC->stSOL->USDC").build()],
+            opportunity_debounce_slots: Some(10),
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let opportunity = || {
+            MevMsg::Opportunities(vec![MevTxOutput {
+                blueprint: None,
+                path_idx: 0,
+                reversed: false,
+                input_output_pairs: vec![],
+                selected_pools: vec![],
+                profit: 10,
+                non_token_costs_lamports: 0,
+                trigger_compute_unit_price: None,
+                compute_unit_price: None,
+                estimated_compute_units: 80_000,
+                slippage_bps: None,
+                compute_unit_limit: None,
+                setting_sources: TxSettingSources::default(),
+                dry_run_transaction: None,
+                reported_profit: None,
+                marginal_price: 1.1,
+                optimal_input: 1000.0,
+                input_mint: Pubkey::new_unique(),
+                skip_reason: None,
+                observations: None,
+                input_cap: None,
+                capped: false,
+                trigger_kind: TriggerKind::User,
+                sequence: None,
+                decision_trace: None,
+            }])
+        };
+
+        mev_log.log_send_channel.send(MevMsg::Tick(5, 0)).unwrap();
+        mev_log.log_send_channel.send(opportunity()).unwrap();
+        mev_log.log_send_channel.send(opportunity()).unwrap();
+        // After the reload, path 0 is a different path.
+        mev_log
+            .log_send_channel
+            .send(MevMsg::Paths(vec![path("USDC->wSOL->USDC").build()]))
+            .unwrap();
+        mev_log.log_send_channel.send(opportunity()).unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged.len(), 3);
+        assert_eq!(logged[0]["event"], "opportunity");
+        assert_eq!(
+            logged[0]["data"][0]["opportunity"]["name"],
+            "USDC->stSOL->USDC"
+        );
+        // The reload flushes what was pending under the old paths.
+        assert_eq!(logged[1]["event"], "aggregated_opportunity");
+        assert_eq!(
+            logged[1]["data"]["opportunity"]["name"],
+            "USDC->stSOL->USDC"
+        );
+        assert_eq!(logged[2]["event"], "opportunity");
+        assert_eq!(
+            logged[2]["data"][0]["opportunity"]["name"],
+            "USDC->wSOL->USDC"
+        );
+    }
+
+    #[test]
+    fn test_dry_run_transaction_is_logged() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            mev_paths: vec![path("USDC->stSOL->USDC").build()],
+            dry_run: true,
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+        let opportunity = |dry_run_transaction: Option<String>| {
+            MevMsg::Opportunities(vec![MevTxOutput {
+                blueprint: None,
+                path_idx: 0,
+                reversed: false,
+                input_output_pairs: vec![],
+                selected_pools: vec![],
+                profit: 10,
+                non_token_costs_lamports: 0,
+                trigger_compute_unit_price: None,
+                compute_unit_price: None,
+                estimated_compute_units: 80_000,
+                slippage_bps: None,
+                compute_unit_limit: None,
+                setting_sources: TxSettingSources::default(),
+                dry_run_transaction,
+                reported_profit: None,
+                marginal_price: 1.1,
+                optimal_input: 1000.0,
+                input_mint: Pubkey::new_unique(),
+                skip_reason: None,
+                observations: None,
+                input_cap: None,
+                capped: false,
+                trigger_kind: TriggerKind::User,
+                sequence: None,
+                decision_trace: None,
+            }])
+        };
+
+        mev_log.log_send_channel.send(MevMsg::Tick(5, 0)).unwrap();
+        mev_log
+            .log_send_channel
+            .send(opportunity(Some("AQID".to_owned())))
+            .unwrap();
+        mev_log.log_send_channel.send(MevMsg::Tick(6, 0)).unwrap();
+        mev_log.log_send_channel.send(opportunity(None)).unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged[0]["event"], "opportunity");
+        assert_eq!(logged[0]["data"][0]["transaction"], "AQID");
+        // Without a transaction, we leave the field out.
+        assert!(logged[1]["data"][0].get("transaction").is_none());
+    }
+
+    #[test]
+    fn test_skipped_paths_are_logged() {
+        let log_file = tempfile::NamedTempFile::new().unwrap();
+        let mev_config = MevConfig {
+            log_path: log_file.path().to_owned(),
+            mev_paths: vec![path("USDC->stSOL->USDC").build()],
+            log_skipped_paths: true,
+            ..MevConfig::default()
+        };
+        let mev_log = MevLog::new(&mev_config, None);
+
+        mev_log
+            .log_send_channel
+            .send(MevMsg::PathsSkipped(vec![
+                PathSkipped {
+                    path_idx: 0,
+                    reversed: false,
+                    reason: PathSkipReason::NotProfitable {
+                        marginal_price: 0.5,
+                    },
+                },
+                PathSkipped {
+                    path_idx: 0,
+                    reversed: true,
+                    reason: PathSkipReason::DrainedPool { hop: 1 },
+                },
+            ]))
+            .unwrap();
+        mev_log.log_send_channel.send(MevMsg::Exit).unwrap();
+        mev_log.thread_handle.join().unwrap();
+
+        let logged = read_events(log_file.path());
+        let logged: Vec<serde_json::Value> = logged
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged[0]["event"], "path_skipped");
+        assert_eq!(
+            logged[0]["data"]["opportunity"]["name"],
+            "USDC->stSOL->USDC"
+        );
+        assert_eq!(logged[0]["data"]["reversed"], false);
+        assert_eq!(logged[0]["data"]["reason"], "not_profitable");
+        assert_eq!(logged[0]["data"]["marginal_price"], 0.5);
+        assert_eq!(logged[1]["data"]["reversed"], true);
+        assert_eq!(logged[1]["data"]["reason"], "drained_pool");
+        assert_eq!(logged[1]["data"]["hop"], 1);
+    }
+}
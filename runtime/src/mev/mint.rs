@@ -0,0 +1,321 @@
+use serde::Serialize;
+use solana_sdk::clock::Epoch;
+use spl_token::solana_program::program_pack::Pack;
+
+use crate::inline_spl_token_2022;
+
+/// Token-2022 pads a mint with extensions to the size of a token account, and
+/// puts the account type after that, followed by the extensions.
+const ACCOUNT_TYPE_OFFSET: usize = spl_token::state::Account::LEN;
+
+/// `spl_token_2022::extension::ExtensionType`, a zero type ends the extensions.
+const EXTENSION_TYPE_UNINITIALIZED: u16 = 0;
+const EXTENSION_TYPE_TRANSFER_FEE_CONFIG: u16 = 1;
+
+/// Serialized size of a `TransferFee`: epoch, maximum fee, and basis points.
+const TRANSFER_FEE_LEN: usize = 8 + 8 + 2;
+
+/// Serialized size of the `TransferFeeConfig` extension: the config and
+/// withdraw authorities, the withheld amount, and the older and newer fee.
+const TRANSFER_FEE_CONFIG_LEN: usize = 32 + 32 + 8 + 2 * TRANSFER_FEE_LEN;
+
+/// Basis points of the whole transfer.
+const MAX_FEE_BASIS_POINTS: u128 = 10_000;
+
+/// A fee that a Token-2022 mint charges on every transfer of its token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferFee {
+    /// First epoch in which the fee applies.
+    pub epoch: Epoch,
+
+    /// The most that one transfer pays, in the smallest unit of the token.
+    pub maximum_fee: u64,
+
+    pub basis_points: u16,
+}
+
+impl TransferFee {
+    /// The fee that a transfer of `amount` pays, like Token-2022 computes it:
+    /// the basis points of the amount, rounded up, but at most `maximum_fee`.
+    pub fn calculate_fee(&self, amount: u128) -> u128 {
+        if self.basis_points == 0 || amount == 0 {
+            return 0;
+        }
+        let fee = (amount.saturating_mul(self.basis_points as u128) + MAX_FEE_BASIS_POINTS - 1)
+            / MAX_FEE_BASIS_POINTS;
+        fee.min(self.maximum_fee as u128)
+    }
+
+    fn unpack(data: &[u8]) -> Self {
+        TransferFee {
+            epoch: u64::from_le_bytes(data[0..8].try_into().unwrap()),
+            maximum_fee: u64::from_le_bytes(data[8..16].try_into().unwrap()),
+            basis_points: u16::from_le_bytes(data[16..18].try_into().unwrap()),
+        }
+    }
+}
+
+/// The fees of the `TransferFeeConfig` extension. When the fee is changed,
+/// the newer one replaces the older one from its epoch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferFeeConfig {
+    pub older: TransferFee,
+    pub newer: TransferFee,
+}
+
+impl TransferFeeConfig {
+    /// The fee that applies in `epoch`.
+    pub fn get_fee(&self, epoch: Epoch) -> &TransferFee {
+        if epoch >= self.newer.epoch {
+            &self.newer
+        } else {
+            &self.older
+        }
+    }
+
+    /// The higher of the older and the newer fee of a transfer of `amount`.
+    /// We evaluate paths without knowing the epoch, and rather underestimate
+    /// what we receive than overstate it.
+    pub fn max_fee(&self, amount: u128) -> u128 {
+        self.older
+            .calculate_fee(amount)
+            .max(self.newer.calculate_fee(amount))
+    }
+
+    /// The higher of the basis points of the older and the newer fee.
+    pub fn max_basis_points(&self) -> u16 {
+        self.older.basis_points.max(self.newer.basis_points)
+    }
+}
+
+/// What we read from the mint of a pool's token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintInfo {
+    pub decimals: u8,
+
+    /// Only Token-2022 mints with the extension have it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer_fee_config: Option<TransferFeeConfig>,
+}
+
+impl MintInfo {
+    /// The transfer fee of `amount` of this token, see
+    /// `TransferFeeConfig::max_fee`, 0 for mints without one.
+    pub fn transfer_fee(&self, amount: u128) -> u128 {
+        self.transfer_fee_config
+            .map_or(0, |transfer_fee_config| transfer_fee_config.max_fee(amount))
+    }
+
+    /// The fraction of a transfer that is left after the transfer fee, when
+    /// the fee is below its maximum.
+    pub fn transfer_fee_remainder(&self) -> f64 {
+        self.transfer_fee_config
+            .map_or(1_f64, |transfer_fee_config| {
+                1_f64 - transfer_fee_config.max_basis_points() as f64 / MAX_FEE_BASIS_POINTS as f64
+            })
+    }
+
+    /// Like `transfer_fee_remainder`, as (numerator, denominator).
+    pub fn transfer_fee_remaining_fraction(&self) -> (u128, u128) {
+        let basis_points = self.transfer_fee_config.map_or(0, |transfer_fee_config| {
+            transfer_fee_config.max_basis_points()
+        });
+        (
+            MAX_FEE_BASIS_POINTS.saturating_sub(basis_points as u128),
+            MAX_FEE_BASIS_POINTS,
+        )
+    }
+
+    /// Unpack an initialized mint of the SPL token program, or of Token-2022
+    /// with its extensions. Returns `None` when `data` holds no such mint, or
+    /// when its extensions are malformed.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let mint_len = spl_token::state::Mint::LEN;
+        let mint = spl_token::state::Mint::unpack(data.get(..mint_len)?).ok()?;
+        let mut mint_info = MintInfo {
+            decimals: mint.decimals,
+            transfer_fee_config: None,
+        };
+        if data.len() == mint_len {
+            return Some(mint_info);
+        }
+
+        if *data.get(ACCOUNT_TYPE_OFFSET)? != inline_spl_token_2022::ACCOUNTTYPE_MINT {
+            return None;
+        }
+        // Extensions are laid out as type, length, and value, where type and
+        // length are little-endian `u16`s.
+        let mut extensions = &data[ACCOUNT_TYPE_OFFSET + 1..];
+        while extensions.len() >= 4 {
+            let extension_type = u16::from_le_bytes([extensions[0], extensions[1]]);
+            let len = u16::from_le_bytes([extensions[2], extensions[3]]) as usize;
+            if extension_type == EXTENSION_TYPE_UNINITIALIZED {
+                break;
+            }
+            let value = extensions.get(4..4 + len)?;
+            if extension_type == EXTENSION_TYPE_TRANSFER_FEE_CONFIG {
+                if len != TRANSFER_FEE_CONFIG_LEN {
+                    return None;
+                }
+                let fees = &value[TRANSFER_FEE_CONFIG_LEN - 2 * TRANSFER_FEE_LEN..];
+                mint_info.transfer_fee_config = Some(TransferFeeConfig {
+                    older: TransferFee::unpack(&fees[..TRANSFER_FEE_LEN]),
+                    newer: TransferFee::unpack(&fees[TRANSFER_FEE_LEN..]),
+                });
+            }
+            extensions = &extensions[4 + len..];
+        }
+        Some(mint_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mint_data(decimals: u8) -> Vec<u8> {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(
+            spl_token::state::Mint {
+                decimals,
+                is_initialized: true,
+                ..spl_token::state::Mint::default()
+            },
+            &mut data,
+        )
+        .unwrap();
+        data
+    }
+
+    /// A Token-2022 mint with `extensions`, each as type and value.
+    fn mint_2022_data(decimals: u8, extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut data = mint_data(decimals);
+        data.resize(ACCOUNT_TYPE_OFFSET, 0);
+        data.push(inline_spl_token_2022::ACCOUNTTYPE_MINT);
+        for (extension_type, value) in extensions {
+            data.extend_from_slice(&extension_type.to_le_bytes());
+            data.extend_from_slice(&(value.len() as u16).to_le_bytes());
+            data.extend_from_slice(value);
+        }
+        data
+    }
+
+    fn transfer_fee_config_data(older: &TransferFee, newer: &TransferFee) -> Vec<u8> {
+        let mut data = vec![0; 32 + 32 + 8];
+        for fee in [older, newer] {
+            data.extend_from_slice(&fee.epoch.to_le_bytes());
+            data.extend_from_slice(&fee.maximum_fee.to_le_bytes());
+            data.extend_from_slice(&fee.basis_points.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_unpack_mint() {
+        assert_eq!(
+            MintInfo::unpack(&mint_data(6)),
+            Some(MintInfo {
+                decimals: 6,
+                transfer_fee_config: None,
+            })
+        );
+        assert_eq!(MintInfo::unpack(&[0; 3]), None);
+        // An uninitialized mint.
+        assert_eq!(MintInfo::unpack(&[0; spl_token::state::Mint::LEN]), None);
+    }
+
+    #[test]
+    fn test_unpack_mint_2022() {
+        let older = TransferFee {
+            epoch: 0,
+            maximum_fee: 5_000,
+            basis_points: 50,
+        };
+        let newer = TransferFee {
+            epoch: 300,
+            maximum_fee: 10_000,
+            basis_points: 100,
+        };
+        // The transfer fee config after another extension, the mint close
+        // authority.
+        let data = mint_2022_data(
+            9,
+            &[
+                (3, vec![7; 32]),
+                (
+                    EXTENSION_TYPE_TRANSFER_FEE_CONFIG,
+                    transfer_fee_config_data(&older, &newer),
+                ),
+            ],
+        );
+        let mint_info = MintInfo::unpack(&data).unwrap();
+        assert_eq!(mint_info.decimals, 9);
+        let transfer_fee_config = mint_info.transfer_fee_config.unwrap();
+        assert_eq!(transfer_fee_config.older, older);
+        assert_eq!(transfer_fee_config.newer, newer);
+        assert_eq!(transfer_fee_config.get_fee(299), &older);
+        assert_eq!(transfer_fee_config.get_fee(300), &newer);
+
+        // Without extensions.
+        assert_eq!(
+            MintInfo::unpack(&mint_2022_data(9, &[])),
+            Some(MintInfo {
+                decimals: 9,
+                transfer_fee_config: None,
+            })
+        );
+
+        // Not a mint, and a truncated extension.
+        let mut data = mint_2022_data(9, &[]);
+        data[ACCOUNT_TYPE_OFFSET] = inline_spl_token_2022::ACCOUNTTYPE_ACCOUNT;
+        assert_eq!(MintInfo::unpack(&data), None);
+        let mut data = mint_2022_data(
+            9,
+            &[(
+                EXTENSION_TYPE_TRANSFER_FEE_CONFIG,
+                transfer_fee_config_data(&older, &newer),
+            )],
+        );
+        data.truncate(data.len() - 1);
+        assert_eq!(MintInfo::unpack(&data), None);
+    }
+
+    #[test]
+    fn test_transfer_fee() {
+        let fee = |basis_points: u16, maximum_fee: u64| TransferFee {
+            epoch: 0,
+            maximum_fee,
+            basis_points,
+        };
+        assert_eq!(fee(100, u64::MAX).calculate_fee(10_000), 100);
+        // Rounded up, and capped at the maximum.
+        assert_eq!(fee(100, u64::MAX).calculate_fee(10_001), 101);
+        assert_eq!(fee(100, 50).calculate_fee(10_000), 50);
+        assert_eq!(fee(0, u64::MAX).calculate_fee(10_000), 0);
+        assert_eq!(fee(100, u64::MAX).calculate_fee(0), 0);
+
+        // Without the epoch, we take the higher fee.
+        let mint_info = MintInfo {
+            decimals: 6,
+            transfer_fee_config: Some(TransferFeeConfig {
+                older: fee(100, 50),
+                newer: fee(50, u64::MAX),
+            }),
+        };
+        assert_eq!(mint_info.transfer_fee(1_000), 10);
+        assert_eq!(mint_info.transfer_fee(1_000_000), 5_000);
+        assert_eq!(mint_info.transfer_fee_remainder(), 0.99);
+        assert_eq!(mint_info.transfer_fee_remaining_fraction(), (9_900, 10_000));
+
+        let mint_info = MintInfo {
+            decimals: 6,
+            transfer_fee_config: None,
+        };
+        assert_eq!(mint_info.transfer_fee(1_000_000), 0);
+        assert_eq!(mint_info.transfer_fee_remainder(), 1.0);
+        assert_eq!(
+            mint_info.transfer_fee_remaining_fraction(),
+            (10_000, 10_000)
+        );
+    }
+}
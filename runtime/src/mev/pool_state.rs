@@ -0,0 +1,1531 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use serde::{
+    ser::{SerializeMap, SerializeStruct},
+    Serialize, Serializer,
+};
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount},
+    pubkey::Pubkey,
+};
+use spl_token::solana_program::program_pack::Pack;
+use spl_token_swap::{
+    curve::{
+        base::{CurveType, SwapCurve},
+        calculator::{CurveCalculator, SwapWithoutFeesResult},
+    },
+    state::SwapVersion,
+};
+use thiserror::Error;
+
+use crate::{
+    accounts::{
+        LoadedTransaction,
+        MevAccountOrIdx::{Idx, ReadAccount},
+        MevAccounts, MevPoolAccounts,
+    },
+    inline_spl_associated_token_account, inline_spl_token, inline_spl_token_2022,
+};
+
+use super::{
+    arbitrage::TradeDirection,
+    mint::{MintInfo, TransferFeeConfig},
+    utils::{deserialize_b58, deserialize_opt_b58, serialize_b58, serialize_opt_b58},
+};
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrcaPoolAddresses {
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub(super) program_id: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub(super) address: Pubkey,
+
+    /// Source address, owned by the pool.
+    #[serde(serialize_with = "serialize_b58")]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub(super) pool_a_account: Pubkey,
+
+    /// Destination address, owned by the pool.
+    #[serde(serialize_with = "serialize_b58")]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub(super) pool_b_account: Pubkey,
+
+    /// Source address, owned by us.
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    #[serde(deserialize_with = "deserialize_opt_b58")]
+    pub source: Option<Pubkey>,
+
+    /// Destination address, owned by us.
+    #[serde(default)]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    #[serde(deserialize_with = "deserialize_opt_b58")]
+    pub destination: Option<Pubkey>,
+
+    /// Pool's mint account.
+    #[serde(serialize_with = "serialize_b58")]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub pool_mint: Pubkey,
+
+    /// Pool's fee account.
+    #[serde(serialize_with = "serialize_b58")]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub pool_fee: Pubkey,
+
+    /// Our account of the pool's pool token that receives the host fee, for
+    /// pools where we are the registered host. Set in the config.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(serialize_with = "serialize_opt_b58")]
+    #[serde(deserialize_with = "deserialize_opt_b58")]
+    pub host_fee: Option<Pubkey>,
+
+    /// Calculated by us from the pool's data.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub pool_authority: Pubkey,
+
+    /// The token program that owns the pool's token accounts, SPL Token or
+    /// Token-2022. We read it from the owner of the accounts when we load the
+    /// pool.
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub token_program: Pubkey,
+
+    /// Mint of pool's a account. We read it from the pool's data, but it can
+    /// also be set in the config, so we can look up pools by token pair.
+    #[serde(skip_serializing)]
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub pool_a_mint: Pubkey,
+
+    /// Mint of pool's b account, like `pool_a_mint`.
+    #[serde(skip_serializing)]
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_b58")]
+    pub pool_b_mint: Pubkey,
+
+    /// Set in the config. We only log it for observe pools.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "PoolRole::is_trade")]
+    pub role: PoolRole,
+}
+
+/// What we use a pool from the config for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolRole {
+    /// Paths may trade through the pool.
+    Trade,
+
+    /// We load and log the pool's state, e.g. as a price reference, but never
+    /// trade through it. We lock its accounts read-only, and no path may use
+    /// it.
+    Observe,
+}
+
+impl Default for PoolRole {
+    fn default() -> Self {
+        PoolRole::Trade
+    }
+}
+
+impl PoolRole {
+    pub fn is_trade(&self) -> bool {
+        *self == PoolRole::Trade
+    }
+}
+
+impl OrcaPoolAddresses {
+    /// (`pool_a_mint`, `pool_b_mint`) when both are set, which for the pools
+    /// from the config means that they were set in the config.
+    pub fn known_mints(&self) -> Option<(Pubkey, Pubkey)> {
+        if self.pool_a_mint == Pubkey::default() || self.pool_b_mint == Pubkey::default() {
+            return None;
+        }
+        Some((self.pool_a_mint, self.pool_b_mint))
+    }
+
+    /// Mints as (`from`, `to`) for a trade in `direction`, when known.
+    pub fn known_mints_in_direction(&self, direction: &TradeDirection) -> Option<(Pubkey, Pubkey)> {
+        let (mint_a, mint_b) = self.known_mints()?;
+        match direction {
+            TradeDirection::AtoB => Some((mint_a, mint_b)),
+            TradeDirection::BtoA => Some((mint_b, mint_a)),
+        }
+    }
+
+    /// Set `source` and `destination`, where they are not set yet, to the
+    /// associated token accounts of `authority` for `pool_a_mint` and
+    /// `pool_b_mint`. Does nothing when the mints are not known.
+    pub fn derive_token_accounts(&mut self, authority: &Pubkey) {
+        if let Some((mint_a, mint_b)) = self.known_mints() {
+            self.source = self
+                .source
+                .or_else(|| Some(associated_token_address(authority, &mint_a)));
+            self.destination = self
+                .destination
+                .or_else(|| Some(associated_token_address(authority, &mint_b)));
+        }
+    }
+}
+
+/// The associated token account of `owner` for `mint`, for the SPL token
+/// program.
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    let (address, _bump_seed) = Pubkey::find_program_address(
+        &[
+            owner.as_ref(),
+            inline_spl_token::id().as_ref(),
+            mint.as_ref(),
+        ],
+        &inline_spl_associated_token_account::id(),
+    );
+    address
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrcaPoolWithBalance {
+    pub(super) pool: OrcaPoolAddresses,
+    pub(super) pool_a_balance: u64,
+    pub(super) pool_b_balance: u64,
+    pub(super) source_balance: Option<u64>,
+    pub(super) destination_balance: Option<u64>,
+    pub(super) fees: Fees,
+
+    /// What we read from the mints of the pool's tokens, when their keys were
+    /// loaded with the pool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) pool_a_mint_info: Option<MintInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) pool_b_mint_info: Option<MintInfo>,
+
+    /// The kind of `curve_calculator`, so that we can pack the curve again.
+    #[serde(skip_serializing)]
+    pub(super) curve_type: CurveType,
+    #[serde(skip_serializing)]
+    pub(super) curve_calculator: Arc<dyn CurveCalculator + Sync + Send>,
+}
+
+impl OrcaPoolWithBalance {
+    /// State of a pool that is not loaded from its accounts, e.g. in
+    /// benchmarks. Only the mints of the addresses are known, and we do not
+    /// know our token balances.
+    pub fn new(
+        pool_a_mint: Pubkey,
+        pool_b_mint: Pubkey,
+        pool_a_balance: u64,
+        pool_b_balance: u64,
+        fees: Fees,
+        swap_curve: SwapCurve,
+    ) -> Self {
+        OrcaPoolWithBalance {
+            pool: OrcaPoolAddresses {
+                pool_a_mint,
+                pool_b_mint,
+                ..OrcaPoolAddresses::default()
+            },
+            pool_a_balance,
+            pool_b_balance,
+            source_balance: None,
+            destination_balance: None,
+            fees,
+            pool_a_mint_info: None,
+            pool_b_mint_info: None,
+            curve_type: swap_curve.curve_type,
+            curve_calculator: swap_curve.calculator,
+        }
+    }
+
+    /// Whether both reserves of the pool are at least `min_reserve`, and not
+    /// empty. The price of a drained pool is meaningless, and the float math
+    /// of the optimal input divides by its reserves.
+    pub fn has_reserves(&self, min_reserve: u64) -> bool {
+        let min_reserve = min_reserve.max(1);
+        self.pool_a_balance >= min_reserve && self.pool_b_balance >= min_reserve
+    }
+
+    /// Balances of the pool's token accounts as (`from`, `to`) for a trade in
+    /// `direction`.
+    pub fn get_balances(&self, direction: &TradeDirection) -> (u64, u64) {
+        match direction {
+            TradeDirection::AtoB => (self.pool_a_balance, self.pool_b_balance),
+            TradeDirection::BtoA => (self.pool_b_balance, self.pool_a_balance),
+        }
+    }
+
+    /// What we read from the mints of the tokens that go (`in`, `out`) of the
+    /// pool in `direction`.
+    pub fn get_mint_infos(
+        &self,
+        direction: &TradeDirection,
+    ) -> (Option<MintInfo>, Option<MintInfo>) {
+        match direction {
+            TradeDirection::AtoB => (self.pool_a_mint_info, self.pool_b_mint_info),
+            TradeDirection::BtoA => (self.pool_b_mint_info, self.pool_a_mint_info),
+        }
+    }
+
+    /// Amount of tokens we get out of the pool when we put `amount_in` in, in
+    /// `direction`, after fees.
+    pub fn get_swap_output(&self, direction: &TradeDirection, amount_in: u128) -> Option<u128> {
+        let (_, _, amount_out) = self.get_swap_amounts(direction, amount_in)?;
+        Some(amount_out)
+    }
+
+    /// What a swap of `amount_in` in `direction` moves, as (what the pool
+    /// receives, what the pool sends, what we receive). A Token-2022 mint with
+    /// a transfer fee withholds the fee from each transfer, so the pool swaps
+    /// `amount_in` less the fee of the input mint, and we receive the output of
+    /// the curve less the fee of the output mint.
+    fn get_swap_amounts(
+        &self,
+        direction: &TradeDirection,
+        amount_in: u128,
+    ) -> Option<(u128, u128, u128)> {
+        let (mint_in, mint_out) = self.get_mint_infos(direction);
+        let transfer_fee = |mint_info: Option<MintInfo>, amount: u128| {
+            mint_info.map_or(0, |mint_info| mint_info.transfer_fee(amount))
+        };
+
+        let pool_amount_in = amount_in.checked_sub(transfer_fee(mint_in, amount_in))?;
+        let source_amount_less_fees = self.fees.apply_fees(pool_amount_in)?;
+
+        let (swap_source_amount, swap_destination_amount) = self.get_balances(direction);
+        let trade_direction = match direction {
+            TradeDirection::AtoB => spl_token_swap::curve::calculator::TradeDirection::AtoB,
+            TradeDirection::BtoA => spl_token_swap::curve::calculator::TradeDirection::BtoA,
+        };
+
+        // For the Constant Product Curve the `trade_direction` is
+        // ignored and it's our responsibility to provide the right
+        // token's balance from the pool.
+        let SwapWithoutFeesResult {
+            source_amount_swapped: _,
+            destination_amount_swapped,
+        } = self.curve_calculator.swap_without_fees(
+            source_amount_less_fees,
+            swap_source_amount as u128,
+            swap_destination_amount as u128,
+            // Again, this argument is useless!
+            trade_direction,
+        )?;
+        let amount_out = destination_amount_swapped
+            .checked_sub(transfer_fee(mint_out, destination_amount_swapped))?;
+        Some((pool_amount_in, destination_amount_swapped, amount_out))
+    }
+
+    /// Update the balances as if someone swapped `amount_in` into the pool in
+    /// `direction`, and return what they got out. The pool's fees stay in the
+    /// pool, so the `from` side grows by all of `amount_in` that reaches it,
+    /// which is less than `amount_in` only for a mint with a transfer fee.
+    pub fn apply_swap(&mut self, direction: &TradeDirection, amount_in: u64) -> Option<u64> {
+        let (pool_amount_in, pool_amount_out, amount_out) =
+            self.get_swap_amounts(direction, amount_in as u128)?;
+        let amount_out = u64::try_from(amount_out).ok()?;
+        let (balance_from, balance_to) = match direction {
+            TradeDirection::AtoB => (&mut self.pool_a_balance, &mut self.pool_b_balance),
+            TradeDirection::BtoA => (&mut self.pool_b_balance, &mut self.pool_a_balance),
+        };
+        let (new_balance_from, new_balance_to) = (
+            balance_from.checked_add(u64::try_from(pool_amount_in).ok()?)?,
+            balance_to.checked_sub(u64::try_from(pool_amount_out).ok()?)?,
+        );
+        *balance_from = new_balance_from;
+        *balance_to = new_balance_to;
+        Some(amount_out)
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FeeError {
+    #[error("the {0} fee has a numerator but a zero denominator")]
+    ZeroDenominator(&'static str),
+
+    #[error("the trade and owner fees together take 100% or more of the input")]
+    FeeTooHigh,
+}
+
+/// The fees of an Orca pool. Only the trade fee and the owner trade fee reduce
+/// the amount that we swap, the host fee is a share of the owner fee.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fees(pub(super) spl_token_swap::curve::fees::Fees);
+
+impl Fees {
+    pub fn new(fees: spl_token_swap::curve::fees::Fees) -> Self {
+        Fees(fees)
+    }
+
+    /// Fees with only a trade fee and an owner trade fee, as most pools have.
+    pub fn from_trade_fees(
+        trade_fee_numerator: u64,
+        trade_fee_denominator: u64,
+        owner_trade_fee_numerator: u64,
+        owner_trade_fee_denominator: u64,
+    ) -> Self {
+        Fees(spl_token_swap::curve::fees::Fees {
+            trade_fee_numerator,
+            trade_fee_denominator,
+            owner_trade_fee_numerator,
+            owner_trade_fee_denominator,
+            owner_withdraw_fee_numerator: 0,
+            owner_withdraw_fee_denominator: 0,
+            host_fee_numerator: 0,
+            host_fee_denominator: 0,
+        })
+    }
+
+    /// Fraction of the input that the fees take, when we ignore rounding.
+    pub fn total_fee_fraction(&self) -> f64 {
+        let fraction = |numerator: u64, denominator: u64| {
+            if numerator == 0 {
+                0_f64
+            } else {
+                numerator as f64 / denominator as f64
+            }
+        };
+        fraction(self.0.trade_fee_numerator, self.0.trade_fee_denominator)
+            + fraction(
+                self.0.owner_trade_fee_numerator,
+                self.0.owner_trade_fee_denominator,
+            )
+    }
+
+    /// The fraction of the input that remains after fees, when we ignore
+    /// rounding, as (numerator, denominator). Returns `None` when the fees
+    /// take more than the input, or when the fraction does not fit a `u128`.
+    pub fn remaining_fraction(&self) -> Option<(u128, u128)> {
+        [
+            (self.0.trade_fee_numerator, self.0.trade_fee_denominator),
+            (
+                self.0.owner_trade_fee_numerator,
+                self.0.owner_trade_fee_denominator,
+            ),
+        ]
+        .into_iter()
+        // A zero numerator means no fee, like in `total_fee_fraction`.
+        .filter(|(numerator, _denominator)| *numerator != 0)
+        .try_fold(
+            (1_u128, 1_u128),
+            |(numerator, denominator), (fee_numerator, fee_denominator)| {
+                let (fee_numerator, fee_denominator) =
+                    (fee_numerator as u128, fee_denominator as u128);
+                Some((
+                    numerator
+                        .checked_mul(fee_denominator)?
+                        .checked_sub(fee_numerator.checked_mul(denominator)?)?,
+                    denominator.checked_mul(fee_denominator)?,
+                ))
+            },
+        )
+    }
+
+    /// The part of `amount` that remains after fees, rounded like the token
+    /// swap program does. Returns `None` when the fees are invalid or take
+    /// more than `amount`.
+    pub fn apply_fees(&self, amount: u128) -> Option<u128> {
+        let trade_fee = self.0.trading_fee(amount)?;
+        let owner_fee = self.0.owner_trading_fee(amount)?;
+        amount.checked_sub(trade_fee.checked_add(owner_fee)?)
+    }
+
+    /// Check that we can compute with the fees. A zero denominator is fine
+    /// when the numerator is zero too, that means there is no such fee.
+    pub fn validate(&self) -> Result<(), FeeError> {
+        let fees = [
+            (
+                "trade",
+                self.0.trade_fee_numerator,
+                self.0.trade_fee_denominator,
+            ),
+            (
+                "owner trade",
+                self.0.owner_trade_fee_numerator,
+                self.0.owner_trade_fee_denominator,
+            ),
+            (
+                "host",
+                self.0.host_fee_numerator,
+                self.0.host_fee_denominator,
+            ),
+        ];
+        for (name, numerator, denominator) in fees {
+            if numerator != 0 && denominator == 0 {
+                return Err(FeeError::ZeroDenominator(name));
+            }
+        }
+
+        // Compare trade/trade_den + owner/owner_den >= 1 without rounding.
+        let as_fraction = |numerator: u64, denominator: u64| {
+            if numerator == 0 {
+                (0_u128, 1_u128)
+            } else {
+                (numerator as u128, denominator as u128)
+            }
+        };
+        let (trade_numerator, trade_denominator) =
+            as_fraction(self.0.trade_fee_numerator, self.0.trade_fee_denominator);
+        let (owner_numerator, owner_denominator) = as_fraction(
+            self.0.owner_trade_fee_numerator,
+            self.0.owner_trade_fee_denominator,
+        );
+        if trade_numerator * owner_denominator + owner_numerator * trade_denominator
+            >= trade_denominator * owner_denominator
+        {
+            return Err(FeeError::FeeTooHigh);
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Fees {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct("Fees", 3)?;
+        state.serialize_field("host_fee_denominator", &self.0.host_fee_denominator)?;
+        state.serialize_field("host_fee_numerator", &self.0.host_fee_numerator)?;
+        state.serialize_field(
+            "owner_trade_fee_denominator",
+            &self.0.owner_trade_fee_denominator,
+        )?;
+        state.serialize_field(
+            "owner_trade_fee_numerator",
+            &self.0.owner_trade_fee_numerator,
+        )?;
+        state.serialize_field("trade_fee_denominator", &self.0.trade_fee_denominator)?;
+        state.serialize_field("trade_fee_numerator", &self.0.trade_fee_numerator)?;
+        state.end()
+    }
+}
+
+/// The reserves, fees and curve of a pool, all that we need of it to evaluate
+/// paths through it, see `PoolStates::from_balances`.
+#[derive(Debug)]
+pub struct PoolBalances {
+    pub pool_a_balance: u64,
+    pub pool_b_balance: u64,
+    pub fees: Fees,
+    /// The curve type of the pool with its parameters, e.g. the
+    /// amplification coefficient of a stable curve.
+    pub swap_curve: SwapCurve,
+}
+
+// A map from `Pubkey` as `String` to `OrcaPoolWithBalance` so it's easier to
+// serialize with `serde_json`
+#[derive(Debug)]
+pub struct PoolStates(pub(super) HashMap<Pubkey, OrcaPoolWithBalance>);
+
+impl FromIterator<(Pubkey, OrcaPoolWithBalance)> for PoolStates {
+    fn from_iter<T: IntoIterator<Item = (Pubkey, OrcaPoolWithBalance)>>(iter: T) -> Self {
+        let hashmap = HashMap::from_iter(iter);
+        PoolStates(hashmap)
+    }
+}
+
+impl Serialize for PoolStates {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in &self.0 {
+            map.serialize_entry(&k.to_string(), &v)?;
+        }
+        map.end()
+    }
+}
+
+/// Version of the layout of `PoolStates::to_snapshot_bytes`, in the first two
+/// bytes of every snapshot. Bump it on every change to `PoolSnapshot`.
+pub const POOL_STATES_SNAPSHOT_VERSION: u16 = 3;
+
+/// A pool in a snapshot, with every field of `OrcaPoolWithBalance`. The fees
+/// and the curve are packed as in the state of the token swap program.
+#[derive(Serialize, Deserialize)]
+struct PoolSnapshot {
+    program_id: Pubkey,
+    address: Pubkey,
+    pool_a_account: Pubkey,
+    pool_b_account: Pubkey,
+    source: Option<Pubkey>,
+    destination: Option<Pubkey>,
+    pool_mint: Pubkey,
+    pool_fee: Pubkey,
+    pool_authority: Pubkey,
+    token_program: Pubkey,
+    pool_a_mint: Pubkey,
+    pool_b_mint: Pubkey,
+    role: PoolRole,
+    pool_a_balance: u64,
+    pool_b_balance: u64,
+    source_balance: Option<u64>,
+    destination_balance: Option<u64>,
+    fees: Vec<u8>,
+    swap_curve: Vec<u8>,
+    pool_a_mint_info: Option<MintSnapshot>,
+    pool_b_mint_info: Option<MintSnapshot>,
+}
+
+/// A `MintInfo` in a snapshot. bincode needs every field, also the ones that
+/// we leave out of the JSON log when they are not set.
+#[derive(Serialize, Deserialize)]
+struct MintSnapshot {
+    decimals: u8,
+    transfer_fee_config: Option<TransferFeeConfig>,
+}
+
+impl From<MintInfo> for MintSnapshot {
+    fn from(mint_info: MintInfo) -> Self {
+        MintSnapshot {
+            decimals: mint_info.decimals,
+            transfer_fee_config: mint_info.transfer_fee_config,
+        }
+    }
+}
+
+impl From<MintSnapshot> for MintInfo {
+    fn from(mint_snapshot: MintSnapshot) -> Self {
+        MintInfo {
+            decimals: mint_snapshot.decimals,
+            transfer_fee_config: mint_snapshot.transfer_fee_config,
+        }
+    }
+}
+
+impl PoolSnapshot {
+    fn new(pool_state: &OrcaPoolWithBalance) -> Self {
+        let pool = &pool_state.pool;
+        let mut fees = vec![0; spl_token_swap::curve::fees::Fees::LEN];
+        pool_state.fees.0.pack_into_slice(&mut fees);
+        let mut swap_curve = vec![0; SwapCurve::LEN];
+        SwapCurve {
+            curve_type: pool_state.curve_type,
+            calculator: pool_state.curve_calculator.clone(),
+        }
+        .pack_into_slice(&mut swap_curve);
+        PoolSnapshot {
+            program_id: pool.program_id,
+            address: pool.address,
+            pool_a_account: pool.pool_a_account,
+            pool_b_account: pool.pool_b_account,
+            source: pool.source,
+            destination: pool.destination,
+            pool_mint: pool.pool_mint,
+            pool_fee: pool.pool_fee,
+            pool_authority: pool.pool_authority,
+            token_program: pool.token_program,
+            pool_a_mint: pool.pool_a_mint,
+            pool_b_mint: pool.pool_b_mint,
+            role: pool.role,
+            pool_a_balance: pool_state.pool_a_balance,
+            pool_b_balance: pool_state.pool_b_balance,
+            source_balance: pool_state.source_balance,
+            destination_balance: pool_state.destination_balance,
+            fees,
+            swap_curve,
+            pool_a_mint_info: pool_state.pool_a_mint_info.map(MintSnapshot::from),
+            pool_b_mint_info: pool_state.pool_b_mint_info.map(MintSnapshot::from),
+        }
+    }
+
+    fn into_pool_state(self) -> Result<OrcaPoolWithBalance, String> {
+        let fees = spl_token_swap::curve::fees::Fees::unpack_unchecked(&self.fees)
+            .map_err(|_| format!("pool {} has invalid fees", self.address))?;
+        let swap_curve = SwapCurve::unpack_unchecked(&self.swap_curve)
+            .map_err(|_| format!("pool {} has an invalid curve", self.address))?;
+        Ok(OrcaPoolWithBalance {
+            pool: OrcaPoolAddresses {
+                program_id: self.program_id,
+                address: self.address,
+                pool_a_account: self.pool_a_account,
+                pool_b_account: self.pool_b_account,
+                source: self.source,
+                destination: self.destination,
+                pool_mint: self.pool_mint,
+                pool_fee: self.pool_fee,
+                // Only the config knows the host fee account, the engine sets
+                // it.
+                host_fee: None,
+                pool_authority: self.pool_authority,
+                token_program: self.token_program,
+                pool_a_mint: self.pool_a_mint,
+                pool_b_mint: self.pool_b_mint,
+                role: self.role,
+            },
+            pool_a_balance: self.pool_a_balance,
+            pool_b_balance: self.pool_b_balance,
+            source_balance: self.source_balance,
+            destination_balance: self.destination_balance,
+            fees: Fees(fees),
+            pool_a_mint_info: self.pool_a_mint_info.map(MintInfo::from),
+            pool_b_mint_info: self.pool_b_mint_info.map(MintInfo::from),
+            curve_type: swap_curve.curve_type,
+            curve_calculator: swap_curve.calculator,
+        })
+    }
+}
+
+/// What went wrong loading an account of a pool, see `PoolLoadError`.
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PoolLoadErrorKind {
+    /// The account was not loaded with the transaction, or it holds no
+    /// lamports, i.e. it does not exist.
+    #[error("the account does not exist")]
+    MissingAccount,
+
+    #[error("the account is owned by {actual}, expected {expected}")]
+    OwnerMismatch {
+        #[serde(serialize_with = "serialize_b58")]
+        expected: Pubkey,
+        #[serde(serialize_with = "serialize_b58")]
+        actual: Pubkey,
+    },
+
+    #[error("the account does not hold a token swap state")]
+    UnpackSwapState,
+
+    #[error("the account does not hold an SPL token account")]
+    UnpackTokenAccount,
+
+    #[error("the account does not hold an SPL token mint")]
+    UnpackMint,
+}
+
+/// Why we could not load the state of `pool` from the accounts that were
+/// loaded with a transaction: `account` is the pool's account that failed.
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq, Serialize)]
+#[error("pool {pool}, account {account}: {kind}")]
+pub struct PoolLoadError {
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool: Pubkey,
+
+    #[serde(serialize_with = "serialize_b58")]
+    pub account: Pubkey,
+
+    #[serde(flatten)]
+    pub kind: PoolLoadErrorKind,
+}
+
+/// Counts of `PoolLoadError`s by kind, since we last reported them.
+#[derive(Debug, Default)]
+pub struct PoolLoadErrorCounters {
+    missing_account: AtomicU64,
+    owner_mismatch: AtomicU64,
+    unpack_swap_state: AtomicU64,
+    unpack_token_account: AtomicU64,
+    unpack_mint: AtomicU64,
+}
+
+impl PoolLoadErrorCounters {
+    pub fn record(&self, errors: &[PoolLoadError]) {
+        for error in errors {
+            let counter = match error.kind {
+                PoolLoadErrorKind::MissingAccount => &self.missing_account,
+                PoolLoadErrorKind::OwnerMismatch { .. } => &self.owner_mismatch,
+                PoolLoadErrorKind::UnpackSwapState => &self.unpack_swap_state,
+                PoolLoadErrorKind::UnpackTokenAccount => &self.unpack_token_account,
+                PoolLoadErrorKind::UnpackMint => &self.unpack_mint,
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Report the counts, and reset them.
+    pub fn report_metrics(&self) {
+        // `datapoint_info!` only evaluates its fields when metrics are
+        // enabled, so we reset the counters outside of it.
+        let missing_account = self.missing_account.swap(0, Ordering::Relaxed);
+        let owner_mismatch = self.owner_mismatch.swap(0, Ordering::Relaxed);
+        let unpack_swap_state = self.unpack_swap_state.swap(0, Ordering::Relaxed);
+        let unpack_token_account = self.unpack_token_account.swap(0, Ordering::Relaxed);
+        let unpack_mint = self.unpack_mint.swap(0, Ordering::Relaxed);
+        datapoint_info!(
+            "mev-pool-load-errors",
+            ("missing_account", missing_account as i64, i64),
+            ("owner_mismatch", owner_mismatch as i64, i64),
+            ("unpack_swap_state", unpack_swap_state as i64, i64),
+            ("unpack_token_account", unpack_token_account as i64, i64),
+            ("unpack_mint", unpack_mint as i64, i64),
+        );
+    }
+}
+
+/// The account `pubkey` as loaded with `loaded_transaction`, if it exists.
+fn get_loaded_account<'a>(
+    loaded_transaction: &'a LoadedTransaction,
+    mev_accounts: &'a MevAccounts,
+    pubkey: &Pubkey,
+) -> Option<&'a AccountSharedData> {
+    let account = match mev_accounts.pubkey_account_map.get(pubkey)? {
+        Idx(idx) => &loaded_transaction.accounts.get(*idx)?.1,
+        ReadAccount((_pubkey, account)) => account,
+    };
+    Some(account).filter(|account| account.lamports() > 0)
+}
+
+/// The lamports of the user authorities that were loaded for MEV along with
+/// the transaction, they pay the fees of our transactions. An authority that
+/// does not exist has none.
+pub fn loaded_user_authority_balances(
+    loaded_transaction: &LoadedTransaction,
+) -> HashMap<Pubkey, u64> {
+    let mev_accounts = match &loaded_transaction.mev_accounts {
+        Some(mev_accounts) => mev_accounts,
+        None => return HashMap::new(),
+    };
+    mev_accounts
+        .user_authorities
+        .iter()
+        .map(|user_authority| {
+            let lamports = get_loaded_account(loaded_transaction, mev_accounts, user_authority)
+                .map_or(0, |account| account.lamports());
+            (*user_authority, lamports)
+        })
+        .collect()
+}
+
+/// Load the state of the pool with `mev_account`s from the accounts that
+/// `find_account` returns. The owner of the pool's a account, SPL Token or
+/// Token-2022, has to own all other token accounts and mints of the pool.
+fn load_pool_state<'a>(
+    find_account: impl Fn(&Pubkey) -> Option<&'a AccountSharedData>,
+    mev_account: &MevPoolAccounts,
+) -> Result<OrcaPoolWithBalance, PoolLoadError> {
+    let error = |account: &Pubkey, kind: PoolLoadErrorKind| PoolLoadError {
+        pool: mev_account.pool,
+        account: *account,
+        kind,
+    };
+    let get_account = |pubkey: &Pubkey| {
+        find_account(pubkey).ok_or_else(|| error(pubkey, PoolLoadErrorKind::MissingAccount))
+    };
+    let token_program = *get_account(&mev_account.token_a)?.owner();
+    if token_program != inline_spl_token::id() && token_program != inline_spl_token_2022::id() {
+        return Err(error(
+            &mev_account.token_a,
+            PoolLoadErrorKind::OwnerMismatch {
+                expected: inline_spl_token::id(),
+                actual: token_program,
+            },
+        ));
+    }
+    let get_token_program_account = |pubkey: &Pubkey| {
+        let account = get_account(pubkey)?;
+        if *account.owner() != token_program {
+            return Err(error(
+                pubkey,
+                PoolLoadErrorKind::OwnerMismatch {
+                    expected: token_program,
+                    actual: *account.owner(),
+                },
+            ));
+        }
+        Ok(account)
+    };
+    // A Token-2022 account starts like an SPL Token account, its extensions
+    // follow.
+    let get_token_account = |pubkey: &Pubkey| {
+        let data = get_token_program_account(pubkey)?.data();
+        let data = match data.get(..spl_token::state::Account::LEN) {
+            Some(base) if token_program == inline_spl_token_2022::id() => base,
+            _ => data,
+        };
+        spl_token::state::Account::unpack(data)
+            .map_err(|_| error(pubkey, PoolLoadErrorKind::UnpackTokenAccount))
+    };
+    // A mint that the pool's token account does not hold, e.g. from a wrong
+    // config, tells us nothing about the pool, so we ignore it.
+    let get_mint_info = |mint: Option<Pubkey>, token_account: &spl_token::state::Account| {
+        let mint = match mint {
+            Some(mint) if mint.to_bytes() == token_account.mint.to_bytes() => mint,
+            _ => return Ok(None),
+        };
+        MintInfo::unpack(get_token_program_account(&mint)?.data())
+            .map(Some)
+            .ok_or_else(|| error(&mint, PoolLoadErrorKind::UnpackMint))
+    };
+
+    let pool_acc = get_account(&mev_account.pool)?;
+    // Owner of the pool should be the `program_id`.
+    let program_id = pool_acc.owner();
+    let (pool_authority, _authority_bump_seed) =
+        Pubkey::find_program_address(&[&mev_account.pool.to_bytes()[..]], program_id);
+    let pool = SwapVersion::unpack(pool_acc.data())
+        .map_err(|_| error(&mev_account.pool, PoolLoadErrorKind::UnpackSwapState))?;
+
+    let pool_a_account = get_token_account(&mev_account.token_a)?;
+    let pool_b_account = get_token_account(&mev_account.token_b)?;
+    let source_balance = mev_account
+        .source
+        .as_ref()
+        .map(|src| get_token_account(src).map(|spl_acc| spl_acc.amount))
+        .transpose()?;
+    let destination_balance = mev_account
+        .destination
+        .as_ref()
+        .map(|dst| get_token_account(dst).map(|spl_acc| spl_acc.amount))
+        .transpose()?;
+    let pool_a_mint_info = get_mint_info(mev_account.token_a_mint, &pool_a_account)?;
+    let pool_b_mint_info = get_mint_info(mev_account.token_b_mint, &pool_b_account)?;
+
+    Ok(OrcaPoolWithBalance {
+        pool: OrcaPoolAddresses {
+            program_id: *program_id,
+            address: mev_account.pool,
+            pool_a_account: mev_account.token_a,
+            pool_b_account: mev_account.token_b,
+            source: mev_account.source,
+            destination: mev_account.destination,
+            pool_mint: mev_account.pool_mint,
+            pool_fee: mev_account.pool_fee,
+            host_fee: None,
+            pool_authority,
+            token_program,
+            pool_a_mint: Pubkey::new(&pool_a_account.mint.to_bytes()),
+            pool_b_mint: Pubkey::new(&pool_b_account.mint.to_bytes()),
+            // Only the config knows the role and the host fee account, the
+            // engine sets them.
+            role: PoolRole::Trade,
+        },
+        pool_a_balance: pool_a_account.amount,
+        pool_b_balance: pool_b_account.amount,
+        fees: Fees(pool.fees().clone()),
+        curve_type: pool.swap_curve().curve_type,
+        curve_calculator: pool.swap_curve().calculator.clone(),
+        source_balance,
+        destination_balance,
+        pool_a_mint_info,
+        pool_b_mint_info,
+    })
+}
+
+/// Load the states of `pool_accounts`, with `find_account` as in
+/// `load_pool_state`. Returns the errors of all pools that we could not load.
+fn load_pool_states<'a>(
+    find_account: impl Fn(&Pubkey) -> Option<&'a AccountSharedData> + Copy,
+    pool_accounts: &[MevPoolAccounts],
+) -> Result<PoolStates, Vec<PoolLoadError>> {
+    let mut pool_states = HashMap::with_capacity(pool_accounts.len());
+    let mut errors = Vec::new();
+    for mev_account in pool_accounts {
+        match load_pool_state(find_account, mev_account) {
+            Ok(pool_state) => {
+                pool_states.insert(mev_account.pool, pool_state);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+    if errors.is_empty() {
+        Ok(PoolStates(pool_states))
+    } else {
+        Err(errors)
+    }
+}
+
+impl PoolStates {
+    /// A compact binary snapshot of the pool states, with everything that we
+    /// need to evaluate paths against them, e.g. to replay many slots without
+    /// parsing the JSON log. Load it with `from_snapshot_bytes`.
+    pub fn to_snapshot_bytes(&self) -> Vec<u8> {
+        let mut pools: Vec<PoolSnapshot> = self.0.values().map(PoolSnapshot::new).collect();
+        // The same states always give the same snapshot.
+        pools.sort_by_key(|pool| pool.address);
+        let mut bytes = POOL_STATES_SNAPSHOT_VERSION.to_le_bytes().to_vec();
+        bincode::serialize_into(&mut bytes, &pools).expect("Pool states should serialize");
+        bytes
+    }
+
+    /// Load a snapshot from `to_snapshot_bytes`, of the current version.
+    pub fn from_snapshot_bytes(bytes: &[u8]) -> Result<PoolStates, String> {
+        let version = match bytes {
+            [low, high, ..] => u16::from_le_bytes([*low, *high]),
+            _ => return Err("the snapshot has no version".to_owned()),
+        };
+        if version != POOL_STATES_SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot version {} is not supported, expected {}",
+                version, POOL_STATES_SNAPSHOT_VERSION
+            ));
+        }
+        let pools: Vec<PoolSnapshot> = bincode::deserialize(&bytes[2..])
+            .map_err(|err| format!("could not read the snapshot: {}", err))?;
+        pools
+            .into_iter()
+            .map(|pool| {
+                let pool_state = pool.into_pool_state()?;
+                Ok((pool_state.pool.address, pool_state))
+            })
+            .collect()
+    }
+
+    /// Attempts to deserialize the Orca accounts that were loaded for MEV
+    /// along with the transaction. Returns `None` when the transaction has no
+    /// MEV accounts, and the errors of all pools that we could not load.
+    pub fn from_loaded_transaction(
+        loaded_transaction: &LoadedTransaction,
+    ) -> Option<Result<PoolStates, Vec<PoolLoadError>>> {
+        let mev_accounts = loaded_transaction.mev_accounts.as_ref()?;
+        Some(load_pool_states(
+            |pubkey| get_loaded_account(loaded_transaction, mev_accounts, pubkey),
+            &mev_accounts.pool_accounts,
+        ))
+    }
+
+    /// Like `from_loaded_transaction`, but from accounts that we got some
+    /// other way, e.g. from an RPC node. Accounts that are not in `accounts`,
+    /// or that hold no lamports, do not exist.
+    pub fn from_accounts(
+        accounts: &HashMap<Pubkey, AccountSharedData>,
+        pool_accounts: &[MevPoolAccounts],
+    ) -> Result<PoolStates, Vec<PoolLoadError>> {
+        load_pool_states(
+            |pubkey| {
+                accounts
+                    .get(pubkey)
+                    .filter(|account| account.lamports() > 0)
+            },
+            pool_accounts,
+        )
+    }
+
+    /// Pool states from plain balances, by pool address, e.g. from historical
+    /// snapshots of the pools' accounts, to evaluate paths against them
+    /// offline. The pools have no mints and none of our token accounts, so we
+    /// can quote paths through them, but not build transactions.
+    pub fn from_balances(pools: impl IntoIterator<Item = (Pubkey, PoolBalances)>) -> PoolStates {
+        pools
+            .into_iter()
+            .map(|(address, balances)| {
+                let mut pool_state = OrcaPoolWithBalance::new(
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    balances.pool_a_balance,
+                    balances.pool_b_balance,
+                    balances.fees,
+                    balances.swap_curve,
+                );
+                pool_state.pool.address = address;
+                (address, pool_state)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::account::WritableAccount;
+
+    use spl_token_swap::curve::stable::StableCurve;
+
+    use super::*;
+    use crate::{
+        inline_spl_token,
+        mev::test_utils::{pool, steth_stsol_usdc_pool_states, transfer_fee_mint_info},
+    };
+
+    #[test]
+    fn test_fees_total_fee_fraction() {
+        assert_eq!(
+            Fees::from_trade_fees(25, 10_000, 5, 10_000).total_fee_fraction(),
+            0.003
+        );
+        // A zero numerator means no fee, whatever the denominator.
+        assert_eq!(Fees::from_trade_fees(0, 0, 0, 0).total_fee_fraction(), 0.0);
+        // The host fee is part of the owner fee.
+        let mut fees = Fees::from_trade_fees(25, 10_000, 5, 10_000);
+        fees.0.host_fee_numerator = 1;
+        fees.0.host_fee_denominator = 5;
+        assert_eq!(fees.total_fee_fraction(), 0.003);
+    }
+
+    #[test]
+    fn test_fees_remaining_fraction() {
+        assert_eq!(
+            Fees::from_trade_fees(25, 10_000, 5, 10_000).remaining_fraction(),
+            Some((99_700_000, 100_000_000))
+        );
+        assert_eq!(
+            Fees::from_trade_fees(0, 0, 0, 0).remaining_fraction(),
+            Some((1, 1))
+        );
+        assert_eq!(Fees::from_trade_fees(1, 2, 2, 3).remaining_fraction(), None);
+    }
+
+    #[test]
+    fn test_fees_apply_fees() {
+        let fees = Fees::from_trade_fees(25, 10_000, 5, 10_000);
+        assert_eq!(fees.apply_fees(1_000_000), Some(997_000));
+        // The token swap program charges at least one token per fee.
+        assert_eq!(fees.apply_fees(10), Some(8));
+        assert_eq!(fees.apply_fees(0), Some(0));
+        assert_eq!(fees.apply_fees(1), None);
+        assert_eq!(
+            Fees::from_trade_fees(0, 0, 0, 0).apply_fees(1_000),
+            Some(1_000)
+        );
+        assert_eq!(Fees::from_trade_fees(1, 0, 0, 0).apply_fees(1_000), None);
+    }
+
+    #[test]
+    fn test_fees_validate() {
+        assert_eq!(
+            Fees::from_trade_fees(25, 10_000, 5, 10_000).validate(),
+            Ok(())
+        );
+        assert_eq!(Fees::from_trade_fees(0, 0, 0, 0).validate(), Ok(()));
+        assert_eq!(
+            Fees::from_trade_fees(25, 0, 5, 10_000).validate(),
+            Err(FeeError::ZeroDenominator("trade"))
+        );
+        assert_eq!(
+            Fees::from_trade_fees(25, 10_000, 5, 0).validate(),
+            Err(FeeError::ZeroDenominator("owner trade"))
+        );
+        let mut fees = Fees::from_trade_fees(25, 10_000, 5, 10_000);
+        fees.0.host_fee_numerator = 1;
+        assert_eq!(fees.validate(), Err(FeeError::ZeroDenominator("host")));
+
+        assert_eq!(
+            Fees::from_trade_fees(1, 2, 1, 2).validate(),
+            Err(FeeError::FeeTooHigh)
+        );
+        assert_eq!(
+            Fees::from_trade_fees(1, 1, 0, 0).validate(),
+            Err(FeeError::FeeTooHigh)
+        );
+        assert_eq!(Fees::from_trade_fees(1, 2, 1, 3).validate(), Ok(()));
+    }
+
+    /// The accounts of a pool with our source account, all loaded read-only,
+    /// with 1_000 tokens in the pool's a account.
+    fn loaded_pool() -> (LoadedTransaction, MevPoolAccounts) {
+        let keys = MevPoolAccounts {
+            pool: Pubkey::new_unique(),
+            source: Some(Pubkey::new_unique()),
+            destination: None,
+            token_a: Pubkey::new_unique(),
+            token_b: Pubkey::new_unique(),
+            pool_mint: Pubkey::new_unique(),
+            pool_fee: Pubkey::new_unique(),
+            pool_authority: Pubkey::new_unique(),
+            token_program: inline_spl_token::id(),
+            token_a_mint: None,
+            token_b_mint: None,
+        };
+        let account = |data: Vec<u8>, owner: Pubkey| {
+            AccountSharedData::from(solana_sdk::account::Account {
+                lamports: 1,
+                data,
+                owner,
+                ..Default::default()
+            })
+        };
+        let mut swap_data = vec![0; SwapVersion::LATEST_LEN];
+        SwapVersion::pack(
+            SwapVersion::SwapV1(spl_token_swap::state::SwapV1 {
+                is_initialized: true,
+                bump_seed: 0,
+                token_program_id: Default::default(),
+                token_a: Default::default(),
+                token_b: Default::default(),
+                pool_mint: Default::default(),
+                token_a_mint: Default::default(),
+                token_b_mint: Default::default(),
+                pool_fee_account: Default::default(),
+                fees: Fees::from_trade_fees(25, 10_000, 5, 10_000).0,
+                swap_curve: Default::default(),
+            }),
+            &mut swap_data,
+        )
+        .unwrap();
+        let token_account = |amount: u64| {
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account::pack(
+                spl_token::state::Account {
+                    mint: spl_token::solana_program::pubkey::Pubkey::new_unique(),
+                    amount,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..Default::default()
+                },
+                &mut data,
+            )
+            .unwrap();
+            account(data, inline_spl_token::id())
+        };
+        let accounts = vec![
+            (keys.pool, account(swap_data, Pubkey::new_unique())),
+            (keys.token_a, token_account(1_000)),
+            (keys.token_b, token_account(2_000)),
+            (keys.source.unwrap(), token_account(10)),
+        ];
+        let loaded_transaction = LoadedTransaction {
+            accounts: vec![],
+            mev_accounts: Some(MevAccounts {
+                pool_accounts: vec![keys.clone()],
+                user_authorities: Vec::new(),
+                pubkey_account_map: accounts
+                    .into_iter()
+                    .map(|(pubkey, account)| (pubkey, ReadAccount((pubkey, account))))
+                    .collect(),
+            }),
+            program_indices: vec![],
+            rent: 0,
+            rent_debits: Default::default(),
+        };
+        (loaded_transaction, keys)
+    }
+
+    /// Change the loaded account `pubkey` with `f`.
+    fn modify_account(
+        loaded_transaction: &mut LoadedTransaction,
+        pubkey: &Pubkey,
+        f: impl FnOnce(&mut AccountSharedData),
+    ) {
+        let mev_accounts = loaded_transaction.mev_accounts.as_mut().unwrap();
+        match mev_accounts.pubkey_account_map.get_mut(pubkey) {
+            Some(ReadAccount((_pubkey, account))) => f(account),
+            _ => panic!("Expected a read-only account"),
+        }
+    }
+
+    #[test]
+    fn test_from_loaded_transaction() {
+        let (loaded_transaction, keys) = loaded_pool();
+        let pool_states = PoolStates::from_loaded_transaction(&loaded_transaction)
+            .unwrap()
+            .unwrap();
+        let pool_state = &pool_states.0[&keys.pool];
+        assert_eq!(pool_state.pool_a_balance, 1_000);
+        assert_eq!(pool_state.pool_b_balance, 2_000);
+        assert_eq!(pool_state.source_balance, Some(10));
+        assert_eq!(pool_state.destination_balance, None);
+        assert_eq!(pool_state.pool.source, keys.source);
+        assert_eq!(pool_state.pool.token_program, inline_spl_token::id());
+
+        // Without MEV accounts, there is nothing to load, which is not an
+        // error.
+        let loaded_transaction = LoadedTransaction {
+            mev_accounts: None,
+            ..loaded_transaction
+        };
+        assert!(PoolStates::from_loaded_transaction(&loaded_transaction).is_none());
+    }
+
+    #[test]
+    fn test_from_loaded_transaction_token_2022() {
+        // Token-2022 accounts with extensions are longer than SPL Token ones.
+        let (mut loaded_transaction, keys) = loaded_pool();
+        for pubkey in [keys.token_a, keys.token_b, keys.source.unwrap()] {
+            modify_account(&mut loaded_transaction, &pubkey, |account| {
+                account.set_owner(inline_spl_token_2022::id());
+                let mut data = account.data().to_vec();
+                data.resize(spl_token::state::Account::LEN + 10, 0);
+                account.set_data(data);
+            });
+        }
+        let pool_states = PoolStates::from_loaded_transaction(&loaded_transaction)
+            .unwrap()
+            .unwrap();
+        let pool_state = &pool_states.0[&keys.pool];
+        assert_eq!(pool_state.pool.token_program, inline_spl_token_2022::id());
+        assert_eq!(pool_state.pool_a_balance, 1_000);
+        assert_eq!(pool_state.source_balance, Some(10));
+
+        // All token accounts of a pool belong to the same token program.
+        modify_account(&mut loaded_transaction, &keys.token_b, |account| {
+            account.set_owner(inline_spl_token::id())
+        });
+        match PoolStates::from_loaded_transaction(&loaded_transaction) {
+            Some(Err(errors)) => assert_eq!(
+                errors,
+                vec![PoolLoadError {
+                    pool: keys.pool,
+                    account: keys.token_b,
+                    kind: PoolLoadErrorKind::OwnerMismatch {
+                        expected: inline_spl_token_2022::id(),
+                        actual: inline_spl_token::id(),
+                    },
+                }]
+            ),
+            _ => panic!("Expected a pool load error"),
+        }
+    }
+
+    #[test]
+    fn test_loaded_user_authority_balances() {
+        let (mut loaded_transaction, _keys) = loaded_pool();
+        let (funded, missing) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mev_accounts = loaded_transaction.mev_accounts.as_mut().unwrap();
+        mev_accounts.user_authorities = vec![funded, missing];
+        mev_accounts.pubkey_account_map.insert(
+            funded,
+            ReadAccount((funded, AccountSharedData::new(5_000, 0, &Pubkey::default()))),
+        );
+        mev_accounts.pubkey_account_map.insert(
+            missing,
+            ReadAccount((missing, AccountSharedData::default())),
+        );
+        assert_eq!(
+            loaded_user_authority_balances(&loaded_transaction),
+            vec![(funded, 5_000), (missing, 0)].into_iter().collect()
+        );
+
+        let loaded_transaction = LoadedTransaction {
+            mev_accounts: None,
+            ..loaded_transaction
+        };
+        assert!(loaded_user_authority_balances(&loaded_transaction).is_empty());
+    }
+
+    #[test]
+    fn test_from_loaded_transaction_with_mints() {
+        let (mut loaded_transaction, mut keys) = loaded_pool();
+        let mev_accounts = loaded_transaction.mev_accounts.as_mut().unwrap();
+        let mint_a = match &mev_accounts.pubkey_account_map[&keys.token_a] {
+            ReadAccount((_pubkey, account)) => {
+                let token_account = spl_token::state::Account::unpack(account.data()).unwrap();
+                Pubkey::new(&token_account.mint.to_bytes())
+            }
+            _ => panic!("Expected a read-only account"),
+        };
+        let mut mint_data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint::pack(
+            spl_token::state::Mint {
+                decimals: 6,
+                is_initialized: true,
+                ..Default::default()
+            },
+            &mut mint_data,
+        )
+        .unwrap();
+        let mint_account = AccountSharedData::from(solana_sdk::account::Account {
+            lamports: 1,
+            data: mint_data,
+            owner: inline_spl_token::id(),
+            ..Default::default()
+        });
+        mev_accounts
+            .pubkey_account_map
+            .insert(mint_a, ReadAccount((mint_a, mint_account)));
+        // The mint of b does not match the pool's token account, so we ignore
+        // it, even though there is no account for it.
+        keys.token_a_mint = Some(mint_a);
+        keys.token_b_mint = Some(Pubkey::new_unique());
+        mev_accounts.pool_accounts = vec![keys.clone()];
+
+        let pool_states = PoolStates::from_loaded_transaction(&loaded_transaction)
+            .unwrap()
+            .unwrap();
+        let pool_state = &pool_states.0[&keys.pool];
+        assert_eq!(
+            pool_state.pool_a_mint_info,
+            Some(MintInfo {
+                decimals: 6,
+                transfer_fee_config: None,
+            })
+        );
+        assert_eq!(pool_state.pool_b_mint_info, None);
+
+        modify_account(&mut loaded_transaction, &mint_a, |account| {
+            account.set_data(vec![0; 3])
+        });
+        assert_eq!(
+            PoolStates::from_loaded_transaction(&loaded_transaction)
+                .unwrap()
+                .unwrap_err(),
+            vec![PoolLoadError {
+                pool: keys.pool,
+                account: mint_a,
+                kind: PoolLoadErrorKind::UnpackMint,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_swap_with_transfer_fees() {
+        let address = Pubkey::new_unique();
+        let (_, without_fees) = pool(address).balances(1_000_000, 1_000_000).build();
+        let (_, mut with_fees) = pool(address)
+            .balances(1_000_000, 1_000_000)
+            .mint_infos(Some(transfer_fee_mint_info(100)), None)
+            .build();
+
+        // 1% of the input in token A does not reach the pool.
+        assert_eq!(
+            with_fees.get_swap_output(&TradeDirection::AtoB, 10_000),
+            without_fees.get_swap_output(&TradeDirection::AtoB, 9_900)
+        );
+        // And 1% of the output in token A, rounded up, does not reach us.
+        let output = without_fees
+            .get_swap_output(&TradeDirection::BtoA, 10_000)
+            .unwrap();
+        assert_eq!(
+            with_fees.get_swap_output(&TradeDirection::BtoA, 10_000),
+            Some(output - (output + 99) / 100)
+        );
+
+        // The pool only receives what is left after the fee, and sends all of
+        // its output.
+        let pool_output = without_fees
+            .get_swap_output(&TradeDirection::AtoB, 9_900)
+            .unwrap() as u64;
+        assert_eq!(
+            with_fees.apply_swap(&TradeDirection::AtoB, 10_000),
+            Some(pool_output)
+        );
+        assert_eq!(with_fees.pool_a_balance, 1_009_900);
+        assert_eq!(with_fees.pool_b_balance, 1_000_000 - pool_output);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut pool_states = steth_stsol_usdc_pool_states();
+        // A curve with parameters, and a pool with everything set.
+        let (address, pool_state) = pool(Pubkey::new_unique())
+            .mints(Pubkey::new_unique(), Pubkey::new_unique())
+            .balances(1_000_000, 1_100_000)
+            .source(Pubkey::new_unique())
+            .source_balance(500)
+            .build();
+        pool_states.0.insert(
+            address,
+            OrcaPoolWithBalance {
+                pool: OrcaPoolAddresses {
+                    role: PoolRole::Observe,
+                    pool_authority: Pubkey::new_unique(),
+                    ..pool_state.pool
+                },
+                pool_a_mint_info: Some(MintInfo {
+                    decimals: 6,
+                    transfer_fee_config: None,
+                }),
+                pool_b_mint_info: Some(transfer_fee_mint_info(100)),
+                curve_type: CurveType::Stable,
+                curve_calculator: Arc::new(StableCurve { amp: 100 }),
+                ..pool_state
+            },
+        );
+
+        let bytes = pool_states.to_snapshot_bytes();
+        assert_eq!(&bytes[..2], &POOL_STATES_SNAPSHOT_VERSION.to_le_bytes());
+        let loaded = PoolStates::from_snapshot_bytes(&bytes).unwrap();
+        assert_eq!(loaded.to_snapshot_bytes(), bytes);
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&pool_states).unwrap()
+        );
+        let loaded_pool = &loaded.0[&address];
+        assert_eq!(loaded_pool.pool, pool_states.0[&address].pool);
+        assert_eq!(loaded_pool.curve_type, CurveType::Stable);
+        for amount_in in [1, 1_000, 100_000] {
+            assert_eq!(
+                loaded_pool.get_swap_output(&TradeDirection::AtoB, amount_in),
+                pool_states.0[&address].get_swap_output(&TradeDirection::AtoB, amount_in)
+            );
+        }
+
+        let mut other_version = bytes.clone();
+        other_version[0] += 1;
+        assert!(PoolStates::from_snapshot_bytes(&other_version).is_err());
+        assert!(PoolStates::from_snapshot_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PoolStates::from_snapshot_bytes(&[]).is_err());
+    }
+
+    #[test]
+    fn test_pool_load_errors() {
+        fn load_error(loaded_transaction: &LoadedTransaction) -> PoolLoadError {
+            match PoolStates::from_loaded_transaction(loaded_transaction) {
+                Some(Err(errors)) => {
+                    assert_eq!(errors.len(), 1);
+                    errors[0]
+                }
+                _ => panic!("Expected a pool load error"),
+            }
+        }
+
+        // An account that does not exist is loaded as an empty account.
+        let (mut loaded_transaction, keys) = loaded_pool();
+        modify_account(&mut loaded_transaction, &keys.token_b, |account| {
+            *account = AccountSharedData::default()
+        });
+        assert_eq!(
+            load_error(&loaded_transaction),
+            PoolLoadError {
+                pool: keys.pool,
+                account: keys.token_b,
+                kind: PoolLoadErrorKind::MissingAccount,
+            }
+        );
+
+        let (mut loaded_transaction, keys) = loaded_pool();
+        let owner = Pubkey::new_unique();
+        modify_account(&mut loaded_transaction, &keys.source.unwrap(), |account| {
+            account.set_owner(owner)
+        });
+        assert_eq!(
+            load_error(&loaded_transaction),
+            PoolLoadError {
+                pool: keys.pool,
+                account: keys.source.unwrap(),
+                kind: PoolLoadErrorKind::OwnerMismatch {
+                    expected: inline_spl_token::id(),
+                    actual: owner,
+                },
+            }
+        );
+
+        let (mut loaded_transaction, keys) = loaded_pool();
+        modify_account(&mut loaded_transaction, &keys.pool, |account| {
+            account.set_data(vec![0; 3])
+        });
+        assert_eq!(
+            load_error(&loaded_transaction),
+            PoolLoadError {
+                pool: keys.pool,
+                account: keys.pool,
+                kind: PoolLoadErrorKind::UnpackSwapState,
+            }
+        );
+
+        let (mut loaded_transaction, keys) = loaded_pool();
+        modify_account(&mut loaded_transaction, &keys.token_a, |account| {
+            account.set_data(vec![0; 3])
+        });
+        let error = load_error(&loaded_transaction);
+        assert_eq!(
+            error,
+            PoolLoadError {
+                pool: keys.pool,
+                account: keys.token_a,
+                kind: PoolLoadErrorKind::UnpackTokenAccount,
+            }
+        );
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "pool": keys.pool.to_string(),
+                "account": keys.token_a.to_string(),
+                "kind": "unpack_token_account",
+            })
+        );
+
+        // Reporting resets the counts, also when metrics are not enabled.
+        let counters = PoolLoadErrorCounters::default();
+        counters.record(&[error, error]);
+        assert_eq!(counters.unpack_token_account.load(Ordering::Relaxed), 2);
+        counters.report_metrics();
+        assert_eq!(counters.unpack_token_account.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+use super::{logging::PrePostPoolStates, utils::serialize_b58};
+
+/// How much every pool contributed to the profit that we realized, and how
+/// often a trigger traded through it, to tell which pools are worth keeping in
+/// the config.
+#[derive(Debug, Default)]
+pub struct PoolContributions {
+    pools: HashMap<Pubkey, PoolContribution>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolContribution {
+    /// Our share of the profit of the successful MEV transactions that traded
+    /// through this pool.
+    pub realized_profit: u64,
+
+    /// Successful MEV transactions that traded through this pool.
+    pub executions: u64,
+
+    /// Triggers that changed the balances of this pool.
+    pub triggers: u64,
+}
+
+/// A row of `PoolContributions::ranked`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RankedPoolContribution {
+    #[serde(serialize_with = "serialize_b58")]
+    pub pool: Pubkey,
+
+    #[serde(flatten)]
+    pub contribution: PoolContribution,
+}
+
+impl PoolContributions {
+    /// Attribute the profit of an executed MEV transaction to the pools it
+    /// traded through, in equal parts. The remainder of the division goes to
+    /// the first pools of the path, so that the parts add up to `profit`.
+    /// Failed transactions did not realize any profit, we ignore them.
+    pub fn record_execution(&mut self, pools: &[Pubkey], profit: u64, is_successful: bool) {
+        if !is_successful || pools.is_empty() {
+            return;
+        }
+        let share = profit / pools.len() as u64;
+        let remainder = profit % pools.len() as u64;
+        for (i, pool) in pools.iter().enumerate() {
+            let contribution = self.pools.entry(*pool).or_default();
+            let extra = if (i as u64) < remainder { 1 } else { 0 };
+            contribution.realized_profit =
+                contribution.realized_profit.saturating_add(share + extra);
+            contribution.executions += 1;
+        }
+    }
+
+    /// Count the trigger for every pool whose balances it changed.
+    pub fn record_trigger(&mut self, pool_states: &PrePostPoolStates) {
+        for (pool, post) in &pool_states.orca_post_tx_pool.0 {
+            let is_changed = pool_states
+                .orca_pre_tx_pool
+                .0
+                .get(pool)
+                .map_or(false, |pre| {
+                    (pre.pool_a_balance, pre.pool_b_balance)
+                        != (post.pool_a_balance, post.pool_b_balance)
+                });
+            if is_changed {
+                self.pools.entry(*pool).or_default().triggers += 1;
+            }
+        }
+    }
+
+    pub fn get(&self, pool: &Pubkey) -> Option<&PoolContribution> {
+        self.pools.get(pool)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// All pools that we saw, by decreasing realized profit, then by
+    /// decreasing number of triggers. Pools at the bottom are candidates to
+    /// drop from the config.
+    pub fn ranked(&self) -> Vec<RankedPoolContribution> {
+        let mut ranked: Vec<RankedPoolContribution> = self
+            .pools
+            .iter()
+            .map(|(pool, contribution)| RankedPoolContribution {
+                pool: *pool,
+                contribution: contribution.clone(),
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            (b.contribution.realized_profit, b.contribution.triggers)
+                .cmp(&(a.contribution.realized_profit, a.contribution.triggers))
+                .then_with(|| a.pool.cmp(&b.pool))
+        });
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{hash::Hash, signature::Signature};
+
+    use super::*;
+    use crate::mev::{arbitrage::TriggerKind, test_utils::pool, PoolStates};
+
+    #[test]
+    fn test_profit_attribution() {
+        let shared = Pubkey::new_unique();
+        let path_1 = [shared, Pubkey::new_unique(), Pubkey::new_unique()];
+        let path_2 = [Pubkey::new_unique(), shared];
+
+        let mut contributions = PoolContributions::default();
+        contributions.record_execution(&path_1, 100, true);
+        contributions.record_execution(&path_2, 51, true);
+        // Failed transactions do not count.
+        contributions.record_execution(&path_2, 1_000, false);
+
+        // 100 / 3 and 51 / 2 leave 1, which goes to the first pool of the path.
+        assert_eq!(
+            contributions.get(&shared),
+            Some(&PoolContribution {
+                realized_profit: 34 + 25,
+                executions: 2,
+                triggers: 0,
+            })
+        );
+        assert_eq!(contributions.get(&path_1[1]).unwrap().realized_profit, 33);
+        assert_eq!(contributions.get(&path_1[2]).unwrap().realized_profit, 33);
+        assert_eq!(contributions.get(&path_2[0]).unwrap().realized_profit, 26);
+        let total: u64 = contributions
+            .ranked()
+            .iter()
+            .map(|row| row.contribution.realized_profit)
+            .sum();
+        assert_eq!(total, 151);
+
+        let ranked: Vec<Pubkey> = contributions.ranked().iter().map(|row| row.pool).collect();
+        assert_eq!(ranked[0], shared);
+        assert_eq!(ranked[ranked.len() - 1], path_2[0]);
+    }
+
+    #[test]
+    fn test_trigger_counts() {
+        let (traded, untouched, idle) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let pool_states = |traded_balance: u64| -> PoolStates {
+            [
+                pool(traded).balances(traded_balance, 1_000).build(),
+                pool(untouched).balances(1_000, 1_000).build(),
+            ]
+            .into_iter()
+            .collect()
+        };
+        let mut contributions = PoolContributions::default();
+        for _ in 0..2 {
+            contributions.record_trigger(&PrePostPoolStates {
+                transaction_hash: Hash::default(),
+                transaction_signature: Signature::default(),
+                slot: 1,
+                trigger_kind: TriggerKind::User,
+                stale_generation: None,
+                trigger_failed: false,
+                orca_pre_tx_pool: pool_states(1_000),
+                orca_post_tx_pool: pool_states(1_100),
+            });
+        }
+        assert_eq!(contributions.get(&traded).unwrap().triggers, 2);
+        assert_eq!(contributions.get(&untouched), None);
+
+        // A pool that only sees triggers ranks above one that we only know of
+        // through an execution without profit.
+        contributions.record_execution(&[idle], 0, true);
+        let ranked: Vec<Pubkey> = contributions.ranked().iter().map(|row| row.pool).collect();
+        assert_eq!(ranked, vec![traded, idle]);
+    }
+}
@@ -0,0 +1,239 @@
+use solana_program_runtime::prioritization_fee::{PrioritizationFeeDetails, PrioritizationFeeType};
+use solana_sdk::{
+    borsh::try_from_slice_unchecked,
+    compute_budget::{self, ComputeBudgetInstruction},
+    transaction::SanitizedTransaction,
+};
+
+/// How to derive the compute unit price of our transaction from the compute
+/// unit price that the transaction which triggered the opportunity paid.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityFeePolicy {
+    /// Pay the same compute unit price as the trigger.
+    Match,
+
+    /// Pay the compute unit price of the trigger times this factor.
+    Multiply(f64),
+
+    /// Always pay this compute unit price, regardless of the trigger.
+    Fixed(u64),
+
+    /// Pay this fraction of the expected profit as priority fee, over the
+    /// compute unit limit of the transaction, regardless of the trigger. We
+    /// can only value the profit in lamports when it is in wSOL, paths that
+    /// start with another token pay no priority fee.
+    ProfitShare(f64),
+}
+
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct PriorityFeeConfig {
+    pub policy: PriorityFeePolicy,
+
+    /// Upper bound for the compute unit price we pay, in micro-lamports.
+    pub max_compute_unit_price: u64,
+}
+
+impl PriorityFeeConfig {
+    /// Get the compute unit price in micro-lamports for our transaction, given
+    /// the compute unit price of the trigger. Returns `None` when we should
+    /// not set a compute unit price at all. With `ProfitShare`, the price
+    /// depends on the opportunity, see `get_profit_share_compute_unit_price`.
+    pub fn get_compute_unit_price(&self, trigger_compute_unit_price: Option<u64>) -> Option<u64> {
+        let trigger_compute_unit_price = trigger_compute_unit_price.unwrap_or(0);
+        let compute_unit_price = match self.policy {
+            PriorityFeePolicy::Match => trigger_compute_unit_price,
+            // The cast saturates, and we cap the result below anyway.
+            PriorityFeePolicy::Multiply(factor) => {
+                (trigger_compute_unit_price as f64 * factor) as u64
+            }
+            PriorityFeePolicy::Fixed(compute_unit_price) => compute_unit_price,
+            PriorityFeePolicy::ProfitShare(_) => 0,
+        };
+        Some(compute_unit_price.min(self.max_compute_unit_price)).filter(|price| *price > 0)
+    }
+
+    /// With `ProfitShare`, get the compute unit price in micro-lamports that
+    /// pays the share of `profit_lamports` for a transaction with
+    /// `compute_unit_limit`. Returns `None` for the other policies, and when
+    /// the share is not worth a micro-lamport per compute unit.
+    pub fn get_profit_share_compute_unit_price(
+        &self,
+        profit_lamports: u64,
+        compute_unit_limit: u32,
+    ) -> Option<u64> {
+        let share = match self.policy {
+            PriorityFeePolicy::ProfitShare(share) => share,
+            _ => return None,
+        };
+        // The cast saturates, and we cap the result below anyway.
+        let compute_unit_price = (profit_lamports as f64 * share * MICRO_LAMPORTS_PER_LAMPORT
+            / compute_unit_limit.max(1) as f64) as u64;
+        Some(compute_unit_price.min(self.max_compute_unit_price)).filter(|price| *price > 0)
+    }
+
+    /// Whether the compute unit price depends on the opportunity, rather than
+    /// on the trigger.
+    pub fn is_profit_share(&self) -> bool {
+        matches!(self.policy, PriorityFeePolicy::ProfitShare(_))
+    }
+}
+
+const MICRO_LAMPORTS_PER_LAMPORT: f64 = 1_000_000.0;
+
+/// The fee in lamports that a transaction pays on top of its signatures, for
+/// `compute_unit_price` micro-lamports per unit of its `compute_unit_limit`.
+pub fn priority_fee_lamports(compute_unit_price: u64, compute_unit_limit: u32) -> u64 {
+    PrioritizationFeeDetails::new(
+        PrioritizationFeeType::ComputeUnitPrice(compute_unit_price),
+        compute_unit_limit as u64,
+    )
+    .get_fee()
+}
+
+/// Get the compute unit price, in micro-lamports, that `tx` sets with a
+/// `ComputeBudgetInstruction::SetComputeUnitPrice`, if any.
+pub fn get_compute_unit_price(tx: &SanitizedTransaction) -> Option<u64> {
+    tx.message()
+        .program_instructions_iter()
+        .filter(|(program_id, _instruction)| compute_budget::check_id(program_id))
+        .find_map(
+            |(_program_id, instruction)| match try_from_slice_unchecked(&instruction.data) {
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice(compute_unit_price)) => {
+                    Some(compute_unit_price)
+                }
+                _ => None,
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        hash::Hash, signature::Keypair, signer::Signer, system_instruction,
+        transaction::Transaction,
+    };
+
+    use super::*;
+
+    fn trigger_tx(compute_unit_price: Option<u64>) -> SanitizedTransaction {
+        let payer = Keypair::new();
+        let mut instructions = vec![system_instruction::transfer(
+            &payer.pubkey(),
+            &solana_sdk::pubkey::new_rand(),
+            1,
+        )];
+        if let Some(compute_unit_price) = compute_unit_price {
+            instructions.insert(
+                0,
+                ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price),
+            );
+        }
+        let tx = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::new_unique(),
+        );
+        SanitizedTransaction::try_from_legacy_transaction(tx).unwrap()
+    }
+
+    #[test]
+    fn test_get_compute_unit_price() {
+        assert_eq!(get_compute_unit_price(&trigger_tx(None)), None);
+        assert_eq!(
+            get_compute_unit_price(&trigger_tx(Some(5_000))),
+            Some(5_000)
+        );
+    }
+
+    #[test]
+    fn test_priority_fee_policy() {
+        let config = |policy| PriorityFeeConfig {
+            policy,
+            max_compute_unit_price: 10_000,
+        };
+
+        let match_config = config(PriorityFeePolicy::Match);
+        assert_eq!(match_config.get_compute_unit_price(None), None);
+        assert_eq!(match_config.get_compute_unit_price(Some(500)), Some(500));
+        assert_eq!(
+            match_config.get_compute_unit_price(Some(50_000)),
+            Some(10_000)
+        );
+
+        let multiply_config = config(PriorityFeePolicy::Multiply(1.5));
+        assert_eq!(multiply_config.get_compute_unit_price(None), None);
+        assert_eq!(multiply_config.get_compute_unit_price(Some(500)), Some(750));
+        assert_eq!(
+            multiply_config.get_compute_unit_price(Some(u64::MAX)),
+            Some(10_000)
+        );
+
+        let fixed_config = config(PriorityFeePolicy::Fixed(1_000));
+        assert_eq!(fixed_config.get_compute_unit_price(None), Some(1_000));
+        assert_eq!(fixed_config.get_compute_unit_price(Some(500)), Some(1_000));
+        assert_eq!(
+            config(PriorityFeePolicy::Fixed(20_000)).get_compute_unit_price(None),
+            Some(10_000)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_priority_fee_config() {
+        let config: PriorityFeeConfig =
+            toml::from_str("policy = { multiply = 1.5 }\nmax_compute_unit_price = 100").unwrap();
+        assert_eq!(config.policy, PriorityFeePolicy::Multiply(1.5));
+        let config: PriorityFeeConfig =
+            toml::from_str("policy = \"match\"\nmax_compute_unit_price = 100").unwrap();
+        assert_eq!(config.policy, PriorityFeePolicy::Match);
+        let config: PriorityFeeConfig =
+            toml::from_str("policy = { fixed = 10 }\nmax_compute_unit_price = 100").unwrap();
+        assert_eq!(config.policy, PriorityFeePolicy::Fixed(10));
+        let config: PriorityFeeConfig =
+            toml::from_str("policy = { profit_share = 0.1 }\nmax_compute_unit_price = 100")
+                .unwrap();
+        assert_eq!(config.policy, PriorityFeePolicy::ProfitShare(0.1));
+    }
+
+    #[test]
+    fn test_profit_share_policy() {
+        let config = |policy| PriorityFeeConfig {
+            policy,
+            max_compute_unit_price: 10_000,
+        };
+
+        let profit_share_config = config(PriorityFeePolicy::ProfitShare(0.1));
+        assert!(profit_share_config.is_profit_share());
+        // The price does not depend on the trigger.
+        assert_eq!(profit_share_config.get_compute_unit_price(Some(500)), None);
+        // 10% of 4,000 lamports over 80,000 compute units.
+        assert_eq!(
+            profit_share_config.get_profit_share_compute_unit_price(4_000, 80_000),
+            Some(5_000)
+        );
+        assert_eq!(
+            profit_share_config.get_profit_share_compute_unit_price(u64::MAX, 80_000),
+            Some(10_000)
+        );
+        assert_eq!(
+            profit_share_config.get_profit_share_compute_unit_price(0, 80_000),
+            None
+        );
+
+        let fixed_config = config(PriorityFeePolicy::Fixed(1_000));
+        assert!(!fixed_config.is_profit_share());
+        assert_eq!(
+            fixed_config.get_profit_share_compute_unit_price(80_000, 80_000),
+            None
+        );
+    }
+
+    #[test]
+    fn test_priority_fee_lamports() {
+        assert_eq!(priority_fee_lamports(0, 200_000), 0);
+        assert_eq!(priority_fee_lamports(100, 80_000), 8);
+        // A fraction of a lamport is rounded up.
+        assert_eq!(priority_fee_lamports(1, 80_000), 1);
+    }
+}
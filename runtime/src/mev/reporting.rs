@@ -0,0 +1,415 @@
+//! Converting the profits of our transactions into one reporting mint. A
+//! profit is in the mint that its path starts with, so to sum or compare the
+//! profits of different paths, we value them at a configured price, or at the
+//! mid-price of the pools that we have in hand when we craft the transaction.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature};
+
+use super::{
+    config::AllOrcaPoolAddresses,
+    pool_state::{OrcaPoolWithBalance, PoolStates},
+    utils::B58Pubkey,
+};
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReportingConfig {
+    /// The mint that we report profits in, e.g. USDC.
+    pub mint: B58Pubkey,
+
+    /// Per profit mint, the pools to convert through, in order. Without a
+    /// route, we convert through a pool between the mint and `mint`, if one
+    /// is loaded.
+    #[serde(default)]
+    pub routes: HashMap<B58Pubkey, Vec<B58Pubkey>>,
+
+    /// Per profit mint, a fixed price of its smallest unit in the smallest
+    /// unit of `mint`, for mints that we cannot or do not want to convert
+    /// through pools. Takes precedence over `routes`.
+    #[serde(default)]
+    pub quote_prices: HashMap<B58Pubkey, f64>,
+}
+
+/// The profit of one of our transactions, and its value in the reporting
+/// mint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ReportedProfit {
+    /// The mint that the profit is in.
+    pub profit_mint: B58Pubkey,
+
+    /// `None` when there is no route to convert the profit, or a pool of the
+    /// route was not loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reported_value: Option<u64>,
+}
+
+/// Converts amounts into the reporting mint, see `ReportingConfig`.
+#[derive(Debug, Clone)]
+pub struct ProfitReporter {
+    mint: Pubkey,
+    routes: HashMap<Pubkey, Vec<Pubkey>>,
+    quote_prices: HashMap<Pubkey, f64>,
+}
+
+impl ProfitReporter {
+    /// Check that the pools of every route are in `pools`, and that the quote
+    /// prices are positive.
+    pub fn new(config: &ReportingConfig, pools: &AllOrcaPoolAddresses) -> Result<Self, String> {
+        let mut routes = HashMap::with_capacity(config.routes.len());
+        for (mint, route) in &config.routes {
+            if route.is_empty() {
+                return Err(format!("the route for mint {} has no pools", mint.0));
+            }
+            if let Some(unknown) = route
+                .iter()
+                .find(|pool| pools.by_address(&pool.0).is_none())
+            {
+                return Err(format!(
+                    "the route for mint {} goes through pool {}, which is not an orca_account",
+                    mint.0, unknown.0
+                ));
+            }
+            routes.insert(mint.0, route.iter().map(|pool| pool.0).collect());
+        }
+        let mut quote_prices = HashMap::with_capacity(config.quote_prices.len());
+        for (mint, price) in &config.quote_prices {
+            if !price.is_finite() || *price <= 0.0 {
+                return Err(format!(
+                    "the quote price of mint {} is {}, it has to be positive",
+                    mint.0, price
+                ));
+            }
+            quote_prices.insert(mint.0, *price);
+        }
+        Ok(ProfitReporter {
+            mint: config.mint.0,
+            routes,
+            quote_prices,
+        })
+    }
+
+    /// Value `amount` of `mint` in the reporting mint, at its quote price, or
+    /// at the mid-price of the pools in `pool_states`, before fees.
+    pub fn convert(&self, amount: u64, mint: Pubkey, pool_states: &PoolStates) -> Option<u64> {
+        if mint == self.mint {
+            return Some(amount);
+        }
+        let value = match (self.quote_prices.get(&mint), self.routes.get(&mint)) {
+            (Some(price), _) => amount as f64 * price,
+            (None, Some(route)) => {
+                let mut value = amount as f64;
+                let mut value_mint = mint;
+                for pool in route {
+                    let pool_state = pool_states.0.get(pool)?;
+                    let (price, out_mint) = mid_price(pool_state, value_mint)?;
+                    value *= price;
+                    value_mint = out_mint;
+                }
+                if value_mint != self.mint {
+                    return None;
+                }
+                value
+            }
+            (None, None) => {
+                // Of the pools between the two mints, the one with the most
+                // liquidity gives the most reliable price.
+                let (price, _balance) = pool_states
+                    .0
+                    .values()
+                    .filter_map(|pool_state| match mid_price(pool_state, mint)? {
+                        (price, out_mint) if out_mint == self.mint => {
+                            Some((price, out_balance(pool_state, out_mint)))
+                        }
+                        _ => None,
+                    })
+                    .max_by_key(|(_price, balance)| *balance)?;
+                amount as f64 * price
+            }
+        };
+        (value.is_finite() && value >= 0.0 && value < u64::MAX as f64).then(|| value.round() as u64)
+    }
+
+    /// What we report for a profit of `amount` in `mint`.
+    pub fn report(&self, amount: u64, mint: Pubkey, pool_states: &PoolStates) -> ReportedProfit {
+        ReportedProfit {
+            profit_mint: B58Pubkey(mint),
+            reported_value: self.convert(amount, mint, pool_states),
+        }
+    }
+}
+
+/// The price of `mint` in the other mint of the pool, from the pool's
+/// balances, and that other mint. `None` when `mint` is not in the pool, or
+/// the pool is empty.
+fn mid_price(pool_state: &OrcaPoolWithBalance, mint: Pubkey) -> Option<(f64, Pubkey)> {
+    let pool = &pool_state.pool;
+    let (balance_in, balance_out, out_mint) = if pool.pool_a_mint == mint {
+        (
+            pool_state.pool_a_balance,
+            pool_state.pool_b_balance,
+            pool.pool_b_mint,
+        )
+    } else if pool.pool_b_mint == mint {
+        (
+            pool_state.pool_b_balance,
+            pool_state.pool_a_balance,
+            pool.pool_a_mint,
+        )
+    } else {
+        return None;
+    };
+    if balance_in == 0 || balance_out == 0 {
+        return None;
+    }
+    Some((balance_out as f64 / balance_in as f64, out_mint))
+}
+
+fn out_balance(pool_state: &OrcaPoolWithBalance, mint: Pubkey) -> u64 {
+    if pool_state.pool.pool_a_mint == mint {
+        pool_state.pool_a_balance
+    } else {
+        pool_state.pool_b_balance
+    }
+}
+
+/// What we report for the transactions that we crafted, until they execute,
+/// keyed by their signature.
+#[derive(Debug, Default)]
+pub struct PendingReportedProfits {
+    transactions: HashMap<Signature, (Slot, ReportedProfit)>,
+}
+
+impl PendingReportedProfits {
+    /// Transactions crafted in an earlier slot than `slot` never execute, so
+    /// we forget them.
+    pub fn insert(&mut self, signature: Signature, slot: Slot, reported_profit: ReportedProfit) {
+        self.transactions
+            .retain(|_signature, (crafted_slot, _reported_profit)| *crafted_slot >= slot);
+        self.transactions.insert(signature, (slot, reported_profit));
+    }
+
+    pub fn take(&mut self, signature: &Signature) -> Option<ReportedProfit> {
+        self.transactions
+            .remove(signature)
+            .map(|(_slot, reported_profit)| reported_profit)
+    }
+}
+
+/// The profits of the transactions that executed successfully, summed in the
+/// reporting mint, and per profit mint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProfitSummary {
+    pub reporting_mint: B58Pubkey,
+
+    /// The sum of the converted profits, in `reporting_mint`.
+    pub reported_value: u64,
+
+    /// The profits that we converted, per profit mint, in that mint.
+    pub converted: BTreeMap<String, u64>,
+
+    /// The profits that we could not convert, per profit mint, in that mint.
+    pub unconverted: BTreeMap<String, u64>,
+}
+
+impl ProfitSummary {
+    pub fn new(reporting_mint: Pubkey) -> Self {
+        ProfitSummary {
+            reporting_mint: B58Pubkey(reporting_mint),
+            reported_value: 0,
+            converted: BTreeMap::new(),
+            unconverted: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, profit: u64, reported_profit: &ReportedProfit) {
+        let by_mint = match reported_profit.reported_value {
+            Some(reported_value) => {
+                self.reported_value = self.reported_value.saturating_add(reported_value);
+                &mut self.converted
+            }
+            None => &mut self.unconverted,
+        };
+        let total = by_mint
+            .entry(reported_profit.profit_mint.0.to_string())
+            .or_insert(0);
+        *total = total.saturating_add(profit);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.converted.is_empty() && self.unconverted.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mev::test_utils::{orca_accounts, pool};
+
+    struct Mints {
+        usdc: Pubkey,
+        stsol: Pubkey,
+        steth: Pubkey,
+    }
+
+    /// A stSOL/USDC pool at 20 USDC per stSOL, and an stETH/stSOL pool at 40
+    /// stSOL per stETH.
+    fn pool_states(mints: &Mints) -> (Pubkey, Pubkey, PoolStates) {
+        let stsol_usdc = Pubkey::new_unique();
+        let steth_stsol = Pubkey::new_unique();
+        let pool_states = [
+            pool(stsol_usdc)
+                .balances(1_000, 20_000)
+                .mints(mints.stsol, mints.usdc),
+            pool(steth_stsol)
+                .balances(10, 400)
+                .mints(mints.steth, mints.stsol),
+        ]
+        .into_iter()
+        .map(|pool| pool.build())
+        .collect();
+        (stsol_usdc, steth_stsol, pool_states)
+    }
+
+    fn mints() -> Mints {
+        Mints {
+            usdc: Pubkey::new_unique(),
+            stsol: Pubkey::new_unique(),
+            steth: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn test_convert_through_direct_pool() {
+        let mints = mints();
+        let (_stsol_usdc, _steth_stsol, pool_states) = pool_states(&mints);
+        let config = ReportingConfig {
+            mint: B58Pubkey(mints.usdc),
+            routes: HashMap::new(),
+            quote_prices: HashMap::new(),
+        };
+        let reporter = ProfitReporter::new(&config, &orca_accounts(&pool_states)).unwrap();
+        assert_eq!(reporter.convert(3, mints.stsol, &pool_states), Some(60));
+        assert_eq!(reporter.convert(7, mints.usdc, &pool_states), Some(7));
+        // There is no pool between stETH and USDC.
+        assert_eq!(reporter.convert(3, mints.steth, &pool_states), None);
+    }
+
+    #[test]
+    fn test_convert_through_route() {
+        let mints = mints();
+        let (stsol_usdc, steth_stsol, pool_states) = pool_states(&mints);
+        let config = ReportingConfig {
+            mint: B58Pubkey(mints.usdc),
+            routes: vec![(
+                B58Pubkey(mints.steth),
+                vec![B58Pubkey(steth_stsol), B58Pubkey(stsol_usdc)],
+            )]
+            .into_iter()
+            .collect(),
+            quote_prices: HashMap::new(),
+        };
+        let reporter = ProfitReporter::new(&config, &orca_accounts(&pool_states)).unwrap();
+        assert_eq!(
+            reporter.report(2, mints.steth, &pool_states),
+            ReportedProfit {
+                profit_mint: B58Pubkey(mints.steth),
+                reported_value: Some(1_600),
+            }
+        );
+
+        // A route that does not end in the reporting mint converts nothing.
+        let config = ReportingConfig {
+            mint: B58Pubkey(mints.usdc),
+            routes: vec![(B58Pubkey(mints.steth), vec![B58Pubkey(steth_stsol)])]
+                .into_iter()
+                .collect(),
+            quote_prices: HashMap::new(),
+        };
+        let reporter = ProfitReporter::new(&config, &orca_accounts(&pool_states)).unwrap();
+        assert_eq!(reporter.convert(2, mints.steth, &pool_states), None);
+    }
+
+    #[test]
+    fn test_convert_at_quote_price() {
+        let mints = mints();
+        let (_stsol_usdc, steth_stsol, pool_states) = pool_states(&mints);
+        // The price beats the pools, and covers a mint without a pool.
+        let config = ReportingConfig {
+            mint: B58Pubkey(mints.usdc),
+            routes: vec![(B58Pubkey(mints.steth), vec![B58Pubkey(steth_stsol)])]
+                .into_iter()
+                .collect(),
+            quote_prices: vec![
+                (B58Pubkey(mints.stsol), 25.0),
+                (B58Pubkey(mints.steth), 1_000.0),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let reporter = ProfitReporter::new(&config, &orca_accounts(&pool_states)).unwrap();
+        assert_eq!(reporter.convert(3, mints.stsol, &pool_states), Some(75));
+        assert_eq!(reporter.convert(2, mints.steth, &pool_states), Some(2_000));
+        assert_eq!(reporter.convert(7, mints.usdc, &pool_states), Some(7));
+
+        for price in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let config = ReportingConfig {
+                quote_prices: vec![(B58Pubkey(mints.stsol), price)].into_iter().collect(),
+                ..config.clone()
+            };
+            let err = ProfitReporter::new(&config, &orca_accounts(&pool_states)).unwrap_err();
+            assert!(err.contains("it has to be positive"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_unconvertible_profits() {
+        let mints = mints();
+        let (_stsol_usdc, steth_stsol, pool_states) = pool_states(&mints);
+        let config = ReportingConfig {
+            mint: B58Pubkey(mints.usdc),
+            routes: vec![(
+                B58Pubkey(mints.steth),
+                vec![B58Pubkey(steth_stsol), B58Pubkey(Pubkey::new_unique())],
+            )]
+            .into_iter()
+            .collect(),
+            quote_prices: HashMap::new(),
+        };
+        let err = ProfitReporter::new(&config, &orca_accounts(&pool_states)).unwrap_err();
+        assert!(err.contains("which is not an orca_account"), "{}", err);
+
+        let reporter = ProfitReporter::new(
+            &ReportingConfig {
+                mint: B58Pubkey(mints.usdc),
+                routes: HashMap::new(),
+                quote_prices: HashMap::new(),
+            },
+            &orca_accounts(&pool_states),
+        )
+        .unwrap();
+        let mut summary = ProfitSummary::new(mints.usdc);
+        for (amount, mint) in [(3, mints.stsol), (5, mints.steth), (4, mints.steth)] {
+            summary.record(amount, &reporter.report(amount, mint, &pool_states));
+        }
+        assert_eq!(summary.reported_value, 60);
+        assert_eq!(summary.converted[&mints.stsol.to_string()], 3);
+        assert_eq!(summary.unconverted[&mints.steth.to_string()], 9);
+    }
+
+    #[test]
+    fn test_pending_reported_profits_expire() {
+        let mut pending = PendingReportedProfits::default();
+        let reported_profit = ReportedProfit {
+            profit_mint: B58Pubkey(Pubkey::new_unique()),
+            reported_value: Some(1),
+        };
+        let (first, second) = (Signature::new_unique(), Signature::new_unique());
+        pending.insert(first, 1, reported_profit);
+        pending.insert(second, 2, reported_profit);
+        assert_eq!(pending.take(&first), None);
+        assert_eq!(pending.take(&second), Some(reported_profit));
+        assert_eq!(pending.take(&second), None);
+    }
+}
@@ -0,0 +1,524 @@
+//! Running the MEV engine outside of the validator, e.g. in a searcher that
+//! follows the pools over RPC, see `examples/standalone_searcher.rs`.
+//!
+//! This module, with what it re-exports, is the API for such uses, and we
+//! keep it compatible within a minor release. The other modules of `mev` serve
+//! the validator, and change with it.
+
+use std::collections::{BTreeMap, HashMap};
+
+use solana_sdk::{account::AccountSharedData, hash::Hash, pubkey::Pubkey, rent::Rent};
+
+pub use super::{
+    arbitrage::{InputOutputPairs, MevPath, MevTxOutput, SequencePart, TradeDirection},
+    checkpoint::{read_checkpoint_json, read_latest_checkpoint, PoolStatesCheckpoint},
+    compute_units::CuCalibration,
+    config::{get_mev_config_file, parse_mev_config, MevConfig},
+    error::MevError,
+    log_channel::{LogChannelPolicy, MevLogSender},
+    log_reader::{
+        parse_log_records, read_log_records, ExecutedTransactionRecord, LogReadError, MevLogRecord,
+        OpportunityRecord, TriggerRecord,
+    },
+    pool_state::{PoolLoadError, PoolLoadErrorKind},
+    simulate::HypotheticalSwap,
+    verify::{
+        accounts_to_verify, configured_pool_authorities, configured_user_authority, verify_pools,
+        PoolVerification,
+    },
+    MevEvent, MevLog, MevMsg, PoolStates,
+};
+use super::{engine::Mev, pool_state::PoolRole, simulate::apply_hypothetical_swaps};
+use crate::{accounts::MevPoolAccounts, inline_spl_token};
+
+/// The MEV engine, fed with the accounts of the pools in the config as they
+/// change, rather than with the accounts that the validator loads for a
+/// transaction.
+#[derive(Debug)]
+pub struct MevEngine {
+    mev: Mev,
+
+    // The accounts of the pools that we load, from the config.
+    pool_accounts: Vec<MevPoolAccounts>,
+
+    // The latest version of every account in `pool_accounts` that we know of.
+    accounts: HashMap<Pubkey, AccountSharedData>,
+
+    // Pool states from a checkpoint, for the pools that we miss accounts of.
+    checkpoint: PoolStates,
+}
+
+impl MevEngine {
+    /// Like `try_new`, but panics when `config` is not valid.
+    pub fn new(log_send_channel: impl Into<MevLogSender>, config: MevConfig) -> Self {
+        MevEngine::try_new(log_send_channel, config).unwrap_or_else(|err| panic!("[MEV] {}", err))
+    }
+
+    /// Returns an error when `config` is not valid, see `Mev::try_new`. What
+    /// we log is sent to `log_send_channel`: pass the channel of a `MevLog` to
+    /// write it to the MEV log, or one whose receiver is dropped to ignore it.
+    pub fn try_new(
+        log_send_channel: impl Into<MevLogSender>,
+        config: MevConfig,
+    ) -> Result<Self, MevError> {
+        let mev = Mev::try_new(log_send_channel, config)?;
+        let pool_accounts = mev
+            .orca_monitored_accounts
+            .unique()
+            .map(|orca_pool| {
+                let (source, destination) = match orca_pool.role {
+                    PoolRole::Trade => (orca_pool.source, orca_pool.destination),
+                    PoolRole::Observe => (None, None),
+                };
+                let (token_a_mint, token_b_mint) = match orca_pool.known_mints() {
+                    Some((mint_a, mint_b)) => (Some(mint_a), Some(mint_b)),
+                    None => (None, None),
+                };
+                MevPoolAccounts {
+                    pool: orca_pool.address,
+                    source,
+                    destination,
+                    token_a: orca_pool.pool_a_account,
+                    token_b: orca_pool.pool_b_account,
+                    pool_mint: orca_pool.pool_mint,
+                    pool_fee: orca_pool.pool_fee,
+                    pool_authority: orca_pool.pool_authority,
+                    // We read the token program from the owner of the pool's
+                    // token accounts, so this is only what we expect.
+                    token_program: inline_spl_token::id(),
+                    token_a_mint,
+                    token_b_mint,
+                }
+            })
+            .collect();
+        Ok(MevEngine {
+            mev,
+            pool_accounts,
+            accounts: HashMap::new(),
+            checkpoint: PoolStates(HashMap::new()),
+        })
+    }
+
+    /// Start from the pool states in `checkpoint`, e.g. the latest one in the
+    /// MEV log of a validator, see `read_latest_checkpoint`. Until we got all
+    /// accounts of a pool, we evaluate it as it was in the checkpoint. Pools
+    /// that are not in our config are ignored. Our token accounts can differ
+    /// from the validator's, then we do not know their balances.
+    pub fn load_checkpoint(&mut self, checkpoint: &PoolStatesCheckpoint) -> Result<(), String> {
+        let mut pool_states = checkpoint.pool_states()?;
+        let mut checkpoint_states = HashMap::new();
+        for pool_accounts in &self.pool_accounts {
+            let mut pool_state = match pool_states.0.remove(&pool_accounts.pool) {
+                Some(pool_state) => pool_state,
+                None => continue,
+            };
+            if pool_state.pool.source != pool_accounts.source {
+                pool_state.pool.source = pool_accounts.source;
+                pool_state.source_balance = None;
+            }
+            if pool_state.pool.destination != pool_accounts.destination {
+                pool_state.pool.destination = pool_accounts.destination;
+                pool_state.destination_balance = None;
+            }
+            checkpoint_states.insert(pool_accounts.pool, pool_state);
+        }
+        self.checkpoint = PoolStates(checkpoint_states);
+        Ok(())
+    }
+
+    pub fn mev_paths(&self) -> &[MevPath] {
+        &self.mev.mev_paths
+    }
+
+    /// The accounts to follow, without duplicates: for every pool its state,
+    /// its token accounts, the mints of its tokens when the config has them,
+    /// and our token accounts that trade through it.
+    pub fn watched_accounts(&self) -> Vec<Pubkey> {
+        let mut watched_accounts = Vec::new();
+        for pool_accounts in &self.pool_accounts {
+            let pool_keys = [
+                pool_accounts.pool,
+                pool_accounts.token_a,
+                pool_accounts.token_b,
+            ]
+            .into_iter()
+            .chain(pool_accounts.token_a_mint)
+            .chain(pool_accounts.token_b_mint)
+            .chain(pool_accounts.source)
+            .chain(pool_accounts.destination);
+            for pubkey in pool_keys {
+                if !watched_accounts.contains(&pubkey) {
+                    watched_accounts.push(pubkey);
+                }
+            }
+        }
+        watched_accounts
+    }
+
+    /// Store the latest version of `pubkey`. Returns whether we watch it,
+    /// accounts that we do not watch are ignored.
+    pub fn update_account(&mut self, pubkey: Pubkey, account: AccountSharedData) -> bool {
+        let is_watched = self.watched_accounts().contains(&pubkey);
+        if is_watched {
+            self.accounts.insert(pubkey, account);
+        }
+        is_watched
+    }
+
+    /// The pool states from the accounts that we know of. Until we got every
+    /// watched account, the missing ones are `PoolLoadErrorKind::MissingAccount`,
+    /// unless the pool is in the checkpoint that we loaded.
+    pub fn pool_states(&self) -> Result<PoolStates, Vec<PoolLoadError>> {
+        let mut pool_states = HashMap::with_capacity(self.pool_accounts.len());
+        let mut errors = Vec::new();
+        for pool_accounts in &self.pool_accounts {
+            match PoolStates::from_accounts(&self.accounts, std::slice::from_ref(pool_accounts)) {
+                Ok(loaded) => pool_states.extend(loaded.0),
+                Err(pool_errors) => match self.checkpoint.0.get(&pool_accounts.pool) {
+                    Some(pool_state)
+                        if pool_errors
+                            .iter()
+                            .all(|error| error.kind == PoolLoadErrorKind::MissingAccount) =>
+                    {
+                        pool_states.insert(pool_accounts.pool, pool_state.clone());
+                    }
+                    _ => errors.extend(pool_errors),
+                },
+            }
+        }
+        if errors.is_empty() {
+            Ok(PoolStates(pool_states))
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Evaluate all paths against the current pool states, see
+    /// `Mev::get_arbitrage_tx_outputs`. The outputs carry unsigned
+    /// transactions when the config has a user authority, it is up to the
+    /// caller which to sign, see `MevTxBlueprint::sign`, and send.
+    pub fn evaluate(
+        &self,
+        blockhash: Hash,
+        rent: &Rent,
+    ) -> Result<Vec<MevTxOutput>, Vec<PoolLoadError>> {
+        Ok(self.evaluate_pool_states(self.pool_states()?, blockhash, rent))
+    }
+
+    /// Like `evaluate`, but against `pool_states`, e.g. the current pool
+    /// states after hypothetical swaps.
+    pub fn evaluate_pool_states(
+        &self,
+        mut pool_states: PoolStates,
+        blockhash: Hash,
+        rent: &Rent,
+    ) -> Vec<MevTxOutput> {
+        self.mev.set_pool_config(&mut pool_states);
+        // There is no trigger here, and so no state before it.
+        self.mev
+            .disable_mismatched_pools(&mut PoolStates(HashMap::new()), &mut pool_states);
+        self.mev
+            .get_arbitrage_tx_outputs(&pool_states, blockhash, rent, None)
+    }
+
+    /// Evaluate all paths against the current pool states, and against the
+    /// pool states after `swaps`, which we apply in order, see
+    /// `apply_hypothetical_swaps`.
+    pub fn evaluate_hypothetical_swaps(
+        &self,
+        swaps: &[HypotheticalSwap],
+        blockhash: Hash,
+        rent: &Rent,
+    ) -> Result<HypotheticalEvaluation, String> {
+        let pool_states = self.pool_states().map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        })?;
+        let mut hypothetical_states = PoolStates(pool_states.0.clone());
+        let swap_outputs = apply_hypothetical_swaps(&mut hypothetical_states, swaps)?;
+        Ok(HypotheticalEvaluation {
+            swap_outputs,
+            baseline: self.evaluate_pool_states(pool_states, blockhash, rent),
+            hypothetical: self.evaluate_pool_states(hypothetical_states, blockhash, rent),
+        })
+    }
+}
+
+/// The opportunities before and after hypothetical swaps, see
+/// `MevEngine::evaluate_hypothetical_swaps`.
+#[derive(Debug)]
+pub struct HypotheticalEvaluation {
+    /// What every swap got out, in order.
+    pub swap_outputs: Vec<u64>,
+    pub baseline: Vec<MevTxOutput>,
+    pub hypothetical: Vec<MevTxOutput>,
+}
+
+impl HypotheticalEvaluation {
+    /// By path index, the profit of the path before and after the swaps, for
+    /// every path that has an opportunity in either. The profit of a path
+    /// that we split is that of all its parts.
+    pub fn profit_by_path(&self) -> BTreeMap<usize, (Option<u64>, Option<u64>)> {
+        let mut profits = BTreeMap::new();
+        for output in &self.baseline {
+            let (baseline, _) = profits.entry(output.path_idx).or_insert((None, None));
+            *baseline = Some(baseline.unwrap_or(0) + output.profit);
+        }
+        for output in &self.hypothetical {
+            let (_, hypothetical) = profits.entry(output.path_idx).or_insert((None, None));
+            *hypothetical = Some(hypothetical.unwrap_or(0) + output.profit);
+        }
+        profits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+
+    use super::*;
+    use crate::mev::{
+        checkpoint::latest_checkpoint,
+        config::{AllOrcaPoolAddresses, TokenAmount, TokenRef},
+        test_utils::{
+            pool, pubkey, steth_stsol_usdc_path, PoolBuilder, STETH_USDC_POOL, STSOL_STETH_POOL,
+            STSOL_USDC_POOL,
+        },
+    };
+
+    /// The pools of the stSOL/USDC/wstETH scenario as fixture accounts, with
+    /// our USDC in the token account that the first hop trades from. Pools
+    /// share mints, which we return once.
+    fn fixture_accounts() -> (AllOrcaPoolAddresses, Vec<(Pubkey, AccountSharedData)>) {
+        let (steth, stsol, usdc) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (pools, accounts): (Vec<_>, Vec<_>) = [
+            pool(pubkey(STETH_USDC_POOL))
+                .mints(steth, usdc)
+                .balances(4618233234, 6400518033)
+                .destination(Pubkey::new_unique())
+                .destination_balance(1_000_000_000),
+            pool(pubkey(STSOL_STETH_POOL))
+                .mints(stsol, steth)
+                .balances(54896627850684, 13408494240),
+            pool(pubkey(STSOL_USDC_POOL))
+                .mints(stsol, usdc)
+                .balances(400881658679, 138436018345),
+        ]
+        .into_iter()
+        .map(PoolBuilder::build_accounts)
+        .unzip();
+        let accounts: HashMap<Pubkey, AccountSharedData> = accounts.into_iter().flatten().collect();
+        (
+            AllOrcaPoolAddresses::new(pools),
+            accounts.into_iter().collect(),
+        )
+    }
+
+    #[test]
+    fn test_evaluate_fixture_accounts() {
+        let (orca_accounts, accounts) = super::tests::fixture_accounts();
+        let usdc = orca_accounts
+            .by_address(&pubkey(STETH_USDC_POOL))
+            .unwrap()
+            .pool_b_mint;
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mut engine = MevEngine::new(
+            log_send_channel,
+            MevConfig {
+                orca_accounts,
+                mev_paths: vec![steth_stsol_usdc_path()],
+                minimum_profit: [(TokenRef::Mint(usdc), TokenAmount::Raw(0))]
+                    .into_iter()
+                    .collect(),
+                ..MevConfig::default()
+            },
+        );
+        let mut watched_accounts = engine.watched_accounts();
+        watched_accounts.sort();
+        let mut fixture_pubkeys: Vec<Pubkey> =
+            accounts.iter().map(|(pubkey, _account)| *pubkey).collect();
+        fixture_pubkeys.sort();
+        assert_eq!(watched_accounts, fixture_pubkeys);
+
+        // Until we got all accounts, we cannot evaluate.
+        let mut accounts = accounts.into_iter();
+        let (pubkey, account) = accounts.next().unwrap();
+        assert!(engine.update_account(pubkey, account));
+        let errors = engine
+            .evaluate(Hash::default(), &Rent::default())
+            .unwrap_err();
+        assert!(errors
+            .iter()
+            .all(|error| error.kind == PoolLoadErrorKind::MissingAccount));
+
+        assert!(!engine.update_account(Pubkey::new_unique(), AccountSharedData::default()));
+        for (pubkey, account) in accounts {
+            assert!(engine.update_account(pubkey, account));
+        }
+        let outputs = engine.evaluate(Hash::default(), &Rent::default()).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].path_idx, 0);
+        assert!(outputs[0].profit > 0);
+        // Without a user authority, we only quote.
+        assert!(outputs[0].blueprint.is_none());
+    }
+
+    #[test]
+    fn test_evaluate_from_checkpoint() {
+        let (orca_accounts, accounts) = super::tests::fixture_accounts();
+        let usdc = orca_accounts
+            .by_address(&pubkey(STETH_USDC_POOL))
+            .unwrap()
+            .pool_b_mint;
+        let new_engine = || {
+            let (log_send_channel, _log_receiver) = unbounded();
+            MevEngine::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: AllOrcaPoolAddresses::new(
+                        orca_accounts.iter().cloned().collect(),
+                    ),
+                    mev_paths: vec![steth_stsol_usdc_path()],
+                    minimum_profit: [(TokenRef::Mint(usdc), TokenAmount::Raw(0))]
+                        .into_iter()
+                        .collect(),
+                    ..MevConfig::default()
+                },
+            )
+        };
+
+        // The validator logs a checkpoint of the pools as it loaded them.
+        let mut live_engine = new_engine();
+        for (pubkey, account) in accounts.iter().cloned() {
+            live_engine.update_account(pubkey, account);
+        }
+        let live_states = live_engine.pool_states().unwrap();
+        let event = PoolStatesCheckpoint::new(10, 9, &live_states).to_event();
+        let checkpoint = latest_checkpoint(&event).unwrap().unwrap();
+
+        // A searcher without any account evaluates the checkpoint, the same as
+        // the live accounts.
+        let mut engine = new_engine();
+        assert!(engine.evaluate(Hash::default(), &Rent::default()).is_err());
+        engine.load_checkpoint(&checkpoint).unwrap();
+        let outputs = engine.evaluate(Hash::default(), &Rent::default()).unwrap();
+        let live_outputs = live_engine
+            .evaluate(Hash::default(), &Rent::default())
+            .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].profit, live_outputs[0].profit);
+        assert_eq!(
+            outputs[0].input_output_pairs,
+            live_outputs[0].input_output_pairs
+        );
+
+        // The checkpoint only stands in for missing accounts, not for ones
+        // that we cannot load.
+        for (address, mut account) in accounts {
+            if address == pubkey(STETH_USDC_POOL) {
+                account.set_data(vec![]);
+            }
+            engine.update_account(address, account);
+        }
+        let errors = engine
+            .evaluate(Hash::default(), &Rent::default())
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].pool, pubkey(STETH_USDC_POOL));
+        assert_eq!(errors[0].kind, PoolLoadErrorKind::UnpackSwapState);
+    }
+
+    #[test]
+    fn test_evaluate_hypothetical_swaps_from_pools_json() {
+        let (orca_accounts, accounts) = super::tests::fixture_accounts();
+        let usdc = orca_accounts
+            .by_address(&pubkey(STETH_USDC_POOL))
+            .unwrap()
+            .pool_b_mint;
+        let new_engine = || {
+            let (log_send_channel, _log_receiver) = unbounded();
+            MevEngine::new(
+                log_send_channel,
+                MevConfig {
+                    orca_accounts: AllOrcaPoolAddresses::new(
+                        orca_accounts.iter().cloned().collect(),
+                    ),
+                    mev_paths: vec![steth_stsol_usdc_path()],
+                    minimum_profit: [(TokenRef::Mint(usdc), TokenAmount::Raw(0))]
+                        .into_iter()
+                        .collect(),
+                    ..MevConfig::default()
+                },
+            )
+        };
+
+        // The pool states as `--pools-json` takes them.
+        let mut live_engine = new_engine();
+        for (pubkey, account) in accounts {
+            live_engine.update_account(pubkey, account);
+        }
+        let pools_json = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            pools_json.path(),
+            PoolStatesCheckpoint::new(10, 10, &live_engine.pool_states().unwrap()).to_event(),
+        )
+        .unwrap();
+        let mut engine = new_engine();
+        engine
+            .load_checkpoint(&read_checkpoint_json(pools_json.path()).unwrap())
+            .unwrap();
+
+        // Someone buys stSOL with USDC in the pool where we sell stSOL, which
+        // makes our last hop pay more. Two swaps compose in order.
+        let swap: HypotheticalSwap =
+            format!("pool={},direction=BtoA,amount=500000000", STSOL_USDC_POOL)
+                .parse()
+                .unwrap();
+        let evaluation = engine
+            .evaluate_hypothetical_swaps(&[swap.clone(), swap], Hash::default(), &Rent::default())
+            .unwrap();
+        assert_eq!(evaluation.swap_outputs.len(), 2);
+        assert!(evaluation.swap_outputs[1] < evaluation.swap_outputs[0]);
+        let (baseline, hypothetical) = evaluation.profit_by_path()[&0];
+        assert_eq!(
+            baseline,
+            Some(
+                live_engine
+                    .evaluate(Hash::default(), &Rent::default())
+                    .unwrap()[0]
+                    .profit
+            )
+        );
+        assert!(hypothetical.unwrap() > baseline.unwrap());
+
+        // Selling stSOL there makes our last hop pay less.
+        let swap = HypotheticalSwap {
+            pool: pubkey(STSOL_USDC_POOL),
+            direction: TradeDirection::AtoB,
+            amount: 100_000_000_000,
+        };
+        let evaluation = engine
+            .evaluate_hypothetical_swaps(&[swap], Hash::default(), &Rent::default())
+            .unwrap();
+        let (sell_baseline, sell_hypothetical) = evaluation.profit_by_path()[&0];
+        assert_eq!(sell_baseline, baseline);
+        assert!(sell_hypothetical.unwrap() < baseline.unwrap());
+
+        // A pool that is not in the pool states is an error.
+        let swap = HypotheticalSwap {
+            pool: Pubkey::new_unique(),
+            direction: TradeDirection::AtoB,
+            amount: 1,
+        };
+        assert!(engine
+            .evaluate_hypothetical_swaps(&[swap], Hash::default(), &Rent::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    fmt::Debug,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{Builder, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use serde_json::{json, Value};
+use solana_sdk::{
+    bs58,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+};
+use thiserror::Error;
+
+use super::utils::{deserialize_b58, serialize_b58};
+
+/// Signs the MEV transactions on behalf of the MEV authority.
+pub trait MevSigner: Debug + Send + Sync {
+    fn pubkey(&self) -> Pubkey;
+    fn sign_message(&self, message: &[u8]) -> Result<Signature, SigningError>;
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SigningError {
+    #[error("signer did not respond in time")]
+    Timeout,
+
+    #[error("could not reach signer: {0}")]
+    Io(String),
+
+    #[error("signer returned an error: {0}")]
+    Remote(String),
+
+    #[error("signer returned an invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl MevSigner for Keypair {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(self)
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<Signature, SigningError> {
+        Ok(Signer::sign_message(self, message))
+    }
+}
+
+fn default_timeout_ms() -> u64 {
+    50
+}
+
+/// How to sign MEV transactions, when they are not signed with the keypair at
+/// `user_authority_path`.
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    /// Delegate signing to another process, that listens for JSON-RPC requests
+    /// on a Unix socket.
+    Remote {
+        socket: PathBuf,
+
+        /// Public key of the MEV authority, the remote signer signs with its
+        /// private key.
+        #[serde(serialize_with = "serialize_b58")]
+        #[serde(deserialize_with = "deserialize_b58")]
+        pubkey: Pubkey,
+
+        /// Time after which we give up on a signature, and skip the
+        /// opportunity.
+        #[serde(default = "default_timeout_ms")]
+        timeout_ms: u64,
+    },
+}
+
+impl SignerConfig {
+    pub fn into_signer(self) -> Box<dyn MevSigner> {
+        match self {
+            SignerConfig::Remote {
+                socket,
+                pubkey,
+                timeout_ms,
+            } => Box::new(RemoteSigner {
+                socket,
+                pubkey,
+                timeout: Duration::from_millis(timeout_ms),
+            }),
+        }
+    }
+}
+
+/// Client for a signer that listens on a Unix socket. For every message, we
+/// open a connection and send a single JSON-RPC request on one line:
+///
+/// `{"jsonrpc":"2.0","id":1,"method":"signMessage","params":["<base58 message>"]}`
+///
+/// The signer should respond with a single line, with either the base58
+/// signature in `result`, or an `error` object.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    pub socket: PathBuf,
+    pub pubkey: Pubkey,
+    pub timeout: Duration,
+}
+
+fn map_io_error(err: std::io::Error) -> SigningError {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => SigningError::Timeout,
+        _ => SigningError::Io(err.to_string()),
+    }
+}
+
+impl MevSigner for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    fn sign_message(&self, message: &[u8]) -> Result<Signature, SigningError> {
+        let deadline = Instant::now() + self.timeout;
+        let remaining = || {
+            deadline
+                .checked_duration_since(Instant::now())
+                .filter(|remaining| !remaining.is_zero())
+                .ok_or(SigningError::Timeout)
+        };
+
+        let mut stream = UnixStream::connect(&self.socket).map_err(map_io_error)?;
+        stream
+            .set_write_timeout(Some(remaining()?))
+            .map_err(map_io_error)?;
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "signMessage",
+            "params": [bs58::encode(message).into_string()],
+        });
+        writeln!(stream, "{}", request).map_err(map_io_error)?;
+
+        stream
+            .set_read_timeout(Some(remaining()?))
+            .map_err(map_io_error)?;
+        let mut response = String::new();
+        BufReader::new(stream)
+            .read_line(&mut response)
+            .map_err(map_io_error)?;
+        // The read timeout applies to every read call, so check the deadline
+        // for the response as a whole too.
+        remaining()?;
+
+        let response: Value = serde_json::from_str(&response)
+            .map_err(|err| SigningError::InvalidResponse(err.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(SigningError::Remote(error.to_string()));
+        }
+        let signature = response
+            .get("result")
+            .and_then(Value::as_str)
+            .ok_or_else(|| SigningError::InvalidResponse(response.to_string()))?;
+        let signature = Signature::from_str(signature)
+            .map_err(|err| SigningError::InvalidResponse(err.to_string()))?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SigningError::InvalidResponse(format!(
+                "signature {} is not valid for {}",
+                signature, self.pubkey
+            )));
+        }
+        Ok(signature)
+    }
+}
+
+/// A message for the signing thread to sign, and where to send the signature.
+struct SigningRequest {
+    signer: Arc<dyn MevSigner>,
+    message: Vec<u8>,
+    response: Sender<Result<Signature, SigningError>>,
+}
+
+/// Signs the selected MEV transaction on a thread of its own, so that the
+/// banking thread waits for the signature at most `wait`, however long the
+/// signer takes. The thread exits when the last clone of its `SigningThread`
+/// is dropped.
+#[derive(Debug, Clone)]
+pub struct SigningThread {
+    requests: Sender<SigningRequest>,
+
+    // Set from when we send a request until the thread signed it. There is at
+    // most one request in flight.
+    busy: Arc<AtomicBool>,
+
+    wait: Duration,
+}
+
+impl SigningThread {
+    pub fn spawn(wait: Duration) -> (SigningThread, JoinHandle<()>) {
+        let (requests, receiver) = bounded(1);
+        let busy = Arc::new(AtomicBool::new(false));
+        let thread_busy = busy.clone();
+        let handle = Builder::new()
+            .name("solMevSigner".to_owned())
+            .spawn(move || SigningThread::run(receiver, thread_busy))
+            .expect("Failed to spawn the MEV signing thread");
+        let signing_thread = SigningThread {
+            requests,
+            busy,
+            wait,
+        };
+        (signing_thread, handle)
+    }
+
+    fn run(receiver: Receiver<SigningRequest>, busy: Arc<AtomicBool>) {
+        for request in receiver {
+            let signature = request.signer.sign_message(&request.message);
+            busy.store(false, Ordering::Release);
+            // The banking thread may have stopped waiting already.
+            let _ = request.response.send(signature);
+        }
+    }
+
+    /// Sign `message` with `signer` on the signing thread. When the thread is
+    /// still busy with an earlier message, e.g. one that we gave up waiting
+    /// for, we sign on the calling thread instead.
+    pub fn sign(
+        &self,
+        signer: &Arc<dyn MevSigner>,
+        message: Vec<u8>,
+    ) -> Result<Signature, SigningError> {
+        if self.busy.swap(true, Ordering::AcqRel) {
+            return signer.sign_message(&message);
+        }
+        let (response, response_receiver) = bounded(1);
+        let request = SigningRequest {
+            signer: signer.clone(),
+            message,
+            response,
+        };
+        // With the flag set, the channel has room.
+        if let Err(err) = self.requests.try_send(request) {
+            self.busy.store(false, Ordering::Release);
+            let request = err.into_inner();
+            return request.signer.sign_message(&request.message);
+        }
+        match response_receiver.recv_timeout(self.wait) {
+            Ok(signature) => signature,
+            Err(RecvTimeoutError::Timeout) => Err(SigningError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(SigningError::Io("signing thread exited".to_owned()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::net::UnixListener, thread};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Start a signer that handles a single request with `respond`, which
+    /// receives the decoded message and returns the response line.
+    fn mock_remote_signer<F>(dir: &TempDir, respond: F) -> PathBuf
+    where
+        F: FnOnce(Vec<u8>) -> String + Send + 'static,
+    {
+        let socket = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        thread::spawn(move || {
+            let (stream, _addr) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let request: Value = serde_json::from_str(&request).unwrap();
+            assert_eq!(request["method"], "signMessage");
+            let message = bs58::decode(request["params"][0].as_str().unwrap())
+                .into_vec()
+                .unwrap();
+            let response = respond(message);
+            // The client may have hung up already after a timeout.
+            let _ = writeln!(&stream, "{}", response);
+        });
+        socket
+    }
+
+    #[test]
+    fn test_remote_signer_success() {
+        let dir = TempDir::new().unwrap();
+        let keypair = Keypair::new();
+        let pubkey = Signer::pubkey(&keypair);
+        let socket = mock_remote_signer(&dir, move |message| {
+            let signature = Signer::sign_message(&keypair, &message);
+            json!({"jsonrpc": "2.0", "id": 1, "result": signature.to_string()}).to_string()
+        });
+        let signer = RemoteSigner {
+            socket,
+            pubkey,
+            timeout: Duration::from_secs(5),
+        };
+        let signature = signer.sign_message(b"hello").unwrap();
+        assert!(signature.verify(pubkey.as_ref(), b"hello"));
+    }
+
+    #[test]
+    fn test_remote_signer_error() {
+        let dir = TempDir::new().unwrap();
+        let socket = mock_remote_signer(&dir, |_message| {
+            json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32000, "message": "locked"}})
+                .to_string()
+        });
+        let signer = RemoteSigner {
+            socket,
+            pubkey: Pubkey::new_unique(),
+            timeout: Duration::from_secs(5),
+        };
+        assert!(matches!(
+            signer.sign_message(b"hello"),
+            Err(SigningError::Remote(_))
+        ));
+    }
+
+    #[test]
+    fn test_remote_signer_timeout() {
+        let dir = TempDir::new().unwrap();
+        let socket = mock_remote_signer(&dir, |_message| {
+            thread::sleep(Duration::from_millis(500));
+            String::new()
+        });
+        let signer = RemoteSigner {
+            socket,
+            pubkey: Pubkey::new_unique(),
+            timeout: Duration::from_millis(50),
+        };
+        let start = Instant::now();
+        assert_eq!(signer.sign_message(b"hello"), Err(SigningError::Timeout));
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    /// Signs after `delay`.
+    #[derive(Debug)]
+    struct SlowSigner {
+        keypair: Keypair,
+        delay: Duration,
+    }
+
+    impl MevSigner for SlowSigner {
+        fn pubkey(&self) -> Pubkey {
+            Signer::pubkey(&self.keypair)
+        }
+
+        fn sign_message(&self, message: &[u8]) -> Result<Signature, SigningError> {
+            thread::sleep(self.delay);
+            Ok(Signer::sign_message(&self.keypair, message))
+        }
+    }
+
+    #[test]
+    fn test_signing_thread() {
+        let keypair = Keypair::new();
+        let expected = Signer::sign_message(&keypair, b"hello");
+        let signer: Arc<dyn MevSigner> = Arc::new(keypair);
+        let (signing_thread, handle) = SigningThread::spawn(Duration::from_secs(5));
+        for _ in 0..2 {
+            assert_eq!(
+                signing_thread.sign(&signer, b"hello".to_vec()),
+                Ok(expected)
+            );
+        }
+
+        drop(signing_thread);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_signing_thread_timeout_and_fallback() {
+        let slow_signer: Arc<dyn MevSigner> = Arc::new(SlowSigner {
+            keypair: Keypair::new(),
+            delay: Duration::from_millis(500),
+        });
+        let fast_signer: Arc<dyn MevSigner> = Arc::new(Keypair::new());
+        let (signing_thread, handle) = SigningThread::spawn(Duration::from_millis(50));
+
+        let start = Instant::now();
+        assert_eq!(
+            signing_thread.sign(&slow_signer, b"hello".to_vec()),
+            Err(SigningError::Timeout)
+        );
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        // The thread is still busy with the slow signer, so we sign inline.
+        let signature = signing_thread
+            .sign(&fast_signer, b"hello".to_vec())
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(signature.verify(fast_signer.pubkey().as_ref(), b"hello"));
+
+        drop(signing_thread);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_deserialize_signer_config() {
+        let config: SignerConfig = toml::from_str(
+            r#"
+            type = "remote"
+            socket = "/run/mev-signer.sock"
+            pubkey = "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            SignerConfig::Remote {
+                socket: PathBuf::from("/run/mev-signer.sock"),
+                pubkey: Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap(),
+                timeout_ms: 50,
+            }
+        );
+    }
+}
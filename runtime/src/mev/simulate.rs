@@ -0,0 +1,431 @@
+use std::str::FromStr;
+
+use solana_sdk::pubkey::Pubkey;
+
+use super::{
+    arbitrage::{
+        HopCandidates, InputOutputPairs, MevPath, PathSkipReason, PoolSplit, PoolTrade,
+        TradeDirection,
+    },
+    utils::{parse_b58_pubkey, warn_throttled},
+    PoolStates, LOG_THROTTLE_INTERVAL,
+};
+
+/// A trade that did not happen, to see which opportunities it would create.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HypotheticalSwap {
+    pub pool: Pubkey,
+    pub direction: TradeDirection,
+    pub amount: u64,
+}
+
+impl FromStr for HypotheticalSwap {
+    type Err = String;
+
+    /// Parse `pool=<PUBKEY>,direction=<AtoB|BtoA>,amount=<N>`, with the keys
+    /// in any order.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut pool = None;
+        let mut direction = None;
+        let mut amount = None;
+        for part in input.split(',') {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, but got {:?}", part))?;
+            let is_new = match key.trim() {
+                "pool" => pool.replace(parse_b58_pubkey(value)?).is_none(),
+                "direction" => {
+                    let parsed = match value.trim() {
+                        "AtoB" => TradeDirection::AtoB,
+                        "BtoA" => TradeDirection::BtoA,
+                        _ => {
+                            return Err(format!(
+                                "direction should be AtoB or BtoA, but got {:?}",
+                                value
+                            ))
+                        }
+                    };
+                    direction.replace(parsed).is_none()
+                }
+                "amount" => {
+                    let parsed = value
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|err| format!("amount {:?} is invalid: {}", value, err))?;
+                    amount.replace(parsed).is_none()
+                }
+                _ => return Err(format!("unknown key {:?}", key)),
+            };
+            if !is_new {
+                return Err(format!("{} is given more than once", key.trim()));
+            }
+        }
+        Ok(HypotheticalSwap {
+            pool: pool.ok_or("pool is missing")?,
+            direction: direction.ok_or("direction is missing")?,
+            amount: amount.ok_or("amount is missing")?,
+        })
+    }
+}
+
+/// Apply `swaps` to `pool_states` in order, so that every swap trades against
+/// the balances that the swaps before it left. Returns the amount that every
+/// swap got out.
+pub fn apply_hypothetical_swaps(
+    pool_states: &mut PoolStates,
+    swaps: &[HypotheticalSwap],
+) -> Result<Vec<u64>, String> {
+    swaps
+        .iter()
+        .map(|swap| {
+            pool_states
+                .0
+                .get_mut(&swap.pool)
+                .ok_or_else(|| format!("pool {} is not in the pool states", swap.pool))?
+                .apply_swap(&swap.direction, swap.amount)
+                .ok_or_else(|| {
+                    format!(
+                        "cannot swap {} into pool {} {:?}",
+                        swap.amount, swap.pool, swap.direction
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Convert `amount`, that `mev_path` would swap in `hop`, to a token amount.
+/// Returns `AmountOverflow` when it is more than a token account holds.
+pub fn to_token_amount(
+    mev_path: &MevPath,
+    amount: u128,
+    hop: usize,
+) -> Result<u64, PathSkipReason> {
+    u64::try_from(amount).map_err(|_| {
+        warn_throttled!(
+            "mev_amount_overflow",
+            LOG_THROTTLE_INTERVAL,
+            "[MEV] Path {} would swap {} tokens in hop {}, more than a token account holds, skipping it",
+            mev_path.name,
+            amount,
+            hop
+        );
+        PathSkipReason::AmountOverflow { hop }
+    })
+}
+
+/// What one hop of a path trades, see `simulate_hops`.
+#[derive(Debug, Clone)]
+pub struct HopSimulation<'a> {
+    /// The trade of every pool of the hop that gets a share of its input.
+    pub trades: Vec<PoolTrade<'a>>,
+    pub input_output_pair: InputOutputPairs,
+    /// The trade fees and owner trade fees that the pools take from the input
+    /// of the hop, in the token that goes in.
+    pub fee: u64,
+}
+
+/// Trade `initial_amount` through the hops of `mev_path`, where every hop puts
+/// in what the hop before it got out. `hops` are the candidates of every hop,
+/// as returned by `MevPath::resolve_hops`. This is only the math of the path,
+/// it does not check the output against the reserves of the pools.
+pub fn simulate_hops<'a>(
+    mev_path: &MevPath,
+    hops: &[HopCandidates<'a>],
+    initial_amount: u128,
+) -> Result<Vec<HopSimulation<'a>>, PathSkipReason> {
+    let mut amount_in = initial_amount;
+    let mut hop_simulations = Vec::with_capacity(mev_path.path.len());
+    for (hop, (pair_info, candidates)) in mev_path.path.iter().zip(hops).enumerate() {
+        // Select the candidate pool that gives us the most output for this
+        // hop, or for a pool group, split the input across its pools.
+        let trades = pair_info
+            .swap(candidates, amount_in)
+            .ok_or(PathSkipReason::SwapFailed)?;
+        let amount_out: u128 = trades.iter().map(|trade| trade.amount_out).sum();
+        let mut fee = 0;
+        let mut pool_splits = Vec::new();
+        for trade in &trades {
+            let amount_after_fees = trade
+                .state
+                .fees
+                .apply_fees(trade.amount_in)
+                .ok_or(PathSkipReason::SwapFailed)?;
+            fee += trade.amount_in - amount_after_fees;
+            if trades.len() > 1 {
+                pool_splits.push(PoolSplit {
+                    pool: *trade.pool,
+                    token_in: to_token_amount(mev_path, trade.amount_in, hop)?,
+                    token_out: to_token_amount(mev_path, trade.amount_out, hop)?,
+                });
+            }
+        }
+        hop_simulations.push(HopSimulation {
+            input_output_pair: InputOutputPairs {
+                token_in: to_token_amount(mev_path, amount_in, hop)?,
+                token_out: to_token_amount(mev_path, amount_out, hop)?,
+                pool_splits,
+            },
+            fee: to_token_amount(mev_path, fee, hop)?,
+            trades,
+        });
+        amount_in = amount_out;
+    }
+    Ok(hop_simulations)
+}
+
+/// What trading an amount through a path would give, see `simulate_path`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathSimulation {
+    pub input_output_pairs: Vec<InputOutputPairs>,
+    /// The output of the last hop minus the input of the first hop, before
+    /// the fees of the transaction.
+    pub profit: i128,
+    /// By hop, the fees that its pools take, see `HopSimulation::fee`.
+    pub hop_fees: Vec<u64>,
+}
+
+/// Trade `initial_amount` through `mev_path` against `pool_states`, as the
+/// engine quotes a path, but without a `Mev`, e.g. to backtest paths against
+/// historical pool states. Returns `None` when a pool of the path is missing,
+/// or when a hop cannot swap.
+pub fn simulate_path(
+    mev_path: &MevPath,
+    pool_states: &PoolStates,
+    initial_amount: u128,
+) -> Option<PathSimulation> {
+    let hops = mev_path.resolve_hops(pool_states).ok()?;
+    let hop_simulations = simulate_hops(mev_path, &hops, initial_amount).ok()?;
+    let amount_out = hop_simulations.last()?.input_output_pair.token_out;
+    Some(PathSimulation {
+        profit: amount_out as i128 - initial_amount as i128,
+        hop_fees: hop_simulations.iter().map(|hop| hop.fee).collect(),
+        input_output_pairs: hop_simulations
+            .into_iter()
+            .map(|hop| hop.input_output_pair)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crossbeam_channel::unbounded;
+    use solana_sdk::{hash::Hash, rent::Rent};
+    use spl_token_swap::curve::base::SwapCurve;
+
+    use super::*;
+    use crate::mev::{
+        arbitrage::{MevPath, PairInfo},
+        config::MevConfig,
+        pool_state::{Fees, PoolBalances},
+        test_utils::{
+            orca_accounts, pubkey, steth_stsol_usdc_path, steth_stsol_usdc_pool_states,
+            STETH_USDC_POOL, STSOL_STETH_POOL, STSOL_USDC_POOL,
+        },
+        Mev, OrcaPoolWithBalance,
+    };
+
+    #[test]
+    fn test_parse_hypothetical_swap() {
+        let pool = Pubkey::new_unique();
+        assert_eq!(
+            format!("pool={},direction=AtoB,amount=100000", pool).parse::<HypotheticalSwap>(),
+            Ok(HypotheticalSwap {
+                pool,
+                direction: TradeDirection::AtoB,
+                amount: 100_000,
+            })
+        );
+        assert_eq!(
+            format!("amount=5, direction=BtoA, pool={}", pool).parse::<HypotheticalSwap>(),
+            Ok(HypotheticalSwap {
+                pool,
+                direction: TradeDirection::BtoA,
+                amount: 5,
+            })
+        );
+
+        let parse = |input: String| input.parse::<HypotheticalSwap>().unwrap_err();
+        assert_eq!(
+            parse(format!("pool={},direction=AtoB", pool)),
+            "amount is missing"
+        );
+        assert_eq!(
+            parse(format!("pool={},direction=up,amount=1", pool)),
+            "direction should be AtoB or BtoA, but got \"up\""
+        );
+        assert_eq!(
+            parse(format!("pool={},direction=AtoB,amount=1,amount=2", pool)),
+            "amount is given more than once"
+        );
+        assert_eq!(
+            parse(format!("pool={},direction=AtoB,amount=1,fee=2", pool)),
+            "unknown key \"fee\""
+        );
+        assert_eq!(
+            parse("pool".to_owned()),
+            "expected key=value, but got \"pool\""
+        );
+    }
+
+    #[test]
+    fn test_hypothetical_swap_creates_opportunity() {
+        // Both pools trade A for B one to one, there is no opportunity until
+        // someone buys A from pool 1.
+        let (pool_1, pool_2) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut pool_states: PoolStates = [pool_1, pool_2]
+            .into_iter()
+            .map(|address| {
+                let mut pool_state = OrcaPoolWithBalance::new(
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    1_000_000_000,
+                    1_000_000_000,
+                    Fees::from_trade_fees(25, 10_000, 5, 10_000),
+                    SwapCurve::default(),
+                );
+                pool_state.pool.address = address;
+                (address, pool_state)
+            })
+            .collect();
+        let mev_config = MevConfig {
+            orca_accounts: orca_accounts(&pool_states),
+            mev_paths: vec![MevPath {
+                name: "A->B->A".to_owned(),
+                path: vec![
+                    PairInfo {
+                        pool: pool_1,
+                        alternative_pools: vec![],
+                        group: None,
+                        direction: TradeDirection::AtoB,
+                    },
+                    PairInfo {
+                        pool: pool_2,
+                        alternative_pools: vec![],
+                        group: None,
+                        direction: TradeDirection::BtoA,
+                    },
+                ],
+                split_parts: None,
+                require_confirmations: None,
+                max_input: None,
+                slippage_bps: None,
+                compute_unit_price: None,
+                compute_unit_limit: None,
+                enabled: true,
+                probe_reverse: false,
+            }],
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let evaluate = |pool_states: &PoolStates| {
+            mev.get_arbitrage_tx_outputs(pool_states, Hash::default(), &Rent::default(), None)
+        };
+        assert!(evaluate(&pool_states).is_empty());
+
+        // The swaps compose: the second one trades against the balances that
+        // the first one left, so it gets less out.
+        let swap = HypotheticalSwap {
+            pool: pool_1,
+            direction: TradeDirection::BtoA,
+            amount: 100_000_000,
+        };
+        let amounts_out =
+            apply_hypothetical_swaps(&mut pool_states, &[swap.clone(), swap.clone()]).unwrap();
+        assert!(amounts_out[1] < amounts_out[0]);
+        let pool_state = &pool_states.0[&pool_1];
+        assert_eq!(pool_state.pool_b_balance, 1_200_000_000);
+        assert_eq!(
+            pool_state.pool_a_balance,
+            1_000_000_000 - amounts_out[0] - amounts_out[1]
+        );
+
+        let outputs = evaluate(&pool_states);
+        assert_eq!(outputs.len(), 1);
+        assert!(outputs[0].profit > 0);
+
+        let unknown_pool = HypotheticalSwap {
+            pool: Pubkey::new_unique(),
+            ..swap
+        };
+        assert_eq!(
+            apply_hypothetical_swaps(&mut pool_states, &[unknown_pool.clone()]),
+            Err(format!(
+                "pool {} is not in the pool states",
+                unknown_pool.pool
+            ))
+        );
+    }
+
+    #[test]
+    fn test_simulate_path_reproduces_the_engine() {
+        // The balances of `steth_stsol_usdc_pool_states`, as we would read
+        // them from snapshots of the pools' accounts.
+        let pool_states = PoolStates::from_balances(
+            [
+                (STETH_USDC_POOL, 4618233234, 6400518033),
+                (STSOL_STETH_POOL, 54896627850684, 13408494240),
+                (STSOL_USDC_POOL, 400881658679, 138436018345),
+            ]
+            .into_iter()
+            .map(|(address, pool_a_balance, pool_b_balance)| {
+                let balances = PoolBalances {
+                    pool_a_balance,
+                    pool_b_balance,
+                    fees: Fees::from_trade_fees(25, 10_000, 5, 10_000),
+                    swap_curve: SwapCurve::default(),
+                };
+                (pubkey(address), balances)
+            }),
+        );
+        let mev_path = steth_stsol_usdc_path();
+        let simulation = simulate_path(&mev_path, &pool_states, 4099483579).unwrap();
+        assert_eq!(
+            simulation,
+            PathSimulation {
+                input_output_pairs: vec![
+                    InputOutputPairs {
+                        token_in: 4099483579,
+                        token_out: 1799781506,
+                        pool_splits: vec![],
+                    },
+                    InputOutputPairs {
+                        token_in: 1799781506,
+                        token_out: 6479400819484,
+                        pool_splits: vec![],
+                    },
+                    InputOutputPairs {
+                        token_in: 6479400819484,
+                        token_out: 130347150790,
+                        pool_splits: vec![],
+                    },
+                ],
+                profit: 126247667211,
+                // 25 and 5 basis points of the input of every hop, rounded
+                // down.
+                hop_fees: vec![12298449, 5399343, 19438202457],
+            }
+        );
+
+        // The engine quotes the optimal input of the path the same way.
+        let fixture_states = steth_stsol_usdc_pool_states();
+        let mev_config = MevConfig {
+            orca_accounts: orca_accounts(&fixture_states),
+            mev_paths: vec![mev_path.clone()],
+            ..MevConfig::default()
+        };
+        let (log_send_channel, _log_receiver) = unbounded();
+        let mev = Mev::new(log_send_channel, mev_config);
+        let outputs =
+            mev.get_arbitrage_tx_outputs(&fixture_states, Hash::default(), &Rent::default(), None);
+        assert_eq!(outputs[0].input_output_pairs, simulation.input_output_pairs);
+        assert_eq!(outputs[0].profit as i128, simulation.profit);
+
+        // A trade against the price loses.
+        let simulation = simulate_path(&mev_path, &pool_states, 1_000_000_000_000).unwrap();
+        assert!(simulation.profit < 0);
+
+        let missing_pool = PoolStates::from_balances([]);
+        assert_eq!(simulate_path(&mev_path, &missing_pool, 4099483579), None);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+
+use super::{
+    arbitrage::MevPath,
+    lock_impact::{LockImpactReport, LockImpactStatus},
+    watchdog::Watchdog,
+};
+
+/// A point-in-time view of the MEV module that the log thread keeps up to
+/// date, and writes to `status_path` on request, see `MevMsg::WriteStatus`.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MevStatus {
+    /// The latest slot that we know of.
+    pub slot: Option<Slot>,
+
+    pub counters: MevCounters,
+
+    /// Per path, in config order.
+    pub paths: Vec<PathStatus>,
+
+    /// Per token mint, the amount committed to MEV transactions that did not
+    /// execute yet, at the time of the request.
+    pub inflight_exposure: BTreeMap<String, u64>,
+
+    pub watchdog: WatchdogStatus,
+
+    /// The lock impact of the MEV keys on the transactions that we sampled,
+    /// see `lock_impact_sampling_rate`. Not written when we sample none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_impact: Option<LockImpactStatus>,
+}
+
+/// Counts since the log thread started.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MevCounters {
+    pub triggers: u64,
+    pub opportunities: u64,
+    /// Opportunities whose input our balance of the input token limited.
+    pub capped_opportunities: u64,
+    pub executed_transactions: u64,
+    pub successful_transactions: u64,
+    pub dropped_transactions: u64,
+    pub competitor_arbitrages: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct PathStatus {
+    pub name: String,
+    pub opportunities: u64,
+    pub last_opportunity_slot: Option<Slot>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct WatchdogStatus {
+    pub trigger_stale: bool,
+    pub opportunity_stale: bool,
+}
+
+impl MevStatus {
+    pub fn new(mev_paths: &[MevPath]) -> Self {
+        MevStatus {
+            paths: mev_paths
+                .iter()
+                .map(|mev_path| PathStatus {
+                    name: mev_path.name.clone(),
+                    opportunities: 0,
+                    last_opportunity_slot: None,
+                })
+                .collect(),
+            ..MevStatus::default()
+        }
+    }
+
+    /// Follow the paths of a reloaded config. The counts of a path carry over
+    /// when the new config has a path with the same name.
+    pub fn set_paths(&mut self, mev_paths: &[MevPath]) {
+        let mut old_paths: HashMap<String, PathStatus> = self
+            .paths
+            .drain(..)
+            .map(|path| (path.name.clone(), path))
+            .collect();
+        self.paths = mev_paths
+            .iter()
+            .map(|mev_path| {
+                old_paths
+                    .remove(&mev_path.name)
+                    .unwrap_or_else(|| PathStatus {
+                        name: mev_path.name.clone(),
+                        opportunities: 0,
+                        last_opportunity_slot: None,
+                    })
+            })
+            .collect();
+    }
+
+    /// Count an opportunity on the path at `path_idx`, found in `slot`, that
+    /// our balance `capped` or not.
+    pub fn record_opportunity(&mut self, path_idx: usize, slot: Option<Slot>, capped: bool) {
+        self.counters.opportunities += 1;
+        if capped {
+            self.counters.capped_opportunities += 1;
+        }
+        if let Some(path) = self.paths.get_mut(path_idx) {
+            path.opportunities += 1;
+            path.last_opportunity_slot = slot.or(path.last_opportunity_slot);
+        }
+    }
+
+    pub fn record_lock_impact(&mut self, report: LockImpactReport) {
+        self.lock_impact
+            .get_or_insert_with(LockImpactStatus::default)
+            .record(report);
+    }
+
+    pub fn record_execution(&mut self, is_successful: bool) {
+        self.counters.executed_transactions += 1;
+        if is_successful {
+            self.counters.successful_transactions += 1;
+        }
+    }
+
+    /// Fill in what the log thread does not keep track of itself, before we
+    /// write the status.
+    pub fn update(&mut self, watchdog: &Watchdog, inflight_exposure: &HashMap<Pubkey, u64>) {
+        self.slot = watchdog.slot();
+        self.watchdog = WatchdogStatus {
+            trigger_stale: watchdog.is_trigger_stale(),
+            opportunity_stale: watchdog.is_opportunity_stale(),
+        };
+        self.inflight_exposure = inflight_exposure
+            .iter()
+            .map(|(mint, amount)| (mint.to_string(), *amount))
+            .collect();
+    }
+}
+
+/// Where we write the status for the MEV log at `log_path`:
+/// `<log_path>.status.json`.
+pub fn status_path(log_path: &Path) -> PathBuf {
+    let mut path = OsString::from(log_path.as_os_str());
+    path.push(".status.json");
+    PathBuf::from(path)
+}
+
+/// Write `status` as pretty-printed JSON to `path`. We write to a temporary
+/// file first and rename it, so that readers see either the previous status
+/// or the new one in full.
+pub fn write_status_file(path: &Path, status: &MevStatus) -> io::Result<()> {
+    let mut tmp_path = OsString::from(path.as_os_str());
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut file = File::create(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut file, status)?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mev::test_utils::path;
+
+    #[test]
+    fn test_write_status_file() {
+        let dir = TempDir::new().unwrap();
+        let status_file = status_path(&dir.path().join("mev.log"));
+        assert_eq!(status_file, dir.path().join("mev.log.status.json"));
+
+        let mut status = MevStatus::new(&[path("a").build(), path("b").build()]);
+        write_status_file(&status_file, &status).unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&status_file).unwrap()).unwrap();
+        assert_eq!(written["counters"]["opportunities"], 0);
+        assert_eq!(written["paths"][1]["name"], "b");
+
+        // A second write replaces the file as a whole, and leaves no
+        // temporary file behind.
+        status.record_opportunity(1, Some(7), true);
+        status.record_execution(true);
+        write_status_file(&status_file, &status).unwrap();
+        let contents = fs::read_to_string(&status_file).unwrap();
+        assert!(contents.starts_with("{\n  \"slot\""));
+        let written: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_eq!(written["counters"]["opportunities"], 1);
+        assert_eq!(written["counters"]["capped_opportunities"], 1);
+        assert_eq!(written["counters"]["successful_transactions"], 1);
+        assert_eq!(written["paths"][1]["last_opportunity_slot"], 7);
+        let files: Vec<PathBuf> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files, vec![status_file]);
+    }
+
+    #[test]
+    fn test_set_paths() {
+        let mut status = MevStatus::new(&[path("a").build(), path("b").build()]);
+        status.record_opportunity(1, Some(7), false);
+        status.set_paths(&[path("b").build(), path("c").build()]);
+        assert_eq!(
+            status.paths,
+            vec![
+                PathStatus {
+                    name: "b".to_owned(),
+                    opportunities: 1,
+                    last_opportunity_slot: Some(7),
+                },
+                PathStatus {
+                    name: "c".to_owned(),
+                    opportunities: 0,
+                    last_opportunity_slot: None,
+                },
+            ]
+        );
+        assert_eq!(status.counters.opportunities, 1);
+    }
+}
@@ -0,0 +1,444 @@
+//! Builders for the pool states and paths that tests evaluate, so that a test
+//! only spells out what matters to it. Compiled for our own tests, and with
+//! the `testing` feature for benchmarks and other crates.
+
+use std::{str::FromStr, sync::Arc};
+
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    pubkey::Pubkey,
+};
+use spl_token::solana_program::program_pack::Pack;
+use spl_token_swap::{
+    curve::{
+        base::{CurveType, SwapCurve},
+        calculator::CurveCalculator,
+        constant_product::ConstantProductCurve,
+        stable::StableCurve,
+    },
+    state::SwapVersion,
+};
+
+use super::{
+    arbitrage::{MevPath, PairInfo, TradeDirection},
+    config::AllOrcaPoolAddresses,
+    mint::{MintInfo, TransferFee, TransferFeeConfig},
+    pool_state::Fees,
+    OrcaPoolAddresses, OrcaPoolWithBalance, PoolStates,
+};
+use crate::inline_spl_token;
+
+pub const ORCA_PROGRAM_ID: &str = "9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP";
+
+/// Pools of the stSOL/USDC/wstETH scenario, see `steth_stsol_usdc_pool_states`.
+pub const STETH_USDC_POOL: &str = "v51xWrRwmFVH6EKe8eZTjgK5E4uC2tzY5sVt5cHbrkG";
+pub const STSOL_STETH_POOL: &str = "B32UuhPSp6srSBbRTh4qZNjkegsehY9qXTwQgnPWYMZy";
+pub const STSOL_USDC_POOL: &str = "EfK84vYEKT1PoTJr6fBVKFbyA7ZoftfPo2LQPAJG1exL";
+
+/// Parse a base58 pubkey, panics when it is not valid.
+pub fn pubkey(b58: &str) -> Pubkey {
+    Pubkey::from_str(b58).unwrap_or_else(|_| panic!("{} should be a valid pubkey", b58))
+}
+
+/// Start building the state of the Orca pool at `address`. Unless set
+/// otherwise, the pool has the usual Orca fees of 25 and 5 basis points, a
+/// constant product curve, no balances, token accounts of the SPL token
+/// program, and none of our token accounts.
+pub fn pool(address: Pubkey) -> PoolBuilder {
+    PoolBuilder {
+        pool: OrcaPoolAddresses {
+            program_id: pubkey(ORCA_PROGRAM_ID),
+            address,
+            token_program: inline_spl_token::id(),
+            ..OrcaPoolAddresses::default()
+        },
+        pool_a_balance: 0,
+        pool_b_balance: 0,
+        fees: Fees::from_trade_fees(25, 10_000, 5, 10_000),
+        curve_type: CurveType::ConstantProduct,
+        curve_calculator: Arc::new(ConstantProductCurve::default()),
+        source_balance: None,
+        destination_balance: None,
+        pool_a_mint_info: None,
+        pool_b_mint_info: None,
+    }
+}
+
+/// A Token-2022 mint with 6 decimals that takes a transfer fee of
+/// `basis_points`, without a maximum, in every epoch.
+pub fn transfer_fee_mint_info(basis_points: u16) -> MintInfo {
+    let transfer_fee = TransferFee {
+        epoch: 0,
+        maximum_fee: u64::MAX,
+        basis_points,
+    };
+    MintInfo {
+        decimals: 6,
+        transfer_fee_config: Some(TransferFeeConfig {
+            older: transfer_fee,
+            newer: transfer_fee,
+        }),
+    }
+}
+
+pub struct PoolBuilder {
+    pool: OrcaPoolAddresses,
+    pool_a_balance: u64,
+    pool_b_balance: u64,
+    fees: Fees,
+    curve_type: CurveType,
+    curve_calculator: Arc<dyn CurveCalculator + Sync + Send>,
+    source_balance: Option<u64>,
+    destination_balance: Option<u64>,
+    pool_a_mint_info: Option<MintInfo>,
+    pool_b_mint_info: Option<MintInfo>,
+}
+
+impl PoolBuilder {
+    pub fn balances(mut self, pool_a_balance: u64, pool_b_balance: u64) -> Self {
+        self.pool_a_balance = pool_a_balance;
+        self.pool_b_balance = pool_b_balance;
+        self
+    }
+
+    /// Set the trade fee and the owner trade fee, in basis points.
+    pub fn fees_bps(mut self, trade_fee: u64, owner_trade_fee: u64) -> Self {
+        self.fees = Fees::from_trade_fees(trade_fee, 10_000, owner_trade_fee, 10_000);
+        self
+    }
+
+    pub fn fees(mut self, fees: Fees) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Make the pool a stable curve pool with amplification coefficient `amp`.
+    pub fn stable(mut self, amp: u64) -> Self {
+        self.curve_type = CurveType::Stable;
+        self.curve_calculator = Arc::new(StableCurve { amp });
+        self
+    }
+
+    pub fn mints(mut self, pool_a_mint: Pubkey, pool_b_mint: Pubkey) -> Self {
+        self.pool.pool_a_mint = pool_a_mint;
+        self.pool.pool_b_mint = pool_b_mint;
+        self
+    }
+
+    /// Set what we read from the mints of the pool's tokens, as when the
+    /// mints were loaded with the pool. Only `build` uses them.
+    pub fn mint_infos(
+        mut self,
+        pool_a_mint_info: Option<MintInfo>,
+        pool_b_mint_info: Option<MintInfo>,
+    ) -> Self {
+        self.pool_a_mint_info = pool_a_mint_info;
+        self.pool_b_mint_info = pool_b_mint_info;
+        self
+    }
+
+    /// Set the token program that owns the pool's token accounts, e.g.
+    /// Token-2022.
+    pub fn token_program(mut self, token_program: Pubkey) -> Self {
+        self.pool.token_program = token_program;
+        self
+    }
+
+    /// Set our token account that we trade into the pool from.
+    pub fn source(mut self, source: Pubkey) -> Self {
+        self.pool.source = Some(source);
+        self
+    }
+
+    /// Set our token account that we receive the output of the pool in.
+    pub fn destination(mut self, destination: Pubkey) -> Self {
+        self.pool.destination = Some(destination);
+        self
+    }
+
+    pub fn source_balance(mut self, source_balance: u64) -> Self {
+        self.source_balance = Some(source_balance);
+        self
+    }
+
+    pub fn destination_balance(mut self, destination_balance: u64) -> Self {
+        self.destination_balance = Some(destination_balance);
+        self
+    }
+
+    /// The pool's address and its state, to collect into `PoolStates`.
+    pub fn build(self) -> (Pubkey, OrcaPoolWithBalance) {
+        (
+            self.pool.address,
+            OrcaPoolWithBalance {
+                pool: self.pool,
+                pool_a_balance: self.pool_a_balance,
+                pool_b_balance: self.pool_b_balance,
+                fees: self.fees,
+                curve_type: self.curve_type,
+                curve_calculator: self.curve_calculator,
+                source_balance: self.source_balance,
+                destination_balance: self.destination_balance,
+                pool_a_mint_info: self.pool_a_mint_info,
+                pool_b_mint_info: self.pool_b_mint_info,
+            },
+        )
+    }
+
+    /// The pool's addresses, as in the config, and its accounts, as an RPC
+    /// node returns them: the swap state, owned by the pool's program, and the
+    /// pool's and our token accounts and the mints of its tokens, owned by the
+    /// SPL token program. Token accounts and mints that are not set get unique
+    /// addresses.
+    pub fn build_accounts(mut self) -> (OrcaPoolAddresses, Vec<(Pubkey, AccountSharedData)>) {
+        let unique_if_default = |pubkey: &mut Pubkey| {
+            if *pubkey == Pubkey::default() {
+                *pubkey = Pubkey::new_unique();
+            }
+        };
+        unique_if_default(&mut self.pool.pool_a_account);
+        unique_if_default(&mut self.pool.pool_b_account);
+        unique_if_default(&mut self.pool.pool_a_mint);
+        unique_if_default(&mut self.pool.pool_b_mint);
+        unique_if_default(&mut self.pool.pool_mint);
+        unique_if_default(&mut self.pool.pool_fee);
+
+        let mut swap_data = vec![0; SwapVersion::LATEST_LEN];
+        SwapVersion::pack(
+            SwapVersion::SwapV1(spl_token_swap::state::SwapV1 {
+                is_initialized: true,
+                bump_seed: 0,
+                token_program_id: to_program_pubkey(&inline_spl_token::id()),
+                token_a: to_program_pubkey(&self.pool.pool_a_account),
+                token_b: to_program_pubkey(&self.pool.pool_b_account),
+                pool_mint: to_program_pubkey(&self.pool.pool_mint),
+                token_a_mint: to_program_pubkey(&self.pool.pool_a_mint),
+                token_b_mint: to_program_pubkey(&self.pool.pool_b_mint),
+                pool_fee_account: to_program_pubkey(&self.pool.pool_fee),
+                fees: self.fees.0.clone(),
+                swap_curve: SwapCurve {
+                    curve_type: self.curve_type,
+                    calculator: self.curve_calculator.clone(),
+                },
+            }),
+            &mut swap_data,
+        )
+        .expect("The swap state should fit");
+        let mut accounts = vec![(self.pool.address, account(swap_data, self.pool.program_id))];
+
+        let pool_a_mint = self.pool.pool_a_mint;
+        let pool_b_mint = self.pool.pool_b_mint;
+        accounts.push((pool_a_mint, mint_account()));
+        accounts.push((pool_b_mint, mint_account()));
+        accounts.push((
+            self.pool.pool_a_account,
+            token_account(&pool_a_mint, self.pool_a_balance),
+        ));
+        accounts.push((
+            self.pool.pool_b_account,
+            token_account(&pool_b_mint, self.pool_b_balance),
+        ));
+        if let Some(source) = self.pool.source {
+            accounts.push((
+                source,
+                token_account(&pool_a_mint, self.source_balance.unwrap_or(0)),
+            ));
+        }
+        if let Some(destination) = self.pool.destination {
+            accounts.push((
+                destination,
+                token_account(&pool_b_mint, self.destination_balance.unwrap_or(0)),
+            ));
+        }
+        (self.pool, accounts)
+    }
+}
+
+/// The SPL crates use the `Pubkey` of their own `solana_program`.
+fn to_program_pubkey(pubkey: &Pubkey) -> spl_token::solana_program::pubkey::Pubkey {
+    spl_token::solana_program::pubkey::Pubkey::new(&pubkey.to_bytes())
+}
+
+fn account(data: Vec<u8>, owner: Pubkey) -> AccountSharedData {
+    AccountSharedData::from(Account {
+        lamports: 1,
+        data,
+        owner,
+        ..Account::default()
+    })
+}
+
+/// An initialized SPL token account of `mint` that holds `amount`.
+fn token_account(mint: &Pubkey, amount: u64) -> AccountSharedData {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account::pack(
+        spl_token::state::Account {
+            mint: to_program_pubkey(mint),
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        },
+        &mut data,
+    )
+    .expect("The token account should fit");
+    account(data, inline_spl_token::id())
+}
+
+/// An initialized SPL token mint with 6 decimals.
+fn mint_account() -> AccountSharedData {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint::pack(
+        spl_token::state::Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..spl_token::state::Mint::default()
+        },
+        &mut data,
+    )
+    .expect("The mint should fit");
+    account(data, inline_spl_token::id())
+}
+
+/// Start building the path called `name`, without hops.
+pub fn path(name: &str) -> PathBuilder {
+    PathBuilder {
+        path: MevPath {
+            name: name.to_owned(),
+            path: vec![],
+            split_parts: None,
+            require_confirmations: None,
+            max_input: None,
+            slippage_bps: None,
+            compute_unit_price: None,
+            compute_unit_limit: None,
+            enabled: true,
+            probe_reverse: false,
+        },
+    }
+}
+
+pub struct PathBuilder {
+    path: MevPath,
+}
+
+impl PathBuilder {
+    pub fn hop(self, pool: Pubkey, direction: TradeDirection) -> Self {
+        self.hop_with_alternatives(pool, vec![], direction)
+    }
+
+    /// Add a hop that can trade through `pool` or any of `alternative_pools`.
+    pub fn hop_with_alternatives(
+        mut self,
+        pool: Pubkey,
+        alternative_pools: Vec<Pubkey>,
+        direction: TradeDirection,
+    ) -> Self {
+        self.path.path.push(PairInfo {
+            pool,
+            alternative_pools,
+            group: None,
+            direction,
+        });
+        self
+    }
+
+    /// Add a hop through the pool group `group` of `pools`, resolved as
+    /// `MevConfig::resolve_pool_groups` does it.
+    pub fn group_hop(mut self, group: &str, pools: &[Pubkey], direction: TradeDirection) -> Self {
+        self.path.path.push(PairInfo {
+            pool: pools[0],
+            alternative_pools: pools[1..].to_vec(),
+            group: Some(group.to_owned()),
+            direction,
+        });
+        self
+    }
+
+    /// Also quote the path as `split_parts` transactions, see
+    /// `MevPath::split_parts`.
+    pub fn split_parts(mut self, split_parts: u8) -> Self {
+        self.path.split_parts = Some(split_parts);
+        self
+    }
+
+    /// See `MevPath::require_confirmations`.
+    pub fn require_confirmations(mut self, require_confirmations: u8) -> Self {
+        self.path.require_confirmations = Some(require_confirmations);
+        self
+    }
+
+    /// See `MevPath::max_input`.
+    pub fn max_input(mut self, max_input: u64) -> Self {
+        self.path.max_input = Some(max_input);
+        self
+    }
+
+    /// See `MevPath::slippage_bps`.
+    pub fn slippage_bps(mut self, slippage_bps: u16) -> Self {
+        self.path.slippage_bps = Some(slippage_bps);
+        self
+    }
+
+    /// See `MevPath::compute_unit_price`.
+    pub fn compute_unit_price(mut self, compute_unit_price: u64) -> Self {
+        self.path.compute_unit_price = Some(compute_unit_price);
+        self
+    }
+
+    /// See `MevPath::compute_unit_limit`.
+    pub fn compute_unit_limit(mut self, compute_unit_limit: u32) -> Self {
+        self.path.compute_unit_limit = Some(compute_unit_limit);
+        self
+    }
+
+    /// See `MevPath::probe_reverse`.
+    pub fn probe_reverse(mut self) -> Self {
+        self.path.probe_reverse = true;
+        self
+    }
+
+    /// See `MevPath::enabled`.
+    pub fn disabled(mut self) -> Self {
+        self.path.enabled = false;
+        self
+    }
+
+    pub fn build(self) -> MevPath {
+        self.path
+    }
+}
+
+/// The config entries of the pools in `pool_states`, so that the paths
+/// through them pass the checks of `Mev::new`.
+pub fn orca_accounts(pool_states: &PoolStates) -> AllOrcaPoolAddresses {
+    AllOrcaPoolAddresses::new(
+        pool_states
+            .0
+            .values()
+            .map(|pool_state| pool_state.pool.clone())
+            .collect(),
+    )
+}
+
+/// Realistic balances of the stETH/USDC, stSOL/stETH and stSOL/USDC pools,
+/// with a large opportunity on `steth_stsol_usdc_path`.
+pub fn steth_stsol_usdc_pool_states() -> PoolStates {
+    [
+        pool(pubkey(STETH_USDC_POOL)).balances(4618233234, 6400518033),
+        pool(pubkey(STSOL_STETH_POOL)).balances(54896627850684, 13408494240),
+        pool(pubkey(STSOL_USDC_POOL)).balances(400881658679, 138436018345),
+    ]
+    .into_iter()
+    .map(PoolBuilder::build)
+    .collect()
+}
+
+/// The USDC -> stETH -> stSOL -> USDC cycle through the pools of
+/// `steth_stsol_usdc_pool_states`.
+pub fn steth_stsol_usdc_path() -> MevPath {
+    path("USDC->stETH->stSOL->USDC")
+        .hop(pubkey(STETH_USDC_POOL), TradeDirection::BtoA)
+        .hop(pubkey(STSOL_STETH_POOL), TradeDirection::BtoA)
+        .hop(pubkey(STSOL_USDC_POOL), TradeDirection::AtoB)
+        .build()
+}
@@ -1,30 +1,59 @@
-use std::{collections::HashMap, fs::read_to_string, path::PathBuf, str::FromStr};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
+use lazy_static::lazy_static;
 use serde::{Deserialize, Deserializer, Serializer};
-use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{bs58, pubkey::Pubkey};
 
-use super::{arbitrage::MevPath, OrcaPoolAddresses};
+// The config used to live in this module, keep it reachable from here.
+pub use super::config::{get_mev_config_file, parse_mev_config, AllOrcaPoolAddresses, MevConfig};
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct AllOrcaPoolAddresses(pub Vec<OrcaPoolAddresses>);
-
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
-pub struct MevConfig {
-    pub log_path: PathBuf,
-
-    pub watched_programs: Vec<B58Pubkey>,
-
-    #[serde(rename(deserialize = "orca_account"))]
-    pub orca_accounts: AllOrcaPoolAddresses,
-
-    /// Specify paths to look for MEV opportunities.
-    // #[serde(rename(deserialize = "mev_path"))]
-    #[serde(rename(deserialize = "mev_path"))]
-    pub mev_paths: Vec<MevPath>,
-
-    pub user_authority_path: Option<PathBuf>,
+/// Expand a path from the config: a leading `~` becomes `$HOME`, and
+/// `${VAR}` becomes the value of the environment variable `VAR`. A variable
+/// that is not set is an error, rather than a path that we cannot open.
+pub fn expand_path(path: &Path) -> Result<PathBuf, String> {
+    expand_path_with(path, |name| std::env::var(name).ok())
+}
 
-    pub minimum_profit: HashMap<B58Pubkey, u64>,
+fn expand_path_with(
+    path: &Path,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<PathBuf, String> {
+    // There is nothing to expand in a path that is not valid UTF-8.
+    let path_str = match path.to_str() {
+        Some(path_str) => path_str,
+        None => return Ok(path.to_owned()),
+    };
+    let mut expanded = String::with_capacity(path_str.len());
+    let mut rest = path_str;
+    if rest == "~" || rest.starts_with("~/") {
+        let home = lookup("HOME")
+            .ok_or_else(|| format!("path {} starts with ~, but HOME is not set", path_str))?;
+        expanded.push_str(&home);
+        rest = &rest[1..];
+    }
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("path {} has a ${{ without a closing }}", path_str))?;
+        let name = &rest[start + 2..end];
+        let value = lookup(name).ok_or_else(|| {
+            format!(
+                "path {} uses the environment variable {:?}, but it is not set",
+                path_str, name
+            )
+        })?;
+        expanded.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
 }
 
 /// Function to use when serializing a public key, to print it using base58.
@@ -43,13 +72,44 @@ pub fn serialize_opt_b58<S: Serializer, T: ToString>(
     }
 }
 
+/// Characters that do not show up when copying a key, but that are not
+/// whitespace either.
+const INVISIBLE_CHARS: [char; 4] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{FEFF}'];
+
+/// Parse a base58 public key, ignoring leading and trailing whitespace, with
+/// an error that says what is wrong with the key.
+pub fn parse_b58_pubkey(input: &str) -> Result<Pubkey, String> {
+    let trimmed =
+        input.trim_matches(|c: char| c.is_ascii_whitespace() || INVISIBLE_CHARS.contains(&c));
+    if let Some(c) = trimmed
+        .chars()
+        .find(|c| c.is_control() || c.is_whitespace() || INVISIBLE_CHARS.contains(c))
+    {
+        return Err(format!(
+            "public key {:?} contains the invisible or control character {:?}",
+            input, c
+        ));
+    }
+    let bytes = bs58::decode(trimmed)
+        .into_vec()
+        .map_err(|err| format!("public key {:?} is not valid base58: {}", input, err))?;
+    if bytes.len() != 32 {
+        return Err(format!(
+            "public key {:?} should be 32 bytes, but it is {} bytes",
+            input,
+            bytes.len()
+        ));
+    }
+    Ok(Pubkey::new(&bytes))
+}
+
 /// Function to use when deserializing a public key.
 pub fn deserialize_b58<'de, D>(deserializer: D) -> Result<Pubkey, D::Error>
 where
     D: Deserializer<'de>,
 {
     let buf = String::deserialize(deserializer)?;
-    Pubkey::from_str(&buf).map_err(serde::de::Error::custom)
+    parse_b58_pubkey(&buf).map_err(serde::de::Error::custom)
 }
 
 /// Function to use when deserializing an optional public key.
@@ -59,14 +119,14 @@ where
 {
     match Option::<String>::deserialize(deserializer)? {
         Some(str) => {
-            let pubkey = Pubkey::from_str(&str).map_err(serde::de::Error::custom)?;
+            let pubkey = parse_b58_pubkey(&str).map_err(serde::de::Error::custom)?;
             Ok(Some(pubkey))
         }
         None => Ok(None),
     }
 }
 
-#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Eq, Hash, PartialEq, Debug, Clone, Copy)]
 #[serde(transparent)]
 pub struct B58Pubkey(
     #[serde(serialize_with = "serialize_b58")]
@@ -74,118 +134,226 @@ pub struct B58Pubkey(
     pub Pubkey,
 );
 
-pub fn get_mev_config_file(config_path: &PathBuf) -> MevConfig {
-    let config_str = read_to_string(config_path).expect("Could not open config path.");
-    let config_file: MevConfig =
-        toml::from_str(&config_str).expect("Could not deserialize MEV config file.");
-    config_file
+/// Maximum number of distinct keys `LOG_THROTTLE` keeps track of.
+const LOG_THROTTLE_CAPACITY: usize = 1024;
+
+lazy_static! {
+    pub static ref LOG_THROTTLE: Mutex<LogThrottle> =
+        Mutex::new(LogThrottle::new(LOG_THROTTLE_CAPACITY));
+}
+
+/// Keeps track of when a log message with a given key was last emitted, so
+/// that messages on the hot path can be rate limited.
+pub struct LogThrottle {
+    capacity: usize,
+    // Per key, when it was last emitted and how many messages were suppressed
+    // since then.
+    entries: HashMap<String, (Instant, u64)>,
 }
 
+impl LogThrottle {
+    pub fn new(capacity: usize) -> Self {
+        LogThrottle {
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Returns `Some(suppressed)` if a message with `key` should be emitted at
+    /// `now`, where `suppressed` is the number of messages that were dropped
+    /// since the last one was emitted. Returns `None` if the message should be
+    /// suppressed, because the last one was emitted less than `interval` ago.
+    pub fn check(&mut self, key: &str, interval: Duration, now: Instant) -> Option<u64> {
+        if let Some((last_emitted, suppressed)) = self.entries.get_mut(key) {
+            if now.saturating_duration_since(*last_emitted) < interval {
+                *suppressed += 1;
+                return None;
+            }
+            let result = *suppressed;
+            *last_emitted = now;
+            *suppressed = 0;
+            return Some(result);
+        }
+
+        if self.entries.len() >= self.capacity {
+            // Make room by forgetting the key that was emitted the longest ago.
+            let oldest_key = self
+                .entries
+                .iter()
+                .min_by_key(|(_key, (last_emitted, _suppressed))| *last_emitted)
+                .map(|(key, _entry)| key.clone());
+            if let Some(oldest_key) = oldest_key {
+                self.entries.remove(&oldest_key);
+            }
+        }
+        self.entries.insert(key.to_owned(), (now, 0));
+        Some(0)
+    }
+}
+
+/// Log at `level`, but at most once per `interval` for every `key`. When a
+/// message is emitted after others were suppressed, the number of suppressed
+/// messages is appended.
+macro_rules! log_throttled {
+    ($level:expr, $key:expr, $interval:expr, $($arg:tt)+) => {{
+        let suppressed = $crate::mev::utils::LOG_THROTTLE.lock().unwrap().check(
+            $key,
+            $interval,
+            std::time::Instant::now(),
+        );
+        match suppressed {
+            Some(0) => log::log!($level, $($arg)+),
+            Some(suppressed) => log::log!(
+                $level,
+                "{} ({} similar messages suppressed)",
+                format_args!($($arg)+),
+                suppressed
+            ),
+            None => {}
+        }
+    }};
+}
+
+/// `warn!`, but at most once per `interval` for every `key`.
+macro_rules! warn_throttled {
+    ($key:expr, $interval:expr, $($arg:tt)+) => {
+        $crate::mev::utils::log_throttled!(log::Level::Warn, $key, $interval, $($arg)+)
+    };
+}
+
+/// `error!`, but at most once per `interval` for every `key`.
+macro_rules! error_throttled {
+    ($key:expr, $interval:expr, $($arg:tt)+) => {
+        $crate::mev::utils::log_throttled!(log::Level::Error, $key, $interval, $($arg)+)
+    };
+}
+
+pub(crate) use {error_throttled, log_throttled, warn_throttled};
+
 #[cfg(test)]
 mod tests {
-    use std::{path::PathBuf, str::FromStr};
-
-    use crate::mev::{
-        arbitrage::{PairInfo, TradeDirection},
-        utils::B58Pubkey,
-        *,
+    use std::{
+        str::FromStr,
+        time::{Duration, Instant},
     };
 
+    use super::*;
+
+    #[test]
+    fn test_parse_b58_pubkey() {
+        let pubkey = Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap();
+        assert_eq!(
+            parse_b58_pubkey(" 9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP\t"),
+            Ok(pubkey)
+        );
+        assert_eq!(
+            parse_b58_pubkey("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP\u{200B}"),
+            Ok(pubkey)
+        );
+        assert_eq!(
+            parse_b58_pubkey("9W959DqEETiGZocYW\u{7}CQPaJ6sBmUzgfxXfqGeTEdp3aQP"),
+            Err(
+                "public key \"9W959DqEETiGZocYW\\u{7}CQPaJ6sBmUzgfxXfqGeTEdp3aQP\" \
+                contains the invisible or control character '\\u{7}'"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            parse_b58_pubkey("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEd"),
+            Err(
+                "public key \"9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEd\" should be 32 bytes, \
+                but it is 29 bytes"
+                    .to_owned()
+            )
+        );
+        assert!(
+            parse_b58_pubkey("0W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP")
+                .unwrap_err()
+                .contains("is not valid base58")
+        );
+    }
+
     #[test]
-    fn test_deserialization() {
-        let sample_config: MevConfig = toml::from_str(
-            r#"
-    log_path = '/tmp/mev.log'
-    watched_programs = ['9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP']
-    minimum_profit = {}
-
-    [[orca_account]]
-        _id = 'USDC/USDT[stable]'
-        address = 'FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ'
-        pool_a_account = 'EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82'
-        pool_b_account = 'C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe'
-        pool_mint = '33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM'
-        pool_fee = 'GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr'
-
-    [[orca_account]]
-        _id = 'SOL/USDC[aquafarm]'
-        address = 'EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U'
-        pool_a_account = 'ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHoNd13Lg'
-        pool_b_account = '75HgnSvXbWKZBpZHveX68ZzAhDqMzNDS29X6BGLtxMo1'
-        pool_mint = 'APDFRM3HMr8CAGXwKHiu2f5ePSpaiEJhaURwhsRrUUt9'
-        pool_fee = '8JnSiuvQq3BVuCU3n4DrSTw9chBSPvEMswrhtifVkr1o'
-    
-    [[mev_path]]
-        name = "USDT->USDC->SOL"
-        path = [
-            { pool = "FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ", direction = "BtoA" },
-            { pool = "EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U", direction = "BtoA" },
-        ]
-    "#,
-        )
-        .expect("Failed to deserialize");
-
-        let expected_mev_config = MevConfig {
-            log_path: PathBuf::from_str("/tmp/mev.log").unwrap(),
-            watched_programs: vec![B58Pubkey(
-                Pubkey::from_str("9W959DqEETiGZocYWCQPaJ6sBmUzgfxXfqGeTEdp3aQP").unwrap(),
-            )],
-            orca_accounts: AllOrcaPoolAddresses(vec![
-                OrcaPoolAddresses {
-                    program_id: Pubkey::default(),
-                    address: Pubkey::from_str("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ")
-                        .unwrap(),
-                    pool_a_account: Pubkey::from_str(
-                        "EjUNm7Lzp6X8898JiCU28SbfQBfsYoWaViXUhCgizv82",
-                    )
-                    .unwrap(),
-                    pool_b_account: Pubkey::from_str(
-                        "C1ZrV56rf1wbDzcnHY6FpNaVmzT5D8WtyEKS1FAGrboe",
-                    )
-                    .unwrap(),
-                    pool_mint: Pubkey::from_str("33k9G5HeH5JFukXTVxx3EmZrqjhb19Ej2GC2kqVPCKnM")
-                        .unwrap(),
-                    pool_fee: Pubkey::from_str("GqtosegQU4ad7W9AMHAQuuAFnjBQZ4VB4eZuPFrz8ALr")
-                        .unwrap(),
-                    ..Default::default()
-                },
-                OrcaPoolAddresses {
-                    program_id: Pubkey::default(),
-                    address: Pubkey::from_str("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U")
-                        .unwrap(),
-                    pool_a_account: Pubkey::from_str(
-                        "ANP74VNsHwSrq9uUSjiSNyNWvf6ZPrKTmE4gHoNd13Lg",
-                    )
-                    .unwrap(),
-                    pool_b_account: Pubkey::from_str(
-                        "75HgnSvXbWKZBpZHveX68ZzAhDqMzNDS29X6BGLtxMo1",
-                    )
-                    .unwrap(),
-                    pool_mint: Pubkey::from_str("APDFRM3HMr8CAGXwKHiu2f5ePSpaiEJhaURwhsRrUUt9")
-                        .unwrap(),
-                    pool_fee: Pubkey::from_str("8JnSiuvQq3BVuCU3n4DrSTw9chBSPvEMswrhtifVkr1o")
-                        .unwrap(),
-                    ..Default::default()
-                },
-            ]),
-            mev_paths: vec![MevPath {
-                name: "USDT->USDC->SOL".to_owned(),
-                path: vec![
-                    PairInfo {
-                        pool: Pubkey::from_str("FX5UWkujjpU4yKB4yvKVEzG2Z8r2PLmLpyVmv12yqAUQ")
-                            .unwrap(),
-                        direction: TradeDirection::BtoA,
-                    },
-                    PairInfo {
-                        pool: Pubkey::from_str("EGZ7tiLeH62TPV1gL8WwbXGzEPa9zmcpVnnkPKKnrE2U")
-                            .unwrap(),
-                        direction: TradeDirection::BtoA,
-                    },
-                ],
-            }],
-            user_authority_path: None,
-            minimum_profit: HashMap::new(),
+    fn test_expand_path() {
+        let lookup = |name: &str| match name {
+            "HOME" => Some("/home/sol".to_owned()),
+            "MEV_DIR" => Some("/srv/mev".to_owned()),
+            _ => None,
         };
-        assert_eq!(sample_config, expected_mev_config);
+        let expand = |path: &str| expand_path_with(Path::new(path), lookup);
+        assert_eq!(
+            expand("~/mev/authority.json"),
+            Ok(PathBuf::from("/home/sol/mev/authority.json"))
+        );
+        assert_eq!(expand("~"), Ok(PathBuf::from("/home/sol")));
+        assert_eq!(
+            expand("${MEV_DIR}/logs/${MEV_DIR}.log"),
+            Ok(PathBuf::from("/srv/mev/logs//srv/mev.log"))
+        );
+        assert_eq!(
+            expand("~/${MEV_DIR}"),
+            Ok(PathBuf::from("/home/sol//srv/mev"))
+        );
+        // Only a leading `~` is the home directory, and strftime fields of
+        // the log path are left alone.
+        assert_eq!(
+            expand("/tmp/~user/$HOME/%Y.log"),
+            Ok(PathBuf::from("/tmp/~user/$HOME/%Y.log"))
+        );
+    }
+
+    #[test]
+    fn test_expand_path_errors() {
+        let lookup = |_name: &str| None;
+        let expand = |path: &str| expand_path_with(Path::new(path), lookup);
+        assert_eq!(
+            expand("${MEV_DIR}/authority.json"),
+            Err(
+                "path ${MEV_DIR}/authority.json uses the environment variable \"MEV_DIR\", \
+                but it is not set"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            expand("~/authority.json"),
+            Err("path ~/authority.json starts with ~, but HOME is not set".to_owned())
+        );
+        assert_eq!(
+            expand("/srv/${MEV_DIR"),
+            Err("path /srv/${MEV_DIR has a ${ without a closing }".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_log_throttle() {
+        let interval = Duration::from_secs(10);
+        let start = Instant::now();
+        let mut throttle = LogThrottle::new(2);
+
+        assert_eq!(throttle.check("a", interval, start), Some(0));
+        assert_eq!(throttle.check("a", interval, start), None);
+        assert_eq!(
+            throttle.check("a", interval, start + Duration::from_secs(5)),
+            None
+        );
+        // Other keys are not affected.
+        assert_eq!(throttle.check("b", interval, start), Some(0));
+        // Once the interval passed, we emit again, with the suppressed count.
+        assert_eq!(
+            throttle.check("a", interval, start + Duration::from_secs(10)),
+            Some(2)
+        );
+        assert_eq!(
+            throttle.check("a", interval, start + Duration::from_secs(20)),
+            Some(0)
+        );
+
+        // When full, the key emitted the longest ago is forgotten.
+        assert_eq!(
+            throttle.check("c", interval, start + Duration::from_secs(20)),
+            Some(0)
+        );
+        assert_eq!(throttle.entries.len(), 2);
+        assert!(!throttle.entries.contains_key("b"));
     }
 }
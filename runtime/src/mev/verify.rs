@@ -0,0 +1,318 @@
+//! Checking the pools of a config against their accounts on chain, so that a
+//! stale pool address or token account shows up before it leaves a path dead.
+//! See `examples/mev_verify_config.rs`, which fetches the accounts over RPC.
+
+use std::collections::HashMap;
+
+use solana_sdk::{
+    account::{AccountSharedData, ReadableAccount},
+    pubkey::Pubkey,
+};
+use spl_token::solana_program::{program_pack::Pack, pubkey::Pubkey as ProgramPubkey};
+use spl_token_swap::state::SwapVersion;
+
+use super::{
+    authority::PoolAuthorities,
+    config::{load_user_authority, AllOrcaPoolAddresses, MevConfig},
+    pool_state::{OrcaPoolAddresses, PoolRole},
+};
+use crate::inline_spl_token_2022;
+
+/// The outcome of checking one pool of the config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolVerification {
+    pub pool: Pubkey,
+
+    /// What does not match the accounts on chain, empty when the pool is fine.
+    pub failures: Vec<String>,
+}
+
+impl PoolVerification {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// The public key of the user authority in `config`, which should own our
+/// token accounts. Panics like the validator does when the config is invalid.
+pub fn configured_user_authority(config: &MevConfig) -> Option<Pubkey> {
+    load_user_authority(config.user_authority_path.clone(), config.signer.clone())
+        .unwrap_or_else(|err| panic!("[MEV] {}", err))
+        .map(|signer| signer.pubkey())
+}
+
+/// The public keys of the `[[user_authority]]` entries in `config`, per pool
+/// that they own the token accounts of. Panics like `configured_user_authority`.
+pub fn configured_pool_authorities(config: &MevConfig) -> HashMap<Pubkey, Pubkey> {
+    PoolAuthorities::load(config)
+        .unwrap_or_else(|err| panic!("[MEV] {}", err))
+        .pubkeys_by_pool()
+}
+
+/// The accounts that `verify_pools` reads, without duplicates: the state of
+/// every pool, and our token accounts that trade through it.
+pub fn accounts_to_verify(pools: &AllOrcaPoolAddresses) -> Vec<Pubkey> {
+    let mut accounts = Vec::new();
+    for pool in pools.unique() {
+        let pool_accounts = [Some(pool.address), pool.source, pool.destination];
+        for pubkey in pool_accounts.into_iter().flatten() {
+            if !accounts.contains(&pubkey) {
+                accounts.push(pubkey);
+            }
+        }
+    }
+    accounts
+}
+
+/// Check every pool in `pools` against `accounts`, which holds those of
+/// `accounts_to_verify` that exist, in config order. Our token accounts should
+/// belong to the pool's authority in `pool_authorities`, or else to
+/// `user_authority`. Without either, we do not check who owns them.
+pub fn verify_pools(
+    pools: &AllOrcaPoolAddresses,
+    user_authority: Option<&Pubkey>,
+    pool_authorities: &HashMap<Pubkey, Pubkey>,
+    accounts: &HashMap<Pubkey, AccountSharedData>,
+) -> Vec<PoolVerification> {
+    pools
+        .unique()
+        .map(|pool| PoolVerification {
+            pool: pool.address,
+            failures: verify_pool(
+                pool,
+                pool_authorities.get(&pool.address).or(user_authority),
+                accounts,
+            ),
+        })
+        .collect()
+}
+
+fn verify_pool(
+    pool: &OrcaPoolAddresses,
+    user_authority: Option<&Pubkey>,
+    accounts: &HashMap<Pubkey, AccountSharedData>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    let swap = match accounts.get(&pool.address) {
+        Some(account) => match SwapVersion::unpack(account.data()) {
+            Ok(swap) => swap,
+            Err(_) => {
+                failures.push(format!(
+                    "pool account {} does not hold a token swap state",
+                    pool.address
+                ));
+                return failures;
+            }
+        },
+        None => {
+            failures.push(format!("pool account {} does not exist", pool.address));
+            return failures;
+        }
+    };
+
+    let mut expect = |field: &str, configured: &Pubkey, on_chain: &ProgramPubkey| {
+        if configured.to_bytes() != on_chain.to_bytes() {
+            failures.push(format!(
+                "{} is {} in the config, but {} in the pool",
+                field, configured, on_chain
+            ));
+        }
+    };
+    expect(
+        "pool_a_account",
+        &pool.pool_a_account,
+        swap.token_a_account(),
+    );
+    expect(
+        "pool_b_account",
+        &pool.pool_b_account,
+        swap.token_b_account(),
+    );
+    expect("pool_mint", &pool.pool_mint, swap.pool_mint());
+    expect("pool_fee", &pool.pool_fee, swap.pool_fee_account());
+    if let Some((mint_a, mint_b)) = pool.known_mints() {
+        expect("pool_a_mint", &mint_a, swap.token_a_mint());
+        expect("pool_b_mint", &mint_b, swap.token_b_mint());
+    }
+
+    // We never trade through observe pools, so we ignore our accounts there.
+    if pool.role == PoolRole::Trade {
+        let our_accounts = [
+            ("source", pool.source, swap.token_a_mint()),
+            ("destination", pool.destination, swap.token_b_mint()),
+        ];
+        for (field, token_account, mint) in our_accounts {
+            let token_account = match token_account {
+                Some(token_account) => token_account,
+                None => continue,
+            };
+            if let Err(failure) = verify_token_account(
+                &token_account,
+                mint,
+                swap.token_program_id(),
+                user_authority,
+                accounts,
+            ) {
+                failures.push(format!("{} {}", field, failure));
+            }
+        }
+    }
+    failures
+}
+
+/// Check that `pubkey` is a token account of `mint` of the pool's
+/// `token_program`, SPL Token or Token-2022, owned by `user_authority` if set.
+fn verify_token_account(
+    pubkey: &Pubkey,
+    mint: &ProgramPubkey,
+    token_program: &ProgramPubkey,
+    user_authority: Option<&Pubkey>,
+    accounts: &HashMap<Pubkey, AccountSharedData>,
+) -> Result<(), String> {
+    let account = accounts
+        .get(pubkey)
+        .ok_or_else(|| format!("{} does not exist", pubkey))?;
+    if account.owner().to_bytes() != token_program.to_bytes() {
+        return Err(format!(
+            "{} is owned by program {}, not by the pool's token program {}",
+            pubkey,
+            account.owner(),
+            token_program
+        ));
+    }
+    // A Token-2022 account starts like an SPL Token account, its extensions
+    // follow.
+    let data = match account.data().get(..spl_token::state::Account::LEN) {
+        Some(base) if *account.owner() == inline_spl_token_2022::id() => base,
+        _ => account.data(),
+    };
+    let token_account = spl_token::state::Account::unpack(data)
+        .map_err(|_| format!("{} does not hold an SPL token account", pubkey))?;
+    if token_account.mint != *mint {
+        return Err(format!(
+            "{} holds mint {}, but the pool trades {}",
+            pubkey, token_account.mint, mint
+        ));
+    }
+    if let Some(user_authority) = user_authority {
+        if token_account.owner.to_bytes() != user_authority.to_bytes() {
+            return Err(format!(
+                "{} belongs to {}, not to the user authority {}",
+                pubkey, token_account.owner, user_authority
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mev::test_utils::pool;
+
+    /// A pool with our token accounts for both of its tokens, and its
+    /// accounts as on chain.
+    fn pool_with_accounts() -> (AllOrcaPoolAddresses, HashMap<Pubkey, AccountSharedData>) {
+        let (pool_addresses, accounts) = pool(Pubkey::new_unique())
+            .mints(Pubkey::new_unique(), Pubkey::new_unique())
+            .source(Pubkey::new_unique())
+            .destination(Pubkey::new_unique())
+            .build_accounts();
+        (
+            AllOrcaPoolAddresses::new(vec![pool_addresses]),
+            accounts.into_iter().collect(),
+        )
+    }
+
+    #[test]
+    fn test_verify_pools() {
+        let (pools, accounts) = pool_with_accounts();
+        let pool = pools.iter().next().unwrap().clone();
+        assert_eq!(
+            accounts_to_verify(&pools),
+            vec![
+                pool.address,
+                pool.source.unwrap(),
+                pool.destination.unwrap()
+            ]
+        );
+        let verifications = verify_pools(&pools, None, &HashMap::new(), &accounts);
+        assert_eq!(verifications.len(), 1);
+        assert!(verifications[0].is_ok(), "{:?}", verifications[0]);
+
+        // The fixture's token accounts belong to the default pubkey.
+        let user_authority = Pubkey::new_unique();
+        let failures =
+            &verify_pools(&pools, Some(&user_authority), &HashMap::new(), &accounts)[0].failures;
+        assert_eq!(failures.len(), 2);
+        assert_eq!(
+            failures[0],
+            format!(
+                "source {} belongs to {}, not to the user authority {}",
+                pool.source.unwrap(),
+                Pubkey::default(),
+                user_authority
+            )
+        );
+
+        // The authority of the pool wins over the default one.
+        let pool_authorities = [(pool.address, Pubkey::default())].into_iter().collect();
+        let verifications =
+            verify_pools(&pools, Some(&user_authority), &pool_authorities, &accounts);
+        assert!(verifications[0].is_ok(), "{:?}", verifications[0]);
+    }
+
+    #[test]
+    fn test_verify_pools_stale_config() {
+        let (pools, mut accounts) = pool_with_accounts();
+        let mut pool = pools.iter().next().unwrap().clone();
+
+        // A pool token account that the pool no longer uses, and our token
+        // accounts swapped.
+        let stale_account = Pubkey::new_unique();
+        let configured_pool = OrcaPoolAddresses {
+            pool_a_account: stale_account,
+            source: pool.destination,
+            destination: pool.source,
+            ..pool.clone()
+        };
+        let failures = verify_pool(&configured_pool, None, &accounts);
+        assert_eq!(failures.len(), 3);
+        assert_eq!(
+            failures[0],
+            format!(
+                "pool_a_account is {} in the config, but {} in the pool",
+                stale_account, pool.pool_a_account
+            )
+        );
+        assert_eq!(
+            failures[1],
+            format!(
+                "source {} holds mint {}, but the pool trades {}",
+                pool.destination.unwrap(),
+                pool.pool_b_mint,
+                pool.pool_a_mint
+            )
+        );
+
+        // As an observe pool, our accounts do not matter.
+        pool.role = PoolRole::Observe;
+        accounts.remove(&pool.source.unwrap());
+        assert!(verify_pool(&pool, None, &accounts).is_empty());
+
+        // A pool that does not exist, or is not a pool.
+        accounts.insert(pool.address, AccountSharedData::default());
+        assert_eq!(
+            verify_pool(&pool, None, &accounts),
+            vec![format!(
+                "pool account {} does not hold a token swap state",
+                pool.address
+            )]
+        );
+        accounts.remove(&pool.address);
+        assert_eq!(
+            verify_pool(&pool, None, &accounts),
+            vec![format!("pool account {} does not exist", pool.address)]
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use serde::Serialize;
+use solana_sdk::clock::Slot;
+
+/// What the watchdog waits for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchdogCondition {
+    /// No transaction that touched the watched programs, so we did not even
+    /// look for opportunities.
+    NoTriggers,
+
+    /// We looked for opportunities, but found none.
+    NoOpportunities,
+}
+
+/// Written to the log when a condition becomes stale, and again when it
+/// recovers.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct WatchdogEvent {
+    pub condition: WatchdogCondition,
+    pub stale: bool,
+    pub slot: Slot,
+
+    /// Number of slots since we last saw what the condition waits for.
+    pub stale_slots: u64,
+}
+
+#[derive(Debug)]
+struct Staleness {
+    condition: WatchdogCondition,
+    max_slots: Option<u64>,
+    // Slot in which we last saw what we wait for, or the first slot we were
+    // told about if we did not see it yet.
+    last_seen_slot: Option<Slot>,
+    is_stale: bool,
+}
+
+impl Staleness {
+    fn new(condition: WatchdogCondition, max_slots: Option<u64>) -> Self {
+        Staleness {
+            condition,
+            max_slots,
+            last_seen_slot: None,
+            is_stale: false,
+        }
+    }
+
+    fn stale_slots(&self, slot: Slot) -> u64 {
+        self.last_seen_slot
+            .map_or(0, |last_seen_slot| slot.saturating_sub(last_seen_slot))
+    }
+
+    fn tick(&mut self, slot: Slot) -> Option<WatchdogEvent> {
+        let last_seen_slot = *self.last_seen_slot.get_or_insert(slot);
+        let max_slots = self.max_slots?;
+        let stale_slots = slot.saturating_sub(last_seen_slot);
+        if self.is_stale || stale_slots < max_slots {
+            return None;
+        }
+        self.is_stale = true;
+        Some(WatchdogEvent {
+            condition: self.condition,
+            stale: true,
+            slot,
+            stale_slots,
+        })
+    }
+
+    fn seen(&mut self, slot: Slot) -> Option<WatchdogEvent> {
+        let stale_slots = self.stale_slots(slot);
+        self.last_seen_slot = Some(self.last_seen_slot.map_or(slot, |last| last.max(slot)));
+        if !self.is_stale {
+            return None;
+        }
+        self.is_stale = false;
+        Some(WatchdogEvent {
+            condition: self.condition,
+            stale: false,
+            slot,
+            stale_slots,
+        })
+    }
+}
+
+/// Keeps track of how long ago the log thread last received a trigger and an
+/// opportunity, so that a misconfiguration that makes us silently find nothing
+/// does not go unnoticed. Slots are the slots that the banking stage told us
+/// about through `MevMsg::Tick`, so only the slots in which we are leader count.
+#[derive(Debug)]
+pub struct Watchdog {
+    trigger: Staleness,
+    opportunity: Staleness,
+    slot: Option<Slot>,
+}
+
+impl Watchdog {
+    /// A condition with `None` for its number of slots never becomes stale.
+    pub fn new(trigger_slots: Option<u64>, opportunity_slots: Option<u64>) -> Self {
+        Watchdog {
+            trigger: Staleness::new(WatchdogCondition::NoTriggers, trigger_slots),
+            opportunity: Staleness::new(WatchdogCondition::NoOpportunities, opportunity_slots),
+            slot: None,
+        }
+    }
+
+    /// The latest slot we know of.
+    pub fn slot(&self) -> Option<Slot> {
+        self.slot
+    }
+
+    pub fn is_trigger_stale(&self) -> bool {
+        self.trigger.is_stale
+    }
+
+    pub fn is_opportunity_stale(&self) -> bool {
+        self.opportunity.is_stale
+    }
+
+    /// Advance to `slot`, returns the conditions that became stale.
+    pub fn tick(&mut self, slot: Slot) -> Vec<WatchdogEvent> {
+        self.advance(slot);
+        self.trigger
+            .tick(slot)
+            .into_iter()
+            .chain(self.opportunity.tick(slot))
+            .collect()
+    }
+
+    /// We received a trigger in `slot`, returns the recovery if we were stale.
+    pub fn on_trigger(&mut self, slot: Slot) -> Option<WatchdogEvent> {
+        self.advance(slot);
+        self.trigger.seen(slot)
+    }
+
+    /// We found an opportunity in `slot`, returns the recovery if we were
+    /// stale.
+    pub fn on_opportunity(&mut self, slot: Slot) -> Option<WatchdogEvent> {
+        self.advance(slot);
+        self.opportunity.seen(slot)
+    }
+
+    fn advance(&mut self, slot: Slot) {
+        self.slot = Some(self.slot.map_or(slot, |last| last.max(slot)));
+    }
+
+    /// Report the state as gauges, a value of 1 for `*_stale` is the alert
+    /// condition.
+    pub fn report_metrics(&self) {
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None => return,
+        };
+        datapoint_info!(
+            "mev-watchdog",
+            ("trigger_stale", self.trigger.is_stale as i64, i64),
+            ("opportunity_stale", self.opportunity.is_stale as i64, i64),
+            (
+                "slots_since_trigger",
+                self.trigger.stale_slots(slot) as i64,
+                i64
+            ),
+            (
+                "slots_since_opportunity",
+                self.opportunity.stale_slots(slot) as i64,
+                i64
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_silence_and_recovery() {
+        let mut watchdog = Watchdog::new(Some(10), Some(100));
+        assert_eq!(watchdog.tick(1_000), vec![]);
+        assert_eq!(watchdog.on_trigger(1_001), None);
+        assert_eq!(watchdog.tick(1_010), vec![]);
+
+        // No triggers for 10 slots since the last one.
+        assert_eq!(
+            watchdog.tick(1_011),
+            vec![WatchdogEvent {
+                condition: WatchdogCondition::NoTriggers,
+                stale: true,
+                slot: 1_011,
+                stale_slots: 10,
+            }]
+        );
+        assert!(watchdog.is_trigger_stale());
+        // We only report becoming stale once.
+        assert_eq!(watchdog.tick(1_020), vec![]);
+
+        // No opportunities since the first slot we were told about.
+        assert_eq!(
+            watchdog.tick(1_100),
+            vec![WatchdogEvent {
+                condition: WatchdogCondition::NoOpportunities,
+                stale: true,
+                slot: 1_100,
+                stale_slots: 100,
+            }]
+        );
+
+        assert_eq!(
+            watchdog.on_trigger(1_101),
+            Some(WatchdogEvent {
+                condition: WatchdogCondition::NoTriggers,
+                stale: false,
+                slot: 1_101,
+                stale_slots: 100,
+            })
+        );
+        assert!(!watchdog.is_trigger_stale());
+        assert!(watchdog.is_opportunity_stale());
+        assert_eq!(
+            watchdog.on_opportunity(1_101),
+            Some(WatchdogEvent {
+                condition: WatchdogCondition::NoOpportunities,
+                stale: false,
+                slot: 1_101,
+                stale_slots: 101,
+            })
+        );
+        assert!(!watchdog.is_opportunity_stale());
+        assert_eq!(watchdog.on_opportunity(1_102), None);
+
+        // After recovering, it can become stale again.
+        assert_eq!(watchdog.tick(1_111).len(), 1);
+        assert_eq!(watchdog.slot(), Some(1_111));
+    }
+
+    #[test]
+    fn test_watchdog_disabled() {
+        let mut watchdog = Watchdog::new(None, None);
+        assert_eq!(watchdog.tick(0), vec![]);
+        assert_eq!(watchdog.tick(u64::MAX), vec![]);
+        assert!(!watchdog.is_trigger_stale());
+        assert!(!watchdog.is_opportunity_stale());
+    }
+}
@@ -27,6 +27,8 @@ use {
 /// needed for the Neon EVM implementation.
 pub const MAX_TX_ACCOUNT_LOCKS: usize = 128;
 
+/// The accounts of one pool that MEV wants to read, and, when `source` and
+/// `destination` are set, the accounts it wants to trade with.
 #[derive(Debug, Clone)]
 pub struct MevPoolKeys {
     pub pool: Pubkey,
@@ -37,20 +39,106 @@ pub struct MevPoolKeys {
     pub pool_mint: Pubkey,
     pub pool_fee: Pubkey,
     pub pool_authority: Pubkey,
+
+    /// The token program that owns `token_a` and `token_b`, SPL Token or
+    /// Token-2022. Pools of one path may use different ones.
+    pub token_program: Pubkey,
+
+    /// The mints of `token_a` and `token_b`, when we know them. A swap never
+    /// writes to them, so they are always locked for reading.
+    pub token_a_mint: Option<Pubkey>,
+    pub token_b_mint: Option<Pubkey>,
+}
+
+impl MevPoolKeys {
+    /// Keys for a pool that we only read, without our own token accounts.
+    pub fn new(
+        pool: Pubkey,
+        token_a: Pubkey,
+        token_b: Pubkey,
+        pool_mint: Pubkey,
+        pool_fee: Pubkey,
+        pool_authority: Pubkey,
+        token_program: Pubkey,
+    ) -> Self {
+        Self {
+            pool,
+            source: None,
+            destination: None,
+            token_a,
+            token_b,
+            pool_mint,
+            pool_fee,
+            pool_authority,
+            token_program,
+            token_a_mint: None,
+            token_b_mint: None,
+        }
+    }
+
+    /// Set our token accounts that trade against this pool. Only when both are
+    /// set, the pool's accounts are locked for writing.
+    pub fn with_trade_accounts(mut self, source: Pubkey, destination: Pubkey) -> Self {
+        self.source = Some(source);
+        self.destination = Some(destination);
+        self
+    }
+
+    /// Set the mints of `token_a` and `token_b`, so they are loaded too.
+    pub fn with_mints(mut self, token_a_mint: Pubkey, token_b_mint: Pubkey) -> Self {
+        self.token_a_mint = Some(token_a_mint);
+        self.token_b_mint = Some(token_b_mint);
+        self
+    }
 }
 
+/// The accounts that MEV loads alongside a transaction, so it can evaluate the
+/// pools after the transaction executed.
+///
+/// Every key must refer to an existing account, otherwise loading the
+/// transaction's accounts fails.
 #[derive(Debug, Clone)]
 pub struct MevKeys {
     pub pool_keys: Vec<MevPoolKeys>,
-    pub token_program: Pubkey,
-    pub user_authority: Option<Pubkey>,
+
+    /// The signers of MEV transactions, each locked read-only, like the fee
+    /// payer of a transaction that may follow this one.
+    pub user_authorities: Vec<Pubkey>,
+
+    /// Generation of the MEV config that `pool_keys` were taken from. When the
+    /// config is reloaded before the transaction is evaluated, the pools may no
+    /// longer be in it.
+    pub config_generation: u64,
 }
 
 impl MevKeys {
+    /// Keys for `pool_keys`, without user authorities, from the first
+    /// generation of the config.
+    pub fn new(pool_keys: Vec<MevPoolKeys>) -> Self {
+        Self {
+            pool_keys,
+            user_authorities: Vec::new(),
+            config_generation: 0,
+        }
+    }
+
+    pub fn with_user_authority(mut self, user_authority: Pubkey) -> Self {
+        self.user_authorities.push(user_authority);
+        self
+    }
+
+    pub fn with_config_generation(mut self, config_generation: u64) -> Self {
+        self.config_generation = config_generation;
+        self
+    }
+
     pub fn get_readonly_accounts<'a>(&'a self, readonly_accounts: &mut HashSet<&'a Pubkey>) {
         for pool_keys in &self.pool_keys {
             readonly_accounts.insert(&pool_keys.pool);
             readonly_accounts.insert(&pool_keys.pool_authority);
+            readonly_accounts.insert(&pool_keys.token_program);
+            readonly_accounts.extend(&pool_keys.token_a_mint);
+            readonly_accounts.extend(&pool_keys.token_b_mint);
             if pool_keys.source.is_some() && pool_keys.destination.is_some() {
                 continue;
             }
@@ -59,10 +147,7 @@ impl MevKeys {
             readonly_accounts.insert(&pool_keys.pool_mint);
             readonly_accounts.insert(&pool_keys.pool_fee);
         }
-        if let Some(user_authority) = &self.user_authority {
-            readonly_accounts.insert(user_authority);
-        }
-        readonly_accounts.insert(&self.token_program);
+        readonly_accounts.extend(&self.user_authorities);
     }
 
     pub fn get_write_accounts<'a>(&'a self, write_accounts: &mut HashSet<&'a Pubkey>) {
@@ -206,6 +291,19 @@ impl SanitizedTransaction {
         Self::try_from_legacy_transaction(tx).unwrap()
     }
 
+    /// Attach `mev_keys`, so the accounts they refer to are locked and loaded
+    /// along with the transaction. Normally `Mev::fill_tx_mev_accounts` sets
+    /// these, this is for building such transactions directly, e.g. in tests.
+    pub fn with_mev_keys(mut self, mev_keys: MevKeys) -> Self {
+        self.mev_keys = Some(mev_keys);
+        self
+    }
+
+    /// Return the MEV keys that are loaded along with this transaction, if any.
+    pub fn mev_keys(&self) -> Option<&MevKeys> {
+        self.mev_keys.as_ref()
+    }
+
     /// Return the first signature for this transaction.
     ///
     /// Notes:
@@ -342,3 +440,119 @@ impl SanitizedTransaction {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{signature::Keypair, system_transaction},
+    };
+
+    fn transfer_tx() -> SanitizedTransaction {
+        let from = Keypair::new();
+        let tx = system_transaction::transfer(&from, &Pubkey::new_unique(), 1, Hash::default());
+        SanitizedTransaction::from_transaction_for_tests(tx)
+    }
+
+    fn pool_keys() -> MevPoolKeys {
+        MevPoolKeys::new(
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        )
+    }
+
+    #[test]
+    fn test_without_mev_keys() {
+        let tx = transfer_tx();
+        assert!(tx.mev_keys().is_none());
+        assert!(tx.get_account_locks_unchecked().readonly_mev.is_none());
+    }
+
+    #[test]
+    fn test_mev_account_locks() {
+        let read_pool = pool_keys();
+        let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let trade_pool = pool_keys().with_trade_accounts(source, destination);
+        let (user_authority, other_user_authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let tx = transfer_tx().with_mev_keys(
+            MevKeys::new(vec![read_pool.clone(), trade_pool.clone()])
+                .with_user_authority(user_authority)
+                .with_user_authority(other_user_authority),
+        );
+        assert_eq!(tx.mev_keys().unwrap().pool_keys.len(), 2);
+
+        let locks = tx.get_account_locks(MAX_TX_ACCOUNT_LOCKS).unwrap();
+        let mev_keys = locks.readonly_mev.unwrap();
+        let mut readonly = HashSet::new();
+        mev_keys.get_readonly_accounts(&mut readonly);
+        let mut writable = HashSet::new();
+        mev_keys.get_write_accounts(&mut writable);
+
+        // The pool we only read is locked for reading entirely.
+        for key in [
+            &read_pool.pool,
+            &read_pool.token_a,
+            &read_pool.token_b,
+            &read_pool.pool_mint,
+            &read_pool.pool_fee,
+            &read_pool.pool_authority,
+        ] {
+            assert!(readonly.contains(key));
+            assert!(!writable.contains(key));
+        }
+
+        // The pool we trade with is locked for writing, except for the
+        // accounts that a swap does not modify.
+        for key in [&trade_pool.pool, &trade_pool.pool_authority] {
+            assert!(readonly.contains(key));
+            assert!(!writable.contains(key));
+        }
+        for key in [
+            &source,
+            &destination,
+            &trade_pool.token_a,
+            &trade_pool.token_b,
+            &trade_pool.pool_mint,
+            &trade_pool.pool_fee,
+        ] {
+            assert!(writable.contains(key));
+            assert!(!readonly.contains(key));
+        }
+
+        // Every pool's token program is read, they can differ per pool.
+        assert!(readonly.contains(&read_pool.token_program));
+        assert!(readonly.contains(&trade_pool.token_program));
+        assert!(readonly.contains(&user_authority));
+        assert!(readonly.contains(&other_user_authority));
+    }
+
+    #[test]
+    fn test_mev_account_locks_with_mints() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let read_pool = pool_keys().with_mints(mint_a, mint_b);
+        let trade_pool = pool_keys()
+            .with_trade_accounts(Pubkey::new_unique(), Pubkey::new_unique())
+            .with_mints(mint_b, mint_a);
+        let mev_keys = MevKeys::new(vec![read_pool, trade_pool]);
+        let mut readonly = HashSet::new();
+        mev_keys.get_readonly_accounts(&mut readonly);
+        let mut writable = HashSet::new();
+        mev_keys.get_write_accounts(&mut writable);
+
+        // Also for the pool we trade with, the mints are only read.
+        for key in [&mint_a, &mint_b] {
+            assert!(readonly.contains(key));
+            assert!(!writable.contains(key));
+        }
+
+        // Without mints, the locks stay as they were.
+        let mut readonly_without_mints = HashSet::new();
+        MevKeys::new(vec![pool_keys()]).get_readonly_accounts(&mut readonly_without_mints);
+        assert_eq!(readonly_without_mints.len(), 7);
+    }
+}